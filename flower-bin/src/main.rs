@@ -44,7 +44,7 @@ struct Args {
     #[argh(option, default = "default_thread_stack_size()")]
     thread_stack_size: usize,
 
-    /// tests the configuration and exit
+    /// validates the configuration without listening and exit
     #[argh(switch, short = 'T')]
     test: bool,
 
@@ -1,4 +1,4 @@
-use std::{ffi::CStr, os::raw::c_char};
+use std::{convert::TryFrom, ffi::CStr, os::raw::c_char};
 
 /// No error.
 pub const ERR_OK: i32 = 0;
@@ -22,6 +22,7 @@ pub const ERR_NO_CONFIG_FILE: i32 = 8;
 fn to_errno(e: flower::Error) -> i32 {
     match e {
         flower::Error::Config(..) => ERR_CONFIG,
+        flower::Error::ConfigPath(..) => ERR_CONFIG_PATH,
         flower::Error::NoConfigFile => ERR_NO_CONFIG_FILE,
         flower::Error::Io(..) => ERR_IO,
         #[cfg(feature = "auto-reload")]
@@ -39,8 +40,8 @@ fn to_errno(e: flower::Error) -> i32 {
 ///
 /// @param rt_id A unique ID to associate this flower instance, this is required when
 ///              calling subsequent FFI functions, e.g. reload, shutdown.
-/// @param config_path The path of the config file, must be a file with suffix .conf
-///                    or .json, according to the enabled features.
+/// @param config_path The path of the config file, must be a file with suffix .conf,
+///                    .json or .yaml, according to the enabled features.
 /// @param auto_reload Enabls auto reloading when config file changes are detected,
 ///                    takes effect only when the "auto-reload" feature is enabled.
 /// @param multi_thread Whether to use a multi-threaded runtime.
@@ -85,8 +86,8 @@ pub extern "C" fn flower_run_with_options(
 ///
 /// @param rt_id A unique ID to associate this flower instance, this is required when
 ///              calling subsequent FFI functions, e.g. reload, shutdown.
-/// @param config_path The path of the config file, must be a file with suffix .conf
-///                    or .json, according to the enabled features.
+/// @param config_path The path of the config file, must be a file with suffix .conf,
+///                    .json or .yaml, according to the enabled features.
 /// @return ERR_OK on finish running, any other errors means a startup failure.
 #[no_mangle]
 pub extern "C" fn flower_run(rt_id: u16, config_path: *const c_char) -> i32 {
@@ -106,7 +107,8 @@ pub extern "C" fn flower_run(rt_id: u16, config_path: *const c_char) -> i32 {
     }
 }
 
-/// Reloads DNS servers, outbounds and routing rules from the config file.
+/// Reloads DNS servers, inbounds, outbounds and routing rules from the config
+/// file, only the changed ones are rebuilt.
 ///
 /// @param rt_id The ID of the flower instance to reload.
 ///
@@ -129,11 +131,65 @@ pub extern "C" fn flower_shutdown(rt_id: u16) -> bool {
     flower::shutdown(rt_id)
 }
 
+// Returns None if the pointer is null or the string is not valid UTF-8.
+fn path_from_ptr(path: *const c_char) -> Option<String> {
+    if path.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(path) }
+        .to_str()
+        .ok()
+        .map(|s| s.to_string())
+}
+
+/// Starts flower with a single-threaded runtime, on a successful start this function
+/// blocks the current thread until `flower_stop` is called.
+///
+/// @param id A unique ID to associate this flower instance, must fit in 16 bits.
+/// @param config_path The nul-terminated path of the config file, must be a file with
+///                    suffix .conf, .json or .yaml, according to the enabled features.
+/// @return ERR_OK on finish running, any other errors means a startup failure.
+#[no_mangle]
+pub extern "C" fn flower_start(id: u32, config_path: *const c_char) -> i32 {
+    let rt_id = match flower::RuntimeId::try_from(id) {
+        Ok(id) => id,
+        Err(_) => return ERR_RUNTIME_MANAGER,
+    };
+    let config_path = match path_from_ptr(config_path) {
+        Some(p) => p,
+        None => return ERR_CONFIG_PATH,
+    };
+    let opts = flower::StartOptions {
+        config: flower::Config::File(config_path),
+        #[cfg(feature = "auto-reload")]
+        auto_reload: false,
+        runtime_opt: flower::RuntimeOption::SingleThread,
+    };
+    if let Err(e) = flower::start(rt_id, opts) {
+        return to_errno(e);
+    }
+    ERR_OK
+}
+
+/// Stops a flower instance started by `flower_start`, must be called from a different
+/// thread.
+///
+/// @param id The ID of the flower instance to stop.
+/// @return ERR_OK on success, ERR_RUNTIME_MANAGER if the instance is not running.
+#[no_mangle]
+pub extern "C" fn flower_stop(id: u32) -> i32 {
+    match flower::RuntimeId::try_from(id) {
+        Ok(rt_id) if flower::shutdown(rt_id) => ERR_OK,
+        _ => ERR_RUNTIME_MANAGER,
+    }
+}
+
 /// Tests the configuration.
 ///
-/// @param config_path The path of the config file, must be a file with suffix .conf
-///                    or .json, according to the enabled features.
-/// @return Returns ERR_OK on success, i.e no syntax error.
+/// @param config_path The path of the config file, must be a file with suffix .conf,
+///                    .json or .yaml, according to the enabled features.
+/// @return Returns ERR_OK on success, i.e the config parses and all the routing rules and
+///         handlers build, nothing is bound though.
 #[no_mangle]
 pub extern "C" fn flower_test_config(config_path: *const c_char) -> i32 {
     if let Ok(config_path) = unsafe { CStr::from_ptr(config_path).to_str() } {
@@ -145,3 +201,16 @@ pub extern "C" fn flower_test_config(config_path: *const c_char) -> i32 {
         ERR_CONFIG_PATH
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    #[test]
+    fn test_start_missing_config() {
+        let config_path = CString::new("/nonexistent/flower.conf").unwrap();
+        assert_eq!(flower_start(1, config_path.as_ptr()), ERR_CONFIG_PATH);
+    }
+}
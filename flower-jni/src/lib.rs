@@ -1,5 +1,9 @@
+use std::convert::TryFrom;
+use std::sync::Mutex;
+
 use jni::{
     objects::{JClass, JString},
+    sys::{jint, jstring},
     JNIEnv,
 };
 
@@ -21,10 +25,30 @@ pub const ERR_SYNC_CHANNEL_RECV: i32 = 6;
 pub const ERR_RUNTIME_MANAGER: i32 = 7;
 /// No associated config file.
 pub const ERR_NO_CONFIG_FILE: i32 = 8;
+/// Invalid argument, e.g. a null or malformed string.
+pub const ERR_INVALID_ARG: i32 = 9;
+
+/// The runtime ID of the instance started by `runFlower`.
+const RUNTIME_ID: flower::RuntimeId = 0;
+
+// The message of the last error returned, shared by all threads since the
+// errors of `runFlower` are usually checked from another thread.
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+fn set_last_error(msg: String) {
+    *LAST_ERROR.lock().unwrap_or_else(|e| e.into_inner()) = Some(msg);
+}
+
+fn arg_error(code: i32, msg: &str) -> i32 {
+    set_last_error(msg.to_string());
+    code
+}
 
 fn to_errno(e: flower::Error) -> i32 {
+    set_last_error(e.to_string());
     match e {
         flower::Error::Config(..) => ERR_CONFIG,
+        flower::Error::ConfigPath(..) => ERR_CONFIG_PATH,
         flower::Error::NoConfigFile => ERR_NO_CONFIG_FILE,
         flower::Error::Io(..) => ERR_IO,
         #[cfg(feature = "auto-reload")]
@@ -35,6 +59,15 @@ fn to_errno(e: flower::Error) -> i32 {
     }
 }
 
+// Returns None if the string is null or not valid UTF-8.
+fn get_string(env: &JNIEnv, s: JString) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let s = env.get_string(s).ok()?;
+    s.to_str().ok().map(|s| s.to_owned())
+}
+
 #[no_mangle]
 #[allow(non_snake_case)]
 pub unsafe extern "C" fn Java_com_sllt_app_flower_SimpleVpnService_runFlower(
@@ -43,34 +76,85 @@ pub unsafe extern "C" fn Java_com_sllt_app_flower_SimpleVpnService_runFlower(
     config_path: JString,
     protect_path: JString,
 ) -> i32 {
-    let config_path = env
-        .get_string(config_path)
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_owned();
-    let protect_path = env
-        .get_string(protect_path)
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_owned();
+    let config_path = match get_string(&env, config_path) {
+        Some(s) => s,
+        None => return arg_error(ERR_CONFIG_PATH, "invalid config path"),
+    };
+    let protect_path = match get_string(&env, protect_path) {
+        Some(s) => s,
+        None => return arg_error(ERR_INVALID_ARG, "invalid protect path"),
+    };
 
     std::env::set_var("SOCKET_PROTECT_PATH", protect_path);
 
-    let a = std::env::var("SOCKET_PROTECT_PATH").unwrap();
-    println!("{}", a);
-    println!("{}", "Hello World");
-
     let opts = flower::StartOptions {
         config: flower::Config::File(config_path),
         #[cfg(feature = "auto-reload")]
         auto_reload: false,
         runtime_opt: flower::RuntimeOption::SingleThread,
     };
-    if let Err(e) = flower::start(0, opts) {
+    if let Err(e) = flower::start(RUNTIME_ID, opts) {
         return to_errno(e);
     } else {
         0
     }
 }
+
+/// Shuts down the instance, `runFlower` returns once it's stopped. This is
+/// meant to be called from a different thread than `runFlower`.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_sllt_app_flower_SimpleVpnService_stopFlower(
+    _: JNIEnv,
+    _: JClass,
+    runtime_id: jint,
+) -> jint {
+    match flower::RuntimeId::try_from(runtime_id) {
+        Ok(id) if flower::shutdown(id) => ERR_OK,
+        _ => to_errno(flower::Error::RuntimeManager),
+    }
+}
+
+/// Reloads the config file, only the DNS servers, routing rules, outbounds
+/// and inbounds changed since the last load are rebuilt, and the inbounds
+/// changed are rebound. The current config keeps running if the new one
+/// fails to load or an inbound fails to bind. This is meant to be called
+/// from a different thread than `runFlower`.
+#[cfg(feature = "auto-reload")]
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_sllt_app_flower_SimpleVpnService_reloadFlower(
+    _: JNIEnv,
+    _: JClass,
+    runtime_id: jint,
+) -> jint {
+    let id = match flower::RuntimeId::try_from(runtime_id) {
+        Ok(id) => id,
+        Err(_) => return to_errno(flower::Error::RuntimeManager),
+    };
+    if let Err(e) = flower::reload(id) {
+        return to_errno(e);
+    }
+    ERR_OK
+}
+
+/// Returns the message of the last error returned by the functions above, or
+/// null if there's none.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn Java_com_sllt_app_flower_SimpleVpnService_lastErrorMessage(
+    env: JNIEnv,
+    _: JClass,
+) -> jstring {
+    match LAST_ERROR
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_deref()
+    {
+        Some(msg) => env
+            .new_string(msg)
+            .map(|s| s.into_inner())
+            .unwrap_or(std::ptr::null_mut()),
+        None => std::ptr::null_mut(),
+    }
+}
@@ -0,0 +1,126 @@
+//! Measures the throughput of relaying many concurrent TCP connections,
+//! comparing `relay_tcp` with a relay allocating a buffer per direction and
+//! flushing after every write.
+//!
+//! cargo bench -p flower --bench relay --features inbound-trojan
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+
+use flower::proxy::trojan::inbound::relay_tcp;
+use flower::proxy::ProxyStream;
+use flower::session::Traffic;
+
+const CONNECTIONS: usize = 256;
+const BYTES_PER_CONNECTION: usize = 4 * 1024 * 1024;
+const CHUNK_SIZE: usize = 1024;
+const ROUNDS: usize = 3;
+
+async fn naive_copy<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    r: &mut R,
+    w: &mut W,
+) -> io::Result<()> {
+    let mut buf = [0u8; 0x4000];
+    loop {
+        let len = r.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        w.write_all(&buf[..len]).await?;
+        w.flush().await?;
+    }
+    Ok(())
+}
+
+async fn naive_relay<T: ProxyStream, U: ProxyStream>(a: T, b: U) {
+    let (mut a_rx, mut a_tx) = split(a);
+    let (mut b_rx, mut b_tx) = split(b);
+    let t1 = naive_copy(&mut a_rx, &mut b_tx);
+    let t2 = naive_copy(&mut b_rx, &mut a_tx);
+    let _ = futures::future::select(Box::pin(t1), Box::pin(t2)).await;
+    let mut a = a_rx.unsplit(a_tx);
+    let mut b = b_rx.unsplit(b_tx);
+    let _ = a.shutdown().await;
+    let _ = b.shutdown().await;
+}
+
+async fn tcp_pair(listener: &TcpListener) -> (TcpStream, TcpStream) {
+    let client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (client, server)
+}
+
+// Relays `CONNECTIONS` connections at the same time, the relayed streams are
+// buffered so that every flush costs a write to the socket.
+async fn run(naive: bool) -> Duration {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut pairs = Vec::new();
+    for _ in 0..CONNECTIONS {
+        let (client, inbound) = tcp_pair(&listener).await;
+        let (outbound, server) = tcp_pair(&listener).await;
+        pairs.push((client, inbound, outbound, server));
+    }
+
+    let start = Instant::now();
+    let mut tasks = Vec::new();
+    for (mut client, inbound, outbound, mut server) in pairs {
+        let inbound = BufWriter::new(inbound);
+        let outbound = BufWriter::new(outbound);
+        tasks.push(tokio::spawn(async move {
+            if naive {
+                naive_relay(inbound, outbound).await;
+            } else {
+                relay_tcp(inbound, outbound, &Traffic::default()).await;
+            }
+        }));
+        tasks.push(tokio::spawn(async move {
+            let chunk = [0u8; CHUNK_SIZE];
+            for _ in 0..BYTES_PER_CONNECTION / CHUNK_SIZE {
+                client.write_all(&chunk).await.unwrap();
+            }
+            client.shutdown().await.unwrap();
+            let _ = client.read(&mut [0u8; 1]).await;
+        }));
+        tasks.push(tokio::spawn(async move {
+            let mut buf = vec![0u8; 0x4000];
+            let mut n = 0;
+            loop {
+                match server.read(&mut buf).await.unwrap() {
+                    0 => break,
+                    len => n += len,
+                }
+            }
+            assert_eq!(n, BYTES_PER_CONNECTION);
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let total = (CONNECTIONS * BYTES_PER_CONNECTION) as f64 / (1024.0 * 1024.0);
+    for (name, naive) in [("flush per write", true), ("relay_tcp", false)] {
+        let mut best = Duration::MAX;
+        for _ in 0..ROUNDS {
+            best = best.min(rt.block_on(run(naive)));
+        }
+        println!(
+            "{:>16}: {:>8.1} MiB/s ({} connections, {:?})",
+            name,
+            total / best.as_secs_f64(),
+            CONNECTIONS,
+            best
+        );
+    }
+}
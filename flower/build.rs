@@ -191,5 +191,18 @@ fn main() {
             })
             .run()
             .expect("protoc");
+
+        protoc_rust::Codegen::new()
+            .out_dir("src/app/outbound")
+            .inputs(&["src/app/outbound/quota_cache.proto"])
+            .customize(protoc_rust::Customize {
+                expose_oneof: Some(true),
+                expose_fields: Some(true),
+                generate_accessors: Some(false),
+                lite_runtime: Some(true),
+                ..Default::default()
+            })
+            .run()
+            .expect("protoc");
     }
 }
@@ -1,14 +1,20 @@
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
+use anyhow::{anyhow, Result};
 use warp::Filter;
 
 use crate::RuntimeManager;
 
 mod models {
+    use std::collections::HashMap;
+
     use serde_derive::{Deserialize, Serialize};
 
+    use crate::app::events;
+
     #[derive(Debug, Deserialize)]
     pub struct SelectOptions {
         pub outbound: Option<String>,
@@ -19,6 +25,141 @@ mod models {
     pub struct SelectReply {
         pub selected: Option<String>,
     }
+
+    #[derive(Debug, Serialize)]
+    pub struct QuotaUsage {
+        pub outbound: String,
+        pub used: u64,
+        pub limit: u64,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct OutboundStats {
+        pub outbound: String,
+        pub uplink: u64,
+        pub downlink: u64,
+        pub connections: usize,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct HealthCheck {
+        pub outbound: String,
+        pub actor: String,
+        pub network: &'static str,
+        // In millis, `None` if the check failed.
+        pub latency: Option<u64>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Stats {
+        pub uplink: u64,
+        pub downlink: u64,
+        pub connections: usize,
+        pub quotas: Vec<QuotaUsage>,
+        pub outbounds: Vec<OutboundStats>,
+        pub health_checks: Vec<HealthCheck>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Runtime {
+        pub id: crate::RuntimeId,
+        pub uptime: u64,
+        pub inbounds: HashMap<String, String>,
+        pub uplink: u64,
+        pub downlink: u64,
+        pub connections: usize,
+    }
+
+    #[derive(Debug, Serialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum Event<'a> {
+        SessionStart {
+            id: u64,
+            network: &'a str,
+            source: &'a str,
+            destination: &'a str,
+            inbound_tag: &'a str,
+            outbound_tag: &'a str,
+        },
+        SessionEnd {
+            id: u64,
+            uplink: u64,
+            downlink: u64,
+        },
+        Error {
+            network: &'a str,
+            source: &'a str,
+            destination: &'a str,
+            inbound_tag: &'a str,
+            outbound_tag: &'a str,
+            error: &'a str,
+        },
+        // Events missed by a lagging consumer.
+        Dropped {
+            count: u64,
+        },
+    }
+
+    impl<'a> From<&'a events::Event> for Event<'a> {
+        fn from(ev: &'a events::Event) -> Self {
+            match ev {
+                events::Event::SessionStart {
+                    id,
+                    network,
+                    source,
+                    destination,
+                    inbound_tag,
+                    outbound_tag,
+                } => Event::SessionStart {
+                    id: *id,
+                    network,
+                    source,
+                    destination,
+                    inbound_tag,
+                    outbound_tag,
+                },
+                events::Event::SessionEnd {
+                    id,
+                    uplink,
+                    downlink,
+                } => Event::SessionEnd {
+                    id: *id,
+                    uplink: *uplink,
+                    downlink: *downlink,
+                },
+                events::Event::Error {
+                    network,
+                    source,
+                    destination,
+                    inbound_tag,
+                    outbound_tag,
+                    error,
+                } => Event::Error {
+                    network,
+                    source,
+                    destination,
+                    inbound_tag,
+                    outbound_tag,
+                    error,
+                },
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Connection {
+        pub id: u64,
+        pub network: String,
+        pub source: String,
+        pub destination: String,
+        pub inbound_tag: String,
+        pub outbound_tag: String,
+        pub start_time: u64,
+        /// Seconds since the connection started.
+        pub age: u64,
+        pub uplink: u64,
+        pub downlink: u64,
+    }
 }
 
 mod handlers {
@@ -67,6 +208,16 @@ mod handlers {
         }
     }
 
+    pub async fn runtime_reload_assets(
+        rm: Arc<RuntimeManager>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        if rm.reload_assets().await.is_ok() {
+            Ok(StatusCode::OK)
+        } else {
+            Ok(StatusCode::ACCEPTED)
+        }
+    }
+
     pub async fn runtime_shutdown(rm: Arc<RuntimeManager>) -> Result<impl warp::Reply, Infallible> {
         if rm.shutdown().await {
             Ok(StatusCode::OK)
@@ -74,11 +225,208 @@ mod handlers {
             Ok(StatusCode::ACCEPTED)
         }
     }
+
+    pub async fn runtime_stats(rm: Arc<RuntimeManager>) -> Result<impl warp::Reply, Infallible> {
+        let (uplink, downlink) = rm.conn_manager().traffic();
+        let mut quotas: Vec<models::QuotaUsage> = rm
+            .quota_usage()
+            .await
+            .into_iter()
+            .map(|(outbound, (used, limit))| models::QuotaUsage {
+                outbound,
+                used,
+                limit,
+            })
+            .collect();
+        quotas.sort_by(|a, b| a.outbound.cmp(&b.outbound));
+        let mut outbounds: Vec<models::OutboundStats> = rm
+            .conn_manager()
+            .outbound_stats()
+            .into_iter()
+            .map(|(outbound, stats)| models::OutboundStats {
+                outbound,
+                uplink: stats.uplink,
+                downlink: stats.downlink,
+                connections: stats.connections,
+            })
+            .collect();
+        outbounds.sort_by(|a, b| a.outbound.cmp(&b.outbound));
+        let mut health_checks = Vec::new();
+        for (outbound, stats) in rm.health_stats().await {
+            for (network, latencies) in [("tcp", stats.tcp()), ("udp", stats.udp())] {
+                for (actor, latency) in latencies {
+                    health_checks.push(models::HealthCheck {
+                        outbound: outbound.clone(),
+                        actor,
+                        network,
+                        latency,
+                    });
+                }
+            }
+        }
+        health_checks.sort_by(|a, b| {
+            (&a.outbound, a.network, &a.actor).cmp(&(&b.outbound, b.network, &b.actor))
+        });
+        Ok(warp::reply::json(&models::Stats {
+            uplink,
+            downlink,
+            connections: rm.conn_manager().connections().len(),
+            quotas,
+            outbounds,
+            health_checks,
+        }))
+    }
+
+    // Lists the runtimes of the process, not only the one serving the API.
+    pub async fn runtimes() -> Result<impl warp::Reply, Infallible> {
+        let runtimes: Vec<models::Runtime> = crate::runtime_ids()
+            .into_iter()
+            .filter_map(|id| {
+                let rm = crate::RUNTIME_MANAGER.lock().unwrap().get(&id).cloned()?;
+                let (uplink, downlink) = rm.conn_manager().traffic();
+                Some(models::Runtime {
+                    id,
+                    uptime: rm.uptime().as_secs(),
+                    inbounds: rm
+                        .inbound_addrs()
+                        .iter()
+                        .map(|(tag, addr)| (tag.to_owned(), addr.to_string()))
+                        .collect(),
+                    uplink,
+                    downlink,
+                    connections: rm.conn_manager().connections().len(),
+                })
+            })
+            .collect();
+        Ok(warp::reply::json(&runtimes))
+    }
+
+    pub async fn runtime_connections(
+        rm: Arc<RuntimeManager>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let conns: Vec<models::Connection> = rm
+            .conn_manager()
+            .connections()
+            .iter()
+            .map(|c| models::Connection {
+                id: c.id,
+                network: c.network.to_string(),
+                source: c.source.clone(),
+                destination: c.destination.clone(),
+                inbound_tag: c.inbound_tag.clone(),
+                outbound_tag: c.outbound_tag.clone(),
+                start_time: c.start_time,
+                age: c.age(),
+                uplink: c.uplink(),
+                downlink: c.downlink(),
+            })
+            .collect();
+        Ok(warp::reply::json(&conns))
+    }
+
+    pub async fn runtime_connection_kill(
+        id: u64,
+        rm: Arc<RuntimeManager>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        if rm.conn_manager().kill(id) {
+            Ok(StatusCode::OK)
+        } else {
+            Ok(StatusCode::NOT_FOUND)
+        }
+    }
+
+    pub async fn runtime_inbounds(rm: Arc<RuntimeManager>) -> Result<impl warp::Reply, Infallible> {
+        let addrs: HashMap<String, String> = rm
+            .inbound_addrs()
+            .iter()
+            .map(|(tag, addr)| (tag.to_owned(), addr.to_string()))
+            .collect();
+        Ok(warp::reply::json(&addrs))
+    }
+
+    // Streams newline-delimited JSON events until the consumer goes away.
+    pub async fn runtime_events(rm: Arc<RuntimeManager>) -> Result<impl warp::Reply, Infallible> {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let rx = rm.conn_manager().events().subscribe();
+        let lines = futures::stream::unfold(rx, |mut rx| async move {
+            let line = match rx.recv().await {
+                Ok(ev) => serde_json::to_string(&models::Event::from(&*ev)),
+                Err(RecvError::Lagged(n)) => {
+                    if *crate::option::EVENT_STREAM_DISCONNECT_LAGGING {
+                        log::debug!("disconnect event stream lagging behind {} events", n);
+                        return None;
+                    }
+                    serde_json::to_string(&models::Event::Dropped { count: n })
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            let mut line = line.unwrap_or_default();
+            line.push('\n');
+            Some((Ok::<_, Infallible>(line), rx))
+        });
+        Ok(warp::http::Response::builder()
+            .header("content-type", "application/x-ndjson")
+            .body(warp::hyper::Body::wrap_stream(lines)))
+    }
+
+    pub async fn runtime_dns_flush(
+        rm: Arc<RuntimeManager>,
+    ) -> Result<impl warp::Reply, Infallible> {
+        rm.flush_dns_cache().await;
+        Ok(StatusCode::OK)
+    }
+
+    pub async fn recover(err: warp::Rejection) -> Result<impl warp::Reply, warp::Rejection> {
+        if err.find::<filters::Unauthorized>().is_some() {
+            Ok(StatusCode::UNAUTHORIZED)
+        } else {
+            Err(err)
+        }
+    }
 }
 
 mod filters {
     use super::*;
 
+    #[derive(Debug)]
+    pub struct Unauthorized;
+
+    impl warp::reject::Reject for Unauthorized {}
+
+    // Requires `Authorization: Bearer <secret>` if a secret is configured.
+    pub fn with_auth(
+        secret: Option<String>,
+    ) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("authorization")
+            .and_then(move |auth: Option<String>| {
+                let secret = secret.clone();
+                async move {
+                    let secret = match secret {
+                        Some(s) => s,
+                        None => return Ok(()),
+                    };
+                    match auth.as_deref().and_then(|v| v.strip_prefix("Bearer ")) {
+                        Some(token) if secret_matches(token, &secret) => Ok(()),
+                        _ => Err(warp::reject::custom(Unauthorized)),
+                    }
+                }
+            })
+            .untuple_one()
+    }
+
+    // Compares in constant time so the secret can't be guessed byte by byte
+    // from the response time.
+    fn secret_matches(token: &str, secret: &str) -> bool {
+        let (token, secret) = (token.as_bytes(), secret.as_bytes());
+        token.len() == secret.len()
+            && token
+                .iter()
+                .zip(secret)
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+
     fn with_runtime_manager(
         rm: Arc<RuntimeManager>,
     ) -> impl Filter<Extract = (Arc<RuntimeManager>,), Error = Infallible> + Clone {
@@ -117,6 +465,16 @@ mod filters {
             .and_then(handlers::runtime_reload)
     }
 
+    // POST /api/v1/runtime/assets/reload
+    pub fn runtime_reload_assets(
+        rm: Arc<RuntimeManager>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtime" / "assets" / "reload")
+            .and(warp::post())
+            .and(with_runtime_manager(rm))
+            .and_then(handlers::runtime_reload_assets)
+    }
+
     // POST /api/v1/runtime/shutdown
     pub fn runtime_shutdown(
         rm: Arc<RuntimeManager>,
@@ -126,23 +484,113 @@ mod filters {
             .and(with_runtime_manager(rm))
             .and_then(handlers::runtime_shutdown)
     }
+
+    // GET /api/v1/runtime/stats
+    pub fn runtime_stats(
+        rm: Arc<RuntimeManager>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtime" / "stats")
+            .and(warp::get())
+            .and(with_runtime_manager(rm))
+            .and_then(handlers::runtime_stats)
+    }
+
+    // GET /api/v1/runtimes
+    pub fn runtimes() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtimes")
+            .and(warp::get())
+            .and_then(handlers::runtimes)
+    }
+
+    // GET /api/v1/runtime/connections
+    pub fn runtime_connections(
+        rm: Arc<RuntimeManager>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtime" / "connections")
+            .and(warp::get())
+            .and(with_runtime_manager(rm))
+            .and_then(handlers::runtime_connections)
+    }
+
+    // DELETE /api/v1/runtime/connections/42
+    pub fn runtime_connection_kill(
+        rm: Arc<RuntimeManager>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtime" / "connections" / u64)
+            .and(warp::delete())
+            .and(with_runtime_manager(rm))
+            .and_then(handlers::runtime_connection_kill)
+    }
+
+    // GET /api/v1/runtime/inbounds
+    pub fn runtime_inbounds(
+        rm: Arc<RuntimeManager>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtime" / "inbounds")
+            .and(warp::get())
+            .and(with_runtime_manager(rm))
+            .and_then(handlers::runtime_inbounds)
+    }
+
+    // GET /api/v1/runtime/events
+    pub fn runtime_events(
+        rm: Arc<RuntimeManager>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtime" / "events")
+            .and(warp::get())
+            .and(with_runtime_manager(rm))
+            .and_then(handlers::runtime_events)
+    }
+
+    // POST /api/v1/runtime/dns/flush
+    pub fn runtime_dns_flush(
+        rm: Arc<RuntimeManager>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "runtime" / "dns" / "flush")
+            .and(warp::post())
+            .and(with_runtime_manager(rm))
+            .and_then(handlers::runtime_dns_flush)
+    }
 }
 
 pub struct ApiServer {
     runtime_manager: Arc<RuntimeManager>,
+    secret: Option<String>,
 }
 
 impl ApiServer {
-    pub fn new(runtime_manager: Arc<RuntimeManager>) -> Self {
-        Self { runtime_manager }
+    /// Requests must carry the secret as a bearer token if one is given.
+    pub fn new(runtime_manager: Arc<RuntimeManager>, secret: Option<String>) -> Self {
+        Self {
+            runtime_manager,
+            secret,
+        }
     }
 
-    pub fn serve(&self, listen_addr: SocketAddr) -> crate::Runner {
+    /// Binds the listen address, returns the bound address and the runner
+    /// serving the requests.
+    pub fn serve(&self, listen_addr: SocketAddr) -> Result<(SocketAddr, crate::Runner)> {
         let routes = filters::select_update(self.runtime_manager.clone())
             .or(filters::select_get(self.runtime_manager.clone()))
             .or(filters::runtime_reload(self.runtime_manager.clone()))
-            .or(filters::runtime_shutdown(self.runtime_manager.clone()));
-        log::info!("api server listening tcp {}", &listen_addr);
-        Box::pin(warp::serve(routes).bind(listen_addr))
+            .or(filters::runtime_reload_assets(self.runtime_manager.clone()))
+            .or(filters::runtime_shutdown(self.runtime_manager.clone()))
+            .or(filters::runtime_stats(self.runtime_manager.clone()))
+            .or(filters::runtimes())
+            .or(filters::runtime_connections(self.runtime_manager.clone()))
+            .or(filters::runtime_connection_kill(
+                self.runtime_manager.clone(),
+            ))
+            .or(filters::runtime_inbounds(self.runtime_manager.clone()))
+            .or(filters::runtime_events(self.runtime_manager.clone()))
+            .or(filters::runtime_dns_flush(self.runtime_manager.clone()));
+        let routes = filters::with_auth(self.secret.clone())
+            .and(routes)
+            .recover(handlers::recover);
+        let (addr, server) = warp::serve(routes)
+            .try_bind_ephemeral(listen_addr)
+            .map_err(|e| anyhow!("bind api server {} failed: {}", &listen_addr, e))?;
+        log::info!("api server listening tcp {}", &addr);
+        Ok((addr, Box::pin(server)))
     }
 }
@@ -0,0 +1,628 @@
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use futures::future::{self, Either};
+use futures::task::AtomicWaker;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::Notify;
+
+use crate::proxy::{OutboundDatagram, OutboundDatagramRecvHalf, OutboundDatagramSendHalf};
+use crate::session::{Network, Session, SocksAddr, Traffic};
+
+use super::events::{Event, EventBus};
+
+/// A TCP connection or UDP session being relayed.
+pub struct Connection {
+    pub id: u64,
+    pub network: Network,
+    pub source: String,
+    pub destination: String,
+    pub inbound_tag: String,
+    pub outbound_tag: String,
+    /// Unix timestamp in seconds.
+    pub start_time: u64,
+    uplink: AtomicU64,
+    downlink: AtomicU64,
+    // The traffic of the session, shared with the session.
+    traffic: Arc<Traffic>,
+    killed: AtomicBool,
+    // One waker per half polling the connection, a shared one would only
+    // wake the half which polled last.
+    wakers: Mutex<Vec<Arc<AtomicWaker>>>,
+}
+
+impl Connection {
+    pub fn uplink(&self) -> u64 {
+        self.uplink.load(Ordering::Relaxed)
+    }
+
+    pub fn downlink(&self) -> u64 {
+        self.downlink.load(Ordering::Relaxed)
+    }
+
+    fn add_uplink(&self, n: u64) {
+        self.uplink.fetch_add(n, Ordering::Relaxed);
+        self.traffic.add_uplink(n);
+    }
+
+    fn add_downlink(&self, n: u64) {
+        self.downlink.fetch_add(n, Ordering::Relaxed);
+        self.traffic.add_downlink(n);
+    }
+
+    /// Seconds since the connection started.
+    pub fn age(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs().saturating_sub(self.start_time))
+            .unwrap_or(0)
+    }
+
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+        for waker in self.wakers.lock().unwrap().iter() {
+            waker.wake();
+        }
+    }
+
+    // Returns a waker to be woken once the connection is killed.
+    fn waker(&self) -> Arc<AtomicWaker> {
+        let waker = Arc::new(AtomicWaker::new());
+        self.wakers.lock().unwrap().push(waker.clone());
+        waker
+    }
+
+    fn poll_killed(&self, waker: &AtomicWaker, cx: &mut Context<'_>) -> Option<io::Error> {
+        waker.register(cx.waker());
+        if self.killed.load(Ordering::Relaxed) {
+            Some(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "connection killed",
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Traffic and active connections of an outbound.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OutboundStats {
+    pub uplink: u64,
+    pub downlink: u64,
+    pub connections: usize,
+}
+
+/// Keeps track of the connections being relayed, and the traffic of the
+/// connections.
+#[derive(Default)]
+pub struct ConnManager {
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Arc<Connection>>>,
+    // Traffic of closed connections.
+    uplink: AtomicU64,
+    downlink: AtomicU64,
+    // Traffic of closed connections by outbound tag.
+    outbound_traffic: Mutex<HashMap<String, (u64, u64)>>,
+    // Number of closed connections.
+    finished: AtomicUsize,
+    events: EventBus,
+    // Notified whenever a connection is removed.
+    closed: Notify,
+}
+
+impl ConnManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers a connection, the connection is removed once the returned
+    /// guard drops.
+    pub fn track(self: &Arc<Self>, sess: &Session, outbound_tag: &str) -> ConnGuard {
+        let conn = Arc::new(Connection {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            network: sess.network,
+            source: sess.source.to_string(),
+            destination: sess.destination.to_string(),
+            inbound_tag: sess.inbound_tag.clone(),
+            outbound_tag: outbound_tag.to_string(),
+            start_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            uplink: AtomicU64::new(0),
+            downlink: AtomicU64::new(0),
+            traffic: sess.traffic.clone(),
+            killed: AtomicBool::new(false),
+            wakers: Mutex::new(Vec::new()),
+        });
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(conn.id, conn.clone());
+        self.events.publish(Event::SessionStart {
+            id: conn.id,
+            network: conn.network.to_string(),
+            source: conn.source.clone(),
+            destination: conn.destination.clone(),
+            inbound_tag: conn.inbound_tag.clone(),
+            outbound_tag: conn.outbound_tag.clone(),
+        });
+        ConnGuard {
+            manager: self.clone(),
+            conn,
+        }
+    }
+
+    pub fn connections(&self) -> Vec<Arc<Connection>> {
+        let mut conns: Vec<Arc<Connection>> =
+            self.connections.lock().unwrap().values().cloned().collect();
+        conns.sort_by_key(|c| c.id);
+        conns
+    }
+
+    /// Closes a connection, returns false if the connection is not found.
+    pub fn kill(&self, id: u64) -> bool {
+        if let Some(conn) = self.connections.lock().unwrap().get(&id) {
+            conn.kill();
+            return true;
+        }
+        false
+    }
+
+    /// Waits up to `timeout` for the connections to finish, then kills the
+    /// remaining ones. Returns the number of connections finished during the
+    /// wait and the number killed.
+    pub async fn drain(&self, timeout: Duration) -> (usize, usize) {
+        let finished = self.finished.load(Ordering::Relaxed);
+        let wait = async {
+            loop {
+                let closed = self.closed.notified();
+                if self.connections.lock().unwrap().is_empty() {
+                    break;
+                }
+                closed.await;
+            }
+        };
+        let _ = tokio::time::timeout(timeout, wait).await;
+        let remaining = self.connections();
+        for conn in remaining.iter() {
+            conn.kill();
+        }
+        (
+            self.finished.load(Ordering::Relaxed) - finished,
+            remaining.len(),
+        )
+    }
+
+    /// Session start and end events of the tracked connections, also carries
+    /// dispatch errors.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Total uplink and downlink traffic in bytes, including the running
+    /// connections.
+    pub fn traffic(&self) -> (u64, u64) {
+        let mut uplink = self.uplink.load(Ordering::Relaxed);
+        let mut downlink = self.downlink.load(Ordering::Relaxed);
+        for conn in self.connections.lock().unwrap().values() {
+            uplink += conn.uplink();
+            downlink += conn.downlink();
+        }
+        (uplink, downlink)
+    }
+
+    /// Traffic and active connections by outbound tag, the traffic includes
+    /// the running connections.
+    pub fn outbound_stats(&self) -> HashMap<String, OutboundStats> {
+        let mut stats: HashMap<String, OutboundStats> = self
+            .outbound_traffic
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tag, (uplink, downlink))| {
+                (
+                    tag.clone(),
+                    OutboundStats {
+                        uplink: *uplink,
+                        downlink: *downlink,
+                        connections: 0,
+                    },
+                )
+            })
+            .collect();
+        for conn in self.connections.lock().unwrap().values() {
+            let s = stats.entry(conn.outbound_tag.clone()).or_default();
+            s.uplink += conn.uplink();
+            s.downlink += conn.downlink();
+            s.connections += 1;
+        }
+        stats
+    }
+}
+
+pub struct ConnGuard {
+    manager: Arc<ConnManager>,
+    conn: Arc<Connection>,
+}
+
+impl ConnGuard {
+    pub fn connection(&self) -> Arc<Connection> {
+        self.conn.clone()
+    }
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        self.manager
+            .connections
+            .lock()
+            .unwrap()
+            .remove(&self.conn.id);
+        self.manager
+            .uplink
+            .fetch_add(self.conn.uplink(), Ordering::Relaxed);
+        self.manager
+            .downlink
+            .fetch_add(self.conn.downlink(), Ordering::Relaxed);
+        {
+            let mut outbound_traffic = self.manager.outbound_traffic.lock().unwrap();
+            let traffic = outbound_traffic
+                .entry(self.conn.outbound_tag.clone())
+                .or_default();
+            traffic.0 += self.conn.uplink();
+            traffic.1 += self.conn.downlink();
+        }
+        self.manager.finished.fetch_add(1, Ordering::Relaxed);
+        self.manager.events.publish(Event::SessionEnd {
+            id: self.conn.id,
+            uplink: self.conn.uplink(),
+            downlink: self.conn.downlink(),
+        });
+        self.manager.closed.notify_waiters();
+    }
+}
+
+/// Wraps a stream of a tracked connection, I/O fails once the connection is
+/// killed. Traffic is counted if the stream is the outbound side, for both the
+/// connection and its session.
+pub struct TrackedStream<T> {
+    inner: T,
+    conn: Arc<Connection>,
+    outbound: bool,
+    read_waker: Arc<AtomicWaker>,
+    write_waker: Arc<AtomicWaker>,
+}
+
+impl<T> TrackedStream<T> {
+    fn new(inner: T, conn: Arc<Connection>, outbound: bool) -> Self {
+        TrackedStream {
+            inner,
+            read_waker: conn.waker(),
+            write_waker: conn.waker(),
+            conn,
+            outbound,
+        }
+    }
+
+    pub fn inbound(inner: T, conn: Arc<Connection>) -> Self {
+        Self::new(inner, conn, false)
+    }
+
+    pub fn outbound(inner: T, conn: Arc<Connection>) -> Self {
+        Self::new(inner, conn, true)
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for TrackedStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some(e) = self.conn.poll_killed(&self.read_waker, cx) {
+            return Poll::Ready(Err(e));
+        }
+        let filled = buf.filled().len();
+        let res = AsyncRead::poll_read(Pin::new(&mut self.inner), cx, buf);
+        if self.outbound {
+            if let Poll::Ready(Ok(())) = res {
+                let n = (buf.filled().len() - filled) as u64;
+                self.conn.add_downlink(n);
+            }
+        }
+        res
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for TrackedStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if let Some(e) = self.conn.poll_killed(&self.write_waker, cx) {
+            return Poll::Ready(Err(e));
+        }
+        let res = AsyncWrite::poll_write(Pin::new(&mut self.inner), cx, buf);
+        if self.outbound {
+            if let Poll::Ready(Ok(n)) = res {
+                self.conn.add_uplink(n as u64);
+            }
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.inner), cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_shutdown(Pin::new(&mut self.inner), cx)
+    }
+}
+
+/// Wraps the outbound datagram of a tracked UDP session, counting the traffic.
+/// Receiving fails once the session is killed, the session is removed after
+/// both halves drop.
+pub struct TrackedDatagram {
+    inner: Box<dyn OutboundDatagram>,
+    guard: ConnGuard,
+}
+
+impl TrackedDatagram {
+    pub fn new(inner: Box<dyn OutboundDatagram>, guard: ConnGuard) -> Self {
+        TrackedDatagram { inner, guard }
+    }
+}
+
+impl OutboundDatagram for TrackedDatagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ) {
+        let guard = Arc::new(self.guard);
+        let waker = guard.conn.waker();
+        let (r, s) = self.inner.split();
+        (
+            Box::new(TrackedDatagramRecvHalf(r, guard.clone(), waker)),
+            Box::new(TrackedDatagramSendHalf(s, guard)),
+        )
+    }
+}
+
+pub struct TrackedDatagramRecvHalf(
+    Box<dyn OutboundDatagramRecvHalf>,
+    Arc<ConnGuard>,
+    Arc<AtomicWaker>,
+);
+
+#[async_trait]
+impl OutboundDatagramRecvHalf for TrackedDatagramRecvHalf {
+    async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocksAddr)> {
+        let conn = self.1.conn.clone();
+        let waker = self.2.clone();
+        let killed = future::poll_fn(|cx| match conn.poll_killed(&waker, cx) {
+            Some(e) => Poll::Ready(e),
+            None => Poll::Pending,
+        });
+        let (n, addr) = match future::select(self.0.recv_from(buf), killed).await {
+            Either::Left((res, _)) => res?,
+            Either::Right((e, _)) => return Err(e),
+        };
+        conn.add_downlink(n as u64);
+        Ok((n, addr))
+    }
+}
+
+pub struct TrackedDatagramSendHalf(Box<dyn OutboundDatagramSendHalf>, Arc<ConnGuard>);
+
+#[async_trait]
+impl OutboundDatagramSendHalf for TrackedDatagramSendHalf {
+    async fn send_to(&mut self, buf: &[u8], dst_addr: &SocksAddr) -> io::Result<usize> {
+        if self.1.conn.killed.load(Ordering::Relaxed) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "connection killed",
+            ));
+        }
+        let n = self.0.send_to(buf, dst_addr).await?;
+        self.1.conn.add_uplink(n as u64);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outbound_stats() {
+        let manager = Arc::new(ConnManager::new());
+        let sess = Session::default();
+        let a = manager.track(&sess, "a");
+        let b = manager.track(&sess, "b");
+        a.connection().uplink.fetch_add(10, Ordering::Relaxed);
+        b.connection().downlink.fetch_add(20, Ordering::Relaxed);
+        let closed = manager.track(&sess, "a");
+        closed.connection().downlink.fetch_add(5, Ordering::Relaxed);
+        drop(closed);
+
+        let stats = manager.outbound_stats();
+        assert_eq!(
+            stats["a"],
+            OutboundStats {
+                uplink: 10,
+                downlink: 5,
+                connections: 1,
+            }
+        );
+        assert_eq!(
+            stats["b"],
+            OutboundStats {
+                uplink: 0,
+                downlink: 20,
+                connections: 1,
+            }
+        );
+        assert_eq!(manager.traffic(), (10, 25));
+
+        drop((a, b));
+        assert_eq!(manager.outbound_stats()["a"].connections, 0);
+        assert_eq!(manager.traffic(), (10, 25));
+    }
+
+    struct PendingDatagram;
+
+    impl OutboundDatagram for PendingDatagram {
+        fn split(
+            self: Box<Self>,
+        ) -> (
+            Box<dyn OutboundDatagramRecvHalf>,
+            Box<dyn OutboundDatagramSendHalf>,
+        ) {
+            (Box::new(PendingDatagram), Box::new(PendingDatagram))
+        }
+    }
+
+    #[async_trait]
+    impl OutboundDatagramRecvHalf for PendingDatagram {
+        async fn recv_from(&mut self, _buf: &mut [u8]) -> io::Result<(usize, SocksAddr)> {
+            futures::future::pending().await
+        }
+    }
+
+    #[async_trait]
+    impl OutboundDatagramSendHalf for PendingDatagram {
+        async fn send_to(&mut self, buf: &[u8], _dst_addr: &SocksAddr) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn test_tracked_datagram() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let manager = Arc::new(ConnManager::new());
+            let sess = Session {
+                network: Network::Udp,
+                ..Default::default()
+            };
+            let guard = manager.track(&sess, "direct");
+            let id = guard.connection().id;
+            let datagram = Box::new(TrackedDatagram::new(Box::new(PendingDatagram), guard));
+            let (mut r, mut s) = datagram.split();
+            s.send_to(&[0u8; 8], &sess.destination).await.unwrap();
+            assert_eq!(manager.outbound_stats()["direct"].uplink, 8);
+            assert_eq!(sess.traffic.uplink(), 8);
+
+            let recv = tokio::spawn(async move {
+                let mut buf = [0u8; 8];
+                r.recv_from(&mut buf).await.map(|_| ())
+            });
+            tokio::task::yield_now().await;
+            assert!(manager.kill(id));
+            let err = recv.await.unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+            assert!(s.send_to(&[0u8; 8], &sess.destination).await.is_err());
+            assert_eq!(manager.connections().len(), 1);
+
+            drop(s);
+            assert!(manager.connections().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_tracked_stream_traffic() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let manager = Arc::new(ConnManager::new());
+            let sess = Session::default();
+            let guard = manager.track(&sess, "direct");
+            let (client, mut server) = tokio::io::duplex(1024);
+            let mut stream = TrackedStream::outbound(client, guard.connection());
+            stream.write_all(b"hello").await.unwrap();
+            server.write_all(b"hi").await.unwrap();
+            let mut buf = [0u8; 2];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(guard.connection().uplink(), 5);
+            assert_eq!(guard.connection().downlink(), 2);
+            // The session shares the traffic.
+            assert_eq!(sess.traffic.uplink(), 5);
+            assert_eq!(sess.traffic.downlink(), 2);
+        });
+    }
+
+    #[test]
+    fn test_kill_wakes_both_halves() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let manager = Arc::new(ConnManager::new());
+            let guard = manager.track(&Session::default(), "direct");
+            let (client, _server) = tokio::io::duplex(4);
+            let stream = TrackedStream::inbound(client, guard.connection());
+            let (mut r, mut w) = tokio::io::split(stream);
+            // Both halves are pending, the reader on no data and the writer
+            // on a full buffer.
+            let read = tokio::spawn(async move {
+                let mut buf = [0u8; 8];
+                r.read(&mut buf).await.map(|_| ())
+            });
+            let write = tokio::spawn(async move { w.write_all(&[0u8; 16]).await });
+            tokio::task::yield_now().await;
+            assert!(manager.kill(guard.connection().id));
+            let err = read.await.unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+            let err = write.await.unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+        });
+    }
+
+    #[test]
+    fn test_drain() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let manager = Arc::new(ConnManager::new());
+            let sess = Session::default();
+            let finishing = manager.track(&sess, "direct");
+            let stuck = manager.track(&sess, "direct");
+            let stuck_conn = stuck.connection();
+            let manager2 = manager.clone();
+            let started = tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                drop(finishing);
+                // A connection started and finished during the drain.
+                drop(manager2.track(&Session::default(), "direct"));
+                // And another one left running.
+                manager2.track(&Session::default(), "direct")
+            });
+            let res = manager.drain(Duration::from_millis(200)).await;
+            assert_eq!(res, (2, 2));
+            assert!(stuck_conn.killed.load(Ordering::Relaxed));
+            drop((stuck, started.await.unwrap()));
+            assert_eq!(manager.drain(Duration::from_secs(1)).await, (0, 0));
+        });
+    }
+}
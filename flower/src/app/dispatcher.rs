@@ -13,11 +13,18 @@ use crate::{
     app::SyncDnsClient,
     common::sniff,
     option,
-    proxy::{OutboundDatagram, ProxyStream, TcpOutboundHandler, UdpOutboundHandler},
+    proxy::{
+        stream::ShutdownOnDrop, NoDelaySwitch, OutboundDatagram, ProxyStream, TcpOutboundHandler,
+        UdpOutboundHandler,
+    },
     session::{Network, Session, SocksAddr},
 };
 
+use super::conn_manager::{ConnManager, TrackedDatagram, TrackedStream};
+use super::events::Event;
+use super::logger;
 use super::outbound::manager::OutboundManager;
+use super::outbound::quota::{QuotaDatagram, QuotaStream};
 use super::router::Router;
 
 #[inline]
@@ -53,6 +60,7 @@ pub struct Dispatcher {
     outbound_manager: Arc<RwLock<OutboundManager>>,
     router: Arc<RwLock<Router>>,
     dns_client: SyncDnsClient,
+    conn_manager: Arc<ConnManager>,
 }
 
 impl Dispatcher {
@@ -60,81 +68,116 @@ impl Dispatcher {
         outbound_manager: Arc<RwLock<OutboundManager>>,
         router: Arc<RwLock<Router>>,
         dns_client: SyncDnsClient,
+        conn_manager: Arc<ConnManager>,
     ) -> Self {
         Dispatcher {
             outbound_manager,
             router,
             dns_client,
+            conn_manager,
         }
     }
 
+    /// Returns the DNS client, whose fake DNS is shared with the inbounds
+    /// answering queries with fake IPs.
+    pub fn dns_client(&self) -> &SyncDnsClient {
+        &self.dns_client
+    }
+
+    /// Maps a destination targeting a fake IP back to the domain the IP is
+    /// allocated for.
+    pub async fn restore_fake_destination(&self, addr: &SocksAddr) -> Option<SocksAddr> {
+        let ip = addr.ip()?;
+        let domain = self.dns_client.read().await.reverse_lookup(&ip).await?;
+        SocksAddr::try_from((domain, addr.port())).ok()
+    }
+
     pub async fn dispatch_tcp<T>(&self, sess: &mut Session, lhs: T)
     where
         T: 'static + AsyncRead + AsyncWrite + Unpin + Send + Sync,
     {
-        let mut lhs: Box<dyn ProxyStream> =
-            if !sess.destination.is_domain() && sess.destination.port() == 443 {
-                let mut lhs = sniff::SniffingStream::new(lhs);
-                match lhs.sniff().await {
-                    Ok(res) => {
-                        if let Some(domain) = res {
-                            debug!(
-                                "sniffed domain {} for tcp link {} <-> {}",
-                                &domain, &sess.source, &sess.destination,
-                            );
-                            sess.destination =
-                                match SocksAddr::try_from((&domain, sess.destination.port())) {
-                                    Ok(a) => a,
-                                    Err(e) => {
-                                        debug!(
-                                            "convert sniffed domain {} to destination failed: {}",
-                                            &domain, e,
-                                        );
-                                        return;
-                                    }
-                                };
-                        }
-                    }
-                    Err(e) => {
-                        trace!(
-                            "sniff tcp uplink {} -> {} failed: {}",
-                            &sess.source,
-                            &sess.destination,
-                            e,
-                        );
-                        return;
+        self.dispatch_tcp_with_nodelay(sess, lhs, None).await
+    }
+
+    /// Same as `dispatch_tcp`, the optional switch of the inbound socket is
+    /// flipped to Nagle once the outbound handshake is done and the link
+    /// enters relay.
+    pub async fn dispatch_tcp_with_nodelay<T>(
+        &self,
+        sess: &mut Session,
+        lhs: T,
+        lhs_nodelay: Option<NoDelaySwitch>,
+    ) where
+        T: 'static + AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    {
+        logger::session_scope(self.relay_tcp(sess, lhs, lhs_nodelay)).await
+    }
+
+    async fn relay_tcp<T>(&self, sess: &mut Session, lhs: T, lhs_nodelay: Option<NoDelaySwitch>)
+    where
+        T: 'static + AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    {
+        if let Some(destination) = self.restore_fake_destination(&sess.destination).await {
+            debug!(
+                "restored fake destination {} to {}",
+                &sess.destination, &destination
+            );
+            sess.destination = destination;
+        }
+        let sniff_domain = !sess.destination.is_domain() && sess.destination.port() == 443;
+        let (sniff_protocol, route_only) = {
+            let router = self.router.read().await;
+            (router.sniff_protocol(sess), router.route_only())
+        };
+        let mut lhs: Box<dyn ProxyStream> = if sniff_domain || sniff_protocol {
+            let mut lhs = sniff::SniffingStream::new(lhs);
+            match lhs.sniff().await {
+                Ok(res) => {
+                    sess.protocol = lhs.protocol();
+                    // Connects to the sniffed domain as well unless only
+                    // routing by it.
+                    if let Some(domain) = res.as_ref().filter(|_| sniff_domain && !route_only) {
+                        debug!("sniffed domain {} for tcp link {}", domain, sess);
+                        sess.destination =
+                            match SocksAddr::try_from((domain, sess.destination.port())) {
+                                Ok(a) => a,
+                                Err(e) => {
+                                    debug!(
+                                        "convert sniffed domain {} to destination failed: {}",
+                                        domain, e,
+                                    );
+                                    return;
+                                }
+                            };
                     }
+                    sess.sniffed_host = res;
                 }
-                Box::new(lhs)
-            } else {
-                Box::new(lhs)
-            };
+                Err(e) => {
+                    trace!("sniff tcp uplink {} failed: {}", sess, e);
+                    return;
+                }
+            }
+            Box::new(lhs)
+        } else {
+            Box::new(lhs)
+        };
 
         let outbound = {
             let router = self.router.read().await;
             let outbound = match router.pick_route(sess).await {
                 Ok(tag) => {
-                    debug!(
-                        "picked route [{}] for {} -> {}",
-                        tag, &sess.source, &sess.destination
-                    );
+                    debug!("picked route [{}] for {}", tag, sess);
                     tag.to_owned()
                 }
                 Err(err) => {
                     trace!("pick route failed: {}", err);
                     if let Some(tag) = self.outbound_manager.read().await.default_handler() {
-                        debug!(
-                            "picked default route [{}] for {} -> {}",
-                            tag, &sess.source, &sess.destination
-                        );
+                        debug!("picked default route [{}] for {}", tag, sess);
                         tag
                     } else {
                         warn!("can not find any handlers");
                         if let Err(e) = lhs.shutdown().await {
-                            debug!(
-                                "tcp downlink {} <- {} error: {}",
-                                &sess.source, &sess.destination, e,
-                            );
+                            debug!("tcp downlink {} error: {}", sess, e);
                         }
                         return;
                     }
@@ -143,45 +186,76 @@ impl Dispatcher {
             outbound
         };
 
+        let outbound = match self.outbound_manager.read().await.resolve_quota(&outbound) {
+            Some(tag) => tag,
+            None => {
+                debug!(
+                    "rejected {}, [{}] used up its traffic quota",
+                    sess, &outbound
+                );
+                if let Err(e) = lhs.shutdown().await {
+                    debug!("tcp downlink {} error: {}", sess, e);
+                }
+                return;
+            }
+        };
+        let quota = self.outbound_manager.read().await.quota(&outbound);
+
         let h = if let Some(h) = self.outbound_manager.read().await.get(&outbound) {
             h
         } else {
             // FIXME use  the default handler
             debug!("handler not found");
             if let Err(e) = lhs.shutdown().await {
-                debug!(
-                    "tcp downlink {} <- {} error: {}",
-                    &sess.source, &sess.destination, e,
-                );
+                debug!("tcp downlink {} error: {}", sess, e);
             }
             return;
         };
 
         let handshake_start = tokio::time::Instant::now();
-        let stream =
-            match crate::proxy::connect_tcp_outbound(sess, self.dns_client.clone(), &h).await {
-                Ok(s) => s,
-                Err(e) => {
-                    debug!(
-                        "dispatch tcp {} -> {} to [{}] failed: {}",
-                        &sess.source,
-                        &sess.destination,
-                        &h.tag(),
-                        e
-                    );
-                    return;
-                }
-            };
+        let (stream, rhs_nodelay) = match crate::proxy::connect_tcp_outbound_with_nodelay(
+            sess,
+            self.dns_client.clone(),
+            &h,
+        )
+        .await
+        {
+            Ok(s) => s,
+            Err(e) => {
+                debug!("dispatch {} to [{}] failed: {}", sess, &h.tag(), e);
+                self.conn_manager
+                    .events()
+                    .publish(Event::error(sess, h.tag(), e.to_string()));
+                return;
+            }
+        };
         match TcpOutboundHandler::handle(h.as_ref(), sess, stream).await {
             Ok(rhs) => {
                 let elapsed = tokio::time::Instant::now().duration_since(handshake_start);
 
-                if *crate::option::LOG_NO_COLOR {
-                    log_request(sess, h.tag(), None, elapsed.as_millis());
-                } else {
+                if logger::colored() {
                     log_request(sess, h.tag(), Some(h.color()), elapsed.as_millis());
+                } else {
+                    log_request(sess, h.tag(), None, elapsed.as_millis());
                 }
 
+                for switch in lhs_nodelay.iter().chain(rhs_nodelay.iter()) {
+                    if let Err(e) = switch.enter_relay() {
+                        debug!("disable nodelay for {} failed: {}", sess, e);
+                    }
+                }
+
+                let rhs: Box<dyn ProxyStream> = match quota {
+                    Some(quota) => Box::new(QuotaStream::new(rhs, quota)),
+                    None => rhs,
+                };
+
+                let conn = self.conn_manager.track(sess, h.tag());
+                logger::set_session_id(conn.connection().id);
+                // Both sides are shut down even if the relay is aborted midway.
+                let lhs = ShutdownOnDrop::new(TrackedStream::inbound(lhs, conn.connection()));
+                let rhs = ShutdownOnDrop::new(TrackedStream::outbound(rhs, conn.connection()));
+
                 let (lr, mut lw) = tokio::io::split(lhs);
                 let (rr, mut rw) = tokio::io::split(rhs);
 
@@ -203,22 +277,15 @@ impl Dispatcher {
                         match up_res {
                             Ok(up_n) => {
                                 debug!(
-                                    "tcp uplink {} -> {} done, {} bytes transfered [{}]",
-                                    &sess.source,
-                                    &sess.destination,
+                                    "tcp uplink {} done, {} bytes transfered [{}]",
+                                    sess,
                                     up_n,
                                     &h.tag(),
                                 );
                             }
                             Err(up_e) => {
                                 // FIXME Perhaps we should terminate the pipe immediately.
-                                debug!(
-                                    "tcp uplink {} -> {} error: {} [{}]",
-                                    &sess.source,
-                                    &sess.destination,
-                                    up_e,
-                                    &h.tag()
-                                );
+                                debug!("tcp uplink {} error: {} [{}]", sess, up_e, &h.tag());
                             }
                         }
 
@@ -229,10 +296,9 @@ impl Dispatcher {
                             timeout(Duration::from_secs(*option::TCP_DOWNLINK_TIMEOUT), new_r2l);
 
                         trace!(
-                            "applied {}s downlink timeout to {} <- {}",
+                            "applied {}s downlink timeout to {}",
                             *option::TCP_DOWNLINK_TIMEOUT,
-                            &sess.source,
-                            &sess.destination
+                            sess
                         );
 
                         // Because uplink has been completed, no furture data from the inbound
@@ -267,18 +333,16 @@ impl Dispatcher {
                             Ok(down_res) => match down_res {
                                 Ok(down_n) => {
                                     debug!(
-                                        "tcp downlink {} <- {} done, {} bytes transfered [{}]",
-                                        &sess.source,
-                                        &sess.destination,
+                                        "tcp downlink {} done, {} bytes transfered [{}]",
+                                        sess,
                                         down_n,
                                         &h.tag(),
                                     );
                                 }
                                 Err(down_e) => {
                                     debug!(
-                                        "tcp downlink {} <- {} error: {} [{}]",
-                                        &sess.source,
-                                        &sess.destination,
+                                        "tcp downlink {} error: {} [{}]",
+                                        sess,
                                         down_e,
                                         &h.tag()
                                     );
@@ -286,9 +350,8 @@ impl Dispatcher {
                             },
                             Err(timeout_e) => {
                                 debug!(
-                                    "tcp downlink {} <- {} timeout: {} [{}]",
-                                    &sess.source,
-                                    &sess.destination,
+                                    "tcp downlink {} timeout: {} [{}]",
+                                    sess,
                                     timeout_e,
                                     &h.tag()
                                 );
@@ -313,21 +376,14 @@ impl Dispatcher {
                         match down_res {
                             Ok(down_n) => {
                                 debug!(
-                                    "tcp downlink {} <- {} done, {} bytes transfered [{}]",
-                                    &sess.source,
-                                    &sess.destination,
+                                    "tcp downlink {} done, {} bytes transfered [{}]",
+                                    sess,
                                     down_n,
                                     &h.tag(),
                                 );
                             }
                             Err(down_e) => {
-                                debug!(
-                                    "tcp downlink {} <- {} error: {} [{}]",
-                                    &sess.source,
-                                    &sess.destination,
-                                    down_e,
-                                    &h.tag()
-                                );
+                                debug!("tcp downlink {} error: {} [{}]", sess, down_e, &h.tag());
                             }
                         }
 
@@ -335,10 +391,9 @@ impl Dispatcher {
                             timeout(Duration::from_secs(*option::TCP_UPLINK_TIMEOUT), new_l2r);
 
                         trace!(
-                            "applied {}s uplink timeout to {} -> {}",
+                            "applied {}s uplink timeout to {}",
                             *option::TCP_UPLINK_TIMEOUT,
-                            &sess.source,
-                            &sess.destination
+                            sess
                         );
 
                         // let (shutdown_res, timed_l2r_res) =
@@ -360,31 +415,18 @@ impl Dispatcher {
                             Ok(up_res) => match up_res {
                                 Ok(up_n) => {
                                     debug!(
-                                        "tcp uplink {} -> {} done, {} bytes transfered [{}]",
-                                        &sess.source,
-                                        &sess.destination,
+                                        "tcp uplink {} done, {} bytes transfered [{}]",
+                                        sess,
                                         up_n,
                                         &h.tag(),
                                     );
                                 }
                                 Err(up_e) => {
-                                    debug!(
-                                        "tcp uplink {} -> {} error: {} [{}]",
-                                        &sess.source,
-                                        &sess.destination,
-                                        up_e,
-                                        &h.tag()
-                                    );
+                                    debug!("tcp uplink {} error: {} [{}]", sess, up_e, &h.tag());
                                 }
                             },
                             Err(timeout_e) => {
-                                debug!(
-                                    "tcp uplink {} -> {} timeout: {} [{}]",
-                                    &sess.source,
-                                    &sess.destination,
-                                    timeout_e,
-                                    &h.tag()
-                                );
+                                debug!("tcp uplink {} timeout: {} [{}]", sess, timeout_e, &h.tag());
                             }
                         }
 
@@ -401,65 +443,43 @@ impl Dispatcher {
                 }
 
                 if let Err(e) = rw.shutdown().await {
-                    debug!(
-                        "tcp uplink {} -> {} error: {} [{}]",
-                        &sess.source,
-                        &sess.destination,
-                        e,
-                        &h.tag()
-                    );
+                    debug!("tcp uplink {} error: {} [{}]", sess, e, &h.tag());
                 }
 
                 if let Err(e) = lw.shutdown().await {
-                    debug!(
-                        "tcp downlink {} <- {} error: {} [{}]",
-                        &sess.source,
-                        &sess.destination,
-                        e,
-                        &h.tag()
-                    );
+                    debug!("tcp downlink {} error: {} [{}]", sess, e, &h.tag());
                 }
             }
             Err(e) => {
-                debug!(
-                    "dispatch tcp {} -> {} to [{}] failed: {}",
-                    &sess.source,
-                    &sess.destination,
-                    &h.tag(),
-                    e
-                );
+                debug!("dispatch {} to [{}] failed: {}", sess, &h.tag(), e);
+                self.conn_manager
+                    .events()
+                    .publish(Event::error(sess, h.tag(), e.to_string()));
 
                 if let Err(e) = lhs.shutdown().await {
-                    debug!(
-                        "tcp downlink {} <- {} error: {} [{}]",
-                        &sess.source,
-                        &sess.destination,
-                        e,
-                        &h.tag()
-                    );
+                    debug!("tcp downlink {} error: {} [{}]", sess, e, &h.tag());
                 }
             }
         }
     }
 
     pub async fn dispatch_udp(&self, sess: &Session) -> io::Result<Box<dyn OutboundDatagram>> {
+        let mut sess = sess.clone();
+        if let Some(destination) = self.restore_fake_destination(&sess.destination).await {
+            sess.destination = destination;
+        }
+        let sess = &sess;
         let outbound = {
             let router = self.router.read().await;
             let outbound = match router.pick_route(sess).await {
                 Ok(tag) => {
-                    debug!(
-                        "picked route [{}] for {} -> {}",
-                        tag, &sess.source, &sess.destination
-                    );
+                    debug!("picked route [{}] for {}", tag, sess);
                     tag.to_owned()
                 }
                 Err(err) => {
                     trace!("pick route failed: {}", err);
                     if let Some(tag) = self.outbound_manager.read().await.default_handler() {
-                        debug!(
-                            "picked default route [{}] for {} -> {}",
-                            tag, &sess.source, &sess.destination
-                        );
+                        debug!("picked default route [{}] for {}", tag, sess);
                         tag
                     } else {
                         return Err(io::Error::new(ErrorKind::Other, "no available handler"));
@@ -469,6 +489,17 @@ impl Dispatcher {
             outbound
         };
 
+        let outbound = match self.outbound_manager.read().await.resolve_quota(&outbound) {
+            Some(tag) => tag,
+            None => {
+                return Err(io::Error::new(
+                    ErrorKind::Other,
+                    format!("[{}] used up its traffic quota", &outbound),
+                ));
+            }
+        };
+        let quota = self.outbound_manager.read().await.quota(&outbound);
+
         let h = if let Some(h) = self.outbound_manager.read().await.get(&outbound) {
             h
         } else {
@@ -482,22 +513,24 @@ impl Dispatcher {
             Ok(c) => {
                 let elapsed = tokio::time::Instant::now().duration_since(handshake_start);
 
-                if *crate::option::LOG_NO_COLOR {
-                    log_request(sess, h.tag(), None, elapsed.as_millis());
-                } else {
+                if logger::colored() {
                     log_request(sess, h.tag(), Some(h.color()), elapsed.as_millis());
+                } else {
+                    log_request(sess, h.tag(), None, elapsed.as_millis());
                 }
 
-                Ok(c)
+                let c: Box<dyn OutboundDatagram> = match quota {
+                    Some(quota) => Box::new(QuotaDatagram::new(c, quota)),
+                    None => c,
+                };
+                let conn = self.conn_manager.track(sess, h.tag());
+                Ok(Box::new(TrackedDatagram::new(c, conn)))
             }
             Err(e) => {
-                debug!(
-                    "dispatch udp {} -> {} to [{}] failed: {}",
-                    &sess.source,
-                    &sess.destination,
-                    &h.tag(),
-                    e
-                );
+                debug!("dispatch {} to [{}] failed: {}", sess, &h.tag(), e);
+                self.conn_manager
+                    .events()
+                    .publish(Event::error(sess, h.tag(), e.to_string()));
                 Err(e)
             }
         }
@@ -1,15 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
 use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
-use futures::future::select_ok;
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::Future;
 use log::*;
 use lru::LruCache;
 use rand::{rngs::StdRng, Rng, SeedableRng};
-use tokio::sync::Mutex as TokioMutex;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex as TokioMutex, RwLock};
 use tokio::time::timeout;
 use trust_dns_proto::{
     op::{
@@ -18,20 +22,342 @@ use trust_dns_proto::{
     rr::{record_data::RData, record_type::RecordType, Name},
 };
 
-use crate::{option, proxy::UdpConnector};
+use crate::{
+    app::SyncDnsClient,
+    config::Dns_IpStrategy,
+    option,
+    proxy::{
+        self, AnyOutboundHandler, AnyStream, OutboundConnect, OutboundDatagramRecvHalf,
+        OutboundDatagramSendHalf, TcpOutboundHandler, UdpConnector, UdpOutboundHandler,
+    },
+    session::{Network, Session, SocksAddr},
+};
+
+use super::fake_dns::{FakeDns, FakeDnsMode};
+use super::outbound::manager::OutboundManager;
 
 #[derive(Clone, Debug)]
 struct CacheEntry {
     pub ips: Vec<IpAddr>,
     // The deadline this entry should be considered expired.
     pub deadline: Instant,
+    // The TTL the deadline is derived from.
+    pub ttl: Duration,
+}
+
+// A socket sending queries to a server, either directly or through an
+// outbound, or a TCP stream, either way, where messages are prefixed with
+// their lengths.
+enum QuerySocket {
+    Direct(UdpSocket),
+    Outbound(
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ),
+    Stream(AnyStream),
+}
+
+impl QuerySocket {
+    async fn send_to(&mut self, buf: &[u8], server: &SocketAddr) -> io::Result<usize> {
+        match self {
+            QuerySocket::Direct(s) => s.send_to(buf, server).await,
+            QuerySocket::Outbound(_, s) => s.send_to(buf, &SocksAddr::Ip(*server)).await,
+            QuerySocket::Stream(s) => {
+                let mut msg = Vec::with_capacity(2 + buf.len());
+                msg.extend_from_slice(&(buf.len() as u16).to_be_bytes());
+                msg.extend_from_slice(buf);
+                s.write_all(&msg).await?;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    async fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            QuerySocket::Direct(s) => s.recv_from(buf).await.map(|(n, _)| n),
+            QuerySocket::Outbound(r, _) => r.recv_from(buf).await.map(|(n, _)| n),
+            QuerySocket::Stream(s) => {
+                let n = s.read_u16().await? as usize;
+                if n > buf.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("response too large: {}", n),
+                    ));
+                }
+                s.read_exact(&mut buf[..n]).await?;
+                Ok(n)
+            }
+        }
+    }
+}
+
+// Limits of the response to a DNS over HTTPS query, the body is a DNS
+// message which can't be larger than 64K.
+const MAX_DOH_HEADER_SIZE: usize = 8 * 1024;
+const MAX_DOH_BODY_SIZE: usize = 0xffff;
+// Idle connections kept for each DoH server, and how long they're kept, most
+// servers close idle connections after a while.
+const MAX_DOH_IDLE_CONNECTIONS: usize = 4;
+const DOH_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// A DNS over HTTPS server.
+struct DohServer {
+    url: String,
+    host: String,
+    port: u16,
+    path: String,
+    #[cfg(feature = "outbound-tls")]
+    tls: proxy::tls::outbound::TcpHandler,
+    // Connections kept alive for later queries, along with the time they
+    // became idle.
+    idle: Mutex<Vec<(Instant, AnyStream)>>,
+}
+
+impl DohServer {
+    // Accepts URLs like https://dns.google/dns-query, the port defaults to 443
+    // and the path to /dns-query.
+    fn new(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("https://")
+            .ok_or_else(|| anyhow!("invalid doh server [{}]: not an https url", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/dns-query"),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => {
+                let port = authority[i + 1..]
+                    .parse::<u16>()
+                    .map_err(|e| anyhow!("invalid doh server [{}]: {}", url, e))?;
+                (&authority[..i], port)
+            }
+            _ => (authority, 443),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(anyhow!("invalid doh server [{}]: empty host", url));
+        }
+        // Certificates are verified against domains only by rustls.
+        #[cfg(feature = "rustls-tls")]
+        if host.parse::<IpAddr>().is_ok() {
+            return Err(anyhow!(
+                "invalid doh server [{}]: ip addresses are not supported, use the domain of the server",
+                url
+            ));
+        }
+        Ok(DohServer {
+            url: url.to_owned(),
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+            #[cfg(feature = "outbound-tls")]
+            tls: proxy::tls::outbound::TcpHandler::new(
+                host.to_owned(),
+                Vec::new(),
+                None,
+                None,
+                0,
+                false,
+                proxy::tls::Protocols::default(),
+                Duration::from_secs(*crate::option::TLS_HANDSHAKE_TIMEOUT),
+            )?,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    // The value of the Host header.
+    fn authority(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        if self.port == 443 {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+
+    // Takes the most recently used idle connection, those idle for too long
+    // are dropped.
+    fn take_idle(&self) -> Option<AnyStream> {
+        let mut idle = self.idle.lock().unwrap();
+        idle.retain(|(t, _)| t.elapsed() < DOH_IDLE_TIMEOUT);
+        idle.pop().map(|(_, stream)| stream)
+    }
+
+    fn put_idle(&self, stream: AnyStream) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < MAX_DOH_IDLE_CONNECTIONS {
+            idle.push((Instant::now(), stream));
+        }
+    }
 }
 
+// Decodes a body in chunked transfer encoding, trailers are ignored. Returns
+// None if the body is incomplete.
+fn decode_chunked(mut buf: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    loop {
+        let i = match buf.windows(2).position(|w| w == b"\r\n") {
+            Some(i) => i,
+            None if buf.len() > 32 => return Err(anyhow!("invalid chunk size")),
+            None => return Ok(None),
+        };
+        let size = std::str::from_utf8(&buf[..i])
+            .ok()
+            .and_then(|s| s.split(';').next())
+            .and_then(|s| usize::from_str_radix(s.trim(), 16).ok())
+            .ok_or_else(|| anyhow!("invalid chunk size"))?;
+        buf = &buf[i + 2..];
+        if size == 0 {
+            // The body ends with an empty line, after the trailers if any.
+            if buf.starts_with(b"\r\n") || buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                return Ok(Some(body));
+            }
+            return Ok(None);
+        }
+        if body.len() + size > MAX_DOH_BODY_SIZE {
+            return Err(anyhow!("invalid chunk"));
+        }
+        if buf.len() < size + 2 {
+            return Ok(None);
+        }
+        if &buf[size..size + 2] != b"\r\n" {
+            return Err(anyhow!("invalid chunk"));
+        }
+        body.extend_from_slice(&buf[..size]);
+        buf = &buf[size + 2..];
+    }
+}
+
+// Sends a query in an HTTP/1.1 POST request as described in RFC 8484, returns
+// the body of the response, and whether the connection can be reused.
+async fn doh_exchange<S>(
+    stream: &mut S,
+    server: &DohServer,
+    request: &[u8],
+) -> Result<(Vec<u8>, bool)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/dns-message\r\n\
+         Accept: application/dns-message\r\n\
+         Content-Length: {}\r\n\r\n",
+        server.path,
+        server.authority(),
+        request.len()
+    )
+    .into_bytes();
+    buf.extend_from_slice(request);
+    stream.write_all(&buf).await?;
+    stream.flush().await?;
+
+    let mut buf = Vec::new();
+    let header_len = loop {
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_DOH_HEADER_SIZE {
+            return Err(anyhow!("http header too large"));
+        }
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Err(anyhow!("unexpected eof"));
+        }
+    };
+    let header =
+        std::str::from_utf8(&buf[..header_len]).map_err(|_| anyhow!("invalid http response"))?;
+    let mut lines = header.split("\r\n");
+    let mut status_line = lines
+        .next()
+        .ok_or_else(|| anyhow!("invalid http response"))?
+        .split(' ');
+    // HTTP/1.0 connections are closed by default.
+    let mut keep_alive = status_line.next() == Some("HTTP/1.1");
+    let status = status_line
+        .next()
+        .ok_or_else(|| anyhow!("invalid http response"))?;
+    if status != "200" {
+        return Err(anyhow!("http status {}", status));
+    }
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| anyhow!("invalid content length"))?,
+                );
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            } else if name.eq_ignore_ascii_case("connection") {
+                keep_alive &= !value.eq_ignore_ascii_case("close");
+            }
+        }
+    }
+
+    let mut body = buf.split_off(header_len);
+    if chunked {
+        loop {
+            if let Some(decoded) = decode_chunked(&body)? {
+                return Ok((decoded, keep_alive));
+            }
+            if stream.read_buf(&mut body).await? == 0 {
+                return Err(anyhow!("unexpected eof"));
+            }
+        }
+    }
+    if let Some(n) = content_length {
+        if n > MAX_DOH_BODY_SIZE {
+            return Err(anyhow!("http body too large"));
+        }
+        while body.len() < n {
+            if stream.read_buf(&mut body).await? == 0 {
+                return Err(anyhow!("unexpected eof"));
+            }
+        }
+        // Extra bytes leave the connection in an unknown state.
+        let keep_alive = keep_alive && body.len() == n;
+        body.truncate(n);
+        return Ok((body, keep_alive));
+    }
+    // The body ends along with the connection.
+    while stream.read_buf(&mut body).await? != 0 {
+        if body.len() > MAX_DOH_BODY_SIZE {
+            return Err(anyhow!("http body too large"));
+        }
+    }
+    Ok((body, false))
+}
+
+#[derive(Clone)]
 pub struct DnsClient {
     servers: Vec<SocketAddr>,
-    hosts: HashMap<String, Vec<IpAddr>>,
+    tcp_servers: Vec<SocketAddr>,
+    doh_servers: Arc<Vec<DohServer>>,
+    hosts: Arc<HashMap<String, Vec<IpAddr>>>,
     ipv4_cache: Arc<TokioMutex<LruCache<String, CacheEntry>>>,
     ipv6_cache: Arc<TokioMutex<LruCache<String, CacheEntry>>>,
+    cache_size: usize,
+    cache_enabled: bool,
+    ip_strategy: Dns_IpStrategy,
+    // Records being refreshed in the background.
+    refreshing: Arc<Mutex<HashSet<(String, RecordType)>>>,
+    fake_dns: Option<Arc<TokioMutex<FakeDns>>>,
+    // Tag of the outbound queries are sent through.
+    outbound: Option<String>,
+    outbound_manager: Option<Weak<RwLock<OutboundManager>>>,
+    // Sends queries directly, for resolving the address of the outbound
+    // queries are sent through, which can't be resolved through itself, and
+    // the addresses of DoH servers, also dials the TCP servers directly.
+    bootstrap: Option<SyncDnsClient>,
 }
 
 impl DnsClient {
@@ -40,12 +366,57 @@ impl DnsClient {
         for server in dns.servers.iter() {
             servers.push(SocketAddr::new(server.parse::<IpAddr>()?, 53));
         }
-        if servers.is_empty() {
+        if servers.is_empty() && dns.tcp_servers.is_empty() && dns.doh_servers.is_empty() {
             return Err(anyhow!("no dns servers"));
         }
         Ok(servers)
     }
 
+    // Either an address or an IP with the default port 53.
+    fn parse_server(server: &str) -> Result<SocketAddr> {
+        server
+            .parse::<SocketAddr>()
+            .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .map_err(|_| anyhow!("invalid dns server [{}]", server))
+    }
+
+    fn load_tcp_servers(dns: &crate::config::Dns) -> Result<Vec<SocketAddr>> {
+        dns.tcp_servers
+            .iter()
+            .map(|server| Self::parse_server(server))
+            .collect()
+    }
+
+    fn load_bootstrap_servers(
+        dns: &crate::config::Dns,
+        servers: &[SocketAddr],
+    ) -> Result<Vec<SocketAddr>> {
+        if dns.bootstrap_servers.is_empty() {
+            // The domains of the DoH servers are resolved by them.
+            if servers.is_empty() && !dns.doh_servers.is_empty() {
+                return Err(anyhow!(
+                    "dns over https requires bootstrap servers or plain servers"
+                ));
+            }
+            return Ok(servers.to_vec());
+        }
+        dns.bootstrap_servers
+            .iter()
+            .map(|server| Self::parse_server(server))
+            .collect()
+    }
+
+    fn load_doh_servers(dns: &crate::config::Dns) -> Result<Vec<DohServer>> {
+        #[cfg(not(feature = "outbound-tls"))]
+        if !dns.doh_servers.is_empty() {
+            return Err(anyhow!("dns over https requires the tls outbound"));
+        }
+        dns.doh_servers
+            .iter()
+            .map(|url| DohServer::new(url))
+            .collect()
+    }
+
     fn load_hosts(dns: &crate::config::Dns) -> HashMap<String, Vec<IpAddr>> {
         let mut hosts = HashMap::new();
         for (name, ips) in dns.hosts.iter() {
@@ -64,6 +435,97 @@ impl DnsClient {
         parsed_hosts
     }
 
+    // Returns the static IPs of a host, names like *.example.com match all
+    // subdomains of example.com, the most specific match wins.
+    fn lookup_hosts(&self, host: &str) -> Option<&Vec<IpAddr>> {
+        if self.hosts.is_empty() {
+            return None;
+        }
+        if let Some(ips) = self.hosts.get(host) {
+            return Some(ips);
+        }
+        let mut suffix = host;
+        while let Some(i) = suffix.find('.') {
+            suffix = &suffix[i + 1..];
+            if let Some(ips) = self.hosts.get(&format!("*.{}", suffix)) {
+                return Some(ips);
+            }
+        }
+        None
+    }
+
+    fn load_outbound(dns: &crate::config::Dns) -> Option<String> {
+        if dns.outbound.is_empty() {
+            None
+        } else {
+            Some(dns.outbound.clone())
+        }
+    }
+
+    fn load_fake_dns(dns: &crate::config::Dns) -> Result<Option<Arc<TokioMutex<FakeDns>>>> {
+        let fake_dns = if let Some(fake_dns) = dns.fake_dns.as_ref() {
+            fake_dns
+        } else {
+            return Ok(None);
+        };
+        if !fake_dns.include.is_empty() && !fake_dns.exclude.is_empty() {
+            return Err(anyhow!(
+                "fake DNS run in either include mode or exclude mode"
+            ));
+        }
+        let (mode, filters) = if !fake_dns.include.is_empty() {
+            (FakeDnsMode::Include, &fake_dns.include)
+        } else {
+            (FakeDnsMode::Exclude, &fake_dns.exclude)
+        };
+        let mut f = if fake_dns.range.is_empty() {
+            FakeDns::new(mode)
+        } else {
+            FakeDns::with_range(mode, &fake_dns.range)?
+        };
+        for filter in filters.iter() {
+            f.add_filter(filter.to_owned());
+        }
+        Ok(Some(Arc::new(TokioMutex::new(f))))
+    }
+
+    fn load_cache_size(dns: &crate::config::Dns) -> usize {
+        if dns.cache_size == 0 {
+            *option::DNS_CACHE_SIZE
+        } else {
+            dns.cache_size as usize
+        }
+    }
+
+    fn new_cache(size: usize) -> Arc<TokioMutex<LruCache<String, CacheEntry>>> {
+        Arc::new(TokioMutex::new(LruCache::new(size)))
+    }
+
+    fn new_bootstrap(
+        servers: &[SocketAddr],
+        hosts: &Arc<HashMap<String, Vec<IpAddr>>>,
+        cache_size: usize,
+        cache_enabled: bool,
+        ip_strategy: Dns_IpStrategy,
+    ) -> SyncDnsClient {
+        Arc::new(RwLock::new(DnsClient {
+            servers: servers.to_vec(),
+            tcp_servers: Vec::new(),
+            doh_servers: Arc::new(Vec::new()),
+            hosts: hosts.clone(),
+            ipv4_cache: Self::new_cache(cache_size),
+            ipv6_cache: Self::new_cache(cache_size),
+            cache_size,
+            cache_enabled,
+            ip_strategy,
+            refreshing: Arc::new(Mutex::new(HashSet::new())),
+            fake_dns: None,
+            outbound: None,
+            outbound_manager: None,
+            bootstrap: None,
+        }))
+    }
+
     pub fn new(dns: &protobuf::SingularPtrField<crate::config::Dns>) -> Result<Self> {
         let dns = if let Some(dns) = dns.as_ref() {
             dns
@@ -71,22 +533,61 @@ impl DnsClient {
             return Err(anyhow!("empty dns config"));
         };
         let servers = Self::load_servers(dns)?;
-        let hosts = Self::load_hosts(dns);
-        let ipv4_cache = Arc::new(TokioMutex::new(LruCache::<String, CacheEntry>::new(
-            *option::DNS_CACHE_SIZE,
-        )));
-        let ipv6_cache = Arc::new(TokioMutex::new(LruCache::<String, CacheEntry>::new(
-            *option::DNS_CACHE_SIZE,
-        )));
+        let tcp_servers = Self::load_tcp_servers(dns)?;
+        let doh_servers = Self::load_doh_servers(dns)?;
+        let bootstrap_servers = Self::load_bootstrap_servers(dns, &servers)?;
+        let hosts = Arc::new(Self::load_hosts(dns));
+        let outbound = Self::load_outbound(dns);
+        let cache_size = Self::load_cache_size(dns);
+        let cache_enabled = !dns.disable_cache;
+        let ip_strategy = dns.ip_strategy;
+        let fake_dns = Self::load_fake_dns(dns)?;
+        let bootstrap = (outbound.is_some() || !tcp_servers.is_empty() || !doh_servers.is_empty())
+            .then(|| {
+                Self::new_bootstrap(
+                    &bootstrap_servers,
+                    &hosts,
+                    cache_size,
+                    cache_enabled,
+                    ip_strategy,
+                )
+            });
 
         Ok(DnsClient {
             servers,
+            tcp_servers,
+            doh_servers: Arc::new(doh_servers),
             hosts,
-            ipv4_cache,
-            ipv6_cache,
+            ipv4_cache: Self::new_cache(cache_size),
+            ipv6_cache: Self::new_cache(cache_size),
+            cache_size,
+            cache_enabled,
+            ip_strategy,
+            refreshing: Arc::new(Mutex::new(HashSet::new())),
+            fake_dns,
+            outbound,
+            outbound_manager: None,
+            bootstrap,
         })
     }
 
+    /// Sets the outbound manager the outbound for sending queries is looked
+    /// up from, queries are sent directly until it's set.
+    pub fn set_outbound_manager(&mut self, outbound_manager: Weak<RwLock<OutboundManager>>) {
+        self.outbound_manager = Some(outbound_manager);
+    }
+
+    /// Builds a client with the servers of a new config, sharing the caches
+    /// of this client unless they're resized or disabled. Nothing running is
+    /// touched until it's swapped in.
+    pub fn stage(&self, dns: &protobuf::SingularPtrField<crate::config::Dns>) -> Result<Self> {
+        let mut dns_client = self.clone();
+        dns_client.reload(dns)?;
+        Ok(dns_client)
+    }
+
+    // The fake DNS is kept as is, since the tun inbound answering queries
+    // with it isn't reloaded.
     pub fn reload(&mut self, dns: &protobuf::SingularPtrField<crate::config::Dns>) -> Result<()> {
         let dns = if let Some(dns) = dns.as_ref() {
             dns
@@ -94,12 +595,60 @@ impl DnsClient {
             return Err(anyhow!("empty dns config"));
         };
         let servers = Self::load_servers(dns)?;
-        let hosts = Self::load_hosts(dns);
+        let tcp_servers = Self::load_tcp_servers(dns)?;
+        let doh_servers = Self::load_doh_servers(dns)?;
+        let bootstrap_servers = Self::load_bootstrap_servers(dns, &servers)?;
+        let hosts = Arc::new(Self::load_hosts(dns));
+        let outbound = Self::load_outbound(dns);
+        let cache_size = Self::load_cache_size(dns);
+        let cache_enabled = !dns.disable_cache;
+        let ip_strategy = dns.ip_strategy;
+        self.bootstrap = (outbound.is_some() || !tcp_servers.is_empty() || !doh_servers.is_empty())
+            .then(|| {
+                Self::new_bootstrap(
+                    &bootstrap_servers,
+                    &hosts,
+                    cache_size,
+                    cache_enabled,
+                    ip_strategy,
+                )
+            });
+        // Cached records are dropped if the cache is resized or disabled.
+        if (cache_size, cache_enabled) != (self.cache_size, self.cache_enabled) {
+            self.ipv4_cache = Self::new_cache(cache_size);
+            self.ipv6_cache = Self::new_cache(cache_size);
+            self.cache_size = cache_size;
+            self.cache_enabled = cache_enabled;
+        }
+        self.outbound = outbound;
+        self.ip_strategy = ip_strategy;
         self.servers = servers;
+        self.tcp_servers = tcp_servers;
+        self.doh_servers = Arc::new(doh_servers);
         self.hosts = hosts;
         Ok(())
     }
 
+    /// Returns the fake DNS if enabled, for answering queries with fake IPs.
+    pub fn fake_dns(&self) -> Option<Arc<TokioMutex<FakeDns>>> {
+        self.fake_dns.clone()
+    }
+
+    /// Returns the domain a fake IP is allocated for.
+    pub async fn reverse_lookup(&self, ip: &IpAddr) -> Option<String> {
+        let mut fake_dns = self.fake_dns.as_ref()?.lock().await;
+        if !fake_dns.is_fake_ip(ip) {
+            return None;
+        }
+        fake_dns.query_domain(ip)
+    }
+
+    /// Drops all cached records.
+    pub async fn flush_cache(&self) {
+        self.ipv4_cache.lock().await.clear();
+        self.ipv6_cache.lock().await.clear();
+    }
+
     async fn optimize_cache_ipv4(&self, address: String, connected_ip: IpAddr) {
         // Nothing to do if the target address is an IP address.
         if address.parse::<IpAddr>().is_ok() {
@@ -162,88 +711,120 @@ impl DnsClient {
         }
     }
 
+    // Whether the outbound connects to the host, e.g. the host is the server
+    // address of a proxy.
+    fn is_outbound_addr(handler: &AnyOutboundHandler, host: &str) -> bool {
+        [
+            TcpOutboundHandler::connect_addr(handler.as_ref()),
+            UdpOutboundHandler::connect_addr(handler.as_ref()),
+        ]
+        .iter()
+        .any(|c| matches!(c, Some(OutboundConnect::Proxy(addr, _)) if addr == host))
+    }
+
+    async fn new_query_socket(
+        &self,
+        host: &str,
+        server: &SocketAddr,
+        tcp: bool,
+    ) -> Result<QuerySocket> {
+        let outbound_manager = self.outbound_manager.as_ref().and_then(Weak::upgrade);
+        if let (Some(tag), Some(outbound_manager), Some(bootstrap)) =
+            (&self.outbound, outbound_manager, &self.bootstrap)
+        {
+            let h = outbound_manager
+                .read()
+                .await
+                .get(tag)
+                .ok_or_else(|| anyhow!("dns outbound [{}] not found", tag))?;
+            // Looking up the address of the outbound through itself would
+            // never end.
+            if !Self::is_outbound_addr(&h, host) {
+                let mut sess = Session {
+                    network: Network::Udp,
+                    destination: SocksAddr::Ip(*server),
+                    ..Default::default()
+                };
+                if tcp {
+                    sess.network = Network::Tcp;
+                    let transport =
+                        proxy::connect_tcp_outbound(&sess, bootstrap.clone(), &h).await?;
+                    let stream = TcpOutboundHandler::handle(h.as_ref(), &sess, transport).await?;
+                    return Ok(QuerySocket::Stream(stream));
+                }
+                let transport = proxy::connect_udp_outbound(&sess, bootstrap.clone(), &h).await?;
+                let dgram = UdpOutboundHandler::handle(h.as_ref(), &sess, transport).await?;
+                let (r, s) = dgram.split();
+                return Ok(QuerySocket::Outbound(r, s));
+            }
+            debug!("looking up outbound address {} directly", host);
+        }
+        if tcp {
+            let bootstrap = self
+                .bootstrap
+                .as_ref()
+                .ok_or_else(|| anyhow!("no bootstrap dns client"))?;
+            let stream =
+                proxy::new_tcp_stream(bootstrap.clone(), &server.ip().to_string(), &server.port())
+                    .await?;
+            return Ok(QuerySocket::Stream(stream));
+        }
+        Ok(QuerySocket::Direct(self.new_udp_socket(server).await?))
+    }
+
     async fn query_task(
         &self,
         request: Vec<u8>,
         host: &str,
         server: &SocketAddr,
+        tcp: bool,
     ) -> Result<CacheEntry> {
-        let socket = self.new_udp_socket(server).await?;
+        let mut socket = None;
         let mut last_err = None;
+        // Responses over TCP aren't truncated to 512 bytes.
+        let mut buf = vec![0u8; if tcp { 0xffff } else { 512 }];
         for _i in 0..*option::MAX_DNS_RETRIES {
+            // A stream is reconnected after an error, it's left in an unknown
+            // state in the middle of a message.
+            let s = match socket.as_mut() {
+                Some(s) => s,
+                None => match self.new_query_socket(host, server, tcp).await {
+                    Ok(s) => socket.insert(s),
+                    Err(e) => {
+                        last_err = Some(anyhow!("connect failed: {}", e));
+                        continue;
+                    }
+                },
+            };
             debug!("looking up host {} on {}", host, server);
             let start = tokio::time::Instant::now();
-            match socket.send_to(&request, server).await {
+            match s.send_to(&request, server).await {
                 Ok(_) => {
-                    let mut buf = vec![0u8; 512];
-                    match timeout(
-                        Duration::from_secs(*option::DNS_TIMEOUT),
-                        socket.recv_from(&mut buf),
-                    )
-                    .await
+                    match timeout(Duration::from_secs(*option::DNS_TIMEOUT), s.recv(&mut buf)).await
                     {
                         Ok(res) => match res {
-                            Ok((n, _)) => {
-                                let resp = match Message::from_vec(&buf[..n]) {
-                                    Ok(resp) => resp,
-                                    Err(err) => {
-                                        last_err = Some(anyhow!("parse message failed: {:?}", err));
-                                        // broken response, no retry
-                                        break;
-                                    }
-                                };
-                                if resp.response_code() != ResponseCode::NoError {
-                                    last_err =
-                                        Some(anyhow!("response error {}", resp.response_code()));
-                                    // error response, no retry
-                                    //
-                                    // TODO Needs more careful investigations, I'm not quite sure about
-                                    // this.
-                                    break;
-                                }
-                                let mut ips = Vec::new();
-                                for ans in resp.answers() {
-                                    // TODO checks?
-                                    match ans.rdata() {
-                                        RData::A(ip) => {
-                                            ips.push(IpAddr::V4(ip.to_owned()));
-                                        }
-                                        RData::AAAA(ip) => {
-                                            ips.push(IpAddr::V6(ip.to_owned()));
-                                        }
-                                        _ => (),
-                                    }
-                                }
-                                if !ips.is_empty() {
+                            Ok(n) => match Self::parse_response(&buf[..n]) {
+                                Ok(entry) => {
                                     let elapsed = tokio::time::Instant::now().duration_since(start);
-                                    let ttl = resp.answers().iter().next().unwrap().ttl();
                                     debug!(
-                                        "return {} ips (ttl {}) for {} from {} in {}ms",
-                                        ips.len(),
-                                        ttl,
+                                        "return {} ips for {} from {} in {}ms",
+                                        entry.ips.len(),
                                         host,
                                         server,
                                         elapsed.as_millis(),
                                     );
-                                    let deadline = if let Some(d) =
-                                        Instant::now().checked_add(Duration::from_secs(ttl.into()))
-                                    {
-                                        d
-                                    } else {
-                                        last_err = Some(anyhow!("invalid ttl"));
-                                        break;
-                                    };
-                                    let entry = CacheEntry { ips, deadline };
                                     trace!("ips for {}:\n{:#?}", host, &entry);
                                     return Ok(entry);
-                                } else {
-                                    // response with 0 records
+                                }
+                                Err(e) => {
+                                    last_err = Some(e);
+                                    // broken, error or empty response, no retry
                                     //
-                                    // TODO Not sure how to due with this.
-                                    last_err = Some(anyhow!("no records"));
+                                    // TODO Needs more careful investigations, I'm not quite sure about
+                                    // this.
                                     break;
                                 }
-                            }
+                            },
                             Err(err) => {
                                 last_err = Some(anyhow!("recv failed: {:?}", err));
                                 // socket recv_from error, retry
@@ -260,10 +841,206 @@ impl DnsClient {
                     // socket send_to error, retry
                 }
             }
+            if tcp {
+                socket = None;
+            }
         }
         Err(last_err.unwrap_or_else(|| anyhow!("all lookup attempts failed")))
     }
 
+    // Parses a response into a cache entry, which expires along with the
+    // record having the shortest TTL.
+    fn parse_response(buf: &[u8]) -> Result<CacheEntry> {
+        let resp = Message::from_vec(buf).map_err(|e| anyhow!("parse message failed: {:?}", e))?;
+        if resp.response_code() != ResponseCode::NoError {
+            return Err(anyhow!("response error {}", resp.response_code()));
+        }
+        let mut ips = Vec::new();
+        let mut ttl: Option<u32> = None;
+        for ans in resp.answers() {
+            let ip = match ans.rdata() {
+                RData::A(ip) => IpAddr::V4(ip.to_owned()),
+                RData::AAAA(ip) => IpAddr::V6(ip.to_owned()),
+                _ => continue,
+            };
+            ips.push(ip);
+            ttl = Some(ttl.map_or(ans.ttl(), |t| t.min(ans.ttl())));
+        }
+        let ttl = ttl.ok_or_else(|| anyhow!("no records"))?;
+        let ttl = Duration::from_secs(ttl.into());
+        let deadline = Instant::now()
+            .checked_add(ttl)
+            .ok_or_else(|| anyhow!("invalid ttl"))?;
+        Ok(CacheEntry { ips, deadline, ttl })
+    }
+
+    // Connects to a DoH server, through the outbound queries are sent through
+    // if there's one.
+    async fn new_doh_stream(&self, host: &str, server: &DohServer) -> Result<AnyStream> {
+        let bootstrap = self
+            .bootstrap
+            .as_ref()
+            .ok_or_else(|| anyhow!("no bootstrap dns client"))?;
+        let sess = Session {
+            network: Network::Tcp,
+            destination: SocksAddr::try_from((&server.host, server.port))?,
+            ..Default::default()
+        };
+        let mut stream = None;
+        let outbound_manager = self.outbound_manager.as_ref().and_then(Weak::upgrade);
+        if let (Some(tag), Some(outbound_manager)) = (&self.outbound, outbound_manager) {
+            let h = outbound_manager
+                .read()
+                .await
+                .get(tag)
+                .ok_or_else(|| anyhow!("dns outbound [{}] not found", tag))?;
+            if !Self::is_outbound_addr(&h, host) {
+                let transport = proxy::connect_tcp_outbound(&sess, bootstrap.clone(), &h).await?;
+                stream = Some(TcpOutboundHandler::handle(h.as_ref(), &sess, transport).await?);
+            } else {
+                debug!("looking up outbound address {} directly", host);
+            }
+        }
+        let stream = match stream {
+            Some(stream) => stream,
+            None => proxy::new_tcp_stream(bootstrap.clone(), &server.host, &server.port).await?,
+        };
+        #[cfg(feature = "outbound-tls")]
+        let stream = TcpOutboundHandler::handle(&server.tls, &sess, Some(stream)).await?;
+        Ok(stream)
+    }
+
+    async fn doh_query_task(
+        &self,
+        request: Vec<u8>,
+        host: &str,
+        server: &DohServer,
+    ) -> Result<CacheEntry> {
+        debug!("looking up host {} on {}", host, server.url);
+        let start = tokio::time::Instant::now();
+        let resp = timeout(Duration::from_secs(*option::DNS_TIMEOUT), async {
+            // An idle connection may have been closed by the server, the
+            // query is retried on a new one then.
+            if let Some(mut stream) = server.take_idle() {
+                match doh_exchange(&mut stream, server, &request).await {
+                    Ok((resp, keep_alive)) => {
+                        if keep_alive {
+                            server.put_idle(stream);
+                        }
+                        return Ok(resp);
+                    }
+                    Err(e) => debug!(
+                        "doh query on idle connection to {} failed: {}",
+                        server.url, e
+                    ),
+                }
+            }
+            let mut stream = self.new_doh_stream(host, server).await?;
+            let (resp, keep_alive) = doh_exchange(&mut stream, server, &request).await?;
+            if keep_alive {
+                server.put_idle(stream);
+            }
+            Ok::<_, anyhow::Error>(resp)
+        })
+        .await
+        .map_err(|e| anyhow!("doh query timeout: {}", e))??;
+        let entry = Self::parse_response(&resp)?;
+        let elapsed = tokio::time::Instant::now().duration_since(start);
+        debug!(
+            "return {} ips for {} from {} in {}ms",
+            entry.ips.len(),
+            host,
+            server.url,
+            elapsed.as_millis(),
+        );
+        trace!("ips for {}:\n{:#?}", host, &entry);
+        Ok(entry)
+    }
+
+    // Races the tasks, the first successful response wins and the others are
+    // abandoned, the errors are aggregated if all of them fail.
+    async fn race<F>(tasks: impl Iterator<Item = (String, F)>) -> io::Result<CacheEntry>
+    where
+        F: Future<Output = Result<CacheEntry>>,
+    {
+        let mut tasks: FuturesUnordered<_> = tasks
+            .map(|(server, task)| async move { task.await.map_err(|e| (server, e)) })
+            .collect();
+        if tasks.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no dns servers"));
+        }
+        let mut errors = Vec::new();
+        while let Some(res) = tasks.next().await {
+            match res {
+                Ok(entry) => return Ok(entry),
+                Err((server, e)) => errors.push(format!("{}: {}", server, e)),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("all dns servers failed: {}", errors.join("; ")),
+        ))
+    }
+
+    // Queries the DoH servers concurrently if there're any, falls back to the
+    // UDP and TCP servers, also queried concurrently, if all of them fail or
+    // none of them responds in a query timeout.
+    async fn query_servers(&self, request: Vec<u8>, host: &str) -> io::Result<CacheEntry> {
+        if !self.doh_servers.is_empty() {
+            let tasks = self.doh_servers.iter().map(|server| {
+                let task = self.doh_query_task(request.clone(), host, server);
+                (server.url.clone(), task)
+            });
+            if self.servers.is_empty() && self.tcp_servers.is_empty() {
+                return Self::race(tasks).await;
+            }
+            let res = timeout(Duration::from_secs(*option::DNS_TIMEOUT), Self::race(tasks))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "doh servers timed out",
+                    ))
+                });
+            match res {
+                Ok(entry) => return Ok(entry),
+                Err(e) => debug!("doh lookup {} failed, falling back to udp: {}", host, e),
+            }
+        }
+        let tasks = self
+            .servers
+            .iter()
+            .map(|server| (server, false))
+            .chain(self.tcp_servers.iter().map(|server| (server, true)))
+            .map(|(server, tcp)| {
+                let task = self.query_task(request.clone(), host, server, tcp);
+                let name = if tcp {
+                    format!("tcp://{}", server)
+                } else {
+                    server.to_string()
+                };
+                (name, task)
+            });
+        Self::race(tasks).await
+    }
+
+    // The errors are IO errors, timing out if none of the servers responds in
+    // time.
+    async fn query(&self, request: Vec<u8>, host: &str) -> Result<CacheEntry> {
+        timeout(
+            Duration::from_secs(*option::DNS_LOOKUP_TIMEOUT),
+            self.query_servers(request, host),
+        )
+        .await
+        .unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("lookup {} timed out", host),
+            ))
+        })
+        .map_err(anyhow::Error::new)
+    }
+
     fn new_query(name: Name, ty: RecordType) -> Message {
         let mut msg = Message::new();
         msg.add_query(Query::query(name, ty));
@@ -276,8 +1053,52 @@ impl DnsClient {
         msg
     }
 
+    fn new_name(host: &str) -> Result<Name> {
+        let mut fqdn = host.to_owned();
+        fqdn.push('.');
+        Name::from_str(&fqdn).map_err(|e| anyhow!("invalid domain name [{}]: {}", host, e))
+    }
+
+    // The types of records to query, in the order of preference.
+    fn record_types(&self) -> &'static [RecordType] {
+        match self.ip_strategy {
+            Dns_IpStrategy::PREFER_IPV4 => &[RecordType::A, RecordType::AAAA],
+            Dns_IpStrategy::PREFER_IPV6 => &[RecordType::AAAA, RecordType::A],
+            Dns_IpStrategy::IPV4_ONLY => &[RecordType::A],
+            Dns_IpStrategy::IPV6_ONLY => &[RecordType::AAAA],
+            Dns_IpStrategy::DEFAULT => {
+                match (*crate::option::ENABLE_IPV6, *crate::option::PREFER_IPV6) {
+                    (true, true) => &[RecordType::AAAA, RecordType::A],
+                    (true, false) => &[RecordType::A, RecordType::AAAA],
+                    _ => &[RecordType::A],
+                }
+            }
+        }
+    }
+
+    // Drops the addresses of the families not looked up, and puts those of
+    // the preferred family first, the order within a family is kept.
+    fn sort_ips(&self, ips: &[IpAddr]) -> Vec<IpAddr> {
+        let mut sorted = Vec::with_capacity(ips.len());
+        for ty in self.record_types() {
+            sorted.extend(ips.iter().filter(|ip| match ip {
+                IpAddr::V4(..) => *ty == RecordType::A,
+                IpAddr::V6(..) => *ty == RecordType::AAAA,
+            }));
+        }
+        sorted
+    }
+
+    fn cache(&self, ty: RecordType) -> &TokioMutex<LruCache<String, CacheEntry>> {
+        if ty == RecordType::AAAA {
+            &self.ipv6_cache
+        } else {
+            &self.ipv4_cache
+        }
+    }
+
     async fn cache_insert(&self, host: &str, entry: CacheEntry) {
-        if entry.ips.is_empty() {
+        if !self.cache_enabled || entry.ips.is_empty() {
             return;
         }
         match entry.ips[0] {
@@ -286,205 +1107,124 @@ impl DnsClient {
         };
     }
 
-    async fn get_cached(&self, host: &String) -> Result<Vec<IpAddr>> {
+    // Returns the cached IPs, along with the types of the records about to
+    // expire, i.e. in the last tenth of their TTLs.
+    async fn get_cached(&self, host: &String) -> Result<(Vec<IpAddr>, Vec<RecordType>)> {
         let mut cached_ips = Vec::new();
-
-        // TODO reduce boilerplates
-        match (*crate::option::ENABLE_IPV6, *crate::option::PREFER_IPV6) {
-            (true, true) => {
-                if let Some(entry) = self.ipv6_cache.lock().await.get(host) {
-                    if entry
-                        .deadline
-                        .checked_duration_since(Instant::now())
-                        .is_none()
-                    {
-                        return Err(anyhow!("entry expired"));
-                    }
-                    let mut ips = entry.ips.to_vec();
-                    cached_ips.append(&mut ips);
-                }
-                if let Some(entry) = self.ipv4_cache.lock().await.get(host) {
-                    if entry
-                        .deadline
-                        .checked_duration_since(Instant::now())
-                        .is_none()
-                    {
-                        return Err(anyhow!("entry expired"));
-                    }
-                    let mut ips = entry.ips.to_vec();
-                    cached_ips.append(&mut ips);
-                }
-            }
-            (true, false) => {
-                if let Some(entry) = self.ipv4_cache.lock().await.get(host) {
-                    if entry
-                        .deadline
-                        .checked_duration_since(Instant::now())
-                        .is_none()
-                    {
-                        return Err(anyhow!("entry expired"));
-                    }
-                    let mut ips = entry.ips.to_vec();
-                    cached_ips.append(&mut ips);
-                }
-                if let Some(entry) = self.ipv6_cache.lock().await.get(host) {
-                    if entry
-                        .deadline
-                        .checked_duration_since(Instant::now())
-                        .is_none()
-                    {
-                        return Err(anyhow!("entry expired"));
-                    }
-                    let mut ips = entry.ips.to_vec();
-                    cached_ips.append(&mut ips);
-                }
-            }
-            _ => {
-                if let Some(entry) = self.ipv4_cache.lock().await.get(host) {
-                    if entry
-                        .deadline
-                        .checked_duration_since(Instant::now())
-                        .is_none()
-                    {
-                        return Err(anyhow!("entry expired"));
-                    }
-                    let mut ips = entry.ips.to_vec();
-                    cached_ips.append(&mut ips);
+        let mut expiring = Vec::new();
+        let now = Instant::now();
+        for ty in self.record_types() {
+            if let Some(entry) = self.cache(*ty).lock().await.get(host) {
+                let remaining = entry
+                    .deadline
+                    .checked_duration_since(now)
+                    .ok_or_else(|| anyhow!("entry expired"))?;
+                if remaining <= entry.ttl / 10 {
+                    expiring.push(*ty);
                 }
+                cached_ips.extend_from_slice(&entry.ips);
             }
         }
 
         if !cached_ips.is_empty() {
-            Ok(cached_ips)
+            Ok((cached_ips, expiring))
         } else {
             Err(anyhow!("empty result"))
         }
     }
 
+    // Queries records of a type and caches them.
+    async fn query_record(&self, name: Name, ty: RecordType, host: &str) -> Result<CacheEntry> {
+        let msg_buf = Self::new_query(name, ty)
+            .to_vec()
+            .map_err(|e| anyhow!("encode message to buffer failed: {}", e))?;
+        let entry = self.query(msg_buf, host).await?;
+        self.cache_insert(host, entry.clone()).await;
+        Ok(entry)
+    }
+
+    // Queries records about to expire in the background, lookups are answered
+    // from the cache meanwhile.
+    fn refresh(&self, host: &str, ty: RecordType) {
+        let key = (host.to_owned(), ty);
+        if !self.refreshing.lock().unwrap().insert(key.clone()) {
+            return;
+        }
+        let client = self.clone();
+        tokio::spawn(async move {
+            let (host, ty) = &key;
+            let res = match Self::new_name(host) {
+                Ok(name) => client.query_record(name, *ty, host).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                debug!("refresh {} records of {} failed: {}", ty, host, e);
+            }
+            client.refreshing.lock().unwrap().remove(&key);
+        });
+    }
+
     pub async fn lookup(&self, host: &String) -> Result<Vec<IpAddr>> {
         if let Ok(ip) = host.parse::<IpAddr>() {
             return Ok(vec![ip]);
         }
 
-        if let Ok(ips) = self.get_cached(host).await {
-            return Ok(ips);
-        }
-
-        // Making cache lookup a priority rather than static hosts lookup
-        // and insert the static IPs to the cache because there's a chance
-        // for the IPs in the cache to be re-ordered.
-        if !self.hosts.is_empty() {
-            if let Some(ips) = self.hosts.get(host) {
-                if !ips.is_empty() {
-                    if ips.len() > 1 {
-                        let deadline = Instant::now()
-                            .checked_add(Duration::from_secs(6000))
-                            .unwrap();
-                        self.cache_insert(
-                            host,
-                            CacheEntry {
-                                ips: ips.clone(),
-                                deadline,
-                            },
-                        )
-                        .await;
+        // Static hosts are never resolved and never expire. Multiple IPs are
+        // cached as well because there's a chance for the IPs in the cache
+        // to be re-ordered.
+        if let Some(ips) = self.lookup_hosts(host) {
+            if !ips.is_empty() {
+                if ips.len() > 1 && self.cache_enabled {
+                    let ty = match ips[0] {
+                        IpAddr::V4(..) => RecordType::A,
+                        IpAddr::V6(..) => RecordType::AAAA,
+                    };
+                    let mut cache = self.cache(ty).lock().await;
+                    match cache.get(host) {
+                        Some(entry)
+                            if entry.ips.len() == ips.len()
+                                && ips.iter().all(|ip| entry.ips.contains(ip)) =>
+                        {
+                            return Ok(self.sort_ips(&entry.ips));
+                        }
+                        _ => {
+                            let ttl = Duration::from_secs(6000);
+                            let deadline = Instant::now().checked_add(ttl).unwrap();
+                            cache.put(
+                                host.to_owned(),
+                                CacheEntry {
+                                    ips: ips.clone(),
+                                    deadline,
+                                    ttl,
+                                },
+                            );
+                        }
                     }
-                    return Ok(ips.to_vec());
                 }
+                return Ok(self.sort_ips(ips));
             }
         }
 
-        let mut fqdn = host.to_owned();
-        fqdn.push('.');
-        let name = match Name::from_str(&fqdn) {
-            Ok(n) => n,
-            Err(e) => return Err(anyhow!("invalid domain name [{}]: {}", host, e)),
-        };
-
-        let mut query_tasks = Vec::new();
-
-        // TODO reduce boilerplates
-        match (*crate::option::ENABLE_IPV6, *crate::option::PREFER_IPV6) {
-            (true, true) => {
-                let msg = Self::new_query(name.clone(), RecordType::AAAA);
-                let msg_buf = match msg.to_vec() {
-                    Ok(b) => b,
-                    Err(e) => return Err(anyhow!("encode message to buffer failed: {}", e)),
-                };
-                let mut tasks = Vec::new();
-                for server in &self.servers {
-                    let t = self.query_task(msg_buf.clone(), host, server);
-                    tasks.push(Box::pin(t));
-                }
-                let query_task = select_ok(tasks.into_iter());
-                query_tasks.push(query_task);
-
-                let msg = Self::new_query(name.clone(), RecordType::A);
-                let msg_buf = match msg.to_vec() {
-                    Ok(b) => b,
-                    Err(e) => return Err(anyhow!("encode message to buffer failed: {}", e)),
-                };
-                let mut tasks = Vec::new();
-                for server in &self.servers {
-                    let t = self.query_task(msg_buf.clone(), host, server);
-                    tasks.push(Box::pin(t));
-                }
-                let query_task = select_ok(tasks.into_iter());
-                query_tasks.push(query_task);
-            }
-            (true, false) => {
-                let msg = Self::new_query(name.clone(), RecordType::A);
-                let msg_buf = match msg.to_vec() {
-                    Ok(b) => b,
-                    Err(e) => return Err(anyhow!("encode message to buffer failed: {}", e)),
-                };
-                let mut tasks = Vec::new();
-                for server in &self.servers {
-                    let t = self.query_task(msg_buf.clone(), host, server);
-                    tasks.push(Box::pin(t));
-                }
-                let query_task = select_ok(tasks.into_iter());
-                query_tasks.push(query_task);
-
-                let msg = Self::new_query(name.clone(), RecordType::AAAA);
-                let msg_buf = match msg.to_vec() {
-                    Ok(b) => b,
-                    Err(e) => return Err(anyhow!("encode message to buffer failed: {}", e)),
-                };
-                let mut tasks = Vec::new();
-                for server in &self.servers {
-                    let t = self.query_task(msg_buf.clone(), host, server);
-                    tasks.push(Box::pin(t));
-                }
-                let query_task = select_ok(tasks.into_iter());
-                query_tasks.push(query_task);
-            }
-            _ => {
-                let msg = Self::new_query(name.clone(), RecordType::A);
-                let msg_buf = match msg.to_vec() {
-                    Ok(b) => b,
-                    Err(e) => return Err(anyhow!("encode message to buffer failed: {}", e)),
-                };
-                let mut tasks = Vec::new();
-                for server in &self.servers {
-                    let t = self.query_task(msg_buf.clone(), host, server);
-                    tasks.push(Box::pin(t));
-                }
-                let query_task = select_ok(tasks.into_iter());
-                query_tasks.push(query_task);
+        if let Ok((ips, expiring)) = self.get_cached(host).await {
+            for ty in expiring {
+                self.refresh(host, ty);
             }
+            return Ok(ips);
         }
 
+        let name = Self::new_name(host)?;
+        let query_tasks = self
+            .record_types()
+            .iter()
+            .map(|ty| self.query_record(name.clone(), *ty, host));
+
         let mut ips = Vec::new();
         let mut last_err = None;
 
         for v in futures::future::join_all(query_tasks).await {
             match v {
-                Ok(mut v) => {
-                    self.cache_insert(host, v.0.clone()).await;
-                    ips.append(&mut v.0.ips);
-                }
-                Err(e) => last_err = Some(anyhow!("all dns servers failed, last error: {}", e)),
+                Ok(mut v) => ips.append(&mut v.ips),
+                Err(e) => last_err = Some(e),
             }
         }
 
@@ -497,3 +1237,688 @@ impl DnsClient {
 }
 
 impl UdpConnector for DnsClient {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use trust_dns_proto::rr::Record;
+
+    // Answers A queries with 10.1.2.3 in two records with different TTLs.
+    fn new_response(req: &Message) -> Message {
+        let mut resp = Message::new();
+        resp.set_id(req.id());
+        resp.set_message_type(MessageType::Response);
+        resp.add_queries(req.queries().to_vec());
+        if req.queries()[0].query_type() == RecordType::A {
+            for ttl in [600, 60] {
+                resp.add_answer(Record::from_rdata(
+                    req.queries()[0].name().clone(),
+                    ttl,
+                    RData::A("10.1.2.3".parse().unwrap()),
+                ));
+            }
+        } else if req.queries()[0].query_type() == RecordType::AAAA {
+            resp.add_answer(Record::from_rdata(
+                req.queries()[0].name().clone(),
+                600,
+                RData::AAAA("fd00::1".parse().unwrap()),
+            ));
+        }
+        resp
+    }
+
+    #[test]
+    fn test_doh_server() {
+        let server = DohServer::new("https://dns.google").unwrap();
+        assert_eq!(server.host, "dns.google");
+        assert_eq!(server.port, 443);
+        assert_eq!(server.path, "/dns-query");
+        assert_eq!(server.authority(), "dns.google");
+
+        let server = DohServer::new("https://dns.example:8443/resolve").unwrap();
+        assert_eq!(server.host, "dns.example");
+        assert_eq!(server.port, 8443);
+        assert_eq!(server.path, "/resolve");
+        assert_eq!(server.authority(), "dns.example:8443");
+
+        assert!(DohServer::new("http://dns.google/dns-query").is_err());
+        assert!(DohServer::new("https://dns.google:https/dns-query").is_err());
+        assert!(DohServer::new("https:///dns-query").is_err());
+        // Certificates of IP addresses can't be verified.
+        #[cfg(feature = "rustls-tls")]
+        {
+            assert!(DohServer::new("https://1.1.1.1/dns-query").is_err());
+            assert!(DohServer::new("https://[2606:4700::1111]:8443/dns-query").is_err());
+        }
+    }
+
+    #[test]
+    fn test_doh_bootstrap() {
+        let mut dns = crate::config::Dns::new();
+        dns.doh_servers.push("https://dns.google".to_string());
+        assert!(DnsClient::new(&protobuf::SingularPtrField::some(dns.clone())).is_err());
+        dns.bootstrap_servers.push("192.0.2.1".to_string());
+        assert!(DnsClient::new(&protobuf::SingularPtrField::some(dns)).is_ok());
+    }
+
+    #[test]
+    fn test_decode_chunked() {
+        let body = decode_chunked(b"3;ext=1\r\nabc\r\n2\r\nde\r\n0\r\n\r\n").unwrap();
+        assert_eq!(body.unwrap(), b"abcde");
+        let body = decode_chunked(b"3\r\nabc\r\n0\r\nx-trailer: 1\r\n\r\n").unwrap();
+        assert_eq!(body.unwrap(), b"abc");
+        assert!(decode_chunked(b"3\r\nab").unwrap().is_none());
+        assert!(decode_chunked(b"3\r\nabc\r\n0\r\n").unwrap().is_none());
+        assert!(decode_chunked(b"x\r\nabc\r\n0\r\n\r\n").is_err());
+        assert!(decode_chunked(b"3\r\nabcd\r\n0\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_doh_exchange() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut client, mut server) = tokio::io::duplex(4096);
+            // Answers two queries on the connection, the second one in a
+            // chunked body before closing the connection.
+            tokio::spawn(async move {
+                let mut buf = Vec::new();
+                for i in 0..2 {
+                    let header_len = loop {
+                        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break i + 4;
+                        }
+                        server.read_buf(&mut buf).await.unwrap();
+                    };
+                    let header = String::from_utf8(buf[..header_len].to_vec()).unwrap();
+                    assert!(header.starts_with("POST /dns-query HTTP/1.1\r\n"));
+                    assert!(header.contains("Host: dns.example:8443\r\n"));
+                    assert!(header.contains("Content-Type: application/dns-message\r\n"));
+                    let len = header
+                        .split("\r\n")
+                        .find_map(|l| l.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse::<usize>()
+                        .unwrap();
+                    while buf.len() < header_len + len {
+                        server.read_buf(&mut buf).await.unwrap();
+                    }
+                    let req = Message::from_vec(&buf[header_len..header_len + len]).unwrap();
+                    buf.drain(..header_len + len);
+                    let resp = new_response(&req).to_vec().unwrap();
+                    let mut out = if i == 0 {
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/dns-message\r\ncontent-length: {}\r\n\r\n",
+                            resp.len()
+                        )
+                        .into_bytes()
+                    } else {
+                        b"HTTP/1.1 200 OK\r\nconnection: close\r\ntransfer-encoding: chunked\r\n\r\n"
+                            .to_vec()
+                    };
+                    if i == 0 {
+                        out.extend_from_slice(&resp);
+                    } else {
+                        out.extend_from_slice(format!("{:x}\r\n", resp.len()).as_bytes());
+                        out.extend_from_slice(&resp);
+                        out.extend_from_slice(b"\r\n0\r\n\r\n");
+                    }
+                    server.write_all(&out).await.unwrap();
+                }
+            });
+
+            let server = DohServer::new("https://dns.example:8443/dns-query").unwrap();
+            let name = Name::from_str("example.com.").unwrap();
+            let req = DnsClient::new_query(name, RecordType::A).to_vec().unwrap();
+            let (resp, keep_alive) = doh_exchange(&mut client, &server, &req).await.unwrap();
+            assert!(keep_alive);
+            let entry = DnsClient::parse_response(&resp).unwrap();
+            assert_eq!(entry.ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+            // Expires along with the record having the shortest TTL.
+            let ttl = entry.deadline.duration_since(Instant::now());
+            assert!(ttl > Duration::from_secs(50) && ttl <= Duration::from_secs(60));
+
+            // The connection is reused.
+            let (resp, keep_alive) = doh_exchange(&mut client, &server, &req).await.unwrap();
+            assert!(!keep_alive);
+            let entry = DnsClient::parse_response(&resp).unwrap();
+            assert_eq!(entry.ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+        });
+    }
+
+    #[test]
+    fn test_reverse_lookup() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut dns = crate::config::Dns::new();
+            dns.servers.push("192.0.2.1".to_string());
+            let mut fake_dns = crate::config::Dns_FakeDns::new();
+            fake_dns.range = "10.0.0.0/24".to_string();
+            dns.fake_dns = protobuf::SingularPtrField::some(fake_dns);
+            let dns_client = DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+
+            let name = Name::from_str("example.com.").unwrap();
+            let req = DnsClient::new_query(name, RecordType::A).to_vec().unwrap();
+            let resp = dns_client
+                .fake_dns()
+                .unwrap()
+                .lock()
+                .await
+                .generate_fake_response(&req)
+                .unwrap();
+            let ip = DnsClient::parse_response(&resp).unwrap().ips[0];
+            assert_eq!(ip, "10.0.0.0".parse::<IpAddr>().unwrap());
+            assert_eq!(
+                dns_client.reverse_lookup(&ip).await,
+                Some("example.com".to_string())
+            );
+            assert_eq!(
+                dns_client
+                    .reverse_lookup(&"10.0.0.1".parse().unwrap())
+                    .await,
+                None
+            );
+        });
+    }
+
+    #[test]
+    fn test_hosts() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut dns = crate::config::Dns::new();
+            dns.servers.push("192.0.2.1".to_string());
+            for (name, ips) in [
+                ("example.com", vec!["10.0.0.1", "10.0.0.2"]),
+                ("*.example.com", vec!["0.0.0.0"]),
+                ("*.cdn.example.com", vec!["10.0.1.1"]),
+            ] {
+                let mut values = crate::config::Dns_Ips::new();
+                values.values = ips.iter().map(|ip| ip.to_string()).collect();
+                dns.hosts.insert(name.to_string(), values);
+            }
+            let dns_client = DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+            let lookup = |host: &str| {
+                let host = host.to_string();
+                let dns_client = &dns_client;
+                async move { dns_client.lookup(&host).await.unwrap() }
+            };
+
+            let ips: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+            assert_eq!(lookup("example.com").await, ips);
+            assert_eq!(
+                lookup("www.example.com").await,
+                vec![IpAddr::from([0, 0, 0, 0])]
+            );
+            assert_eq!(
+                lookup("a.b.example.com").await,
+                vec![IpAddr::from([0, 0, 0, 0])]
+            );
+            assert_eq!(
+                lookup("a.cdn.example.com").await,
+                vec![IpAddr::from([10, 0, 1, 1])]
+            );
+            assert!(dns_client.lookup_hosts("cdn.example.com").is_some());
+            assert!(dns_client.lookup_hosts("example.org").is_none());
+            assert!(dns_client.lookup_hosts("notexample.com").is_none());
+
+            // The order optimized in the cache is kept.
+            dns_client
+                .optimize_cache("example.com".to_string(), ips[1])
+                .await;
+            assert_eq!(lookup("example.com").await, vec![ips[1], ips[0]]);
+        });
+    }
+
+    #[cfg(all(feature = "config-json", feature = "outbound-redirect"))]
+    async fn serve_udp(server: UdpSocket, queries: Arc<std::sync::atomic::AtomicUsize>) {
+        let mut buf = vec![0u8; 512];
+        loop {
+            let (n, peer) = server.recv_from(&mut buf).await.unwrap();
+            queries.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let req = Message::from_vec(&buf[..n]).unwrap();
+            let resp = new_response(&req);
+            server.send_to(&resp.to_vec().unwrap(), peer).await.unwrap();
+        }
+    }
+
+    async fn serve_tcp(
+        listener: tokio::net::TcpListener,
+        queries: Arc<std::sync::atomic::AtomicUsize>,
+    ) {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let queries = queries.clone();
+            tokio::spawn(async move {
+                while let Ok(n) = stream.read_u16().await {
+                    let mut buf = vec![0u8; n as usize];
+                    stream.read_exact(&mut buf).await.unwrap();
+                    queries.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    let req = Message::from_vec(&buf).unwrap();
+                    let resp = new_response(&req).to_vec().unwrap();
+                    stream.write_u16(resp.len() as u16).await.unwrap();
+                    stream.write_all(&resp).await.unwrap();
+                }
+            });
+        }
+    }
+
+    // A TCP server closing the connection is reconnected to on retries.
+    #[test]
+    fn test_tcp_reconnect() {
+        use std::sync::atomic::Ordering;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = server.local_addr().unwrap();
+            let queries = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let server_queries = queries.clone();
+            tokio::spawn(async move {
+                drop(server.accept().await.unwrap());
+                serve_tcp(server, server_queries).await;
+            });
+
+            let mut dns = crate::config::Dns::new();
+            dns.tcp_servers.push(server_addr.to_string());
+            let dns_client = DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+            let name = Name::from_str("example.com.").unwrap();
+            let req = DnsClient::new_query(name, RecordType::A).to_vec().unwrap();
+            let entry = dns_client
+                .query_task(req, "example.com", &server_addr, true)
+                .await
+                .unwrap();
+            assert_eq!(entry.ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+            assert_eq!(queries.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    // A silent server doesn't hold up lookups, the errors of all the servers
+    // are aggregated if none of them responds.
+    #[test]
+    fn test_race_servers() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let silent_addr = silent.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut conns = Vec::new();
+                loop {
+                    conns.push(silent.accept().await.unwrap().0);
+                }
+            });
+            let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = server.local_addr().unwrap();
+            let queries = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            tokio::spawn(serve_tcp(server, queries));
+
+            let mut dns = crate::config::Dns::new();
+            dns.tcp_servers.push(silent_addr.to_string());
+            dns.tcp_servers.push(server_addr.to_string());
+            let dns_client = DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+            let start = Instant::now();
+            let ips = dns_client.lookup(&"example.com".to_string()).await.unwrap();
+            assert_eq!(ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+            assert!(start.elapsed() < Duration::from_secs(*option::DNS_TIMEOUT));
+
+            // Nothing listens on the ports of the dropped listeners.
+            let mut dns = crate::config::Dns::new();
+            let mut closed = Vec::new();
+            for _ in 0..2 {
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                closed.push(listener.local_addr().unwrap());
+                dns.tcp_servers.push(closed.last().unwrap().to_string());
+            }
+            let dns_client = DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+            let err = dns_client
+                .lookup(&"example.com".to_string())
+                .await
+                .unwrap_err();
+            let err = err.downcast_ref::<io::Error>().unwrap().to_string();
+            assert!(err.starts_with("all dns servers failed: "));
+            for addr in closed {
+                assert!(err.contains(&format!("tcp://{}: ", addr)));
+            }
+        });
+    }
+
+    #[test]
+    fn test_ip_strategy() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = server.local_addr().unwrap();
+            let queries = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            tokio::spawn(serve_tcp(server, queries));
+
+            let v4: IpAddr = "10.1.2.3".parse().unwrap();
+            let v6: IpAddr = "fd00::1".parse().unwrap();
+            let host_v4: IpAddr = "10.0.0.1".parse().unwrap();
+            let host_v6: IpAddr = "::1".parse().unwrap();
+            for (ip_strategy, resolved, hosts) in [
+                (
+                    Dns_IpStrategy::PREFER_IPV4,
+                    vec![v4, v4, v6],
+                    vec![host_v4, host_v6],
+                ),
+                (
+                    Dns_IpStrategy::PREFER_IPV6,
+                    vec![v6, v4, v4],
+                    vec![host_v6, host_v4],
+                ),
+                (Dns_IpStrategy::IPV4_ONLY, vec![v4, v4], vec![host_v4]),
+                (Dns_IpStrategy::IPV6_ONLY, vec![v6], vec![host_v6]),
+            ] {
+                let mut dns = crate::config::Dns::new();
+                dns.tcp_servers.push(server_addr.to_string());
+                dns.ip_strategy = ip_strategy;
+                let mut values = crate::config::Dns_Ips::new();
+                values.values = vec!["10.0.0.1".to_string(), "::1".to_string()].into();
+                dns.hosts.insert("dual.example".to_string(), values);
+                let dns_client = DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+                assert_eq!(
+                    dns_client.lookup(&"example.com".to_string()).await.unwrap(),
+                    resolved
+                );
+                // Served from the cache.
+                assert_eq!(
+                    dns_client.lookup(&"example.com".to_string()).await.unwrap(),
+                    resolved
+                );
+                assert_eq!(
+                    dns_client
+                        .lookup(&"dual.example".to_string())
+                        .await
+                        .unwrap(),
+                    hosts
+                );
+                // The preferred family is dialed first.
+                let dialed = crate::common::net::happy_eyeballs(
+                    hosts.clone(),
+                    443,
+                    Duration::from_secs(1),
+                    |addr| async move { Ok(addr) },
+                )
+                .await
+                .unwrap();
+                assert_eq!(dialed.ip(), hosts[0]);
+            }
+        });
+    }
+
+    // Creates a client sending queries through a redirect outbound to a
+    // server answering every query with 10.1.2.3, over TCP as well if there're
+    // TCP servers, the outbound manager must be kept alive along with the
+    // client. Also returns the number of queries the server received.
+    #[cfg(all(feature = "config-json", feature = "outbound-redirect"))]
+    async fn new_client(
+        dns: &str,
+    ) -> (
+        SyncDnsClient,
+        Arc<RwLock<OutboundManager>>,
+        Arc<std::sync::atomic::AtomicUsize>,
+    ) {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let queries = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        tokio::spawn(serve_udp(server, queries.clone()));
+        // Otherwise the port is left for DoH connections to fail.
+        if dns.contains("tcpServers") {
+            let listener = tokio::net::TcpListener::bind(server_addr).await.unwrap();
+            tokio::spawn(serve_tcp(listener, queries.clone()));
+        }
+
+        let config = format!(
+            r#"
+            {{
+                "dns": {},
+                "outbounds": [
+                    {{
+                        "protocol": "redirect",
+                        "tag": "dns-out",
+                        "settings": {{
+                            "address": "127.0.0.1",
+                            "port": {}
+                        }}
+                    }}
+                ]
+            }}
+            "#,
+            dns,
+            server_addr.port()
+        );
+        let config = crate::config::json::from_string(&config).unwrap();
+        let dns_client = Arc::new(RwLock::new(DnsClient::new(&config.dns).unwrap()));
+        let outbound_manager = Arc::new(RwLock::new(
+            OutboundManager::new(&config.outbounds, dns_client.clone()).unwrap(),
+        ));
+        dns_client
+            .write()
+            .await
+            .set_outbound_manager(Arc::downgrade(&outbound_manager));
+        (dns_client, outbound_manager, queries)
+    }
+
+    // Queries to an unreachable server go through the redirect outbound.
+    #[cfg(all(feature = "config-json", feature = "outbound-redirect"))]
+    #[test]
+    fn test_lookup_via_outbound() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (dns_client, _outbound_manager, _) =
+                new_client(r#"{"servers": ["192.0.2.1"], "outbound": "dns-out"}"#).await;
+            let ips = dns_client
+                .read()
+                .await
+                .lookup(&"example.com".to_string())
+                .await
+                .unwrap();
+            assert_eq!(ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+        });
+    }
+
+    // Queries to an unreachable TCP server go through the redirect outbound,
+    // while the outbound address is looked up on the bootstrap servers.
+    #[cfg(all(feature = "config-json", feature = "outbound-redirect"))]
+    #[test]
+    fn test_tcp_lookup_via_outbound() {
+        use std::sync::atomic::Ordering;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (dns_client, _outbound_manager, queries) = new_client(
+                r#"{
+                    "servers": ["192.0.2.1"],
+                    "tcpServers": ["192.0.2.2", "192.0.2.3:5353"],
+                    "bootstrapServers": ["192.0.2.4"],
+                    "outbound": "dns-out"
+                }"#,
+            )
+            .await;
+            let dns_client = dns_client.read().await;
+            assert_eq!(
+                dns_client.tcp_servers,
+                vec![
+                    "192.0.2.2:53".parse::<SocketAddr>().unwrap(),
+                    "192.0.2.3:5353".parse().unwrap()
+                ]
+            );
+            assert_eq!(
+                dns_client.bootstrap.as_ref().unwrap().read().await.servers,
+                vec!["192.0.2.4:53".parse::<SocketAddr>().unwrap()]
+            );
+
+            let name = Name::from_str("example.com.").unwrap();
+            let req = DnsClient::new_query(name, RecordType::A).to_vec().unwrap();
+            let entry = dns_client
+                .query_task(req, "example.com", &dns_client.tcp_servers[0], true)
+                .await
+                .unwrap();
+            assert_eq!(entry.ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+            assert_eq!(queries.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    // The redirect outbound sends the DoH connection to a port with no TCP
+    // listener, queries fall back to the UDP server.
+    #[cfg(all(
+        feature = "config-json",
+        feature = "outbound-redirect",
+        feature = "outbound-tls"
+    ))]
+    #[test]
+    fn test_doh_fallback() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (dns_client, _outbound_manager, _) = new_client(
+                r#"{
+                    "servers": ["127.0.0.1"],
+                    "dohServers": ["https://dns.example/dns-query"],
+                    "outbound": "dns-out"
+                }"#,
+            )
+            .await;
+            let ips = dns_client
+                .read()
+                .await
+                .lookup(&"example.com".to_string())
+                .await
+                .unwrap();
+            assert_eq!(ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+        });
+    }
+
+    // A DoH server not responding doesn't hold up the fallback to the other
+    // servers for the whole lookup.
+    #[cfg(feature = "outbound-tls")]
+    #[test]
+    fn test_doh_timeout() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let silent_addr = silent.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut conns = Vec::new();
+                loop {
+                    conns.push(silent.accept().await.unwrap().0);
+                }
+            });
+            let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = server.local_addr().unwrap();
+            let queries = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            tokio::spawn(serve_tcp(server, queries));
+
+            let mut dns = crate::config::Dns::new();
+            dns.tcp_servers.push(server_addr.to_string());
+            dns.bootstrap_servers.push("127.0.0.1".to_string());
+            dns.doh_servers.push(format!(
+                "https://doh.example:{}/dns-query",
+                silent_addr.port()
+            ));
+            let mut ips = crate::config::Dns_Ips::new();
+            ips.values.push("127.0.0.1".to_string());
+            dns.hosts.insert("doh.example".to_string(), ips);
+            let dns_client = DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+            let start = Instant::now();
+            let ips = dns_client.lookup(&"example.com".to_string()).await.unwrap();
+            assert_eq!(ips, vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2]);
+            assert!(start.elapsed() < Duration::from_secs(*option::DNS_TIMEOUT * 2));
+        });
+    }
+
+    #[cfg(all(feature = "config-json", feature = "outbound-redirect"))]
+    #[test]
+    fn test_disable_cache() {
+        use std::sync::atomic::Ordering;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let host = "example.com".to_string();
+            let (dns_client, _outbound_manager, queries) =
+                new_client(r#"{"servers": ["192.0.2.1"], "outbound": "dns-out"}"#).await;
+            dns_client.read().await.lookup(&host).await.unwrap();
+            let n = queries.load(Ordering::SeqCst);
+            dns_client.read().await.lookup(&host).await.unwrap();
+            assert_eq!(queries.load(Ordering::SeqCst), n);
+
+            let (dns_client, _outbound_manager, queries) = new_client(
+                r#"{"servers": ["192.0.2.1"], "outbound": "dns-out", "disableCache": true}"#,
+            )
+            .await;
+            dns_client.read().await.lookup(&host).await.unwrap();
+            let n = queries.load(Ordering::SeqCst);
+            dns_client.read().await.lookup(&host).await.unwrap();
+            assert_eq!(queries.load(Ordering::SeqCst), n * 2);
+        });
+    }
+
+    // A record in the last tenth of its TTL is still served, and refreshed in
+    // the background.
+    #[cfg(all(feature = "config-json", feature = "outbound-redirect"))]
+    #[test]
+    fn test_cache_refresh() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let host = "example.com".to_string();
+            let (dns_client, _outbound_manager, _) =
+                new_client(r#"{"servers": ["192.0.2.1"], "outbound": "dns-out"}"#).await;
+            let dns_client = dns_client.read().await;
+            let stale = vec!["10.0.0.1".parse::<IpAddr>().unwrap()];
+            dns_client
+                .cache_insert(
+                    &host,
+                    CacheEntry {
+                        ips: stale.clone(),
+                        deadline: Instant::now() + Duration::from_secs(5),
+                        ttl: Duration::from_secs(60),
+                    },
+                )
+                .await;
+            assert_eq!(dns_client.lookup(&host).await.unwrap(), stale);
+
+            let fresh = vec!["10.1.2.3".parse::<IpAddr>().unwrap(); 2];
+            for _ in 0..50 {
+                if dns_client.lookup(&host).await.unwrap() == fresh {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("record not refreshed");
+        });
+    }
+}
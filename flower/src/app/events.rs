@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::session::Session;
+
+/// Runtime events published to live consumers.
+#[derive(Debug, Clone)]
+pub enum Event {
+    SessionStart {
+        id: u64,
+        network: String,
+        source: String,
+        destination: String,
+        inbound_tag: String,
+        outbound_tag: String,
+    },
+    SessionEnd {
+        id: u64,
+        uplink: u64,
+        downlink: u64,
+    },
+    Error {
+        network: String,
+        source: String,
+        destination: String,
+        inbound_tag: String,
+        outbound_tag: String,
+        error: String,
+    },
+}
+
+impl Event {
+    pub fn error(sess: &Session, outbound_tag: &str, error: String) -> Self {
+        Event::Error {
+            network: sess.network.to_string(),
+            source: sess.source.to_string(),
+            destination: sess.destination.to_string(),
+            inbound_tag: sess.inbound_tag.clone(),
+            outbound_tag: outbound_tag.to_string(),
+            error,
+        }
+    }
+}
+
+/// Fans out events to the subscribers. Publishing never blocks, a subscriber
+/// falling behind by more than the buffer size misses the oldest events.
+pub struct EventBus {
+    tx: broadcast::Sender<Arc<Event>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(*crate::option::EVENT_BUFFER_SIZE)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        EventBus { tx }
+    }
+
+    pub fn publish(&self, event: Event) {
+        // Fails only if there are no subscribers.
+        let _ = self.tx.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_bus_lagging() {
+        let bus = EventBus::new(2);
+        bus.publish(Event::SessionEnd {
+            id: 0,
+            uplink: 0,
+            downlink: 0,
+        });
+        let mut rx = bus.subscribe();
+        for id in 1..=3 {
+            bus.publish(Event::SessionEnd {
+                id,
+                uplink: 0,
+                downlink: 0,
+            });
+        }
+        assert!(matches!(
+            rx.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(1))
+        ));
+        assert!(matches!(
+            *rx.try_recv().unwrap(),
+            Event::SessionEnd { id: 2, .. }
+        ));
+    }
+}
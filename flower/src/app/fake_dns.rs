@@ -31,7 +31,26 @@ impl FakeDns {
     pub fn new(mode: FakeDnsMode) -> Self {
         let min_cursor = Self::ip_to_u32(&Ipv4Addr::new(198, 18, 0, 0));
         let max_cursor = Self::ip_to_u32(&Ipv4Addr::new(198, 18, 4, 255));
+        Self::with_bounds(mode, min_cursor, max_cursor)
+    }
+
+    /// Creates a fake DNS allocating IPs from an IPv4 range in CIDR notation,
+    /// e.g. 198.18.0.0/15.
+    pub fn with_range(mode: FakeDnsMode, range: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid fake dns range {}", range);
+        let (addr, len) = range.split_once('/').ok_or_else(invalid)?;
+        let addr = addr.parse::<Ipv4Addr>().map_err(|_| invalid())?;
+        let len = len
+            .parse::<u32>()
+            .ok()
+            .filter(|len| *len <= 32)
+            .ok_or_else(invalid)?;
+        let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+        let min_cursor = Self::ip_to_u32(&addr) & mask;
+        Ok(Self::with_bounds(mode, min_cursor, min_cursor | !mask))
+    }
 
+    fn with_bounds(mode: FakeDnsMode, min_cursor: u32, max_cursor: u32) -> Self {
         FakeDns {
             ip_to_domain: HashMap::new(),
             domain_to_ip: HashMap::new(),
@@ -56,9 +75,10 @@ impl FakeDns {
         }
         self.domain_to_ip.insert(domain.to_owned(), self.cursor);
         let ip = Self::u32_to_ip(self.cursor);
-        self.cursor += 1;
-        if self.cursor > self.max_cursor {
+        if self.cursor >= self.max_cursor {
             self.cursor = self.min_cursor;
+        } else {
+            self.cursor += 1;
         }
         ip
     }
@@ -203,6 +223,35 @@ mod tests {
         assert_eq!(ip1, ip2);
     }
 
+    #[test]
+    fn test_with_range() {
+        let mut fake_dns = FakeDns::with_range(FakeDnsMode::Exclude, "10.0.0.5/30").unwrap();
+        let ips: Vec<_> = ["a.com", "b.com", "c.com", "d.com", "e.com"]
+            .iter()
+            .map(|d| fake_dns.allocate_ip(d))
+            .collect();
+        assert_eq!(ips[0], Ipv4Addr::new(10, 0, 0, 4));
+        assert_eq!(ips[3], Ipv4Addr::new(10, 0, 0, 7));
+        // Wraps around and takes over the IP of the first domain.
+        assert_eq!(ips[4], ips[0]);
+        assert_eq!(
+            fake_dns.query_domain(&IpAddr::V4(ips[4])),
+            Some("e.com".to_string())
+        );
+        assert_eq!(fake_dns.query_fake_ip("a.com"), None);
+        assert!(fake_dns.is_fake_ip(&"10.0.0.7".parse().unwrap()));
+        assert!(!fake_dns.is_fake_ip(&"10.0.0.8".parse().unwrap()));
+
+        let mut fake_dns = FakeDns::with_range(FakeDnsMode::Exclude, "0.0.0.0/0").unwrap();
+        fake_dns.cursor = u32::MAX;
+        assert_eq!(fake_dns.allocate_ip("a.com"), Ipv4Addr::BROADCAST);
+        assert_eq!(fake_dns.allocate_ip("b.com"), Ipv4Addr::UNSPECIFIED);
+
+        assert!(FakeDns::with_range(FakeDnsMode::Exclude, "198.18.0.0").is_err());
+        assert!(FakeDns::with_range(FakeDnsMode::Exclude, "198.18.0.0/33").is_err());
+        assert!(FakeDns::with_range(FakeDnsMode::Exclude, "fc00::/7").is_err());
+    }
+
     #[test]
     fn test_ip_to_u32() {
         let ip = Ipv4Addr::new(127, 0, 0, 1);
@@ -1,20 +1,26 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Result};
+use log::*;
 use protobuf::Message;
+use tokio::task::JoinHandle;
 
 use crate::app::dispatcher::Dispatcher;
-use crate::app::nat_manager::NatManager;
-use crate::config;
+use crate::app::nat_manager::{self, NatManager};
+use crate::config::{self, diff::Diff};
 use crate::proxy;
-use crate::proxy::AnyInboundHandler;
-use crate::Runner;
+use crate::proxy::{AnyInboundHandler, NoDelay};
 
 #[cfg(feature = "inbound-amux")]
 use crate::proxy::amux;
+#[cfg(feature = "inbound-grpc")]
+use crate::proxy::grpc;
 #[cfg(feature = "inbound-http")]
 use crate::proxy::http;
+#[cfg(feature = "inbound-obfs")]
+use crate::proxy::obfs;
 #[cfg(feature = "inbound-quic")]
 use crate::proxy::quic;
 #[cfg(feature = "inbound-shadowsocks")]
@@ -23,6 +29,11 @@ use crate::proxy::shadowsocks;
 use crate::proxy::socks;
 #[cfg(feature = "inbound-tls")]
 use crate::proxy::tls;
+#[cfg(all(
+    feature = "inbound-tproxy",
+    any(target_os = "linux", target_os = "macos")
+))]
+use crate::proxy::tproxy;
 #[cfg(feature = "inbound-trojan")]
 use crate::proxy::trojan;
 #[cfg(feature = "inbound-ws")]
@@ -45,7 +56,15 @@ use super::network_listener::NetworkInboundListener;
 use super::tun_listener::TunInboundListener;
 
 pub struct InboundManager {
+    inbounds: protobuf::RepeatedField<config::Inbound>,
+    dispatcher: Arc<Dispatcher>,
+    nat_manager: Arc<NatManager>,
     network_listeners: HashMap<String, NetworkInboundListener>,
+    listener_tasks: HashMap<String, JoinHandle<()>>,
+    bound_addrs: HashMap<String, SocketAddr>,
+    // Listeners bound by `stage`, spawned by `swap`. The runners are not
+    // Sync, the mutex keeps the manager so.
+    staged_listeners: Mutex<HashMap<String, (Vec<crate::Runner>, SocketAddr)>>,
     #[cfg(all(
         feature = "inbound-tun",
         any(
@@ -72,8 +91,33 @@ impl InboundManager {
             match inbound.protocol.as_str() {
                 #[cfg(feature = "inbound-socks")]
                 "socks" => {
-                    let tcp = Arc::new(socks::inbound::TcpHandler);
-                    let udp = Arc::new(socks::inbound::UdpHandler);
+                    let settings =
+                        config::SocksInboundSettings::parse_from_bytes(&inbound.settings)
+                            .map_err(|e| anyhow!("invalid [{}] inbound settings: {}", &tag, e))?;
+                    let advertised_address = if settings.advertised_address.is_empty() {
+                        None
+                    } else {
+                        // The port is the one actually bound, it's unknown
+                        // until listening with a random port.
+                        crate::session::SocksAddr::try_from((
+                            settings.advertised_address.clone(),
+                            0,
+                        ))
+                        .map_err(|e| anyhow!("invalid [{}] advertised address: {}", &tag, e))?;
+                        Some(settings.advertised_address.clone())
+                    };
+                    let associations = if settings.username.is_empty() {
+                        None
+                    } else {
+                        Some(Arc::new(socks::inbound::UdpAssociations::new()))
+                    };
+                    let tcp = Arc::new(socks::inbound::TcpHandler {
+                        advertised_address,
+                        username: settings.username.clone(),
+                        password: settings.password.clone(),
+                        associations: associations.clone(),
+                    });
+                    let udp = Arc::new(socks::inbound::UdpHandler { associations });
                     let handler = Arc::new(proxy::inbound::Handler::new(
                         tag.clone(),
                         Some(tcp),
@@ -88,17 +132,37 @@ impl InboundManager {
                         Arc::new(proxy::inbound::Handler::new(tag.clone(), Some(tcp), None));
                     handlers.insert(tag.clone(), handler);
                 }
+                #[cfg(all(
+                    feature = "inbound-tproxy",
+                    any(target_os = "linux", target_os = "macos")
+                ))]
+                "tproxy" => {
+                    let tcp = Arc::new(tproxy::inbound::TcpHandler);
+                    // pf doesn't expose the original destination of UDP.
+                    #[cfg(target_os = "linux")]
+                    let udp: Option<proxy::AnyUdpInboundHandler> =
+                        Some(Arc::new(tproxy::inbound::UdpHandler));
+                    #[cfg(target_os = "macos")]
+                    let udp = None;
+                    let handler =
+                        Arc::new(proxy::inbound::Handler::new(tag.clone(), Some(tcp), udp));
+                    handlers.insert(tag.clone(), handler);
+                }
                 #[cfg(feature = "inbound-shadowsocks")]
                 "shadowsocks" => {
                     let settings =
                         config::ShadowsocksInboundSettings::parse_from_bytes(&inbound.settings)
                             .map_err(|e| anyhow!("invalid [{}] inbound settings: {}", &tag, e))?;
+                    let cipher = settings
+                        .method
+                        .parse()
+                        .map_err(|e| anyhow!("invalid [{}] inbound settings: {}", &tag, e))?;
                     let tcp = Arc::new(shadowsocks::inbound::TcpHandler {
-                        cipher: settings.method.clone(),
+                        cipher,
                         password: settings.password.clone(),
                     });
                     let udp = Arc::new(shadowsocks::inbound::UdpHandler {
-                        cipher: settings.method.clone(),
+                        cipher,
                         password: settings.password.clone(),
                     });
                     let handler = Arc::new(proxy::inbound::Handler::new(
@@ -127,6 +191,28 @@ impl InboundManager {
                         Arc::new(proxy::inbound::Handler::new(tag.clone(), Some(tcp), None));
                     handlers.insert(tag.clone(), handler);
                 }
+                #[cfg(feature = "inbound-grpc")]
+                "grpc" => {
+                    let settings = config::GrpcInboundSettings::parse_from_bytes(&inbound.settings)
+                        .map_err(|e| anyhow!("invalid [{}] inbound settings: {}", &tag, e))?;
+                    let tcp = Arc::new(grpc::inbound::TcpHandler::new(&settings.service_name));
+                    let handler =
+                        Arc::new(proxy::inbound::Handler::new(tag.clone(), Some(tcp), None));
+                    handlers.insert(tag.clone(), handler);
+                }
+                #[cfg(feature = "inbound-obfs")]
+                "obfs" => {
+                    let settings = config::ObfsInboundSettings::parse_from_bytes(&inbound.settings)
+                        .map_err(|e| anyhow!("invalid [{}] inbound settings: {}", &tag, e))?;
+                    let mode = settings
+                        .mode
+                        .parse()
+                        .map_err(|e| anyhow!("invalid [{}] inbound settings: {}", &tag, e))?;
+                    let tcp = Arc::new(obfs::inbound::TcpHandler::new(mode, settings.path.clone()));
+                    let handler =
+                        Arc::new(proxy::inbound::Handler::new(tag.clone(), Some(tcp), None));
+                    handlers.insert(tag.clone(), handler);
+                }
                 #[cfg(feature = "inbound-quic")]
                 "quic" => {
                     let settings =
@@ -134,6 +220,11 @@ impl InboundManager {
                     let udp = Arc::new(quic::inbound::UdpHandler::new(
                         settings.certificate.clone(),
                         settings.certificate_key.clone(),
+                        settings.certificate_password.clone(),
+                        settings.alpn.to_vec(),
+                        settings.idle_timeout,
+                        settings.max_concurrent_bidi_streams,
+                        settings.datagram,
                     ));
                     let handler =
                         Arc::new(proxy::inbound::Handler::new(tag.clone(), None, Some(udp)));
@@ -146,6 +237,14 @@ impl InboundManager {
                     let tcp = Arc::new(tls::inbound::TcpHandler::new(
                         settings.certificate.clone(),
                         settings.certificate_key.clone(),
+                        settings.certificate_password.clone(),
+                        settings.client_ca.clone(),
+                        tls::Protocols::new(
+                            &settings.min_version,
+                            &settings.max_version,
+                            &settings.cipher_suites,
+                        )
+                        .map_err(|e| anyhow!("invalid [{}] inbound settings: {}", &tag, e))?,
                     )?);
                     let handler =
                         Arc::new(proxy::inbound::Handler::new(tag.clone(), Some(tcp), None));
@@ -187,6 +286,28 @@ impl InboundManager {
                                 .map_err(|e| {
                                     anyhow!("invalid [{}] inbound settings: {}", &tag, e)
                                 })?;
+                        // The UDP sessions relayed in QUIC datagrams would
+                        // skip the actors after the QUIC one, e.g. their
+                        // authentication.
+                        #[cfg(feature = "inbound-quic")]
+                        if settings.actors.len() > 1 {
+                            for a in inbounds.iter().filter(|x| {
+                                x.protocol == "quic" && settings.actors.contains(&x.tag)
+                            }) {
+                                let quic =
+                                    config::QuicInboundSettings::parse_from_bytes(&a.settings)
+                                        .map_err(|e| {
+                                            anyhow!("invalid [{}] inbound settings: {}", &a.tag, e)
+                                        })?;
+                                if quic.datagram {
+                                    return Err(anyhow!(
+                                        "invalid [{}] inbound settings: [{}] relays udp in quic datagrams and can't be chained",
+                                        &tag,
+                                        &a.tag
+                                    ));
+                                }
+                            }
+                        }
                         let mut actors = Vec::new();
                         for actor in settings.actors.iter() {
                             if let Some(a) = handlers.get(actor) {
@@ -251,11 +372,16 @@ impl InboundManager {
                     tun_auto = settings.auto;
                 }
                 _ => {
-                    if inbound.port != 0 {
+                    if inbound.port != 0 || inbound.random_port {
                         if let Some(h) = handlers.get(&tag) {
+                            let nodelay = inbound.nodelay.parse::<NoDelay>().map_err(|e| {
+                                anyhow!("invalid [{}] inbound nodelay: {}", &tag, e)
+                            })?;
                             let listener = NetworkInboundListener {
                                 address: inbound.address.clone(),
                                 port: inbound.port as u16,
+                                nodelay,
+                                transparent: inbound.protocol == "tproxy",
                                 handler: h.clone(),
                                 dispatcher: dispatcher.clone(),
                                 nat_manager: nat_manager.clone(),
@@ -268,7 +394,13 @@ impl InboundManager {
         }
 
         Ok(InboundManager {
+            inbounds: inbounds.clone(),
+            dispatcher,
+            nat_manager,
             network_listeners,
+            listener_tasks: HashMap::new(),
+            bound_addrs: HashMap::new(),
+            staged_listeners: Mutex::new(HashMap::new()),
             #[cfg(all(
                 feature = "inbound-tun",
                 any(
@@ -283,12 +415,163 @@ impl InboundManager {
         })
     }
 
-    pub fn get_network_runners(&self) -> Result<Vec<Runner>> {
-        let mut runners: Vec<Runner> = Vec::new();
-        for (_, listener) in self.network_listeners.iter() {
-            runners.append(&mut listener.listen()?);
+    fn start_listener(&mut self, tag: &str) -> Result<()> {
+        if let Some(listener) = self.network_listeners.get(tag) {
+            let (runners, addr) = listener.listen()?;
+            self.spawn_listener(tag, runners, addr);
+        }
+        Ok(())
+    }
+
+    fn spawn_listener(&mut self, tag: &str, runners: Vec<crate::Runner>, addr: SocketAddr) {
+        let task = tokio::spawn(async move {
+            futures::future::join_all(runners).await;
+        });
+        self.listener_tasks.insert(tag.to_string(), task);
+        self.bound_addrs.insert(tag.to_string(), addr);
+    }
+
+    async fn stop_listener(&mut self, tag: &str) {
+        self.bound_addrs.remove(tag);
+        if let Some(task) = self.listener_tasks.remove(tag) {
+            task.abort();
+            // Waits for the sockets being closed so that the addresses can
+            // be bound again.
+            let _ = task.await;
+        }
+    }
+
+    /// Binds the network inbounds and spawns the listening tasks, must be
+    /// called within a runtime. The bound addresses are available from
+    /// `bound_addrs` afterwards.
+    pub fn start_listeners(&mut self) -> Result<()> {
+        let tags: Vec<String> = self.network_listeners.keys().cloned().collect();
+        for tag in tags {
+            self.start_listener(&tag)?;
+        }
+        Ok(())
+    }
+
+    /// Stops all network inbounds from accepting new connections, the
+    /// relays already spawned keep running.
+    pub async fn stop_listeners(&mut self) {
+        let tags: Vec<String> = self.listener_tasks.keys().cloned().collect();
+        for tag in tags {
+            self.stop_listener(&tag).await;
+        }
+    }
+
+    pub fn has_network_listeners(&self) -> bool {
+        !self.network_listeners.is_empty()
+    }
+
+    /// Builds the inbounds of a new config, sharing the dispatcher of this
+    /// manager, and binds the network inbounds added or updated since the
+    /// last load. Those listening on a port the current inbounds bind are
+    /// left to `swap`, which can only bind them once the current ones close.
+    pub fn stage(&self, inbounds: &protobuf::RepeatedField<config::Inbound>) -> Result<Self> {
+        let mut staged = Self::new(inbounds, self.dispatcher.clone(), self.nat_manager.clone())?;
+        let diff = Diff::inbounds(&self.inbounds, &staged.inbounds);
+        for tag in diff.added.iter().chain(diff.updated.iter()) {
+            let listener = match staged.network_listeners.get(tag) {
+                Some(v) => v,
+                None => continue,
+            };
+            if listener.port != 0 && self.bound_addrs.values().any(|x| x.port() == listener.port) {
+                continue;
+            }
+            let bound = listener.listen()?;
+            staged
+                .staged_listeners
+                .get_mut()
+                .unwrap()
+                .insert(tag.clone(), bound);
+        }
+        Ok(staged)
+    }
+
+    /// Swaps in the inbounds built by `stage`, restarting the network inbounds
+    /// changed since the last load, along with the ones depending on them.
+    /// The others keep listening. If any of the new inbounds fails to bind,
+    /// the current ones are restarted and nothing is swapped in. Changes to
+    /// the TUN inbound take effect only after a restart.
+    pub async fn swap(&mut self, mut staged: Self) -> Result<Diff> {
+        let diff = Diff::inbounds(&self.inbounds, &staged.inbounds);
+        if diff.is_empty() {
+            return Ok(diff);
+        }
+        let closed: Vec<String> = diff
+            .removed
+            .iter()
+            .chain(diff.updated.iter())
+            .cloned()
+            .collect();
+        let closed_addrs = self.bound_addrs.clone();
+        for tag in closed.iter() {
+            self.stop_listener(tag).await;
         }
-        Ok(runners)
+        for tag in diff.added.iter().chain(diff.updated.iter()) {
+            if staged.staged_listeners.get_mut().unwrap().contains_key(tag) {
+                continue;
+            }
+            let res = match staged.network_listeners.get(tag) {
+                Some(listener) => listener.listen(),
+                None => continue,
+            };
+            match res {
+                Ok(bound) => {
+                    staged
+                        .staged_listeners
+                        .get_mut()
+                        .unwrap()
+                        .insert(tag.clone(), bound);
+                }
+                Err(e) => {
+                    // Closes the new listeners before rebinding the current
+                    // ones.
+                    drop(staged);
+                    for tag in closed.iter() {
+                        // On the ports they had, random ones included.
+                        if let (Some(listener), Some(addr)) =
+                            (self.network_listeners.get_mut(tag), closed_addrs.get(tag))
+                        {
+                            listener.port = addr.port();
+                        }
+                        if let Err(e) = self.start_listener(tag) {
+                            error!("restart [{}] inbound failed: {}", tag, e);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        for tag in closed.iter() {
+            self.network_listeners.remove(tag);
+        }
+        for (tag, (runners, addr)) in std::mem::take(staged.staged_listeners.get_mut().unwrap()) {
+            if let Some(listener) = staged.network_listeners.remove(&tag) {
+                self.network_listeners.insert(tag.clone(), listener);
+            }
+            self.spawn_listener(&tag, runners, addr);
+        }
+        if self
+            .inbounds
+            .iter()
+            .chain(staged.inbounds.iter())
+            .any(|x| x.protocol == "tun" && diff.contains(&x.tag))
+        {
+            warn!("tun inbound changed, restart to apply");
+        }
+        self.nat_manager
+            .set_session_timeouts(nat_manager::session_timeouts(&staged.inbounds))
+            .await;
+        self.inbounds = std::mem::take(&mut staged.inbounds);
+        Ok(diff)
+    }
+
+    /// Actually bound addresses of the network inbounds, keyed by tag.
+    pub fn bound_addrs(&self) -> &HashMap<String, SocketAddr> {
+        &self.bound_addrs
     }
 
     #[cfg(all(
@@ -300,7 +583,7 @@ impl InboundManager {
             target_os = "linux"
         )
     ))]
-    pub fn get_tun_runner(&self) -> Result<Runner> {
+    pub fn get_tun_runner(&self) -> Result<crate::Runner> {
         if let Some(listener) = &self.tun_listener {
             return listener.listen();
         }
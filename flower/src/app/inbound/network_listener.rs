@@ -1,7 +1,7 @@
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use futures::stream::StreamExt;
 use log::*;
@@ -92,6 +92,7 @@ async fn handle_inbound_datagram(
                         source: dgram_src.address,
                         destination: dst_addr.clone(),
                         inbound_tag: inbound_tag.clone(),
+                        protocol: crate::common::sniff::sniff_datagram_protocol(&buf[..n]),
                         ..Default::default()
                     };
 
@@ -99,12 +100,7 @@ async fn handle_inbound_datagram(
                         .add_session(&sess, dgram_src, client_ch_tx.clone())
                         .await;
 
-                    debug!(
-                        "added udp session {} -> {} ({})",
-                        &dgram_src,
-                        &dst_addr.to_string(),
-                        nat_manager.size().await,
-                    );
+                    debug!("added udp session {} ({})", &sess, nat_manager.size().await);
                 }
 
                 let pkt = UdpPacket {
@@ -118,18 +114,99 @@ async fn handle_inbound_datagram(
     }
 }
 
+// Accepts traffic redirected by TPROXY on the socket.
+#[cfg(all(feature = "inbound-tproxy", target_os = "linux"))]
+fn set_transparent<S: std::os::unix::io::AsRawFd>(socket: &S) -> std::io::Result<()> {
+    crate::common::net::set_ip_transparent(&socket2::SockRef::from(socket))
+}
+
+// pf redirects connections to the local address, nothing to set.
+#[cfg(all(feature = "inbound-tproxy", target_os = "macos"))]
+fn set_transparent<S>(_socket: &S) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(all(
+    feature = "inbound-tproxy",
+    any(target_os = "linux", target_os = "macos")
+)))]
+fn set_transparent<S>(_socket: &S) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "transparent proxy not supported",
+    ))
+}
+
+#[cfg(all(
+    feature = "inbound-tproxy",
+    any(target_os = "linux", target_os = "macos")
+))]
+async fn original_dst(stream: &TcpStream) -> std::io::Result<SocketAddr> {
+    crate::proxy::tproxy::inbound::original_dst(stream).await
+}
+
+#[cfg(not(all(
+    feature = "inbound-tproxy",
+    any(target_os = "linux", target_os = "macos")
+)))]
+async fn original_dst(stream: &TcpStream) -> std::io::Result<SocketAddr> {
+    stream.local_addr()
+}
+
+// The pf rules redirecting TCP to a transparent listener, removed along with
+// the listener.
+#[cfg(all(feature = "inbound-tproxy", target_os = "macos"))]
+struct PfRedirect(u16);
+
+#[cfg(all(feature = "inbound-tproxy", target_os = "macos"))]
+impl PfRedirect {
+    fn add(addr: SocketAddr) -> Result<Self> {
+        let interface = crate::common::cmd::get_default_interface()?;
+        crate::common::cmd::add_pf_redirect_rules(&interface, addr)?;
+        Ok(PfRedirect(addr.port()))
+    }
+}
+
+#[cfg(all(feature = "inbound-tproxy", target_os = "macos"))]
+impl Drop for PfRedirect {
+    fn drop(&mut self) {
+        if let Err(e) = crate::common::cmd::delete_pf_redirect_rules(self.0) {
+            warn!("delete pf rules failed: {}", e);
+        }
+    }
+}
+
 async fn handle_inbound_stream(
     stream: TcpStream,
+    nodelay: NoDelay,
+    transparent: bool,
+    listen_addr: SocketAddr,
     h: AnyInboundHandler,
     dispatcher: Arc<Dispatcher>,
     nat_manager: Arc<NatManager>,
 ) {
+    let switch = match apply_nodelay(&stream, nodelay) {
+        Ok(v) => v,
+        Err(e) => {
+            debug!("set nodelay failed: {}", e);
+            None
+        }
+    };
     let source = stream
         .peer_addr()
         .unwrap_or_else(|_| *crate::option::UNSPECIFIED_BIND_ADDR);
-    let local_addr = stream
-        .local_addr()
-        .unwrap_or_else(|_| *crate::option::UNSPECIFIED_BIND_ADDR);
+    // The original destination for redirected connections.
+    let local_addr = if transparent {
+        original_dst(&stream).await
+    } else {
+        stream.local_addr()
+    }
+    .unwrap_or_else(|_| *crate::option::UNSPECIFIED_BIND_ADDR);
+    // Proxying connections to the listener itself would loop.
+    if transparent && crate::common::net::is_listener_addr(&local_addr, &listen_addr) {
+        debug!("dropping connection from {} to the listener", source);
+        return;
+    }
     let sess = Session {
         network: Network::Tcp,
         source,
@@ -141,7 +218,9 @@ async fn handle_inbound_stream(
     match TcpInboundHandler::handle(h.as_ref(), sess, Box::new(stream)).await {
         Ok(res) => match res {
             InboundTransport::Stream(stream, mut sess) => {
-                dispatcher.dispatch_tcp(&mut sess, stream).await;
+                dispatcher
+                    .dispatch_tcp_with_nodelay(&mut sess, stream, switch)
+                    .await;
             }
             InboundTransport::Datagram(socket) => {
                 handle_inbound_datagram(h.tag().clone(), socket, nat_manager).await;
@@ -177,30 +256,108 @@ async fn handle_inbound_stream(
 pub struct NetworkInboundListener {
     pub address: String,
     pub port: u16,
+    pub nodelay: NoDelay,
+    /// Accepts traffic redirected by TPROXY, REDIRECT or pf, the local
+    /// address of the sessions is the original destination. On macOS, the pf
+    /// rules redirecting TCP arriving on the default interface are added
+    /// while listening.
+    pub transparent: bool,
     pub handler: AnyInboundHandler,
     pub dispatcher: Arc<Dispatcher>,
     pub nat_manager: Arc<NatManager>,
 }
 
+// Attempts to find a port free for both TCP and UDP if the port is 0.
+const BIND_ATTEMPTS: usize = 10;
+
 impl NetworkInboundListener {
-    pub fn listen(&self) -> Result<Vec<Runner>> {
+    // Binds the sockets the handler needs. A random free port is picked if the
+    // port is 0, in which case the UDP socket shares the port picked for TCP,
+    // another one is picked if it's taken for UDP.
+    fn bind(
+        &self,
+    ) -> Result<(
+        Option<std::net::TcpListener>,
+        Option<std::net::UdpSocket>,
+        SocketAddr,
+    )> {
+        let addr = SocketAddr::new(self.address.parse::<IpAddr>()?, self.port);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let mut bound_addr = addr;
+            let listener = if self.handler.has_tcp() {
+                let listener = std::net::TcpListener::bind(bound_addr)
+                    .map_err(|e| anyhow!("bind tcp {} failed: {}", &bound_addr, e))?;
+                bound_addr = listener.local_addr()?;
+                Some(listener)
+            } else {
+                None
+            };
+            let socket = if self.handler.has_udp() {
+                let retry = listener.is_some() && addr.port() == 0 && attempts < BIND_ATTEMPTS;
+                match std::net::UdpSocket::bind(bound_addr) {
+                    Ok(socket) => {
+                        bound_addr = socket.local_addr()?;
+                        Some(socket)
+                    }
+                    Err(_) if retry => continue,
+                    Err(e) => return Err(anyhow!("bind udp {} failed: {}", &bound_addr, e)),
+                }
+            } else {
+                None
+            };
+            return Ok((listener, socket, bound_addr));
+        }
+    }
+
+    /// Binds the sockets and returns the runners serving them, along with the
+    /// bound address. A random free port is picked if the port is 0, in which
+    /// case the UDP socket shares the port picked for TCP.
+    pub fn listen(&self) -> Result<(Vec<Runner>, SocketAddr)> {
         let mut runners: Vec<Runner> = Vec::new();
         let handler = self.handler.clone();
         let dispatcher = self.dispatcher.clone();
         let nat_manager = self.nat_manager.clone();
-        let address = self.address.clone();
-        let port = self.port;
+        let nodelay = self.nodelay;
+        let transparent = self.transparent;
+        let (listener, socket, bound_addr) = self.bind()?;
 
-        if self.handler.has_tcp() {
-            let listen_addr = SocketAddr::new(address.parse::<IpAddr>()?, port);
+        if let Some(listener) = listener {
+            if transparent {
+                set_transparent(&listener)
+                    .map_err(|e| anyhow!("set transparent tcp {} failed: {}", &bound_addr, e))?;
+            }
+            listener.set_nonblocking(true)?;
+            let listen_addr = bound_addr;
+            #[cfg(all(feature = "inbound-tproxy", target_os = "macos"))]
+            let pf_redirect = if transparent {
+                Some(
+                    PfRedirect::add(listen_addr)
+                        .map_err(|e| anyhow!("add pf rules for {} failed: {}", &listen_addr, e))?,
+                )
+            } else {
+                None
+            };
             let tcp_task = async move {
-                let listener = TcpListener::bind(&listen_addr).await.unwrap();
+                #[cfg(all(feature = "inbound-tproxy", target_os = "macos"))]
+                let _pf_redirect = pf_redirect;
+                let listener = match TcpListener::from_std(listener) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("listen tcp {} failed: {}", &listen_addr, e);
+                        return;
+                    }
+                };
                 info!("inbound listening tcp {}", &listen_addr);
                 loop {
                     match listener.accept().await {
                         Ok((stream, _)) => {
                             tokio::spawn(handle_inbound_stream(
                                 stream,
+                                nodelay,
+                                transparent,
+                                listen_addr,
                                 handler.clone(),
                                 dispatcher.clone(),
                                 nat_manager.clone(),
@@ -216,15 +373,24 @@ impl NetworkInboundListener {
             runners.push(Box::pin(tcp_task));
         }
 
-        if self.handler.has_udp() {
+        if let Some(socket) = socket {
             let nat_manager = self.nat_manager.clone();
             let dispatcher = self.dispatcher.clone();
             let handler = self.handler.clone();
-            let address = self.address.clone();
-            let port = self.port;
-            let listen_addr = SocketAddr::new(address.parse()?, port);
+            if transparent {
+                set_transparent(&socket)
+                    .map_err(|e| anyhow!("set transparent udp {} failed: {}", &bound_addr, e))?;
+            }
+            socket.set_nonblocking(true)?;
+            let listen_addr = bound_addr;
             let udp_task = async move {
-                let socket = UdpSocket::bind(&listen_addr).await.unwrap();
+                let socket = match UdpSocket::from_std(socket) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("listen udp {} failed: {}", &listen_addr, e);
+                        return;
+                    }
+                };
                 info!("inbound listening udp {}", &listen_addr);
 
                 // FIXME spawn
@@ -266,13 +432,13 @@ impl NetworkInboundListener {
                         InboundTransport::Empty => (),
                     },
                     Err(e) => {
-                        debug!("handle inbound socket failed: {}", e);
+                        error!("handle inbound udp {} failed: {}", &listen_addr, e);
                     }
                 }
             };
             runners.push(Box::pin(udp_task));
         }
 
-        Ok(runners)
+        Ok((runners, bound_addr))
     }
 }
@@ -0,0 +1,122 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A log file rotated by size. Once the file grows beyond the limit, it's
+/// renamed to `<path>.1`, the older ones shifted to `<path>.2` and so on, up
+/// to the number of retained files, and a new file is started.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+    line_start: bool,
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(format!(".{}", n));
+    PathBuf::from(p)
+}
+
+impl RotatingFile {
+    /// Opens the file for appending, a `max_size` of 0 disables rotation.
+    pub fn new<P: AsRef<Path>>(path: P, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+            line_start: true,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated_path(&self.path, self.max_files));
+            for n in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, n);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A line may come in several writes, rotates only between lines.
+        if self.max_size > 0 && self.size >= self.max_size && self.line_start {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        if n > 0 {
+            self.line_start = buf[n - 1] == b'\n';
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("flower-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("flower.log");
+        let mut f = RotatingFile::new(&path, 4, 2).unwrap();
+        for line in ["line-0", "line-1", "line-2", "line-3"] {
+            f.write_all(line.as_bytes()).unwrap();
+            f.write_all(b"\n").unwrap();
+        }
+        f.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line-3\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "line-2\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "line-1\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+
+        // Appends to the existing file.
+        drop(f);
+        let mut f = RotatingFile::new(&path, 4, 0).unwrap();
+        f.write_all(b"line-4\n").unwrap();
+        f.write_all(b"line-5\n").unwrap();
+        f.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line-5\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "line-2\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,23 +1,170 @@
-use crate::config;
+use std::cell::Cell;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::{anyhow, Result};
+use log::LevelFilter;
+
+use crate::config;
+use crate::RuntimeId;
+
+use super::log_file::RotatingFile;
+
+thread_local! {
+    static RUNTIME_ID: Cell<Option<RuntimeId>> = const { Cell::new(None) };
+}
+
+tokio::task_local! {
+    static SESSION_ID: Cell<Option<u64>>;
+}
+
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+/// Tags the lines logged on the current thread with the runtime id, all
+/// threads of a runtime are expected to call this on start.
+pub fn set_runtime_id(id: RuntimeId) {
+    RUNTIME_ID.with(|x| x.set(Some(id)));
+}
+
+/// Runs the future in a scope in which `set_session_id` tags the lines
+/// logged by the task.
+pub async fn session_scope<F: Future>(f: F) -> F::Output {
+    SESSION_ID.scope(Cell::new(None), f).await
+}
+
+/// Tags the lines logged by the current task with the session id, takes
+/// effect only within a `session_scope`.
+pub fn set_session_id(id: u64) {
+    let _ = SESSION_ID.try_with(|x| x.set(Some(id)));
+}
+
+fn runtime_id() -> Option<RuntimeId> {
+    RUNTIME_ID.with(|x| x.get())
+}
+
+fn session_id() -> Option<u64> {
+    SESSION_ID.try_with(|x| x.get()).ok().flatten()
+}
+
+/// Whether log messages may carry ANSI colors.
+pub fn colored() -> bool {
+    !*crate::option::LOG_NO_COLOR && !JSON_FORMAT.load(Ordering::Relaxed)
+}
+
+fn level_filter(level: config::Log_Level) -> LevelFilter {
+    match level {
+        config::Log_Level::TRACE => LevelFilter::Trace,
+        config::Log_Level::DEBUG => LevelFilter::Debug,
+        config::Log_Level::INFO => LevelFilter::Info,
+        config::Log_Level::WARN => LevelFilter::Warn,
+        config::Log_Level::ERROR => LevelFilter::Error,
+    }
+}
+
+/// Parses a per-target override in the form of "target=level".
+fn parse_target_level(s: &str) -> Result<(String, LevelFilter)> {
+    let (target, level) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("invalid target level {}", s))?;
+    let target = target.trim();
+    if target.is_empty() {
+        return Err(anyhow!("invalid target level {}", s));
+    }
+    let level = level
+        .trim()
+        .parse::<LevelFilter>()
+        .map_err(|_| anyhow!("invalid log level in {}", s))?;
+    Ok((target.to_string(), level))
+}
+
+fn context() -> String {
+    let mut ctx = String::new();
+    if let Some(id) = runtime_id() {
+        let _ = write!(ctx, "[rt-{}]", id);
+    }
+    if let Some(id) = session_id() {
+        let _ = write!(ctx, "[sess-{}]", id);
+    }
+    ctx
+}
+
+fn escape_json(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+fn json_line(
+    time: &str,
+    level: log::Level,
+    target: &str,
+    rt_id: Option<RuntimeId>,
+    sess_id: Option<u64>,
+    message: &str,
+) -> String {
+    let mut line = String::with_capacity(128 + message.len());
+    let _ = write!(
+        line,
+        "{{\"time\":\"{}\",\"level\":\"{}\",\"target\":\"",
+        time, level
+    );
+    escape_json(target, &mut line);
+    line.push('"');
+    if let Some(id) = rt_id {
+        let _ = write!(line, ",\"runtime\":{}", id);
+    }
+    if let Some(id) = sess_id {
+        let _ = write!(line, ",\"session\":{}", id);
+    }
+    line.push_str(",\"message\":\"");
+    escape_json(message, &mut line);
+    line.push_str("\"}");
+    line
+}
 
 pub fn setup_logger(config: &config::Log) -> Result<()> {
-    let loglevel = match config.level {
-        config::Log_Level::TRACE => log::LevelFilter::Trace,
-        config::Log_Level::DEBUG => log::LevelFilter::Debug,
-        config::Log_Level::INFO => log::LevelFilter::Info,
-        config::Log_Level::WARN => log::LevelFilter::Warn,
-        config::Log_Level::ERROR => log::LevelFilter::Error,
-    };
+    let loglevel = level_filter(config.level);
+    let target_levels = config
+        .target_levels
+        .iter()
+        .map(|x| parse_target_level(x))
+        .collect::<Result<Vec<_>>>()?;
+    let json = config.format == config::Log_Format::JSON;
+    JSON_FORMAT.store(json, Ordering::Relaxed);
+
+    crate::session::set_redact_user(config.redact_user);
 
     let mut dispatch = fern::Dispatch::new()
         .format(move |out, message, record| {
-            if *crate::option::LOG_NO_COLOR {
+            if json {
                 out.finish(format_args!(
-                    "[{date}][{level}] {message}",
+                    "{}",
+                    json_line(
+                        &chrono::Local::now().to_rfc3339(),
+                        record.level(),
+                        record.target(),
+                        runtime_id(),
+                        session_id(),
+                        &message.to_string(),
+                    )
+                ))
+            } else if *crate::option::LOG_NO_COLOR {
+                out.finish(format_args!(
+                    "[{date}][{level}]{context} {message}",
                     date = chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
                     level = record.level(),
+                    context = context(),
                     message = message,
                 ))
             } else {
@@ -32,7 +179,7 @@ pub fn setup_logger(config: &config::Log) -> Result<()> {
                 let colors_level = colors_line.info(Color::Green);
                 out.finish(format_args!(
                     // "{color_line}[{date}][{level}{color_line}][{target}] {message}\x1B[0m",
-                    "{color_line}[{date}][{level}{color_line}] {message}\x1B[0m",
+                    "{color_line}[{date}][{level}{color_line}]{context} {message}\x1B[0m",
                     color_line = format_args!(
                         "\x1B[{}m",
                         colors_line.get_color(&record.level()).to_fg_str()
@@ -40,6 +187,7 @@ pub fn setup_logger(config: &config::Log) -> Result<()> {
                     date = chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
                     // target = record.target(),
                     level = colors_level.color(record.level()),
+                    context = context(),
                     message = message,
                 ))
             }
@@ -47,6 +195,10 @@ pub fn setup_logger(config: &config::Log) -> Result<()> {
         .level(log::LevelFilter::Warn)
         .level_for("flower", loglevel);
 
+    for (target, level) in target_levels {
+        dispatch = dispatch.level_for(target, level);
+    }
+
     match config.output {
         config::Log_Output::CONSOLE => {
             #[cfg(any(target_os = "ios", target_os = "android"))]
@@ -71,9 +223,18 @@ pub fn setup_logger(config: &config::Log) -> Result<()> {
             }
         }
         config::Log_Output::FILE => {
-            let f = fern::log_file(&config.output_file)?;
-            let file_output = fern::Output::file(f, "\n");
-            dispatch = dispatch.chain(file_output);
+            if config.max_size_mb > 0 {
+                let f = RotatingFile::new(
+                    &config.output_file,
+                    config.max_size_mb as u64 * 1024 * 1024,
+                    config.max_files as usize,
+                )?;
+                dispatch = dispatch.chain(fern::Output::writer(Box::new(f), "\n"));
+            } else {
+                let f = fern::log_file(&config.output_file)?;
+                let file_output = fern::Output::file(f, "\n");
+                dispatch = dispatch.chain(file_output);
+            }
         }
     }
 
@@ -83,3 +244,69 @@ pub fn setup_logger(config: &config::Log) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target_level() {
+        let (target, level) = parse_target_level("flower::proxy = debug").unwrap();
+        assert_eq!(target, "flower::proxy");
+        assert_eq!(level, LevelFilter::Debug);
+        let (_, level) = parse_target_level("rustls=OFF").unwrap();
+        assert_eq!(level, LevelFilter::Off);
+        assert!(parse_target_level("flower::proxy").is_err());
+        assert!(parse_target_level("=debug").is_err());
+        assert!(parse_target_level("flower=verbose").is_err());
+    }
+
+    #[test]
+    fn test_json_line() {
+        let line = json_line(
+            "2021-01-01T00:00:00+00:00",
+            log::Level::Info,
+            "flower::app",
+            Some(1),
+            Some(42),
+            "say \"hi\"\n\u{1}",
+        );
+        assert_eq!(
+            line,
+            r#"{"time":"2021-01-01T00:00:00+00:00","level":"INFO","target":"flower::app","runtime":1,"session":42,"message":"say \"hi\"\n\u0001"}"#
+        );
+        let line = json_line(
+            "2021-01-01T00:00:00+00:00",
+            log::Level::Warn,
+            "flower",
+            None,
+            None,
+            "",
+        );
+        assert_eq!(
+            line,
+            r#"{"time":"2021-01-01T00:00:00+00:00","level":"WARN","target":"flower","message":""}"#
+        );
+    }
+
+    #[test]
+    fn test_session_id() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            set_session_id(1);
+            assert_eq!(session_id(), None);
+            session_scope(async {
+                assert_eq!(session_id(), None);
+                set_session_id(42);
+                assert_eq!(session_id(), Some(42));
+            })
+            .await;
+            assert_eq!(session_id(), None);
+        });
+        set_runtime_id(3);
+        assert_eq!(runtime_id(), Some(3));
+        assert_eq!(context(), "[rt-3]");
+    }
+}
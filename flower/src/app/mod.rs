@@ -2,9 +2,12 @@ use std::sync::Arc;
 
 use tokio::sync::RwLock;
 
+pub mod conn_manager;
 pub mod dispatcher;
 pub mod dns_client;
+pub mod events;
 pub mod inbound;
+pub mod log_file;
 pub mod logger;
 pub mod nat_manager;
 pub mod outbound;
@@ -13,12 +16,6 @@ pub mod router;
 #[cfg(feature = "api")]
 pub mod api;
 
-#[cfg(any(
-    target_os = "ios",
-    target_os = "android",
-    target_os = "macos",
-    target_os = "linux"
-))]
 pub mod fake_dns;
 
 pub type SyncDnsClient = Arc<RwLock<dns_client::DnsClient>>;
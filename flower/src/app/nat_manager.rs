@@ -10,6 +10,7 @@ use tokio::sync::{
 };
 
 use crate::app::dispatcher::Dispatcher;
+use crate::config;
 use crate::option;
 use crate::session::{DatagramSource, Session, SocksAddr};
 
@@ -20,17 +21,40 @@ pub struct UdpPacket {
     pub dst_addr: Option<SocksAddr>,
 }
 
-type SessionMap =
-    Arc<TokioMutex<HashMap<DatagramSource, (Sender<UdpPacket>, oneshot::Sender<bool>, Instant)>>>;
+/// Returns the UDP session timeouts configured by the inbounds, by tag.
+pub fn session_timeouts(inbounds: &[config::Inbound]) -> HashMap<String, Duration> {
+    inbounds
+        .iter()
+        .filter(|x| x.udp_session_timeout != 0)
+        .map(|x| {
+            let timeout = Duration::from_secs(x.udp_session_timeout as u64);
+            (x.tag.clone(), timeout)
+        })
+        .collect()
+}
+
+// The uplink channel, the downlink abort signal, the last activity and the
+// idle timeout of the sessions.
+type SessionMap = Arc<
+    TokioMutex<
+        HashMap<DatagramSource, (Sender<UdpPacket>, oneshot::Sender<bool>, Instant, Duration)>,
+    >,
+>;
 
 pub struct NatManager {
     sessions: SessionMap,
     dispatcher: Arc<Dispatcher>,
+    // Idle timeouts of the sessions by inbound tag, overriding
+    // `option::UDP_SESSION_TIMEOUT`.
+    session_timeouts: TokioMutex<HashMap<String, Duration>>,
     timeout_check_task: TokioMutex<Option<BoxFuture<'static, ()>>>,
 }
 
 impl NatManager {
-    pub fn new(dispatcher: Arc<Dispatcher>) -> Self {
+    /// Sessions are closed along with their upstream sockets once idle for
+    /// the timeout of their inbound in `session_timeouts`, or
+    /// `option::UDP_SESSION_TIMEOUT` seconds.
+    pub fn new(dispatcher: Arc<Dispatcher>, session_timeouts: HashMap<String, Duration>) -> Self {
         let sessions: SessionMap = Arc::new(TokioMutex::new(HashMap::new()));
         let sessions2 = sessions.clone();
 
@@ -42,7 +66,7 @@ impl NatManager {
                 let now = Instant::now();
                 let mut to_be_remove = Vec::new();
                 for (key, val) in sessions.iter() {
-                    if now.duration_since(val.2).as_secs() >= *option::UDP_SESSION_TIMEOUT {
+                    if now.duration_since(val.2) >= val.3 {
                         to_be_remove.push(key.to_owned());
                     }
                 }
@@ -78,10 +102,17 @@ impl NatManager {
         NatManager {
             sessions,
             dispatcher,
+            session_timeouts: TokioMutex::new(session_timeouts),
             timeout_check_task: TokioMutex::new(Some(timeout_check_task)),
         }
     }
 
+    /// Replaces the timeouts by inbound tag, for the sessions created from
+    /// now on.
+    pub async fn set_session_timeouts(&self, session_timeouts: HashMap<String, Duration>) {
+        *self.session_timeouts.lock().await = session_timeouts;
+    }
+
     pub async fn contains_key(&self, key: &DatagramSource) -> bool {
         self.sessions.lock().await.contains_key(key)
     }
@@ -115,11 +146,18 @@ impl NatManager {
 
         let (target_ch_tx, mut target_ch_rx) = mpsc::channel(64);
         let (downlink_abort_tx, downlink_abort_rx) = oneshot::channel();
-
-        self.sessions
+        let timeout = self
+            .session_timeouts
             .lock()
             .await
-            .insert(raddr, (target_ch_tx, downlink_abort_tx, Instant::now()));
+            .get(&sess.inbound_tag)
+            .copied()
+            .unwrap_or_else(|| Duration::from_secs(*option::UDP_SESSION_TIMEOUT));
+
+        self.sessions.lock().await.insert(
+            raddr,
+            (target_ch_tx, downlink_abort_tx, Instant::now(), timeout),
+        );
 
         let dispatcher = self.dispatcher.clone();
         let sessions = self.sessions.clone();
@@ -180,9 +218,9 @@ impl NatManager {
                                         // If the destination port is 53, we assume it's a
                                         // DNS query and set a negative timeout so it will
                                         // be removed on next check.
-                                        sess.2.checked_sub(Duration::from_secs(
-                                            *option::UDP_SESSION_TIMEOUT,
-                                        ));
+                                        if let Some(t) = sess.2.checked_sub(sess.3) {
+                                            sess.2 = t;
+                                        }
                                     } else {
                                         sess.2 = Instant::now();
                                     }
@@ -221,6 +259,10 @@ impl NatManager {
                             continue;
                         }
                     };
+                    let addr = match dispatcher.restore_fake_destination(&addr).await {
+                        Some(a) => a,
+                        None => addr,
+                    };
                     match target_sock_send.send_to(&pkt.data, &addr).await {
                         Ok(0) => {
                             debug!("uplink send zero bytes");
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The latest health check results of an outbound group, as latencies in
+/// millis keyed by actor tag, `None` if the actor failed the check.
+#[derive(Default)]
+pub struct HealthStats {
+    tcp: Mutex<HashMap<String, Option<u64>>>,
+    udp: Mutex<HashMap<String, Option<u64>>>,
+}
+
+impl HealthStats {
+    pub fn update_tcp(&self, tag: &str, latency: Option<u64>) {
+        self.tcp.lock().unwrap().insert(tag.to_owned(), latency);
+    }
+
+    pub fn update_udp(&self, tag: &str, latency: Option<u64>) {
+        self.udp.lock().unwrap().insert(tag.to_owned(), latency);
+    }
+
+    pub fn tcp(&self) -> HashMap<String, Option<u64>> {
+        self.tcp.lock().unwrap().clone()
+    }
+
+    pub fn udp(&self) -> HashMap<String, Option<u64>> {
+        self.udp.lock().unwrap().clone()
+    }
+}
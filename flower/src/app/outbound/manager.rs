@@ -3,6 +3,7 @@ use std::{
     convert::From,
     sync::atomic::AtomicUsize,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{anyhow, Result};
@@ -13,6 +14,8 @@ use tokio::sync::RwLock;
 
 use crate::proxy::null;
 
+#[cfg(feature = "outbound-balance")]
+use crate::proxy::balance;
 #[cfg(feature = "outbound-chain")]
 use crate::proxy::chain;
 #[cfg(feature = "outbound-failover")]
@@ -34,6 +37,12 @@ use crate::proxy::amux;
 use crate::proxy::direct;
 #[cfg(feature = "outbound-drop")]
 use crate::proxy::drop;
+#[cfg(feature = "outbound-grpc")]
+use crate::proxy::grpc;
+#[cfg(feature = "outbound-http")]
+use crate::proxy::http;
+#[cfg(feature = "outbound-obfs")]
+use crate::proxy::obfs;
 #[cfg(feature = "outbound-quic")]
 use crate::proxy::quic;
 #[cfg(feature = "outbound-redirect")]
@@ -46,28 +55,118 @@ use crate::proxy::socks;
 use crate::proxy::tls;
 #[cfg(feature = "outbound-trojan")]
 use crate::proxy::trojan;
+#[cfg(feature = "outbound-vless")]
+use crate::proxy::vless;
 #[cfg(feature = "outbound-vmess")]
 use crate::proxy::vmess;
+#[cfg(feature = "outbound-wireguard")]
+use crate::proxy::wireguard;
 #[cfg(feature = "outbound-ws")]
 use crate::proxy::ws;
 
 use crate::{
     app::SyncDnsClient,
-    config::{self, Outbound},
+    config::{
+        self,
+        diff::{self, Diff},
+        Outbound,
+    },
     proxy::{self, outbound::HandlerBuilder, *},
 };
 
+use super::health::HealthStats;
+use super::quota::{self, Quota};
 use super::selector::OutboundSelector;
 
+const QUOTA_CACHE: &str = "quota.cache";
+
 pub struct OutboundManager {
     handlers: HashMap<String, AnyOutboundHandler>,
+    // Keeps the plugin libraries loaded.
+    #[allow(dead_code)]
     external_handlers: super::plugin::ExternalHandlers,
     selectors: Arc<super::Selectors>,
     default_handler: Option<String>,
-    abort_handles: Vec<AbortHandle>,
+    abort_handles: HashMap<String, Vec<AbortHandle>>,
+    quotas: HashMap<String, Arc<Quota>>,
+    health_stats: HashMap<String, Arc<HealthStats>>,
+    outbounds: protobuf::RepeatedField<Outbound>,
+}
+
+impl Drop for OutboundManager {
+    // Stops the tasks spawned inside the handlers, e.g. health checks.
+    fn drop(&mut self) {
+        for abort_handle in self.abort_handles.values().flatten() {
+            abort_handle.abort();
+        }
+    }
 }
 
 impl OutboundManager {
+    // Builds the quotas not yet in `quotas`, which may hold the ones kept
+    // from a previous load, the usage of those built is restored from the
+    // cache file.
+    fn load_quotas(
+        outbounds: &protobuf::RepeatedField<Outbound>,
+        mut quotas: HashMap<String, Arc<Quota>>,
+        abort_handles: &mut HashMap<String, Vec<AbortHandle>>,
+    ) -> Result<HashMap<String, Arc<Quota>>> {
+        let mut loaded = Vec::new();
+        for outbound in outbounds.iter() {
+            if quotas.contains_key(&outbound.tag) {
+                continue;
+            }
+            if let Some(quota) = outbound.quota.as_ref() {
+                if quota.bytes == 0 {
+                    continue;
+                }
+                let quota = Quota::new(outbound.tag.clone(), quota)
+                    .map_err(|e| anyhow!("invalid [{}] outbound quota: {}", &outbound.tag, e))?;
+                let quota = Arc::new(quota);
+                quotas.insert(outbound.tag.clone(), quota.clone());
+                loaded.push(quota);
+            }
+        }
+        // The groups dial their actors directly, bypassing the quotas.
+        for outbound in outbounds.iter() {
+            for actor in diff::outbound_actors(outbound) {
+                if quotas.contains_key(&actor) {
+                    return Err(anyhow!(
+                        "invalid [{}] outbound quota: not enforced for the [{}] outbound using it",
+                        &actor,
+                        &outbound.tag
+                    ));
+                }
+            }
+        }
+        if quotas.is_empty() {
+            return Ok(quotas);
+        }
+        let cache_file = match super::selector::get_cache_file_path(QUOTA_CACHE) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("traffic quota usage will not be persisted: {}", e);
+                return Ok(quotas);
+            }
+        };
+        if !loaded.is_empty() {
+            let cache = quota::load(&cache_file)?;
+            for quota in loaded.iter() {
+                quota.restore(&cache);
+            }
+        }
+        // Keyed by an empty tag so it's replaced along with the quotas on
+        // every reload.
+        abort_handles
+            .entry(String::new())
+            .or_default()
+            .push(quota::spawn_persist(
+                quotas.values().cloned().collect(),
+                cache_file,
+            ));
+        Ok(quotas)
+    }
+
     #[allow(clippy::type_complexity)]
     fn load_handlers(
         outbounds: &protobuf::RepeatedField<Outbound>,
@@ -75,13 +174,28 @@ impl OutboundManager {
         handlers: &mut HashMap<String, AnyOutboundHandler>,
         external_handlers: &mut super::plugin::ExternalHandlers,
         default_handler: &mut Option<String>,
-        abort_handles: &mut Vec<AbortHandle>,
+        abort_handles: &mut HashMap<String, Vec<AbortHandle>>,
+        health_stats: &mut HashMap<String, Arc<HealthStats>>,
     ) -> Result<()> {
         for outbound in outbounds.iter() {
             let tag = String::from(&outbound.tag);
             if handlers.contains_key(&tag) {
                 continue;
             }
+            let nodelay = outbound
+                .nodelay
+                .parse::<NoDelay>()
+                .map_err(|e| anyhow!("invalid [{}] outbound nodelay: {}", &tag, e))?;
+            let connect_timeout = Duration::from_secs(if outbound.connect_timeout == 0 {
+                *crate::option::OUTBOUND_DIAL_TIMEOUT
+            } else {
+                outbound.connect_timeout as u64
+            });
+            let handshake_timeout = Duration::from_secs(if outbound.handshake_timeout == 0 {
+                *crate::option::TLS_HANDSHAKE_TIMEOUT
+            } else {
+                outbound.handshake_timeout as u64
+            });
             if default_handler.is_none() {
                 default_handler.replace(String::from(&outbound.tag));
                 debug!("default handler [{}]", &outbound.tag);
@@ -89,24 +203,47 @@ impl OutboundManager {
             match outbound.protocol.as_str() {
                 #[cfg(feature = "outbound-direct")]
                 "direct" => {
+                    let settings =
+                        config::DirectOutboundSettings::parse_from_bytes(&outbound.settings)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let binds =
+                        direct::parse_binds(&settings.bind_address, &settings.bind_interface)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let tcp = Box::new(direct::TcpHandler {
+                        binds: binds.clone(),
+                        nodelay,
+                        connect_timeout,
+                        dns_client: dns_client.clone(),
+                    });
+                    let udp = Box::new(direct::UdpHandler {
+                        binds,
+                        dns_client: dns_client.clone(),
+                    });
                     handlers.insert(
                         tag.clone(),
                         HandlerBuilder::default()
                             .tag(tag.clone())
+                            .nodelay(nodelay)
+                            .connect_timeout(connect_timeout)
                             .color(colored::Color::Green)
-                            .tcp_handler(Box::new(direct::TcpHandler))
-                            .udp_handler(Box::new(direct::UdpHandler))
+                            .tcp_handler(tcp)
+                            .udp_handler(udp)
                             .build(),
                     );
                     trace!("added handler [{}]", &tag);
                 }
                 #[cfg(feature = "outbound-drop")]
-                "drop" => {
+                "drop" | "reject" => {
+                    let tcp = Box::new(drop::TcpHandler {
+                        reject: outbound.protocol == "reject",
+                    });
                     handlers.insert(
                         tag.clone(),
                         HandlerBuilder::default()
                             .tag(tag.clone())
-                            .tcp_handler(Box::new(drop::TcpHandler))
+                            .nodelay(nodelay)
+                            .connect_timeout(connect_timeout)
+                            .tcp_handler(tcp)
                             .udp_handler(Box::new(drop::UdpHandler))
                             .build(),
                     );
@@ -127,6 +264,8 @@ impl OutboundManager {
                     });
                     let handler = HandlerBuilder::default()
                         .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
                         .tcp_handler(tcp)
                         .udp_handler(udp)
                         .build();
@@ -141,39 +280,75 @@ impl OutboundManager {
                     let tcp = Box::new(socks::outbound::TcpHandler {
                         address: settings.address.clone(),
                         port: settings.port as u16,
+                        username: settings.username.clone(),
+                        password: settings.password.clone(),
                     });
                     let udp = Box::new(socks::outbound::UdpHandler {
                         address: settings.address.clone(),
                         port: settings.port as u16,
+                        username: settings.username.clone(),
+                        password: settings.password.clone(),
+                        udp_over_tcp: settings.udp_over_tcp,
                         dns_client: dns_client.clone(),
                     });
                     let handler = HandlerBuilder::default()
                         .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
                         .tcp_handler(tcp)
                         .udp_handler(udp)
                         .build();
                     handlers.insert(tag.clone(), handler);
                     trace!("added handler [{}]", &tag);
                 }
+                #[cfg(feature = "outbound-http")]
+                "http" => {
+                    let settings =
+                        config::HttpOutboundSettings::parse_from_bytes(&outbound.settings)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    crate::common::header::validate(&settings.headers)
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let tcp = Box::new(http::outbound::TcpHandler {
+                        address: settings.address,
+                        port: settings.port as u16,
+                        username: settings.username,
+                        password: settings.password,
+                        headers: settings.headers,
+                    });
+                    let handler = HandlerBuilder::default()
+                        .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
+                        .tcp_handler(tcp)
+                        .build();
+                    handlers.insert(tag.clone(), handler);
+                    trace!("added handler [{}]", &tag);
+                }
                 #[cfg(feature = "outbound-shadowsocks")]
                 "shadowsocks" => {
                     let settings =
                         config::ShadowsocksOutboundSettings::parse_from_bytes(&outbound.settings)
                             .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let cipher = settings
+                        .method
+                        .parse()
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
                     let tcp = Box::new(shadowsocks::outbound::TcpHandler {
                         address: settings.address.clone(),
                         port: settings.port as u16,
-                        cipher: settings.method.clone(),
+                        cipher,
                         password: settings.password.clone(),
                     });
                     let udp = Box::new(shadowsocks::outbound::UdpHandler {
                         address: settings.address,
                         port: settings.port as u16,
-                        cipher: settings.method,
+                        cipher,
                         password: settings.password,
                     });
                     let handler = HandlerBuilder::default()
                         .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
                         .tcp_handler(tcp)
                         .udp_handler(udp)
                         .build();
@@ -197,6 +372,8 @@ impl OutboundManager {
                     });
                     let handler = HandlerBuilder::default()
                         .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
                         .tcp_handler(tcp)
                         .udp_handler(udp)
                         .build();
@@ -208,20 +385,75 @@ impl OutboundManager {
                     let settings =
                         config::VMessOutboundSettings::parse_from_bytes(&outbound.settings)
                             .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let security = vmess::parse_security(&settings.security)
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
                     let tcp = Box::new(vmess::TcpHandler {
                         address: settings.address.clone(),
                         port: settings.port as u16,
                         uuid: settings.uuid.clone(),
-                        security: settings.security.clone(),
+                        alter_id: settings.alter_id as u16,
+                        security,
                         dns_client: dns_client.clone(),
                     });
                     let udp = Box::new(vmess::UdpHandler {
                         address: settings.address.clone(),
                         port: settings.port as u16,
                         uuid: settings.uuid.clone(),
-                        security: settings.security.clone(),
+                        alter_id: settings.alter_id as u16,
+                        security,
                         dns_client: dns_client.clone(),
                     });
+                    let handler = HandlerBuilder::default()
+                        .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
+                        .tcp_handler(tcp)
+                        .udp_handler(udp)
+                        .build();
+                    handlers.insert(tag.clone(), handler);
+                    trace!("added handler [{}]", &tag);
+                }
+                #[cfg(feature = "outbound-vless")]
+                "vless" => {
+                    let settings =
+                        config::VlessOutboundSettings::parse_from_bytes(&outbound.settings)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let tcp = Box::new(vless::outbound::TcpHandler {
+                        address: settings.address,
+                        port: settings.port as u16,
+                        uuid: settings.uuid,
+                        flow: settings.flow,
+                    });
+                    let handler = HandlerBuilder::default()
+                        .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
+                        .tcp_handler(tcp)
+                        .build();
+                    handlers.insert(tag.clone(), handler);
+                    trace!("added handler [{}]", &tag);
+                }
+                #[cfg(feature = "outbound-wireguard")]
+                "wireguard" => {
+                    let settings =
+                        config::WireGuardOutboundSettings::parse_from_bytes(&outbound.settings)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let tunnel = Arc::new(
+                        wireguard::outbound::Tunnel::new(
+                            &settings.private_key,
+                            &settings.peer_public_key,
+                            &settings.endpoint,
+                            &settings.address,
+                            settings.mtu as usize,
+                            &settings.dns,
+                            dns_client.clone(),
+                        )
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?,
+                    );
+                    let tcp = Box::new(wireguard::outbound::TcpHandler {
+                        tunnel: tunnel.clone(),
+                    });
+                    let udp = Box::new(wireguard::outbound::UdpHandler { tunnel });
                     let handler = HandlerBuilder::default()
                         .tag(tag.clone())
                         .tcp_handler(tcp)
@@ -244,10 +476,25 @@ impl OutboundManager {
                     } else {
                         Some(settings.certificate.clone())
                     };
+                    let verify_time = if settings.verify_time.is_empty() {
+                        None
+                    } else {
+                        Some(settings.verify_time.clone())
+                    };
                     let tcp = Box::new(tls::outbound::TcpHandler::new(
                         settings.server_name.clone(),
                         alpns.clone(),
                         certificate,
+                        verify_time,
+                        settings.session_cache_size as usize,
+                        settings.insecure,
+                        tls::Protocols::new(
+                            &settings.min_version,
+                            &settings.max_version,
+                            &settings.cipher_suites,
+                        )
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?,
+                        handshake_timeout,
                     )?);
                     let udp = Box::new(null::outbound::UdpHandler {
                         connect: None,
@@ -255,6 +502,8 @@ impl OutboundManager {
                     });
                     let handler = HandlerBuilder::default()
                         .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
                         .tcp_handler(tcp)
                         .udp_handler(udp)
                         .build();
@@ -266,8 +515,11 @@ impl OutboundManager {
                     let settings =
                         config::WebSocketOutboundSettings::parse_from_bytes(&outbound.settings)
                             .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    crate::common::header::validate(&settings.headers)
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
                     let tcp = Box::new(ws::outbound::TcpHandler {
                         path: settings.path.clone(),
+                        host: settings.host.clone(),
                         headers: settings.headers.clone(),
                     });
                     let udp = Box::new(null::outbound::UdpHandler {
@@ -276,6 +528,67 @@ impl OutboundManager {
                     });
                     let handler = HandlerBuilder::default()
                         .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
+                        .tcp_handler(tcp)
+                        .udp_handler(udp)
+                        .build();
+                    handlers.insert(tag.clone(), handler);
+                    trace!("added handler [{}]", &tag);
+                }
+                #[cfg(feature = "outbound-grpc")]
+                "grpc" => {
+                    let settings =
+                        config::GrpcOutboundSettings::parse_from_bytes(&outbound.settings)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    crate::common::header::validate(&settings.headers)
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    // Pooled ones depend on the actors.
+                    if !settings.address.is_empty() {
+                        continue;
+                    }
+                    let tcp = Box::new(grpc::outbound::TcpHandler {
+                        service_name: settings.service_name.clone(),
+                        host: settings.host.clone(),
+                        headers: settings.headers.clone(),
+                        pool: None,
+                    });
+                    let udp = Box::new(null::outbound::UdpHandler {
+                        connect: None,
+                        transport_type: proxy::DatagramTransportType::Stream,
+                    });
+                    let handler = HandlerBuilder::default()
+                        .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
+                        .tcp_handler(tcp)
+                        .udp_handler(udp)
+                        .build();
+                    handlers.insert(tag.clone(), handler);
+                    trace!("added handler [{}]", &tag);
+                }
+                #[cfg(feature = "outbound-obfs")]
+                "obfs" => {
+                    let settings =
+                        config::ObfsOutboundSettings::parse_from_bytes(&outbound.settings)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let mode = settings
+                        .mode
+                        .parse()
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                    let tcp = Box::new(obfs::outbound::TcpHandler {
+                        mode,
+                        host: settings.host.clone(),
+                        path: settings.path.clone(),
+                    });
+                    let udp = Box::new(null::outbound::UdpHandler {
+                        connect: None,
+                        transport_type: proxy::DatagramTransportType::Stream,
+                    });
+                    let handler = HandlerBuilder::default()
+                        .tag(tag.clone())
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout)
                         .tcp_handler(tcp)
                         .udp_handler(udp)
                         .build();
@@ -297,22 +610,32 @@ impl OutboundManager {
                     } else {
                         Some(settings.certificate.clone())
                     };
-                    let tcp = Box::new(quic::outbound::TcpHandler::new(
+                    let tcp = quic::outbound::TcpHandler::new(
                         settings.address.clone(),
                         settings.port as u16,
                         server_name,
                         certificate,
+                        settings.alpn.to_vec(),
+                        settings.idle_timeout,
+                        settings.streams_per_connection as usize,
+                        settings.datagram,
+                        settings.zero_rtt,
+                        handshake_timeout,
                         dns_client.clone(),
-                    ));
-                    let udp = Box::new(null::outbound::UdpHandler {
-                        connect: Some(OutboundConnect::NoConnect),
-                        transport_type: DatagramTransportType::Stream,
-                    });
-                    let handler = HandlerBuilder::default()
+                    );
+                    let builder = HandlerBuilder::default()
                         .tag(tag.clone())
-                        .tcp_handler(tcp)
-                        .udp_handler(udp)
-                        .build();
+                        .nodelay(nodelay)
+                        .connect_timeout(connect_timeout);
+                    let builder = if settings.datagram {
+                        builder.udp_handler(Box::new(tcp.udp_handler()))
+                    } else {
+                        builder.udp_handler(Box::new(null::outbound::UdpHandler {
+                            connect: Some(OutboundConnect::NoConnect),
+                            transport_type: DatagramTransportType::Stream,
+                        }))
+                    };
+                    let handler = builder.tcp_handler(Box::new(tcp)).build();
                     handlers.insert(tag.clone(), handler);
                     trace!("added handler [{}]", &tag);
                 }
@@ -468,6 +791,50 @@ impl OutboundManager {
                             settings.actors.join(",")
                         );
                     }
+                    #[cfg(feature = "outbound-balance")]
+                    "balance" => {
+                        let settings = config::BalanceOutboundSettings::parse_from_bytes(
+                            &outbound.settings,
+                        )
+                        .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                        let mut actors = Vec::new();
+                        for actor in settings.actors.iter() {
+                            if let Some(a) = handlers.get(actor) {
+                                actors.push(a.clone());
+                            } else {
+                                continue 'outbounds;
+                            }
+                        }
+                        if actors.is_empty() {
+                            continue;
+                        }
+                        let strategy = balance::Strategy::parse(&settings.strategy)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                        let balancer = Arc::new(balance::Balancer::new(
+                            actors,
+                            strategy,
+                            settings.fail_cooldown,
+                        ));
+                        let tcp = Box::new(balance::TcpHandler {
+                            balancer: balancer.clone(),
+                            dns_client: dns_client.clone(),
+                        });
+                        let udp = Box::new(balance::UdpHandler {
+                            balancer,
+                            dns_client: dns_client.clone(),
+                        });
+                        let handler = HandlerBuilder::default()
+                            .tag(tag.clone())
+                            .tcp_handler(tcp)
+                            .udp_handler(udp)
+                            .build();
+                        handlers.insert(tag.clone(), handler);
+                        trace!(
+                            "added handler [{}] with actors: {}",
+                            &tag,
+                            settings.actors.join(",")
+                        );
+                    }
                     #[cfg(feature = "outbound-failover")]
                     "failover" => {
                         let settings =
@@ -486,6 +853,9 @@ impl OutboundManager {
                         if actors.is_empty() {
                             continue;
                         }
+                        let probe = failover::Probe::parse(&settings.test_url)
+                            .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &tag, e))?;
+                        let stats = Arc::new(HealthStats::default());
                         let (tcp, mut tcp_abort_handles) = failover::TcpHandler::new(
                             actors.clone(),
                             settings.fail_timeout,
@@ -495,6 +865,9 @@ impl OutboundManager {
                             settings.fallback_cache,
                             settings.cache_size as usize,
                             settings.cache_timeout as u64,
+                            probe,
+                            settings.tolerance,
+                            stats.clone(),
                             dns_client.clone(),
                         );
                         let (udp, mut udp_abort_handles) = failover::UdpHandler::new(
@@ -503,6 +876,8 @@ impl OutboundManager {
                             settings.health_check,
                             settings.check_interval,
                             settings.failover,
+                            settings.tolerance,
+                            stats.clone(),
                             dns_client.clone(),
                         );
                         let handler = HandlerBuilder::default()
@@ -511,8 +886,10 @@ impl OutboundManager {
                             .udp_handler(Box::new(udp))
                             .build();
                         handlers.insert(tag.clone(), handler);
-                        abort_handles.append(&mut tcp_abort_handles);
-                        abort_handles.append(&mut udp_abort_handles);
+                        let tag_abort_handles = abort_handles.entry(tag.clone()).or_default();
+                        tag_abort_handles.append(&mut tcp_abort_handles);
+                        tag_abort_handles.append(&mut udp_abort_handles);
+                        health_stats.insert(tag.clone(), stats);
                         trace!(
                             "added handler [{}] with actors: {}",
                             &tag,
@@ -552,7 +929,52 @@ impl OutboundManager {
                             .udp_handler(udp)
                             .build();
                         handlers.insert(tag.clone(), handler);
-                        abort_handles.append(&mut tcp_abort_handles);
+                        abort_handles
+                            .entry(tag.clone())
+                            .or_default()
+                            .append(&mut tcp_abort_handles);
+                        trace!(
+                            "added handler [{}] with actors: {}",
+                            &tag,
+                            settings.actors.join(",")
+                        );
+                    }
+                    #[cfg(feature = "outbound-grpc")]
+                    "grpc" => {
+                        let settings =
+                            config::GrpcOutboundSettings::parse_from_bytes(&outbound.settings)
+                                .map_err(|e| {
+                                    anyhow!("invalid [{}] outbound settings: {}", &tag, e)
+                                })?;
+                        let mut actors = Vec::new();
+                        for actor in settings.actors.iter() {
+                            if let Some(a) = handlers.get(actor) {
+                                actors.push(a.clone());
+                            } else {
+                                continue 'outbounds;
+                            }
+                        }
+                        let tcp = Box::new(grpc::outbound::TcpHandler {
+                            service_name: settings.service_name.clone(),
+                            host: settings.host.clone(),
+                            headers: settings.headers.clone(),
+                            pool: Some(grpc::outbound::Pool::new(
+                                settings.address.clone(),
+                                settings.port as u16,
+                                actors,
+                                dns_client.clone(),
+                            )),
+                        });
+                        let udp = Box::new(null::outbound::UdpHandler {
+                            connect: Some(OutboundConnect::NoConnect),
+                            transport_type: DatagramTransportType::Stream,
+                        });
+                        let handler = HandlerBuilder::default()
+                            .tag(tag.clone())
+                            .tcp_handler(tcp)
+                            .udp_handler(udp)
+                            .build();
+                        handlers.insert(tag.clone(), handler);
                         trace!(
                             "added handler [{}] with actors: {}",
                             &tag,
@@ -738,98 +1160,212 @@ impl OutboundManager {
         Ok(())
     }
 
+    // Outbounds are loaded once their actors are, a chain with an actor
+    // which never loads would otherwise be left out silently.
+    #[cfg(feature = "outbound-chain")]
+    fn check_chains(
+        outbounds: &protobuf::RepeatedField<Outbound>,
+        handlers: &HashMap<String, AnyOutboundHandler>,
+    ) -> Result<()> {
+        for outbound in outbounds.iter().filter(|o| o.protocol == "chain") {
+            let settings = config::ChainOutboundSettings::parse_from_bytes(&outbound.settings)
+                .map_err(|e| anyhow!("invalid [{}] outbound settings: {}", &outbound.tag, e))?;
+            if let Some(actor) = settings.actors.iter().find(|a| !handlers.contains_key(*a)) {
+                return Err(anyhow!(
+                    "invalid [{}] outbound settings: actor [{}] not found",
+                    &outbound.tag,
+                    actor
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Builds the outbounds not yet in `handlers`, which may hold the ones kept
+    // from a previous load, the tasks spawned are aborted if this fails.
+    fn build(
+        outbounds: &protobuf::RepeatedField<Outbound>,
+        dns_client: SyncDnsClient,
+        mut handlers: HashMap<String, AnyOutboundHandler>,
+        mut selectors: super::Selectors,
+        mut health_stats: HashMap<String, Arc<HealthStats>>,
+        quotas: HashMap<String, Arc<Quota>>,
+        mut default_handler: Option<String>,
+    ) -> Result<Self> {
+        let mut external_handlers = super::plugin::ExternalHandlers::new();
+        let mut abort_handles: HashMap<String, Vec<AbortHandle>> = HashMap::new();
+        let res = (|| {
+            for _i in 0..4 {
+                Self::load_handlers(
+                    outbounds,
+                    dns_client.clone(),
+                    &mut handlers,
+                    &mut external_handlers,
+                    &mut default_handler,
+                    &mut abort_handles,
+                    &mut health_stats,
+                )?;
+                Self::load_selectors(
+                    outbounds,
+                    &mut handlers,
+                    &mut external_handlers,
+                    &mut selectors,
+                )?;
+            }
+            #[cfg(feature = "outbound-chain")]
+            Self::check_chains(outbounds, &handlers)?;
+            Self::load_quotas(outbounds, quotas, &mut abort_handles)
+        })();
+        let quotas = match res {
+            Ok(v) => v,
+            Err(e) => {
+                for abort_handle in abort_handles.values().flatten() {
+                    abort_handle.abort();
+                }
+                return Err(e);
+            }
+        };
+        Ok(OutboundManager {
+            handlers,
+            external_handlers,
+            selectors: Arc::new(selectors),
+            default_handler,
+            abort_handles,
+            quotas,
+            health_stats,
+            outbounds: outbounds.clone(),
+        })
+    }
+
+    // Whether the outbound is kept as is on a reload, plugins are always
+    // loaded again as their libraries are not carried over.
+    fn kept(diff: &Diff, outbounds: &protobuf::RepeatedField<Outbound>, tag: &str) -> bool {
+        !diff.contains(tag)
+            && outbounds
+                .iter()
+                .any(|x| x.tag == tag && x.protocol != "plugin")
+    }
+
+    // Whether the quota of the outbound is kept as is on a reload.
+    fn quota_kept(
+        last: &protobuf::RepeatedField<Outbound>,
+        outbounds: &protobuf::RepeatedField<Outbound>,
+        tag: &str,
+    ) -> bool {
+        let quota = |outbounds: &protobuf::RepeatedField<Outbound>| {
+            outbounds
+                .iter()
+                .find(|x| x.tag == tag)
+                .and_then(|x| x.quota.as_ref().cloned())
+        };
+        matches!((quota(last), quota(outbounds)), (Some(a), Some(b)) if a == b)
+    }
+
     // TODO make this non-async?
-    pub async fn reload(
-        &mut self,
+    /// Builds a manager with the outbounds changed since the last load, along
+    /// with the ones depending on them, and the unchanged ones shared from
+    /// this manager. Nothing running is touched until it's swapped in.
+    pub async fn stage(
+        &self,
         outbounds: &protobuf::RepeatedField<Outbound>,
         dns_client: SyncDnsClient,
-    ) -> Result<()> {
+    ) -> Result<Self> {
+        let diff = Diff::outbounds(&self.outbounds, outbounds);
+
         // Save outound select states.
         let mut selected_outbounds = HashMap::new();
         for (k, v) in self.selectors.iter() {
             selected_outbounds.insert(k.to_owned(), v.read().await.get_selected_tag());
         }
 
-        // Load new outbounds.
-        let mut handlers: HashMap<String, AnyOutboundHandler> = HashMap::new();
+        let handlers = self
+            .handlers
+            .iter()
+            .filter(|(tag, _)| Self::kept(&diff, outbounds, tag))
+            .map(|(tag, h)| (tag.clone(), h.clone()))
+            .collect();
+        let selectors = self
+            .selectors
+            .iter()
+            .filter(|(tag, _)| Self::kept(&diff, outbounds, tag))
+            .map(|(tag, s)| (tag.clone(), s.clone()))
+            .collect();
+        let health_stats = self
+            .health_stats
+            .iter()
+            .filter(|(tag, _)| Self::kept(&diff, outbounds, tag))
+            .map(|(tag, s)| (tag.clone(), s.clone()))
+            .collect();
 
-        let mut external_handlers = super::plugin::ExternalHandlers::new();
-        let mut default_handler: Option<String> = None;
-        let mut abort_handles: Vec<AbortHandle> = Vec::new();
-        let mut selectors: super::Selectors = HashMap::new();
-        for _i in 0..4 {
-            Self::load_handlers(
-                outbounds,
-                dns_client.clone(),
-                &mut handlers,
-                &mut external_handlers,
-                &mut default_handler,
-                &mut abort_handles,
-            )?;
-            Self::load_selectors(
-                outbounds,
-                &mut handlers,
-                &mut external_handlers,
-                &mut selectors,
-            )?;
+        // The sessions running keep counting against the quotas kept, only
+        // those changed are built again.
+        let quotas: HashMap<_, _> = self
+            .quotas
+            .iter()
+            .filter(|(tag, _)| Self::quota_kept(&self.outbounds, outbounds, tag))
+            .map(|(tag, q)| (tag.clone(), q.clone()))
+            .collect();
+
+        // Save the usage counted so far before the changed quotas are loaded
+        // again.
+        if quotas.len() < self.quotas.len() {
+            if let Ok(cache_file) = super::selector::get_cache_file_path(QUOTA_CACHE) {
+                if let Err(e) = quota::persist(self.quotas.values(), &cache_file) {
+                    warn!("persist quota usage failed: {}", e);
+                }
+            }
         }
 
+        let staged = Self::build(
+            outbounds,
+            dns_client,
+            handlers,
+            selectors,
+            health_stats,
+            quotas,
+            outbounds.first().map(|x| x.tag.clone()),
+        )?;
+
         // Restore outbound select states.
         for (k, v) in selected_outbounds.iter() {
-            for (k2, v2) in selectors.iter_mut() {
-                if k == k2 {
-                    if let Some(v) = v {
-                        let _ = v2.write().await.set_selected(v);
-                    }
-                }
+            if let (Some(selector), Some(v)) = (staged.selectors.get(k), v) {
+                let _ = selector.write().await.set_selected(v);
             }
         }
 
-        // Abort spawned tasks inside handlers.
-        for abort_handle in self.abort_handles.iter() {
-            abort_handle.abort();
-        }
+        Ok(staged)
+    }
 
-        self.handlers = handlers;
-        self.external_handlers = external_handlers;
-        self.selectors = Arc::new(selectors);
-        self.default_handler = default_handler;
-        self.abort_handles = abort_handles;
-        Ok(())
+    /// Swaps in a manager built by `stage`, the tasks spawned for the
+    /// outbounds rebuilt or removed are aborted. Returns the outbounds changed.
+    pub fn swap(&mut self, mut staged: Self) -> Diff {
+        let diff = Diff::outbounds(&self.outbounds, &staged.outbounds);
+        for (tag, handles) in self.abort_handles.drain() {
+            if Self::kept(&diff, &staged.outbounds, &tag) {
+                staged.abort_handles.insert(tag, handles);
+            } else {
+                for abort_handle in handles.iter() {
+                    abort_handle.abort();
+                }
+            }
+        }
+        *self = staged;
+        diff
     }
 
     pub fn new(
         outbounds: &protobuf::RepeatedField<Outbound>,
         dns_client: SyncDnsClient,
     ) -> Result<Self> {
-        let mut handlers: HashMap<String, AnyOutboundHandler> = HashMap::new();
-        let mut external_handlers = super::plugin::ExternalHandlers::new();
-        let mut default_handler: Option<String> = None;
-        let mut abort_handles: Vec<AbortHandle> = Vec::new();
-        let mut selectors: super::Selectors = HashMap::new();
-        for _i in 0..4 {
-            Self::load_handlers(
-                outbounds,
-                dns_client.clone(),
-                &mut handlers,
-                &mut external_handlers,
-                &mut default_handler,
-                &mut abort_handles,
-            )?;
-            Self::load_selectors(
-                outbounds,
-                &mut handlers,
-                &mut external_handlers,
-                &mut selectors,
-            )?;
-        }
-        Ok(OutboundManager {
-            handlers,
-            external_handlers,
-            selectors: Arc::new(selectors),
-            default_handler,
-            abort_handles,
-        })
+        Self::build(
+            outbounds,
+            dns_client,
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            None,
+        )
     }
 
     pub fn add(&mut self, tag: String, handler: AnyOutboundHandler) {
@@ -844,6 +1380,35 @@ impl OutboundManager {
         self.default_handler.as_ref().map(Clone::clone)
     }
 
+    pub fn quota(&self, tag: &str) -> Option<Arc<Quota>> {
+        self.quotas.get(tag).map(Clone::clone)
+    }
+
+    pub fn quotas(&self) -> hash_map::Iter<'_, String, Arc<Quota>> {
+        self.quotas.iter()
+    }
+
+    /// Health check results of the outbound groups, keyed by tag.
+    pub fn health_stats(&self) -> hash_map::Iter<'_, String, Arc<HealthStats>> {
+        self.health_stats.iter()
+    }
+
+    /// Returns the outbound to handle sessions routed to `tag`, sessions
+    /// are diverted away from outbounds which used up their traffic quotas,
+    /// `None` means the session should be rejected.
+    pub fn resolve_quota(&self, tag: &str) -> Option<String> {
+        match self.quotas.get(tag) {
+            Some(quota) if quota.exceeded() => {
+                let fallback = quota.divert(self.default_handler())?;
+                match self.quotas.get(&fallback) {
+                    Some(quota) if quota.exceeded() => None,
+                    _ => Some(fallback),
+                }
+            }
+            _ => Some(tag.to_owned()),
+        }
+    }
+
     pub fn handlers(&self) -> Handlers {
         Handlers {
             inner: self.handlers.values(),
@@ -866,3 +1431,80 @@ impl<'a> Iterator for Handlers<'a> {
         self.inner.next()
     }
 }
+
+#[cfg(all(test, feature = "config-json", feature = "outbound-direct"))]
+mod tests {
+    use super::*;
+
+    fn load_config(outbounds: &str) -> config::Config {
+        config::json::from_string(&format!(r#"{{"outbounds": [{}]}}"#, outbounds)).unwrap()
+    }
+
+    #[test]
+    fn test_stage_keeps_quotas() {
+        let metered = r#"{
+            "protocol": "direct",
+            "tag": "metered",
+            "quota": {"bytes": 1024, "period": "monthly"}
+        }"#;
+        let direct = r#"{"protocol": "direct", "tag": "direct"}"#;
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config = load_config(metered);
+            let dns_client = Arc::new(RwLock::new(
+                crate::app::dns_client::DnsClient::new(&config.dns).unwrap(),
+            ));
+            let manager = OutboundManager::new(&config.outbounds, dns_client.clone()).unwrap();
+            let quota = manager.quota("metered").unwrap();
+
+            // The sessions running keep counting against the same quota if
+            // only other outbounds changed.
+            let config = load_config(&format!("{}, {}", metered, direct));
+            let staged = manager
+                .stage(&config.outbounds, dns_client.clone())
+                .await
+                .unwrap();
+            assert!(Arc::ptr_eq(&quota, &staged.quota("metered").unwrap()));
+
+            let config = load_config(&metered.replace("1024", "2048"));
+            let staged = manager
+                .stage(&config.outbounds, dns_client.clone())
+                .await
+                .unwrap();
+            let new_quota = staged.quota("metered").unwrap();
+            assert!(!Arc::ptr_eq(&quota, &new_quota));
+            assert_eq!(new_quota.limit(), 2048);
+        });
+    }
+
+    #[test]
+    fn test_reject_quota_on_group_actor() {
+        let metered = r#"{
+            "protocol": "direct",
+            "tag": "metered",
+            "quota": {"bytes": 1024, "period": "monthly"}
+        }"#;
+        let group = r#"{
+            "protocol": "failover",
+            "tag": "group",
+            "settings": {"actors": ["metered"]}
+        }"#;
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config = load_config(&format!("{}, {}", metered, group));
+            let dns_client = Arc::new(RwLock::new(
+                crate::app::dns_client::DnsClient::new(&config.dns).unwrap(),
+            ));
+            let err = OutboundManager::new(&config.outbounds, dns_client)
+                .err()
+                .unwrap();
+            assert!(err.to_string().contains("not enforced"), "{}", err);
+        });
+    }
+}
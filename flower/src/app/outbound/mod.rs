@@ -3,8 +3,11 @@ use std::sync::Arc;
 
 use tokio::sync::RwLock;
 
+pub mod health;
 pub mod manager;
 pub mod plugin;
+pub mod quota;
+pub mod quota_cache;
 pub mod selector;
 pub mod selector_cache;
 
@@ -0,0 +1,455 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Datelike;
+use futures::future::{abortable, AbortHandle};
+use log::*;
+use protobuf::Message;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::config;
+use crate::proxy::{OutboundDatagram, OutboundDatagramRecvHalf, OutboundDatagramSendHalf};
+use crate::session::SocksAddr;
+
+use super::quota_cache::{QuotaCache, QuotaCache_Usage};
+
+// Interval at which changed usage is written to the cache file.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, PartialEq)]
+pub enum QuotaAction {
+    /// Rejects sessions routed to the outbound.
+    Reject,
+    /// Diverts sessions to the given outbound, or the default outbound if
+    /// empty.
+    Failover(String),
+}
+
+/// Traffic quota of an outbound, counting bytes in both directions.
+pub struct Quota {
+    tag: String,
+    limit: u64,
+    action: QuotaAction,
+    used: AtomicU64,
+    period: AtomicI64,
+    changed: AtomicBool,
+}
+
+impl Quota {
+    pub fn new(tag: String, quota: &config::Outbound_Quota) -> Result<Self> {
+        // A quota never starting over would stay exceeded for good.
+        match quota.period.as_str() {
+            "monthly" => (),
+            "" => return Err(anyhow!("missing quota period")),
+            _ => return Err(anyhow!("invalid quota period: {}", &quota.period)),
+        }
+        let action = match quota.action.as_str() {
+            "reject" | "" => QuotaAction::Reject,
+            "failover" => QuotaAction::Failover(quota.fallback.clone()),
+            _ => return Err(anyhow!("invalid quota action: {}", &quota.action)),
+        };
+        let q = Quota {
+            tag,
+            limit: quota.bytes,
+            action,
+            used: AtomicU64::new(0),
+            period: AtomicI64::new(0),
+            changed: AtomicBool::new(false),
+        };
+        q.period.store(q.current_period(), Ordering::Relaxed);
+        Ok(q)
+    }
+
+    fn current_period(&self) -> i64 {
+        let now = chrono::Utc::now();
+        now.year() as i64 * 12 + now.month0() as i64
+    }
+
+    // Starts over if a new period has begun.
+    fn roll(&self) {
+        let period = self.current_period();
+        if self.period.swap(period, Ordering::Relaxed) != period {
+            self.reset();
+            info!("traffic quota of [{}] starts a new period", &self.tag);
+        }
+    }
+
+    /// Restores the usage saved in the cache, if it's from the current period.
+    pub fn restore(&self, cache: &QuotaCache) {
+        if let Some(usage) = cache.items.get(&self.tag) {
+            if usage.period == self.period.load(Ordering::Relaxed) {
+                self.used.store(usage.bytes, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn usage(&self) -> QuotaCache_Usage {
+        let mut usage = QuotaCache_Usage::new();
+        usage.bytes = self.used.load(Ordering::Relaxed);
+        usage.period = self.period.load(Ordering::Relaxed);
+        usage
+    }
+
+    pub fn add(&self, n: u64) {
+        let prev = self.used.fetch_add(n, Ordering::Relaxed);
+        self.changed.store(true, Ordering::Relaxed);
+        if prev < self.limit && prev + n >= self.limit {
+            warn!("outbound [{}] used up its traffic quota", &self.tag);
+        }
+    }
+
+    pub fn used(&self) -> u64 {
+        self.roll();
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.used() >= self.limit
+    }
+
+    fn reset(&self) {
+        self.used.store(0, Ordering::Relaxed);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn action(&self) -> &QuotaAction {
+        &self.action
+    }
+
+    /// Returns the outbound taking over sessions while the quota is used up,
+    /// `None` means rejecting them.
+    pub fn divert(&self, default_handler: Option<String>) -> Option<String> {
+        match &self.action {
+            QuotaAction::Reject => None,
+            QuotaAction::Failover(tag) if !tag.is_empty() => Some(tag.to_owned()),
+            QuotaAction::Failover(_) => default_handler.filter(|tag| tag != &self.tag),
+        }
+    }
+}
+
+/// Reads the usage saved in the cache file, a missing file is an empty cache.
+pub fn load(cache_file: &Path) -> Result<QuotaCache> {
+    if !cache_file.exists() {
+        return Ok(QuotaCache::new());
+    }
+    let content =
+        std::fs::read(cache_file).map_err(|e| anyhow!("read {}: {}", cache_file.display(), e))?;
+    QuotaCache::parse_from_bytes(&content).map_err(|e| {
+        anyhow!(
+            "corrupt quota cache {}, remove it to start over: {}",
+            cache_file.display(),
+            e
+        )
+    })
+}
+
+/// Writes the usage of the quotas to the cache file. The cache is written to
+/// a temporary file first and renamed over the old one, so it's never left
+/// half written.
+pub fn persist<'a, I>(quotas: I, cache_file: &Path) -> Result<()>
+where
+    I: IntoIterator<Item = &'a Arc<Quota>>,
+{
+    let mut cache = QuotaCache::new();
+    for quota in quotas {
+        quota.changed.store(false, Ordering::Relaxed);
+        cache.items.insert(quota.tag.clone(), quota.usage());
+    }
+    let tmp_file = cache_file.with_extension("tmp");
+    std::fs::write(&tmp_file, cache.write_to_bytes()?)?;
+    std::fs::rename(&tmp_file, cache_file)?;
+    Ok(())
+}
+
+/// Spawns a task persisting the usage of the quotas whenever it changed.
+pub fn spawn_persist(quotas: Vec<Arc<Quota>>, cache_file: PathBuf) -> AbortHandle {
+    let (task, abort_handle) = abortable(async move {
+        let mut interval = tokio::time::interval(PERSIST_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if !quotas.iter().any(|q| q.changed.load(Ordering::Relaxed)) {
+                continue;
+            }
+            let quotas = quotas.clone();
+            let cache_file = cache_file.clone();
+            match tokio::task::spawn_blocking(move || persist(&quotas, &cache_file)).await {
+                Ok(Err(e)) => warn!("persist quota usage failed: {}", e),
+                Err(e) => warn!("persist quota usage failed: {}", e),
+                _ => (),
+            }
+        }
+    });
+    tokio::spawn(task);
+    abort_handle
+}
+
+/// Counts the traffic of an outbound stream against a quota.
+pub struct QuotaStream<T> {
+    inner: T,
+    quota: Arc<Quota>,
+}
+
+impl<T> QuotaStream<T> {
+    pub fn new(inner: T, quota: Arc<Quota>) -> Self {
+        QuotaStream { inner, quota }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for QuotaStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let res = AsyncRead::poll_read(Pin::new(&mut self.inner), cx, buf);
+        if let Poll::Ready(Ok(())) = res {
+            self.quota.add((buf.filled().len() - filled) as u64);
+        }
+        res
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for QuotaStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let res = AsyncWrite::poll_write(Pin::new(&mut self.inner), cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            self.quota.add(n as u64);
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.inner), cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_shutdown(Pin::new(&mut self.inner), cx)
+    }
+}
+
+/// Counts the traffic of an outbound datagram against a quota.
+pub struct QuotaDatagram {
+    inner: Box<dyn OutboundDatagram>,
+    quota: Arc<Quota>,
+}
+
+impl QuotaDatagram {
+    pub fn new(inner: Box<dyn OutboundDatagram>, quota: Arc<Quota>) -> Self {
+        QuotaDatagram { inner, quota }
+    }
+}
+
+impl OutboundDatagram for QuotaDatagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ) {
+        let (r, s) = self.inner.split();
+        (
+            Box::new(QuotaDatagramRecvHalf(r, self.quota.clone())),
+            Box::new(QuotaDatagramSendHalf(s, self.quota)),
+        )
+    }
+}
+
+pub struct QuotaDatagramRecvHalf(Box<dyn OutboundDatagramRecvHalf>, Arc<Quota>);
+
+#[async_trait]
+impl OutboundDatagramRecvHalf for QuotaDatagramRecvHalf {
+    async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocksAddr)> {
+        let (n, addr) = self.0.recv_from(buf).await?;
+        self.1.add(n as u64);
+        Ok((n, addr))
+    }
+}
+
+pub struct QuotaDatagramSendHalf(Box<dyn OutboundDatagramSendHalf>, Arc<Quota>);
+
+#[async_trait]
+impl OutboundDatagramSendHalf for QuotaDatagramSendHalf {
+    async fn send_to(&mut self, buf: &[u8], dst_addr: &SocksAddr) -> io::Result<usize> {
+        let n = self.0.send_to(buf, dst_addr).await?;
+        self.1.add(n as u64);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    fn new_quota(tag: &str, bytes: u64) -> Arc<Quota> {
+        let mut cfg = config::Outbound_Quota::new();
+        cfg.bytes = bytes;
+        cfg.period = "monthly".to_string();
+        cfg.action = "failover".to_string();
+        Arc::new(Quota::new(tag.to_string(), &cfg).unwrap())
+    }
+
+    #[test]
+    fn test_quota_exceeded() {
+        let quota = new_quota("metered", 16);
+        assert_eq!(quota.action(), &QuotaAction::Failover("".to_string()));
+        assert!(!quota.exceeded());
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (client, mut server) = tokio::io::duplex(1024);
+            let mut stream = QuotaStream::new(client, quota.clone());
+            stream.write_all(b"0123456789").await.unwrap();
+            let mut buf = [0u8; 10];
+            server.read_exact(&mut buf).await.unwrap();
+            server.write_all(&buf[..6]).await.unwrap();
+            stream.read_exact(&mut buf[..6]).await.unwrap();
+        });
+        assert_eq!(quota.used(), 16);
+        assert!(quota.exceeded());
+
+        // Subsequent sessions are diverted to the default outbound.
+        assert_eq!(
+            quota.divert(Some("backup".to_string())),
+            Some("backup".to_string())
+        );
+        assert_eq!(quota.divert(Some("metered".to_string())), None);
+
+        let mut cfg = config::Outbound_Quota::new();
+        cfg.bytes = 16;
+        cfg.period = "monthly".to_string();
+        cfg.action = "reject".to_string();
+        let quota = Quota::new("metered".to_string(), &cfg).unwrap();
+        assert_eq!(quota.divert(Some("backup".to_string())), None);
+
+        // A quota has to start over at some point.
+        cfg.period = "".to_string();
+        assert!(Quota::new("metered".to_string(), &cfg).is_err());
+    }
+
+    #[test]
+    fn test_quota_period_rollover() {
+        let quota = new_quota("metered", 16);
+        quota.add(20);
+        assert!(quota.exceeded());
+
+        // The usage is reset once the next month begins.
+        quota
+            .period
+            .store(quota.current_period() - 1, Ordering::Relaxed);
+        quota.changed.store(false, Ordering::Relaxed);
+        assert!(!quota.exceeded());
+        assert_eq!(quota.used(), 0);
+        assert!(quota.changed.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_quota_persist() {
+        let dir = std::env::temp_dir().join(format!("flower-quota-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache_file = dir.join("quota.cache");
+        let _ = std::fs::remove_file(&cache_file);
+
+        // A missing cache file starts from zero.
+        assert!(load(&cache_file).unwrap().items.is_empty());
+
+        let metered = new_quota("metered", 16);
+        let other = new_quota("other", 1024);
+        metered.add(20);
+        other.add(100);
+        assert!(metered.changed.load(Ordering::Relaxed));
+        persist(&[metered.clone(), other.clone()], &cache_file).unwrap();
+        assert!(!metered.changed.load(Ordering::Relaxed));
+        assert!(!cache_file.with_extension("tmp").exists());
+
+        // The usage survives restarts within the period.
+        let cache = load(&cache_file).unwrap();
+        let metered = new_quota("metered", 16);
+        let other = new_quota("other", 1024);
+        metered.restore(&cache);
+        other.restore(&cache);
+        assert_eq!(metered.used(), 20);
+        assert!(metered.exceeded());
+        assert_eq!(other.used(), 100);
+
+        // Usage of an earlier period is dropped.
+        let mut cache = QuotaCache::new();
+        let mut usage = QuotaCache_Usage::new();
+        usage.bytes = 20;
+        usage.period = metered.current_period() - 1;
+        cache.items.insert("metered".to_string(), usage);
+        let metered = new_quota("metered", 16);
+        metered.restore(&cache);
+        assert_eq!(metered.used(), 0);
+
+        // A corrupt cache is an error rather than a silent reset.
+        std::fs::write(&cache_file, b"\xff\xff\xff").unwrap();
+        assert!(load(&cache_file).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_quota_datagram() {
+        struct Echo;
+
+        impl OutboundDatagram for Echo {
+            fn split(
+                self: Box<Self>,
+            ) -> (
+                Box<dyn OutboundDatagramRecvHalf>,
+                Box<dyn OutboundDatagramSendHalf>,
+            ) {
+                (Box::new(Echo), Box::new(Echo))
+            }
+        }
+
+        #[async_trait]
+        impl OutboundDatagramRecvHalf for Echo {
+            async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocksAddr)> {
+                buf[..4].copy_from_slice(b"pong");
+                Ok((4, SocksAddr::any()))
+            }
+        }
+
+        #[async_trait]
+        impl OutboundDatagramSendHalf for Echo {
+            async fn send_to(&mut self, buf: &[u8], _: &SocksAddr) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+        }
+
+        let quota = new_quota("metered", 1024);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut r, mut s) =
+                Box::new(QuotaDatagram::new(Box::new(Echo), quota.clone())).split();
+            s.send_to(b"ping-ping", &SocksAddr::any()).await.unwrap();
+            let mut buf = [0u8; 16];
+            r.recv_from(&mut buf).await.unwrap();
+        });
+        assert_eq!(quota.used(), 13);
+    }
+}
@@ -6,7 +6,7 @@ use protobuf::Message;
 use crate::proxy::AnyOutboundHandler;
 use anyhow::{anyhow, Result};
 
-fn get_cache_file_path() -> Result<PathBuf> {
+pub(super) fn get_cache_file_path(name: &str) -> Result<PathBuf> {
     let cache_loc = if !(&*crate::option::CACHE_LOCATION).is_empty() {
         Path::new(&*crate::option::CACHE_LOCATION).to_owned()
     } else {
@@ -20,18 +20,18 @@ fn get_cache_file_path() -> Result<PathBuf> {
     if !cache_loc.exists() {
         std::fs::create_dir_all(&cache_loc)?;
     }
-    Ok(cache_loc.join("selector.cache"))
+    Ok(cache_loc.join(name))
 }
 
 pub fn get_selected_from_cache(id: &str) -> Result<Option<String>> {
-    let cache_file = get_cache_file_path()?;
+    let cache_file = get_cache_file_path("selector.cache")?;
     let content = std::fs::read(&cache_file)?;
     let cache = super::selector_cache::SelectorCache::parse_from_bytes(&content)?;
     Ok(cache.items.get(id).map(Clone::clone))
 }
 
 pub fn persist_selected_to_cache(id: String, selected: String) -> Result<()> {
-    let cache_file = get_cache_file_path()?;
+    let cache_file = get_cache_file_path("selector.cache")?;
     let mut cache = if cache_file.exists() {
         let content = std::fs::read(&cache_file)?;
         super::selector_cache::SelectorCache::parse_from_bytes(&content)?
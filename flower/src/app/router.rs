@@ -1,13 +1,16 @@
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 use anyhow::anyhow;
 use anyhow::Result;
+use arc_swap::ArcSwap;
 use cidr::{Cidr, IpCidr};
 use futures::TryFutureExt;
 use log::*;
 use maxminddb::geoip2::Country;
 use memmap::Mmap;
+use regex::Regex;
 
 use crate::app::SyncDnsClient;
 use crate::config::{self, Router_Rule};
@@ -20,11 +23,18 @@ pub trait Condition: Send + Sync + Unpin {
 struct Rule {
     target: String,
     condition: Box<dyn Condition>,
+    // Whether domain destinations are resolved for the rule, that's always the
+    // case for geoip rules, and optional for ip-cidr ones.
+    resolve: bool,
 }
 
 impl Rule {
-    fn new(target: String, condition: Box<dyn Condition>) -> Self {
-        Rule { target, condition }
+    fn new(target: String, condition: Box<dyn Condition>, resolve: bool) -> Self {
+        Rule {
+            target,
+            condition,
+            resolve,
+        }
     }
 }
 
@@ -34,13 +44,29 @@ impl Condition for Rule {
     }
 }
 
+// Readers are shared by all matchers referring to the same file, so that a
+// database can be replaced in place without rebuilding the rules.
+type MmdbReader = Arc<ArcSwap<maxminddb::Reader<Mmap>>>;
+
+fn open_mmdb(file: &str) -> Result<maxminddb::Reader<Mmap>> {
+    let reader = maxminddb::Reader::open_mmap(file)
+        .map_err(|e| anyhow!("open mmdb file {} failed: {:?}", file, e))?;
+    // A lookup walks both the search tree and the data section, make sure
+    // the database is usable before it's put into service.
+    let probe: std::net::IpAddr = [8, 8, 8, 8].into();
+    match reader.lookup::<Country>(probe) {
+        Ok(_) | Err(maxminddb::MaxMindDBError::AddressNotFoundError(_)) => Ok(reader),
+        Err(e) => Err(anyhow!("invalid mmdb file {}: {:?}", file, e)),
+    }
+}
+
 struct MmdbMatcher {
-    reader: Arc<maxminddb::Reader<Mmap>>,
+    reader: MmdbReader,
     country_code: String,
 }
 
 impl MmdbMatcher {
-    fn new(reader: Arc<maxminddb::Reader<Mmap>>, country_code: String) -> Self {
+    fn new(reader: MmdbReader, country_code: String) -> Self {
         MmdbMatcher {
             reader,
             country_code,
@@ -48,56 +74,166 @@ impl MmdbMatcher {
     }
 }
 
+impl MmdbMatcher {
+    fn matches(&self, ip: IpAddr) -> bool {
+        if let Ok(country) = self.reader.load().lookup::<Country>(ip) {
+            if let Some(country) = country.country {
+                if let Some(iso_code) = country.iso_code {
+                    if iso_code.to_lowercase() == self.country_code.to_lowercase() {
+                        debug!("[{}] matches geoip code [{}]", ip, &self.country_code);
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
 impl Condition for MmdbMatcher {
     fn apply(&self, sess: &Session) -> bool {
         if !sess.destination.is_domain() {
             if let Some(ip) = sess.destination.ip() {
-                if let Ok(country) = self.reader.lookup::<Country>(ip) {
-                    if let Some(country) = country.country {
-                        if let Some(iso_code) = country.iso_code {
-                            if iso_code.to_lowercase() == self.country_code.to_lowercase() {
-                                debug!("[{}] matches geoip code [{}]", ip, &self.country_code);
-                                return true;
-                            }
-                        }
-                    }
-                }
+                return self.matches(ip);
             }
         }
         false
     }
 }
 
+// Whether an address can't be located, sessions from such addresses never
+// match a source geoip condition.
+fn is_local_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // shared address space for carrier-grade NAT, 100.64.0.0/10
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // unique local, fc00::/7
+                || (ip.segments()[0] & 0xfe00) == 0xfc00
+                // link-local unicast, fe80::/10
+                || (ip.segments()[0] & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+// Dual-stack listeners report IPv4 peers as mapped IPv6 addresses.
+fn source_ip(sess: &Session) -> IpAddr {
+    match sess.source.ip() {
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            Some(v4) if ip.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => IpAddr::V4(v4),
+            _ => IpAddr::V6(ip),
+        },
+        ip => ip,
+    }
+}
+
+struct SourceMmdbMatcher {
+    matcher: MmdbMatcher,
+}
+
+impl SourceMmdbMatcher {
+    fn new(reader: MmdbReader, country_code: String) -> Self {
+        SourceMmdbMatcher {
+            matcher: MmdbMatcher::new(reader, country_code),
+        }
+    }
+}
+
+impl Condition for SourceMmdbMatcher {
+    fn apply(&self, sess: &Session) -> bool {
+        let ip = source_ip(sess);
+        if is_local_ip(&ip) {
+            return false;
+        }
+        self.matcher.matches(ip)
+    }
+}
+
+// Address ranges sorted by their start, overlapping ranges are merged so that
+// a lookup is a binary search.
+struct IpRanges<T> {
+    ranges: Vec<(T, T)>,
+}
+
+impl<T: Ord + Copy> IpRanges<T> {
+    fn new(mut ranges: Vec<(T, T)>) -> Self {
+        ranges.sort_unstable();
+        let mut merged: Vec<(T, T)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        IpRanges { ranges: merged }
+    }
+
+    fn contains(&self, v: T) -> bool {
+        // the last range starting at or before v
+        let i = self.ranges.partition_point(|(start, _)| *start <= v);
+        i > 0 && v <= self.ranges[i - 1].1
+    }
+}
+
 struct IpCidrMatcher {
-    values: Vec<IpCidr>,
+    v4: IpRanges<u32>,
+    v6: IpRanges<u128>,
 }
 
 impl IpCidrMatcher {
-    fn new(ips: &mut protobuf::RepeatedField<String>) -> Self {
-        let mut cidrs = Vec::new();
-        for ip in ips.iter_mut() {
-            let ip = std::mem::take(ip);
-            match ip.parse::<IpCidr>() {
-                Ok(cidr) => cidrs.push(cidr),
-                Err(err) => {
-                    debug!("parsing cidr {} failed: {}", ip, err);
-                }
+    fn new(ips: &protobuf::RepeatedField<String>) -> Result<Self> {
+        let mut v4: Vec<(u32, u32)> = Vec::new();
+        let mut v6: Vec<(u128, u128)> = Vec::new();
+        for ip in ips.iter() {
+            // a plain address is a single host
+            match ip.parse::<IpAddr>() {
+                Ok(IpAddr::V4(addr)) => v4.push((addr.into(), addr.into())),
+                Ok(IpAddr::V6(addr)) => v6.push((addr.into(), addr.into())),
+                Err(_) => match ip
+                    .parse::<IpCidr>()
+                    .map_err(|e| anyhow!("invalid ip-cidr {}: {}", ip, e))?
+                {
+                    IpCidr::V4(cidr) => {
+                        v4.push((cidr.first_address().into(), cidr.last_address().into()))
+                    }
+                    IpCidr::V6(cidr) => {
+                        v6.push((cidr.first_address().into(), cidr.last_address().into()))
+                    }
+                },
             }
-            drop(ip);
         }
-        IpCidrMatcher { values: cidrs }
+        Ok(IpCidrMatcher {
+            v4: IpRanges::new(v4),
+            v6: IpRanges::new(v6),
+        })
+    }
+}
+
+impl IpCidrMatcher {
+    fn matches(&self, ip: IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => self.v4.contains(ip.into()),
+            IpAddr::V6(ip) => self.v6.contains(ip.into()),
+        }
     }
 }
 
 impl Condition for IpCidrMatcher {
     fn apply(&self, sess: &Session) -> bool {
         if !sess.destination.is_domain() {
-            for cidr in &self.values {
-                if let Some(ip) = sess.destination.ip() {
-                    if cidr.contains(&ip) {
-                        debug!("[{}] matches ip-cidr [{}]", ip, &cidr);
-                        return true;
-                    }
+            if let Some(ip) = sess.destination.ip() {
+                if self.matches(ip) {
+                    debug!("[{}] matches ip-cidr", ip);
+                    return true;
                 }
             }
         }
@@ -105,6 +241,29 @@ impl Condition for IpCidrMatcher {
     }
 }
 
+struct SourceIpCidrMatcher {
+    matcher: IpCidrMatcher,
+}
+
+impl SourceIpCidrMatcher {
+    fn new(ips: &protobuf::RepeatedField<String>) -> Result<Self> {
+        Ok(SourceIpCidrMatcher {
+            matcher: IpCidrMatcher::new(ips)?,
+        })
+    }
+}
+
+impl Condition for SourceIpCidrMatcher {
+    fn apply(&self, sess: &Session) -> bool {
+        let ip = source_ip(sess);
+        if self.matcher.matches(ip) {
+            debug!("[{}] matches source ip-cidr", ip);
+            return true;
+        }
+        false
+    }
+}
+
 struct InboundTagMatcher {
     values: Vec<String>,
 }
@@ -136,16 +295,16 @@ struct NetworkMatcher {
 }
 
 impl NetworkMatcher {
-    fn new(networks: &mut protobuf::RepeatedField<String>) -> Self {
+    fn new(networks: &mut protobuf::RepeatedField<String>) -> Result<Self> {
         let mut values = Vec::new();
         for net in networks.iter_mut() {
             match std::mem::take(net).to_uppercase().as_str() {
                 "TCP" => values.push(Network::Tcp),
                 "UDP" => values.push(Network::Udp),
-                _ => (),
+                net => return Err(anyhow!("invalid network [{}]", net.to_lowercase())),
             }
         }
-        Self { values }
+        Ok(Self { values })
     }
 }
 
@@ -161,22 +320,47 @@ impl Condition for NetworkMatcher {
     }
 }
 
+struct ProtocolMatcher {
+    values: Vec<String>,
+}
+
+impl ProtocolMatcher {
+    fn new(protocols: &mut protobuf::RepeatedField<String>) -> Self {
+        let mut values = Vec::new();
+        for p in protocols.iter_mut() {
+            values.push(std::mem::take(p).to_lowercase());
+        }
+        Self { values }
+    }
+}
+
+impl Condition for ProtocolMatcher {
+    fn apply(&self, sess: &Session) -> bool {
+        if let Some(protocol) = sess.protocol {
+            for v in &self.values {
+                if v == protocol {
+                    debug!("[{}] matches protocol [{}]", protocol, v);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
 struct PortMatcher {
     condition: Box<dyn Condition>,
 }
 
 impl PortMatcher {
-    fn new(port_ranges: &protobuf::RepeatedField<String>) -> Self {
+    fn new(port_ranges: &protobuf::RepeatedField<String>) -> Result<Self> {
         let mut cond_or = ConditionOr::new();
         for pr in port_ranges.iter() {
-            match PortRangeMatcher::new(pr) {
-                Ok(m) => cond_or.add(Box::new(m)),
-                Err(e) => warn!("failed to add port range matcher: {}", e),
-            }
+            cond_or.add(Box::new(PortRangeMatcher::new(pr)?));
         }
-        PortMatcher {
+        Ok(PortMatcher {
             condition: Box::new(cond_or),
-        }
+        })
     }
 }
 
@@ -192,23 +376,16 @@ struct PortRangeMatcher {
 }
 
 impl PortRangeMatcher {
+    // Either a single port or an inclusive range, e.g. `443` or `1000-2000`.
     fn new(port_range: &str) -> Result<Self> {
-        let parts: Vec<&str> = port_range.split('-').collect();
-        if parts.len() != 2 {
-            return Err(anyhow!("invalid port range"));
-        }
-        let start = if let Ok(v) = parts[0].parse::<u16>() {
-            v
-        } else {
-            return Err(anyhow!("invalid port range"));
-        };
-        let end = if let Ok(v) = parts[1].parse::<u16>() {
-            v
-        } else {
-            return Err(anyhow!("invalid port range"));
-        };
+        let invalid = || anyhow!("invalid port range [{}]", port_range);
+        let (start, end) = port_range
+            .split_once('-')
+            .unwrap_or((port_range, port_range));
+        let start = start.parse::<u16>().map_err(|_| invalid())?;
+        let end = end.parse::<u16>().map_err(|_| invalid())?;
         if start > end {
-            return Err(anyhow!("invalid port range"));
+            return Err(invalid());
         }
         Ok(PortRangeMatcher { start, end })
     }
@@ -229,6 +406,12 @@ impl Condition for PortRangeMatcher {
     }
 }
 
+// The domain the domain rules match, the sniffed one if the destination is an
+// IP, which the IP rules still match.
+fn route_domain(sess: &Session) -> Option<&String> {
+    sess.destination.domain().or(sess.sniffed_host.as_ref())
+}
+
 struct DomainKeywordMatcher {
     value: String,
 }
@@ -241,150 +424,314 @@ impl DomainKeywordMatcher {
 
 impl Condition for DomainKeywordMatcher {
     fn apply(&self, sess: &Session) -> bool {
-        if sess.destination.is_domain() {
-            if let Some(domain) = sess.destination.domain() {
-                if domain.contains(&self.value) {
-                    debug!("[{}] matches domain keyword [{}]", domain, &self.value);
-                    return true;
-                }
+        if let Some(domain) = route_domain(sess) {
+            if domain.contains(&self.value) {
+                debug!("[{}] matches domain keyword [{}]", domain, &self.value);
+                return true;
             }
         }
         false
     }
 }
 
-struct DomainSuffixMatcher {
-    value: String,
+// Domain suffixes and full domains in a trie of labels, from the top level
+// domain down, so that a lookup takes as many steps as there are labels in
+// the domain, however many entries there are. Large lists, e.g. those loaded
+// from geosite files, would otherwise be scanned one by one.
+#[derive(Default)]
+struct DomainTrie {
+    children: HashMap<String, DomainTrie>,
+    suffix: bool,
+    full: bool,
 }
 
-impl DomainSuffixMatcher {
-    fn new(value: String) -> Self {
-        DomainSuffixMatcher { value }
+impl DomainTrie {
+    fn insert(&mut self, domain: &str, suffix: bool) {
+        let mut node = self;
+        for label in domain.rsplit('.') {
+            node = node.children.entry(label.to_string()).or_default();
+        }
+        if suffix {
+            node.suffix = true;
+        } else {
+            node.full = true;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    // examples, with google.com inserted as a suffix:
+    //   video.google.com -> true
+    //   video.gle.com -> false
+    //   com -> false
+    fn matches(&self, domain: &str) -> bool {
+        let mut node = self;
+        let mut labels = domain.rsplit('.').peekable();
+        while let Some(label) = labels.next() {
+            node = match node.children.get(label) {
+                Some(child) => child,
+                None => return false,
+            };
+            if node.suffix || (node.full && labels.peek().is_none()) {
+                return true;
+            }
+        }
+        false
     }
 }
 
-// test if domain1 is a subdomain of domain2
-// examples:
-//   video.google.com vs google.com -> true
-//   video.google.com vs gle.com -> false
-//   google.com vs video.google.com -> false
-fn is_sub_domain(d1: &str, d2: &str) -> bool {
-    let d1_parts: Vec<&str> = d1.split('.').rev().collect();
-    let d2_parts: Vec<&str> = d2.split('.').rev().collect();
-    if d1_parts.len() < d2_parts.len() {
-        return false;
+impl Condition for DomainTrie {
+    fn apply(&self, sess: &Session) -> bool {
+        if let Some(domain) = route_domain(sess) {
+            if self.matches(domain) {
+                debug!("[{}] matches domain suffix or full domain", domain);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// A domain pattern with `*` wildcards, each `*` matches any sequence of
+// characters, dots included. The pattern is split at the wildcards once so
+// that matching is a single left-to-right scan.
+struct Wildcard {
+    parts: Vec<String>,
+}
+
+impl Wildcard {
+    fn new(pattern: &str) -> Self {
+        Wildcard {
+            parts: pattern.split('*').map(str::to_string).collect(),
+        }
     }
-    let d2_enum = d2_parts.iter().enumerate();
-    for (i, v) in d2_enum {
-        if &d1_parts[i] != v {
+
+    fn matches(&self, s: &str) -> bool {
+        let n = self.parts.len();
+        if n == 1 {
+            return s == self.parts[0];
+        }
+        let (first, last) = (self.parts[0].as_str(), self.parts[n - 1].as_str());
+        if s.len() < first.len() + last.len() || !s.starts_with(first) || !s.ends_with(last) {
             return false;
         }
+        let mut rest = &s[first.len()..s.len() - last.len()];
+        for part in &self.parts[1..n - 1] {
+            match rest.find(part.as_str()) {
+                Some(i) => rest = &rest[i + part.len()..],
+                None => return false,
+            }
+        }
+        true
     }
-    true
 }
 
-impl Condition for DomainSuffixMatcher {
+struct DomainWildcardMatcher {
+    value: String,
+    wildcard: Wildcard,
+    suffix: bool,
+}
+
+impl DomainWildcardMatcher {
+    fn new(value: String, field_type: config::Router_Rule_Domain_Type) -> Self {
+        let (wildcard, suffix) = match field_type {
+            config::Router_Rule_Domain_Type::PLAIN => {
+                (Wildcard::new(&format!("*{}*", &value)), false)
+            }
+            config::Router_Rule_Domain_Type::DOMAIN => (Wildcard::new(&value), true),
+            // regex domains never contain wildcards
+            _ => (Wildcard::new(&value), false),
+        };
+        DomainWildcardMatcher {
+            value,
+            wildcard,
+            suffix,
+        }
+    }
+}
+
+impl Condition for DomainWildcardMatcher {
     fn apply(&self, sess: &Session) -> bool {
-        if sess.destination.is_domain() {
-            if let Some(domain) = sess.destination.domain() {
-                if is_sub_domain(domain, &self.value) {
-                    debug!("[{}] matches domain suffix [{}]", domain, &self.value);
+        if let Some(domain) = route_domain(sess) {
+            let mut d = domain.as_str();
+            loop {
+                if self.wildcard.matches(d) {
+                    debug!("[{}] matches domain wildcard [{}]", domain, &self.value);
                     return true;
                 }
+                match d.find('.') {
+                    Some(i) if self.suffix => d = &d[i + 1..],
+                    _ => return false,
+                }
             }
         }
         false
     }
 }
 
-struct DomainFullMatcher {
-    value: String,
+// The pattern is compiled when the rules are loaded, an invalid one fails the
+// whole router.
+struct DomainRegexMatcher {
+    regex: Regex,
 }
 
-impl DomainFullMatcher {
-    fn new(value: String) -> Self {
-        DomainFullMatcher { value }
+impl DomainRegexMatcher {
+    fn new(value: &str) -> Result<Self> {
+        let regex =
+            Regex::new(value).map_err(|e| anyhow!("invalid domain regex {}: {}", value, e))?;
+        Ok(DomainRegexMatcher { regex })
     }
 }
 
-impl Condition for DomainFullMatcher {
+impl Condition for DomainRegexMatcher {
     fn apply(&self, sess: &Session) -> bool {
-        if sess.destination.is_domain() {
-            if let Some(domain) = sess.destination.domain() {
-                if domain == &self.value {
-                    debug!("{} matches domain [{}]", domain, &self.value);
-                    return true;
-                }
+        if let Some(domain) = route_domain(sess) {
+            if self.regex.is_match(domain) {
+                debug!(
+                    "[{}] matches domain regex [{}]",
+                    domain,
+                    self.regex.as_str()
+                );
+                return true;
             }
         }
         false
     }
 }
 
+// Domain suffixes and full domains without wildcards go to the trie, the
+// others are matched one by one.
+fn add_domain_condition(
+    cond: &mut ConditionOr,
+    trie: &mut DomainTrie,
+    value: String,
+    field_type: config::Router_Rule_Domain_Type,
+) -> Result<()> {
+    match field_type {
+        config::Router_Rule_Domain_Type::REGEX => {
+            cond.add(Box::new(DomainRegexMatcher::new(&value)?))
+        }
+        _ if value.contains('*') => {
+            cond.add(Box::new(DomainWildcardMatcher::new(value, field_type)))
+        }
+        config::Router_Rule_Domain_Type::PLAIN => {
+            cond.add(Box::new(DomainKeywordMatcher::new(value)))
+        }
+        config::Router_Rule_Domain_Type::DOMAIN => trie.insert(&value, true),
+        config::Router_Rule_Domain_Type::FULL => trie.insert(&value, false),
+    }
+    Ok(())
+}
+
+// Domains prefixed with `!` are negated. A domain matches if it matches none
+// of the negated entries, and at least one of the other entries, if any.
+// That is, negated entries take precedence, and a rule with only negated
+// entries matches every domain except those.
 struct DomainMatcher {
-    condition: Box<dyn Condition>,
+    include: Option<ConditionOr>,
+    exclude: ConditionOr,
 }
 
 impl DomainMatcher {
-    fn new(domains: &mut protobuf::RepeatedField<config::Router_Rule_Domain>) -> Self {
-        let mut cond_or = ConditionOr::new();
+    fn new(domains: &mut protobuf::RepeatedField<config::Router_Rule_Domain>) -> Result<Self> {
+        let mut include = ConditionOr::new();
+        let mut exclude = ConditionOr::new();
+        let mut include_trie = DomainTrie::default();
+        let mut exclude_trie = DomainTrie::default();
         for rr_domain in domains.iter_mut() {
             let filter = std::mem::take(&mut rr_domain.value);
-            match rr_domain.field_type {
-                config::Router_Rule_Domain_Type::PLAIN => {
-                    cond_or.add(Box::new(DomainKeywordMatcher::new(filter)));
-                }
-                config::Router_Rule_Domain_Type::DOMAIN => {
-                    cond_or.add(Box::new(DomainSuffixMatcher::new(filter)));
-                }
-                config::Router_Rule_Domain_Type::FULL => {
-                    cond_or.add(Box::new(DomainFullMatcher::new(filter)));
-                }
+            if let Some(filter) = filter.strip_prefix('!') {
+                add_domain_condition(
+                    &mut exclude,
+                    &mut exclude_trie,
+                    filter.to_string(),
+                    rr_domain.field_type,
+                )?;
+            } else {
+                add_domain_condition(
+                    &mut include,
+                    &mut include_trie,
+                    filter,
+                    rr_domain.field_type,
+                )?;
             }
         }
-        DomainMatcher {
-            condition: Box::new(cond_or),
+        if !include_trie.is_empty() {
+            include.add(Box::new(include_trie));
+        }
+        if !exclude_trie.is_empty() {
+            exclude.add(Box::new(exclude_trie));
         }
+        Ok(DomainMatcher {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(include)
+            },
+            exclude,
+        })
     }
 }
 
 impl Condition for DomainMatcher {
     fn apply(&self, sess: &Session) -> bool {
-        self.condition.apply(sess)
+        if route_domain(sess).is_none() || self.exclude.apply(sess) {
+            return false;
+        }
+        match &self.include {
+            Some(include) => include.apply(sess),
+            None => true,
+        }
     }
 }
 
-// struct ProcessMatcher {
-//     names: Vec<String>,
-// }
-//
-// impl ProcessMatcher {
-//     fn new(names: &mut protobuf::RepeatedField<String>) -> Self {
-//         let mut v = Vec::new();
-//         for name in names.iter_mut() {
-//             let name = std::mem::take(name);
-//             v.push(name);
-//         }
-//         ProcessMatcher { names: v }
-//     }
-// }
-//
-// impl Condition for ProcessMatcher {
-//     fn apply(&self, sess: &Session) -> bool {
-//         let addr = sess.local_addr.ip().to_string().to_owned();
-//         let sock_name = crate::common::process::get_command_name_by_socket(sess.network, addr.as_str(), sess.local_addr.port());
-//
-//         let sock_name = sock_name.unwrap();
-//         for name in self.names.iter() {
-//             debug!("{}, {}", sock_name, name);
-//             if sock_name == *name {
-//                 debug!("[{:?}] matched, port: {}", sock_name, sess.local_addr.port());
-//                 return true;
-//             }
-//         }
-//         false
-//     }
-// }
+// Process names are looked up by scanning the system socket tables, which
+// blocks, so it's done off the async workers.
+async fn lookup_process_name(network: Network, source: SocketAddr) -> Option<String> {
+    tokio::task::spawn_blocking(move || {
+        crate::common::process::get_command_name_by_socket(
+            network,
+            &source.ip().to_string(),
+            source.port(),
+        )
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+// Matches the name of the process originating the session, which is only
+// known for local sources on platforms supporting the lookup. The name is
+// looked up by the router before matching, once for all rules.
+struct ProcessMatcher {
+    names: Vec<String>,
+}
+
+impl ProcessMatcher {
+    fn new(names: &mut protobuf::RepeatedField<String>) -> Self {
+        let mut values = Vec::new();
+        for name in names.iter_mut() {
+            values.push(std::mem::take(name));
+        }
+        ProcessMatcher { names: values }
+    }
+}
+
+impl Condition for ProcessMatcher {
+    fn apply(&self, sess: &Session) -> bool {
+        if let Some(name) = &sess.process_name {
+            for v in &self.names {
+                if v == name {
+                    debug!("[{}] matches process name [{}]", &sess.source, v);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
 
 struct ConditionAnd {
     conditions: Vec<Box<dyn Condition>>,
@@ -431,6 +778,10 @@ impl ConditionOr {
     fn add(&mut self, cond: Box<dyn Condition>) {
         self.conditions.push(cond)
     }
+
+    fn is_empty(&self) -> bool {
+        self.conditions.len() == 0
+    }
 }
 
 impl Condition for ConditionOr {
@@ -446,40 +797,64 @@ impl Condition for ConditionOr {
 
 pub struct Router {
     rules: Vec<Rule>,
+    mmdb_readers: HashMap<String, MmdbReader>,
     domain_resolve: bool,
+    route_only: bool,
+    // The conditions known before sniffing of each rule matching on the
+    // sniffed protocol.
+    sniff_filters: Vec<ConditionAnd>,
+    // Whether a rule matches on the process name, which is looked up then.
+    lookup_process: bool,
     dns_client: SyncDnsClient,
 }
 
 impl Router {
-    fn load_rules(rules: &mut Vec<Rule>, routing_rules: &mut protobuf::RepeatedField<Router_Rule>) {
-        let mut mmdb_readers: HashMap<String, Arc<maxminddb::Reader<Mmap>>> = HashMap::new();
+    fn get_mmdb_reader(
+        mmdb_readers: &mut HashMap<String, MmdbReader>,
+        file: &str,
+    ) -> Result<MmdbReader> {
+        if let Some(r) = mmdb_readers.get(file) {
+            return Ok(r.clone());
+        }
+        let r = Arc::new(ArcSwap::from_pointee(open_mmdb(file)?));
+        mmdb_readers.insert(file.to_owned(), r.clone());
+        Ok(r)
+    }
+
+    // Rules are tried in order and the first matching one wins. Within a rule,
+    // the conditions of different kinds, e.g. domains, IP-CIDRs, ports and
+    // networks, must all match, while any of the values of a kind matches.
+    // Domain destinations are resolved before matching the IP conditions of
+    // the rules requiring it, see `Rule::resolve`.
+    // Returns the sniff filters of the rules matching on the protocol.
+    fn load_rules(
+        rules: &mut Vec<Rule>,
+        mmdb_readers: &mut HashMap<String, MmdbReader>,
+        routing_rules: &mut protobuf::RepeatedField<Router_Rule>,
+    ) -> Result<Vec<ConditionAnd>> {
+        let mut sniff_filters = Vec::new();
         for rr in routing_rules.iter_mut() {
             let mut cond_and = ConditionAnd::new();
 
+            if rr.protocols.len() > 0 {
+                sniff_filters.push(Self::sniff_filter(rr)?);
+            }
+
             if rr.domains.len() > 0 {
-                cond_and.add(Box::new(DomainMatcher::new(&mut rr.domains)));
+                cond_and.add(Box::new(DomainMatcher::new(&mut rr.domains)?));
             }
 
             if rr.ip_cidrs.len() > 0 {
-                cond_and.add(Box::new(IpCidrMatcher::new(&mut rr.ip_cidrs)));
+                cond_and.add(Box::new(IpCidrMatcher::new(&rr.ip_cidrs)?));
+            }
+
+            if !rr.source_ip_cidrs.is_empty() {
+                cond_and.add(Box::new(SourceIpCidrMatcher::new(&rr.source_ip_cidrs)?));
             }
 
             if rr.mmdbs.len() > 0 {
                 for mmdb in rr.mmdbs.iter() {
-                    let reader = match mmdb_readers.get(&mmdb.file) {
-                        Some(r) => r.clone(),
-                        None => match maxminddb::Reader::open_mmap(&mmdb.file) {
-                            Ok(r) => {
-                                let r = Arc::new(r);
-                                mmdb_readers.insert((&mmdb.file).to_owned(), r.clone());
-                                r
-                            }
-                            Err(e) => {
-                                warn!("open mmdb file {} failed: {:?}", mmdb.file, e);
-                                continue;
-                            }
-                        },
-                    };
+                    let reader = Self::get_mmdb_reader(mmdb_readers, &mmdb.file)?;
                     cond_and.add(Box::new(MmdbMatcher::new(
                         reader,
                         mmdb.country_code.clone(),
@@ -487,20 +862,35 @@ impl Router {
                 }
             }
 
+            if rr.source_mmdbs.len() > 0 {
+                for mmdb in rr.source_mmdbs.iter() {
+                    let reader = Self::get_mmdb_reader(mmdb_readers, &mmdb.file)?;
+                    cond_and.add(Box::new(SourceMmdbMatcher::new(
+                        reader,
+                        mmdb.country_code.clone(),
+                    )));
+                }
+            }
+
             if rr.port_ranges.len() > 0 {
-                cond_and.add(Box::new(PortMatcher::new(&rr.port_ranges)));
+                cond_and.add(Box::new(PortMatcher::new(&rr.port_ranges)?));
             }
 
             if rr.networks.len() > 0 {
-                cond_and.add(Box::new(NetworkMatcher::new(&mut rr.networks)));
+                cond_and.add(Box::new(NetworkMatcher::new(&mut rr.networks)?));
             }
 
             if rr.inbound_tags.len() > 0 {
                 cond_and.add(Box::new(InboundTagMatcher::new(&mut rr.inbound_tags)));
             }
-            // if rr.processes.len() > 0 {
-            //     cond_and.add(Box::new(ProcessMatcher::new(&mut rr.processes)));
-            // }
+
+            if rr.protocols.len() > 0 {
+                cond_and.add(Box::new(ProtocolMatcher::new(&mut rr.protocols)));
+            }
+
+            if rr.processes.len() > 0 {
+                cond_and.add(Box::new(ProcessMatcher::new(&mut rr.processes)));
+            }
 
             if cond_and.is_empty() {
                 warn!("empty rule at target {}", rr.target_tag);
@@ -508,70 +898,183 @@ impl Router {
             }
 
             let tag = std::mem::take(&mut rr.target_tag);
-            rules.push(Rule::new(tag, Box::new(cond_and)));
+            let resolve = rr.resolve || !rr.mmdbs.is_empty();
+            rules.push(Rule::new(tag, Box::new(cond_and), resolve));
         }
+        Ok(sniff_filters)
     }
 
-    pub fn new(
+    fn lookup_process(routing_rules: &[Router_Rule]) -> bool {
+        routing_rules.iter().any(|rr| !rr.processes.is_empty())
+    }
+
+    // The conditions of a rule which are known before sniffing, sessions not
+    // matching them can't match the rule whatever the sniffed protocol is.
+    fn sniff_filter(rr: &Router_Rule) -> Result<ConditionAnd> {
+        let mut cond_and = ConditionAnd::new();
+        if !rr.source_ip_cidrs.is_empty() {
+            cond_and.add(Box::new(SourceIpCidrMatcher::new(&rr.source_ip_cidrs)?));
+        }
+        if !rr.port_ranges.is_empty() {
+            cond_and.add(Box::new(PortMatcher::new(&rr.port_ranges)?));
+        }
+        if !rr.networks.is_empty() {
+            cond_and.add(Box::new(NetworkMatcher::new(&mut rr.networks.clone())?));
+        }
+        if !rr.inbound_tags.is_empty() {
+            cond_and.add(Box::new(InboundTagMatcher::new(
+                &mut rr.inbound_tags.clone(),
+            )));
+        }
+        Ok(cond_and)
+    }
+
+    // Builds the rules of the config, opening the mmdb files not in the
+    // readers yet.
+    fn build(
         router: &mut protobuf::SingularPtrField<config::Router>,
+        mut mmdb_readers: HashMap<String, MmdbReader>,
         dns_client: SyncDnsClient,
-    ) -> Self {
+    ) -> Result<Self> {
         let mut rules: Vec<Rule> = Vec::new();
         let mut domain_resolve = false;
+        let mut route_only = false;
+        let mut sniff_filters = Vec::new();
+        let mut lookup_process = false;
         if let Some(router) = router.as_mut() {
-            Self::load_rules(&mut rules, &mut router.rules);
+            lookup_process = Self::lookup_process(&router.rules);
+            sniff_filters = Self::load_rules(&mut rules, &mut mmdb_readers, &mut router.rules)?;
             domain_resolve = router.domain_resolve;
+            route_only = router.route_only;
         }
-        Router {
+        Ok(Router {
             rules,
+            mmdb_readers,
             domain_resolve,
+            route_only,
+            sniff_filters,
+            lookup_process,
             dns_client,
-        }
+        })
     }
 
-    pub fn reload(
-        &mut self,
+    pub fn new(
         router: &mut protobuf::SingularPtrField<config::Router>,
-    ) -> Result<()> {
-        self.rules.clear();
-        if let Some(router) = router.as_mut() {
-            Self::load_rules(&mut self.rules, &mut router.rules);
-            self.domain_resolve = router.domain_resolve;
+        dns_client: SyncDnsClient,
+    ) -> Result<Self> {
+        Self::build(router, HashMap::new(), dns_client)
+    }
+
+    /// Builds a new router from the config, mmdb databases already loaded by
+    /// this router are shared instead of being opened again.
+    pub fn rebuild(&self, router: &mut protobuf::SingularPtrField<config::Router>) -> Result<Self> {
+        Self::build(router, self.mmdb_readers.clone(), self.dns_client.clone())
+    }
+
+    /// Re-opens all mmdb files referenced by the rules and swaps them in
+    /// place, routing in progress keeps using the old database until the swap
+    /// completes. A database failing to open or validate is left untouched.
+    pub fn reload_mmdbs(&self) -> Result<()> {
+        let mut failed = Vec::new();
+        for (file, reader) in self.mmdb_readers.iter() {
+            match open_mmdb(file) {
+                Ok(r) => {
+                    reader.store(Arc::new(r));
+                    info!("reloaded mmdb file {}", file);
+                }
+                Err(e) => {
+                    warn!("{}, keep using the loaded one", e);
+                    failed.push(file.as_str());
+                }
+            }
+        }
+        if !failed.is_empty() {
+            return Err(anyhow!("reload mmdb files failed: {}", failed.join(", ")));
         }
         Ok(())
     }
 
+    /// Whether the session may match a rule on the sniffed application
+    /// protocol, it needs to be sniffed before routing in that case.
+    pub fn sniff_protocol(&self, sess: &Session) -> bool {
+        self.sniff_filters.iter().any(|x| x.apply(sess))
+    }
+
+    /// Whether sessions are only routed by the sniffed domain, connecting to
+    /// the original address, instead of connecting to the domain as well.
+    pub fn route_only(&self) -> bool {
+        self.route_only
+    }
+
+    // Returns a copy of the session with the destination domain replaced by
+    // its first resolved ip.
+    async fn resolve(&self, sess: &Session) -> Result<Session> {
+        let ips = {
+            self.dns_client
+                .read()
+                .await
+                .lookup(
+                    sess.destination
+                        .domain()
+                        .ok_or_else(|| anyhow!("illegal domain name"))?,
+                )
+                .map_err(|e| anyhow!("lookup {} failed: {}", sess.destination.host(), e))
+                .await?
+        };
+        let ip = ips
+            .first()
+            .ok_or_else(|| anyhow!("no ip found for {}", sess.destination.host()))?;
+        let mut new_sess = sess.clone();
+        new_sess.destination = SocksAddr::from((*ip, sess.destination.port()));
+        Ok(new_sess)
+    }
+
     pub async fn pick_route(&self, sess: &Session) -> Result<&String> {
+        let mut routed_sess = None;
+        if self.lookup_process && sess.process_name.is_none() {
+            let source = SocketAddr::new(source_ip(sess), sess.source.port());
+            let name = lookup_process_name(sess.network, source).await;
+            routed_sess.get_or_insert_with(|| sess.clone()).process_name = name;
+        }
+        let sess = routed_sess.as_ref().unwrap_or(sess);
+        self.pick_route_for(sess).await
+    }
+
+    async fn pick_route_for(&self, sess: &Session) -> Result<&String> {
+        // The destination domain is resolved at most once for a session.
+        let mut resolved: Option<Result<Session>> = None;
         for rule in &self.rules {
             if rule.apply(sess) {
                 return Ok(&rule.target);
             }
+            if rule.resolve && sess.destination.is_domain() {
+                if resolved.is_none() {
+                    let res = self.resolve(sess).await;
+                    if let Err(e) = &res {
+                        debug!("{}", e);
+                    }
+                    resolved = Some(res);
+                }
+                if let Some(Ok(new_sess)) = &resolved {
+                    if rule.apply(new_sess) {
+                        return Ok(&rule.target);
+                    }
+                }
+            }
         }
         if sess.destination.is_domain() && self.domain_resolve {
-            let ips = {
-                self.dns_client
-                    .read()
-                    .await
-                    .lookup(
-                        sess.destination
-                            .domain()
-                            .ok_or_else(|| anyhow!("illegal domain name"))?,
-                    )
-                    .map_err(|e| anyhow!("lookup {} failed: {}", sess.destination.host(), e))
-                    .await?
+            let new_sess = match resolved {
+                Some(res) => res?,
+                None => self.resolve(sess).await?,
             };
-            if !ips.is_empty() {
-                let mut new_sess = sess.clone();
-                new_sess.destination = SocksAddr::from((ips[0], sess.destination.port()));
-                log::trace!(
-                    "re-matching with resolved ip [{}] for [{}]",
-                    ips[0],
-                    sess.destination.host()
-                );
-                for rule in &self.rules {
-                    if rule.apply(&new_sess) {
-                        return Ok(&rule.target);
-                    }
+            log::trace!(
+                "re-matching with resolved ip [{}] for [{}]",
+                new_sess.destination.host(),
+                sess.destination.host()
+            );
+            for rule in &self.rules {
+                if rule.apply(&new_sess) {
+                    return Ok(&rule.target);
                 }
             }
         }
@@ -586,14 +1089,21 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_is_sub_domain() {
-        let d1 = "video.google.com".to_string();
-        let d2 = "google.com".to_string();
-        assert!(is_sub_domain(&d1, &d2));
-
-        let d1 = "video.google.com".to_string();
-        let d2 = "gle.com".to_string();
-        assert!(!is_sub_domain(&d1, &d2));
+    fn test_domain_trie() {
+        let mut trie = DomainTrie::default();
+        trie.insert("google.com", true);
+        trie.insert("www.example.com", false);
+        assert!(trie.matches("google.com"));
+        assert!(trie.matches("video.google.com"));
+        assert!(!trie.matches("video.gle.com"));
+        assert!(!trie.matches("com"));
+        assert!(trie.matches("www.example.com"));
+        assert!(!trie.matches("example.com"));
+        assert!(!trie.matches("a.www.example.com"));
+
+        // a suffix covers the full domains under it
+        trie.insert("example.com", true);
+        assert!(trie.matches("a.www.example.com"));
     }
 
     #[test]
@@ -607,7 +1117,8 @@ mod tests {
         let m = PortMatcher::new(&protobuf::RepeatedField::from_vec(vec![
             "1024-5000".to_string(),
             "6000-7000".to_string(),
-        ]));
+        ]))
+        .unwrap();
         sess.destination = SocksAddr::Domain("www.google.com".to_string(), 2000);
         assert!(m.apply(&sess));
         sess.destination = SocksAddr::Domain("www.google.com".to_string(), 5001);
@@ -618,14 +1129,26 @@ mod tests {
         // test single port range
         let m = PortMatcher::new(&protobuf::RepeatedField::from_vec(
             vec!["22-22".to_string()],
-        ));
+        ))
+        .unwrap();
         sess.destination = SocksAddr::Domain("www.google.com".to_string(), 22);
         assert!(m.apply(&sess));
 
+        // test single port
+        let m = PortMatcher::new(&protobuf::RepeatedField::from_vec(vec![
+            "443".to_string(),
+            "8000-8080".to_string(),
+        ]))
+        .unwrap();
+        sess.destination = SocksAddr::Domain("www.google.com".to_string(), 443);
+        assert!(m.apply(&sess));
+        sess.destination = SocksAddr::Domain("www.google.com".to_string(), 444);
+        assert!(!m.apply(&sess));
+
         // test invalid port ranges
         let m = PortRangeMatcher::new("22-21");
         assert!(m.is_err());
-        let m = PortRangeMatcher::new("22");
+        let m = PortRangeMatcher::new("abc");
         assert!(m.is_err());
         let m = PortRangeMatcher::new("22-");
         assert!(m.is_err());
@@ -635,5 +1158,617 @@ mod tests {
         assert!(m.is_err());
         let m = PortRangeMatcher::new("22-23-24");
         assert!(m.is_err());
+        assert!(PortMatcher::new(&protobuf::RepeatedField::from_vec(vec![
+            "443".to_string(),
+            "65536".to_string(),
+        ]))
+        .is_err());
+    }
+
+    // Builds a minimal IPv4 database mapping every address to `iso_code`.
+    fn build_mmdb(iso_code: &str) -> Vec<u8> {
+        build_split_mmdb(iso_code, Some(iso_code))
+    }
+
+    // Builds a minimal IPv4 database mapping 0.0.0.0/1 to `low` and
+    // 128.0.0.0/1 to `high`, or leaving the latter unknown.
+    fn build_split_mmdb(low: &str, high: Option<&str>) -> Vec<u8> {
+        fn put_str(buf: &mut Vec<u8>, s: &str) {
+            buf.push(0x40 | s.len() as u8);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        fn put_country(buf: &mut Vec<u8>, iso_code: &str) {
+            buf.push(0xe1);
+            put_str(buf, "country");
+            buf.push(0xe1);
+            put_str(buf, "iso_code");
+            put_str(buf, iso_code);
+        }
+        let mut data = Vec::new();
+        put_country(&mut data, low);
+        // records pointing to the data section are offset by the node count
+        // plus the 16 bytes data section separator, a record equal to the
+        // node count means no data
+        let high_record = match high {
+            Some(high) => {
+                let record = 17 + data.len() as u8;
+                put_country(&mut data, high);
+                record
+            }
+            None => 1,
+        };
+        let mut buf = Vec::new();
+        // search tree, 1 node with 24-bit records pointing to the data
+        buf.extend_from_slice(&[0, 0, 17, 0, 0, high_record]);
+        buf.extend_from_slice(&[0u8; 16]);
+        // data section
+        buf.extend_from_slice(&data);
+        // metadata
+        buf.extend_from_slice(b"\xab\xcd\xefMaxMind.com");
+        buf.push(0xe9);
+        put_str(&mut buf, "node_count");
+        buf.extend_from_slice(&[0xc1, 1]);
+        put_str(&mut buf, "record_size");
+        buf.extend_from_slice(&[0xa1, 24]);
+        put_str(&mut buf, "ip_version");
+        buf.extend_from_slice(&[0xa1, 4]);
+        put_str(&mut buf, "database_type");
+        put_str(&mut buf, "test");
+        put_str(&mut buf, "languages");
+        buf.extend_from_slice(&[0x00, 0x04]);
+        put_str(&mut buf, "binary_format_major_version");
+        buf.extend_from_slice(&[0xa1, 2]);
+        put_str(&mut buf, "binary_format_minor_version");
+        buf.push(0xa0);
+        put_str(&mut buf, "build_epoch");
+        buf.extend_from_slice(&[0x00, 0x02]);
+        put_str(&mut buf, "description");
+        buf.push(0xe0);
+        buf
+    }
+
+    #[test]
+    fn test_reload_mmdbs() {
+        let dir = std::env::temp_dir().join(format!("flower-mmdb-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("geo.mmdb");
+        let tmp = dir.join("geo.mmdb.tmp");
+        std::fs::write(&file, build_mmdb("XX")).unwrap();
+
+        let mut mmdb = config::Router_Rule_Mmdb::new();
+        mmdb.file = file.to_string_lossy().to_string();
+        mmdb.country_code = "xx".to_string();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "geo".to_string();
+        rule.mmdbs.push(mmdb);
+        let router = new_router(vec![rule]).unwrap();
+
+        let sess = Session {
+            destination: SocksAddr::Ip("1.2.3.4:443".parse().unwrap()),
+            ..Default::default()
+        };
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        assert!(rt.block_on(router.pick_route(&sess)).is_ok());
+
+        // Replace the file rather than writing over the mapped one.
+        std::fs::write(&tmp, build_mmdb("YY")).unwrap();
+        std::fs::rename(&tmp, &file).unwrap();
+        router.reload_mmdbs().unwrap();
+        assert!(rt.block_on(router.pick_route(&sess)).is_err());
+
+        // A broken database is rejected and the loaded one is kept.
+        std::fs::write(&tmp, b"invalid").unwrap();
+        std::fs::rename(&tmp, &file).unwrap();
+        assert!(router.reload_mmdbs().is_err());
+        assert!(rt.block_on(router.pick_route(&sess)).is_err());
+
+        std::fs::write(&tmp, build_mmdb("XX")).unwrap();
+        std::fs::rename(&tmp, &file).unwrap();
+        router.reload_mmdbs().unwrap();
+        assert!(rt.block_on(router.pick_route(&sess)).is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_source_geoip() {
+        let dir = std::env::temp_dir().join(format!("flower-source-mmdb-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("geo.mmdb");
+        std::fs::write(&file, build_split_mmdb("XX", Some("YY"))).unwrap();
+
+        let mut rules = Vec::new();
+        for (code, target) in [("xx", "region-x"), ("yy", "region-y")] {
+            let mut mmdb = config::Router_Rule_Mmdb::new();
+            mmdb.file = file.to_string_lossy().to_string();
+            mmdb.country_code = code.to_string();
+            let mut rule = config::Router_Rule::new();
+            rule.target_tag = target.to_string();
+            rule.source_mmdbs.push(mmdb);
+            rules.push(rule);
+        }
+        let router = new_router(rules).unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        // The destination is irrelevant, sources in different regions are
+        // routed differently.
+        let mut sess = Session {
+            destination: SocksAddr::Ip("200.1.1.1:443".parse().unwrap()),
+            ..Default::default()
+        };
+        sess.source = "1.2.3.4:50000".parse().unwrap();
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "region-x");
+        sess.source = "200.2.3.4:50000".parse().unwrap();
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "region-y");
+        sess.source = "[::ffff:1.2.3.4]:50000".parse().unwrap();
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "region-x");
+
+        // Local sources don't match.
+        for source in [
+            "192.168.1.2:50000",
+            "10.0.0.2:50000",
+            "127.0.0.1:50000",
+            "100.64.0.1:50000",
+            "[fd00::1]:50000",
+        ] {
+            sess.source = source.parse().unwrap();
+            assert!(rt.block_on(router.pick_route(&sess)).is_err(), "{}", source);
+        }
+
+        // Nor do sources missing from the database.
+        let file = dir.join("partial.mmdb");
+        std::fs::write(&file, build_split_mmdb("XX", None)).unwrap();
+        let reader = Arc::new(ArcSwap::from_pointee(
+            open_mmdb(&file.to_string_lossy()).unwrap(),
+        ));
+        let m = SourceMmdbMatcher::new(reader, "xx".to_string());
+        sess.source = "1.2.3.4:50000".parse().unwrap();
+        assert!(m.apply(&sess));
+        sess.source = "200.2.3.4:50000".parse().unwrap();
+        assert!(!m.apply(&sess));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_geoip_resolve() {
+        let dir = std::env::temp_dir().join(format!("flower-geoip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("geo.mmdb");
+        std::fs::write(&file, build_split_mmdb("XX", Some("YY"))).unwrap();
+
+        let mut router_config = config::Router::new();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "port".to_string();
+        rule.port_ranges.push("22-22".to_string());
+        router_config.rules.push(rule);
+        let mut mmdb = config::Router_Rule_Mmdb::new();
+        mmdb.file = file.to_string_lossy().to_string();
+        mmdb.country_code = "xx".to_string();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "region-x".to_string();
+        rule.mmdbs.push(mmdb);
+        router_config.rules.push(rule);
+        let hosts = [("low.example", "1.2.3.4"), ("high.example", "200.1.1.1")];
+        let router = new_router_with(router_config.clone(), &hosts).unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // Domains are resolved before matching geoip rules.
+        let sess = domain_sess("low.example");
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "region-x");
+        let sess = domain_sess("high.example");
+        assert!(rt.block_on(router.pick_route(&sess)).is_err());
+        // Rules not needing the ip match on the domain.
+        let sess = Session {
+            destination: SocksAddr::Domain("low.example".to_string(), 22),
+            ..Default::default()
+        };
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "port");
+
+        // A missing database fails the router.
+        router_config.rules[1].mmdbs[0].file =
+            dir.join("missing.mmdb").to_string_lossy().to_string();
+        assert!(new_router_with(router_config, &hosts).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_ip_cidr() {
+        let ip_sess = |ip: &str| Session {
+            destination: SocksAddr::Ip(std::net::SocketAddr::new(ip.parse().unwrap(), 443)),
+            ..Default::default()
+        };
+        let m = IpCidrMatcher::new(&protobuf::RepeatedField::from_vec(vec![
+            "10.0.0.0/8".to_string(),
+            "10.1.0.0/16".to_string(),
+            "192.168.1.0/24".to_string(),
+            "1.1.1.1".to_string(),
+            "2001:db8::/32".to_string(),
+        ]))
+        .unwrap();
+        assert_eq!(m.v4.ranges.len(), 3);
+        for ip in [
+            "10.0.0.1",
+            "10.1.2.3",
+            "10.255.255.255",
+            "192.168.1.9",
+            "1.1.1.1",
+            "2001:db8::1",
+        ] {
+            assert!(m.apply(&ip_sess(ip)), "{}", ip);
+        }
+        for ip in [
+            "9.255.255.255",
+            "11.0.0.0",
+            "192.168.2.1",
+            "1.1.1.2",
+            "2001:db9::1",
+            "::1",
+        ] {
+            assert!(!m.apply(&ip_sess(ip)), "{}", ip);
+        }
+        assert!(!m.apply(&domain_sess("example.com")));
+
+        for cidr in ["10.0.0.0/33", "10.0.0.1/8", "example.com", ""] {
+            let cidrs = protobuf::RepeatedField::from_vec(vec![cidr.to_string()]);
+            assert!(IpCidrMatcher::new(&cidrs).is_err(), "{}", cidr);
+        }
+    }
+
+    #[test]
+    fn test_ip_cidr_resolve() {
+        let mut router_config = config::Router::new();
+        for (target, resolve) in [("no-resolve", false), ("resolve", true)] {
+            let mut rule = config::Router_Rule::new();
+            rule.target_tag = target.to_string();
+            rule.ip_cidrs.push("1.2.3.0/24".to_string());
+            rule.resolve = resolve;
+            router_config.rules.push(rule);
+        }
+        let router = new_router_with(router_config, &[("cdn.example", "1.2.3.4")]).unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let sess = domain_sess("cdn.example");
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "resolve");
+        let sess = Session {
+            destination: SocksAddr::Ip("1.2.3.4:443".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "no-resolve");
+    }
+
+    #[test]
+    fn test_process_name() {
+        let m = ProcessMatcher::new(&mut protobuf::RepeatedField::from_vec(vec![
+            "curl".to_string(),
+            "wget".to_string(),
+        ]));
+        let mut sess = domain_sess("example.com");
+        assert!(!m.apply(&sess));
+        sess.process_name = Some("wget".to_string());
+        assert!(m.apply(&sess));
+        sess.process_name = Some("ssh".to_string());
+        assert!(!m.apply(&sess));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_route_process_name() {
+        let exe = std::env::current_exe().unwrap();
+        let exe = exe.file_name().unwrap().to_str().unwrap();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "process".to_string();
+        rule.processes.push(exe.to_string());
+        let router = new_router(vec![rule]).unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut sess = domain_sess("example.com");
+        sess.source = stream.local_addr().unwrap();
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "process");
+
+        // The lookup fails for sockets of no local process.
+        drop(stream);
+        drop(listener);
+        sess.source = "10.255.255.1:50000".parse().unwrap();
+        assert!(rt.block_on(router.pick_route(&sess)).is_err());
+
+        // A name already known isn't looked up again.
+        sess.process_name = Some(exe.to_string());
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "process");
+    }
+
+    #[test]
+    fn test_route_sniffed_protocol() {
+        let mut rules = Vec::new();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "reject".to_string();
+        rule.protocols.push("BitTorrent".to_string());
+        rules.push(rule);
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "proxy".to_string();
+        rule.protocols.push("tls".to_string());
+        rules.push(rule);
+        let router = new_router(rules).unwrap();
+        assert!(router.sniff_protocol(&Session::default()));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let client_hello = [0x16, 0x3, 0x1, 0x0, 0xc8, 0x1, 0x0, 0x0, 0xc4, 0x3, 0x3];
+        let mut handshake = b"\x13BitTorrent protocol".to_vec();
+        handshake.extend_from_slice(&[0u8; 48]);
+
+        let mut sess = Session {
+            destination: SocksAddr::Ip("1.2.3.4:443".parse().unwrap()),
+            ..Default::default()
+        };
+        sess.protocol = crate::common::sniff::sniff_stream_protocol(&client_hello);
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "proxy");
+
+        sess.destination = SocksAddr::Ip("1.2.3.4:6881".parse().unwrap());
+        sess.protocol = crate::common::sniff::sniff_stream_protocol(&handshake);
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "reject");
+
+        sess.protocol = None;
+        assert!(rt.block_on(router.pick_route(&sess)).is_err());
+    }
+
+    #[test]
+    fn test_sniff_filter() {
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "proxy".to_string();
+        rule.protocols.push("tls".to_string());
+        rule.port_ranges.push("443".to_string());
+        rule.inbound_tags.push("socks".to_string());
+        let router = new_router(vec![rule]).unwrap();
+
+        let mut sess = Session {
+            destination: SocksAddr::Ip("1.2.3.4:443".parse().unwrap()),
+            inbound_tag: "socks".to_string(),
+            ..Default::default()
+        };
+        assert!(router.sniff_protocol(&sess));
+        sess.inbound_tag = "http".to_string();
+        assert!(!router.sniff_protocol(&sess));
+        sess.inbound_tag = "socks".to_string();
+        sess.destination = SocksAddr::Ip("1.2.3.4:80".parse().unwrap());
+        assert!(!router.sniff_protocol(&sess));
+    }
+
+    #[test]
+    fn test_route_sniffed_host() {
+        let mut router_config = config::Router::new();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "proxy".to_string();
+        let mut domain = config::Router_Rule_Domain::new();
+        domain.field_type = config::Router_Rule_Domain_Type::DOMAIN;
+        domain.value = "example.com".to_string();
+        rule.domains.push(domain);
+        router_config.rules.push(rule);
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "direct".to_string();
+        rule.ip_cidrs.push("1.2.3.0/24".to_string());
+        router_config.rules.push(rule);
+        router_config.route_only = true;
+        let router = new_router_with(router_config, &[]).unwrap();
+        assert!(router.route_only());
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let mut sess = Session {
+            destination: SocksAddr::Ip("1.2.3.4:443".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "direct");
+        sess.sniffed_host = Some("www.example.com".to_string());
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "proxy");
+        // The IP rules match the original destination.
+        sess.sniffed_host = Some("www.example.org".to_string());
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "direct");
+    }
+
+    #[test]
+    fn test_route_network_port() {
+        // QUIC goes direct, other TCP traffic is proxied
+        let mut rules = Vec::new();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "direct".to_string();
+        rule.networks.push("udp".to_string());
+        rule.port_ranges.push("443".to_string());
+        rules.push(rule);
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "proxy".to_string();
+        rule.networks.push("tcp".to_string());
+        rules.push(rule);
+        let router = new_router(rules.clone()).unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let mut sess = Session {
+            network: Network::Udp,
+            destination: SocksAddr::Domain("www.google.com".to_string(), 443),
+            ..Default::default()
+        };
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "direct");
+        sess.destination = SocksAddr::Domain("www.google.com".to_string(), 53);
+        assert!(rt.block_on(router.pick_route(&sess)).is_err());
+        sess.network = Network::Tcp;
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "proxy");
+        sess.destination = SocksAddr::Domain("www.google.com".to_string(), 443);
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "proxy");
+
+        // malformed values fail the router
+        rules[0].port_ranges.push("2000-1000".to_string());
+        assert!(new_router(rules.clone()).is_err());
+        rules[0].port_ranges.pop();
+        rules[0].networks.push("icmp".to_string());
+        assert!(new_router(rules).is_err());
+    }
+
+    #[test]
+    fn test_route_source() {
+        // LAN clients go direct, clients of the wg inbound are proxied
+        let mut rules = Vec::new();
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "direct".to_string();
+        rule.source_ip_cidrs.push("192.168.0.0/16".to_string());
+        rules.push(rule);
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "proxy".to_string();
+        rule.inbound_tags.push("wg".to_string());
+        rules.push(rule);
+        let router = new_router(rules).unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let mut sess = Session {
+            source: "192.168.1.2:50000".parse().unwrap(),
+            destination: SocksAddr::Domain("www.google.com".to_string(), 443),
+            inbound_tag: "wg".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "direct");
+        sess.source = "[::ffff:192.168.1.2]:50000".parse().unwrap();
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "direct");
+        sess.source = "10.0.0.2:50000".parse().unwrap();
+        assert_eq!(rt.block_on(router.pick_route(&sess)).unwrap(), "proxy");
+        sess.inbound_tag = "socks".to_string();
+        assert!(rt.block_on(router.pick_route(&sess)).is_err());
+    }
+
+    fn new_router(rules: Vec<config::Router_Rule>) -> Result<Router> {
+        let mut router_config = config::Router::new();
+        router_config.rules = protobuf::RepeatedField::from_vec(rules);
+        new_router_with(router_config, &[])
+    }
+
+    // The hosts are resolved statically, the DNS server is never queried.
+    fn new_router_with(router_config: config::Router, hosts: &[(&str, &str)]) -> Result<Router> {
+        let mut dns = config::Dns::new();
+        dns.servers.push("127.0.0.1".to_string());
+        for (host, ip) in hosts {
+            let mut ips = config::Dns_Ips::new();
+            ips.values.push(ip.to_string());
+            dns.hosts.insert(host.to_string(), ips);
+        }
+        let dns_client = Arc::new(tokio::sync::RwLock::new(
+            crate::app::dns_client::DnsClient::new(&protobuf::SingularPtrField::some(dns))?,
+        ));
+        Router::new(
+            &mut protobuf::SingularPtrField::some(router_config),
+            dns_client,
+        )
+    }
+
+    fn domain_rule(domains: &[(&str, config::Router_Rule_Domain_Type)]) -> DomainMatcher {
+        let mut rr_domains = protobuf::RepeatedField::new();
+        for (value, field_type) in domains {
+            let mut domain = config::Router_Rule_Domain::new();
+            domain.value = value.to_string();
+            domain.field_type = *field_type;
+            rr_domains.push(domain);
+        }
+        DomainMatcher::new(&mut rr_domains).unwrap()
+    }
+
+    fn domain_sess(domain: &str) -> Session {
+        Session {
+            destination: SocksAddr::Domain(domain.to_string(), 443),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_domain_negation() {
+        use config::Router_Rule_Domain_Type::*;
+
+        // everything except example.com
+        let m = domain_rule(&[("!example.com", DOMAIN)]);
+        assert!(m.apply(&domain_sess("www.google.com")));
+        assert!(!m.apply(&domain_sess("example.com")));
+        assert!(!m.apply(&domain_sess("www.example.com")));
+        assert!(!m.apply(&Session {
+            destination: SocksAddr::Ip("1.2.3.4:443".parse().unwrap()),
+            ..Default::default()
+        }));
+
+        // negated entries take precedence over positive ones
+        let m = domain_rule(&[("google.com", DOMAIN), ("!ads.google.com", DOMAIN)]);
+        assert!(m.apply(&domain_sess("www.google.com")));
+        assert!(!m.apply(&domain_sess("x.ads.google.com")));
+        assert!(!m.apply(&domain_sess("www.example.com")));
+    }
+
+    #[test]
+    fn test_domain_wildcard() {
+        use config::Router_Rule_Domain_Type::*;
+
+        let m = domain_rule(&[("*.cdn.*", FULL)]);
+        assert!(m.apply(&domain_sess("img.cdn.example.com")));
+        assert!(!m.apply(&domain_sess("cdn.example.com")));
+        assert!(!m.apply(&domain_sess("img.cdnx.example.com")));
+
+        let m = domain_rule(&[("img*.example.com", DOMAIN)]);
+        assert!(m.apply(&domain_sess("img01.example.com")));
+        assert!(m.apply(&domain_sess("a.img01.example.com")));
+        assert!(!m.apply(&domain_sess("www.example.com")));
+
+        let m = domain_rule(&[("goo*le", PLAIN)]);
+        assert!(m.apply(&domain_sess("www.google.com")));
+        assert!(!m.apply(&domain_sess("www.example.com")));
+
+        let w = Wildcard::new("a*b*a");
+        assert!(w.matches("aba"));
+        assert!(w.matches("abba"));
+        assert!(!w.matches("ab"));
+        assert!(!w.matches("a"));
+    }
+
+    #[test]
+    fn test_domain_regex() {
+        use config::Router_Rule_Domain_Type::*;
+
+        let m = domain_rule(&[(r"^img\d+\.example\.(com|net)$", REGEX)]);
+        assert!(m.apply(&domain_sess("img01.example.com")));
+        assert!(m.apply(&domain_sess("img2.example.net")));
+        assert!(!m.apply(&domain_sess("img.example.com")));
+        assert!(!m.apply(&domain_sess("a.img01.example.com")));
+
+        // `*` is a regex quantifier here, not a wildcard
+        let m = domain_rule(&[("^go*gle", REGEX), (r"!^google\.cn$", REGEX)]);
+        assert!(m.apply(&domain_sess("gogle.com")));
+        assert!(m.apply(&domain_sess("google.com")));
+        assert!(!m.apply(&domain_sess("google.cn")));
+
+        // invalid patterns fail the router instead of every route
+        let mut domain = config::Router_Rule_Domain::new();
+        domain.value = "(example".to_string();
+        domain.field_type = REGEX;
+        let mut rule = config::Router_Rule::new();
+        rule.target_tag = "proxy".to_string();
+        rule.domains.push(domain);
+        assert!(new_router(vec![rule]).is_err());
     }
 }
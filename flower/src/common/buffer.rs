@@ -0,0 +1,102 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use bytes::BytesMut;
+use lazy_static::lazy_static;
+
+use crate::option;
+
+lazy_static! {
+    /// Buffers for relaying streams.
+    pub static ref RELAY_BUFFER_POOL: BufferPool = BufferPool::new(
+        *option::RELAY_BUFFER_SIZE * 1024,
+        *option::RELAY_BUFFER_POOL_SIZE,
+    );
+}
+
+/// A pool of fixed-size buffers. Buffers are returned to the pool when
+/// dropped, at most `max_idle` of them are kept for reuse.
+pub struct BufferPool {
+    size: usize,
+    max_idle: usize,
+    idle: Mutex<Vec<BytesMut>>,
+}
+
+impl BufferPool {
+    pub fn new(size: usize, max_idle: usize) -> Self {
+        BufferPool {
+            size,
+            max_idle,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Takes an idle buffer, or allocates a new one if there's none. The
+    /// content of a reused buffer is unspecified.
+    pub fn get(&self) -> PooledBuffer<'_> {
+        let buf = self.idle.lock().unwrap().pop().unwrap_or_else(|| {
+            let mut buf = BytesMut::with_capacity(self.size);
+            buf.resize(self.size, 0);
+            buf
+        });
+        PooledBuffer { pool: self, buf }
+    }
+
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+/// A buffer taken from a [`BufferPool`].
+pub struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    buf: BytesMut,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        let mut idle = self.pool.idle.lock().unwrap();
+        if idle.len() < self.pool.max_idle {
+            idle.push(std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new(16, 2);
+        let mut a = pool.get();
+        assert_eq!(a.len(), 16);
+        a[0] = 1;
+        let ptr = a.as_ptr();
+        let b = pool.get();
+        let c = pool.get();
+        assert_eq!(pool.idle(), 0);
+        drop((a, b, c));
+        // At most `max_idle` buffers are kept.
+        assert_eq!(pool.idle(), 2);
+        let a = pool.get();
+        let b = pool.get();
+        assert!(a.as_ptr() == ptr || b.as_ptr() == ptr);
+        assert_eq!(a.len(), 16);
+        assert_eq!(pool.idle(), 0);
+    }
+}
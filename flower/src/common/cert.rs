@@ -0,0 +1,203 @@
+use std::{fs, io, path::Path};
+
+fn has_extension(path: &str, exts: &[&str]) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|x| x.to_str())
+        .map_or(false, |x| exts.iter().any(|e| x.eq_ignore_ascii_case(e)))
+}
+
+/// Whether the file is a PKCS#12 archive, by its `.p12` or `.pfx` extension.
+pub fn is_pkcs12(path: &str) -> bool {
+    has_extension(path, &["p12", "pfx"])
+}
+
+fn invalid_input<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+fn read(path: &str, what: &str) -> io::Result<Vec<u8>> {
+    fs::read(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("read {} {} failed: {}", what, path, e)))
+}
+
+/// Reads the certificate, the private key and the CA certificates from a
+/// PKCS#12 archive.
+#[cfg(feature = "openssl")]
+pub fn load_pkcs12(path: &str, password: &str) -> io::Result<openssl::pkcs12::ParsedPkcs12_2> {
+    let der = read(path, "certificate")?;
+    openssl::pkcs12::Pkcs12::from_der(&der)
+        .and_then(|p| p.parse2(password))
+        .map_err(|e| invalid_input(format!("invalid PKCS#12 certificate {}: {}", path, e)))
+}
+
+/// A certificate chain, the leaf first, and its private key, all in DER.
+#[cfg(feature = "rustls-pemfile")]
+#[derive(Debug)]
+pub struct CertifiedKey {
+    pub certs: Vec<Vec<u8>>,
+    pub key: Vec<u8>,
+}
+
+#[cfg(all(feature = "rustls-pemfile", feature = "openssl"))]
+fn load_pkcs12_der(path: &str, password: &str) -> io::Result<CertifiedKey> {
+    let parsed = load_pkcs12(path, password)?;
+    let invalid = |e| invalid_input(format!("invalid PKCS#12 certificate {}: {}", path, e));
+    let (cert, pkey) = match (parsed.cert, parsed.pkey) {
+        (Some(cert), Some(pkey)) => (cert, pkey),
+        _ => {
+            return Err(invalid_input(format!(
+                "no certificate or private key in {}",
+                path
+            )))
+        }
+    };
+    let mut certs = vec![cert.to_der().map_err(invalid)?];
+    for ca in parsed.ca.iter().flatten() {
+        certs.push(ca.to_der().map_err(invalid)?);
+    }
+    let key = pkey.private_key_to_pkcs8().map_err(invalid)?;
+    Ok(CertifiedKey { certs, key })
+}
+
+#[cfg(all(feature = "rustls-pemfile", not(feature = "openssl")))]
+fn load_pkcs12_der(path: &str, _password: &str) -> io::Result<CertifiedKey> {
+    Err(invalid_input(format!(
+        "PKCS#12 certificate {} is not supported without openssl",
+        path
+    )))
+}
+
+/// Loads the certificate chain and the private key.
+///
+/// `certificate` is a PKCS#12 archive if it has a `.p12` or `.pfx`
+/// extension, decrypted with `password`, and `certificate_key` is ignored.
+/// Otherwise both are in DER if they have a `.der` extension, or PEM, the
+/// private key is read from `certificate` if `certificate_key` is empty.
+#[cfg(feature = "rustls-pemfile")]
+pub fn load(certificate: &str, certificate_key: &str, password: &str) -> io::Result<CertifiedKey> {
+    if is_pkcs12(certificate) {
+        return load_pkcs12_der(certificate, password);
+    }
+
+    let cert_file = read(certificate, "certificate")?;
+    let mut certs = Vec::new();
+    let mut keys = Vec::new();
+    if has_extension(certificate, &["der"]) {
+        certs.push(cert_file);
+    } else {
+        let items = rustls_pemfile::read_all(&mut &*cert_file)
+            .map_err(|_| invalid_input(format!("invalid PEM certificate {}", certificate)))?;
+        for item in items {
+            match item {
+                rustls_pemfile::Item::X509Certificate(cert) => certs.push(cert),
+                rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) => {
+                    keys.push(key)
+                }
+            }
+        }
+    }
+    if certs.is_empty() {
+        return Err(invalid_input(format!("no certificate in {}", certificate)));
+    }
+
+    if certificate_key.is_empty() {
+        return match keys.into_iter().next() {
+            Some(key) => Ok(CertifiedKey { certs, key }),
+            None => Err(invalid_input(format!(
+                "no private key in {} and no separate key given",
+                certificate
+            ))),
+        };
+    }
+    let key = read(certificate_key, "private key")?;
+    let key = if has_extension(certificate_key, &["der"]) {
+        key
+    } else {
+        let invalid_key = |_| invalid_input(format!("invalid PEM private key {}", certificate_key));
+        let mut keys = rustls_pemfile::pkcs8_private_keys(&mut &*key).map_err(invalid_key)?;
+        if keys.is_empty() {
+            keys = rustls_pemfile::rsa_private_keys(&mut &*key).map_err(invalid_key)?;
+        }
+        match keys.into_iter().next() {
+            Some(key) => key,
+            None => {
+                return Err(invalid_input(format!(
+                    "no private key in {}",
+                    certificate_key
+                )))
+            }
+        }
+    };
+    Ok(CertifiedKey { certs, key })
+}
+
+#[cfg(all(test, feature = "rustls-pemfile"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("flower-cert-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_pem = cert.serialize_pem().unwrap();
+        let key_pem = cert.serialize_private_key_pem();
+        fs::write(path("cert.pem"), &cert_pem).unwrap();
+        fs::write(path("key.pem"), &key_pem).unwrap();
+        fs::write(path("combined.pem"), format!("{}{}", cert_pem, key_pem)).unwrap();
+        fs::write(path("cert.der"), cert.serialize_der().unwrap()).unwrap();
+        fs::write(path("key.der"), cert.serialize_private_key_der()).unwrap();
+        fs::write(path("invalid.pem"), "invalid").unwrap();
+
+        let loaded = load(&path("cert.pem"), &path("key.pem"), "").unwrap();
+        assert_eq!(loaded.certs.len(), 1);
+        let loaded = load(&path("cert.der"), &path("key.der"), "").unwrap();
+        assert_eq!(loaded.certs.len(), 1);
+        let loaded = load(&path("combined.pem"), "", "").unwrap();
+        assert_eq!(loaded.certs.len(), 1);
+        assert_eq!(loaded.key, cert.serialize_private_key_der());
+
+        let err = load(&path("cert.pem"), "", "").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = load(&path("cert.pem"), &path("invalid.pem"), "").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = load(&path("invalid.pem"), &path("key.pem"), "").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = load(&path("cert.pem"), &path("missing.pem"), "").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    fn test_load_pkcs12() {
+        use openssl::{pkcs12::Pkcs12, pkey::PKey, x509::X509};
+
+        let dir = std::env::temp_dir().join(format!("flower-p12-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let x509 = X509::from_der(&cert.serialize_der().unwrap()).unwrap();
+        let pkey = PKey::private_key_from_der(&cert.serialize_private_key_der()).unwrap();
+        let p12 = Pkcs12::builder()
+            .name("localhost")
+            .pkey(&pkey)
+            .cert(&x509)
+            .build2("secret")
+            .unwrap();
+        fs::write(path("cert.p12"), p12.to_der().unwrap()).unwrap();
+
+        let loaded = load(&path("cert.p12"), "", "secret").unwrap();
+        assert_eq!(loaded.certs, vec![x509.to_der().unwrap()]);
+        let err = load(&path("cert.p12"), "", "wrong").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
-use std::net::{Ipv4Addr, Ipv6Addr};
-use std::process::Command;
+use std::io::Write;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::{Command, Stdio};
 
 use anyhow::Result;
 
@@ -244,3 +245,58 @@ pub fn set_ipv6_forwarding(val: bool) -> Result<()> {
         .expect("failed to execute command");
     Ok(())
 }
+
+// The pf anchor of the rules redirecting to the listener on `port`, under the
+// com.apple anchors the default pf.conf evaluates.
+fn pf_anchor(port: u16) -> String {
+    format!("com.apple/flower.{}", port)
+}
+
+/// Redirects the TCP connections arriving on `interface` for other hosts to
+/// `addr` with a pf `rdr` rule, or to the address of the interface if `addr`
+/// is unspecified, and enables pf.
+pub fn add_pf_redirect_rules(interface: &str, addr: SocketAddr) -> Result<()> {
+    let (af, target) = match addr {
+        SocketAddr::V4(a) if a.ip().is_unspecified() => ("inet", format!("({})", interface)),
+        SocketAddr::V4(a) => ("inet", a.ip().to_string()),
+        SocketAddr::V6(a) if a.ip().is_unspecified() => ("inet6", format!("({})", interface)),
+        SocketAddr::V6(a) => ("inet6", a.ip().to_string()),
+    };
+    let rules = format!(
+        "rdr pass on {} {} proto tcp from any to ! ({}) -> {} port {}\n",
+        interface,
+        af,
+        interface,
+        target,
+        addr.port()
+    );
+    let mut child = Command::new("pfctl")
+        .arg("-a")
+        .arg(pf_anchor(addr.port()))
+        .arg("-f")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("failed to execute command");
+    child.stdin.take().unwrap().write_all(rules.as_bytes())?;
+    child.wait().expect("failed to execute command");
+    // Fails if pf is enabled already.
+    Command::new("pfctl")
+        .arg("-e")
+        .output()
+        .expect("failed to execute command");
+    Ok(())
+}
+
+/// Removes the rules added by `add_pf_redirect_rules` for the listener on
+/// `port`.
+pub fn delete_pf_redirect_rules(port: u16) -> Result<()> {
+    Command::new("pfctl")
+        .arg("-a")
+        .arg(pf_anchor(port))
+        .arg("-F")
+        .arg("all")
+        .output()
+        .expect("failed to execute command");
+    Ok(())
+}
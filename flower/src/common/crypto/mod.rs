@@ -0,0 +1,555 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use hkdf::Hkdf;
+use md5::{Digest, Md5};
+use sha1::Sha1;
+
+mod stream;
+
+pub use stream::{AeadStream, KeySchedule, MAX_PAYLOAD_SIZE, READ_BUFFER_SIZE};
+
+/// An AEAD cipher, parsed from its name in configs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Cipher {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+    XChaCha20Poly1305,
+}
+
+impl FromStr for Cipher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "aes-128-gcm" => Ok(Cipher::Aes128Gcm),
+            "aes-256-gcm" => Ok(Cipher::Aes256Gcm),
+            "chacha20-poly1305" | "chacha20-ietf-poly1305" => Ok(Cipher::ChaCha20Poly1305),
+            "xchacha20-poly1305" | "xchacha20-ietf-poly1305" => Ok(Cipher::XChaCha20Poly1305),
+            _ => Err(anyhow!("unsupported cipher: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Cipher::Aes128Gcm => "aes-128-gcm",
+            Cipher::Aes256Gcm => "aes-256-gcm",
+            Cipher::ChaCha20Poly1305 => "chacha20-ietf-poly1305",
+            Cipher::XChaCha20Poly1305 => "xchacha20-ietf-poly1305",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Cipher {
+    pub fn key_len(&self) -> usize {
+        match self {
+            Cipher::Aes128Gcm => 16,
+            _ => 32,
+        }
+    }
+
+    pub fn nonce_len(&self) -> usize {
+        match self {
+            Cipher::XChaCha20Poly1305 => 24,
+            _ => 12,
+        }
+    }
+
+    pub fn tag_len(&self) -> usize {
+        // All AEAD ciphers we support use 128-bit tags.
+        16
+    }
+
+    /// Prepares `key` once for sealing and opening any number of messages.
+    pub fn key(&self, key: &[u8]) -> Result<Key> {
+        if key.len() != self.key_len() {
+            return Err(anyhow!("invalid {} key length {}", self, key.len()));
+        }
+        let inner = match self {
+            Cipher::XChaCha20Poly1305 => KeyInner::XChaCha(xchacha::Key::new(key)),
+            _ => KeyInner::Backend(backend::Key::new(self, key)?),
+        };
+        Ok(Key {
+            cipher: *self,
+            inner,
+        })
+    }
+
+    /// Encrypts `in_out` in place and appends the tag.
+    pub fn seal<InOut>(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        in_out: &mut InOut,
+    ) -> Result<()>
+    where
+        InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>,
+    {
+        self.key(key)?.seal(nonce, aad, in_out)
+    }
+
+    /// Decrypts a ciphertext followed by its tag in place, returns the
+    /// plaintext at the beginning of `in_out`.
+    pub fn open<'a>(
+        &self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        in_out: &'a mut [u8],
+    ) -> Result<&'a mut [u8]> {
+        self.key(key)?.open(nonce, aad, in_out)
+    }
+
+    /// Returns an encryptor sealing with the nonces of `nonce` in turn.
+    pub fn encryptor<N: NonceSequence>(&self, key: &[u8], nonce: N) -> Result<AeadEncryptor<N>> {
+        Ok(AeadEncryptor {
+            key: self.key(key)?,
+            nonce,
+        })
+    }
+
+    /// Returns a decryptor opening with the nonces of `nonce` in turn.
+    pub fn decryptor<N: NonceSequence>(&self, key: &[u8], nonce: N) -> Result<AeadDecryptor<N>> {
+        Ok(AeadDecryptor {
+            key: self.key(key)?,
+            nonce,
+        })
+    }
+}
+
+enum KeyInner {
+    XChaCha(xchacha::Key),
+    Backend(backend::Key),
+}
+
+/// A key of a cipher, ready to seal and open with.
+pub struct Key {
+    cipher: Cipher,
+    inner: KeyInner,
+}
+
+impl Key {
+    fn check(&self, nonce: &[u8]) -> Result<()> {
+        if nonce.len() != self.cipher.nonce_len() {
+            return Err(anyhow!(
+                "invalid {} nonce length {}",
+                self.cipher,
+                nonce.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encrypts `in_out` in place and appends the tag.
+    pub fn seal<InOut>(&self, nonce: &[u8], aad: &[u8], in_out: &mut InOut) -> Result<()>
+    where
+        InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>,
+    {
+        self.check(nonce)?;
+        match &self.inner {
+            KeyInner::XChaCha(key) => key.seal(nonce, aad, in_out),
+            KeyInner::Backend(key) => key.seal(nonce, aad, in_out),
+        }
+        .map_err(|e| anyhow!("encrypt failed: {}", e))
+    }
+
+    /// Decrypts a ciphertext followed by its tag in place, returns the
+    /// plaintext at the beginning of `in_out`.
+    pub fn open<'a>(&self, nonce: &[u8], aad: &[u8], in_out: &'a mut [u8]) -> Result<&'a mut [u8]> {
+        self.check(nonce)?;
+        if in_out.len() < self.cipher.tag_len() {
+            return Err(anyhow!("decrypt failed: short ciphertext"));
+        }
+        match &self.inner {
+            KeyInner::XChaCha(key) => key.open(nonce, aad, in_out),
+            KeyInner::Backend(key) => key.open(nonce, aad, in_out),
+        }
+        .map_err(|e| anyhow!("decrypt failed: {}", e))
+    }
+}
+
+pub trait Encryptor: Sync + Send + Unpin {
+    fn encrypt<InOut>(&mut self, in_out: &mut InOut) -> Result<()>
+    where
+        InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>;
+}
+
+pub trait Decryptor: Sync + Send + Unpin {
+    fn decrypt<InOut>(&mut self, in_out: &mut InOut) -> Result<()>
+    where
+        InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>;
+}
+
+pub trait NonceSequence: Sync + Send + Unpin {
+    fn advance(&mut self) -> Result<Vec<u8>>;
+}
+
+impl<N: NonceSequence + ?Sized> NonceSequence for Box<N> {
+    fn advance(&mut self) -> Result<Vec<u8>> {
+        (**self).advance()
+    }
+}
+
+pub struct AeadEncryptor<N> {
+    key: Key,
+    nonce: N,
+}
+
+impl<N> Encryptor for AeadEncryptor<N>
+where
+    N: NonceSequence,
+{
+    fn encrypt<InOut>(&mut self, in_out: &mut InOut) -> Result<()>
+    where
+        InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>,
+    {
+        let nonce = self
+            .nonce
+            .advance()
+            .map_err(|e| anyhow!("encrypt failed: {}", e))?;
+        self.key.seal(&nonce, &[], in_out)
+    }
+}
+
+pub struct AeadDecryptor<N> {
+    key: Key,
+    nonce: N,
+}
+
+impl<N> Decryptor for AeadDecryptor<N>
+where
+    N: NonceSequence,
+{
+    // The plaintext is left at the beginning of `in_out`, followed by the
+    // length of a tag of garbage.
+    fn decrypt<InOut>(&mut self, in_out: &mut InOut) -> Result<()>
+    where
+        InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>,
+    {
+        let nonce = self
+            .nonce
+            .advance()
+            .map_err(|e| anyhow!("decrypt failed: {}", e))?;
+        self.key.open(&nonce, &[], in_out.as_mut())?;
+        Ok(())
+    }
+}
+
+/// HKDF with SHA-1, as used to derive the subkeys of Shadowsocks.
+pub fn hkdf_sha1(key: &[u8], salt: &[u8], info: &[u8], size: usize) -> Result<Vec<u8>> {
+    let (_, h) = Hkdf::<Sha1>::extract(Some(salt), key);
+    let mut okm = vec![0u8; size];
+    h.expand(info, &mut okm)
+        .map_err(|_| anyhow!("hkdf expand failed"))?;
+    Ok(okm)
+}
+
+/// OpenSSL's EVP_BytesToKey with MD5, no salt and a single iteration, as
+/// used to derive keys from passwords.
+pub fn evp_bytes_to_key(pass: &[u8], size: usize) -> Vec<u8> {
+    let mut key = Vec::with_capacity(size + 16);
+    let mut sum = Md5::digest(pass).to_vec();
+    key.extend_from_slice(&sum);
+    while key.len() < size {
+        sum = Md5::digest(&[&sum, pass].concat()).to_vec();
+        key.extend_from_slice(&sum);
+    }
+    key.truncate(size);
+    key
+}
+
+// Neither backend supports XChaCha20-Poly1305.
+mod xchacha {
+    use chacha20poly1305::aead::{AeadInPlace, NewAead};
+    use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+
+    use super::*;
+
+    pub struct Key(XChaCha20Poly1305);
+
+    impl Key {
+        pub fn new(key: &[u8]) -> Self {
+            Key(XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(
+                key,
+            )))
+        }
+
+        pub fn seal<InOut>(&self, nonce: &[u8], aad: &[u8], in_out: &mut InOut) -> Result<()>
+        where
+            InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>,
+        {
+            let tag = self
+                .0
+                .encrypt_in_place_detached(XNonce::from_slice(nonce), aad, in_out.as_mut())
+                .map_err(|e| anyhow!("{}", e))?;
+            in_out.extend(tag.as_slice());
+            Ok(())
+        }
+
+        pub fn open<'a>(
+            &self,
+            nonce: &[u8],
+            aad: &[u8],
+            in_out: &'a mut [u8],
+        ) -> Result<&'a mut [u8]> {
+            let (data, tag) = in_out.split_at_mut(in_out.len() - 16);
+            self.0
+                .decrypt_in_place_detached(
+                    XNonce::from_slice(nonce),
+                    aad,
+                    data,
+                    Tag::from_slice(tag),
+                )
+                .map_err(|e| anyhow!("{}", e))?;
+            Ok(data)
+        }
+    }
+}
+
+#[cfg(feature = "openssl-aead")]
+mod backend {
+    use openssl::symm;
+
+    use super::*;
+
+    // OpenSSL has no key object outliving a single operation.
+    pub struct Key {
+        algorithm: symm::Cipher,
+        key: Vec<u8>,
+        tag_len: usize,
+    }
+
+    impl Key {
+        pub fn new(cipher: &Cipher, key: &[u8]) -> Result<Self> {
+            let algorithm = match cipher {
+                Cipher::Aes128Gcm => symm::Cipher::aes_128_gcm(),
+                Cipher::Aes256Gcm => symm::Cipher::aes_256_gcm(),
+                Cipher::ChaCha20Poly1305 => symm::Cipher::chacha20_poly1305(),
+                Cipher::XChaCha20Poly1305 => unreachable!(),
+            };
+            Ok(Key {
+                algorithm,
+                key: key.to_vec(),
+                tag_len: cipher.tag_len(),
+            })
+        }
+
+        pub fn seal<InOut>(&self, nonce: &[u8], aad: &[u8], in_out: &mut InOut) -> Result<()>
+        where
+            InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>,
+        {
+            let mut tag = vec![0u8; self.tag_len];
+            // TODO in-place?
+            let ciphertext = symm::encrypt_aead(
+                self.algorithm,
+                &self.key,
+                Some(nonce),
+                aad,
+                in_out.as_ref(),
+                &mut tag,
+            )?;
+            (&mut in_out.as_mut()[..ciphertext.len()]).copy_from_slice(&ciphertext);
+            in_out.extend(&tag);
+            Ok(())
+        }
+
+        pub fn open<'a>(
+            &self,
+            nonce: &[u8],
+            aad: &[u8],
+            in_out: &'a mut [u8],
+        ) -> Result<&'a mut [u8]> {
+            let (data, tag) = in_out.split_at_mut(in_out.len() - self.tag_len);
+            // TODO in-place?
+            let plaintext =
+                symm::decrypt_aead(self.algorithm, &self.key, Some(nonce), aad, data, tag)?;
+            data.copy_from_slice(&plaintext);
+            Ok(data)
+        }
+    }
+}
+
+#[cfg(feature = "ring-aead")]
+mod backend {
+    use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+
+    use super::*;
+
+    pub struct Key(LessSafeKey);
+
+    impl Key {
+        pub fn new(cipher: &Cipher, key: &[u8]) -> Result<Self> {
+            let algorithm = match cipher {
+                Cipher::Aes128Gcm => &aead::AES_128_GCM,
+                Cipher::Aes256Gcm => &aead::AES_256_GCM,
+                Cipher::ChaCha20Poly1305 => &aead::CHACHA20_POLY1305,
+                Cipher::XChaCha20Poly1305 => unreachable!(),
+            };
+            let key = UnboundKey::new(algorithm, key).map_err(|e| anyhow!("{}", e))?;
+            Ok(Key(LessSafeKey::new(key)))
+        }
+
+        pub fn seal<InOut>(&self, nonce: &[u8], aad: &[u8], in_out: &mut InOut) -> Result<()>
+        where
+            InOut: AsRef<[u8]> + AsMut<[u8]> + for<'in_out> Extend<&'in_out u8>,
+        {
+            let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|e| anyhow!("{}", e))?;
+            self.0
+                .seal_in_place_append_tag(nonce, Aad::from(aad), in_out)
+                .map_err(|e| anyhow!("{}", e))
+        }
+
+        pub fn open<'a>(
+            &self,
+            nonce: &[u8],
+            aad: &[u8],
+            in_out: &'a mut [u8],
+        ) -> Result<&'a mut [u8]> {
+            let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|e| anyhow!("{}", e))?;
+            self.0
+                .open_in_place(nonce, Aad::from(aad), in_out)
+                .map_err(|e| anyhow!("{}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_cipher_vectors() {
+        let aad = unhex("50515253c0c1c2c3c4c5c6c7");
+        let vectors = [
+            (
+                "aes-128-gcm",
+                "d99661f5d06278e9804964d4e635eee7c7422641c5eb8f2dea7b5590d2",
+            ),
+            (
+                "aes-256-gcm",
+                "91af6d2a5cc99e1d1783bd1d1e168722058890ff3be76545feb35b6c8b",
+            ),
+            (
+                "chacha20-ietf-poly1305",
+                "15e53e56f1351b8246dd7715e50decbe4b35d8bc3bc34b7d0c1f92dc3b",
+            ),
+            (
+                "xchacha20-ietf-poly1305",
+                "b9691f9834dcd42d94601bb7d758255387536eceb530b7b26b3b3600f6",
+            ),
+        ];
+        for (name, ciphertext) in vectors {
+            let cipher = name.parse::<Cipher>().unwrap();
+            let key: Vec<u8> = (0x80..0x80 + cipher.key_len() as u8).collect();
+            let nonce: Vec<u8> = (0x40..0x40 + cipher.nonce_len() as u8).collect();
+
+            let mut buf = b"Hello, world!".to_vec();
+            cipher.seal(&key, &nonce, &aad, &mut buf).unwrap();
+            assert_eq!(buf, unhex(ciphertext), "{}", name);
+
+            let plaintext = cipher.open(&key, &nonce, &aad, &mut buf).unwrap();
+            assert_eq!(plaintext, b"Hello, world!");
+
+            let mut buf = unhex(ciphertext);
+            buf[0] ^= 1;
+            assert!(cipher.open(&key, &nonce, &aad, &mut buf).is_err());
+            assert!(cipher.open(&key, &nonce, &aad, &mut [0u8; 8]).is_err());
+            assert!(cipher
+                .seal(&key[1..], &nonce, &aad, &mut Vec::new())
+                .is_err());
+        }
+        assert!("rc4-md5".parse::<Cipher>().is_err());
+    }
+
+    #[test]
+    fn test_xchacha20_poly1305() {
+        // draft-irtf-cfrg-xchacha-03, A.3.1.
+        let key: Vec<u8> = (0x80..0xa0).collect();
+        let nonce: Vec<u8> = (0x40..0x58).collect();
+        let aad = unhex("50515253c0c1c2c3c4c5c6c7");
+        let mut buf = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.".to_vec();
+        Cipher::XChaCha20Poly1305
+            .seal(&key, &nonce, &aad, &mut buf)
+            .unwrap();
+        assert_eq!(
+            buf,
+            unhex(concat!(
+                "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb",
+                "731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452",
+                "2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9",
+                "21f9664c97637da9768812f615c68b13b52ec0875924c1c7987947deafd8780a",
+                "cf49",
+            ))
+        );
+    }
+
+    #[test]
+    fn test_aead_enc_dec() {
+        struct CountingNonceSequence(Vec<u8>);
+
+        impl NonceSequence for CountingNonceSequence {
+            fn advance(&mut self) -> Result<Vec<u8>> {
+                self.0[0] = self.0[0].wrapping_add(1);
+                Ok(self.0.clone())
+            }
+        }
+        let plaintext = b"Hello, world!";
+        let cipher = Cipher::ChaCha20Poly1305;
+        let key = vec![0u8; cipher.key_len()];
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(plaintext);
+
+        let nonce = CountingNonceSequence(vec![0; cipher.nonce_len()]);
+        let mut enc = cipher.encryptor(&key, nonce).unwrap();
+        enc.encrypt(&mut buf).unwrap();
+
+        let dec_nonce = CountingNonceSequence(vec![0; cipher.nonce_len()]);
+        let mut dec = cipher.decryptor(&key, dec_nonce).unwrap();
+        dec.decrypt(&mut buf).unwrap();
+
+        assert_eq!(&buf[..plaintext.len()], plaintext);
+    }
+
+    #[test]
+    fn test_hkdf_sha1() {
+        // RFC 5869, test case 4.
+        let okm = hkdf_sha1(
+            &[0x0b; 11],
+            &unhex("000102030405060708090a0b0c"),
+            &unhex("f0f1f2f3f4f5f6f7f8f9"),
+            42,
+        )
+        .unwrap();
+        assert_eq!(
+            okm,
+            unhex(concat!(
+                "085a01ea1b10f36933068b56efa5ad81a4f14b822f5b091568a9cdd4f155fda2",
+                "c22e422478d305f3f896",
+            ))
+        );
+    }
+
+    #[test]
+    fn test_evp_bytes_to_key() {
+        let key = evp_bytes_to_key(b"foobar", 32);
+        assert_eq!(
+            key,
+            unhex("3858f62230ac3c915f300c664312c63f568378529614d22ddb49237d2f60bfdf")
+        );
+        assert_eq!(evp_bytes_to_key(b"foobar", 16), key[..16].to_vec());
+    }
+}
@@ -0,0 +1,402 @@
+use std::{cmp::min, io, pin::Pin};
+
+use anyhow::Result;
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{BufMut, BytesMut};
+use futures::{
+    ready,
+    task::{Context, Poll},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::io::{poll_read_buf, poll_write_buf};
+
+use super::{AeadDecryptor, AeadEncryptor, Cipher, Decryptor, Encryptor, NonceSequence};
+
+/// Maximum payload size of a chunk.
+pub const MAX_PAYLOAD_SIZE: usize = 0x3fff;
+
+/// Size of the ciphertext read ahead from the underlying stream, fits a
+/// full-size chunk along with its sealed length.
+pub const READ_BUFFER_SIZE: usize = 2 + 16 + MAX_PAYLOAD_SIZE + 16;
+
+/// Derives the subkeys of an `AeadStream`.
+pub trait KeySchedule: Sync + Send + Unpin {
+    /// Length of the random salt sent ahead of the first chunk of each
+    /// direction, 0 if the subkeys don't depend on a salt.
+    fn salt_len(&self) -> usize;
+
+    /// Derives the subkey and the nonce sequence sealing the chunks we send.
+    fn send_key(&self, salt: &[u8]) -> Result<(Vec<u8>, Box<dyn NonceSequence>)>;
+
+    /// Derives the subkey and the nonce sequence opening the chunks we
+    /// receive, the same as `send_key` by default.
+    fn recv_key(&self, salt: &[u8]) -> Result<(Vec<u8>, Box<dyn NonceSequence>)> {
+        self.send_key(salt)
+    }
+}
+
+enum ReadState {
+    WaitingSalt,
+    WaitingLength,
+    WaitingData(usize),
+    PendingData(usize),
+}
+
+enum WriteState {
+    WaitingChunk,
+    PendingChunk(usize),
+}
+
+/// Seals what is written to the inner stream into chunks of a sealed 2-byte
+/// payload length followed by the sealed payload, and opens what is read
+/// from it likewise.
+pub struct AeadStream<S> {
+    inner: S,
+    cipher: Cipher,
+    keys: Box<dyn KeySchedule>,
+    enc: Option<AeadEncryptor<Box<dyn NonceSequence>>>,
+    dec: Option<AeadDecryptor<Box<dyn NonceSequence>>>,
+    // Ciphertext read ahead from the inner stream.
+    recv_buf: BytesMut,
+    // The piece being opened, then the plaintext not yet returned.
+    read_buf: BytesMut,
+    // The sealed chunk being written.
+    write_buf: BytesMut,
+    read_state: ReadState,
+    write_state: WriteState,
+}
+
+impl<S> AeadStream<S> {
+    pub fn new<K: KeySchedule + 'static>(inner: S, cipher: Cipher, keys: K) -> Self {
+        AeadStream {
+            inner,
+            cipher,
+            keys: Box::new(keys),
+            enc: None,
+            dec: None,
+            recv_buf: BytesMut::new(),
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+            read_state: ReadState::WaitingSalt,
+            write_state: WriteState::WaitingChunk,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+fn early_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "early eof")
+}
+
+fn crypto_err() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "crypto error")
+}
+
+fn invalid_length(n: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid payload length {}", n),
+    )
+}
+
+impl<S> AeadStream<S>
+where
+    S: AsyncRead + Unpin,
+{
+    // Read exactly `size` bytes into `read_buf`, the inner stream is read in
+    // pieces of up to `READ_BUFFER_SIZE` bytes.
+    fn poll_read_exact(&mut self, cx: &mut Context, size: usize) -> Poll<io::Result<()>> {
+        while self.recv_buf.len() < size {
+            let want = READ_BUFFER_SIZE.max(size) - self.recv_buf.len();
+            self.recv_buf.reserve(want);
+            let n = ready!(poll_read_buf(
+                Pin::new(&mut self.inner),
+                cx,
+                &mut self.recv_buf
+            ))?;
+            if n == 0 {
+                return Poll::Ready(Err(early_eof()));
+            }
+        }
+        self.read_buf = self.recv_buf.split_to(size);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncRead for AeadStream<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let me = &mut *self;
+        loop {
+            match me.read_state {
+                ReadState::WaitingSalt => {
+                    let salt_len = me.keys.salt_len();
+                    if salt_len > 0 {
+                        ready!(me.poll_read_exact(cx, salt_len))?;
+                    }
+                    let (key, nonce) = me
+                        .keys
+                        .recv_key(&me.read_buf[..salt_len])
+                        .map_err(|_| crypto_err())?;
+                    let dec = me.cipher.decryptor(&key, nonce).map_err(|_| crypto_err())?;
+                    me.dec.replace(dec);
+                    me.read_state = ReadState::WaitingLength;
+                }
+                ReadState::WaitingLength => {
+                    // EOF between chunks is a clean EOF.
+                    let read_size = 2 + me.cipher.tag_len();
+                    if let Err(e) = ready!(me.poll_read_exact(cx, read_size)) {
+                        if e.kind() == io::ErrorKind::UnexpectedEof && me.recv_buf.is_empty() {
+                            return Poll::Ready(Ok(()));
+                        } else {
+                            return Poll::Ready(Err(e));
+                        }
+                    }
+                    let dec = me.dec.as_mut().expect("uninitialized cipher");
+                    dec.decrypt(&mut me.read_buf).map_err(|_| crypto_err())?;
+                    let payload_len = BigEndian::read_u16(&me.read_buf) as usize;
+                    if payload_len > MAX_PAYLOAD_SIZE {
+                        return Poll::Ready(Err(invalid_length(payload_len)));
+                    }
+                    me.read_state = ReadState::WaitingData(payload_len);
+                }
+                ReadState::WaitingData(n) => {
+                    let read_size = n + me.cipher.tag_len();
+                    ready!(me.poll_read_exact(cx, read_size))?;
+                    let dec = me.dec.as_mut().expect("uninitialized cipher");
+                    dec.decrypt(&mut me.read_buf).map_err(|_| crypto_err())?;
+                    me.read_state = ReadState::PendingData(n);
+                }
+                ReadState::PendingData(n) => {
+                    // The chunk may not fit in `buf`, the rest is returned
+                    // by the next polls.
+                    let to_read = min(buf.remaining(), n);
+                    let payload = me.read_buf.split_to(to_read);
+                    buf.put_slice(&payload);
+                    if to_read < n {
+                        me.read_state = ReadState::PendingData(n - to_read);
+                    } else {
+                        me.read_state = ReadState::WaitingLength;
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for AeadStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // A chunk without payload is read as EOF by the peer, and the salt
+        // can wait for the first payload.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let me = &mut *self;
+        loop {
+            match me.write_state {
+                WriteState::WaitingChunk => {
+                    if me.enc.is_none() {
+                        // The salt is sent along with the first chunk.
+                        let salt_len = me.keys.salt_len();
+                        me.write_buf.resize(salt_len, 0);
+                        StdRng::from_entropy().fill(&mut me.write_buf[..]);
+                        let (key, nonce) =
+                            me.keys.send_key(&me.write_buf).map_err(|_| crypto_err())?;
+                        let enc = me.cipher.encryptor(&key, nonce).map_err(|_| crypto_err())?;
+                        me.enc.replace(enc);
+                    }
+                    let enc = me.enc.as_mut().expect("uninitialized cipher");
+                    let consume_len = min(buf.len(), MAX_PAYLOAD_SIZE);
+                    let tag_len = me.cipher.tag_len();
+
+                    // seal payload length
+                    me.write_buf.reserve(2 + tag_len + consume_len + tag_len);
+                    let mut piece1 = me.write_buf.split_off(me.write_buf.len());
+                    piece1.put_u16(consume_len as u16);
+                    enc.encrypt(&mut piece1).map_err(|_| crypto_err())?;
+
+                    // seal payload
+                    let mut piece2 = piece1.split_off(piece1.len());
+                    piece2.put_slice(&buf[..consume_len]);
+                    enc.encrypt(&mut piece2).map_err(|_| crypto_err())?;
+
+                    // merge salt, length and payload pieces
+                    piece1.unsplit(piece2);
+                    me.write_buf.unsplit(piece1);
+                    me.write_state = WriteState::PendingChunk(consume_len);
+                }
+                WriteState::PendingChunk(consumed) => {
+                    // There would be trouble if the caller change the buf
+                    // upon pending, but that's not a usual use case.
+                    while !me.write_buf.is_empty() {
+                        let nw = ready!(poll_write_buf(
+                            Pin::new(&mut me.inner),
+                            cx,
+                            &mut me.write_buf
+                        ))?;
+                        if nw == 0 {
+                            return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                        }
+                    }
+                    me.write_state = WriteState::WaitingChunk;
+                    return Poll::Ready(Ok(consumed));
+                }
+            }
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    struct CountingNonceSequence(Vec<u8>);
+
+    impl NonceSequence for CountingNonceSequence {
+        fn advance(&mut self) -> Result<Vec<u8>> {
+            self.0[0] = self.0[0].wrapping_add(1);
+            Ok(self.0.clone())
+        }
+    }
+
+    // Fixed keys, or keys from the salt if `salted`.
+    struct TestKeys(Cipher, bool);
+
+    impl KeySchedule for TestKeys {
+        fn salt_len(&self) -> usize {
+            if self.1 {
+                self.0.key_len()
+            } else {
+                0
+            }
+        }
+
+        fn send_key(&self, salt: &[u8]) -> Result<(Vec<u8>, Box<dyn NonceSequence>)> {
+            let mut key = vec![7u8; self.0.key_len()];
+            for (k, s) in key.iter_mut().zip(salt) {
+                *k ^= s;
+            }
+            let nonce = CountingNonceSequence(vec![0; self.0.nonce_len()]);
+            Ok((key, Box::new(nonce)))
+        }
+    }
+
+    fn new_pair(
+        cipher: Cipher,
+        salted: bool,
+        max_buf_size: usize,
+    ) -> (
+        AeadStream<tokio::io::DuplexStream>,
+        AeadStream<tokio::io::DuplexStream>,
+    ) {
+        let (a, b) = tokio::io::duplex(max_buf_size);
+        (
+            AeadStream::new(a, cipher, TestKeys(cipher, salted)),
+            AeadStream::new(b, cipher, TestKeys(cipher, salted)),
+        )
+    }
+
+    #[test]
+    fn test_aead_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            for salted in [false, true] {
+                let (mut a, mut b) = new_pair(Cipher::ChaCha20Poly1305, salted, 1024);
+                a.write_all(b"hello").await.unwrap();
+                b.write_all(b"world").await.unwrap();
+                let mut buf = [0u8; 5];
+                b.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"hello");
+                a.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"world");
+
+                // A clean EOF between chunks.
+                a.shutdown().await.unwrap();
+                drop(a);
+                assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+            }
+        });
+    }
+
+    #[test]
+    fn test_aead_stream_partial_reads() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut a, mut b) = new_pair(Cipher::Aes128Gcm, true, READ_BUFFER_SIZE);
+            // More than a chunk, read back through a buffer smaller than a
+            // chunk.
+            let data: Vec<u8> = (0..MAX_PAYLOAD_SIZE * 2 + 5).map(|i| i as u8).collect();
+            let data2 = data.clone();
+            let writer = tokio::spawn(async move {
+                a.write_all(&data2).await.unwrap();
+                a
+            });
+            let mut received = Vec::new();
+            let mut buf = [0u8; 1000];
+            while received.len() < data.len() {
+                let n = b.read(&mut buf).await.unwrap();
+                assert!(n > 0);
+                received.extend_from_slice(&buf[..n]);
+            }
+            assert_eq!(received, data);
+            writer.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_aead_stream_oversized_length() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let cipher = Cipher::Aes128Gcm;
+            let (key, nonce) = TestKeys(cipher, false).send_key(&[]).unwrap();
+            let mut enc = cipher.encryptor(&key, nonce).unwrap();
+            let mut length = BytesMut::from(&[0x40u8, 0x00][..]);
+            enc.encrypt(&mut length).unwrap();
+
+            let (mut client, server) = tokio::io::duplex(1024);
+            let mut server = AeadStream::new(server, cipher, TestKeys(cipher, false));
+            client.write_all(&length).await.unwrap();
+            let mut buf = [0u8; 1];
+            let err = server.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        });
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use rand::{thread_rng, Rng};
+
+/// Placeholders recognized in header values, they are substituted each time a
+/// request is built so that consecutive requests don't carry identical headers.
+///
+/// `{random}`    16 random hex characters
+/// `{uuid}`      a random version 4 UUID
+/// `{timestamp}` current unix timestamp in seconds
+const PLACEHOLDERS: [&str; 3] = ["{random}", "{uuid}", "{timestamp}"];
+
+fn is_token_char(c: u8) -> bool {
+    // RFC 7230 tchar
+    c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c)
+}
+
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.bytes().all(is_token_char) {
+        return Err(anyhow!("invalid header name [{}]", name));
+    }
+    Ok(())
+}
+
+pub fn validate_value(name: &str, value: &str) -> Result<()> {
+    if value
+        .bytes()
+        .any(|c| c == b'\r' || c == b'\n' || c == 0 || c == 0x7f)
+    {
+        return Err(anyhow!("invalid value for header [{}]", name));
+    }
+    Ok(())
+}
+
+/// Validates all header names and values of a header template.
+pub fn validate(headers: &HashMap<String, String>) -> Result<()> {
+    for (k, v) in headers.iter() {
+        validate_name(k)?;
+        validate_value(k, v)?;
+    }
+    Ok(())
+}
+
+fn random_hex(n: usize) -> String {
+    let mut rng = thread_rng();
+    (0..n)
+        .map(|_| format!("{:x}", rng.gen_range(0..16u8)))
+        .collect()
+}
+
+fn random_uuid() -> String {
+    let mut b: [u8; 16] = thread_rng().gen();
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let h: String = b.iter().map(|x| format!("{:02x}", x)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &h[0..8],
+        &h[8..12],
+        &h[12..16],
+        &h[16..20],
+        &h[20..32]
+    )
+}
+
+/// Expands the placeholders in a header value, values without placeholders
+/// are returned verbatim.
+pub fn render_value(value: &str) -> String {
+    if !PLACEHOLDERS.iter().any(|p| value.contains(p)) {
+        return value.to_string();
+    }
+    let mut v = value.to_string();
+    while v.contains("{random}") {
+        v = v.replacen("{random}", &random_hex(16), 1);
+    }
+    while v.contains("{uuid}") {
+        v = v.replacen("{uuid}", &random_uuid(), 1);
+    }
+    if v.contains("{timestamp}") {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        v = v.replace("{timestamp}", &ts.to_string());
+    }
+    v
+}
+
+/// Renders a header template, the returned headers are sorted by name for a
+/// deterministic request layout.
+pub fn render(headers: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut rendered: Vec<(String, String)> = headers
+        .iter()
+        .map(|(k, v)| (k.to_owned(), render_value(v)))
+        .collect();
+    rendered.sort_by(|a, b| a.0.cmp(&b.0));
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_name("User-Agent").is_ok());
+        assert!(validate_name("X-Custom_1").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("Bad Name").is_err());
+        assert!(validate_name("Bad:Name").is_err());
+        assert!(validate_value("Cookie", "a=1; b=2").is_ok());
+        assert!(validate_value("Cookie", "a=1\r\nX-Injected: 1").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render_value("Mozilla/5.0"), "Mozilla/5.0");
+
+        let v = render_value("id={random}");
+        assert_eq!(v.len(), 3 + 16);
+        assert!(v[3..].bytes().all(|c| c.is_ascii_hexdigit()));
+
+        let v = render_value("{uuid}");
+        assert_eq!(v.len(), 36);
+        assert_eq!(&v[14..15], "4");
+
+        let v = render_value("t={timestamp}");
+        assert!(v[2..].parse::<u64>().unwrap() > 0);
+    }
+}
@@ -1,10 +1,13 @@
+pub mod buffer;
+#[cfg(any(feature = "rustls-pemfile", feature = "openssl"))]
+pub mod cert;
+#[cfg(any(feature = "ring-aead", feature = "openssl-aead"))]
 pub mod crypto;
+pub mod header;
 pub mod mutex;
 pub mod net;
-pub mod resolver;
-pub mod sniff;
 pub mod process;
-
+pub mod sniff;
 
 #[cfg(target_os = "macos")]
 pub mod cmd_macos;
@@ -1,6 +1,12 @@
-use std::net::{SocketAddr, SocketAddrV6};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
+use socket2::{Socket, TcpKeepalive};
 
 pub fn parse_bind_addr(bind: &str) -> Result<SocketAddr> {
     let mut split = bind.split('%');
@@ -21,3 +27,385 @@ pub fn parse_bind_addr(bind: &str) -> Result<SocketAddr> {
         None => Ok(SocketAddr::new(ip_addr.parse()?, 0)),
     }
 }
+
+/// Sets `TCP_NODELAY` on a TCP socket.
+pub fn set_nodelay(socket: &Socket, nodelay: bool) -> io::Result<()> {
+    socket.set_nodelay(nodelay)
+}
+
+/// Enables `SO_KEEPALIVE` on a TCP socket, probes are sent once the
+/// connection has been idle for `secs` seconds, or the system default if
+/// it's 0.
+pub fn set_keepalive(socket: &Socket, secs: u64) -> io::Result<()> {
+    if secs == 0 {
+        socket.set_keepalive(true)
+    } else {
+        socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(Duration::from_secs(secs)))
+    }
+}
+
+/// Protects an outbound socket from being routed back into the VPN on
+/// Android, before it's bound or connected.
+///
+/// The socket is protected by the service at `SOCKET_PROTECT_PATH`, a Unix
+/// domain socket, and by the one at `SOCKET_PROTECT_SERVER`, a TCP endpoint,
+/// whichever are set. A connection is made to the service for each socket,
+/// and the fd is sent as a big-endian int32. The service calls
+/// `VpnService.protect(fd)` and replies with an int32, 0 on success. Flower
+/// runs in the process of the VPN service, so the fd number is sent as is
+/// rather than passed with `SCM_RIGHTS`.
+#[cfg(target_os = "android")]
+pub async fn protect_socket(fd: std::os::unix::io::RawFd) -> io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpStream, UnixStream};
+
+    let failed = || {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to protect outbound socket {}", fd),
+        )
+    };
+    if let Some(addr) = &*crate::option::SOCKET_PROTECT_SERVER {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_i32(fd).await?;
+        if stream.read_i32().await? != 0 {
+            return Err(failed());
+        }
+    }
+    if !crate::option::SOCKET_PROTECT_PATH.is_empty() {
+        let mut stream = UnixStream::connect(&*crate::option::SOCKET_PROTECT_PATH).await?;
+        stream.write_i32(fd).await?;
+        if stream.read_i32().await? != 0 {
+            return Err(failed());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn setsockopt(
+    socket: &Socket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn is_ipv6(socket: &Socket) -> io::Result<bool> {
+    Ok(socket.local_addr()?.as_socket_ipv6().is_some())
+}
+
+/// Sets `IP_TRANSPARENT`, or `IPV6_TRANSPARENT` on an IPv6 socket, so that
+/// the socket accepts traffic redirected by TPROXY and may bind to non-local
+/// addresses. Requires `CAP_NET_ADMIN`.
+#[cfg(target_os = "linux")]
+pub fn set_ip_transparent(socket: &Socket) -> io::Result<()> {
+    if is_ipv6(socket)? {
+        setsockopt(socket, libc::SOL_IPV6, libc::IPV6_TRANSPARENT, 1)
+    } else {
+        setsockopt(socket, libc::SOL_IP, libc::IP_TRANSPARENT, 1)
+    }
+}
+
+/// Sets `IP_RECVORIGDSTADDR`, or `IPV6_RECVORIGDSTADDR` on an IPv6 socket, so
+/// that the original destination of each datagram received is delivered as
+/// a control message.
+#[cfg(target_os = "linux")]
+pub fn set_recv_orig_dst_addr(socket: &Socket) -> io::Result<()> {
+    if is_ipv6(socket)? {
+        setsockopt(socket, libc::SOL_IPV6, libc::IPV6_RECVORIGDSTADDR, 1)
+    } else {
+        setsockopt(socket, libc::SOL_IP, libc::IP_RECVORIGDSTADDR, 1)
+    }
+}
+
+/// Returns the destination of a TCP connection before it was redirected by
+/// netfilter, `SO_ORIGINAL_DST`. Fails if the connection wasn't redirected,
+/// the local address is the original destination for TPROXY.
+#[cfg(target_os = "linux")]
+pub fn original_dst(socket: &Socket) -> io::Result<SocketAddr> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name) = if is_ipv6(socket)? {
+        (libc::SOL_IPV6, libc::IP6T_SO_ORIGINAL_DST)
+    } else {
+        (libc::SOL_IP, libc::SO_ORIGINAL_DST)
+    };
+    let (_, addr) = unsafe {
+        socket2::SockAddr::init(|storage, len| {
+            if libc::getsockopt(socket.as_raw_fd(), level, name, storage.cast(), len) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        })?
+    };
+    addr.as_socket()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "unexpected original destination"))
+}
+
+// struct pfioc_natlook from the pf headers of macOS.
+#[cfg(target_os = "macos")]
+#[repr(C)]
+struct PfiocNatlook {
+    saddr: [u8; 16],
+    daddr: [u8; 16],
+    rsaddr: [u8; 16],
+    rdaddr: [u8; 16],
+    sxport: [u8; 4],
+    dxport: [u8; 4],
+    rsxport: [u8; 4],
+    rdxport: [u8; 4],
+    af: libc::sa_family_t,
+    proto: u8,
+    proto_variant: u8,
+    direction: u8,
+}
+
+#[cfg(target_os = "macos")]
+lazy_static::lazy_static! {
+    // Opened once for all the lookups.
+    static ref PF_DEVICE: io::Result<std::fs::File> = std::fs::File::open("/dev/pf");
+}
+
+/// Returns the destination of a TCP connection from `peer_addr` to
+/// `local_addr` before it was redirected by a pf `rdr` rule, looked up with
+/// the `DIOCNATLOOK` ioctl. Requires read access to `/dev/pf`. The ioctl may
+/// block, so it's meant to be called off the async workers.
+#[cfg(target_os = "macos")]
+pub fn pf_original_dst(peer_addr: SocketAddr, local_addr: SocketAddr) -> io::Result<SocketAddr> {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::os::unix::io::AsRawFd;
+
+    // _IOWR('D', 23, struct pfioc_natlook)
+    const DIOCNATLOOK: libc::c_ulong = 0xc054_4417;
+    const PF_OUT: u8 = 2;
+
+    let invalid_addr = || io::Error::new(io::ErrorKind::InvalidInput, "invalid socket address");
+    let mut nl: PfiocNatlook = unsafe { std::mem::zeroed() };
+    match (peer_addr.ip(), local_addr.ip()) {
+        (IpAddr::V4(peer_ip), IpAddr::V4(local_ip)) => {
+            nl.af = libc::AF_INET as libc::sa_family_t;
+            nl.saddr[..4].copy_from_slice(&peer_ip.octets());
+            nl.daddr[..4].copy_from_slice(&local_ip.octets());
+        }
+        (IpAddr::V6(peer_ip), IpAddr::V6(local_ip)) => {
+            nl.af = libc::AF_INET6 as libc::sa_family_t;
+            nl.saddr = peer_ip.octets();
+            nl.daddr = local_ip.octets();
+        }
+        _ => return Err(invalid_addr()),
+    }
+    nl.sxport[..2].copy_from_slice(&peer_addr.port().to_be_bytes());
+    nl.dxport[..2].copy_from_slice(&local_addr.port().to_be_bytes());
+    nl.proto = libc::IPPROTO_TCP as u8;
+    nl.direction = PF_OUT;
+
+    let pf = PF_DEVICE
+        .as_ref()
+        .map_err(|e| io::Error::new(e.kind(), e.to_string()))?;
+    if unsafe { libc::ioctl(pf.as_raw_fd(), DIOCNATLOOK, &mut nl as *mut PfiocNatlook) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let ip = if nl.af == libc::AF_INET as libc::sa_family_t {
+        IpAddr::V4(Ipv4Addr::new(
+            nl.rdaddr[0],
+            nl.rdaddr[1],
+            nl.rdaddr[2],
+            nl.rdaddr[3],
+        ))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(nl.rdaddr))
+    };
+    let port = u16::from_be_bytes([nl.rdxport[0], nl.rdxport[1]]);
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Whether `dst` is an address of the listener bound to `listen_addr`, where
+/// proxying a redirected connection or datagram to would loop back. A
+/// listener bound to an unspecified address listens on all local addresses.
+pub fn is_listener_addr(dst: &SocketAddr, listen_addr: &SocketAddr) -> bool {
+    if dst.port() != listen_addr.port() {
+        return false;
+    }
+    if !listen_addr.ip().is_unspecified() {
+        return dst.ip() == listen_addr.ip();
+    }
+    // Only local addresses can be bound to without IP_TRANSPARENT.
+    dst.ip().is_loopback() || std::net::UdpSocket::bind(SocketAddr::new(dst.ip(), 0)).is_ok()
+}
+
+// Alternates the address families starting with the family of the first
+// address, which is the preferred one, keeping the order of the addresses
+// within each family.
+fn interleave_addrs(ips: Vec<IpAddr>) -> Vec<IpAddr> {
+    let first_v6 = matches!(ips.first(), Some(IpAddr::V6(..)));
+    let (first, second): (Vec<IpAddr>, Vec<IpAddr>) =
+        ips.into_iter().partition(|ip| ip.is_ipv6() == first_v6);
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    let mut ips = Vec::new();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return ips,
+            (a, b) => ips.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Connects to the addresses as described by RFC 8305, attempts start one
+/// after another as the previous ones fail or are pending for `delay`, IPv6
+/// and IPv4 addresses in turn starting with the family of the first one.
+/// Returns the first connection established, the pending attempts are
+/// cancelled, or the last error if all of them fail.
+pub async fn happy_eyeballs<T, F, Fut>(
+    ips: Vec<IpAddr>,
+    port: u16,
+    delay: Duration,
+    connect: F,
+) -> io::Result<T>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut addrs: VecDeque<SocketAddr> = interleave_addrs(ips)
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, port))
+        .collect();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+    loop {
+        match addrs.pop_front() {
+            Some(addr) => attempts.push(connect(addr)),
+            None if attempts.is_empty() => break,
+            None => (),
+        }
+        // Waits for an attempt to fail before starting the next one, or
+        // until the delay elapses if there are more addresses.
+        let res = if !addrs.is_empty() {
+            match tokio::time::timeout(delay, attempts.next()).await {
+                Ok(res) => res,
+                Err(_) => continue,
+            }
+        } else {
+            attempts.next().await
+        };
+        match res {
+            Some(Ok(v)) => return Ok(v),
+            Some(Err(e)) => last_err = Some(e),
+            None => (),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "could not resolve to any address",
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use socket2::SockRef;
+
+    use super::*;
+
+    fn ips(ips: &[&str]) -> Vec<IpAddr> {
+        ips.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_set_keepalive() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let socket = SockRef::from(&stream);
+        set_keepalive(&socket, 30).unwrap();
+        assert!(socket.keepalive().unwrap());
+        set_nodelay(&socket, true).unwrap();
+        assert!(stream.nodelay().unwrap());
+    }
+
+    #[test]
+    fn test_is_listener_addr() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let listen_addr = addr("127.0.0.1:1080");
+        assert!(is_listener_addr(&addr("127.0.0.1:1080"), &listen_addr));
+        assert!(!is_listener_addr(&addr("127.0.0.1:1081"), &listen_addr));
+        assert!(!is_listener_addr(&addr("1.2.3.4:1080"), &listen_addr));
+        let listen_addr = addr("0.0.0.0:1080");
+        assert!(is_listener_addr(&addr("127.0.0.1:1080"), &listen_addr));
+        assert!(!is_listener_addr(&addr("1.2.3.4:1080"), &listen_addr));
+    }
+
+    #[test]
+    fn test_interleave_addrs() {
+        assert_eq!(
+            interleave_addrs(ips(&["1.1.1.1", "1.0.0.1", "::1", "8.8.8.8"])),
+            ips(&["1.1.1.1", "::1", "1.0.0.1", "8.8.8.8"])
+        );
+        assert_eq!(
+            interleave_addrs(ips(&["::1", "1.1.1.1", "1.0.0.1", "::2"])),
+            ips(&["::1", "1.1.1.1", "::2", "1.0.0.1"])
+        );
+        assert!(interleave_addrs(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_happy_eyeballs() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let delay = Duration::from_millis(50);
+
+            // The IPv6 address hangs, the IPv4 one wins after the delay.
+            let start = Instant::now();
+            let res = happy_eyeballs(ips(&["::1", "1.1.1.1"]), 80, delay, |addr| async move {
+                if addr.is_ipv6() {
+                    futures::future::pending::<()>().await;
+                }
+                Ok(addr)
+            })
+            .await;
+            assert_eq!(res.unwrap(), "1.1.1.1:80".parse().unwrap());
+            assert!(start.elapsed() >= delay);
+
+            // Failures start the next attempt right away.
+            let start = Instant::now();
+            let res = happy_eyeballs(ips(&["::1", "1.1.1.1"]), 80, delay, |addr| async move {
+                if addr.is_ipv6() {
+                    return Err(io::Error::new(io::ErrorKind::Other, "refused"));
+                }
+                Ok(addr)
+            })
+            .await;
+            assert_eq!(res.unwrap(), "1.1.1.1:80".parse().unwrap());
+            assert!(start.elapsed() < delay);
+
+            let res: io::Result<()> =
+                happy_eyeballs(ips(&["::1", "1.1.1.1"]), 80, delay, |addr| async move {
+                    Err(io::Error::new(io::ErrorKind::Other, addr.to_string()))
+                })
+                .await;
+            assert_eq!(res.unwrap_err().to_string(), "1.1.1.1:80");
+        });
+    }
+}
@@ -0,0 +1,25 @@
+use std::process::Command;
+
+use crate::session::Network;
+
+/// Returns the command name of the process owning the socket bound to
+/// `addr:port`, processes of flower itself are excluded.
+pub fn get_command_name_by_socket(network: Network, addr: &str, port: u16) -> Option<String> {
+    let pattern = match network {
+        Network::Tcp => format!("-i{}@{}:{}", "tcp", addr, port),
+        _ => format!("-i{}:{}", "udp", port),
+    };
+    let out = Command::new("lsof")
+        .arg("-c ^flower")
+        .arg("-n")
+        .arg("-P")
+        .arg("-Fc")
+        .arg(pattern)
+        .output()
+        .ok()?;
+    let out = String::from_utf8_lossy(&out.stdout);
+    out.lines()
+        .find_map(|line| line.strip_prefix('c'))
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+}
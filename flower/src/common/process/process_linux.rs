@@ -0,0 +1,155 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use crate::session::Network;
+
+// Parses an address of /proc/net/{tcp,udp}[6], e.g. `0100007F:1F90`. The
+// address is printed as 32-bit words in host byte order, the port in hex.
+fn parse_proc_addr(s: &str) -> Option<(IpAddr, u16)> {
+    let (addr, port) = s.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let mut octets = Vec::with_capacity(16);
+    for i in (0..addr.len()).step_by(8) {
+        let word = u32::from_str_radix(addr.get(i..i + 8)?, 16).ok()?;
+        octets.extend_from_slice(&word.to_ne_bytes());
+    }
+    let ip = match octets.len() {
+        4 => IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])),
+        16 => {
+            let mut buf = [0u8; 16];
+            buf.copy_from_slice(&octets);
+            let ip = Ipv6Addr::from(buf);
+            // sockets of dual-stack listeners
+            match ip.to_ipv4() {
+                Some(v4) if ip.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => IpAddr::V4(v4),
+                _ => IpAddr::V6(ip),
+            }
+        }
+        _ => return None,
+    };
+    Some((ip, port))
+}
+
+// Finds the inode of the socket bound to `ip:port`, a socket bound to the
+// unspecified address matches any address.
+fn find_socket_inode(network: Network, ip: IpAddr, port: u16) -> Option<u64> {
+    let tables: &[&str] = match network {
+        Network::Tcp => &["/proc/net/tcp", "/proc/net/tcp6"],
+        Network::Udp => &["/proc/net/udp", "/proc/net/udp6"],
+    };
+    for table in tables {
+        let content = match fs::read_to_string(table) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        for line in content.lines().skip(1) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() < 10 {
+                continue;
+            }
+            let (local_ip, local_port) = match parse_proc_addr(cols[1]) {
+                Some(v) => v,
+                None => continue,
+            };
+            if local_port != port || (local_ip != ip && !local_ip.is_unspecified()) {
+                continue;
+            }
+            match cols[9].parse::<u64>() {
+                // sockets in TIME_WAIT have no inode
+                Ok(inode) if inode != 0 => return Some(inode),
+                _ => continue,
+            }
+        }
+    }
+    None
+}
+
+fn find_pid_by_inode(inode: u64) -> Option<u32> {
+    let target = format!("socket:[{}]", inode);
+    for entry in fs::read_dir("/proc").ok()?.flatten() {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        // processes of other users can't be inspected without privileges
+        let fds = match fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        for fd in fds.flatten() {
+            if let Ok(link) = fs::read_link(fd.path()) {
+                if link.as_os_str() == target.as_str() {
+                    return Some(pid);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn command_name(pid: u32) -> Option<String> {
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    // comm is truncated to 15 bytes, prefer the name of the executable
+    if let Ok(exe) = fs::read_link(proc_dir.join("exe")) {
+        if let Some(name) = exe.file_name().and_then(|s| s.to_str()) {
+            return Some(name.to_owned());
+        }
+    }
+    let comm = fs::read_to_string(proc_dir.join("comm")).ok()?;
+    Some(comm.trim_end().to_owned())
+}
+
+/// Returns the command name of the process owning the socket bound to
+/// `addr:port`.
+pub fn get_command_name_by_socket(network: Network, addr: &str, port: u16) -> Option<String> {
+    let ip = addr.parse::<IpAddr>().ok()?;
+    let inode = find_socket_inode(network, ip, port)?;
+    let pid = find_pid_by_inode(inode)?;
+    command_name(pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_addr() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let word = u32::from_ne_bytes([127, 0, 0, 1]);
+        assert_eq!(
+            parse_proc_addr(&format!("{:08X}:1F90", word)),
+            Some((ip, 8080))
+        );
+        let words: Vec<String> = [
+            [0, 0, 0, 0],
+            [0, 0, 0, 0],
+            [0, 0, 0xff, 0xff],
+            [10, 0, 0, 1],
+        ]
+        .iter()
+        .map(|b| format!("{:08X}", u32::from_ne_bytes(*b)))
+        .collect();
+        assert_eq!(
+            parse_proc_addr(&format!("{}:0050", words.concat())),
+            Some(("10.0.0.1".parse().unwrap(), 80))
+        );
+        assert_eq!(parse_proc_addr("0100007F"), None);
+        assert_eq!(parse_proc_addr("0100:0050"), None);
+    }
+
+    #[test]
+    fn test_get_command_name_by_socket() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let local = stream.local_addr().unwrap();
+        let name = get_command_name_by_socket(Network::Tcp, &local.ip().to_string(), local.port())
+            .unwrap();
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(name, exe.file_name().unwrap().to_str().unwrap());
+    }
+}
@@ -0,0 +1,6 @@
+use crate::session::Network;
+
+/// Process lookup isn't supported on this platform, no socket is ever found.
+pub fn get_command_name_by_socket(_network: Network, _addr: &str, _port: u16) -> Option<String> {
+    None
+}
@@ -0,0 +1,185 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::windows::ffi::OsStringExt;
+use std::path::PathBuf;
+
+use winapi::shared::minwindef::{DWORD, FALSE, LPVOID, MAX_PATH, PDWORD};
+use winapi::shared::tcpmib::{
+    MIB_TCP6ROW_OWNER_PID, MIB_TCP6TABLE_OWNER_PID, MIB_TCPROW_OWNER_PID, MIB_TCPTABLE_OWNER_PID,
+    TCP_TABLE_OWNER_PID_ALL,
+};
+use winapi::shared::udpmib::{
+    MIB_UDP6ROW_OWNER_PID, MIB_UDP6TABLE_OWNER_PID, MIB_UDPROW_OWNER_PID, MIB_UDPTABLE_OWNER_PID,
+    UDP_TABLE_OWNER_PID,
+};
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, NO_ERROR};
+use winapi::shared::ws2def::{AF_INET, AF_INET6};
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::iphlpapi::{GetExtendedTcpTable, GetExtendedUdpTable};
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::winbase::QueryFullProcessImageNameW;
+use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+use crate::session::Network;
+
+// Fetches a table with one of the GetExtended*Table functions, the buffer is
+// made of DWORDs to be aligned for the table.
+fn get_table<F>(f: F) -> Option<Vec<u32>>
+where
+    F: Fn(LPVOID, PDWORD) -> DWORD,
+{
+    let mut buf: Vec<u32> = Vec::new();
+    let mut size: DWORD = 0;
+    // the table may grow between the calls
+    for _ in 0..3 {
+        match f(buf.as_mut_ptr() as LPVOID, &mut size) {
+            NO_ERROR => return Some(buf),
+            ERROR_INSUFFICIENT_BUFFER => buf.resize((size as usize + 3) / 4, 0),
+            _ => return None,
+        }
+    }
+    None
+}
+
+// The rows of a table starting with the number of entries.
+//
+// Safety: `buf` must hold a table of type `T` with rows of type `R`.
+unsafe fn rows<T, R, F>(buf: &[u32], first_row: F) -> &[R]
+where
+    F: Fn(&T) -> *const R,
+{
+    if buf.is_empty() {
+        return &[];
+    }
+    let table = &*(buf.as_ptr() as *const T);
+    std::slice::from_raw_parts(first_row(table), buf[0] as usize)
+}
+
+// Ports are in network byte order in the low 16 bits.
+fn row_port(port: DWORD) -> u16 {
+    u16::from_be(port as u16)
+}
+
+fn row_ipv4(addr: DWORD) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(addr.to_ne_bytes()))
+}
+
+fn row_ipv6(addr: [u8; 16]) -> IpAddr {
+    let ip = Ipv6Addr::from(addr);
+    // sockets of dual-stack listeners
+    match ip.to_ipv4() {
+        Some(v4) if ip.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => IpAddr::V4(v4),
+        _ => IpAddr::V6(ip),
+    }
+}
+
+// The local address, port and owning process of the sockets.
+type Sockets = Vec<(IpAddr, u16, u32)>;
+
+fn tcp_sockets() -> Option<Sockets> {
+    let mut sockets = Vec::new();
+    let buf = get_table(|table, size| unsafe {
+        GetExtendedTcpTable(
+            table,
+            size,
+            FALSE,
+            AF_INET as u32,
+            TCP_TABLE_OWNER_PID_ALL,
+            0,
+        )
+    })?;
+    let v4 = unsafe {
+        rows::<MIB_TCPTABLE_OWNER_PID, MIB_TCPROW_OWNER_PID, _>(&buf, |t| t.table.as_ptr())
+    };
+    for row in v4 {
+        sockets.push((
+            row_ipv4(row.dwLocalAddr),
+            row_port(row.dwLocalPort),
+            row.dwOwningPid,
+        ));
+    }
+    let buf = get_table(|table, size| unsafe {
+        GetExtendedTcpTable(
+            table,
+            size,
+            FALSE,
+            AF_INET6 as u32,
+            TCP_TABLE_OWNER_PID_ALL,
+            0,
+        )
+    })?;
+    let v6 = unsafe {
+        rows::<MIB_TCP6TABLE_OWNER_PID, MIB_TCP6ROW_OWNER_PID, _>(&buf, |t| t.table.as_ptr())
+    };
+    for row in v6 {
+        sockets.push((
+            row_ipv6(row.ucLocalAddr),
+            row_port(row.dwLocalPort),
+            row.dwOwningPid,
+        ));
+    }
+    Some(sockets)
+}
+
+fn udp_sockets() -> Option<Sockets> {
+    let mut sockets = Vec::new();
+    let buf = get_table(|table, size| unsafe {
+        GetExtendedUdpTable(table, size, FALSE, AF_INET as u32, UDP_TABLE_OWNER_PID, 0)
+    })?;
+    let v4 = unsafe {
+        rows::<MIB_UDPTABLE_OWNER_PID, MIB_UDPROW_OWNER_PID, _>(&buf, |t| t.table.as_ptr())
+    };
+    for row in v4 {
+        sockets.push((
+            row_ipv4(row.dwLocalAddr),
+            row_port(row.dwLocalPort),
+            row.dwOwningPid,
+        ));
+    }
+    let buf = get_table(|table, size| unsafe {
+        GetExtendedUdpTable(table, size, FALSE, AF_INET6 as u32, UDP_TABLE_OWNER_PID, 0)
+    })?;
+    let v6 = unsafe {
+        rows::<MIB_UDP6TABLE_OWNER_PID, MIB_UDP6ROW_OWNER_PID, _>(&buf, |t| t.table.as_ptr())
+    };
+    for row in v6 {
+        sockets.push((
+            row_ipv6(row.ucLocalAddr),
+            row_port(row.dwLocalPort),
+            row.dwOwningPid,
+        ));
+    }
+    Some(sockets)
+}
+
+fn command_name(pid: u32) -> Option<String> {
+    let mut buf = [0u16; MAX_PATH];
+    let mut len = buf.len() as DWORD;
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+        if handle.is_null() {
+            return None;
+        }
+        let ok = QueryFullProcessImageNameW(handle, 0, buf.as_mut_ptr(), &mut len);
+        CloseHandle(handle);
+        if ok == FALSE {
+            return None;
+        }
+    }
+    let path = PathBuf::from(std::ffi::OsString::from_wide(&buf[..len as usize]));
+    path.file_name()?.to_str().map(str::to_owned)
+}
+
+/// Returns the executable name of the process owning the socket bound to
+/// `addr:port`, e.g. `chrome.exe`.
+pub fn get_command_name_by_socket(network: Network, addr: &str, port: u16) -> Option<String> {
+    let ip = addr.parse::<IpAddr>().ok()?;
+    let sockets = match network {
+        Network::Tcp => tcp_sockets()?,
+        Network::Udp => udp_sockets()?,
+    };
+    // a socket bound to the unspecified address matches any address
+    let (_, _, pid) = sockets.into_iter().find(|(local_ip, local_port, _)| {
+        *local_port == port && (*local_ip == ip || local_ip.is_unspecified())
+    })?;
+    command_name(pid)
+}
@@ -25,134 +25,206 @@ where
         }
     }
 
+    /// Reads the beginning of the stream until a TLS ClientHello is parsed,
+    /// returns `None` if it's not TLS or the client doesn't send it in time.
+    pub async fn sniff_client_hello(&mut self) -> io::Result<Option<ClientHello>> {
+        let read = async {
+            let mut buf = vec![0u8; 2 * 1024];
+            // Reads until the record is complete, its length is bounded.
+            loop {
+                let n = self.inner.read(&mut buf).await?;
+                if n == 0 {
+                    return Ok(None);
+                }
+                self.buf.extend_from_slice(&buf[..n]);
+                match parse_client_hello(&self.buf[..]) {
+                    Ok(Some(hello)) => return Ok(Some(hello)),
+                    Ok(None) => continue,
+                    Err(_) => return Ok(None),
+                }
+            }
+        };
+        timeout(Duration::from_millis(*crate::option::SNIFF_TIMEOUT), read)
+            .await
+            .unwrap_or(Ok(None))
+    }
+
+    /// Sniffs the server name of a TLS stream.
     pub async fn sniff(&mut self) -> io::Result<Option<String>> {
-        let mut buf = vec![0u8; 2 * 1024];
-        'outer: for _ in 0..2 {
-            match timeout(Duration::from_millis(100), self.inner.read(&mut buf)).await {
-                Ok(res) => match res {
-                    Ok(n) => {
-                        self.buf.extend_from_slice(&buf[..n]);
-
-                        // https://tls.ulfheim.net/
-
-                        let sbuf = &self.buf[..];
-                        if sbuf.len() < 5 {
-                            continue;
-                        }
-                        // handshake record type
-                        if sbuf[0] != 0x16 {
-                            return Ok(None);
-                        }
-                        // protocol version
-                        if sbuf[1] != 0x3 {
-                            return Ok(None);
-                        }
-                        let header_len = BigEndian::read_u16(&sbuf[3..5]) as usize;
-                        if sbuf.len() < 5 + header_len {
-                            continue;
-                        }
-                        let sbuf = &sbuf[5..5 + header_len];
-                        // ?
-                        if sbuf.len() < 42 {
-                            continue;
-                        }
-                        let session_id_len = sbuf[38] as usize;
-                        if session_id_len > 32 || sbuf.len() < 39 + session_id_len {
-                            continue;
-                        }
-                        let sbuf = &sbuf[39 + session_id_len..];
-                        if sbuf.len() < 2 {
-                            continue;
-                        }
-                        let cipher_suite_bytes = BigEndian::read_u16(&sbuf[..2]) as usize;
-                        if sbuf.len() < 2 + cipher_suite_bytes {
-                            continue;
-                        }
-                        let sbuf = &sbuf[2 + cipher_suite_bytes..];
-                        if sbuf.is_empty() {
-                            continue;
-                        }
-                        let compression_method_bytes = sbuf[0] as usize;
-                        if sbuf.len() < 1 + compression_method_bytes {
-                            continue;
-                        }
-                        let sbuf = &sbuf[1 + compression_method_bytes..];
-                        if sbuf.len() < 2 {
-                            continue;
-                        }
-                        let extensions_bytes = BigEndian::read_u16(&sbuf[..2]) as usize;
-                        if sbuf.len() < 2 + extensions_bytes {
-                            continue;
-                        }
-                        let mut sbuf = &sbuf[2..2 + extensions_bytes];
-                        while !sbuf.is_empty() {
-                            // extension + extension-specific-len
-                            if sbuf.len() < 4 {
-                                continue 'outer;
-                            }
-                            let extension = BigEndian::read_u16(&sbuf[..2]);
-                            let extension_len = BigEndian::read_u16(&sbuf[2..4]) as usize;
-                            sbuf = &sbuf[4..];
-                            if sbuf.len() < extension_len {
-                                continue 'outer;
-                            }
-                            // extension "server name"
-                            if extension == 0x0 {
-                                let mut ebuf = &sbuf[..extension_len];
-                                if ebuf.len() < 2 {
-                                    continue 'outer;
-                                }
-                                let entry_len = BigEndian::read_u16(&ebuf[..2]) as usize;
-                                ebuf = &ebuf[2..];
-                                if ebuf.len() < entry_len {
-                                    continue 'outer;
-                                }
-                                // just make sure no oob
-                                if ebuf.is_empty() {
-                                    continue 'outer;
-                                }
-                                let entry_type = ebuf[0];
-                                // type "DNS hostname"
-                                if entry_type == 0x0 {
-                                    ebuf = &ebuf[1..];
-                                    // just make sure no oob
-                                    if ebuf.len() < 2 {
-                                        continue 'outer;
-                                    }
-                                    let hostname_len = BigEndian::read_u16(&ebuf[..2]) as usize;
-                                    ebuf = &ebuf[2..];
-                                    if ebuf.len() < hostname_len {
-                                        continue 'outer;
-                                    }
-                                    return Ok(Some(
-                                        String::from_utf8_lossy(&ebuf[..hostname_len]).into(),
-                                    ));
-                                } else {
-                                    // TODO
-                                    // I assume there's only "DNS hostname" type
-                                    // in the the "server name" extension, should
-                                    // check if this is true later.
-                                    //
-                                    // I also assume there's only one entry in the
-                                    // "server name" extension list.
-                                    return Ok(None);
-                                }
-                            } else {
-                                sbuf = &sbuf[extension_len..];
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        return Err(e);
+        Ok(self
+            .sniff_client_hello()
+            .await?
+            .and_then(|hello| hello.server_name))
+    }
+
+    /// The application protocol of the data read by `sniff`.
+    pub fn protocol(&self) -> Option<&'static str> {
+        sniff_stream_protocol(&self.buf[..])
+    }
+}
+
+/// The fields of a TLS ClientHello useful for routing.
+#[derive(Debug, Default, PartialEq)]
+pub struct ClientHello {
+    /// The host name in the server name extension.
+    pub server_name: Option<String>,
+    /// The protocols in the ALPN extension, e.g. "h2".
+    pub alpn: Vec<String>,
+}
+
+fn invalid_client_hello() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid tls client hello")
+}
+
+// Splits a vector prefixed by a length of `len_bytes` bytes off `buf`.
+fn split_vec<'a>(buf: &mut &'a [u8], len_bytes: usize) -> io::Result<&'a [u8]> {
+    if buf.len() < len_bytes {
+        return Err(invalid_client_hello());
+    }
+    let len = BigEndian::read_uint(&buf[..len_bytes], len_bytes) as usize;
+    if buf.len() < len_bytes + len {
+        return Err(invalid_client_hello());
+    }
+    let v = &buf[len_bytes..len_bytes + len];
+    *buf = &buf[len_bytes + len..];
+    Ok(v)
+}
+
+/// Parses the TLS ClientHello at the beginning of a stream, returns `None` if
+/// more data is needed, or an error if the data is not a ClientHello.
+///
+/// https://tls.ulfheim.net/
+pub fn parse_client_hello(buf: &[u8]) -> io::Result<Option<ClientHello>> {
+    if buf.len() < 5 {
+        return Ok(None);
+    }
+    // handshake record type and protocol version
+    if buf[0] != 0x16 || buf[1] != 0x3 {
+        return Err(invalid_client_hello());
+    }
+    let record_len = BigEndian::read_u16(&buf[3..5]) as usize;
+    // The plaintext of a record is at most 16K.
+    if record_len > 0x4000 {
+        return Err(invalid_client_hello());
+    }
+    if buf.len() < 5 + record_len {
+        return Ok(None);
+    }
+    let mut record = &buf[5..5 + record_len];
+    // handshake type "client hello", the message must fit in the record
+    if record.len() < 4 || record[0] != 0x1 {
+        return Err(invalid_client_hello());
+    }
+    record = &record[1..];
+    let mut hello = split_vec(&mut record, 3)?;
+    // client version and random
+    if hello.len() < 34 {
+        return Err(invalid_client_hello());
+    }
+    hello = &hello[34..];
+    let session_id = split_vec(&mut hello, 1)?;
+    if session_id.len() > 32 {
+        return Err(invalid_client_hello());
+    }
+    split_vec(&mut hello, 2)?; // cipher suites
+    split_vec(&mut hello, 1)?; // compression methods
+
+    let mut res = ClientHello::default();
+    if hello.is_empty() {
+        return Ok(Some(res));
+    }
+    let mut extensions = split_vec(&mut hello, 2)?;
+    while !extensions.is_empty() {
+        if extensions.len() < 2 {
+            return Err(invalid_client_hello());
+        }
+        let extension = BigEndian::read_u16(&extensions[..2]);
+        extensions = &extensions[2..];
+        let mut data = split_vec(&mut extensions, 2)?;
+        match extension {
+            // server name
+            0x0 => {
+                let mut names = split_vec(&mut data, 2)?;
+                while !names.is_empty() {
+                    let name_type = names[0];
+                    names = &names[1..];
+                    let name = split_vec(&mut names, 2)?;
+                    // type "DNS hostname"
+                    if name_type == 0x0 && res.server_name.is_none() {
+                        res.server_name = Some(String::from_utf8_lossy(name).into());
                     }
-                },
-                Err(_) => {
-                    return Ok(None);
                 }
             }
+            // application layer protocol negotiation
+            0x10 => {
+                let mut protocols = split_vec(&mut data, 2)?;
+                while !protocols.is_empty() {
+                    let protocol = split_vec(&mut protocols, 1)?;
+                    res.alpn.push(String::from_utf8_lossy(protocol).into());
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(Some(res))
+}
+
+const HTTP_METHODS: [&[u8]; 9] = [
+    b"GET ",
+    b"POST ",
+    b"HEAD ",
+    b"PUT ",
+    b"DELETE ",
+    b"OPTIONS ",
+    b"PATCH ",
+    b"CONNECT ",
+    b"TRACE ",
+];
+
+const BITTORRENT_HANDSHAKE: &[u8] = b"\x13BitTorrent protocol";
+
+/// Detects the application protocol from the first bytes a client sends on
+/// a stream.
+pub fn sniff_stream_protocol(buf: &[u8]) -> Option<&'static str> {
+    // TLS handshake record
+    if buf.len() >= 3 && buf[0] == 0x16 && buf[1] == 0x3 && buf[2] <= 0x4 {
+        return Some("tls");
+    }
+    if buf.starts_with(BITTORRENT_HANDSHAKE) {
+        return Some("bittorrent");
+    }
+    if HTTP_METHODS.iter().any(|m| buf.starts_with(m)) {
+        return Some("http");
+    }
+    None
+}
+
+/// Detects the application protocol from the first datagram of a UDP
+/// session.
+pub fn sniff_datagram_protocol(buf: &[u8]) -> Option<&'static str> {
+    // QUIC Initial packet with a long header, RFC 9000 section 17.2
+    if buf.len() >= 5 && buf[0] & 0xc0 == 0xc0 {
+        let packet_type = (buf[0] & 0x30) >> 4;
+        let initial = match BigEndian::read_u32(&buf[1..5]) {
+            0x1 => packet_type == 0,
+            // QUIC v2 re-numbers the packet types
+            0x6b3343cf => packet_type == 1,
+            // drafts
+            v if v >> 8 == 0xff0000 => packet_type == 0,
+            _ => false,
+        };
+        if initial {
+            return Some("quic");
         }
-        Ok(None)
     }
+    // BitTorrent DHT, KRPC messages are bencoded dictionaries
+    if buf.starts_with(b"d1:") {
+        let krpc = [&b"1:y1:q"[..], &b"1:y1:r"[..], &b"1:y1:e"[..]];
+        if krpc.iter().any(|t| buf.windows(t.len()).any(|w| w == *t)) {
+            return Some("bittorrent");
+        }
+    }
+    None
 }
 
 impl<T: AsyncRead + Unpin> AsyncRead for SniffingStream<T> {
@@ -189,3 +261,157 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for SniffingStream<T> {
         AsyncWrite::poll_shutdown(Pin::new(&mut self.inner), cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_stream_protocol() {
+        let mut handshake = BITTORRENT_HANDSHAKE.to_vec();
+        handshake.extend_from_slice(&[0u8; 48]);
+        assert_eq!(sniff_stream_protocol(&handshake), Some("bittorrent"));
+        assert_eq!(
+            sniff_stream_protocol(&[0x16, 0x3, 0x1, 0x2, 0x0, 0x1]),
+            Some("tls")
+        );
+        assert_eq!(
+            sniff_stream_protocol(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n"),
+            Some("http")
+        );
+        assert_eq!(sniff_stream_protocol(b"SSH-2.0-OpenSSH_8.9\r\n"), None);
+        assert_eq!(sniff_stream_protocol(b""), None);
+    }
+
+    #[test]
+    fn test_sniff_datagram_protocol() {
+        let mut initial = vec![0xc3, 0x0, 0x0, 0x0, 0x1, 0x8];
+        initial.extend_from_slice(&[0u8; 32]);
+        assert_eq!(sniff_datagram_protocol(&initial), Some("quic"));
+        // handshake packet
+        initial[0] = 0xe3;
+        assert_eq!(sniff_datagram_protocol(&initial), None);
+
+        let ping = b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe";
+        assert_eq!(sniff_datagram_protocol(ping), Some("bittorrent"));
+        assert_eq!(sniff_datagram_protocol(&[0u8; 12]), None);
+    }
+
+    #[test]
+    fn test_sniffing_stream_bittorrent() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            use tokio::io::AsyncWriteExt;
+
+            let (mut client, server) = tokio::io::duplex(1024);
+            let mut handshake = BITTORRENT_HANDSHAKE.to_vec();
+            handshake.extend_from_slice(&[0u8; 48]);
+            client.write_all(&handshake).await.unwrap();
+
+            let mut stream = SniffingStream::new(server);
+            assert_eq!(stream.sniff().await.unwrap(), None);
+            assert_eq!(stream.protocol(), Some("bittorrent"));
+
+            // Sniffed data is still delivered to the reader.
+            let mut buf = vec![0u8; handshake.len()];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, handshake);
+        });
+    }
+
+    // Builds a ClientHello record with the server name and ALPN extensions.
+    fn client_hello(server_name: &str, alpn: &[&str]) -> Vec<u8> {
+        fn with_len(len_bytes: usize, data: &[u8]) -> Vec<u8> {
+            let mut v = data.len().to_be_bytes()[8 - len_bytes..].to_vec();
+            v.extend_from_slice(data);
+            v
+        }
+        let mut extensions = Vec::new();
+        let mut names = vec![0x0];
+        names.extend(with_len(2, server_name.as_bytes()));
+        extensions.extend([0x0, 0x0]);
+        extensions.extend(with_len(2, &with_len(2, &names)));
+        let mut protocols = Vec::new();
+        for p in alpn {
+            protocols.extend(with_len(1, p.as_bytes()));
+        }
+        extensions.extend([0x0, 0x10]);
+        extensions.extend(with_len(2, &with_len(2, &protocols)));
+
+        let mut hello = vec![0x3, 0x3];
+        hello.extend([0u8; 32]);
+        hello.extend(with_len(1, &[0u8; 32]));
+        hello.extend(with_len(2, &[0x13, 0x1]));
+        hello.extend(with_len(1, &[0x0]));
+        hello.extend(with_len(2, &extensions));
+        let mut handshake = vec![0x1];
+        handshake.extend(with_len(3, &hello));
+        let mut record = vec![0x16, 0x3, 0x1];
+        record.extend(with_len(2, &handshake));
+        record
+    }
+
+    #[test]
+    fn test_parse_client_hello() {
+        let record = client_hello("example.com", &["h2", "http/1.1"]);
+        assert_eq!(
+            parse_client_hello(&record).unwrap(),
+            Some(ClientHello {
+                server_name: Some("example.com".to_string()),
+                alpn: vec!["h2".to_string(), "http/1.1".to_string()],
+            })
+        );
+        assert_eq!(
+            parse_client_hello(&record[..record.len() - 1]).unwrap(),
+            None
+        );
+        assert_eq!(parse_client_hello(&record[..3]).unwrap(), None);
+        assert!(parse_client_hello(b"GET / HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_sniff_client_hello() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            use tokio::io::AsyncWriteExt;
+
+            // A ClientHello larger than the read buffer, sent in pieces.
+            let name = "a".repeat(60) + ".example.com";
+            let alpns = vec!["h2"; 2000];
+            let record = client_hello(&name, &alpns);
+            assert!(record.len() > 4 * 1024);
+            let (mut client, server) = tokio::io::duplex(1024);
+            let record2 = record.clone();
+            tokio::spawn(async move {
+                for chunk in record2.chunks(1000) {
+                    client.write_all(chunk).await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                // Keeps the stream open.
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            });
+            let mut stream = SniffingStream::new(server);
+            assert_eq!(stream.sniff().await.unwrap(), Some(name));
+            assert_eq!(stream.protocol(), Some("tls"));
+            let mut buf = vec![0u8; record.len()];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, record);
+
+            // Plaintext streams are replayed as well.
+            let (mut client, server) = tokio::io::duplex(1024);
+            client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+            let mut stream = SniffingStream::new(server);
+            assert_eq!(stream.sniff().await.unwrap(), None);
+            assert_eq!(stream.protocol(), Some("http"));
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"GET ");
+        });
+    }
+}
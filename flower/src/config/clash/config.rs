@@ -0,0 +1,300 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde_derive::Deserialize;
+
+use crate::config::{conf, internal};
+
+#[derive(Deserialize, Debug)]
+pub struct WsOpts {
+    pub path: Option<String>,
+    pub headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Proxy {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub server: Option<String>,
+    pub port: Option<u16>,
+    // ss
+    pub cipher: Option<String>,
+    pub plugin: Option<String>,
+    // ss, trojan, socks5
+    pub password: Option<String>,
+    // socks5
+    pub username: Option<String>,
+    // trojan
+    pub sni: Option<String>,
+    pub network: Option<String>,
+    #[serde(rename = "ws-opts")]
+    pub ws_opts: Option<WsOpts>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ProxyGroup {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub proxies: Option<Vec<String>>,
+    pub interval: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub port: Option<u16>,
+    #[serde(rename = "socks-port")]
+    pub socks_port: Option<u16>,
+    #[serde(rename = "allow-lan")]
+    pub allow_lan: Option<bool>,
+    #[serde(rename = "log-level")]
+    pub log_level: Option<String>,
+    // Proxies are kept as raw values so that an unsupported type with
+    // unexpected fields doesn't fail the whole config.
+    pub proxies: Vec<serde_yaml::Value>,
+    #[serde(rename = "proxy-groups")]
+    pub proxy_groups: Option<Vec<ProxyGroup>>,
+    pub rules: Option<Vec<String>>,
+}
+
+/// Returns whether the YAML document looks like a Clash config, i.e. it has
+/// a top-level `proxies` section.
+pub fn is_clash_config(config: &str) -> bool {
+    match serde_yaml::from_str::<serde_yaml::Value>(config) {
+        Ok(serde_yaml::Value::Mapping(m)) => m.contains_key(&"proxies".into()),
+        _ => false,
+    }
+}
+
+pub fn clash_from_string(config: &str) -> Result<Config> {
+    serde_yaml::from_str(config).map_err(|e| anyhow!("deserialize clash config failed: {}", e))
+}
+
+fn to_conf_proxy(ext_proxy: Proxy) -> Result<conf::Proxy> {
+    let mut proxy = conf::Proxy {
+        tag: ext_proxy.name,
+        address: ext_proxy.server,
+        port: ext_proxy.port,
+        password: ext_proxy.password,
+        ..Default::default()
+    };
+    match ext_proxy.type_field.as_str() {
+        "ss" => {
+            if let Some(plugin) = ext_proxy.plugin {
+                return Err(anyhow!("unsupported shadowsocks plugin {}", plugin));
+            }
+            proxy.protocol = "ss".to_string();
+            if ext_proxy.cipher.is_some() {
+                proxy.encrypt_method = ext_proxy.cipher;
+            }
+        }
+        "trojan" => {
+            proxy.protocol = "trojan".to_string();
+            // Clash verifies the certificate against the server address by
+            // default.
+            proxy.sni = ext_proxy.sni.or_else(|| proxy.address.clone());
+            match ext_proxy.network.as_deref() {
+                None | Some("tcp") => (),
+                Some("ws") => {
+                    proxy.ws = Some(true);
+                    if let Some(ws_opts) = ext_proxy.ws_opts {
+                        proxy.ws_path = ws_opts.path;
+                        proxy.ws_host = ws_opts.headers.and_then(|mut h| h.remove("Host"));
+                    }
+                }
+                Some(network) => return Err(anyhow!("unsupported trojan network {}", network)),
+            }
+        }
+        "socks5" => {
+            if ext_proxy.username.is_some() {
+                return Err(anyhow!("socks5 authentication is not supported"));
+            }
+            proxy.protocol = "socks".to_string();
+        }
+        _ => return Err(anyhow!("unsupported proxy type {}", ext_proxy.type_field)),
+    }
+    Ok(proxy)
+}
+
+fn to_conf_proxy_group(ext_group: ProxyGroup) -> Result<conf::ProxyGroup> {
+    let protocol = match ext_group.type_field.as_str() {
+        "select" => "select",
+        "url-test" | "fallback" => "failover",
+        "load-balance" => "rr",
+        "relay" => "chain",
+        _ => {
+            return Err(anyhow!(
+                "unsupported proxy group type {}",
+                ext_group.type_field
+            ))
+        }
+    };
+    let mut group = conf::ProxyGroup {
+        tag: ext_group.name,
+        protocol: protocol.to_string(),
+        actors: ext_group.proxies,
+        ..Default::default()
+    };
+    if ext_group.interval.is_some() {
+        group.check_interval = ext_group.interval;
+    }
+    Ok(group)
+}
+
+// Drops the members skipped by the translation from the groups, a group left
+// without members is skipped as well, which in turn drops it from the groups
+// containing it.
+fn drop_missing_members(proxies: &[conf::Proxy], groups: &mut Vec<conf::ProxyGroup>) {
+    let mut known: HashSet<String> = proxies.iter().map(|p| p.tag.clone()).collect();
+    known.extend(groups.iter().map(|g| g.tag.clone()));
+    loop {
+        for group in groups.iter_mut() {
+            let actors = group.actors.get_or_insert_with(Vec::new);
+            actors.retain(|actor| {
+                let found = known.contains(actor);
+                if !found {
+                    warn!(
+                        "drop missing member {} from clash proxy group {}",
+                        actor, group.tag
+                    );
+                }
+                found
+            });
+        }
+        let (empty, rest): (Vec<_>, Vec<_>) = groups
+            .drain(..)
+            .partition(|g| g.actors.as_ref().map_or(true, Vec::is_empty));
+        *groups = rest;
+        if empty.is_empty() {
+            break;
+        }
+        for group in empty {
+            warn!("skip clash proxy group {}: no members", group.tag);
+            known.remove(&group.tag);
+        }
+    }
+}
+
+fn to_conf_rule(ext_rule: &str) -> Result<conf::Rule> {
+    let parts: Vec<&str> = ext_rule.split(',').map(str::trim).collect();
+    let rule = match parts.as_slice() {
+        ["MATCH", target, ..] => conf::Rule {
+            type_field: "FINAL".to_string(),
+            filter: None,
+            target: target.to_string(),
+        },
+        [type_field, filter, target, ..] => {
+            let type_field = match *type_field {
+                "DOMAIN" | "DOMAIN-SUFFIX" | "DOMAIN-KEYWORD" | "IP-CIDR" | "GEOIP" => *type_field,
+                "IP-CIDR6" => "IP-CIDR",
+                _ => return Err(anyhow!("unsupported rule type {}", type_field)),
+            };
+            conf::Rule {
+                type_field: type_field.to_string(),
+                filter: Some(filter.to_string()),
+                target: target.to_string(),
+            }
+        }
+        _ => return Err(anyhow!("invalid rule {}", ext_rule)),
+    };
+    Ok(rule)
+}
+
+/// Translates a Clash config into a conf one, entries Flower doesn't support
+/// are skipped with a warning.
+pub fn to_conf(config: Config) -> conf::Config {
+    let mut general = conf::General {
+        loglevel: config.log_level.map(|level| match level.as_str() {
+            "warning" => "warn".to_string(),
+            _ => level,
+        }),
+        ..Default::default()
+    };
+    let interface = if config.allow_lan.unwrap_or(false) {
+        "0.0.0.0"
+    } else {
+        "127.0.0.1"
+    };
+    if config.port.is_some() {
+        general.http_interface = Some(interface.to_string());
+        general.http_port = config.port;
+    }
+    if config.socks_port.is_some() {
+        general.socks_interface = Some(interface.to_string());
+        general.socks_port = config.socks_port;
+    }
+
+    // The built-in proxies of Clash, DIRECT goes first to be the default
+    // when there's no MATCH rule.
+    let mut proxies = vec![
+        conf::Proxy {
+            tag: "DIRECT".to_string(),
+            protocol: "direct".to_string(),
+            ..Default::default()
+        },
+        conf::Proxy {
+            tag: "REJECT".to_string(),
+            protocol: "drop".to_string(),
+            ..Default::default()
+        },
+    ];
+    for ext_proxy in config.proxies {
+        let proxy = serde_yaml::from_value::<Proxy>(ext_proxy)
+            .map_err(|e| anyhow!("invalid proxy: {}", e))
+            .and_then(|p| {
+                let name = p.name.clone();
+                to_conf_proxy(p).map_err(|e| anyhow!("{}: {}", name, e))
+            });
+        match proxy {
+            Ok(proxy) => proxies.push(proxy),
+            Err(e) => warn!("skip clash proxy {}", e),
+        }
+    }
+
+    let mut proxy_groups = Vec::new();
+    for ext_group in config.proxy_groups.unwrap_or_default() {
+        let name = ext_group.name.clone();
+        match to_conf_proxy_group(ext_group) {
+            Ok(group) => proxy_groups.push(group),
+            Err(e) => warn!("skip clash proxy group {}: {}", name, e),
+        }
+    }
+    drop_missing_members(&proxies, &mut proxy_groups);
+
+    let mut rules = Vec::new();
+    for ext_rule in config.rules.unwrap_or_default() {
+        match to_conf_rule(&ext_rule) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => warn!("skip clash rule {}: {}", ext_rule, e),
+        }
+    }
+
+    conf::Config {
+        general: Some(general),
+        proxy: Some(proxies),
+        proxy_group: Some(proxy_groups),
+        rule: Some(rules),
+        host: None,
+    }
+}
+
+pub fn to_internal(config: Config) -> Result<internal::Config> {
+    conf::to_internal(&mut to_conf(config))
+}
+
+pub fn from_string(s: &str) -> Result<internal::Config> {
+    let config = clash_from_string(s)?;
+    to_internal(config)
+}
+
+pub fn from_file<P>(path: P) -> Result<internal::Config>
+where
+    P: AsRef<Path>,
+{
+    let config = std::fs::read_to_string(path)?;
+    let config = clash_from_string(&config)?;
+    to_internal(config)
+}
@@ -0,0 +1,145 @@
+use protobuf::Message;
+
+#[test]
+fn test_config() {
+    let yaml_str = r#"
+port: 7890
+socks-port: 7891
+log-level: warning
+proxies:
+  - name: ss1
+    type: ss
+    server: 1.2.3.4
+    port: 8388
+    cipher: aes-128-gcm
+    password: pass
+  - name: trojan1
+    type: trojan
+    server: example.com
+    port: 443
+    password: pass
+    network: ws
+    ws-opts:
+      path: /ws
+      headers:
+        Host: cdn.example.com
+  - name: socks1
+    type: socks5
+    server: 127.0.0.1
+    port: 1080
+  - name: vmess1
+    type: vmess
+    server: 1.2.3.4
+    port: 443
+    uuid: 2e4f6e5c-0000-0000-0000-000000000000
+    alterId: 0
+proxy-groups:
+  - name: Proxy
+    type: select
+    proxies:
+      - ss1
+      - vmess1
+      - trojan1
+      - Legacy
+  - name: Legacy
+    type: select
+    proxies:
+      - vmess1
+  - name: Auto
+    type: url-test
+    proxies:
+      - ss1
+      - socks1
+    url: http://www.gstatic.com/generate_204
+    interval: 600
+rules:
+  - DOMAIN-SUFFIX,google.com,Proxy
+  - IP-CIDR,10.0.0.0/8,DIRECT,no-resolve
+  - GEOIP,CN,DIRECT
+  - PROCESS-NAME,curl,REJECT
+  - MATCH,Auto
+"#;
+
+    let config = crate::config::clash::from_string(yaml_str).unwrap();
+    assert_eq!(config.get_log().level, crate::config::Log_Level::WARN);
+    assert_eq!(config.inbounds[0].protocol, "http");
+    assert_eq!(config.inbounds[0].address, "127.0.0.1");
+    assert_eq!(config.inbounds[0].port, 7890);
+    assert_eq!(config.inbounds[1].protocol, "socks");
+    assert_eq!(config.inbounds[1].port, 7891);
+
+    let outbound = |tag: &str| {
+        config
+            .outbounds
+            .iter()
+            .find(|o| o.tag == tag)
+            .unwrap_or_else(|| panic!("missing outbound {}", tag))
+    };
+    // The MATCH target is the default outbound.
+    assert_eq!(config.outbounds[0].tag, "Auto");
+    assert_eq!(outbound("DIRECT").protocol, "direct");
+    assert_eq!(outbound("REJECT").protocol, "drop");
+    // vmess isn't supported.
+    assert!(config.outbounds.iter().all(|o| o.tag != "vmess1"));
+
+    let settings =
+        crate::config::ShadowsocksOutboundSettings::parse_from_bytes(&outbound("ss1").settings)
+            .unwrap();
+    assert_eq!(settings.address, "1.2.3.4");
+    assert_eq!(settings.port, 8388);
+    assert_eq!(settings.method, "aes-128-gcm");
+    assert_eq!(settings.password, "pass");
+
+    let settings =
+        crate::config::ChainOutboundSettings::parse_from_bytes(&outbound("trojan1").settings)
+            .unwrap();
+    assert_eq!(
+        settings.actors.to_vec(),
+        vec!["trojan1_tls_xxx", "trojan1_ws_xxx", "trojan1_trojan_xxx"]
+    );
+    let settings =
+        crate::config::TlsOutboundSettings::parse_from_bytes(&outbound("trojan1_tls_xxx").settings)
+            .unwrap();
+    assert_eq!(settings.server_name, "example.com");
+    let settings = crate::config::WebSocketOutboundSettings::parse_from_bytes(
+        &outbound("trojan1_ws_xxx").settings,
+    )
+    .unwrap();
+    assert_eq!(settings.path, "/ws");
+    assert_eq!(settings.headers["Host"], "cdn.example.com");
+
+    assert_eq!(outbound("socks1").protocol, "socks");
+
+    // Only the members skipped are dropped, along with the groups left
+    // without members.
+    assert_eq!(outbound("Proxy").protocol, "select");
+    let settings =
+        crate::config::SelectOutboundSettings::parse_from_bytes(&outbound("Proxy").settings)
+            .unwrap();
+    assert_eq!(settings.actors.to_vec(), vec!["ss1", "trojan1"]);
+    assert!(config.outbounds.iter().all(|o| o.tag != "Legacy"));
+    let settings =
+        crate::config::FailOverOutboundSettings::parse_from_bytes(&outbound("Auto").settings)
+            .unwrap();
+    assert_eq!(settings.actors.to_vec(), vec!["ss1", "socks1"]);
+    assert_eq!(settings.check_interval, 600);
+
+    let rules = &config.get_router().rules;
+    assert_eq!(rules.len(), 3);
+    assert_eq!(rules[0].target_tag, "Proxy");
+    assert_eq!(rules[0].domains[0].value, "google.com");
+    assert_eq!(
+        rules[0].domains[0].field_type,
+        crate::config::Router_Rule_Domain_Type::DOMAIN
+    );
+    assert_eq!(rules[1].target_tag, "DIRECT");
+    assert_eq!(rules[1].ip_cidrs[0], "10.0.0.0/8");
+    assert_eq!(rules[2].mmdbs[0].country_code, "CN");
+}
+
+#[test]
+fn test_is_clash_config() {
+    assert!(crate::config::clash::is_clash_config("proxies: []"));
+    assert!(!crate::config::clash::is_clash_config("outbounds: []"));
+    assert!(!crate::config::clash::is_clash_config("proxies: ["));
+}
@@ -30,17 +30,29 @@ pub struct General {
     pub tun_auto: Option<bool>,
     pub loglevel: Option<String>,
     pub logoutput: Option<String>,
+    pub log_redact_user: Option<bool>,
+    pub log_levels: Option<Vec<String>>,
+    pub log_format: Option<String>,
+    pub log_max_size_mb: Option<u32>,
+    pub log_max_files: Option<u32>,
     pub dns_server: Option<Vec<String>>,
     pub dns_interface: Option<String>,
+    pub dns_outbound: Option<String>,
+    pub dns_tcp_server: Option<Vec<String>>,
+    pub dns_bootstrap_server: Option<Vec<String>>,
     pub always_real_ip: Option<Vec<String>>,
     pub always_fake_ip: Option<Vec<String>>,
     pub http_interface: Option<String>,
     pub http_port: Option<u16>,
     pub socks_interface: Option<String>,
     pub socks_port: Option<u16>,
+    pub tproxy_interface: Option<String>,
+    pub tproxy_port: Option<u16>,
     pub api_interface: Option<String>,
     pub api_port: Option<u16>,
+    pub api_secret: Option<String>,
     pub routing_domain_resolve: Option<bool>,
+    pub sniff_route_only: Option<bool>,
 }
 
 #[derive(Debug)]
@@ -63,6 +75,7 @@ pub struct Proxy {
     pub ws: Option<bool>,
     pub tls: Option<bool>,
     pub tls_cert: Option<String>,
+    pub tls_verify_time: Option<String>,
     pub ws_path: Option<String>,
     pub ws_host: Option<String>,
 
@@ -74,6 +87,9 @@ pub struct Proxy {
     pub amux_con: Option<i32>,
 
     pub quic: Option<bool>,
+
+    // socks
+    pub udp_over_tcp: Option<bool>,
 }
 
 impl Default for Proxy {
@@ -90,6 +106,7 @@ impl Default for Proxy {
             ws: Some(false),
             tls: Some(false),
             tls_cert: None,
+            tls_verify_time: None,
             ws_path: None,
             ws_host: None,
             sni: None,
@@ -97,6 +114,7 @@ impl Default for Proxy {
             amux_max: Some(8),
             amux_con: Some(2),
             quic: Some(false),
+            udp_over_tcp: None,
         }
     }
 }
@@ -114,12 +132,18 @@ pub struct ProxyGroup {
     pub fallback_cache: Option<bool>,
     pub cache_size: Option<i32>,
     pub cache_timeout: Option<i32>,
+    pub url: Option<String>,
+    pub tolerance: Option<i32>,
 
     // tryall
     pub delay_base: Option<i32>,
 
     // retry
     pub attempts: Option<i32>,
+
+    // balance
+    pub strategy: Option<String>,
+    pub fail_cooldown: Option<i32>,
 }
 
 impl Default for ProxyGroup {
@@ -135,8 +159,12 @@ impl Default for ProxyGroup {
             fallback_cache: Some(false),
             cache_size: Some(256),
             cache_timeout: Some(60),
+            url: None,
+            tolerance: None,
             delay_base: Some(0),
             attempts: Some(2),
+            strategy: None,
+            fail_cooldown: None,
         }
     }
 }
@@ -269,12 +297,40 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
             "logoutput" => {
                 general.logoutput = Some(parts[1].to_string());
             }
+            "log-redact-user" => {
+                general.log_redact_user = if parts[1] == "true" {
+                    Some(true)
+                } else {
+                    Some(false)
+                };
+            }
+            "log-levels" => {
+                general.log_levels = get_char_sep_slice(parts[1], ',');
+            }
+            "log-format" => {
+                general.log_format = get_string(parts[1]);
+            }
+            "log-max-size-mb" => {
+                general.log_max_size_mb = get_value::<u32>(parts[1]);
+            }
+            "log-max-files" => {
+                general.log_max_files = get_value::<u32>(parts[1]);
+            }
             "dns-server" => {
                 general.dns_server = get_char_sep_slice(parts[1], ',');
             }
             "dns-interface" => {
                 general.dns_interface = get_string(parts[1]);
             }
+            "dns-outbound" => {
+                general.dns_outbound = get_string(parts[1]);
+            }
+            "dns-tcp-server" => {
+                general.dns_tcp_server = get_char_sep_slice(parts[1], ',');
+            }
+            "dns-bootstrap-server" => {
+                general.dns_bootstrap_server = get_char_sep_slice(parts[1], ',');
+            }
             "always-real-ip" => {
                 general.always_real_ip = get_char_sep_slice(parts[1], ',');
             }
@@ -288,6 +344,9 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
                     Some(false)
                 };
             }
+            "sniff-route-only" => {
+                general.sniff_route_only = Some(parts[1] == "true");
+            }
             "http-listen" => {
                 let (interface, port) = parts[1].split_once(':').unwrap();
                 general.http_interface = get_string(interface);
@@ -298,12 +357,20 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
                 general.socks_interface = get_string(interface);
                 general.socks_port = get_value::<u16>(port);
             }
+            "tproxy-listen" => {
+                let (interface, port) = parts[1].split_once(':').unwrap();
+                general.tproxy_interface = get_string(interface);
+                general.tproxy_port = get_value::<u16>(port);
+            }
             "api-interface" => {
                 general.api_interface = get_string(parts[1]);
             }
             "api-port" => {
                 general.api_port = get_value::<u16>(parts[1]);
             }
+            "api-secret" => {
+                general.api_secret = get_string(parts[1]);
+            }
             _ => {}
         }
     }
@@ -360,6 +427,9 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
                 "tls-cert" => {
                     proxy.tls_cert = Some(v.to_string());
                 }
+                "tls-verify-time" => {
+                    proxy.tls_verify_time = Some(v.to_string());
+                }
                 "ws-path" => {
                     proxy.ws_path = Some(v.to_string());
                 }
@@ -387,6 +457,9 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
                     proxy.amux_con = i;
                 }
                 "quic" => proxy.quic = if v == "true" { Some(true) } else { Some(false) },
+                "udp-over-tcp" => {
+                    proxy.udp_over_tcp = if v == "true" { Some(true) } else { Some(false) }
+                }
                 "interface" => {
                     proxy.interface = v.to_string();
                 }
@@ -531,6 +604,17 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
                         };
                         group.cache_timeout = i;
                     }
+                    "url" => {
+                        group.url = Some(v.to_string());
+                    }
+                    "tolerance" => {
+                        let i = if let Ok(i) = v.parse::<i32>() {
+                            Some(i)
+                        } else {
+                            None
+                        };
+                        group.tolerance = i;
+                    }
                     "delay-base" => {
                         let i = if let Ok(i) = v.parse::<i32>() {
                             Some(i)
@@ -547,6 +631,17 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
                         };
                         group.attempts = i;
                     }
+                    "strategy" => {
+                        group.strategy = Some(v.to_string());
+                    }
+                    "fail-cooldown" => {
+                        let i = if let Ok(i) = v.parse::<i32>() {
+                            Some(i)
+                        } else {
+                            None
+                        };
+                        group.fail_cooldown = i;
+                    }
                     _ => {}
                 }
             }
@@ -600,8 +695,9 @@ pub fn from_lines(lines: Vec<io::Result<String>>) -> Result<Config> {
         rule.target = params[2].to_string();
 
         match rule.type_field.as_str() {
-            "IP-CIDR" | "DOMAIN" | "DOMAIN-SUFFIX" | "DOMAIN-KEYWORD" | "GEOIP" | "EXTERNAL"
-            | "PORT-RANGE" | "NETWORK" | "INBOUND-TAG" | "PROCESS" => {
+            "IP-CIDR" | "DOMAIN" | "DOMAIN-SUFFIX" | "DOMAIN-KEYWORD" | "GEOIP"
+            | "SOURCE-GEOIP" | "SRC-IP-CIDR" | "EXTERNAL" | "PORT-RANGE" | "NETWORK"
+            | "INBOUND-TAG" | "PROCESS" | "PROTOCOL" => {
                 rule.filter = Some(params[1].to_string());
             }
             // "RULE-SET" => {
@@ -703,6 +799,31 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                 }
             }
         }
+        if let Some(ext_log_redact_user) = ext_general.log_redact_user {
+            log.redact_user = ext_log_redact_user;
+        }
+        if let Some(ext_log_levels) = &ext_general.log_levels {
+            // Written as "target:level" since "=" separates the key.
+            for ext_log_level in ext_log_levels {
+                let (target, level) = ext_log_level
+                    .rsplit_once(':')
+                    .ok_or_else(|| anyhow!("invalid log level {}", ext_log_level))?;
+                log.target_levels.push(format!("{}={}", target, level));
+            }
+        }
+        if let Some(ext_log_max_size_mb) = ext_general.log_max_size_mb {
+            log.max_size_mb = ext_log_max_size_mb;
+        }
+        if let Some(ext_log_max_files) = ext_general.log_max_files {
+            log.max_files = ext_log_max_files;
+        }
+        if let Some(ext_log_format) = &ext_general.log_format {
+            match ext_log_format.as_str() {
+                "json" => log.format = internal::Log_Format::JSON,
+                "text" => log.format = internal::Log_Format::TEXT,
+                _ => return Err(anyhow!("invalid log format {}", ext_log_format)),
+            }
+        }
     }
 
     let mut inbounds = protobuf::RepeatedField::new();
@@ -713,6 +834,7 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
             inbound.tag = "http".to_string();
             inbound.address = ext_general.http_interface.as_ref().unwrap().to_string();
             inbound.port = ext_general.http_port.unwrap() as u32;
+            inbound.random_port = inbound.port == 0;
             inbounds.push(inbound);
         }
         if ext_general.socks_interface.is_some() && ext_general.socks_port.is_some() {
@@ -721,6 +843,15 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
             inbound.tag = "socks".to_string();
             inbound.address = ext_general.socks_interface.as_ref().unwrap().to_string();
             inbound.port = ext_general.socks_port.unwrap() as u32;
+            inbound.random_port = inbound.port == 0;
+            inbounds.push(inbound);
+        }
+        if ext_general.tproxy_interface.is_some() && ext_general.tproxy_port.is_some() {
+            let mut inbound = internal::Inbound::new();
+            inbound.protocol = "tproxy".to_string();
+            inbound.tag = "tproxy".to_string();
+            inbound.address = ext_general.tproxy_interface.as_ref().unwrap().to_string();
+            inbound.port = ext_general.tproxy_port.unwrap() as u32;
             inbounds.push(inbound);
         }
 
@@ -799,7 +930,21 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
             outbound.protocol = ext_protocol.to_string();
             outbound.tag = ext_proxy.tag.clone();
             match outbound.protocol.as_str() {
-                "direct" | "drop" => {
+                "direct" => {
+                    let mut settings = internal::DirectOutboundSettings::new();
+                    if ext_proxy.interface != crate::option::UNSPECIFIED_BIND_ADDR.ip().to_string()
+                    {
+                        if crate::common::net::parse_bind_addr(&ext_proxy.interface).is_ok() {
+                            settings.bind_address = ext_proxy.interface.clone();
+                        } else {
+                            settings.bind_interface = ext_proxy.interface.clone();
+                        }
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
+                "drop" => {
                     outbounds.push(outbound);
                 }
                 "redirect" => {
@@ -822,6 +967,33 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                     if let Some(ext_port) = &ext_proxy.port {
                         settings.port = *ext_port as u32;
                     }
+                    if let Some(ext_username) = &ext_proxy.username {
+                        settings.username = ext_username.clone();
+                    }
+                    if let Some(ext_password) = &ext_proxy.password {
+                        settings.password = ext_password.clone();
+                    }
+                    if let Some(ext_udp_over_tcp) = ext_proxy.udp_over_tcp {
+                        settings.udp_over_tcp = ext_udp_over_tcp;
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
+                "http" => {
+                    let mut settings = internal::HttpOutboundSettings::new();
+                    if let Some(ext_address) = &ext_proxy.address {
+                        settings.address = ext_address.clone();
+                    }
+                    if let Some(ext_port) = &ext_proxy.port {
+                        settings.port = *ext_port as u32;
+                    }
+                    if let Some(ext_username) = &ext_proxy.username {
+                        settings.username = ext_username.clone();
+                    }
+                    if let Some(ext_password) = &ext_proxy.password {
+                        settings.password = ext_password.clone();
+                    }
                     let settings = settings.write_to_bytes().unwrap();
                     outbound.settings = settings;
                     outbounds.push(outbound);
@@ -864,6 +1036,9 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                             tls_settings.certificate = path;
                         }
                     }
+                    if let Some(ext_tls_verify_time) = &ext_proxy.tls_verify_time {
+                        tls_settings.verify_time = ext_tls_verify_time.clone();
+                    }
                     let tls_settings = tls_settings.write_to_bytes().unwrap();
                     tls_outbound.settings = tls_settings;
                     tls_outbound.tag = format!("{}_tls_xxx", ext_proxy.tag.clone());
@@ -990,6 +1165,9 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                     if let Some(ext_sni) = &ext_proxy.sni {
                         tls_settings.server_name = ext_sni.clone();
                     }
+                    if let Some(ext_tls_verify_time) = &ext_proxy.tls_verify_time {
+                        tls_settings.verify_time = ext_tls_verify_time.clone();
+                    }
                     let tls_settings = tls_settings.write_to_bytes().unwrap();
                     tls_outbound.settings = tls_settings;
                     tls_outbound.tag = format!("{}_tls_xxx", ext_proxy.tag.clone());
@@ -1126,6 +1304,23 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                     outbound.settings = settings;
                     outbounds.push(outbound);
                 }
+                "balance" => {
+                    let mut settings = internal::BalanceOutboundSettings::new();
+                    if let Some(ext_actors) = &ext_proxy_group.actors {
+                        for ext_actor in ext_actors {
+                            settings.actors.push(ext_actor.to_string());
+                        }
+                    }
+                    if let Some(ext_strategy) = &ext_proxy_group.strategy {
+                        settings.strategy = ext_strategy.to_string();
+                    }
+                    if let Some(ext_fail_cooldown) = ext_proxy_group.fail_cooldown {
+                        settings.fail_cooldown = ext_fail_cooldown as u32;
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
                 "failover" => {
                     let mut settings = internal::FailOverOutboundSettings::new();
                     if let Some(ext_actors) = &ext_proxy_group.actors {
@@ -1168,6 +1363,12 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                     } else {
                         settings.cache_timeout = 60; // in minutes
                     }
+                    if let Some(ext_url) = &ext_proxy_group.url {
+                        settings.test_url = ext_url.to_string();
+                    }
+                    if let Some(ext_tolerance) = ext_proxy_group.tolerance {
+                        settings.tolerance = ext_tolerance as u32;
+                    }
                     let settings = settings.write_to_bytes().unwrap();
                     outbound.settings = settings;
                     outbounds.push(outbound);
@@ -1239,6 +1440,9 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                 "IP-CIDR" => {
                     rule.ip_cidrs.push(ext_filter);
                 }
+                "SRC-IP-CIDR" => {
+                    rule.source_ip_cidrs.push(ext_filter);
+                }
                 "DOMAIN" => {
                     let mut domain = internal::Router_Rule_Domain::new();
                     domain.field_type = internal::Router_Rule_Domain_Type::FULL;
@@ -1265,6 +1469,14 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                     mmdb.country_code = ext_filter;
                     rule.mmdbs.push(mmdb)
                 }
+                "SOURCE-GEOIP" => {
+                    let mut mmdb = internal::Router_Rule_Mmdb::new();
+
+                    let asset_loc = Path::new(&*crate::option::ASSET_LOCATION);
+                    mmdb.file = asset_loc.join("geo.mmdb").to_string_lossy().to_string();
+                    mmdb.country_code = ext_filter;
+                    rule.source_mmdbs.push(mmdb)
+                }
                 "EXTERNAL" => match external_rule::add_external_rule(&mut rule, &ext_filter) {
                     Ok(_) => (),
                     Err(e) => {
@@ -1283,6 +1495,9 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                 "PROCESS" => {
                     rule.processes.push(ext_filter);
                 }
+                "PROTOCOL" => {
+                    rule.protocols.push(ext_filter);
+                }
                 _ => {}
             }
             rules.push(rule);
@@ -1293,6 +1508,9 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
         if let Some(ext_domain_resolve) = ext_general.routing_domain_resolve {
             int_router.domain_resolve = ext_domain_resolve;
         }
+        if let Some(ext_route_only) = ext_general.sniff_route_only {
+            int_router.route_only = ext_route_only;
+        }
     }
     let router = protobuf::SingularPtrField::some(int_router);
 
@@ -1308,6 +1526,19 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
                 dns.servers = servers;
             }
         }
+        if let Some(ext_dns_outbound) = &ext_general.dns_outbound {
+            dns.outbound = ext_dns_outbound.clone();
+        }
+        if let Some(ext_dns_tcp_servers) = &ext_general.dns_tcp_server {
+            for ext_dns_tcp_server in ext_dns_tcp_servers {
+                dns.tcp_servers.push(ext_dns_tcp_server.clone());
+            }
+        }
+        if let Some(ext_dns_bootstrap_servers) = &ext_general.dns_bootstrap_server {
+            for ext_dns_bootstrap_server in ext_dns_bootstrap_servers {
+                dns.bootstrap_servers.push(ext_dns_bootstrap_server.clone());
+            }
+        }
     }
     if let Some(ext_hosts) = &conf.host {
         for (name, static_ips) in ext_hosts.iter() {
@@ -1329,6 +1560,9 @@ pub fn to_internal(conf: &mut Config) -> Result<internal::Config> {
             let mut api_inner = internal::Api::new();
             api_inner.address = ext_general.api_interface.as_ref().unwrap().to_string();
             api_inner.port = ext_general.api_port.unwrap() as u32;
+            if let Some(ext_api_secret) = ext_general.api_secret.as_ref() {
+                api_inner.secret = ext_api_secret.to_owned();
+            }
             protobuf::SingularPtrField::some(api_inner)
         } else {
             protobuf::SingularPtrField::none()
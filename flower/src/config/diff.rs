@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use protobuf::Message;
+
+use super::internal;
+
+/// Tags of the inbounds or outbounds changed between two configs. Items
+/// depending on an added, removed or updated one are considered updated too.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl Diff {
+    fn new<T, F, D>(old: &[T], new: &[T], tag: F, deps: D) -> Self
+    where
+        T: PartialEq,
+        F: Fn(&T) -> &str,
+        D: Fn(&T) -> Vec<String>,
+    {
+        let mut diff = Diff::default();
+        for item in new.iter() {
+            match old.iter().find(|x| tag(x) == tag(item)) {
+                None => diff.added.push(tag(item).to_string()),
+                Some(x) if x != item => diff.updated.push(tag(item).to_string()),
+                _ => (),
+            }
+        }
+        for item in old.iter() {
+            if !new.iter().any(|x| tag(x) == tag(item)) {
+                diff.removed.push(tag(item).to_string());
+            }
+        }
+        // Propagates the changes to the dependents until nothing changes.
+        let mut changed: HashSet<String> = diff
+            .added
+            .iter()
+            .chain(diff.removed.iter())
+            .chain(diff.updated.iter())
+            .cloned()
+            .collect();
+        loop {
+            let mut dependents = Vec::new();
+            for item in new.iter() {
+                if !changed.contains(tag(item)) && deps(item).iter().any(|x| changed.contains(x)) {
+                    dependents.push(tag(item).to_string());
+                }
+            }
+            if dependents.is_empty() {
+                break;
+            }
+            for t in dependents {
+                changed.insert(t.clone());
+                diff.updated.push(t);
+            }
+        }
+        diff
+    }
+
+    pub fn inbounds(old: &[internal::Inbound], new: &[internal::Inbound]) -> Self {
+        Self::new(old, new, |x| &x.tag, inbound_deps)
+    }
+
+    pub fn outbounds(old: &[internal::Outbound], new: &[internal::Outbound]) -> Self {
+        Self::new(old, new, |x| &x.tag, outbound_deps)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+
+    /// Whether the item is removed or needs to be rebuilt.
+    pub fn contains(&self, tag: &str) -> bool {
+        self.added
+            .iter()
+            .chain(self.removed.iter())
+            .chain(self.updated.iter())
+            .any(|x| x == tag)
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+        let mut parts = Vec::new();
+        for (name, tags) in [
+            ("added", &self.added),
+            ("removed", &self.removed),
+            ("updated", &self.updated),
+        ] {
+            if !tags.is_empty() {
+                parts.push(format!("{} [{}]", name, tags.join(", ")));
+            }
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+fn inbound_deps(inbound: &internal::Inbound) -> Vec<String> {
+    match inbound.protocol.as_str() {
+        "amux" => internal::AMuxInboundSettings::parse_from_bytes(&inbound.settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "chain" => internal::ChainInboundSettings::parse_from_bytes(&inbound.settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the outbounds a group or chain outbound dials.
+pub fn outbound_actors(outbound: &internal::Outbound) -> Vec<String> {
+    let settings = &outbound.settings;
+    match outbound.protocol.as_str() {
+        "tryall" => internal::TryAllOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "random" => internal::RandomOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "rr" => internal::RROutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "balance" => internal::BalanceOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "amux" => internal::AMuxOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "chain" => internal::ChainOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "retry" => internal::RetryOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "failover" => internal::FailOverOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        "select" => internal::SelectOutboundSettings::parse_from_bytes(settings)
+            .map(|x| x.actors.into_vec())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn outbound_deps(outbound: &internal::Outbound) -> Vec<String> {
+    let mut deps = outbound_actors(outbound);
+    if let Some(quota) = outbound.quota.as_ref() {
+        if !quota.fallback.is_empty() {
+            deps.push(quota.fallback.clone());
+        }
+    }
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbound(tag: &str, protocol: &str, actors: &[&str]) -> internal::Outbound {
+        let mut outbound = internal::Outbound::new();
+        outbound.tag = tag.to_string();
+        outbound.protocol = protocol.to_string();
+        if !actors.is_empty() {
+            let mut settings = internal::FailOverOutboundSettings::new();
+            settings.actors = actors.iter().map(|x| x.to_string()).collect();
+            outbound.settings = settings.write_to_bytes().unwrap();
+        }
+        outbound
+    }
+
+    #[test]
+    fn test_outbounds_diff() {
+        let old = vec![
+            outbound("direct", "direct", &[]),
+            outbound("a", "socks", &[]),
+            outbound("b", "socks", &[]),
+            outbound("failover", "failover", &["a", "direct"]),
+            outbound("outer", "failover", &["failover"]),
+        ];
+        let diff = Diff::outbounds(&old, &old);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no changes");
+
+        let mut new = vec![
+            outbound("direct", "direct", &[]),
+            outbound("a", "socks", &[]),
+            outbound("c", "socks", &[]),
+            outbound("failover", "failover", &["a", "direct"]),
+            outbound("outer", "failover", &["failover"]),
+        ];
+        new[1].bind = "127.0.0.1".to_string();
+        let diff = Diff::outbounds(&old, &new);
+        assert_eq!(diff.added, vec!["c"]);
+        assert_eq!(diff.removed, vec!["b"]);
+        assert_eq!(diff.updated, vec!["a", "failover", "outer"]);
+        assert!(diff.contains("b"));
+        assert!(!diff.contains("direct"));
+        assert_eq!(
+            diff.to_string(),
+            "added [c], removed [b], updated [a, failover, outer]"
+        );
+    }
+
+    #[test]
+    fn test_inbounds_diff() {
+        let mut socks = internal::Inbound::new();
+        socks.tag = "socks".to_string();
+        socks.protocol = "socks".to_string();
+        socks.port = 1080;
+        let mut http = socks.clone();
+        http.tag = "http".to_string();
+        http.protocol = "http".to_string();
+        http.port = 1087;
+        let old = vec![socks.clone(), http.clone()];
+        socks.port = 1081;
+        let new = vec![socks, http];
+        let diff = Diff::inbounds(&old, &new);
+        assert_eq!(diff.updated, vec!["socks"]);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+}
@@ -1,9 +1,11 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
 use anyhow::anyhow;
 use anyhow::Result;
+use protobuf::Message;
 
 use super::{geosite, internal};
 
@@ -35,6 +37,9 @@ pub fn load_site_rule(filter: &str) -> Result<(String, String)> {
     load_file_or_default(filter, "site.dat")
 }
 
+/// Adds the rules of an external filter, either `mmdb:[<file>:]<code>`, or
+/// `site:[<path>:]<tag>` (also `geosite:`), where the path is a v2ray site
+/// file, or a directory of plain domain list files named by tag.
 pub fn add_external_rule(rule: &mut internal::Router_Rule, ext_external: &str) -> Result<()> {
     if ext_external.starts_with("mmdb") {
         let (file, code) = match load_mmdb_rule(ext_external) {
@@ -49,7 +54,7 @@ pub fn add_external_rule(rule: &mut internal::Router_Rule, ext_external: &str) -
         rule.mmdbs.push(mmdb)
     }
 
-    if ext_external.starts_with("site") {
+    if ext_external.starts_with("site") || ext_external.starts_with("geosite") {
         let (file, code) = match load_site_rule(ext_external) {
             Ok((f, c)) => (f, c),
             Err(e) => {
@@ -57,6 +62,13 @@ pub fn add_external_rule(rule: &mut internal::Router_Rule, ext_external: &str) -
             }
         };
 
+        if Path::new(&file).is_dir() {
+            for domain in load_site_list(&file, &code, &mut Vec::new())? {
+                rule.domains.push(domain);
+            }
+            return Ok(());
+        }
+
         // Loads SiteGroup objects one by one instead of loading the whole list.
         let mut reader = BufReader::with_capacity(2048, File::open(&file)?);
         let mut input = protobuf::CodedInputStream::new(&mut reader);
@@ -65,29 +77,9 @@ pub fn add_external_rule(rule: &mut internal::Router_Rule, ext_external: &str) -
             let mut site_group = input.read_message::<geosite::SiteGroup>()?;
             if site_group.tag == code.to_uppercase() {
                 for domain in site_group.domain.iter_mut() {
-                    let mut domain_rule = match domain.field_type {
-                        geosite::Domain_Type::Plain => {
-                            let mut d = internal::Router_Rule_Domain::new();
-                            d.field_type = internal::Router_Rule_Domain_Type::PLAIN;
-                            d
-                        }
-                        geosite::Domain_Type::Domain => {
-                            let mut d = internal::Router_Rule_Domain::new();
-                            d.field_type = internal::Router_Rule_Domain_Type::DOMAIN;
-                            d
-                        }
-                        geosite::Domain_Type::Full => {
-                            let mut d = internal::Router_Rule_Domain::new();
-                            d.field_type = internal::Router_Rule_Domain_Type::FULL;
-                            d
-                        }
-                        _ => {
-                            continue;
-                        }
-                    };
                     let value = std::mem::take(&mut domain.value);
-                    domain_rule.value = value;
-                    rule.domains.push(domain_rule);
+                    rule.domains
+                        .push(new_domain(site_domain_type(domain), value));
                 }
                 println!(
                     "loaded {} domain rules from [{}] for tag [{}]",
@@ -95,9 +87,114 @@ pub fn add_external_rule(rule: &mut internal::Router_Rule, ext_external: &str) -
                     file,
                     code
                 );
-                break; // assume at most 1 matched tag
+                return Ok(());
             }
         }
+        return Err(anyhow!("site tag [{}] not found in [{}]", code, file));
+    }
+    Ok(())
+}
+
+/// Adds the domains of a site tag like `add_external_rule`, a v2ray site
+/// file is parsed as a whole the first time it's referred to and kept in the
+/// cache for the other rules referring to it.
+pub fn add_site_rule(
+    rule: &mut internal::Router_Rule,
+    ext_site: &str,
+    site_lists: &mut HashMap<String, geosite::SiteGroupList>,
+) -> Result<()> {
+    let (file, code) = match load_site_rule(ext_site) {
+        Ok((f, c)) => (f, c),
+        Err(e) => {
+            return Err(anyhow!("load site rule failed: {}", e));
+        }
+    };
+
+    if Path::new(&file).is_dir() {
+        for domain in load_site_list(&file, &code, &mut Vec::new())? {
+            rule.domains.push(domain);
+        }
+        return Ok(());
+    }
+
+    if !site_lists.contains_key(&file) {
+        let site_list = geosite::SiteGroupList::parse_from_bytes(&std::fs::read(&file)?)?;
+        site_lists.insert(file.clone(), site_list);
+    }
+    let site_group = site_lists[&file]
+        .site_group
+        .iter()
+        .find(|x| x.tag == code.to_uppercase())
+        .ok_or_else(|| anyhow!("site tag [{}] not found in [{}]", code, file))?;
+    for domain in site_group.domain.iter() {
+        rule.domains
+            .push(new_domain(site_domain_type(domain), domain.value.clone()));
     }
     Ok(())
 }
+
+fn site_domain_type(domain: &geosite::Domain) -> internal::Router_Rule_Domain_Type {
+    match domain.field_type {
+        geosite::Domain_Type::Plain => internal::Router_Rule_Domain_Type::PLAIN,
+        geosite::Domain_Type::Regex => internal::Router_Rule_Domain_Type::REGEX,
+        geosite::Domain_Type::Domain => internal::Router_Rule_Domain_Type::DOMAIN,
+        geosite::Domain_Type::Full => internal::Router_Rule_Domain_Type::FULL,
+    }
+}
+
+fn new_domain(
+    field_type: internal::Router_Rule_Domain_Type,
+    value: String,
+) -> internal::Router_Rule_Domain {
+    let mut domain = internal::Router_Rule_Domain::new();
+    domain.field_type = field_type;
+    domain.value = value;
+    domain
+}
+
+// Reads a category from a directory of plain domain list files in the format
+// of domain-list-community, one entry per line, optionally prefixed with
+// `domain:`, `full:`, `keyword:`, `regexp:` or `include:`.
+fn load_site_list(
+    dir: &str,
+    category: &str,
+    included: &mut Vec<String>,
+) -> Result<Vec<internal::Router_Rule_Domain>> {
+    if included.iter().any(|x| x == category) {
+        return Err(anyhow!("site tag [{}] includes itself", category));
+    }
+    included.push(category.to_string());
+    let path = Path::new(dir).join(category);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|_| anyhow!("site tag [{}] not found in [{}]", category, dir))?;
+    let mut domains = Vec::new();
+    for line in content.lines() {
+        // Drops comments and attributes, e.g. `ads.example.com @ads # comment`.
+        let line = line.split('#').next().unwrap_or_default();
+        let entry = match line.split_whitespace().next() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let (prefix, value) = entry.split_once(':').unwrap_or(("domain", entry));
+        let field_type = match prefix {
+            "domain" => internal::Router_Rule_Domain_Type::DOMAIN,
+            "full" => internal::Router_Rule_Domain_Type::FULL,
+            "keyword" => internal::Router_Rule_Domain_Type::PLAIN,
+            "regexp" => internal::Router_Rule_Domain_Type::REGEX,
+            "include" => {
+                domains.append(&mut load_site_list(dir, value, included)?);
+                continue;
+            }
+            _ => {
+                return Err(anyhow!(
+                    "invalid entry [{}] in [{}]",
+                    entry,
+                    path.to_string_lossy()
+                ))
+            }
+        };
+        domains.push(new_domain(field_type, value.to_string()));
+    }
+    included.pop();
+    Ok(domains)
+}
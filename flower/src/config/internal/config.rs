@@ -28,6 +28,7 @@ pub struct Api {
     // message fields
     pub address: ::std::string::String,
     pub port: u32,
+    pub secret: ::std::string::String,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -57,6 +58,13 @@ impl Api {
     pub fn get_port(&self) -> u32 {
         self.port
     }
+
+    // string secret = 3;
+
+
+    pub fn get_secret(&self) -> &str {
+        &self.secret
+    }
 }
 
 impl ::protobuf::Message for Api {
@@ -78,6 +86,9 @@ impl ::protobuf::Message for Api {
                     let tmp = is.read_uint32()?;
                     self.port = tmp;
                 },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.secret)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -96,6 +107,9 @@ impl ::protobuf::Message for Api {
         if self.port != 0 {
             my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
         }
+        if !self.secret.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.secret);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -108,6 +122,9 @@ impl ::protobuf::Message for Api {
         if self.port != 0 {
             os.write_uint32(2, self.port)?;
         }
+        if !self.secret.is_empty() {
+            os.write_string(3, &self.secret)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -152,6 +169,7 @@ impl ::protobuf::Clear for Api {
     fn clear(&mut self) {
         self.address.clear();
         self.port = 0;
+        self.secret.clear();
         self.unknown_fields.clear();
     }
 }
@@ -167,6 +185,14 @@ pub struct Dns {
     // message fields
     pub servers: ::protobuf::RepeatedField<::std::string::String>,
     pub hosts: ::std::collections::HashMap<::std::string::String, Dns_Ips>,
+    pub outbound: ::std::string::String,
+    pub doh_servers: ::protobuf::RepeatedField<::std::string::String>,
+    pub cache_size: u32,
+    pub disable_cache: bool,
+    pub fake_dns: ::protobuf::SingularPtrField<Dns_FakeDns>,
+    pub tcp_servers: ::protobuf::RepeatedField<::std::string::String>,
+    pub bootstrap_servers: ::protobuf::RepeatedField<::std::string::String>,
+    pub ip_strategy: Dns_IpStrategy,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -196,10 +222,71 @@ impl Dns {
     pub fn get_hosts(&self) -> &::std::collections::HashMap<::std::string::String, Dns_Ips> {
         &self.hosts
     }
+
+    // string outbound = 4;
+
+
+    pub fn get_outbound(&self) -> &str {
+        &self.outbound
+    }
+
+    // repeated string doh_servers = 5;
+
+
+    pub fn get_doh_servers(&self) -> &[::std::string::String] {
+        &self.doh_servers
+    }
+
+    // uint32 cache_size = 6;
+
+
+    pub fn get_cache_size(&self) -> u32 {
+        self.cache_size
+    }
+
+    // bool disable_cache = 7;
+
+
+    pub fn get_disable_cache(&self) -> bool {
+        self.disable_cache
+    }
+
+    // .Dns.FakeDns fake_dns = 8;
+
+
+    pub fn get_fake_dns(&self) -> &Dns_FakeDns {
+        self.fake_dns.as_ref().unwrap_or_else(|| <Dns_FakeDns as ::protobuf::Message>::default_instance())
+    }
+
+    // repeated string tcp_servers = 9;
+
+
+    pub fn get_tcp_servers(&self) -> &[::std::string::String] {
+        &self.tcp_servers
+    }
+
+    // repeated string bootstrap_servers = 10;
+
+
+    pub fn get_bootstrap_servers(&self) -> &[::std::string::String] {
+        &self.bootstrap_servers
+    }
+
+    // .Dns.IpStrategy ip_strategy = 11;
+
+
+    pub fn get_ip_strategy(&self) -> Dns_IpStrategy {
+        self.ip_strategy
+    }
 }
 
 impl ::protobuf::Message for Dns {
     fn is_initialized(&self) -> bool {
+        for v in &self.fake_dns {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
         true
     }
 
@@ -213,6 +300,38 @@ impl ::protobuf::Message for Dns {
                 3 => {
                     ::protobuf::rt::read_map_into::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeMessage<Dns_Ips>>(wire_type, is, &mut self.hosts)?;
                 },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.outbound)?;
+                },
+                5 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.doh_servers)?;
+                },
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.cache_size = tmp;
+                },
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.disable_cache = tmp;
+                },
+                8 => {
+                    ::protobuf::rt::read_singular_message_into(wire_type, is, &mut self.fake_dns)?;
+                },
+                9 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.tcp_servers)?;
+                },
+                10 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.bootstrap_servers)?;
+                },
+                11 => {
+                    ::protobuf::rt::read_proto3_enum_with_unknown_fields_into(wire_type, is, &mut self.ip_strategy, 11, &mut self.unknown_fields)?
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -229,6 +348,31 @@ impl ::protobuf::Message for Dns {
             my_size += ::protobuf::rt::string_size(1, &value);
         };
         my_size += ::protobuf::rt::compute_map_size::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeMessage<Dns_Ips>>(3, &self.hosts);
+        if !self.outbound.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.outbound);
+        }
+        for value in &self.doh_servers {
+            my_size += ::protobuf::rt::string_size(5, &value);
+        };
+        if self.cache_size != 0 {
+            my_size += ::protobuf::rt::value_size(6, self.cache_size, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.disable_cache != false {
+            my_size += 2;
+        }
+        if let Some(ref v) = self.fake_dns.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        }
+        for value in &self.tcp_servers {
+            my_size += ::protobuf::rt::string_size(9, &value);
+        };
+        for value in &self.bootstrap_servers {
+            my_size += ::protobuf::rt::string_size(10, &value);
+        };
+        if self.ip_strategy != Dns_IpStrategy::DEFAULT {
+            my_size += ::protobuf::rt::enum_size(11, self.ip_strategy);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -239,6 +383,32 @@ impl ::protobuf::Message for Dns {
             os.write_string(1, &v)?;
         };
         ::protobuf::rt::write_map_with_cached_sizes::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeMessage<Dns_Ips>>(3, &self.hosts, os)?;
+        if !self.outbound.is_empty() {
+            os.write_string(4, &self.outbound)?;
+        }
+        for v in &self.doh_servers {
+            os.write_string(5, &v)?;
+        };
+        if self.cache_size != 0 {
+            os.write_uint32(6, self.cache_size)?;
+        }
+        if self.disable_cache != false {
+            os.write_bool(7, self.disable_cache)?;
+        }
+        if let Some(ref v) = self.fake_dns.as_ref() {
+            os.write_tag(8, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        }
+        for v in &self.tcp_servers {
+            os.write_string(9, &v)?;
+        };
+        for v in &self.bootstrap_servers {
+            os.write_string(10, &v)?;
+        };
+        if self.ip_strategy != Dns_IpStrategy::DEFAULT {
+            os.write_enum(11, ::protobuf::ProtobufEnum::value(&self.ip_strategy))?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -283,6 +453,14 @@ impl ::protobuf::Clear for Dns {
     fn clear(&mut self) {
         self.servers.clear();
         self.hosts.clear();
+        self.outbound.clear();
+        self.doh_servers.clear();
+        self.cache_size = 0;
+        self.disable_cache = false;
+        self.fake_dns.clear();
+        self.tcp_servers.clear();
+        self.bootstrap_servers.clear();
+        self.ip_strategy = Dns_IpStrategy::DEFAULT;
         self.unknown_fields.clear();
     }
 }
@@ -411,50 +589,50 @@ impl ::protobuf::reflect::ProtobufValue for Dns_Ips {
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct Log {
+pub struct Dns_FakeDns {
     // message fields
-    pub level: Log_Level,
-    pub output: Log_Output,
-    pub output_file: ::std::string::String,
+    pub range: ::std::string::String,
+    pub include: ::protobuf::RepeatedField<::std::string::String>,
+    pub exclude: ::protobuf::RepeatedField<::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a Log {
-    fn default() -> &'a Log {
-        <Log as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a Dns_FakeDns {
+    fn default() -> &'a Dns_FakeDns {
+        <Dns_FakeDns as ::protobuf::Message>::default_instance()
     }
 }
 
-impl Log {
-    pub fn new() -> Log {
+impl Dns_FakeDns {
+    pub fn new() -> Dns_FakeDns {
         ::std::default::Default::default()
     }
 
-    // .Log.Level level = 1;
+    // string range = 1;
 
 
-    pub fn get_level(&self) -> Log_Level {
-        self.level
+    pub fn get_range(&self) -> &str {
+        &self.range
     }
 
-    // .Log.Output output = 2;
+    // repeated string include = 2;
 
 
-    pub fn get_output(&self) -> Log_Output {
-        self.output
+    pub fn get_include(&self) -> &[::std::string::String] {
+        &self.include
     }
 
-    // string output_file = 3;
+    // repeated string exclude = 3;
 
 
-    pub fn get_output_file(&self) -> &str {
-        &self.output_file
+    pub fn get_exclude(&self) -> &[::std::string::String] {
+        &self.exclude
     }
 }
 
-impl ::protobuf::Message for Log {
+impl ::protobuf::Message for Dns_FakeDns {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -464,13 +642,13 @@ impl ::protobuf::Message for Log {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_proto3_enum_with_unknown_fields_into(wire_type, is, &mut self.level, 1, &mut self.unknown_fields)?
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.range)?;
                 },
                 2 => {
-                    ::protobuf::rt::read_proto3_enum_with_unknown_fields_into(wire_type, is, &mut self.output, 2, &mut self.unknown_fields)?
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.include)?;
                 },
                 3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.output_file)?;
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.exclude)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -484,30 +662,30 @@ impl ::protobuf::Message for Log {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if self.level != Log_Level::INFO {
-            my_size += ::protobuf::rt::enum_size(1, self.level);
-        }
-        if self.output != Log_Output::CONSOLE {
-            my_size += ::protobuf::rt::enum_size(2, self.output);
-        }
-        if !self.output_file.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.output_file);
+        if !self.range.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.range);
         }
+        for value in &self.include {
+            my_size += ::protobuf::rt::string_size(2, &value);
+        };
+        for value in &self.exclude {
+            my_size += ::protobuf::rt::string_size(3, &value);
+        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if self.level != Log_Level::INFO {
-            os.write_enum(1, ::protobuf::ProtobufEnum::value(&self.level))?;
-        }
-        if self.output != Log_Output::CONSOLE {
-            os.write_enum(2, ::protobuf::ProtobufEnum::value(&self.output))?;
-        }
-        if !self.output_file.is_empty() {
-            os.write_string(3, &self.output_file)?;
+        if !self.range.is_empty() {
+            os.write_string(1, &self.range)?;
         }
+        for v in &self.include {
+            os.write_string(2, &v)?;
+        };
+        for v in &self.exclude {
+            os.write_string(3, &v)?;
+        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -538,219 +716,168 @@ impl ::protobuf::Message for Log {
         Self::descriptor_static()
     }
 
-    fn new() -> Log {
-        Log::new()
+    fn new() -> Dns_FakeDns {
+        Dns_FakeDns::new()
     }
 
-    fn default_instance() -> &'static Log {
-        static instance: ::protobuf::rt::LazyV2<Log> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(Log::new)
+    fn default_instance() -> &'static Dns_FakeDns {
+        static instance: ::protobuf::rt::LazyV2<Dns_FakeDns> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Dns_FakeDns::new)
     }
 }
 
-impl ::protobuf::Clear for Log {
+impl ::protobuf::Clear for Dns_FakeDns {
     fn clear(&mut self) {
-        self.level = Log_Level::INFO;
-        self.output = Log_Output::CONSOLE;
-        self.output_file.clear();
+        self.range.clear();
+        self.include.clear();
+        self.exclude.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for Log {
+impl ::protobuf::reflect::ProtobufValue for Dns_FakeDns {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(Clone,PartialEq,Eq,Debug,Hash)]
-pub enum Log_Level {
-    INFO = 0,
-    TRACE = 1,
-    DEBUG = 2,
-    WARN = 3,
-    ERROR = 4,
-}
-
-impl ::protobuf::ProtobufEnum for Log_Level {
-    fn value(&self) -> i32 {
-        *self as i32
-    }
-
-    fn from_i32(value: i32) -> ::std::option::Option<Log_Level> {
-        match value {
-            0 => ::std::option::Option::Some(Log_Level::INFO),
-            1 => ::std::option::Option::Some(Log_Level::TRACE),
-            2 => ::std::option::Option::Some(Log_Level::DEBUG),
-            3 => ::std::option::Option::Some(Log_Level::WARN),
-            4 => ::std::option::Option::Some(Log_Level::ERROR),
-            _ => ::std::option::Option::None
-        }
-    }
-
-    fn values() -> &'static [Self] {
-        static values: &'static [Log_Level] = &[
-            Log_Level::INFO,
-            Log_Level::TRACE,
-            Log_Level::DEBUG,
-            Log_Level::WARN,
-            Log_Level::ERROR,
-        ];
-        values
-    }
-}
-
-impl ::std::marker::Copy for Log_Level {
-}
-
-impl ::std::default::Default for Log_Level {
-    fn default() -> Self {
-        Log_Level::INFO
-    }
-}
-
-impl ::protobuf::reflect::ProtobufValue for Log_Level {
-    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
-        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
-    }
-}
-
-#[derive(Clone,PartialEq,Eq,Debug,Hash)]
-pub enum Log_Output {
-    CONSOLE = 0,
-    FILE = 1,
+pub enum Dns_IpStrategy {
+    DEFAULT = 0,
+    PREFER_IPV4 = 1,
+    PREFER_IPV6 = 2,
+    IPV4_ONLY = 3,
+    IPV6_ONLY = 4,
 }
 
-impl ::protobuf::ProtobufEnum for Log_Output {
+impl ::protobuf::ProtobufEnum for Dns_IpStrategy {
     fn value(&self) -> i32 {
         *self as i32
     }
 
-    fn from_i32(value: i32) -> ::std::option::Option<Log_Output> {
+    fn from_i32(value: i32) -> ::std::option::Option<Dns_IpStrategy> {
         match value {
-            0 => ::std::option::Option::Some(Log_Output::CONSOLE),
-            1 => ::std::option::Option::Some(Log_Output::FILE),
+            0 => ::std::option::Option::Some(Dns_IpStrategy::DEFAULT),
+            1 => ::std::option::Option::Some(Dns_IpStrategy::PREFER_IPV4),
+            2 => ::std::option::Option::Some(Dns_IpStrategy::PREFER_IPV6),
+            3 => ::std::option::Option::Some(Dns_IpStrategy::IPV4_ONLY),
+            4 => ::std::option::Option::Some(Dns_IpStrategy::IPV6_ONLY),
             _ => ::std::option::Option::None
         }
     }
 
     fn values() -> &'static [Self] {
-        static values: &'static [Log_Output] = &[
-            Log_Output::CONSOLE,
-            Log_Output::FILE,
+        static values: &'static [Dns_IpStrategy] = &[
+            Dns_IpStrategy::DEFAULT,
+            Dns_IpStrategy::PREFER_IPV4,
+            Dns_IpStrategy::PREFER_IPV6,
+            Dns_IpStrategy::IPV4_ONLY,
+            Dns_IpStrategy::IPV6_ONLY,
         ];
         values
     }
 }
 
-impl ::std::marker::Copy for Log_Output {
+impl ::std::marker::Copy for Dns_IpStrategy {
 }
 
-impl ::std::default::Default for Log_Output {
+impl ::std::default::Default for Dns_IpStrategy {
     fn default() -> Self {
-        Log_Output::CONSOLE
+        Dns_IpStrategy::DEFAULT
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for Log_Output {
+impl ::protobuf::reflect::ProtobufValue for Dns_IpStrategy {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct TunInboundSettings {
+pub struct Log {
     // message fields
-    pub fd: i32,
-    pub auto: bool,
-    pub name: ::std::string::String,
-    pub address: ::std::string::String,
-    pub gateway: ::std::string::String,
-    pub netmask: ::std::string::String,
-    pub mtu: i32,
-    pub fake_dns_exclude: ::protobuf::RepeatedField<::std::string::String>,
-    pub fake_dns_include: ::protobuf::RepeatedField<::std::string::String>,
+    pub level: Log_Level,
+    pub output: Log_Output,
+    pub output_file: ::std::string::String,
+    pub redact_user: bool,
+    pub target_levels: ::protobuf::RepeatedField<::std::string::String>,
+    pub format: Log_Format,
+    pub max_size_mb: u32,
+    pub max_files: u32,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a TunInboundSettings {
-    fn default() -> &'a TunInboundSettings {
-        <TunInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a Log {
+    fn default() -> &'a Log {
+        <Log as ::protobuf::Message>::default_instance()
     }
 }
 
-impl TunInboundSettings {
-    pub fn new() -> TunInboundSettings {
+impl Log {
+    pub fn new() -> Log {
         ::std::default::Default::default()
     }
 
-    // int32 fd = 1;
+    // .Log.Level level = 1;
 
 
-    pub fn get_fd(&self) -> i32 {
-        self.fd
+    pub fn get_level(&self) -> Log_Level {
+        self.level
     }
 
-    // bool auto = 9;
+    // .Log.Output output = 2;
 
 
-    pub fn get_auto(&self) -> bool {
-        self.auto
+    pub fn get_output(&self) -> Log_Output {
+        self.output
     }
 
-    // string name = 2;
+    // string output_file = 3;
 
 
-    pub fn get_name(&self) -> &str {
-        &self.name
-    }
-
-    // string address = 3;
-
-
-    pub fn get_address(&self) -> &str {
-        &self.address
+    pub fn get_output_file(&self) -> &str {
+        &self.output_file
     }
 
-    // string gateway = 4;
+    // bool redact_user = 4;
 
 
-    pub fn get_gateway(&self) -> &str {
-        &self.gateway
+    pub fn get_redact_user(&self) -> bool {
+        self.redact_user
     }
 
-    // string netmask = 5;
+    // repeated string target_levels = 5;
 
 
-    pub fn get_netmask(&self) -> &str {
-        &self.netmask
+    pub fn get_target_levels(&self) -> &[::std::string::String] {
+        &self.target_levels
     }
 
-    // int32 mtu = 6;
+    // .Log.Format format = 6;
 
 
-    pub fn get_mtu(&self) -> i32 {
-        self.mtu
+    pub fn get_format(&self) -> Log_Format {
+        self.format
     }
 
-    // repeated string fake_dns_exclude = 7;
+    // uint32 max_size_mb = 7;
 
 
-    pub fn get_fake_dns_exclude(&self) -> &[::std::string::String] {
-        &self.fake_dns_exclude
+    pub fn get_max_size_mb(&self) -> u32 {
+        self.max_size_mb
     }
 
-    // repeated string fake_dns_include = 8;
+    // uint32 max_files = 8;
 
 
-    pub fn get_fake_dns_include(&self) -> &[::std::string::String] {
-        &self.fake_dns_include
+    pub fn get_max_files(&self) -> u32 {
+        self.max_files
     }
 }
 
-impl ::protobuf::Message for TunInboundSettings {
+impl ::protobuf::Message for Log {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -760,43 +887,40 @@ impl ::protobuf::Message for TunInboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
-                    }
-                    let tmp = is.read_int32()?;
-                    self.fd = tmp;
-                },
-                9 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
-                    }
-                    let tmp = is.read_bool()?;
-                    self.auto = tmp;
+                    ::protobuf::rt::read_proto3_enum_with_unknown_fields_into(wire_type, is, &mut self.level, 1, &mut self.unknown_fields)?
                 },
                 2 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.name)?;
+                    ::protobuf::rt::read_proto3_enum_with_unknown_fields_into(wire_type, is, &mut self.output, 2, &mut self.unknown_fields)?
                 },
                 3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.output_file)?;
                 },
                 4 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.gateway)?;
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.redact_user = tmp;
                 },
                 5 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.netmask)?;
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.target_levels)?;
                 },
                 6 => {
+                    ::protobuf::rt::read_proto3_enum_with_unknown_fields_into(wire_type, is, &mut self.format, 6, &mut self.unknown_fields)?
+                },
+                7 => {
                     if wire_type != ::protobuf::wire_format::WireTypeVarint {
                         return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
                     }
-                    let tmp = is.read_int32()?;
-                    self.mtu = tmp;
-                },
-                7 => {
-                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.fake_dns_exclude)?;
+                    let tmp = is.read_uint32()?;
+                    self.max_size_mb = tmp;
                 },
                 8 => {
-                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.fake_dns_include)?;
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.max_files = tmp;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -810,66 +934,60 @@ impl ::protobuf::Message for TunInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if self.fd != 0 {
-            my_size += ::protobuf::rt::value_size(1, self.fd, ::protobuf::wire_format::WireTypeVarint);
+        if self.level != Log_Level::INFO {
+            my_size += ::protobuf::rt::enum_size(1, self.level);
         }
-        if self.auto != false {
-            my_size += 2;
+        if self.output != Log_Output::CONSOLE {
+            my_size += ::protobuf::rt::enum_size(2, self.output);
         }
-        if !self.name.is_empty() {
-            my_size += ::protobuf::rt::string_size(2, &self.name);
+        if !self.output_file.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.output_file);
         }
-        if !self.address.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.address);
+        if self.redact_user != false {
+            my_size += 2;
         }
-        if !self.gateway.is_empty() {
-            my_size += ::protobuf::rt::string_size(4, &self.gateway);
+        for value in &self.target_levels {
+            my_size += ::protobuf::rt::string_size(5, &value);
+        };
+        if self.format != Log_Format::TEXT {
+            my_size += ::protobuf::rt::enum_size(6, self.format);
         }
-        if !self.netmask.is_empty() {
-            my_size += ::protobuf::rt::string_size(5, &self.netmask);
+        if self.max_size_mb != 0 {
+            my_size += ::protobuf::rt::value_size(7, self.max_size_mb, ::protobuf::wire_format::WireTypeVarint);
         }
-        if self.mtu != 0 {
-            my_size += ::protobuf::rt::value_size(6, self.mtu, ::protobuf::wire_format::WireTypeVarint);
+        if self.max_files != 0 {
+            my_size += ::protobuf::rt::value_size(8, self.max_files, ::protobuf::wire_format::WireTypeVarint);
         }
-        for value in &self.fake_dns_exclude {
-            my_size += ::protobuf::rt::string_size(7, &value);
-        };
-        for value in &self.fake_dns_include {
-            my_size += ::protobuf::rt::string_size(8, &value);
-        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if self.fd != 0 {
-            os.write_int32(1, self.fd)?;
+        if self.level != Log_Level::INFO {
+            os.write_enum(1, ::protobuf::ProtobufEnum::value(&self.level))?;
         }
-        if self.auto != false {
-            os.write_bool(9, self.auto)?;
+        if self.output != Log_Output::CONSOLE {
+            os.write_enum(2, ::protobuf::ProtobufEnum::value(&self.output))?;
         }
-        if !self.name.is_empty() {
-            os.write_string(2, &self.name)?;
+        if !self.output_file.is_empty() {
+            os.write_string(3, &self.output_file)?;
         }
-        if !self.address.is_empty() {
-            os.write_string(3, &self.address)?;
+        if self.redact_user != false {
+            os.write_bool(4, self.redact_user)?;
         }
-        if !self.gateway.is_empty() {
-            os.write_string(4, &self.gateway)?;
+        for v in &self.target_levels {
+            os.write_string(5, &v)?;
+        };
+        if self.format != Log_Format::TEXT {
+            os.write_enum(6, ::protobuf::ProtobufEnum::value(&self.format))?;
         }
-        if !self.netmask.is_empty() {
-            os.write_string(5, &self.netmask)?;
+        if self.max_size_mb != 0 {
+            os.write_uint32(7, self.max_size_mb)?;
         }
-        if self.mtu != 0 {
-            os.write_int32(6, self.mtu)?;
+        if self.max_files != 0 {
+            os.write_uint32(8, self.max_files)?;
         }
-        for v in &self.fake_dns_exclude {
-            os.write_string(7, &v)?;
-        };
-        for v in &self.fake_dns_include {
-            os.write_string(8, &v)?;
-        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -900,74 +1018,1865 @@ impl ::protobuf::Message for TunInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> TunInboundSettings {
-        TunInboundSettings::new()
+    fn new() -> Log {
+        Log::new()
     }
 
-    fn default_instance() -> &'static TunInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<TunInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(TunInboundSettings::new)
+    fn default_instance() -> &'static Log {
+        static instance: ::protobuf::rt::LazyV2<Log> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Log::new)
     }
 }
 
-impl ::protobuf::Clear for TunInboundSettings {
+impl ::protobuf::Clear for Log {
     fn clear(&mut self) {
-        self.fd = 0;
-        self.auto = false;
-        self.name.clear();
-        self.address.clear();
-        self.gateway.clear();
-        self.netmask.clear();
-        self.mtu = 0;
-        self.fake_dns_exclude.clear();
-        self.fake_dns_include.clear();
+        self.level = Log_Level::INFO;
+        self.output = Log_Output::CONSOLE;
+        self.output_file.clear();
+        self.redact_user = false;
+        self.target_levels.clear();
+        self.format = Log_Format::TEXT;
+        self.max_size_mb = 0;
+        self.max_files = 0;
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Log {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum Log_Level {
+    INFO = 0,
+    TRACE = 1,
+    DEBUG = 2,
+    WARN = 3,
+    ERROR = 4,
+}
+
+impl ::protobuf::ProtobufEnum for Log_Level {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<Log_Level> {
+        match value {
+            0 => ::std::option::Option::Some(Log_Level::INFO),
+            1 => ::std::option::Option::Some(Log_Level::TRACE),
+            2 => ::std::option::Option::Some(Log_Level::DEBUG),
+            3 => ::std::option::Option::Some(Log_Level::WARN),
+            4 => ::std::option::Option::Some(Log_Level::ERROR),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [Log_Level] = &[
+            Log_Level::INFO,
+            Log_Level::TRACE,
+            Log_Level::DEBUG,
+            Log_Level::WARN,
+            Log_Level::ERROR,
+        ];
+        values
+    }
+}
+
+impl ::std::marker::Copy for Log_Level {
+}
+
+impl ::std::default::Default for Log_Level {
+    fn default() -> Self {
+        Log_Level::INFO
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Log_Level {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum Log_Output {
+    CONSOLE = 0,
+    FILE = 1,
+}
+
+impl ::protobuf::ProtobufEnum for Log_Output {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<Log_Output> {
+        match value {
+            0 => ::std::option::Option::Some(Log_Output::CONSOLE),
+            1 => ::std::option::Option::Some(Log_Output::FILE),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [Log_Output] = &[
+            Log_Output::CONSOLE,
+            Log_Output::FILE,
+        ];
+        values
+    }
+}
+
+impl ::std::marker::Copy for Log_Output {
+}
+
+impl ::std::default::Default for Log_Output {
+    fn default() -> Self {
+        Log_Output::CONSOLE
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Log_Output {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum Log_Format {
+    TEXT = 0,
+    JSON = 1,
+}
+
+impl ::protobuf::ProtobufEnum for Log_Format {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<Log_Format> {
+        match value {
+            0 => ::std::option::Option::Some(Log_Format::TEXT),
+            1 => ::std::option::Option::Some(Log_Format::JSON),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [Log_Format] = &[
+            Log_Format::TEXT,
+            Log_Format::JSON,
+        ];
+        values
+    }
+}
+
+impl ::std::marker::Copy for Log_Format {
+}
+
+impl ::std::default::Default for Log_Format {
+    fn default() -> Self {
+        Log_Format::TEXT
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Log_Format {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct TunInboundSettings {
+    // message fields
+    pub fd: i32,
+    pub auto: bool,
+    pub name: ::std::string::String,
+    pub address: ::std::string::String,
+    pub gateway: ::std::string::String,
+    pub netmask: ::std::string::String,
+    pub mtu: i32,
+    pub fake_dns_exclude: ::protobuf::RepeatedField<::std::string::String>,
+    pub fake_dns_include: ::protobuf::RepeatedField<::std::string::String>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a TunInboundSettings {
+    fn default() -> &'a TunInboundSettings {
+        <TunInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl TunInboundSettings {
+    pub fn new() -> TunInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // int32 fd = 1;
+
+
+    pub fn get_fd(&self) -> i32 {
+        self.fd
+    }
+
+    // bool auto = 9;
+
+
+    pub fn get_auto(&self) -> bool {
+        self.auto
+    }
+
+    // string name = 2;
+
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    // string address = 3;
+
+
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    // string gateway = 4;
+
+
+    pub fn get_gateway(&self) -> &str {
+        &self.gateway
+    }
+
+    // string netmask = 5;
+
+
+    pub fn get_netmask(&self) -> &str {
+        &self.netmask
+    }
+
+    // int32 mtu = 6;
+
+
+    pub fn get_mtu(&self) -> i32 {
+        self.mtu
+    }
+
+    // repeated string fake_dns_exclude = 7;
+
+
+    pub fn get_fake_dns_exclude(&self) -> &[::std::string::String] {
+        &self.fake_dns_exclude
+    }
+
+    // repeated string fake_dns_include = 8;
+
+
+    pub fn get_fake_dns_include(&self) -> &[::std::string::String] {
+        &self.fake_dns_include
+    }
+}
+
+impl ::protobuf::Message for TunInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_int32()?;
+                    self.fd = tmp;
+                },
+                9 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.auto = tmp;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.name)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.gateway)?;
+                },
+                5 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.netmask)?;
+                },
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_int32()?;
+                    self.mtu = tmp;
+                },
+                7 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.fake_dns_exclude)?;
+                },
+                8 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.fake_dns_include)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if self.fd != 0 {
+            my_size += ::protobuf::rt::value_size(1, self.fd, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.auto != false {
+            my_size += 2;
+        }
+        if !self.name.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.name);
+        }
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.address);
+        }
+        if !self.gateway.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.gateway);
+        }
+        if !self.netmask.is_empty() {
+            my_size += ::protobuf::rt::string_size(5, &self.netmask);
+        }
+        if self.mtu != 0 {
+            my_size += ::protobuf::rt::value_size(6, self.mtu, ::protobuf::wire_format::WireTypeVarint);
+        }
+        for value in &self.fake_dns_exclude {
+            my_size += ::protobuf::rt::string_size(7, &value);
+        };
+        for value in &self.fake_dns_include {
+            my_size += ::protobuf::rt::string_size(8, &value);
+        };
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if self.fd != 0 {
+            os.write_int32(1, self.fd)?;
+        }
+        if self.auto != false {
+            os.write_bool(9, self.auto)?;
+        }
+        if !self.name.is_empty() {
+            os.write_string(2, &self.name)?;
+        }
+        if !self.address.is_empty() {
+            os.write_string(3, &self.address)?;
+        }
+        if !self.gateway.is_empty() {
+            os.write_string(4, &self.gateway)?;
+        }
+        if !self.netmask.is_empty() {
+            os.write_string(5, &self.netmask)?;
+        }
+        if self.mtu != 0 {
+            os.write_int32(6, self.mtu)?;
+        }
+        for v in &self.fake_dns_exclude {
+            os.write_string(7, &v)?;
+        };
+        for v in &self.fake_dns_include {
+            os.write_string(8, &v)?;
+        };
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> TunInboundSettings {
+        TunInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static TunInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<TunInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(TunInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for TunInboundSettings {
+    fn clear(&mut self) {
+        self.fd = 0;
+        self.auto = false;
+        self.name.clear();
+        self.address.clear();
+        self.gateway.clear();
+        self.netmask.clear();
+        self.mtu = 0;
+        self.fake_dns_exclude.clear();
+        self.fake_dns_include.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for TunInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct SocksInboundSettings {
+    // message fields
+    pub advertised_address: ::std::string::String,
+    pub username: ::std::string::String,
+    pub password: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a SocksInboundSettings {
+    fn default() -> &'a SocksInboundSettings {
+        <SocksInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl SocksInboundSettings {
+    pub fn new() -> SocksInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string advertised_address = 1;
+
+
+    pub fn get_advertised_address(&self) -> &str {
+        &self.advertised_address
+    }
+
+    // string username = 2;
+
+
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    // string password = 3;
+
+
+    pub fn get_password(&self) -> &str {
+        &self.password
+    }
+}
+
+impl ::protobuf::Message for SocksInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.advertised_address)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.username)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.advertised_address.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.advertised_address);
+        }
+        if !self.username.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.username);
+        }
+        if !self.password.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.password);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.advertised_address.is_empty() {
+            os.write_string(1, &self.advertised_address)?;
+        }
+        if !self.username.is_empty() {
+            os.write_string(2, &self.username)?;
+        }
+        if !self.password.is_empty() {
+            os.write_string(3, &self.password)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> SocksInboundSettings {
+        SocksInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static SocksInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<SocksInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(SocksInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for SocksInboundSettings {
+    fn clear(&mut self) {
+        self.advertised_address.clear();
+        self.username.clear();
+        self.password.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for SocksInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct ShadowsocksInboundSettings {
+    // message fields
+    pub method: ::std::string::String,
+    pub password: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a ShadowsocksInboundSettings {
+    fn default() -> &'a ShadowsocksInboundSettings {
+        <ShadowsocksInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl ShadowsocksInboundSettings {
+    pub fn new() -> ShadowsocksInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string method = 1;
+
+
+    pub fn get_method(&self) -> &str {
+        &self.method
+    }
+
+    // string password = 2;
+
+
+    pub fn get_password(&self) -> &str {
+        &self.password
+    }
+}
+
+impl ::protobuf::Message for ShadowsocksInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.method)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.method.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.method);
+        }
+        if !self.password.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.password);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.method.is_empty() {
+            os.write_string(1, &self.method)?;
+        }
+        if !self.password.is_empty() {
+            os.write_string(2, &self.password)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> ShadowsocksInboundSettings {
+        ShadowsocksInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static ShadowsocksInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<ShadowsocksInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(ShadowsocksInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for ShadowsocksInboundSettings {
+    fn clear(&mut self) {
+        self.method.clear();
+        self.password.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for ShadowsocksInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct TrojanInboundSettings {
+    // message fields
+    pub password: ::std::string::String,
+    pub remote_address: ::std::string::String,
+    pub remote_port: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a TrojanInboundSettings {
+    fn default() -> &'a TrojanInboundSettings {
+        <TrojanInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl TrojanInboundSettings {
+    pub fn new() -> TrojanInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string password = 3;
+
+
+    pub fn get_password(&self) -> &str {
+        &self.password
+    }
+
+    // string remote_address = 4;
+
+
+    pub fn get_remote_address(&self) -> &str {
+        &self.remote_address
+    }
+
+    // string remote_port = 5;
+
+
+    pub fn get_remote_port(&self) -> &str {
+        &self.remote_port
+    }
+}
+
+impl ::protobuf::Message for TrojanInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.remote_address)?;
+                },
+                5 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.remote_port)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.password.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.password);
+        }
+        if !self.remote_address.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.remote_address);
+        }
+        if !self.remote_port.is_empty() {
+            my_size += ::protobuf::rt::string_size(5, &self.remote_port);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.password.is_empty() {
+            os.write_string(3, &self.password)?;
+        }
+        if !self.remote_address.is_empty() {
+            os.write_string(4, &self.remote_address)?;
+        }
+        if !self.remote_port.is_empty() {
+            os.write_string(5, &self.remote_port)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> TrojanInboundSettings {
+        TrojanInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static TrojanInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<TrojanInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(TrojanInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for TrojanInboundSettings {
+    fn clear(&mut self) {
+        self.password.clear();
+        self.remote_address.clear();
+        self.remote_port.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for TrojanInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct WebSocketInboundSettings {
+    // message fields
+    pub path: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a WebSocketInboundSettings {
+    fn default() -> &'a WebSocketInboundSettings {
+        <WebSocketInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl WebSocketInboundSettings {
+    pub fn new() -> WebSocketInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string path = 1;
+
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl ::protobuf::Message for WebSocketInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.path)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.path.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.path);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.path.is_empty() {
+            os.write_string(1, &self.path)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> WebSocketInboundSettings {
+        WebSocketInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static WebSocketInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<WebSocketInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(WebSocketInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for WebSocketInboundSettings {
+    fn clear(&mut self) {
+        self.path.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for WebSocketInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct GrpcInboundSettings {
+    // message fields
+    pub service_name: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a GrpcInboundSettings {
+    fn default() -> &'a GrpcInboundSettings {
+        <GrpcInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl GrpcInboundSettings {
+    pub fn new() -> GrpcInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string service_name = 1;
+
+
+    pub fn get_service_name(&self) -> &str {
+        &self.service_name
+    }
+}
+
+impl ::protobuf::Message for GrpcInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.service_name)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.service_name.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.service_name);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.service_name.is_empty() {
+            os.write_string(1, &self.service_name)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> GrpcInboundSettings {
+        GrpcInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static GrpcInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<GrpcInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(GrpcInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for GrpcInboundSettings {
+    fn clear(&mut self) {
+        self.service_name.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for GrpcInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct ObfsInboundSettings {
+    // message fields
+    pub mode: ::std::string::String,
+    pub path: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a ObfsInboundSettings {
+    fn default() -> &'a ObfsInboundSettings {
+        <ObfsInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl ObfsInboundSettings {
+    pub fn new() -> ObfsInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string mode = 1;
+
+
+    pub fn get_mode(&self) -> &str {
+        &self.mode
+    }
+
+    // string path = 2;
+
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl ::protobuf::Message for ObfsInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.mode)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.path)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.mode.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.mode);
+        }
+        if !self.path.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.path);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.mode.is_empty() {
+            os.write_string(1, &self.mode)?;
+        }
+        if !self.path.is_empty() {
+            os.write_string(2, &self.path)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> ObfsInboundSettings {
+        ObfsInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static ObfsInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<ObfsInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(ObfsInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for ObfsInboundSettings {
+    fn clear(&mut self) {
+        self.mode.clear();
+        self.path.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for ObfsInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct AMuxInboundSettings {
+    // message fields
+    pub actors: ::protobuf::RepeatedField<::std::string::String>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a AMuxInboundSettings {
+    fn default() -> &'a AMuxInboundSettings {
+        <AMuxInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl AMuxInboundSettings {
+    pub fn new() -> AMuxInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // repeated string actors = 1;
+
+
+    pub fn get_actors(&self) -> &[::std::string::String] {
+        &self.actors
+    }
+}
+
+impl ::protobuf::Message for AMuxInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.actors)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        for value in &self.actors {
+            my_size += ::protobuf::rt::string_size(1, &value);
+        };
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        for v in &self.actors {
+            os.write_string(1, &v)?;
+        };
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> AMuxInboundSettings {
+        AMuxInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static AMuxInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<AMuxInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(AMuxInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for AMuxInboundSettings {
+    fn clear(&mut self) {
+        self.actors.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for AMuxInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct QuicInboundSettings {
+    // message fields
+    pub certificate: ::std::string::String,
+    pub certificate_key: ::std::string::String,
+    pub certificate_password: ::std::string::String,
+    pub alpn: ::protobuf::RepeatedField<::std::string::String>,
+    pub idle_timeout: u32,
+    pub max_concurrent_bidi_streams: u32,
+    pub datagram: bool,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a QuicInboundSettings {
+    fn default() -> &'a QuicInboundSettings {
+        <QuicInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl QuicInboundSettings {
+    pub fn new() -> QuicInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string certificate = 1;
+
+
+    pub fn get_certificate(&self) -> &str {
+        &self.certificate
+    }
+
+    // string certificate_key = 2;
+
+
+    pub fn get_certificate_key(&self) -> &str {
+        &self.certificate_key
+    }
+
+    // string certificate_password = 3;
+
+
+    pub fn get_certificate_password(&self) -> &str {
+        &self.certificate_password
+    }
+
+    // repeated string alpn = 4;
+
+
+    pub fn get_alpn(&self) -> &[::std::string::String] {
+        &self.alpn
+    }
+
+    // uint32 idle_timeout = 5;
+
+
+    pub fn get_idle_timeout(&self) -> u32 {
+        self.idle_timeout
+    }
+
+    // uint32 max_concurrent_bidi_streams = 6;
+
+
+    pub fn get_max_concurrent_bidi_streams(&self) -> u32 {
+        self.max_concurrent_bidi_streams
+    }
+
+    // bool datagram = 7;
+
+
+    pub fn get_datagram(&self) -> bool {
+        self.datagram
+    }
+}
+
+impl ::protobuf::Message for QuicInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate_key)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate_password)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.alpn)?;
+                },
+                5 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.idle_timeout = tmp;
+                },
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.max_concurrent_bidi_streams = tmp;
+                },
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.datagram = tmp;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.certificate.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.certificate);
+        }
+        if !self.certificate_key.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.certificate_key);
+        }
+        if !self.certificate_password.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.certificate_password);
+        }
+        for value in &self.alpn {
+            my_size += ::protobuf::rt::string_size(4, &value);
+        };
+        if self.idle_timeout != 0 {
+            my_size += ::protobuf::rt::value_size(5, self.idle_timeout, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.max_concurrent_bidi_streams != 0 {
+            my_size += ::protobuf::rt::value_size(6, self.max_concurrent_bidi_streams, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.datagram != false {
+            my_size += 2;
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.certificate.is_empty() {
+            os.write_string(1, &self.certificate)?;
+        }
+        if !self.certificate_key.is_empty() {
+            os.write_string(2, &self.certificate_key)?;
+        }
+        if !self.certificate_password.is_empty() {
+            os.write_string(3, &self.certificate_password)?;
+        }
+        for v in &self.alpn {
+            os.write_string(4, &v)?;
+        };
+        if self.idle_timeout != 0 {
+            os.write_uint32(5, self.idle_timeout)?;
+        }
+        if self.max_concurrent_bidi_streams != 0 {
+            os.write_uint32(6, self.max_concurrent_bidi_streams)?;
+        }
+        if self.datagram != false {
+            os.write_bool(7, self.datagram)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> QuicInboundSettings {
+        QuicInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static QuicInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<QuicInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(QuicInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for QuicInboundSettings {
+    fn clear(&mut self) {
+        self.certificate.clear();
+        self.certificate_key.clear();
+        self.certificate_password.clear();
+        self.alpn.clear();
+        self.idle_timeout = 0;
+        self.max_concurrent_bidi_streams = 0;
+        self.datagram = false;
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for QuicInboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct TlsInboundSettings {
+    // message fields
+    pub certificate: ::std::string::String,
+    pub certificate_key: ::std::string::String,
+    pub client_ca: ::std::string::String,
+    pub min_version: ::std::string::String,
+    pub max_version: ::std::string::String,
+    pub cipher_suites: ::protobuf::RepeatedField<::std::string::String>,
+    pub certificate_password: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a TlsInboundSettings {
+    fn default() -> &'a TlsInboundSettings {
+        <TlsInboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl TlsInboundSettings {
+    pub fn new() -> TlsInboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // string certificate = 1;
+
+
+    pub fn get_certificate(&self) -> &str {
+        &self.certificate
+    }
+
+    // string certificate_key = 2;
+
+
+    pub fn get_certificate_key(&self) -> &str {
+        &self.certificate_key
+    }
+
+    // string client_ca = 3;
+
+
+    pub fn get_client_ca(&self) -> &str {
+        &self.client_ca
+    }
+
+    // string min_version = 4;
+
+
+    pub fn get_min_version(&self) -> &str {
+        &self.min_version
+    }
+
+    // string max_version = 5;
+
+
+    pub fn get_max_version(&self) -> &str {
+        &self.max_version
+    }
+
+    // repeated string cipher_suites = 6;
+
+
+    pub fn get_cipher_suites(&self) -> &[::std::string::String] {
+        &self.cipher_suites
+    }
+
+    // string certificate_password = 7;
+
+
+    pub fn get_certificate_password(&self) -> &str {
+        &self.certificate_password
+    }
+}
+
+impl ::protobuf::Message for TlsInboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate_key)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.client_ca)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.min_version)?;
+                },
+                5 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.max_version)?;
+                },
+                6 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.cipher_suites)?;
+                },
+                7 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate_password)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.certificate.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.certificate);
+        }
+        if !self.certificate_key.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.certificate_key);
+        }
+        if !self.client_ca.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.client_ca);
+        }
+        if !self.min_version.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.min_version);
+        }
+        if !self.max_version.is_empty() {
+            my_size += ::protobuf::rt::string_size(5, &self.max_version);
+        }
+        for value in &self.cipher_suites {
+            my_size += ::protobuf::rt::string_size(6, &value);
+        };
+        if !self.certificate_password.is_empty() {
+            my_size += ::protobuf::rt::string_size(7, &self.certificate_password);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.certificate.is_empty() {
+            os.write_string(1, &self.certificate)?;
+        }
+        if !self.certificate_key.is_empty() {
+            os.write_string(2, &self.certificate_key)?;
+        }
+        if !self.client_ca.is_empty() {
+            os.write_string(3, &self.client_ca)?;
+        }
+        if !self.min_version.is_empty() {
+            os.write_string(4, &self.min_version)?;
+        }
+        if !self.max_version.is_empty() {
+            os.write_string(5, &self.max_version)?;
+        }
+        for v in &self.cipher_suites {
+            os.write_string(6, &v)?;
+        };
+        if !self.certificate_password.is_empty() {
+            os.write_string(7, &self.certificate_password)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> TlsInboundSettings {
+        TlsInboundSettings::new()
+    }
+
+    fn default_instance() -> &'static TlsInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<TlsInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(TlsInboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for TlsInboundSettings {
+    fn clear(&mut self) {
+        self.certificate.clear();
+        self.certificate_key.clear();
+        self.client_ca.clear();
+        self.min_version.clear();
+        self.max_version.clear();
+        self.cipher_suites.clear();
+        self.certificate_password.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for TunInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for TlsInboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct ShadowsocksInboundSettings {
+pub struct ChainInboundSettings {
     // message fields
-    pub method: ::std::string::String,
-    pub password: ::std::string::String,
+    pub actors: ::protobuf::RepeatedField<::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a ShadowsocksInboundSettings {
-    fn default() -> &'a ShadowsocksInboundSettings {
-        <ShadowsocksInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a ChainInboundSettings {
+    fn default() -> &'a ChainInboundSettings {
+        <ChainInboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl ShadowsocksInboundSettings {
-    pub fn new() -> ShadowsocksInboundSettings {
+impl ChainInboundSettings {
+    pub fn new() -> ChainInboundSettings {
         ::std::default::Default::default()
     }
 
-    // string method = 1;
-
-
-    pub fn get_method(&self) -> &str {
-        &self.method
-    }
-
-    // string password = 2;
+    // repeated string actors = 1;
 
 
-    pub fn get_password(&self) -> &str {
-        &self.password
+    pub fn get_actors(&self) -> &[::std::string::String] {
+        &self.actors
     }
 }
 
-impl ::protobuf::Message for ShadowsocksInboundSettings {
+impl ::protobuf::Message for ChainInboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -977,10 +2886,7 @@ impl ::protobuf::Message for ShadowsocksInboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.method)?;
-                },
-                2 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.actors)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -994,24 +2900,18 @@ impl ::protobuf::Message for ShadowsocksInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.method.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.method);
-        }
-        if !self.password.is_empty() {
-            my_size += ::protobuf::rt::string_size(2, &self.password);
-        }
+        for value in &self.actors {
+            my_size += ::protobuf::rt::string_size(1, &value);
+        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.method.is_empty() {
-            os.write_string(1, &self.method)?;
-        }
-        if !self.password.is_empty() {
-            os.write_string(2, &self.password)?;
-        }
+        for v in &self.actors {
+            os.write_string(1, &v)?;
+        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1042,75 +2942,114 @@ impl ::protobuf::Message for ShadowsocksInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> ShadowsocksInboundSettings {
-        ShadowsocksInboundSettings::new()
+    fn new() -> ChainInboundSettings {
+        ChainInboundSettings::new()
     }
 
-    fn default_instance() -> &'static ShadowsocksInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<ShadowsocksInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(ShadowsocksInboundSettings::new)
+    fn default_instance() -> &'static ChainInboundSettings {
+        static instance: ::protobuf::rt::LazyV2<ChainInboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(ChainInboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for ShadowsocksInboundSettings {
+impl ::protobuf::Clear for ChainInboundSettings {
     fn clear(&mut self) {
-        self.method.clear();
-        self.password.clear();
+        self.actors.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for ShadowsocksInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for ChainInboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct TrojanInboundSettings {
+pub struct Inbound {
     // message fields
-    pub password: ::std::string::String,
-    pub remote_address: ::std::string::String,
-    pub remote_port: ::std::string::String,
+    pub tag: ::std::string::String,
+    pub protocol: ::std::string::String,
+    pub address: ::std::string::String,
+    pub port: u32,
+    pub settings: ::std::vec::Vec<u8>,
+    pub nodelay: ::std::string::String,
+    pub random_port: bool,
+    pub udp_session_timeout: u32,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a TrojanInboundSettings {
-    fn default() -> &'a TrojanInboundSettings {
-        <TrojanInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a Inbound {
+    fn default() -> &'a Inbound {
+        <Inbound as ::protobuf::Message>::default_instance()
     }
 }
 
-impl TrojanInboundSettings {
-    pub fn new() -> TrojanInboundSettings {
+impl Inbound {
+    pub fn new() -> Inbound {
         ::std::default::Default::default()
     }
 
-    // string password = 3;
+    // string tag = 1;
 
 
-    pub fn get_password(&self) -> &str {
-        &self.password
+    pub fn get_tag(&self) -> &str {
+        &self.tag
     }
 
-    // string remote_address = 4;
+    // string protocol = 2;
 
 
-    pub fn get_remote_address(&self) -> &str {
-        &self.remote_address
+    pub fn get_protocol(&self) -> &str {
+        &self.protocol
     }
 
-    // string remote_port = 5;
+    // string address = 3;
 
 
-    pub fn get_remote_port(&self) -> &str {
-        &self.remote_port
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    // uint32 port = 4;
+
+
+    pub fn get_port(&self) -> u32 {
+        self.port
+    }
+
+    // bytes settings = 5;
+
+
+    pub fn get_settings(&self) -> &[u8] {
+        &self.settings
+    }
+
+    // string nodelay = 6;
+
+
+    pub fn get_nodelay(&self) -> &str {
+        &self.nodelay
+    }
+
+    // bool random_port = 7;
+
+
+    pub fn get_random_port(&self) -> bool {
+        self.random_port
+    }
+
+    // uint32 udp_session_timeout = 8;
+
+
+    pub fn get_udp_session_timeout(&self) -> u32 {
+        self.udp_session_timeout
     }
 }
 
-impl ::protobuf::Message for TrojanInboundSettings {
+impl ::protobuf::Message for Inbound {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -1119,14 +3058,41 @@ impl ::protobuf::Message for TrojanInboundSettings {
         while !is.eof()? {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.tag)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.protocol)?;
+                },
                 3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
                 },
                 4 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.remote_address)?;
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.port = tmp;
                 },
                 5 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.remote_port)?;
+                    ::protobuf::rt::read_singular_proto3_bytes_into(wire_type, is, &mut self.settings)?;
+                },
+                6 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.nodelay)?;
+                },
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.random_port = tmp;
+                },
+                8 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.udp_session_timeout = tmp;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -1140,14 +3106,29 @@ impl ::protobuf::Message for TrojanInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.password.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.password);
+        if !self.tag.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.tag);
         }
-        if !self.remote_address.is_empty() {
-            my_size += ::protobuf::rt::string_size(4, &self.remote_address);
+        if !self.protocol.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.protocol);
         }
-        if !self.remote_port.is_empty() {
-            my_size += ::protobuf::rt::string_size(5, &self.remote_port);
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.address);
+        }
+        if self.port != 0 {
+            my_size += ::protobuf::rt::value_size(4, self.port, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if !self.settings.is_empty() {
+            my_size += ::protobuf::rt::bytes_size(5, &self.settings);
+        }
+        if !self.nodelay.is_empty() {
+            my_size += ::protobuf::rt::string_size(6, &self.nodelay);
+        }
+        if self.random_port != false {
+            my_size += 2;
+        }
+        if self.udp_session_timeout != 0 {
+            my_size += ::protobuf::rt::value_size(8, self.udp_session_timeout, ::protobuf::wire_format::WireTypeVarint);
         }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
@@ -1155,14 +3136,29 @@ impl ::protobuf::Message for TrojanInboundSettings {
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.password.is_empty() {
-            os.write_string(3, &self.password)?;
+        if !self.tag.is_empty() {
+            os.write_string(1, &self.tag)?;
         }
-        if !self.remote_address.is_empty() {
-            os.write_string(4, &self.remote_address)?;
+        if !self.protocol.is_empty() {
+            os.write_string(2, &self.protocol)?;
         }
-        if !self.remote_port.is_empty() {
-            os.write_string(5, &self.remote_port)?;
+        if !self.address.is_empty() {
+            os.write_string(3, &self.address)?;
+        }
+        if self.port != 0 {
+            os.write_uint32(4, self.port)?;
+        }
+        if !self.settings.is_empty() {
+            os.write_bytes(5, &self.settings)?;
+        }
+        if !self.nodelay.is_empty() {
+            os.write_string(6, &self.nodelay)?;
+        }
+        if self.random_port != false {
+            os.write_bool(7, self.random_port)?;
+        }
+        if self.udp_session_timeout != 0 {
+            os.write_uint32(8, self.udp_session_timeout)?;
         }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
@@ -1194,60 +3190,73 @@ impl ::protobuf::Message for TrojanInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> TrojanInboundSettings {
-        TrojanInboundSettings::new()
+    fn new() -> Inbound {
+        Inbound::new()
     }
 
-    fn default_instance() -> &'static TrojanInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<TrojanInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(TrojanInboundSettings::new)
+    fn default_instance() -> &'static Inbound {
+        static instance: ::protobuf::rt::LazyV2<Inbound> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Inbound::new)
     }
 }
 
-impl ::protobuf::Clear for TrojanInboundSettings {
+impl ::protobuf::Clear for Inbound {
     fn clear(&mut self) {
-        self.password.clear();
-        self.remote_address.clear();
-        self.remote_port.clear();
+        self.tag.clear();
+        self.protocol.clear();
+        self.address.clear();
+        self.port = 0;
+        self.settings.clear();
+        self.nodelay.clear();
+        self.random_port = false;
+        self.udp_session_timeout = 0;
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for TrojanInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for Inbound {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct WebSocketInboundSettings {
+pub struct DirectOutboundSettings {
     // message fields
-    pub path: ::std::string::String,
+    pub bind_address: ::std::string::String,
+    pub bind_interface: ::std::string::String,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a WebSocketInboundSettings {
-    fn default() -> &'a WebSocketInboundSettings {
-        <WebSocketInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a DirectOutboundSettings {
+    fn default() -> &'a DirectOutboundSettings {
+        <DirectOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl WebSocketInboundSettings {
-    pub fn new() -> WebSocketInboundSettings {
+impl DirectOutboundSettings {
+    pub fn new() -> DirectOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string path = 1;
+    // string bind_address = 1;
 
 
-    pub fn get_path(&self) -> &str {
-        &self.path
+    pub fn get_bind_address(&self) -> &str {
+        &self.bind_address
+    }
+
+    // string bind_interface = 2;
+
+
+    pub fn get_bind_interface(&self) -> &str {
+        &self.bind_interface
     }
 }
 
-impl ::protobuf::Message for WebSocketInboundSettings {
+impl ::protobuf::Message for DirectOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -1257,7 +3266,10 @@ impl ::protobuf::Message for WebSocketInboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.path)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.bind_address)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.bind_interface)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -1271,8 +3283,11 @@ impl ::protobuf::Message for WebSocketInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.path.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.path);
+        if !self.bind_address.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.bind_address);
+        }
+        if !self.bind_interface.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.bind_interface);
         }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
@@ -1280,8 +3295,11 @@ impl ::protobuf::Message for WebSocketInboundSettings {
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.path.is_empty() {
-            os.write_string(1, &self.path)?;
+        if !self.bind_address.is_empty() {
+            os.write_string(1, &self.bind_address)?;
+        }
+        if !self.bind_interface.is_empty() {
+            os.write_string(2, &self.bind_interface)?;
         }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
@@ -1313,58 +3331,67 @@ impl ::protobuf::Message for WebSocketInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> WebSocketInboundSettings {
-        WebSocketInboundSettings::new()
+    fn new() -> DirectOutboundSettings {
+        DirectOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static WebSocketInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<WebSocketInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(WebSocketInboundSettings::new)
+    fn default_instance() -> &'static DirectOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<DirectOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(DirectOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for WebSocketInboundSettings {
+impl ::protobuf::Clear for DirectOutboundSettings {
     fn clear(&mut self) {
-        self.path.clear();
+        self.bind_address.clear();
+        self.bind_interface.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for WebSocketInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for DirectOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct AMuxInboundSettings {
+pub struct RedirectOutboundSettings {
     // message fields
-    pub actors: ::protobuf::RepeatedField<::std::string::String>,
+    pub address: ::std::string::String,
+    pub port: u32,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a AMuxInboundSettings {
-    fn default() -> &'a AMuxInboundSettings {
-        <AMuxInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a RedirectOutboundSettings {
+    fn default() -> &'a RedirectOutboundSettings {
+        <RedirectOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl AMuxInboundSettings {
-    pub fn new() -> AMuxInboundSettings {
+impl RedirectOutboundSettings {
+    pub fn new() -> RedirectOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // repeated string actors = 1;
+    // string address = 1;
 
 
-    pub fn get_actors(&self) -> &[::std::string::String] {
-        &self.actors
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    // uint32 port = 2;
+
+
+    pub fn get_port(&self) -> u32 {
+        self.port
     }
 }
 
-impl ::protobuf::Message for AMuxInboundSettings {
+impl ::protobuf::Message for RedirectOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -1374,7 +3401,14 @@ impl ::protobuf::Message for AMuxInboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.actors)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                },
+                2 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.port = tmp;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -1388,18 +3422,24 @@ impl ::protobuf::Message for AMuxInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        for value in &self.actors {
-            my_size += ::protobuf::rt::string_size(1, &value);
-        };
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.address);
+        }
+        if self.port != 0 {
+            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        for v in &self.actors {
-            os.write_string(1, &v)?;
-        };
+        if !self.address.is_empty() {
+            os.write_string(1, &self.address)?;
+        }
+        if self.port != 0 {
+            os.write_uint32(2, self.port)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1430,66 +3470,91 @@ impl ::protobuf::Message for AMuxInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> AMuxInboundSettings {
-        AMuxInboundSettings::new()
+    fn new() -> RedirectOutboundSettings {
+        RedirectOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static AMuxInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<AMuxInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(AMuxInboundSettings::new)
+    fn default_instance() -> &'static RedirectOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<RedirectOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(RedirectOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for AMuxInboundSettings {
+impl ::protobuf::Clear for RedirectOutboundSettings {
     fn clear(&mut self) {
-        self.actors.clear();
+        self.address.clear();
+        self.port = 0;
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for AMuxInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for RedirectOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct QuicInboundSettings {
+pub struct SocksOutboundSettings {
     // message fields
-    pub certificate: ::std::string::String,
-    pub certificate_key: ::std::string::String,
+    pub address: ::std::string::String,
+    pub port: u32,
+    pub username: ::std::string::String,
+    pub password: ::std::string::String,
+    pub udp_over_tcp: bool,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a QuicInboundSettings {
-    fn default() -> &'a QuicInboundSettings {
-        <QuicInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a SocksOutboundSettings {
+    fn default() -> &'a SocksOutboundSettings {
+        <SocksOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl QuicInboundSettings {
-    pub fn new() -> QuicInboundSettings {
+impl SocksOutboundSettings {
+    pub fn new() -> SocksOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string certificate = 1;
+    // string address = 1;
 
 
-    pub fn get_certificate(&self) -> &str {
-        &self.certificate
+    pub fn get_address(&self) -> &str {
+        &self.address
     }
 
-    // string certificate_key = 2;
+    // uint32 port = 2;
 
 
-    pub fn get_certificate_key(&self) -> &str {
-        &self.certificate_key
+    pub fn get_port(&self) -> u32 {
+        self.port
+    }
+
+    // string username = 3;
+
+
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    // string password = 4;
+
+
+    pub fn get_password(&self) -> &str {
+        &self.password
+    }
+
+    // bool udp_over_tcp = 5;
+
+
+    pub fn get_udp_over_tcp(&self) -> bool {
+        self.udp_over_tcp
     }
 }
 
-impl ::protobuf::Message for QuicInboundSettings {
+impl ::protobuf::Message for SocksOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -1499,10 +3564,27 @@ impl ::protobuf::Message for QuicInboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
                 },
                 2 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate_key)?;
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.port = tmp;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.username)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                },
+                5 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.udp_over_tcp = tmp;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -1516,11 +3598,20 @@ impl ::protobuf::Message for QuicInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.certificate.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.certificate);
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.address);
         }
-        if !self.certificate_key.is_empty() {
-            my_size += ::protobuf::rt::string_size(2, &self.certificate_key);
+        if self.port != 0 {
+            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if !self.username.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.username);
+        }
+        if !self.password.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.password);
+        }
+        if self.udp_over_tcp != false {
+            my_size += 2;
         }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
@@ -1528,11 +3619,20 @@ impl ::protobuf::Message for QuicInboundSettings {
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.certificate.is_empty() {
-            os.write_string(1, &self.certificate)?;
+        if !self.address.is_empty() {
+            os.write_string(1, &self.address)?;
         }
-        if !self.certificate_key.is_empty() {
-            os.write_string(2, &self.certificate_key)?;
+        if self.port != 0 {
+            os.write_uint32(2, self.port)?;
+        }
+        if !self.username.is_empty() {
+            os.write_string(3, &self.username)?;
+        }
+        if !self.password.is_empty() {
+            os.write_string(4, &self.password)?;
+        }
+        if self.udp_over_tcp != false {
+            os.write_bool(5, self.udp_over_tcp)?;
         }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
@@ -1564,67 +3664,94 @@ impl ::protobuf::Message for QuicInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> QuicInboundSettings {
-        QuicInboundSettings::new()
+    fn new() -> SocksOutboundSettings {
+        SocksOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static QuicInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<QuicInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(QuicInboundSettings::new)
+    fn default_instance() -> &'static SocksOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<SocksOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(SocksOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for QuicInboundSettings {
+impl ::protobuf::Clear for SocksOutboundSettings {
     fn clear(&mut self) {
-        self.certificate.clear();
-        self.certificate_key.clear();
+        self.address.clear();
+        self.port = 0;
+        self.username.clear();
+        self.password.clear();
+        self.udp_over_tcp = false;
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for QuicInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for SocksOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct TlsInboundSettings {
+pub struct HttpOutboundSettings {
     // message fields
-    pub certificate: ::std::string::String,
-    pub certificate_key: ::std::string::String,
+    pub address: ::std::string::String,
+    pub port: u32,
+    pub username: ::std::string::String,
+    pub password: ::std::string::String,
+    pub headers: ::std::collections::HashMap<::std::string::String, ::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a TlsInboundSettings {
-    fn default() -> &'a TlsInboundSettings {
-        <TlsInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a HttpOutboundSettings {
+    fn default() -> &'a HttpOutboundSettings {
+        <HttpOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl TlsInboundSettings {
-    pub fn new() -> TlsInboundSettings {
+impl HttpOutboundSettings {
+    pub fn new() -> HttpOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string certificate = 1;
+    // string address = 1;
 
 
-    pub fn get_certificate(&self) -> &str {
-        &self.certificate
+    pub fn get_address(&self) -> &str {
+        &self.address
     }
 
-    // string certificate_key = 2;
+    // uint32 port = 2;
 
 
-    pub fn get_certificate_key(&self) -> &str {
-        &self.certificate_key
+    pub fn get_port(&self) -> u32 {
+        self.port
+    }
+
+    // string username = 3;
+
+
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    // string password = 4;
+
+
+    pub fn get_password(&self) -> &str {
+        &self.password
+    }
+
+    // repeated .HttpOutboundSettings.HeadersEntry headers = 5;
+
+
+    pub fn get_headers(&self) -> &::std::collections::HashMap<::std::string::String, ::std::string::String> {
+        &self.headers
     }
 }
 
-impl ::protobuf::Message for TlsInboundSettings {
+impl ::protobuf::Message for HttpOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -1634,10 +3761,23 @@ impl ::protobuf::Message for TlsInboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                },
+                2 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.port = tmp;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.username)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
                 },
-                2 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate_key)?;
+                5 => {
+                    ::protobuf::rt::read_map_into::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(wire_type, is, &mut self.headers)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -1651,24 +3791,38 @@ impl ::protobuf::Message for TlsInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.certificate.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.certificate);
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.address);
         }
-        if !self.certificate_key.is_empty() {
-            my_size += ::protobuf::rt::string_size(2, &self.certificate_key);
+        if self.port != 0 {
+            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if !self.username.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.username);
+        }
+        if !self.password.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.password);
         }
+        my_size += ::protobuf::rt::compute_map_size::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(5, &self.headers);
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.certificate.is_empty() {
-            os.write_string(1, &self.certificate)?;
+        if !self.address.is_empty() {
+            os.write_string(1, &self.address)?;
         }
-        if !self.certificate_key.is_empty() {
-            os.write_string(2, &self.certificate_key)?;
+        if self.port != 0 {
+            os.write_uint32(2, self.port)?;
+        }
+        if !self.username.is_empty() {
+            os.write_string(3, &self.username)?;
+        }
+        if !self.password.is_empty() {
+            os.write_string(4, &self.password)?;
         }
+        ::protobuf::rt::write_map_with_cached_sizes::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(5, &self.headers, os)?;
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1699,59 +3853,86 @@ impl ::protobuf::Message for TlsInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> TlsInboundSettings {
-        TlsInboundSettings::new()
+    fn new() -> HttpOutboundSettings {
+        HttpOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static TlsInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<TlsInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(TlsInboundSettings::new)
+    fn default_instance() -> &'static HttpOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<HttpOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(HttpOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for TlsInboundSettings {
+impl ::protobuf::Clear for HttpOutboundSettings {
     fn clear(&mut self) {
-        self.certificate.clear();
-        self.certificate_key.clear();
+        self.address.clear();
+        self.port = 0;
+        self.username.clear();
+        self.password.clear();
+        self.headers.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for TlsInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for HttpOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct ChainInboundSettings {
+pub struct ShadowsocksOutboundSettings {
     // message fields
-    pub actors: ::protobuf::RepeatedField<::std::string::String>,
+    pub address: ::std::string::String,
+    pub port: u32,
+    pub method: ::std::string::String,
+    pub password: ::std::string::String,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a ChainInboundSettings {
-    fn default() -> &'a ChainInboundSettings {
-        <ChainInboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a ShadowsocksOutboundSettings {
+    fn default() -> &'a ShadowsocksOutboundSettings {
+        <ShadowsocksOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl ChainInboundSettings {
-    pub fn new() -> ChainInboundSettings {
+impl ShadowsocksOutboundSettings {
+    pub fn new() -> ShadowsocksOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // repeated string actors = 1;
+    // string address = 1;
 
 
-    pub fn get_actors(&self) -> &[::std::string::String] {
-        &self.actors
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    // uint32 port = 2;
+
+
+    pub fn get_port(&self) -> u32 {
+        self.port
+    }
+
+    // string method = 3;
+
+
+    pub fn get_method(&self) -> &str {
+        &self.method
+    }
+
+    // string password = 4;
+
+
+    pub fn get_password(&self) -> &str {
+        &self.password
     }
 }
 
-impl ::protobuf::Message for ChainInboundSettings {
+impl ::protobuf::Message for ShadowsocksOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -1761,7 +3942,20 @@ impl ::protobuf::Message for ChainInboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.actors)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                },
+                2 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.port = tmp;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.method)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -1775,18 +3969,36 @@ impl ::protobuf::Message for ChainInboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        for value in &self.actors {
-            my_size += ::protobuf::rt::string_size(1, &value);
-        };
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.address);
+        }
+        if self.port != 0 {
+            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if !self.method.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.method);
+        }
+        if !self.password.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.password);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        for v in &self.actors {
-            os.write_string(1, &v)?;
-        };
+        if !self.address.is_empty() {
+            os.write_string(1, &self.address)?;
+        }
+        if self.port != 0 {
+            os.write_uint32(2, self.port)?;
+        }
+        if !self.method.is_empty() {
+            os.write_string(3, &self.method)?;
+        }
+        if !self.password.is_empty() {
+            os.write_string(4, &self.password)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1817,90 +4029,77 @@ impl ::protobuf::Message for ChainInboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> ChainInboundSettings {
-        ChainInboundSettings::new()
+    fn new() -> ShadowsocksOutboundSettings {
+        ShadowsocksOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static ChainInboundSettings {
-        static instance: ::protobuf::rt::LazyV2<ChainInboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(ChainInboundSettings::new)
+    fn default_instance() -> &'static ShadowsocksOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<ShadowsocksOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(ShadowsocksOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for ChainInboundSettings {
+impl ::protobuf::Clear for ShadowsocksOutboundSettings {
     fn clear(&mut self) {
-        self.actors.clear();
+        self.address.clear();
+        self.port = 0;
+        self.method.clear();
+        self.password.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for ChainInboundSettings {
+impl ::protobuf::reflect::ProtobufValue for ShadowsocksOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct Inbound {
+pub struct TrojanOutboundSettings {
     // message fields
-    pub tag: ::std::string::String,
-    pub protocol: ::std::string::String,
     pub address: ::std::string::String,
     pub port: u32,
-    pub settings: ::std::vec::Vec<u8>,
+    pub password: ::std::string::String,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a Inbound {
-    fn default() -> &'a Inbound {
-        <Inbound as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a TrojanOutboundSettings {
+    fn default() -> &'a TrojanOutboundSettings {
+        <TrojanOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl Inbound {
-    pub fn new() -> Inbound {
+impl TrojanOutboundSettings {
+    pub fn new() -> TrojanOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string tag = 1;
-
-
-    pub fn get_tag(&self) -> &str {
-        &self.tag
-    }
-
-    // string protocol = 2;
-
-
-    pub fn get_protocol(&self) -> &str {
-        &self.protocol
-    }
-
-    // string address = 3;
+    // string address = 1;
 
 
     pub fn get_address(&self) -> &str {
         &self.address
     }
 
-    // uint32 port = 4;
+    // uint32 port = 2;
 
 
     pub fn get_port(&self) -> u32 {
         self.port
     }
 
-    // bytes settings = 5;
+    // string password = 3;
 
 
-    pub fn get_settings(&self) -> &[u8] {
-        &self.settings
+    pub fn get_password(&self) -> &str {
+        &self.password
     }
 }
 
-impl ::protobuf::Message for Inbound {
+impl ::protobuf::Message for TrojanOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -1910,23 +4109,17 @@ impl ::protobuf::Message for Inbound {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.tag)?;
-                },
-                2 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.protocol)?;
-                },
-                3 => {
                     ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
                 },
-                4 => {
+                2 => {
                     if wire_type != ::protobuf::wire_format::WireTypeVarint {
                         return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
                     }
                     let tmp = is.read_uint32()?;
                     self.port = tmp;
                 },
-                5 => {
-                    ::protobuf::rt::read_singular_proto3_bytes_into(wire_type, is, &mut self.settings)?;
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -1940,20 +4133,14 @@ impl ::protobuf::Message for Inbound {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.tag.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.tag);
-        }
-        if !self.protocol.is_empty() {
-            my_size += ::protobuf::rt::string_size(2, &self.protocol);
-        }
         if !self.address.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.address);
+            my_size += ::protobuf::rt::string_size(1, &self.address);
         }
         if self.port != 0 {
-            my_size += ::protobuf::rt::value_size(4, self.port, ::protobuf::wire_format::WireTypeVarint);
+            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
         }
-        if !self.settings.is_empty() {
-            my_size += ::protobuf::rt::bytes_size(5, &self.settings);
+        if !self.password.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.password);
         }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
@@ -1961,20 +4148,14 @@ impl ::protobuf::Message for Inbound {
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.tag.is_empty() {
-            os.write_string(1, &self.tag)?;
-        }
-        if !self.protocol.is_empty() {
-            os.write_string(2, &self.protocol)?;
-        }
         if !self.address.is_empty() {
-            os.write_string(3, &self.address)?;
+            os.write_string(1, &self.address)?;
         }
         if self.port != 0 {
-            os.write_uint32(4, self.port)?;
+            os.write_uint32(2, self.port)?;
         }
-        if !self.settings.is_empty() {
-            os.write_bytes(5, &self.settings)?;
+        if !self.password.is_empty() {
+            os.write_string(3, &self.password)?;
         }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
@@ -2006,51 +4187,52 @@ impl ::protobuf::Message for Inbound {
         Self::descriptor_static()
     }
 
-    fn new() -> Inbound {
-        Inbound::new()
+    fn new() -> TrojanOutboundSettings {
+        TrojanOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static Inbound {
-        static instance: ::protobuf::rt::LazyV2<Inbound> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(Inbound::new)
+    fn default_instance() -> &'static TrojanOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<TrojanOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(TrojanOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for Inbound {
+impl ::protobuf::Clear for TrojanOutboundSettings {
     fn clear(&mut self) {
-        self.tag.clear();
-        self.protocol.clear();
         self.address.clear();
         self.port = 0;
-        self.settings.clear();
+        self.password.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for Inbound {
+impl ::protobuf::reflect::ProtobufValue for TrojanOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct RedirectOutboundSettings {
+pub struct VMessOutboundSettings {
     // message fields
     pub address: ::std::string::String,
     pub port: u32,
+    pub uuid: ::std::string::String,
+    pub security: ::std::string::String,
+    pub alter_id: u32,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a RedirectOutboundSettings {
-    fn default() -> &'a RedirectOutboundSettings {
-        <RedirectOutboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a VMessOutboundSettings {
+    fn default() -> &'a VMessOutboundSettings {
+        <VMessOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl RedirectOutboundSettings {
-    pub fn new() -> RedirectOutboundSettings {
+impl VMessOutboundSettings {
+    pub fn new() -> VMessOutboundSettings {
         ::std::default::Default::default()
     }
 
@@ -2067,9 +4249,30 @@ impl RedirectOutboundSettings {
     pub fn get_port(&self) -> u32 {
         self.port
     }
+
+    // string uuid = 3;
+
+
+    pub fn get_uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    // string security = 4;
+
+
+    pub fn get_security(&self) -> &str {
+        &self.security
+    }
+
+    // uint32 alter_id = 5;
+
+
+    pub fn get_alter_id(&self) -> u32 {
+        self.alter_id
+    }
 }
 
-impl ::protobuf::Message for RedirectOutboundSettings {
+impl ::protobuf::Message for VMessOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -2088,6 +4291,19 @@ impl ::protobuf::Message for RedirectOutboundSettings {
                     let tmp = is.read_uint32()?;
                     self.port = tmp;
                 },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.uuid)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.security)?;
+                },
+                5 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.alter_id = tmp;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -2106,6 +4322,15 @@ impl ::protobuf::Message for RedirectOutboundSettings {
         if self.port != 0 {
             my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
         }
+        if !self.uuid.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.uuid);
+        }
+        if !self.security.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.security);
+        }
+        if self.alter_id != 0 {
+            my_size += ::protobuf::rt::value_size(5, self.alter_id, ::protobuf::wire_format::WireTypeVarint);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -2118,6 +4343,15 @@ impl ::protobuf::Message for RedirectOutboundSettings {
         if self.port != 0 {
             os.write_uint32(2, self.port)?;
         }
+        if !self.uuid.is_empty() {
+            os.write_string(3, &self.uuid)?;
+        }
+        if !self.security.is_empty() {
+            os.write_string(4, &self.security)?;
+        }
+        if self.alter_id != 0 {
+            os.write_uint32(5, self.alter_id)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -2148,48 +4382,53 @@ impl ::protobuf::Message for RedirectOutboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> RedirectOutboundSettings {
-        RedirectOutboundSettings::new()
+    fn new() -> VMessOutboundSettings {
+        VMessOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static RedirectOutboundSettings {
-        static instance: ::protobuf::rt::LazyV2<RedirectOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(RedirectOutboundSettings::new)
+    fn default_instance() -> &'static VMessOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<VMessOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(VMessOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for RedirectOutboundSettings {
+impl ::protobuf::Clear for VMessOutboundSettings {
     fn clear(&mut self) {
         self.address.clear();
         self.port = 0;
+        self.uuid.clear();
+        self.security.clear();
+        self.alter_id = 0;
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for RedirectOutboundSettings {
+impl ::protobuf::reflect::ProtobufValue for VMessOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct SocksOutboundSettings {
+pub struct VlessOutboundSettings {
     // message fields
     pub address: ::std::string::String,
     pub port: u32,
+    pub uuid: ::std::string::String,
+    pub flow: ::std::string::String,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a SocksOutboundSettings {
-    fn default() -> &'a SocksOutboundSettings {
-        <SocksOutboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a VlessOutboundSettings {
+    fn default() -> &'a VlessOutboundSettings {
+        <VlessOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl SocksOutboundSettings {
-    pub fn new() -> SocksOutboundSettings {
+impl VlessOutboundSettings {
+    pub fn new() -> VlessOutboundSettings {
         ::std::default::Default::default()
     }
 
@@ -2206,9 +4445,23 @@ impl SocksOutboundSettings {
     pub fn get_port(&self) -> u32 {
         self.port
     }
+
+    // string uuid = 3;
+
+
+    pub fn get_uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    // string flow = 4;
+
+
+    pub fn get_flow(&self) -> &str {
+        &self.flow
+    }
 }
 
-impl ::protobuf::Message for SocksOutboundSettings {
+impl ::protobuf::Message for VlessOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -2227,6 +4480,12 @@ impl ::protobuf::Message for SocksOutboundSettings {
                     let tmp = is.read_uint32()?;
                     self.port = tmp;
                 },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.uuid)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.flow)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -2245,6 +4504,12 @@ impl ::protobuf::Message for SocksOutboundSettings {
         if self.port != 0 {
             my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
         }
+        if !self.uuid.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.uuid);
+        }
+        if !self.flow.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.flow);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -2257,6 +4522,12 @@ impl ::protobuf::Message for SocksOutboundSettings {
         if self.port != 0 {
             os.write_uint32(2, self.port)?;
         }
+        if !self.uuid.is_empty() {
+            os.write_string(3, &self.uuid)?;
+        }
+        if !self.flow.is_empty() {
+            os.write_string(4, &self.flow)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -2287,83 +4558,101 @@ impl ::protobuf::Message for SocksOutboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> SocksOutboundSettings {
-        SocksOutboundSettings::new()
+    fn new() -> VlessOutboundSettings {
+        VlessOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static SocksOutboundSettings {
-        static instance: ::protobuf::rt::LazyV2<SocksOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(SocksOutboundSettings::new)
+    fn default_instance() -> &'static VlessOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<VlessOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(VlessOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for SocksOutboundSettings {
+impl ::protobuf::Clear for VlessOutboundSettings {
     fn clear(&mut self) {
         self.address.clear();
         self.port = 0;
+        self.uuid.clear();
+        self.flow.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for SocksOutboundSettings {
+impl ::protobuf::reflect::ProtobufValue for VlessOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct ShadowsocksOutboundSettings {
+pub struct WireGuardOutboundSettings {
     // message fields
+    pub private_key: ::std::string::String,
+    pub peer_public_key: ::std::string::String,
+    pub endpoint: ::std::string::String,
     pub address: ::std::string::String,
-    pub port: u32,
-    pub method: ::std::string::String,
-    pub password: ::std::string::String,
+    pub mtu: u32,
+    pub dns: ::protobuf::RepeatedField<::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a ShadowsocksOutboundSettings {
-    fn default() -> &'a ShadowsocksOutboundSettings {
-        <ShadowsocksOutboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a WireGuardOutboundSettings {
+    fn default() -> &'a WireGuardOutboundSettings {
+        <WireGuardOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl ShadowsocksOutboundSettings {
-    pub fn new() -> ShadowsocksOutboundSettings {
+impl WireGuardOutboundSettings {
+    pub fn new() -> WireGuardOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string address = 1;
+    // string private_key = 1;
 
 
-    pub fn get_address(&self) -> &str {
-        &self.address
+    pub fn get_private_key(&self) -> &str {
+        &self.private_key
     }
 
-    // uint32 port = 2;
+    // string peer_public_key = 2;
 
 
-    pub fn get_port(&self) -> u32 {
-        self.port
+    pub fn get_peer_public_key(&self) -> &str {
+        &self.peer_public_key
     }
 
-    // string method = 3;
+    // string endpoint = 3;
 
 
-    pub fn get_method(&self) -> &str {
-        &self.method
+    pub fn get_endpoint(&self) -> &str {
+        &self.endpoint
     }
 
-    // string password = 4;
+    // string address = 4;
 
 
-    pub fn get_password(&self) -> &str {
-        &self.password
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    // uint32 mtu = 5;
+
+
+    pub fn get_mtu(&self) -> u32 {
+        self.mtu
+    }
+
+    // repeated string dns = 6;
+
+
+    pub fn get_dns(&self) -> &[::std::string::String] {
+        &self.dns
     }
 }
 
-impl ::protobuf::Message for ShadowsocksOutboundSettings {
+impl ::protobuf::Message for WireGuardOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -2373,20 +4662,26 @@ impl ::protobuf::Message for ShadowsocksOutboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.private_key)?;
                 },
                 2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.peer_public_key)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.endpoint)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                },
+                5 => {
                     if wire_type != ::protobuf::wire_format::WireTypeVarint {
                         return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
                     }
                     let tmp = is.read_uint32()?;
-                    self.port = tmp;
-                },
-                3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.method)?;
+                    self.mtu = tmp;
                 },
-                4 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                6 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.dns)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -2400,36 +4695,48 @@ impl ::protobuf::Message for ShadowsocksOutboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.address.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.address);
+        if !self.private_key.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.private_key);
         }
-        if self.port != 0 {
-            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
+        if !self.peer_public_key.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.peer_public_key);
         }
-        if !self.method.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.method);
+        if !self.endpoint.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.endpoint);
         }
-        if !self.password.is_empty() {
-            my_size += ::protobuf::rt::string_size(4, &self.password);
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.address);
+        }
+        if self.mtu != 0 {
+            my_size += ::protobuf::rt::value_size(5, self.mtu, ::protobuf::wire_format::WireTypeVarint);
         }
+        for value in &self.dns {
+            my_size += ::protobuf::rt::string_size(6, &value);
+        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.address.is_empty() {
-            os.write_string(1, &self.address)?;
+        if !self.private_key.is_empty() {
+            os.write_string(1, &self.private_key)?;
         }
-        if self.port != 0 {
-            os.write_uint32(2, self.port)?;
+        if !self.peer_public_key.is_empty() {
+            os.write_string(2, &self.peer_public_key)?;
         }
-        if !self.method.is_empty() {
-            os.write_string(3, &self.method)?;
+        if !self.endpoint.is_empty() {
+            os.write_string(3, &self.endpoint)?;
         }
-        if !self.password.is_empty() {
-            os.write_string(4, &self.password)?;
+        if !self.address.is_empty() {
+            os.write_string(4, &self.address)?;
         }
+        if self.mtu != 0 {
+            os.write_uint32(5, self.mtu)?;
+        }
+        for v in &self.dns {
+            os.write_string(6, &v)?;
+        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -2460,77 +4767,127 @@ impl ::protobuf::Message for ShadowsocksOutboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> ShadowsocksOutboundSettings {
-        ShadowsocksOutboundSettings::new()
+    fn new() -> WireGuardOutboundSettings {
+        WireGuardOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static ShadowsocksOutboundSettings {
-        static instance: ::protobuf::rt::LazyV2<ShadowsocksOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(ShadowsocksOutboundSettings::new)
+    fn default_instance() -> &'static WireGuardOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<WireGuardOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(WireGuardOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for ShadowsocksOutboundSettings {
+impl ::protobuf::Clear for WireGuardOutboundSettings {
     fn clear(&mut self) {
+        self.private_key.clear();
+        self.peer_public_key.clear();
+        self.endpoint.clear();
         self.address.clear();
-        self.port = 0;
-        self.method.clear();
-        self.password.clear();
+        self.mtu = 0;
+        self.dns.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for ShadowsocksOutboundSettings {
+impl ::protobuf::reflect::ProtobufValue for WireGuardOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct TrojanOutboundSettings {
+pub struct TlsOutboundSettings {
     // message fields
-    pub address: ::std::string::String,
-    pub port: u32,
-    pub password: ::std::string::String,
+    pub server_name: ::std::string::String,
+    pub alpn: ::protobuf::RepeatedField<::std::string::String>,
+    pub certificate: ::std::string::String,
+    pub verify_time: ::std::string::String,
+    pub session_cache_size: u32,
+    pub insecure: bool,
+    pub min_version: ::std::string::String,
+    pub max_version: ::std::string::String,
+    pub cipher_suites: ::protobuf::RepeatedField<::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a TrojanOutboundSettings {
-    fn default() -> &'a TrojanOutboundSettings {
-        <TrojanOutboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a TlsOutboundSettings {
+    fn default() -> &'a TlsOutboundSettings {
+        <TlsOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl TrojanOutboundSettings {
-    pub fn new() -> TrojanOutboundSettings {
+impl TlsOutboundSettings {
+    pub fn new() -> TlsOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string address = 1;
+    // string server_name = 1;
 
 
-    pub fn get_address(&self) -> &str {
-        &self.address
+    pub fn get_server_name(&self) -> &str {
+        &self.server_name
     }
 
-    // uint32 port = 2;
+    // repeated string alpn = 2;
+
+
+    pub fn get_alpn(&self) -> &[::std::string::String] {
+        &self.alpn
+    }
+
+    // string certificate = 3;
+
+
+    pub fn get_certificate(&self) -> &str {
+        &self.certificate
+    }
+
+    // string verify_time = 4;
+
+
+    pub fn get_verify_time(&self) -> &str {
+        &self.verify_time
+    }
+
+    // uint32 session_cache_size = 5;
+
+
+    pub fn get_session_cache_size(&self) -> u32 {
+        self.session_cache_size
+    }
+
+    // bool insecure = 6;
+
+
+    pub fn get_insecure(&self) -> bool {
+        self.insecure
+    }
+
+    // string min_version = 7;
+
+
+    pub fn get_min_version(&self) -> &str {
+        &self.min_version
+    }
+
+    // string max_version = 8;
 
 
-    pub fn get_port(&self) -> u32 {
-        self.port
+    pub fn get_max_version(&self) -> &str {
+        &self.max_version
     }
 
-    // string password = 3;
+    // repeated string cipher_suites = 9;
 
 
-    pub fn get_password(&self) -> &str {
-        &self.password
+    pub fn get_cipher_suites(&self) -> &[::std::string::String] {
+        &self.cipher_suites
     }
 }
 
-impl ::protobuf::Message for TrojanOutboundSettings {
+impl ::protobuf::Message for TlsOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -2540,17 +4897,39 @@ impl ::protobuf::Message for TrojanOutboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.server_name)?;
                 },
                 2 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.alpn)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.verify_time)?;
+                },
+                5 => {
                     if wire_type != ::protobuf::wire_format::WireTypeVarint {
                         return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
                     }
                     let tmp = is.read_uint32()?;
-                    self.port = tmp;
+                    self.session_cache_size = tmp;
                 },
-                3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.password)?;
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.insecure = tmp;
+                },
+                7 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.min_version)?;
+                },
+                8 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.max_version)?;
+                },
+                9 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.cipher_suites)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -2564,30 +4943,66 @@ impl ::protobuf::Message for TrojanOutboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.address.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.address);
+        if !self.server_name.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.server_name);
         }
-        if self.port != 0 {
-            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
+        for value in &self.alpn {
+            my_size += ::protobuf::rt::string_size(2, &value);
+        };
+        if !self.certificate.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.certificate);
         }
-        if !self.password.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.password);
+        if !self.verify_time.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.verify_time);
+        }
+        if self.session_cache_size != 0 {
+            my_size += ::protobuf::rt::value_size(5, self.session_cache_size, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.insecure != false {
+            my_size += 2;
+        }
+        if !self.min_version.is_empty() {
+            my_size += ::protobuf::rt::string_size(7, &self.min_version);
         }
+        if !self.max_version.is_empty() {
+            my_size += ::protobuf::rt::string_size(8, &self.max_version);
+        }
+        for value in &self.cipher_suites {
+            my_size += ::protobuf::rt::string_size(9, &value);
+        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.address.is_empty() {
-            os.write_string(1, &self.address)?;
+        if !self.server_name.is_empty() {
+            os.write_string(1, &self.server_name)?;
         }
-        if self.port != 0 {
-            os.write_uint32(2, self.port)?;
+        for v in &self.alpn {
+            os.write_string(2, &v)?;
+        };
+        if !self.certificate.is_empty() {
+            os.write_string(3, &self.certificate)?;
         }
-        if !self.password.is_empty() {
-            os.write_string(3, &self.password)?;
+        if !self.verify_time.is_empty() {
+            os.write_string(4, &self.verify_time)?;
+        }
+        if self.session_cache_size != 0 {
+            os.write_uint32(5, self.session_cache_size)?;
         }
+        if self.insecure != false {
+            os.write_bool(6, self.insecure)?;
+        }
+        if !self.min_version.is_empty() {
+            os.write_string(7, &self.min_version)?;
+        }
+        if !self.max_version.is_empty() {
+            os.write_string(8, &self.max_version)?;
+        }
+        for v in &self.cipher_suites {
+            os.write_string(9, &v)?;
+        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -2618,84 +5033,82 @@ impl ::protobuf::Message for TrojanOutboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> TrojanOutboundSettings {
-        TrojanOutboundSettings::new()
+    fn new() -> TlsOutboundSettings {
+        TlsOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static TrojanOutboundSettings {
-        static instance: ::protobuf::rt::LazyV2<TrojanOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(TrojanOutboundSettings::new)
+    fn default_instance() -> &'static TlsOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<TlsOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(TlsOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for TrojanOutboundSettings {
+impl ::protobuf::Clear for TlsOutboundSettings {
     fn clear(&mut self) {
-        self.address.clear();
-        self.port = 0;
-        self.password.clear();
+        self.server_name.clear();
+        self.alpn.clear();
+        self.certificate.clear();
+        self.verify_time.clear();
+        self.session_cache_size = 0;
+        self.insecure = false;
+        self.min_version.clear();
+        self.max_version.clear();
+        self.cipher_suites.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for TrojanOutboundSettings {
+impl ::protobuf::reflect::ProtobufValue for TlsOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct VMessOutboundSettings {
+pub struct WebSocketOutboundSettings {
     // message fields
-    pub address: ::std::string::String,
-    pub port: u32,
-    pub uuid: ::std::string::String,
-    pub security: ::std::string::String,
+    pub path: ::std::string::String,
+    pub headers: ::std::collections::HashMap<::std::string::String, ::std::string::String>,
+    pub host: ::std::string::String,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a VMessOutboundSettings {
-    fn default() -> &'a VMessOutboundSettings {
-        <VMessOutboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a WebSocketOutboundSettings {
+    fn default() -> &'a WebSocketOutboundSettings {
+        <WebSocketOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl VMessOutboundSettings {
-    pub fn new() -> VMessOutboundSettings {
+impl WebSocketOutboundSettings {
+    pub fn new() -> WebSocketOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string address = 1;
-
-
-    pub fn get_address(&self) -> &str {
-        &self.address
-    }
-
-    // uint32 port = 2;
+    // string path = 1;
 
 
-    pub fn get_port(&self) -> u32 {
-        self.port
+    pub fn get_path(&self) -> &str {
+        &self.path
     }
 
-    // string uuid = 3;
+    // repeated .WebSocketOutboundSettings.HeadersEntry headers = 2;
 
 
-    pub fn get_uuid(&self) -> &str {
-        &self.uuid
+    pub fn get_headers(&self) -> &::std::collections::HashMap<::std::string::String, ::std::string::String> {
+        &self.headers
     }
 
-    // string security = 4;
+    // string host = 3;
 
 
-    pub fn get_security(&self) -> &str {
-        &self.security
+    pub fn get_host(&self) -> &str {
+        &self.host
     }
 }
 
-impl ::protobuf::Message for VMessOutboundSettings {
+impl ::protobuf::Message for WebSocketOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -2705,20 +5118,13 @@ impl ::protobuf::Message for VMessOutboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.path)?;
                 },
                 2 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
-                    }
-                    let tmp = is.read_uint32()?;
-                    self.port = tmp;
+                    ::protobuf::rt::read_map_into::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(wire_type, is, &mut self.headers)?;
                 },
                 3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.uuid)?;
-                },
-                4 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.security)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.host)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -2732,17 +5138,12 @@ impl ::protobuf::Message for VMessOutboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.address.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.address);
-        }
-        if self.port != 0 {
-            my_size += ::protobuf::rt::value_size(2, self.port, ::protobuf::wire_format::WireTypeVarint);
-        }
-        if !self.uuid.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.uuid);
+        if !self.path.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.path);
         }
-        if !self.security.is_empty() {
-            my_size += ::protobuf::rt::string_size(4, &self.security);
+        my_size += ::protobuf::rt::compute_map_size::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(2, &self.headers);
+        if !self.host.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.host);
         }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
@@ -2750,17 +5151,12 @@ impl ::protobuf::Message for VMessOutboundSettings {
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.address.is_empty() {
-            os.write_string(1, &self.address)?;
-        }
-        if self.port != 0 {
-            os.write_uint32(2, self.port)?;
-        }
-        if !self.uuid.is_empty() {
-            os.write_string(3, &self.uuid)?;
+        if !self.path.is_empty() {
+            os.write_string(1, &self.path)?;
         }
-        if !self.security.is_empty() {
-            os.write_string(4, &self.security)?;
+        ::protobuf::rt::write_map_with_cached_sizes::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(2, &self.headers, os)?;
+        if !self.host.is_empty() {
+            os.write_string(3, &self.host)?;
         }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
@@ -2792,77 +5188,100 @@ impl ::protobuf::Message for VMessOutboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> VMessOutboundSettings {
-        VMessOutboundSettings::new()
+    fn new() -> WebSocketOutboundSettings {
+        WebSocketOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static VMessOutboundSettings {
-        static instance: ::protobuf::rt::LazyV2<VMessOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(VMessOutboundSettings::new)
+    fn default_instance() -> &'static WebSocketOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<WebSocketOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(WebSocketOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for VMessOutboundSettings {
+impl ::protobuf::Clear for WebSocketOutboundSettings {
     fn clear(&mut self) {
-        self.address.clear();
-        self.port = 0;
-        self.uuid.clear();
-        self.security.clear();
+        self.path.clear();
+        self.headers.clear();
+        self.host.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for VMessOutboundSettings {
+impl ::protobuf::reflect::ProtobufValue for WebSocketOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct TlsOutboundSettings {
+pub struct GrpcOutboundSettings {
     // message fields
-    pub server_name: ::std::string::String,
-    pub alpn: ::protobuf::RepeatedField<::std::string::String>,
-    pub certificate: ::std::string::String,
+    pub service_name: ::std::string::String,
+    pub host: ::std::string::String,
+    pub headers: ::std::collections::HashMap<::std::string::String, ::std::string::String>,
+    pub address: ::std::string::String,
+    pub port: u32,
+    pub actors: ::protobuf::RepeatedField<::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a TlsOutboundSettings {
-    fn default() -> &'a TlsOutboundSettings {
-        <TlsOutboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a GrpcOutboundSettings {
+    fn default() -> &'a GrpcOutboundSettings {
+        <GrpcOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl TlsOutboundSettings {
-    pub fn new() -> TlsOutboundSettings {
+impl GrpcOutboundSettings {
+    pub fn new() -> GrpcOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string server_name = 1;
+    // string service_name = 1;
 
 
-    pub fn get_server_name(&self) -> &str {
-        &self.server_name
+    pub fn get_service_name(&self) -> &str {
+        &self.service_name
     }
 
-    // repeated string alpn = 2;
+    // string host = 2;
 
 
-    pub fn get_alpn(&self) -> &[::std::string::String] {
-        &self.alpn
+    pub fn get_host(&self) -> &str {
+        &self.host
     }
 
-    // string certificate = 3;
+    // repeated .GrpcOutboundSettings.HeadersEntry headers = 3;
 
 
-    pub fn get_certificate(&self) -> &str {
-        &self.certificate
+    pub fn get_headers(&self) -> &::std::collections::HashMap<::std::string::String, ::std::string::String> {
+        &self.headers
+    }
+
+    // string address = 4;
+
+
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    // uint32 port = 5;
+
+
+    pub fn get_port(&self) -> u32 {
+        self.port
+    }
+
+    // repeated string actors = 6;
+
+
+    pub fn get_actors(&self) -> &[::std::string::String] {
+        &self.actors
     }
 }
 
-impl ::protobuf::Message for TlsOutboundSettings {
+impl ::protobuf::Message for GrpcOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -2872,13 +5291,26 @@ impl ::protobuf::Message for TlsOutboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.server_name)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.service_name)?;
                 },
                 2 => {
-                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.alpn)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.host)?;
                 },
                 3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate)?;
+                    ::protobuf::rt::read_map_into::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(wire_type, is, &mut self.headers)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.address)?;
+                },
+                5 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.port = tmp;
+                },
+                6 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.actors)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -2892,30 +5324,44 @@ impl ::protobuf::Message for TlsOutboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
-        if !self.server_name.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.server_name);
+        if !self.service_name.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.service_name);
         }
-        for value in &self.alpn {
-            my_size += ::protobuf::rt::string_size(2, &value);
-        };
-        if !self.certificate.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.certificate);
+        if !self.host.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.host);
+        }
+        my_size += ::protobuf::rt::compute_map_size::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(3, &self.headers);
+        if !self.address.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.address);
+        }
+        if self.port != 0 {
+            my_size += ::protobuf::rt::value_size(5, self.port, ::protobuf::wire_format::WireTypeVarint);
         }
+        for value in &self.actors {
+            my_size += ::protobuf::rt::string_size(6, &value);
+        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if !self.server_name.is_empty() {
-            os.write_string(1, &self.server_name)?;
+        if !self.service_name.is_empty() {
+            os.write_string(1, &self.service_name)?;
         }
-        for v in &self.alpn {
-            os.write_string(2, &v)?;
-        };
-        if !self.certificate.is_empty() {
-            os.write_string(3, &self.certificate)?;
+        if !self.host.is_empty() {
+            os.write_string(2, &self.host)?;
+        }
+        ::protobuf::rt::write_map_with_cached_sizes::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(3, &self.headers, os)?;
+        if !self.address.is_empty() {
+            os.write_string(4, &self.address)?;
+        }
+        if self.port != 0 {
+            os.write_uint32(5, self.port)?;
         }
+        for v in &self.actors {
+            os.write_string(6, &v)?;
+        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -2946,68 +5392,79 @@ impl ::protobuf::Message for TlsOutboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> TlsOutboundSettings {
-        TlsOutboundSettings::new()
+    fn new() -> GrpcOutboundSettings {
+        GrpcOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static TlsOutboundSettings {
-        static instance: ::protobuf::rt::LazyV2<TlsOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(TlsOutboundSettings::new)
+    fn default_instance() -> &'static GrpcOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<GrpcOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(GrpcOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for TlsOutboundSettings {
+impl ::protobuf::Clear for GrpcOutboundSettings {
     fn clear(&mut self) {
-        self.server_name.clear();
-        self.alpn.clear();
-        self.certificate.clear();
+        self.service_name.clear();
+        self.host.clear();
+        self.headers.clear();
+        self.address.clear();
+        self.port = 0;
+        self.actors.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for TlsOutboundSettings {
+impl ::protobuf::reflect::ProtobufValue for GrpcOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
 }
 
 #[derive(PartialEq,Clone,Default,Debug)]
-pub struct WebSocketOutboundSettings {
+pub struct ObfsOutboundSettings {
     // message fields
+    pub mode: ::std::string::String,
+    pub host: ::std::string::String,
     pub path: ::std::string::String,
-    pub headers: ::std::collections::HashMap<::std::string::String, ::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
 }
 
-impl<'a> ::std::default::Default for &'a WebSocketOutboundSettings {
-    fn default() -> &'a WebSocketOutboundSettings {
-        <WebSocketOutboundSettings as ::protobuf::Message>::default_instance()
+impl<'a> ::std::default::Default for &'a ObfsOutboundSettings {
+    fn default() -> &'a ObfsOutboundSettings {
+        <ObfsOutboundSettings as ::protobuf::Message>::default_instance()
     }
 }
 
-impl WebSocketOutboundSettings {
-    pub fn new() -> WebSocketOutboundSettings {
+impl ObfsOutboundSettings {
+    pub fn new() -> ObfsOutboundSettings {
         ::std::default::Default::default()
     }
 
-    // string path = 1;
+    // string mode = 1;
 
 
-    pub fn get_path(&self) -> &str {
-        &self.path
+    pub fn get_mode(&self) -> &str {
+        &self.mode
     }
 
-    // repeated .WebSocketOutboundSettings.HeadersEntry headers = 2;
+    // string host = 2;
 
 
-    pub fn get_headers(&self) -> &::std::collections::HashMap<::std::string::String, ::std::string::String> {
-        &self.headers
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+
+    // string path = 3;
+
+
+    pub fn get_path(&self) -> &str {
+        &self.path
     }
 }
 
-impl ::protobuf::Message for WebSocketOutboundSettings {
+impl ::protobuf::Message for ObfsOutboundSettings {
     fn is_initialized(&self) -> bool {
         true
     }
@@ -3017,10 +5474,13 @@ impl ::protobuf::Message for WebSocketOutboundSettings {
             let (field_number, wire_type) = is.read_tag_unpack()?;
             match field_number {
                 1 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.path)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.mode)?;
                 },
                 2 => {
-                    ::protobuf::rt::read_map_into::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(wire_type, is, &mut self.headers)?;
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.host)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.path)?;
                 },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
@@ -3034,20 +5494,30 @@ impl ::protobuf::Message for WebSocketOutboundSettings {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u32 {
         let mut my_size = 0;
+        if !self.mode.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.mode);
+        }
+        if !self.host.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.host);
+        }
         if !self.path.is_empty() {
-            my_size += ::protobuf::rt::string_size(1, &self.path);
+            my_size += ::protobuf::rt::string_size(3, &self.path);
         }
-        my_size += ::protobuf::rt::compute_map_size::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(2, &self.headers);
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.mode.is_empty() {
+            os.write_string(1, &self.mode)?;
+        }
+        if !self.host.is_empty() {
+            os.write_string(2, &self.host)?;
+        }
         if !self.path.is_empty() {
-            os.write_string(1, &self.path)?;
+            os.write_string(3, &self.path)?;
         }
-        ::protobuf::rt::write_map_with_cached_sizes::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(2, &self.headers, os)?;
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -3078,25 +5548,26 @@ impl ::protobuf::Message for WebSocketOutboundSettings {
         Self::descriptor_static()
     }
 
-    fn new() -> WebSocketOutboundSettings {
-        WebSocketOutboundSettings::new()
+    fn new() -> ObfsOutboundSettings {
+        ObfsOutboundSettings::new()
     }
 
-    fn default_instance() -> &'static WebSocketOutboundSettings {
-        static instance: ::protobuf::rt::LazyV2<WebSocketOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(WebSocketOutboundSettings::new)
+    fn default_instance() -> &'static ObfsOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<ObfsOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(ObfsOutboundSettings::new)
     }
 }
 
-impl ::protobuf::Clear for WebSocketOutboundSettings {
+impl ::protobuf::Clear for ObfsOutboundSettings {
     fn clear(&mut self) {
+        self.mode.clear();
+        self.host.clear();
         self.path.clear();
-        self.headers.clear();
         self.unknown_fields.clear();
     }
 }
 
-impl ::protobuf::reflect::ProtobufValue for WebSocketOutboundSettings {
+impl ::protobuf::reflect::ProtobufValue for ObfsOutboundSettings {
     fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
         ::protobuf::reflect::ReflectValueRef::Message(self)
     }
@@ -3475,6 +5946,163 @@ impl ::protobuf::reflect::ProtobufValue for RROutboundSettings {
     }
 }
 
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct BalanceOutboundSettings {
+    // message fields
+    pub actors: ::protobuf::RepeatedField<::std::string::String>,
+    pub strategy: ::std::string::String,
+    pub fail_cooldown: u32,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a BalanceOutboundSettings {
+    fn default() -> &'a BalanceOutboundSettings {
+        <BalanceOutboundSettings as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl BalanceOutboundSettings {
+    pub fn new() -> BalanceOutboundSettings {
+        ::std::default::Default::default()
+    }
+
+    // repeated string actors = 1;
+
+
+    pub fn get_actors(&self) -> &[::std::string::String] {
+        &self.actors
+    }
+
+    // string strategy = 2;
+
+
+    pub fn get_strategy(&self) -> &str {
+        &self.strategy
+    }
+
+    // uint32 fail_cooldown = 3;
+
+
+    pub fn get_fail_cooldown(&self) -> u32 {
+        self.fail_cooldown
+    }
+}
+
+impl ::protobuf::Message for BalanceOutboundSettings {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.actors)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.strategy)?;
+                },
+                3 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.fail_cooldown = tmp;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        for value in &self.actors {
+            my_size += ::protobuf::rt::string_size(1, &value);
+        };
+        if !self.strategy.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.strategy);
+        }
+        if self.fail_cooldown != 0 {
+            my_size += ::protobuf::rt::value_size(3, self.fail_cooldown, ::protobuf::wire_format::WireTypeVarint);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        for v in &self.actors {
+            os.write_string(1, &v)?;
+        };
+        if !self.strategy.is_empty() {
+            os.write_string(2, &self.strategy)?;
+        }
+        if self.fail_cooldown != 0 {
+            os.write_uint32(3, self.fail_cooldown)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> BalanceOutboundSettings {
+        BalanceOutboundSettings::new()
+    }
+
+    fn default_instance() -> &'static BalanceOutboundSettings {
+        static instance: ::protobuf::rt::LazyV2<BalanceOutboundSettings> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(BalanceOutboundSettings::new)
+    }
+}
+
+impl ::protobuf::Clear for BalanceOutboundSettings {
+    fn clear(&mut self) {
+        self.actors.clear();
+        self.strategy.clear();
+        self.fail_cooldown = 0;
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for BalanceOutboundSettings {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
 #[derive(PartialEq,Clone,Default,Debug)]
 pub struct AMuxOutboundSettings {
     // message fields
@@ -3683,6 +6311,11 @@ pub struct QuicOutboundSettings {
     pub port: u32,
     pub server_name: ::std::string::String,
     pub certificate: ::std::string::String,
+    pub alpn: ::protobuf::RepeatedField<::std::string::String>,
+    pub idle_timeout: u32,
+    pub streams_per_connection: u32,
+    pub datagram: bool,
+    pub zero_rtt: bool,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -3726,6 +6359,41 @@ impl QuicOutboundSettings {
     pub fn get_certificate(&self) -> &str {
         &self.certificate
     }
+
+    // repeated string alpn = 5;
+
+
+    pub fn get_alpn(&self) -> &[::std::string::String] {
+        &self.alpn
+    }
+
+    // uint32 idle_timeout = 6;
+
+
+    pub fn get_idle_timeout(&self) -> u32 {
+        self.idle_timeout
+    }
+
+    // uint32 streams_per_connection = 7;
+
+
+    pub fn get_streams_per_connection(&self) -> u32 {
+        self.streams_per_connection
+    }
+
+    // bool datagram = 8;
+
+
+    pub fn get_datagram(&self) -> bool {
+        self.datagram
+    }
+
+    // bool zero_rtt = 9;
+
+
+    pub fn get_zero_rtt(&self) -> bool {
+        self.zero_rtt
+    }
 }
 
 impl ::protobuf::Message for QuicOutboundSettings {
@@ -3753,6 +6421,37 @@ impl ::protobuf::Message for QuicOutboundSettings {
                 4 => {
                     ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.certificate)?;
                 },
+                5 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.alpn)?;
+                },
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.idle_timeout = tmp;
+                },
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.streams_per_connection = tmp;
+                },
+                8 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.datagram = tmp;
+                },
+                9 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.zero_rtt = tmp;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -3774,8 +6473,23 @@ impl ::protobuf::Message for QuicOutboundSettings {
         if !self.server_name.is_empty() {
             my_size += ::protobuf::rt::string_size(3, &self.server_name);
         }
-        if !self.certificate.is_empty() {
-            my_size += ::protobuf::rt::string_size(4, &self.certificate);
+        if !self.certificate.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.certificate);
+        }
+        for value in &self.alpn {
+            my_size += ::protobuf::rt::string_size(5, &value);
+        };
+        if self.idle_timeout != 0 {
+            my_size += ::protobuf::rt::value_size(6, self.idle_timeout, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.streams_per_connection != 0 {
+            my_size += ::protobuf::rt::value_size(7, self.streams_per_connection, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.datagram != false {
+            my_size += 2;
+        }
+        if self.zero_rtt != false {
+            my_size += 2;
         }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
@@ -3795,6 +6509,21 @@ impl ::protobuf::Message for QuicOutboundSettings {
         if !self.certificate.is_empty() {
             os.write_string(4, &self.certificate)?;
         }
+        for v in &self.alpn {
+            os.write_string(5, &v)?;
+        };
+        if self.idle_timeout != 0 {
+            os.write_uint32(6, self.idle_timeout)?;
+        }
+        if self.streams_per_connection != 0 {
+            os.write_uint32(7, self.streams_per_connection)?;
+        }
+        if self.datagram != false {
+            os.write_bool(8, self.datagram)?;
+        }
+        if self.zero_rtt != false {
+            os.write_bool(9, self.zero_rtt)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -3841,6 +6570,11 @@ impl ::protobuf::Clear for QuicOutboundSettings {
         self.port = 0;
         self.server_name.clear();
         self.certificate.clear();
+        self.alpn.clear();
+        self.idle_timeout = 0;
+        self.streams_per_connection = 0;
+        self.datagram = false;
+        self.zero_rtt = false;
         self.unknown_fields.clear();
     }
 }
@@ -4118,6 +6852,8 @@ pub struct FailOverOutboundSettings {
     pub fallback_cache: bool,
     pub cache_size: u32,
     pub cache_timeout: u32,
+    pub test_url: ::std::string::String,
+    pub tolerance: u32,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -4189,6 +6925,20 @@ impl FailOverOutboundSettings {
     pub fn get_cache_timeout(&self) -> u32 {
         self.cache_timeout
     }
+
+    // string test_url = 9;
+
+
+    pub fn get_test_url(&self) -> &str {
+        &self.test_url
+    }
+
+    // uint32 tolerance = 10;
+
+
+    pub fn get_tolerance(&self) -> u32 {
+        self.tolerance
+    }
 }
 
 impl ::protobuf::Message for FailOverOutboundSettings {
@@ -4252,6 +7002,16 @@ impl ::protobuf::Message for FailOverOutboundSettings {
                     let tmp = is.read_uint32()?;
                     self.cache_timeout = tmp;
                 },
+                9 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.test_url)?;
+                },
+                10 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.tolerance = tmp;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -4288,6 +7048,12 @@ impl ::protobuf::Message for FailOverOutboundSettings {
         if self.cache_timeout != 0 {
             my_size += ::protobuf::rt::value_size(8, self.cache_timeout, ::protobuf::wire_format::WireTypeVarint);
         }
+        if !self.test_url.is_empty() {
+            my_size += ::protobuf::rt::string_size(9, &self.test_url);
+        }
+        if self.tolerance != 0 {
+            my_size += ::protobuf::rt::value_size(10, self.tolerance, ::protobuf::wire_format::WireTypeVarint);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -4318,6 +7084,12 @@ impl ::protobuf::Message for FailOverOutboundSettings {
         if self.cache_timeout != 0 {
             os.write_uint32(8, self.cache_timeout)?;
         }
+        if !self.test_url.is_empty() {
+            os.write_string(9, &self.test_url)?;
+        }
+        if self.tolerance != 0 {
+            os.write_uint32(10, self.tolerance)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -4368,6 +7140,8 @@ impl ::protobuf::Clear for FailOverOutboundSettings {
         self.fallback_cache = false;
         self.cache_size = 0;
         self.cache_timeout = 0;
+        self.test_url.clear();
+        self.tolerance = 0;
         self.unknown_fields.clear();
     }
 }
@@ -4637,6 +7411,10 @@ pub struct Outbound {
     pub protocol: ::std::string::String,
     pub bind: ::std::string::String,
     pub settings: ::std::vec::Vec<u8>,
+    pub nodelay: ::std::string::String,
+    pub quota: ::protobuf::SingularPtrField<Outbound_Quota>,
+    pub connect_timeout: u32,
+    pub handshake_timeout: u32,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -4680,10 +7458,43 @@ impl Outbound {
     pub fn get_settings(&self) -> &[u8] {
         &self.settings
     }
+
+    // string nodelay = 5;
+
+
+    pub fn get_nodelay(&self) -> &str {
+        &self.nodelay
+    }
+
+    // .Outbound.Quota quota = 6;
+
+
+    pub fn get_quota(&self) -> &Outbound_Quota {
+        self.quota.as_ref().unwrap_or_else(|| <Outbound_Quota as ::protobuf::Message>::default_instance())
+    }
+
+    // uint32 connect_timeout = 7;
+
+
+    pub fn get_connect_timeout(&self) -> u32 {
+        self.connect_timeout
+    }
+
+    // uint32 handshake_timeout = 8;
+
+
+    pub fn get_handshake_timeout(&self) -> u32 {
+        self.handshake_timeout
+    }
 }
 
 impl ::protobuf::Message for Outbound {
     fn is_initialized(&self) -> bool {
+        for v in &self.quota {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
         true
     }
 
@@ -4703,6 +7514,26 @@ impl ::protobuf::Message for Outbound {
                 4 => {
                     ::protobuf::rt::read_singular_proto3_bytes_into(wire_type, is, &mut self.settings)?;
                 },
+                5 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.nodelay)?;
+                },
+                6 => {
+                    ::protobuf::rt::read_singular_message_into(wire_type, is, &mut self.quota)?;
+                },
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.connect_timeout = tmp;
+                },
+                8 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.handshake_timeout = tmp;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -4727,6 +7558,19 @@ impl ::protobuf::Message for Outbound {
         if !self.settings.is_empty() {
             my_size += ::protobuf::rt::bytes_size(4, &self.settings);
         }
+        if !self.nodelay.is_empty() {
+            my_size += ::protobuf::rt::string_size(5, &self.nodelay);
+        }
+        if let Some(ref v) = self.quota.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        }
+        if self.connect_timeout != 0 {
+            my_size += ::protobuf::rt::value_size(7, self.connect_timeout, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if self.handshake_timeout != 0 {
+            my_size += ::protobuf::rt::value_size(8, self.handshake_timeout, ::protobuf::wire_format::WireTypeVarint);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -4745,6 +7589,20 @@ impl ::protobuf::Message for Outbound {
         if !self.settings.is_empty() {
             os.write_bytes(4, &self.settings)?;
         }
+        if !self.nodelay.is_empty() {
+            os.write_string(5, &self.nodelay)?;
+        }
+        if let Some(ref v) = self.quota.as_ref() {
+            os.write_tag(6, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        }
+        if self.connect_timeout != 0 {
+            os.write_uint32(7, self.connect_timeout)?;
+        }
+        if self.handshake_timeout != 0 {
+            os.write_uint32(8, self.handshake_timeout)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -4791,6 +7649,10 @@ impl ::protobuf::Clear for Outbound {
         self.protocol.clear();
         self.bind.clear();
         self.settings.clear();
+        self.nodelay.clear();
+        self.quota.clear();
+        self.connect_timeout = 0;
+        self.handshake_timeout = 0;
         self.unknown_fields.clear();
     }
 }
@@ -4801,11 +7663,187 @@ impl ::protobuf::reflect::ProtobufValue for Outbound {
     }
 }
 
+#[derive(PartialEq,Clone,Default,Debug)]
+pub struct Outbound_Quota {
+    // message fields
+    pub bytes: u64,
+    pub period: ::std::string::String,
+    pub action: ::std::string::String,
+    pub fallback: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a Outbound_Quota {
+    fn default() -> &'a Outbound_Quota {
+        <Outbound_Quota as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Outbound_Quota {
+    pub fn new() -> Outbound_Quota {
+        ::std::default::Default::default()
+    }
+
+    // uint64 bytes = 1;
+
+
+    pub fn get_bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    // string period = 2;
+
+
+    pub fn get_period(&self) -> &str {
+        &self.period
+    }
+
+    // string action = 3;
+
+
+    pub fn get_action(&self) -> &str {
+        &self.action
+    }
+
+    // string fallback = 4;
+
+
+    pub fn get_fallback(&self) -> &str {
+        &self.fallback
+    }
+}
+
+impl ::protobuf::Message for Outbound_Quota {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.bytes = tmp;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.period)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.action)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.fallback)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if self.bytes != 0 {
+            my_size += ::protobuf::rt::value_size(1, self.bytes, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if !self.period.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.period);
+        }
+        if !self.action.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.action);
+        }
+        if !self.fallback.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.fallback);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if self.bytes != 0 {
+            os.write_uint64(1, self.bytes)?;
+        }
+        if !self.period.is_empty() {
+            os.write_string(2, &self.period)?;
+        }
+        if !self.action.is_empty() {
+            os.write_string(3, &self.action)?;
+        }
+        if !self.fallback.is_empty() {
+            os.write_string(4, &self.fallback)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> Outbound_Quota {
+        Outbound_Quota::new()
+    }
+
+    fn default_instance() -> &'static Outbound_Quota {
+        static instance: ::protobuf::rt::LazyV2<Outbound_Quota> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Outbound_Quota::new)
+    }
+}
+
+impl ::protobuf::Clear for Outbound_Quota {
+    fn clear(&mut self) {
+        self.bytes = 0;
+        self.period.clear();
+        self.action.clear();
+        self.fallback.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Outbound_Quota {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
 #[derive(PartialEq,Clone,Default,Debug)]
 pub struct Router {
     // message fields
     pub rules: ::protobuf::RepeatedField<Router_Rule>,
     pub domain_resolve: bool,
+    pub route_only: bool,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -4835,6 +7873,13 @@ impl Router {
     pub fn get_domain_resolve(&self) -> bool {
         self.domain_resolve
     }
+
+    // bool route_only = 3;
+
+
+    pub fn get_route_only(&self) -> bool {
+        self.route_only
+    }
 }
 
 impl ::protobuf::Message for Router {
@@ -4861,6 +7906,13 @@ impl ::protobuf::Message for Router {
                     let tmp = is.read_bool()?;
                     self.domain_resolve = tmp;
                 },
+                3 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.route_only = tmp;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -4880,6 +7932,9 @@ impl ::protobuf::Message for Router {
         if self.domain_resolve != false {
             my_size += 2;
         }
+        if self.route_only != false {
+            my_size += 2;
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -4894,6 +7949,9 @@ impl ::protobuf::Message for Router {
         if self.domain_resolve != false {
             os.write_bool(2, self.domain_resolve)?;
         }
+        if self.route_only != false {
+            os.write_bool(3, self.route_only)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -4938,6 +7996,7 @@ impl ::protobuf::Clear for Router {
     fn clear(&mut self) {
         self.rules.clear();
         self.domain_resolve = false;
+        self.route_only = false;
         self.unknown_fields.clear();
     }
 }
@@ -4959,6 +8018,10 @@ pub struct Router_Rule {
     pub networks: ::protobuf::RepeatedField<::std::string::String>,
     pub inbound_tags: ::protobuf::RepeatedField<::std::string::String>,
     pub processes: ::protobuf::RepeatedField<::std::string::String>,
+    pub protocols: ::protobuf::RepeatedField<::std::string::String>,
+    pub source_mmdbs: ::protobuf::RepeatedField<Router_Rule_Mmdb>,
+    pub resolve: bool,
+    pub source_ip_cidrs: ::protobuf::RepeatedField<::std::string::String>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -5030,6 +8093,34 @@ impl Router_Rule {
     pub fn get_processes(&self) -> &[::std::string::String] {
         &self.processes
     }
+
+    // repeated string protocols = 9;
+
+
+    pub fn get_protocols(&self) -> &[::std::string::String] {
+        &self.protocols
+    }
+
+    // repeated .Router.Rule.Mmdb source_mmdbs = 10;
+
+
+    pub fn get_source_mmdbs(&self) -> &[Router_Rule_Mmdb] {
+        &self.source_mmdbs
+    }
+
+    // bool resolve = 11;
+
+
+    pub fn get_resolve(&self) -> bool {
+        self.resolve
+    }
+
+    // repeated string source_ip_cidrs = 12;
+
+
+    pub fn get_source_ip_cidrs(&self) -> &[::std::string::String] {
+        &self.source_ip_cidrs
+    }
 }
 
 impl ::protobuf::Message for Router_Rule {
@@ -5044,6 +8135,11 @@ impl ::protobuf::Message for Router_Rule {
                 return false;
             }
         };
+        for v in &self.source_mmdbs {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
         true
     }
 
@@ -5075,6 +8171,22 @@ impl ::protobuf::Message for Router_Rule {
                 8 => {
                     ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.processes)?;
                 },
+                9 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.protocols)?;
+                },
+                10 => {
+                    ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.source_mmdbs)?;
+                },
+                11 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.resolve = tmp;
+                },
+                12 => {
+                    ::protobuf::rt::read_repeated_string_into(wire_type, is, &mut self.source_ip_cidrs)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -5113,6 +8225,19 @@ impl ::protobuf::Message for Router_Rule {
         for value in &self.processes {
             my_size += ::protobuf::rt::string_size(8, &value);
         };
+        for value in &self.protocols {
+            my_size += ::protobuf::rt::string_size(9, &value);
+        };
+        for value in &self.source_mmdbs {
+            let len = value.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        };
+        if self.resolve != false {
+            my_size += 2;
+        }
+        for value in &self.source_ip_cidrs {
+            my_size += ::protobuf::rt::string_size(12, &value);
+        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -5147,6 +8272,20 @@ impl ::protobuf::Message for Router_Rule {
         for v in &self.processes {
             os.write_string(8, &v)?;
         };
+        for v in &self.protocols {
+            os.write_string(9, &v)?;
+        };
+        for v in &self.source_mmdbs {
+            os.write_tag(10, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        };
+        if self.resolve != false {
+            os.write_bool(11, self.resolve)?;
+        }
+        for v in &self.source_ip_cidrs {
+            os.write_string(12, &v)?;
+        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -5197,6 +8336,10 @@ impl ::protobuf::Clear for Router_Rule {
         self.networks.clear();
         self.inbound_tags.clear();
         self.processes.clear();
+        self.protocols.clear();
+        self.source_mmdbs.clear();
+        self.resolve = false;
+        self.source_ip_cidrs.clear();
         self.unknown_fields.clear();
     }
 }
@@ -5347,6 +8490,7 @@ pub enum Router_Rule_Domain_Type {
     PLAIN = 0,
     DOMAIN = 1,
     FULL = 2,
+    REGEX = 3,
 }
 
 impl ::protobuf::ProtobufEnum for Router_Rule_Domain_Type {
@@ -5359,6 +8503,7 @@ impl ::protobuf::ProtobufEnum for Router_Rule_Domain_Type {
             0 => ::std::option::Option::Some(Router_Rule_Domain_Type::PLAIN),
             1 => ::std::option::Option::Some(Router_Rule_Domain_Type::DOMAIN),
             2 => ::std::option::Option::Some(Router_Rule_Domain_Type::FULL),
+            3 => ::std::option::Option::Some(Router_Rule_Domain_Type::REGEX),
             _ => ::std::option::Option::None
         }
     }
@@ -5368,6 +8513,7 @@ impl ::protobuf::ProtobufEnum for Router_Rule_Domain_Type {
             Router_Rule_Domain_Type::PLAIN,
             Router_Rule_Domain_Type::DOMAIN,
             Router_Rule_Domain_Type::FULL,
+            Router_Rule_Domain_Type::REGEX,
         ];
         values
     }
@@ -13,18 +13,58 @@ use crate::config::{external_rule, internal};
 pub struct Api {
     pub address: Option<String>,
     pub port: Option<u16>,
+    pub secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FakeDns {
+    pub range: Option<String>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Dns {
     pub servers: Option<Vec<String>>,
     pub hosts: Option<HashMap<String, Vec<String>>>,
+    pub outbound: Option<String>,
+    #[serde(rename = "dohServers")]
+    pub doh_servers: Option<Vec<String>>,
+    #[serde(rename = "cacheSize")]
+    pub cache_size: Option<u32>,
+    #[serde(rename = "disableCache")]
+    pub disable_cache: Option<bool>,
+    #[serde(rename = "fakeDns")]
+    pub fake_dns: Option<FakeDns>,
+    #[serde(rename = "tcpServers")]
+    pub tcp_servers: Option<Vec<String>>,
+    #[serde(rename = "bootstrapServers")]
+    pub bootstrap_servers: Option<Vec<String>>,
+    #[serde(rename = "ipStrategy")]
+    pub ip_strategy: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Log {
     pub level: Option<String>,
     pub output: Option<String>,
+    #[serde(rename = "redactUser")]
+    pub redact_user: Option<bool>,
+    pub levels: Option<HashMap<String, String>>,
+    pub format: Option<String>,
+    pub path: Option<String>,
+    #[serde(rename = "maxSizeMb")]
+    pub max_size_mb: Option<u32>,
+    #[serde(rename = "maxFiles")]
+    pub max_files: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SocksInboundSettings {
+    #[serde(rename = "advertisedAddress")]
+    pub advertised_address: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -43,6 +83,18 @@ pub struct WebSocketInboundSettings {
     pub path: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GrpcInboundSettings {
+    #[serde(rename = "serviceName")]
+    pub service_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ObfsInboundSettings {
+    pub mode: Option<String>,
+    pub path: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AMuxInboundSettings {
     pub actors: Option<Vec<String>>,
@@ -53,6 +105,14 @@ pub struct QuicInboundSettings {
     pub certificate: Option<String>,
     #[serde(rename = "certificateKey")]
     pub certificate_key: Option<String>,
+    #[serde(rename = "certificatePassword")]
+    pub certificate_password: Option<String>,
+    pub alpn: Option<Vec<String>>,
+    #[serde(rename = "idleTimeout")]
+    pub idle_timeout: Option<u32>,
+    #[serde(rename = "maxConcurrentBidiStreams")]
+    pub max_concurrent_bidi_streams: Option<u32>,
+    pub datagram: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -60,6 +120,16 @@ pub struct TlsInboundSettings {
     pub certificate: Option<String>,
     #[serde(rename = "certificateKey")]
     pub certificate_key: Option<String>,
+    #[serde(rename = "clientCa")]
+    pub client_ca: Option<String>,
+    #[serde(rename = "minVersion")]
+    pub min_version: Option<String>,
+    #[serde(rename = "maxVersion")]
+    pub max_version: Option<String>,
+    #[serde(rename = "cipherSuites")]
+    pub cipher_suites: Option<Vec<String>>,
+    #[serde(rename = "certificatePassword")]
+    pub certificate_password: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -87,9 +157,20 @@ pub struct Inbound {
     pub tag: Option<String>,
     pub address: Option<String>,
     pub port: Option<u16>,
+    pub nodelay: Option<String>,
+    #[serde(rename = "udpSessionTimeout")]
+    pub udp_session_timeout: Option<u32>,
     pub settings: Option<Box<RawValue>>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DirectOutboundSettings {
+    #[serde(rename = "bindAddress")]
+    pub bind_address: Option<String>,
+    #[serde(rename = "bindInterface")]
+    pub bind_interface: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RedirectOutboundSettings {
     pub address: Option<String>,
@@ -100,6 +181,19 @@ pub struct RedirectOutboundSettings {
 pub struct SocksOutboundSettings {
     pub address: Option<String>,
     pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(rename = "udpOverTcp")]
+    pub udp_over_tcp: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HttpOutboundSettings {
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub headers: Option<HashMap<String, String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -110,6 +204,36 @@ pub struct ShadowsocksOutboundSettings {
     pub password: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VMessOutboundSettings {
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub uuid: Option<String>,
+    pub security: Option<String>,
+    #[serde(rename = "alterId")]
+    pub alter_id: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VlessOutboundSettings {
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub uuid: Option<String>,
+    pub flow: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WireGuardOutboundSettings {
+    #[serde(rename = "privateKey")]
+    pub private_key: Option<String>,
+    #[serde(rename = "peerPublicKey")]
+    pub peer_public_key: Option<String>,
+    pub endpoint: Option<String>,
+    pub address: Option<String>,
+    pub mtu: Option<u32>,
+    pub dns: Option<Vec<String>>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TrojanOutboundSettings {
     pub address: Option<String>,
@@ -129,20 +253,58 @@ pub struct RandomOutboundSettings {
     pub actors: Option<Vec<String>>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BalanceOutboundSettings {
+    pub actors: Option<Vec<String>>,
+    pub strategy: Option<String>,
+    #[serde(rename = "failCooldown")]
+    pub fail_cooldown: Option<u32>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TlsOutboundSettings {
     #[serde(rename = "serverName")]
     pub server_name: Option<String>,
     pub alpn: Option<Vec<String>>,
     pub certificate: Option<String>,
+    #[serde(rename = "verifyTime")]
+    pub verify_time: Option<String>,
+    #[serde(rename = "sessionCacheSize")]
+    pub session_cache_size: Option<u32>,
+    pub insecure: Option<bool>,
+    #[serde(rename = "minVersion")]
+    pub min_version: Option<String>,
+    #[serde(rename = "maxVersion")]
+    pub max_version: Option<String>,
+    #[serde(rename = "cipherSuites")]
+    pub cipher_suites: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WebSocketOutboundSettings {
     pub path: Option<String>,
+    pub host: Option<String>,
     pub headers: Option<HashMap<String, String>>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GrpcOutboundSettings {
+    #[serde(rename = "serviceName")]
+    pub service_name: Option<String>,
+    pub host: Option<String>,
+    pub headers: Option<HashMap<String, String>>,
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub actors: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ObfsOutboundSettings {
+    pub mode: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AMuxOutboundSettings {
     pub address: Option<String>,
@@ -160,6 +322,14 @@ pub struct QuicOutboundSettings {
     #[serde(rename = "serverName")]
     pub server_name: Option<String>,
     pub certificate: Option<String>,
+    pub alpn: Option<Vec<String>>,
+    #[serde(rename = "idleTimeout")]
+    pub idle_timeout: Option<u32>,
+    #[serde(rename = "streamsPerConnection")]
+    pub streams_per_connection: Option<u32>,
+    pub datagram: Option<bool>,
+    #[serde(rename = "zeroRtt")]
+    pub zero_rtt: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -189,6 +359,9 @@ pub struct FailOverOutboundSettings {
     pub cache_size: Option<u32>,
     #[serde(rename = "cacheTimeout")]
     pub cache_timeout: Option<u32>,
+    #[serde(rename = "testUrl")]
+    pub test_url: Option<String>,
+    pub tolerance: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -202,25 +375,66 @@ pub struct PluginOutboundSettings {
     pub args: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Quota {
+    pub bytes: u64,
+    pub period: Option<String>,
+    pub action: Option<String>,
+    pub fallback: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Outbound {
     pub protocol: String,
     pub tag: Option<String>,
+    pub nodelay: Option<String>,
+    pub quota: Option<Quota>,
+    #[serde(rename = "connectTimeout")]
+    pub connect_timeout: Option<u32>,
+    #[serde(rename = "handshakeTimeout")]
+    pub handshake_timeout: Option<u32>,
     pub settings: Option<Box<RawValue>>,
 }
 
+// A port, or a range of ports like `"1000-2000"`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Port {
+    Single(u16),
+    Range(String),
+}
+
+// Conditions of different kinds in a rule must all match, e.g. a rule with
+// `network` and `port` matches UDP:443 only, see `Router::load_rules`.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Rule {
     pub ip: Option<Vec<String>>,
+    #[serde(rename = "ipCidr")]
+    pub ip_cidr: Option<Vec<String>>,
+    // resolves domain destinations to match the ip rules
+    pub resolve: Option<bool>,
     pub domain: Option<Vec<String>>,
     #[serde(rename = "domainKeyword")]
     pub domain_keyword: Option<Vec<String>>,
     #[serde(rename = "domainSuffix")]
     pub domain_suffix: Option<Vec<String>>,
+    #[serde(rename = "domainRegex")]
+    pub domain_regex: Option<Vec<String>>,
     pub geoip: Option<Vec<String>>,
+    #[serde(rename = "sourceGeoip")]
+    pub source_geoip: Option<Vec<String>>,
     pub external: Option<Vec<String>>,
     #[serde(rename = "portRange")]
     pub port_range: Option<Vec<String>>,
+    pub port: Option<Vec<Port>>,
+    pub network: Option<Vec<String>>,
+    #[serde(rename = "inboundTag")]
+    pub inbound_tag: Option<Vec<String>>,
+    #[serde(rename = "sourceIpCidr")]
+    pub source_ip_cidr: Option<Vec<String>>,
+    pub protocol: Option<Vec<String>>,
+    #[serde(rename = "processName")]
+    pub process_name: Option<Vec<String>>,
     pub target: String,
 }
 
@@ -229,6 +443,10 @@ pub struct Router {
     pub rules: Option<Vec<Rule>>,
     #[serde(rename = "domainResolve")]
     pub domain_resolve: Option<bool>,
+    #[serde(rename = "routeOnly")]
+    pub route_only: Option<bool>,
+    #[serde(rename = "geoipDatabase")]
+    pub geoip_database: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -258,12 +476,48 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
         if let Some(ext_output) = &ext_log.output {
             match ext_output.as_str() {
                 "console" => log.output = internal::Log_Output::CONSOLE,
+                "file" => {
+                    log.output = internal::Log_Output::FILE;
+                    log.output_file = ext_log
+                        .path
+                        .clone()
+                        .ok_or_else(|| anyhow!("missing log file path"))?;
+                }
                 _ => {
                     log.output = internal::Log_Output::FILE;
                     log.output_file = ext_output.clone();
                 }
             }
         }
+
+        if let Some(ext_max_size_mb) = ext_log.max_size_mb {
+            log.max_size_mb = ext_max_size_mb;
+        }
+
+        if let Some(ext_max_files) = ext_log.max_files {
+            log.max_files = ext_max_files;
+        }
+
+        if let Some(ext_redact_user) = ext_log.redact_user {
+            log.redact_user = ext_redact_user;
+        }
+
+        if let Some(ext_levels) = &ext_log.levels {
+            let mut target_levels: Vec<String> = ext_levels
+                .iter()
+                .map(|(target, level)| format!("{}={}", target, level))
+                .collect();
+            target_levels.sort();
+            log.target_levels = protobuf::RepeatedField::from_vec(target_levels);
+        }
+
+        if let Some(ext_format) = &ext_log.format {
+            match ext_format.as_str() {
+                "json" => log.format = internal::Log_Format::JSON,
+                "text" => log.format = internal::Log_Format::TEXT,
+                _ => return Err(anyhow!("invalid log format {}", ext_format)),
+            }
+        }
     }
 
     let mut inbounds = protobuf::RepeatedField::new();
@@ -281,6 +535,13 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
             }
             if let Some(ext_port) = ext_inbound.port {
                 inbound.port = ext_port as u32;
+                inbound.random_port = ext_port == 0;
+            }
+            if let Some(ext_nodelay) = &ext_inbound.nodelay {
+                inbound.nodelay = ext_nodelay.to_owned();
+            }
+            if let Some(ext_udp_session_timeout) = ext_inbound.udp_session_timeout {
+                inbound.udp_session_timeout = ext_udp_session_timeout;
             }
             match inbound.protocol.as_str() {
                 #[cfg(any(
@@ -346,7 +607,27 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                 "http" => {
                     inbounds.push(inbound);
                 }
+                "tproxy" => {
+                    inbounds.push(inbound);
+                }
                 "socks" => {
+                    if let Some(ext_settings) = &ext_inbound.settings {
+                        let mut settings = internal::SocksInboundSettings::new();
+                        let ext_settings: SocksInboundSettings =
+                            serde_json::from_str(ext_settings.get())
+                                .map_err(|e| anyhow!("invalid socks inbound settings: {}", e))?;
+                        if let Some(ext_advertised_address) = ext_settings.advertised_address {
+                            settings.advertised_address = ext_advertised_address;
+                        }
+                        if let Some(ext_username) = ext_settings.username {
+                            settings.username = ext_username;
+                        }
+                        if let Some(ext_password) = ext_settings.password {
+                            settings.password = ext_password;
+                        }
+                        let settings = settings.write_to_bytes().unwrap();
+                        inbound.settings = settings;
+                    }
                     inbounds.push(inbound);
                 }
                 "shadowsocks" => {
@@ -394,6 +675,37 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                     inbound.settings = settings;
                     inbounds.push(inbound);
                 }
+                "grpc" => {
+                    let mut settings = internal::GrpcInboundSettings::new();
+                    if let Some(ext_settings) = &ext_inbound.settings {
+                        let ext_settings: GrpcInboundSettings =
+                            serde_json::from_str(ext_settings.get())
+                                .map_err(|e| anyhow!("invalid grpc inbound settings: {}", e))?;
+                        if let Some(ext_service_name) = ext_settings.service_name {
+                            settings.service_name = ext_service_name;
+                        }
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    inbound.settings = settings;
+                    inbounds.push(inbound);
+                }
+                "obfs" => {
+                    let mut settings = internal::ObfsInboundSettings::new();
+                    if let Some(ext_settings) = &ext_inbound.settings {
+                        let ext_settings: ObfsInboundSettings =
+                            serde_json::from_str(ext_settings.get())
+                                .map_err(|e| anyhow!("invalid obfs inbound settings: {}", e))?;
+                        if let Some(ext_mode) = ext_settings.mode {
+                            settings.mode = ext_mode;
+                        }
+                        if let Some(ext_path) = ext_settings.path {
+                            settings.path = ext_path;
+                        }
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    inbound.settings = settings;
+                    inbounds.push(inbound);
+                }
                 "amux" => {
                     let mut settings = internal::AMuxInboundSettings::new();
                     if let Some(ext_settings) = &ext_inbound.settings {
@@ -435,6 +747,23 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                             settings.certificate_key = path;
                         }
                     }
+                    if let Some(ext_certificate_password) = ext_settings.certificate_password {
+                        settings.certificate_password = ext_certificate_password;
+                    }
+                    if let Some(ext_alpn) = ext_settings.alpn {
+                        settings.alpn = protobuf::RepeatedField::from_vec(ext_alpn);
+                    }
+                    if let Some(ext_idle_timeout) = ext_settings.idle_timeout {
+                        settings.idle_timeout = ext_idle_timeout;
+                    }
+                    if let Some(ext_max_concurrent_bidi_streams) =
+                        ext_settings.max_concurrent_bidi_streams
+                    {
+                        settings.max_concurrent_bidi_streams = ext_max_concurrent_bidi_streams;
+                    }
+                    if let Some(ext_datagram) = ext_settings.datagram {
+                        settings.datagram = ext_datagram;
+                    }
                     let settings = settings.write_to_bytes().unwrap();
                     inbound.settings = settings;
                     inbounds.push(inbound);
@@ -463,6 +792,33 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                             settings.certificate_key = path;
                         }
                     }
+                    if let Some(ext_client_ca) = ext_settings.client_ca {
+                        // Otherwise it would be the asset location.
+                        if ext_client_ca.is_empty() {
+                            return Err(anyhow!("invalid tls inbound settings: empty clientCa"));
+                        }
+                        let ca = Path::new(&ext_client_ca);
+                        if ca.is_absolute() {
+                            settings.client_ca = ca.to_string_lossy().to_string();
+                        } else {
+                            let asset_loc = Path::new(&*crate::option::ASSET_LOCATION);
+                            let path = asset_loc.join(ca).to_string_lossy().to_string();
+                            settings.client_ca = path;
+                        }
+                    }
+                    if let Some(ext_min_version) = ext_settings.min_version {
+                        settings.min_version = ext_min_version;
+                    }
+                    if let Some(ext_max_version) = ext_settings.max_version {
+                        settings.max_version = ext_max_version;
+                    }
+                    if let Some(ext_cipher_suites) = ext_settings.cipher_suites {
+                        settings.cipher_suites =
+                            protobuf::RepeatedField::from_vec(ext_cipher_suites);
+                    }
+                    if let Some(ext_certificate_password) = ext_settings.certificate_password {
+                        settings.certificate_password = ext_certificate_password;
+                    }
                     let settings = settings.write_to_bytes().unwrap();
                     inbound.settings = settings;
                     inbounds.push(inbound);
@@ -498,8 +854,47 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
             if let Some(ext_tag) = &ext_outbound.tag {
                 outbound.tag = ext_tag.to_owned();
             }
+            if let Some(ext_nodelay) = &ext_outbound.nodelay {
+                outbound.nodelay = ext_nodelay.to_owned();
+            }
+            if let Some(ext_connect_timeout) = ext_outbound.connect_timeout {
+                outbound.connect_timeout = ext_connect_timeout;
+            }
+            if let Some(ext_handshake_timeout) = ext_outbound.handshake_timeout {
+                outbound.handshake_timeout = ext_handshake_timeout;
+            }
+            if let Some(ext_quota) = &ext_outbound.quota {
+                let mut quota = internal::Outbound_Quota::new();
+                quota.bytes = ext_quota.bytes;
+                if let Some(ext_period) = &ext_quota.period {
+                    quota.period = ext_period.to_owned();
+                }
+                if let Some(ext_action) = &ext_quota.action {
+                    quota.action = ext_action.to_owned();
+                }
+                if let Some(ext_fallback) = &ext_quota.fallback {
+                    quota.fallback = ext_fallback.to_owned();
+                }
+                outbound.quota = protobuf::SingularPtrField::some(quota);
+            }
             match outbound.protocol.as_str() {
-                "direct" | "drop" => {
+                "direct" => {
+                    // Settings are optional.
+                    if let Some(ext_settings) = ext_outbound.settings.as_ref() {
+                        let mut settings = internal::DirectOutboundSettings::new();
+                        let ext_settings: DirectOutboundSettings =
+                            serde_json::from_str(ext_settings.get()).unwrap();
+                        if let Some(ext_bind_address) = ext_settings.bind_address {
+                            settings.bind_address = ext_bind_address;
+                        }
+                        if let Some(ext_bind_interface) = ext_settings.bind_interface {
+                            settings.bind_interface = ext_bind_interface;
+                        }
+                        outbound.settings = settings.write_to_bytes().unwrap();
+                    }
+                    outbounds.push(outbound);
+                }
+                "drop" | "reject" => {
                     outbounds.push(outbound);
                 }
                 "redirect" => {
@@ -534,6 +929,44 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                     if let Some(ext_port) = ext_settings.port {
                         settings.port = ext_port as u32; // TODO checks
                     }
+                    if let Some(ext_username) = ext_settings.username {
+                        settings.username = ext_username;
+                    }
+                    if let Some(ext_password) = ext_settings.password {
+                        settings.password = ext_password;
+                    }
+                    if let Some(ext_udp_over_tcp) = ext_settings.udp_over_tcp {
+                        settings.udp_over_tcp = ext_udp_over_tcp;
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
+                "http" => {
+                    if ext_outbound.settings.is_none() {
+                        return Err(anyhow!("invalid http outbound settings"));
+                    }
+                    let mut settings = internal::HttpOutboundSettings::new();
+                    let ext_settings: HttpOutboundSettings =
+                        serde_json::from_str(ext_outbound.settings.as_ref().unwrap().get())
+                            .map_err(|e| anyhow!("invalid http outbound settings: {}", e))?;
+                    if let Some(ext_address) = ext_settings.address {
+                        settings.address = ext_address;
+                    }
+                    if let Some(ext_port) = ext_settings.port {
+                        settings.port = ext_port as u32;
+                    }
+                    if let Some(ext_username) = ext_settings.username {
+                        settings.username = ext_username;
+                    }
+                    if let Some(ext_password) = ext_settings.password {
+                        settings.password = ext_password;
+                    }
+                    if let Some(ext_headers) = ext_settings.headers {
+                        crate::common::header::validate(&ext_headers)
+                            .map_err(|e| anyhow!("invalid http outbound settings: {}", e))?;
+                        settings.headers = ext_headers;
+                    }
                     let settings = settings.write_to_bytes().unwrap();
                     outbound.settings = settings;
                     outbounds.push(outbound);
@@ -564,6 +997,89 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                     outbound.settings = settings;
                     outbounds.push(outbound);
                 }
+                "vmess" => {
+                    if ext_outbound.settings.is_none() {
+                        return Err(anyhow!("invalid vmess outbound settings"));
+                    }
+                    let mut settings = internal::VMessOutboundSettings::new();
+                    let ext_settings: VMessOutboundSettings =
+                        serde_json::from_str(ext_outbound.settings.as_ref().unwrap().get())
+                            .map_err(|e| anyhow!("invalid vmess outbound settings: {}", e))?;
+                    if let Some(ext_address) = ext_settings.address {
+                        settings.address = ext_address;
+                    }
+                    if let Some(ext_port) = ext_settings.port {
+                        settings.port = ext_port as u32;
+                    }
+                    if let Some(ext_uuid) = ext_settings.uuid {
+                        settings.uuid = ext_uuid;
+                    }
+                    if let Some(ext_security) = ext_settings.security {
+                        settings.security = ext_security;
+                    } else {
+                        settings.security = "chacha20-ietf-poly1305".to_string();
+                    }
+                    if let Some(ext_alter_id) = ext_settings.alter_id {
+                        settings.alter_id = ext_alter_id as u32;
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
+                "vless" => {
+                    if ext_outbound.settings.is_none() {
+                        return Err(anyhow!("invalid vless outbound settings"));
+                    }
+                    let mut settings = internal::VlessOutboundSettings::new();
+                    let ext_settings: VlessOutboundSettings =
+                        serde_json::from_str(ext_outbound.settings.as_ref().unwrap().get())
+                            .map_err(|e| anyhow!("invalid vless outbound settings: {}", e))?;
+                    if let Some(ext_address) = ext_settings.address {
+                        settings.address = ext_address;
+                    }
+                    if let Some(ext_port) = ext_settings.port {
+                        settings.port = ext_port as u32;
+                    }
+                    if let Some(ext_uuid) = ext_settings.uuid {
+                        settings.uuid = ext_uuid;
+                    }
+                    if let Some(ext_flow) = ext_settings.flow {
+                        settings.flow = ext_flow;
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
+                "wireguard" => {
+                    if ext_outbound.settings.is_none() {
+                        return Err(anyhow!("invalid wireguard outbound settings"));
+                    }
+                    let mut settings = internal::WireGuardOutboundSettings::new();
+                    let ext_settings: WireGuardOutboundSettings =
+                        serde_json::from_str(ext_outbound.settings.as_ref().unwrap().get())
+                            .map_err(|e| anyhow!("invalid wireguard outbound settings: {}", e))?;
+                    if let Some(ext_private_key) = ext_settings.private_key {
+                        settings.private_key = ext_private_key;
+                    }
+                    if let Some(ext_peer_public_key) = ext_settings.peer_public_key {
+                        settings.peer_public_key = ext_peer_public_key;
+                    }
+                    if let Some(ext_endpoint) = ext_settings.endpoint {
+                        settings.endpoint = ext_endpoint;
+                    }
+                    if let Some(ext_address) = ext_settings.address {
+                        settings.address = ext_address;
+                    }
+                    if let Some(ext_mtu) = ext_settings.mtu {
+                        settings.mtu = ext_mtu;
+                    }
+                    if let Some(ext_dns) = ext_settings.dns {
+                        settings.dns = protobuf::RepeatedField::from_vec(ext_dns);
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
                 "trojan" => {
                     if ext_outbound.settings.is_none() {
                         return Err(anyhow!("invalid trojan outbound settings"));
@@ -613,6 +1129,25 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                                 settings.certificate = path;
                             }
                         }
+                        if let Some(ext_verify_time) = ext_settings.verify_time {
+                            settings.verify_time = ext_verify_time;
+                        }
+                        if let Some(ext_session_cache_size) = ext_settings.session_cache_size {
+                            settings.session_cache_size = ext_session_cache_size;
+                        }
+                        if let Some(ext_insecure) = ext_settings.insecure {
+                            settings.insecure = ext_insecure;
+                        }
+                        if let Some(ext_min_version) = ext_settings.min_version {
+                            settings.min_version = ext_min_version;
+                        }
+                        if let Some(ext_max_version) = ext_settings.max_version {
+                            settings.max_version = ext_max_version;
+                        }
+                        if let Some(ext_cipher_suites) = ext_settings.cipher_suites {
+                            settings.cipher_suites =
+                                protobuf::RepeatedField::from_vec(ext_cipher_suites);
+                        }
                     }
                     let settings = settings.write_to_bytes().unwrap();
                     outbound.settings = settings;
@@ -630,13 +1165,69 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                     if let Some(ext_path) = ext_settings.path {
                         settings.path = ext_path; // TODO checks
                     }
+                    if let Some(ext_host) = ext_settings.host {
+                        settings.host = ext_host;
+                    }
                     if let Some(ext_headers) = ext_settings.headers {
+                        crate::common::header::validate(&ext_headers)
+                            .map_err(|e| anyhow!("invalid ws outbound settings: {}", e))?;
                         settings.headers = ext_headers;
                     }
                     let settings = settings.write_to_bytes().unwrap();
                     outbound.settings = settings;
                     outbounds.push(outbound);
                 }
+                "grpc" => {
+                    let mut settings = internal::GrpcOutboundSettings::new();
+                    if let Some(ext_settings) = &ext_outbound.settings {
+                        let ext_settings: GrpcOutboundSettings =
+                            serde_json::from_str(ext_settings.get())
+                                .map_err(|e| anyhow!("invalid grpc outbound settings: {}", e))?;
+                        if let Some(ext_service_name) = ext_settings.service_name {
+                            settings.service_name = ext_service_name;
+                        }
+                        if let Some(ext_host) = ext_settings.host {
+                            settings.host = ext_host;
+                        }
+                        if let Some(ext_headers) = ext_settings.headers {
+                            crate::common::header::validate(&ext_headers)
+                                .map_err(|e| anyhow!("invalid grpc outbound settings: {}", e))?;
+                            settings.headers = ext_headers;
+                        }
+                        if let Some(ext_address) = ext_settings.address {
+                            settings.address = ext_address;
+                        }
+                        if let Some(ext_port) = ext_settings.port {
+                            settings.port = ext_port as u32;
+                        }
+                        if let Some(ext_actors) = ext_settings.actors {
+                            settings.actors = protobuf::RepeatedField::from_vec(ext_actors);
+                        }
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
+                "obfs" => {
+                    let mut settings = internal::ObfsOutboundSettings::new();
+                    if let Some(ext_settings) = &ext_outbound.settings {
+                        let ext_settings: ObfsOutboundSettings =
+                            serde_json::from_str(ext_settings.get())
+                                .map_err(|e| anyhow!("invalid obfs outbound settings: {}", e))?;
+                        if let Some(ext_mode) = ext_settings.mode {
+                            settings.mode = ext_mode;
+                        }
+                        if let Some(ext_host) = ext_settings.host {
+                            settings.host = ext_host;
+                        }
+                        if let Some(ext_path) = ext_settings.path {
+                            settings.path = ext_path;
+                        }
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
                 "tryall" => {
                     if ext_outbound.settings.is_none() {
                         return Err(anyhow!("invalid tryall outbound settings"));
@@ -676,6 +1267,29 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                     outbound.settings = settings;
                     outbounds.push(outbound);
                 }
+                "balance" => {
+                    if ext_outbound.settings.is_none() {
+                        return Err(anyhow!("invalid balance outbound settings"));
+                    }
+                    let mut settings = internal::BalanceOutboundSettings::new();
+                    let ext_settings: BalanceOutboundSettings =
+                        serde_json::from_str(ext_outbound.settings.as_ref().unwrap().get())
+                            .unwrap();
+                    if let Some(ext_actors) = ext_settings.actors {
+                        for ext_actor in ext_actors {
+                            settings.actors.push(ext_actor);
+                        }
+                    }
+                    if let Some(ext_strategy) = ext_settings.strategy {
+                        settings.strategy = ext_strategy;
+                    }
+                    if let Some(ext_fail_cooldown) = ext_settings.fail_cooldown {
+                        settings.fail_cooldown = ext_fail_cooldown;
+                    }
+                    let settings = settings.write_to_bytes().unwrap();
+                    outbound.settings = settings;
+                    outbounds.push(outbound);
+                }
                 "failover" => {
                     if ext_outbound.settings.is_none() {
                         return Err(anyhow!("invalid failover outbound settings"));
@@ -724,6 +1338,12 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                     } else {
                         settings.cache_timeout = 60; // in minutes
                     }
+                    if let Some(ext_test_url) = ext_settings.test_url {
+                        settings.test_url = ext_test_url;
+                    }
+                    if let Some(ext_tolerance) = ext_settings.tolerance {
+                        settings.tolerance = ext_tolerance;
+                    }
                     let settings = settings.write_to_bytes().unwrap();
                     outbound.settings = settings;
                     outbounds.push(outbound);
@@ -786,6 +1406,23 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                                 settings.certificate = path;
                             }
                         }
+                        if let Some(ext_alpn) = ext_settings.alpn {
+                            settings.alpn = protobuf::RepeatedField::from_vec(ext_alpn);
+                        }
+                        if let Some(ext_idle_timeout) = ext_settings.idle_timeout {
+                            settings.idle_timeout = ext_idle_timeout;
+                        }
+                        if let Some(ext_streams_per_connection) =
+                            ext_settings.streams_per_connection
+                        {
+                            settings.streams_per_connection = ext_streams_per_connection;
+                        }
+                        if let Some(ext_datagram) = ext_settings.datagram {
+                            settings.datagram = ext_datagram;
+                        }
+                        if let Some(ext_zero_rtt) = ext_settings.zero_rtt {
+                            settings.zero_rtt = ext_zero_rtt;
+                        }
                     }
                     let settings = settings.write_to_bytes().unwrap();
                     outbound.settings = settings;
@@ -876,8 +1513,14 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
     if let Some(ext_router) = json.router.as_mut() {
         let mut int_router = internal::Router::new();
         let mut rules = protobuf::RepeatedField::new();
+        // relative to the asset location if not absolute
+        let geoip_database = Path::new(&*crate::option::ASSET_LOCATION)
+            .join(ext_router.geoip_database.as_deref().unwrap_or("geo.mmdb"))
+            .to_string_lossy()
+            .to_string();
         if let Some(ext_rules) = ext_router.rules.as_mut() {
-            // a map for caching external site so we need not load a same file multiple times
+            // a map for caching parsed site files so we need not load a same file multiple times
+            let mut site_lists = HashMap::new();
             for ext_rule in ext_rules.iter_mut() {
                 let mut rule = internal::Router_Rule::new();
                 let target_tag = std::mem::take(&mut ext_rule.target);
@@ -887,8 +1530,20 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                         rule.ip_cidrs.push(ext_ip);
                     }
                 }
+                if let Some(ext_ip_cidrs) = ext_rule.ip_cidr.as_mut() {
+                    for ext_ip_cidr in ext_ip_cidrs.drain(0..) {
+                        rule.ip_cidrs.push(ext_ip_cidr);
+                    }
+                }
+                if let Some(ext_resolve) = ext_rule.resolve {
+                    rule.resolve = ext_resolve;
+                }
                 if let Some(ext_domains) = ext_rule.domain.as_mut() {
                     for ext_domain in ext_domains.drain(0..) {
+                        if ext_domain.starts_with("geosite:") {
+                            external_rule::add_site_rule(&mut rule, &ext_domain, &mut site_lists)?;
+                            continue;
+                        }
                         let mut domain = internal::Router_Rule_Domain::new();
                         domain.field_type = internal::Router_Rule_Domain_Type::FULL;
                         domain.value = ext_domain;
@@ -911,15 +1566,30 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                         rule.domains.push(domain);
                     }
                 }
+                if let Some(ext_domain_regexes) = ext_rule.domain_regex.as_mut() {
+                    for ext_domain_regex in ext_domain_regexes.drain(0..) {
+                        let mut domain = internal::Router_Rule_Domain::new();
+                        domain.field_type = internal::Router_Rule_Domain_Type::REGEX;
+                        domain.value = ext_domain_regex;
+                        rule.domains.push(domain);
+                    }
+                }
                 if let Some(ext_geoips) = ext_rule.geoip.as_mut() {
                     for ext_geoip in ext_geoips.drain(0..) {
                         let mut mmdb = internal::Router_Rule_Mmdb::new();
-                        let asset_loc = Path::new(&*crate::option::ASSET_LOCATION);
-                        mmdb.file = asset_loc.join("geo.mmdb").to_string_lossy().to_string();
+                        mmdb.file = geoip_database.clone();
                         mmdb.country_code = ext_geoip;
                         rule.mmdbs.push(mmdb)
                     }
                 }
+                if let Some(ext_source_geoips) = ext_rule.source_geoip.as_mut() {
+                    for ext_source_geoip in ext_source_geoips.drain(0..) {
+                        let mut mmdb = internal::Router_Rule_Mmdb::new();
+                        mmdb.file = geoip_database.clone();
+                        mmdb.country_code = ext_source_geoip;
+                        rule.source_mmdbs.push(mmdb)
+                    }
+                }
                 if let Some(ext_externals) = ext_rule.external.as_mut() {
                     for ext_external in ext_externals.drain(0..) {
                         match external_rule::add_external_rule(&mut rule, &ext_external) {
@@ -932,10 +1602,42 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                 }
                 if let Some(ext_port_ranges) = ext_rule.port_range.as_mut() {
                     for ext_port_range in ext_port_ranges.drain(0..) {
-                        // FIXME validate
                         rule.port_ranges.push(ext_port_range);
                     }
                 }
+                if let Some(ext_ports) = ext_rule.port.as_mut() {
+                    for ext_port in ext_ports.drain(0..) {
+                        rule.port_ranges.push(match ext_port {
+                            Port::Single(port) => port.to_string(),
+                            Port::Range(range) => range,
+                        });
+                    }
+                }
+                if let Some(ext_networks) = ext_rule.network.as_mut() {
+                    for ext_network in ext_networks.drain(0..) {
+                        rule.networks.push(ext_network);
+                    }
+                }
+                if let Some(ext_inbound_tags) = ext_rule.inbound_tag.as_mut() {
+                    for ext_inbound_tag in ext_inbound_tags.drain(0..) {
+                        rule.inbound_tags.push(ext_inbound_tag);
+                    }
+                }
+                if let Some(ext_source_ip_cidrs) = ext_rule.source_ip_cidr.as_mut() {
+                    for ext_source_ip_cidr in ext_source_ip_cidrs.drain(0..) {
+                        rule.source_ip_cidrs.push(ext_source_ip_cidr);
+                    }
+                }
+                if let Some(ext_protocols) = ext_rule.protocol.as_mut() {
+                    for ext_protocol in ext_protocols.drain(0..) {
+                        rule.protocols.push(ext_protocol);
+                    }
+                }
+                if let Some(ext_process_names) = ext_rule.process_name.as_mut() {
+                    for ext_process_name in ext_process_names.drain(0..) {
+                        rule.processes.push(ext_process_name);
+                    }
+                }
                 rules.push(rule);
             }
         }
@@ -943,6 +1645,9 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
         if let Some(ext_domain_resolve) = ext_router.domain_resolve {
             int_router.domain_resolve = ext_domain_resolve;
         }
+        if let Some(ext_route_only) = ext_router.route_only {
+            int_router.route_only = ext_route_only;
+        }
         router = protobuf::SingularPtrField::some(int_router);
     }
 
@@ -955,6 +1660,56 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
                 servers.push(ext_server.to_owned());
             }
         }
+        if let Some(ext_outbound) = ext_dns.outbound.as_ref() {
+            dns.outbound = ext_outbound.to_owned();
+        }
+        if let Some(ext_doh_servers) = ext_dns.doh_servers.as_ref() {
+            for ext_doh_server in ext_doh_servers {
+                dns.doh_servers.push(ext_doh_server.to_owned());
+            }
+        }
+        if let Some(ext_tcp_servers) = ext_dns.tcp_servers.as_ref() {
+            for ext_tcp_server in ext_tcp_servers {
+                dns.tcp_servers.push(ext_tcp_server.to_owned());
+            }
+        }
+        if let Some(ext_bootstrap_servers) = ext_dns.bootstrap_servers.as_ref() {
+            for ext_bootstrap_server in ext_bootstrap_servers {
+                dns.bootstrap_servers.push(ext_bootstrap_server.to_owned());
+            }
+        }
+        if let Some(ext_ip_strategy) = &ext_dns.ip_strategy {
+            dns.ip_strategy = match ext_ip_strategy.as_str() {
+                "prefer_ipv4" => internal::Dns_IpStrategy::PREFER_IPV4,
+                "prefer_ipv6" => internal::Dns_IpStrategy::PREFER_IPV6,
+                "ipv4_only" => internal::Dns_IpStrategy::IPV4_ONLY,
+                "ipv6_only" => internal::Dns_IpStrategy::IPV6_ONLY,
+                _ => return Err(anyhow!("invalid ip strategy {}", ext_ip_strategy)),
+            };
+        }
+        if let Some(ext_cache_size) = ext_dns.cache_size {
+            dns.cache_size = ext_cache_size;
+        }
+        if let Some(ext_disable_cache) = ext_dns.disable_cache {
+            dns.disable_cache = ext_disable_cache;
+        }
+        if let Some(ext_fake_dns) = ext_dns.fake_dns.as_ref() {
+            let mut fake_dns = internal::Dns_FakeDns::new();
+            if let Some(ext_range) = ext_fake_dns.range.as_ref() {
+                fake_dns.range = ext_range.to_owned();
+            }
+            if let Some(ext_include) = ext_fake_dns.include.as_ref() {
+                for ext_filter in ext_include {
+                    fake_dns.include.push(ext_filter.to_owned());
+                }
+            }
+            if let Some(ext_exclude) = ext_fake_dns.exclude.as_ref() {
+                for ext_filter in ext_exclude {
+                    fake_dns.exclude.push(ext_filter.to_owned());
+                }
+            }
+            dns.fake_dns = protobuf::SingularPtrField::some(fake_dns);
+        }
         if let Some(ext_hosts) = ext_dns.hosts.as_ref() {
             for (name, static_ips) in ext_hosts.iter() {
                 let mut ips = internal::Dns_Ips::new();
@@ -983,6 +1738,9 @@ pub fn to_internal(json: &mut Config) -> Result<internal::Config> {
             let mut api = internal::Api::new();
             api.address = ext_address.to_owned();
             api.port = ext_port.to_owned() as u32;
+            if let Some(ext_secret) = ext_api.secret.as_ref() {
+                api.secret = ext_secret.to_owned();
+            }
             protobuf::SingularPtrField::some(api)
         } else {
             protobuf::SingularPtrField::none()
@@ -71,6 +71,12 @@ fn test_config() {
                     ],
                     "target": "direct_out"
                 },
+                {
+                    "domainRegex": [
+                        "^img\\d+\\.google\\.com$"
+                    ],
+                    "target": "direct_out"
+                },
                 {
                     "external": [
                         "site:cn"
@@ -90,3 +96,833 @@ fn test_config() {
 
     assert!(crate::config::json::json_from_string(json_str).is_ok());
 }
+
+#[test]
+fn test_vmess_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "vmess",
+                "tag": "vmess_out",
+                "settings": {
+                    "address": "127.0.0.1",
+                    "port": 10086,
+                    "uuid": "b831381d-6324-4d53-ad4f-8cda48b30811",
+                    "security": "aes-128-gcm",
+                    "alterId": 4
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::VMessOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.address, "127.0.0.1");
+    assert_eq!(settings.port, 10086);
+    assert_eq!(settings.uuid, "b831381d-6324-4d53-ad4f-8cda48b30811");
+    assert_eq!(settings.security, "aes-128-gcm");
+    assert_eq!(settings.alter_id, 4);
+
+    let json_str = json_str.replace("aes-128-gcm", "none");
+    let config = crate::config::json::from_string(&json_str).unwrap();
+    let settings =
+        crate::config::VMessOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.security, "none");
+}
+
+#[test]
+fn test_vless_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "vless",
+                "tag": "vless_out",
+                "settings": {
+                    "address": "127.0.0.1",
+                    "port": 443,
+                    "uuid": "b831381d-6324-4d53-ad4f-8cda48b30811"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::VlessOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.address, "127.0.0.1");
+    assert_eq!(settings.port, 443);
+    assert_eq!(settings.uuid, "b831381d-6324-4d53-ad4f-8cda48b30811");
+    assert!(settings.flow.is_empty());
+}
+
+#[test]
+fn test_wireguard_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "wireguard",
+                "tag": "wg_out",
+                "settings": {
+                    "privateKey": "QN1BO3zC2A31cybs07kMIAFr7BDDxtSb84tMlu34hkU=",
+                    "peerPublicKey": "nxLAAeKAampcQ1y9/wJQU60Mu2OG0EEbQVenR03Xn3U=",
+                    "endpoint": "example.com:51820",
+                    "address": "10.0.0.2/32"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::WireGuardOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(
+        settings.private_key,
+        "QN1BO3zC2A31cybs07kMIAFr7BDDxtSb84tMlu34hkU="
+    );
+    assert_eq!(
+        settings.peer_public_key,
+        "nxLAAeKAampcQ1y9/wJQU60Mu2OG0EEbQVenR03Xn3U="
+    );
+    assert_eq!(settings.endpoint, "example.com:51820");
+    assert_eq!(settings.address, "10.0.0.2/32");
+    assert_eq!(settings.mtu, 0);
+}
+
+#[test]
+fn test_http_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "http",
+                "tag": "http_out",
+                "settings": {
+                    "address": "127.0.0.1",
+                    "port": 8080,
+                    "username": "user",
+                    "password": "pass"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::HttpOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.address, "127.0.0.1");
+    assert_eq!(settings.port, 8080);
+    assert_eq!(settings.username, "user");
+    assert_eq!(settings.password, "pass");
+}
+
+#[test]
+fn test_socks_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "socks",
+                "settings": {
+                    "address": "127.0.0.1",
+                    "port": 1080,
+                    "username": "user",
+                    "password": "pass",
+                    "udpOverTcp": true
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::SocksOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.address, "127.0.0.1");
+    assert_eq!(settings.port, 1080);
+    assert_eq!(settings.username, "user");
+    assert_eq!(settings.password, "pass");
+    assert!(settings.udp_over_tcp);
+}
+
+#[test]
+fn test_obfs_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "obfs",
+                "settings": {
+                    "mode": "http",
+                    "host": "www.example.com",
+                    "path": "/upgrade"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::ObfsOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.mode, "http");
+    assert_eq!(settings.host, "www.example.com");
+    assert_eq!(settings.path, "/upgrade");
+}
+
+#[test]
+fn test_socks_inbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "socks",
+                "address": "127.0.0.1",
+                "port": 1086,
+                "udpSessionTimeout": 60,
+                "settings": {
+                    "username": "user",
+                    "password": "pass"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    assert_eq!(config.inbounds[0].udp_session_timeout, 60);
+    let settings =
+        crate::config::SocksInboundSettings::parse_from_bytes(&config.inbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.username, "user");
+    assert_eq!(settings.password, "pass");
+    assert_eq!(settings.advertised_address, "");
+}
+
+#[test]
+fn test_tproxy_inbound() {
+    let json_str = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "tproxy",
+                "address": "0.0.0.0",
+                "port": 1090
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    assert_eq!(config.inbounds.len(), 1);
+    assert_eq!(config.inbounds[0].protocol, "tproxy");
+    assert_eq!(config.inbounds[0].address, "0.0.0.0");
+    assert_eq!(config.inbounds[0].port, 1090);
+}
+
+#[test]
+fn test_tls_inbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "tls",
+                "address": "127.0.0.1",
+                "port": 443,
+                "settings": {
+                    "certificate": "/etc/flower/cert.pem",
+                    "certificateKey": "/etc/flower/key.pem",
+                    "clientCa": "/etc/flower/ca.pem",
+                    "minVersion": "1.3",
+                    "cipherSuites": ["TLS_AES_128_GCM_SHA256"]
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::TlsInboundSettings::parse_from_bytes(&config.inbounds[0].settings).unwrap();
+    assert_eq!(settings.certificate, "/etc/flower/cert.pem");
+    assert_eq!(settings.client_ca, "/etc/flower/ca.pem");
+    assert_eq!(settings.min_version, "1.3");
+    assert_eq!(settings.max_version, "");
+    assert_eq!(
+        settings.cipher_suites.as_slice(),
+        &["TLS_AES_128_GCM_SHA256".to_string()]
+    );
+
+    let json_str = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "tls",
+                "address": "127.0.0.1",
+                "port": 443,
+                "settings": {
+                    "certificate": "/etc/flower/cert.pem",
+                    "clientCa": ""
+                }
+            }
+        ]
+    }
+    "#;
+    assert!(crate::config::json::from_string(json_str).is_err());
+}
+
+#[test]
+fn test_quic_inbound_pkcs12() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "quic",
+                "address": "127.0.0.1",
+                "port": 443,
+                "settings": {
+                    "certificate": "/etc/flower/cert.p12",
+                    "certificatePassword": "secret"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::QuicInboundSettings::parse_from_bytes(&config.inbounds[0].settings).unwrap();
+    assert_eq!(settings.certificate, "/etc/flower/cert.p12");
+    assert_eq!(settings.certificate_key, "");
+    assert_eq!(settings.certificate_password, "secret");
+}
+
+#[test]
+fn test_quic_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "quic",
+                "settings": {
+                    "address": "example.com",
+                    "port": 443,
+                    "alpn": ["h3"],
+                    "idleTimeout": 3600,
+                    "streamsPerConnection": 16,
+                    "datagram": true,
+                    "zeroRtt": true
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::QuicOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.alpn.as_slice(), &["h3".to_string()]);
+    assert_eq!(settings.idle_timeout, 3600);
+    assert_eq!(settings.streams_per_connection, 16);
+    assert!(settings.datagram);
+    assert!(settings.zero_rtt);
+}
+
+#[test]
+fn test_direct_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "direct"
+            },
+            {
+                "protocol": "direct",
+                "settings": {
+                    "bindAddress": "192.168.1.2",
+                    "bindInterface": "eth1"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    assert!(config.outbounds[0].settings.is_empty());
+    let settings =
+        crate::config::DirectOutboundSettings::parse_from_bytes(&config.outbounds[1].settings)
+            .unwrap();
+    assert_eq!(settings.bind_address, "192.168.1.2");
+    assert_eq!(settings.bind_interface, "eth1");
+}
+
+#[test]
+fn test_outbound_timeouts() {
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "direct"
+            },
+            {
+                "protocol": "direct",
+                "connectTimeout": 10,
+                "handshakeTimeout": 15
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    assert_eq!(config.outbounds[0].connect_timeout, 0);
+    assert_eq!(config.outbounds[0].handshake_timeout, 0);
+    assert_eq!(config.outbounds[1].connect_timeout, 10);
+    assert_eq!(config.outbounds[1].handshake_timeout, 15);
+}
+
+#[test]
+fn test_balance_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "balance",
+                "settings": {
+                    "actors": ["a", "b"],
+                    "strategy": "least-connections",
+                    "failCooldown": 60
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::BalanceOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(
+        settings.actors.as_slice(),
+        &["a".to_string(), "b".to_string()]
+    );
+    assert_eq!(settings.strategy, "least-connections");
+    assert_eq!(settings.fail_cooldown, 60);
+}
+
+#[test]
+fn test_failover_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "failover",
+                "settings": {
+                    "actors": ["a", "b"],
+                    "checkInterval": 60,
+                    "testUrl": "http://www.gstatic.com/generate_204",
+                    "tolerance": 50
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::FailOverOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.check_interval, 60);
+    assert_eq!(settings.test_url, "http://www.gstatic.com/generate_204");
+    assert_eq!(settings.tolerance, 50);
+    assert!(settings.health_check);
+}
+
+#[test]
+fn test_tls_outbound() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "tls",
+                "settings": {
+                    "serverName": "example.com",
+                    "alpn": ["h2"],
+                    "sessionCacheSize": 16,
+                    "insecure": true,
+                    "maxVersion": "1.2"
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::TlsOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.server_name, "example.com");
+    assert_eq!(settings.alpn.as_slice(), &["h2".to_string()]);
+    assert_eq!(settings.session_cache_size, 16);
+    assert!(settings.insecure);
+    assert_eq!(settings.max_version, "1.2");
+    assert!(settings.cipher_suites.is_empty());
+}
+
+#[test]
+fn test_grpc() {
+    use protobuf::Message;
+
+    let json_str = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "grpc",
+                "address": "127.0.0.1",
+                "port": 8443,
+                "settings": {
+                    "serviceName": "flower"
+                }
+            }
+        ],
+        "outbounds": [
+            {
+                "protocol": "grpc",
+                "tag": "grpc_out",
+                "settings": {
+                    "serviceName": "flower",
+                    "host": "example.com"
+                }
+            },
+            {
+                "protocol": "grpc",
+                "tag": "grpc_default"
+            },
+            {
+                "protocol": "grpc",
+                "tag": "grpc_pool",
+                "settings": {
+                    "address": "example.com",
+                    "port": 443,
+                    "actors": ["tls"]
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let settings =
+        crate::config::GrpcInboundSettings::parse_from_bytes(&config.inbounds[0].settings).unwrap();
+    assert_eq!(settings.service_name, "flower");
+    let settings =
+        crate::config::GrpcOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.service_name, "flower");
+    assert_eq!(settings.host, "example.com");
+    let settings =
+        crate::config::GrpcOutboundSettings::parse_from_bytes(&config.outbounds[1].settings)
+            .unwrap();
+    assert_eq!(settings.service_name, "");
+    assert!(settings.address.is_empty());
+    let settings =
+        crate::config::GrpcOutboundSettings::parse_from_bytes(&config.outbounds[2].settings)
+            .unwrap();
+    assert_eq!(settings.address, "example.com");
+    assert_eq!(settings.port, 443);
+    assert_eq!(settings.actors.to_vec(), vec!["tls".to_string()]);
+}
+
+#[test]
+fn test_geoip_database() {
+    let json_str = r#"
+    {
+        "router": {
+            "geoipDatabase": "/var/lib/flower/country.mmdb",
+            "rules": [
+                {
+                    "geoip": ["cn"],
+                    "sourceGeoip": ["us"],
+                    "target": "direct"
+                }
+            ]
+        }
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let rule = &config.get_router().rules[0];
+    assert_eq!(rule.mmdbs[0].file, "/var/lib/flower/country.mmdb");
+    assert_eq!(rule.mmdbs[0].country_code, "cn");
+    assert_eq!(rule.source_mmdbs[0].file, "/var/lib/flower/country.mmdb");
+
+    let json_str = r#"{"router": {"rules": [{"geoip": ["cn"], "target": "direct"}]}}"#;
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let file = std::path::Path::new(&*crate::option::ASSET_LOCATION).join("geo.mmdb");
+    assert_eq!(
+        config.get_router().rules[0].mmdbs[0].file,
+        file.to_string_lossy()
+    );
+}
+
+#[test]
+fn test_ip_cidr() {
+    let json_str = r#"
+    {
+        "router": {
+            "rules": [
+                {
+                    "ip": ["8.8.8.8"],
+                    "ipCidr": ["10.0.0.0/8", "2001:db8::/32"],
+                    "resolve": true,
+                    "target": "direct"
+                },
+                {
+                    "ipCidr": ["192.168.0.0/16"],
+                    "target": "direct"
+                }
+            ]
+        }
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let rules = &config.get_router().rules;
+    assert_eq!(
+        rules[0].ip_cidrs.to_vec(),
+        vec!["8.8.8.8", "10.0.0.0/8", "2001:db8::/32"]
+    );
+    assert!(rules[0].resolve);
+    assert!(!rules[1].resolve);
+}
+
+#[test]
+fn test_route_only() {
+    let json_str = r#"
+    {
+        "router": {
+            "routeOnly": true
+        }
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    assert!(config.get_router().route_only);
+    let config = crate::config::json::from_string(r#"{"router": {}}"#).unwrap();
+    assert!(!config.get_router().route_only);
+}
+
+#[test]
+fn test_log() {
+    let json_str = r#"
+    {
+        "log": {
+            "level": "info",
+            "levels": {
+                "flower::proxy": "debug",
+                "rustls": "off"
+            },
+            "format": "json"
+        }
+    }
+    "#;
+
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let log = config.get_log();
+    assert_eq!(log.level, crate::config::Log_Level::INFO);
+    assert_eq!(
+        log.target_levels.to_vec(),
+        vec!["flower::proxy=debug", "rustls=off"]
+    );
+    assert_eq!(log.format, crate::config::Log_Format::JSON);
+
+    let json_str = r#"{"log": {"format": "xml"}}"#;
+    assert!(crate::config::json::from_string(json_str).is_err());
+
+    let json_str = r#"
+    {
+        "log": {
+            "output": "file",
+            "path": "/var/log/flower.log",
+            "maxSizeMb": 10,
+            "maxFiles": 3
+        }
+    }
+    "#;
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let log = config.get_log();
+    assert_eq!(log.output, crate::config::Log_Output::FILE);
+    assert_eq!(log.output_file, "/var/log/flower.log");
+    assert_eq!(log.max_size_mb, 10);
+    assert_eq!(log.max_files, 3);
+
+    let json_str = r#"{"log": {"output": "file"}}"#;
+    assert!(crate::config::json::from_string(json_str).is_err());
+}
+
+#[test]
+fn test_geosite() {
+    let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let rules = |domains: &str| {
+        format!(
+            r#"
+            {{
+                "router": {{
+                    "rules": [
+                        {{
+                            "domain": [{}],
+                            "target": "proxy"
+                        }}
+                    ]
+                }}
+            }}
+            "#,
+            domains
+        )
+    };
+    let domains = |config: &crate::config::Config| {
+        config.get_router().rules[0]
+            .domains
+            .iter()
+            .map(|x| (x.field_type, x.value.clone()))
+            .collect::<Vec<_>>()
+    };
+    use crate::config::Router_Rule_Domain_Type::*;
+
+    let json_str = rules(&format!(
+        r#""geosite:{0}/geosite.dat:google", "geosite:{0}/geosite.dat:cn", "example.com""#,
+        data.display()
+    ));
+    let config = crate::config::json::from_string(&json_str).unwrap();
+    assert_eq!(
+        domains(&config),
+        vec![
+            (DOMAIN, "google.com".to_string()),
+            (FULL, "www.gstatic.com".to_string()),
+            (PLAIN, "googleapis".to_string()),
+            (DOMAIN, "cn".to_string()),
+            (REGEX, r"^baidu\.(com|cn)$".to_string()),
+            (FULL, "example.com".to_string()),
+        ]
+    );
+
+    let json_str = rules(&format!(r#""geosite:{}/geosite:google""#, data.display()));
+    let config = crate::config::json::from_string(&json_str).unwrap();
+    assert_eq!(
+        domains(&config),
+        vec![
+            (DOMAIN, "doubleclick.net".to_string()),
+            (REGEX, r"^ads\d*\.google\.com$".to_string()),
+            (DOMAIN, "google.com".to_string()),
+            (FULL, "www.gstatic.com".to_string()),
+            (PLAIN, "googleapis".to_string()),
+        ]
+    );
+
+    // unknown categories fail the config
+    for dir in ["geosite.dat", "geosite"] {
+        let json_str = rules(&format!(r#""geosite:{}/{}:unknown""#, data.display(), dir));
+        let err = crate::config::json::from_string(&json_str).unwrap_err();
+        assert!(err.to_string().contains("[unknown] not found"));
+    }
+}
+
+#[test]
+fn test_port_network_rule() {
+    let json_str = r#"
+    {
+        "router": {
+            "rules": [
+                {
+                    "network": ["udp"],
+                    "port": [443, "8000-8080"],
+                    "target": "direct"
+                }
+            ]
+        }
+    }
+    "#;
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let rule = &config.get_router().rules[0];
+    assert_eq!(rule.networks.to_vec(), vec!["udp"]);
+    assert_eq!(rule.port_ranges.to_vec(), vec!["443", "8000-8080"]);
+}
+
+#[test]
+fn test_source_rule() {
+    let json_str = r#"
+    {
+        "router": {
+            "rules": [
+                {
+                    "sourceIpCidr": ["192.168.0.0/16"],
+                    "target": "direct"
+                },
+                {
+                    "inboundTag": ["wg"],
+                    "target": "proxy"
+                }
+            ]
+        }
+    }
+    "#;
+    let config = crate::config::json::from_string(json_str).unwrap();
+    let rules = &config.get_router().rules;
+    assert_eq!(rules[0].source_ip_cidrs.to_vec(), vec!["192.168.0.0/16"]);
+    assert_eq!(rules[1].inbound_tags.to_vec(), vec!["wg"]);
+}
+
+#[test]
+fn test_dns_ip_strategy() {
+    let json_str = r#"{"dns": {"ipStrategy": "prefer_ipv6"}}"#;
+    let config = crate::config::json::from_string(json_str).unwrap();
+    assert_eq!(
+        config.get_dns().ip_strategy,
+        crate::config::Dns_IpStrategy::PREFER_IPV6
+    );
+
+    let json_str = r#"{"dns": {"ipStrategy": "ipv6_first"}}"#;
+    assert!(crate::config::json::from_string(json_str).is_err());
+}
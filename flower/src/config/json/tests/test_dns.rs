@@ -32,3 +32,61 @@ fn test_dns_hosts() {
         &ips
     );
 }
+
+#[test]
+fn test_dns_doh_servers() {
+    let json_str = r#"
+    {
+        "dns": {
+            "servers": ["1.1.1.1"],
+            "dohServers": ["https://cloudflare-dns.com/dns-query"]
+        }
+    }
+    "#;
+    let mut config = crate::config::json::json_from_string(json_str).unwrap();
+    let config = crate::config::json::to_internal(&mut config).unwrap();
+    let dns = config.dns.unwrap();
+    assert_eq!(dns.servers.as_slice(), &["1.1.1.1".to_string()]);
+    assert_eq!(
+        dns.doh_servers.as_slice(),
+        &["https://cloudflare-dns.com/dns-query".to_string()]
+    );
+}
+
+#[test]
+fn test_dns_cache() {
+    let json_str = r#"
+    {
+        "dns": {
+            "cacheSize": 1024,
+            "disableCache": true
+        }
+    }
+    "#;
+    let mut config = crate::config::json::json_from_string(json_str).unwrap();
+    let config = crate::config::json::to_internal(&mut config).unwrap();
+    let dns = config.dns.unwrap();
+    assert_eq!(dns.cache_size, 1024);
+    assert!(dns.disable_cache);
+}
+
+#[test]
+fn test_dns_fake_dns() {
+    let json_str = r#"
+    {
+        "dns": {
+            "fakeDns": {
+                "range": "198.18.0.0/15",
+                "exclude": ["apple.com"]
+            }
+        }
+    }
+    "#;
+    let mut config = crate::config::json::json_from_string(json_str).unwrap();
+    let config = crate::config::json::to_internal(&mut config).unwrap();
+    let dns = config.dns.unwrap();
+    let fake_dns = dns.fake_dns.as_ref().unwrap();
+    assert_eq!(fake_dns.range, "198.18.0.0/15");
+    assert!(fake_dns.include.is_empty());
+    assert_eq!(fake_dns.exclude.as_slice(), &["apple.com".to_string()]);
+}
@@ -3,6 +3,7 @@ use std::path::Path;
 use anyhow::anyhow;
 use anyhow::Result;
 
+pub mod diff;
 pub mod external_rule;
 pub mod geosite;
 pub mod internal;
@@ -10,9 +11,15 @@ pub mod internal;
 #[cfg(feature = "config-json")]
 pub mod json;
 
+#[cfg(feature = "config-yaml")]
+pub mod yaml;
+
 #[cfg(feature = "config-conf")]
 pub mod conf;
 
+#[cfg(feature = "config-clash")]
+pub mod clash;
+
 pub use internal::*;
 
 pub fn from_string(s: &str) -> Result<internal::Config> {
@@ -22,6 +29,18 @@ pub fn from_string(s: &str) -> Result<internal::Config> {
             return Ok(c);
         }
     }
+    #[cfg(feature = "config-clash")]
+    {
+        if clash::is_clash_config(s) {
+            return clash::from_string(s);
+        }
+    }
+    #[cfg(feature = "config-yaml")]
+    {
+        if let Ok(c) = yaml::from_string(s) {
+            return Ok(c);
+        }
+    }
     #[cfg(feature = "config-conf")]
     {
         return conf::from_string(s);
@@ -31,16 +50,39 @@ pub fn from_string(s: &str) -> Result<internal::Config> {
 }
 
 pub fn from_file(path: &str) -> Result<internal::Config> {
+    let config = std::fs::read_to_string(path)?;
+    from_file_string(path, &config)
+}
+
+/// Parses the content of a config file, in the format told by the extension
+/// of its path as `from_file` does.
+pub fn from_file_string(path: &str, s: &str) -> Result<internal::Config> {
     if let Some(ext) = Path::new(path).extension() {
         if let Some(ext) = ext.to_str() {
             match ext {
                 #[cfg(feature = "config-json")]
-                "json" => return json::from_file(path),
+                "json" => return json::from_string(s),
+                #[cfg(feature = "config-yaml")]
+                "yaml" | "yml" => {
+                    #[cfg(feature = "config-clash")]
+                    {
+                        if clash::is_clash_config(s) {
+                            return clash::from_string(s);
+                        }
+                    }
+                    return yaml::from_string(s);
+                }
                 #[cfg(feature = "config-conf")]
-                "conf" => return conf::from_file(path),
+                "conf" => return conf::from_string(s),
                 _ => (),
             }
         }
     }
-    Err(anyhow!("config files use extension .json or .conf"))
+    // Defaults to JSON for other extensions.
+    #[cfg(feature = "config-json")]
+    {
+        return json::from_string(s);
+    }
+    #[allow(unreachable_code)]
+    Err(anyhow!("config files use extension .json, .yaml or .conf"))
 }
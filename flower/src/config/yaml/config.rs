@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::config::{internal, json};
+
+pub use json::Config;
+
+/// Parses a YAML config, which has the same structure as a JSON config.
+pub fn yaml_from_string(config: &str) -> Result<Config> {
+    // The settings are kept as raw JSON until the protocol is known, convert
+    // the whole document to JSON for that.
+    let value: serde_json::Value = serde_yaml::from_str(config)
+        .map_err(|e| anyhow!("deserialize yaml config failed: {}", e))?;
+    json::json_from_string(&value.to_string())
+}
+
+pub fn to_internal(config: &mut Config) -> Result<internal::Config> {
+    json::to_internal(config)
+}
+
+pub fn from_string(s: &str) -> Result<internal::Config> {
+    let mut config = yaml_from_string(s)?;
+    to_internal(&mut config)
+}
+
+pub fn from_file<P>(path: P) -> Result<internal::Config>
+where
+    P: AsRef<Path>,
+{
+    let config = std::fs::read_to_string(path)?;
+    let mut config = yaml_from_string(&config)?;
+    to_internal(&mut config)
+}
@@ -0,0 +1,58 @@
+use protobuf::Message;
+
+#[test]
+fn test_config() {
+    let yaml_str = r#"
+log:
+  level: debug
+dns:
+  servers:
+    - 8.8.8.8
+inbounds:
+  - tag: socks_in
+    protocol: socks
+    address: 127.0.0.1
+    port: 1086
+    settings:
+      username: user
+      password: pass
+outbounds:
+  - tag: ws_out
+    protocol: ws
+    settings:
+      path: /ws
+      headers:
+        X-Token: abc
+  - tag: direct
+    protocol: direct
+router:
+  rules:
+    - domain:
+        - example.com
+      target: direct
+"#;
+
+    let config = crate::config::yaml::from_string(yaml_str).unwrap();
+    assert_eq!(config.get_log().level, crate::config::Log_Level::DEBUG);
+    assert_eq!(config.get_dns().servers[0], "8.8.8.8");
+    assert_eq!(config.inbounds[0].tag, "socks_in");
+    assert_eq!(config.inbounds[0].port, 1086);
+    let settings =
+        crate::config::SocksInboundSettings::parse_from_bytes(&config.inbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.username, "user");
+    assert_eq!(config.outbounds.len(), 2);
+    let settings =
+        crate::config::WebSocketOutboundSettings::parse_from_bytes(&config.outbounds[0].settings)
+            .unwrap();
+    assert_eq!(settings.path, "/ws");
+    assert_eq!(settings.headers["X-Token"], "abc");
+    assert_eq!(config.get_router().rules[0].target_tag, "direct");
+    assert_eq!(config.get_router().rules[0].domains[0].value, "example.com");
+}
+
+#[test]
+fn test_invalid_config() {
+    assert!(crate::config::yaml::from_string("inbounds: [").is_err());
+    assert!(crate::config::yaml::from_string("inbounds: 1").is_err());
+}
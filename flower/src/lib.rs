@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 use std::io;
+use std::net::SocketAddr;
 use std::sync::mpsc::sync_channel;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::sync::Once;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
 use lazy_static::lazy_static;
@@ -18,8 +19,13 @@ use notify::{
 };
 
 use app::{
-    dispatcher::Dispatcher, dns_client::DnsClient, inbound::manager::InboundManager,
-    nat_manager::NatManager, outbound::manager::OutboundManager, router::Router,
+    conn_manager::ConnManager,
+    dispatcher::Dispatcher,
+    dns_client::DnsClient,
+    inbound::manager::InboundManager,
+    nat_manager::{self, NatManager},
+    outbound::{health::HealthStats, manager::OutboundManager},
+    router::Router,
 };
 
 #[cfg(feature = "api")]
@@ -43,6 +49,8 @@ mod sys;
 pub enum Error {
     #[error(transparent)]
     Config(#[from] anyhow::Error),
+    #[error("config file {0} not readable: {1}")]
+    ConfigPath(String, #[source] io::Error),
     #[error("no associated config file")]
     NoConfigFile,
     #[error(transparent)]
@@ -62,17 +70,66 @@ pub enum Error {
 
 pub type Runner = futures::future::BoxFuture<'static, ()>;
 
+/// The outcome of a graceful shutdown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GracefulShutdown {
+    /// Sessions finished within the timeout.
+    pub finished: usize,
+    /// Sessions still running at the timeout and closed forcibly.
+    pub aborted: usize,
+}
+
+type DrainRequest = (Duration, std::sync::mpsc::SyncSender<GracefulShutdown>);
+
+/// Where the config of a runtime comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// The path of the config file.
+    File(String),
+    Str,
+    Internal,
+}
+
+/// The status of a running runtime, see [`runtimes`].
+#[derive(Debug, Clone)]
+pub struct RuntimeStatus {
+    pub id: RuntimeId,
+    pub config_source: ConfigSource,
+    /// Time elapsed since the runtime was started.
+    pub uptime: Duration,
+    /// Bound addresses of the network inbounds, keyed by tag.
+    pub inbound_addrs: HashMap<String, SocketAddr>,
+    /// Number of the sessions being relayed.
+    pub active_sessions: usize,
+}
+
 pub struct RuntimeManager {
     #[cfg(feature = "auto-reload")]
     rt_id: RuntimeId,
+    config_source: ConfigSource,
     config_path: Option<String>,
+    started_at: Instant,
     #[cfg(feature = "auto-reload")]
     auto_reload: bool,
     reload_tx: mpsc::Sender<std::sync::mpsc::SyncSender<Result<(), Error>>>,
     shutdown_tx: mpsc::Sender<()>,
+    drain_tx: mpsc::Sender<DrainRequest>,
     router: Arc<RwLock<Router>>,
     dns_client: Arc<RwLock<DnsClient>>,
     outbound_manager: Arc<RwLock<OutboundManager>>,
+    conn_manager: Arc<ConnManager>,
+    inbound_manager: Arc<RwLock<InboundManager>>,
+    inbound_addrs: Mutex<HashMap<String, SocketAddr>>,
+    #[cfg(feature = "api")]
+    api_addr: Mutex<Option<SocketAddr>>,
+    // The config last loaded, as it was before building the runtime.
+    config: Mutex<config::Config>,
+    // The text the config last loaded is parsed from, none for internal
+    // configs.
+    config_text: Mutex<Option<String>>,
+    // Held by the reloads, so that they're staged against the config they
+    // replace.
+    reload_lock: tokio::sync::Mutex<()>,
     #[cfg(feature = "auto-reload")]
     watcher: Mutex<Option<RecommendedWatcher>>,
 }
@@ -81,25 +138,45 @@ impl RuntimeManager {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         #[cfg(feature = "auto-reload")] rt_id: RuntimeId,
-        config_path: Option<String>,
+        config_source: ConfigSource,
         #[cfg(feature = "auto-reload")] auto_reload: bool,
         reload_tx: mpsc::Sender<std::sync::mpsc::SyncSender<Result<(), Error>>>,
         shutdown_tx: mpsc::Sender<()>,
+        drain_tx: mpsc::Sender<DrainRequest>,
         router: Arc<RwLock<Router>>,
         dns_client: Arc<RwLock<DnsClient>>,
         outbound_manager: Arc<RwLock<OutboundManager>>,
+        conn_manager: Arc<ConnManager>,
+        inbound_manager: Arc<RwLock<InboundManager>>,
+        inbound_addrs: HashMap<String, SocketAddr>,
+        config: config::Config,
+        config_text: Option<String>,
     ) -> Arc<Self> {
         Arc::new(Self {
             #[cfg(feature = "auto-reload")]
             rt_id,
-            config_path,
+            config_path: match &config_source {
+                ConfigSource::File(p) => Some(p.to_owned()),
+                _ => None,
+            },
+            config_source,
+            started_at: Instant::now(),
             #[cfg(feature = "auto-reload")]
             auto_reload,
             reload_tx,
             shutdown_tx,
+            drain_tx,
             router,
             dns_client,
             outbound_manager,
+            conn_manager,
+            inbound_manager,
+            inbound_addrs: Mutex::new(inbound_addrs),
+            #[cfg(feature = "api")]
+            api_addr: Mutex::new(None),
+            config: Mutex::new(config),
+            config_text: Mutex::new(config_text),
+            reload_lock: tokio::sync::Mutex::new(()),
             #[cfg(feature = "auto-reload")]
             watcher: Mutex::new(None),
         })
@@ -128,8 +205,10 @@ impl RuntimeManager {
 
     // This function could block by an in-progress connection dialing.
     //
-    // TODO Reload FakeDns. And perhaps the inbounds as long as the listening
-    // addresses haven't changed.
+    // Only the parts changed since the last load are rebuilt, the sessions
+    // going through the others are kept.
+    //
+    // TODO Reload FakeDns.
     pub async fn reload(&self) -> Result<(), Error> {
         let config_path = if let Some(p) = self.config_path.as_ref() {
             p
@@ -137,18 +216,170 @@ impl RuntimeManager {
             return Err(Error::NoConfigFile);
         };
         log::info!("reloading from config file: {}", config_path);
-        let mut config = config::from_file(config_path).map_err(Error::Config)?;
-        self.router.write().await.reload(&mut config.router)?;
-        self.dns_client.write().await.reload(&config.dns)?;
-        self.outbound_manager
-            .write()
-            .await
-            .reload(&config.outbounds, self.dns_client.clone())
-            .await?;
+        if let Err(e) = self.stage_and_swap(config_path).await {
+            log::error!(
+                "reload from config file {} failed, keep running the current config: {}",
+                config_path,
+                e
+            );
+            return Err(e);
+        }
         log::info!("reloaded from config file: {}", config_path);
         Ok(())
     }
 
+    // Builds everything from the new config before swapping any of it in, so
+    // an invalid config leaves the runtime untouched.
+    async fn stage_and_swap(&self, config_path: &str) -> Result<(), Error> {
+        let _guard = self.reload_lock.lock().await;
+        let config_text =
+            std::fs::read_to_string(config_path).map_err(|e| Error::Config(e.into()))?;
+        let config = config::from_file_string(config_path, &config_text).map_err(Error::Config)?;
+        let last = self.config.lock().unwrap().clone();
+        let router = if config.router != last.router {
+            let router = self
+                .router
+                .read()
+                .await
+                .rebuild(&mut config.router.clone())
+                .map_err(Error::Config)?;
+            Some(router)
+        } else {
+            None
+        };
+        let outbound_manager = self
+            .outbound_manager
+            .read()
+            .await
+            .stage(&config.outbounds, self.dns_client.clone())
+            .await
+            .map_err(Error::Config)?;
+        let inbound_manager = self
+            .inbound_manager
+            .read()
+            .await
+            .stage(&config.inbounds)
+            .map_err(Error::Config)?;
+        let dns_client = if config.dns != last.dns {
+            let dns_client = self
+                .dns_client
+                .read()
+                .await
+                .stage(&config.dns)
+                .map_err(Error::Config)?;
+            Some(dns_client)
+        } else {
+            None
+        };
+
+        // The inbounds go first, they roll back on their own if any of them
+        // fails to bind.
+        let mut current = self.inbound_manager.write().await;
+        let res = current.swap(inbound_manager).await;
+        *self.inbound_addrs.lock().unwrap() = current.bound_addrs().clone();
+        drop(current);
+        let inbounds_diff = res.map_err(Error::Config)?;
+
+        if let Some(dns_client) = dns_client {
+            *self.dns_client.write().await = dns_client;
+            log::info!("dns: updated");
+        }
+        if let Some(router) = router {
+            *self.router.write().await = router;
+            log::info!("router: updated");
+        }
+        let diff = self.outbound_manager.write().await.swap(outbound_manager);
+        log::info!("outbounds: {}", diff);
+        log::info!("inbounds: {}", inbounds_diff);
+        *self.config.lock().unwrap() = config;
+        *self.config_text.lock().unwrap() = Some(config_text);
+        Ok(())
+    }
+
+    /// Reloads the routing databases without touching the rest of the
+    /// runtime. Mmdb files are swapped in place. Geosite lists are expanded
+    /// into domain rules when the config is parsed, so the applied config is
+    /// parsed again and the rules rebuilt off the lock, then swapped in, a
+    /// config file changed since is left to the next reload. The rules are
+    /// left alone if any mmdb file fails to reload.
+    pub async fn reload_assets(&self) -> Result<(), Error> {
+        let _guard = self.reload_lock.lock().await;
+        self.router
+            .read()
+            .await
+            .reload_mmdbs()
+            .map_err(Error::Config)?;
+        let config_text = self.config_text.lock().unwrap().clone();
+        let router_config = match config_text {
+            Some(text) => {
+                let config = match self.config_path.as_ref() {
+                    Some(p) => config::from_file_string(p, &text),
+                    None => config::from_string(&text),
+                };
+                config.map_err(Error::Config)?.router
+            }
+            None => self.config.lock().unwrap().router.clone(),
+        };
+        let router = self
+            .router
+            .read()
+            .await
+            .rebuild(&mut router_config.clone())
+            .map_err(Error::Config)?;
+        *self.router.write().await = router;
+        self.config.lock().unwrap().router = router_config;
+        log::info!("reloaded routing assets");
+        Ok(())
+    }
+
+    /// Bound addresses of the network inbounds, keyed by tag.
+    pub fn inbound_addrs(&self) -> HashMap<String, SocketAddr> {
+        self.inbound_addrs.lock().unwrap().clone()
+    }
+
+    /// Bound address of the api server, if it's enabled.
+    #[cfg(feature = "api")]
+    pub fn api_addr(&self) -> Option<SocketAddr> {
+        *self.api_addr.lock().unwrap()
+    }
+
+    pub fn config_source(&self) -> &ConfigSource {
+        &self.config_source
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn conn_manager(&self) -> &Arc<ConnManager> {
+        &self.conn_manager
+    }
+
+    /// Traffic quota usage of the outbounds, as (used, limit) in bytes.
+    pub async fn quota_usage(&self) -> HashMap<String, (u64, u64)> {
+        self.outbound_manager
+            .read()
+            .await
+            .quotas()
+            .map(|(tag, quota)| (tag.to_owned(), (quota.used(), quota.limit())))
+            .collect()
+    }
+
+    /// Health check results of the outbound groups, keyed by tag.
+    pub async fn health_stats(&self) -> HashMap<String, Arc<HealthStats>> {
+        self.outbound_manager
+            .read()
+            .await
+            .health_stats()
+            .map(|(tag, stats)| (tag.to_owned(), stats.clone()))
+            .collect()
+    }
+
+    pub async fn flush_dns_cache(&self) {
+        self.dns_client.read().await.flush_cache().await;
+        log::info!("flushed dns cache");
+    }
+
     pub fn blocking_reload(&self) -> Result<(), Error> {
         let tx = self.reload_tx.clone();
         let (res_tx, res_rx) = sync_channel(0);
@@ -179,14 +410,27 @@ impl RuntimeManager {
         true
     }
 
+    /// Stops accepting new connections on the network inbounds, waits up to
+    /// `timeout` for the running sessions to finish, closes the rest and then
+    /// shuts down. The TUN inbound keeps accepting until the sessions are
+    /// closed.
+    pub fn blocking_shutdown_graceful(&self, timeout: Duration) -> Result<GracefulShutdown, Error> {
+        let (res_tx, res_rx) = sync_channel(1);
+        if let Err(e) = self.drain_tx.blocking_send((timeout, res_tx)) {
+            log::warn!("sending drain signal failed: {}", e);
+            return Err(Error::RuntimeManager);
+        }
+        res_rx.recv().map_err(Error::SyncChannelRecv)
+    }
+
     #[cfg(feature = "auto-reload")]
     pub(crate) fn new_watcher(&self) -> Result<(), Error> {
-        let config_path = if let Some(p) = self.config_path.as_ref() {
-            p
-        } else {
-            return Err(Error::NoConfigFile);
-        };
         if self.auto_reload {
+            let config_path = if let Some(p) = self.config_path.as_ref() {
+                p
+            } else {
+                return Err(Error::NoConfigFile);
+            };
             log::trace!("starting new watcher for config file: {}", config_path);
             let rt_id = self.rt_id;
             let mut watcher: RecommendedWatcher =
@@ -263,6 +507,7 @@ pub type RuntimeId = u16;
 lazy_static! {
     pub static ref RUNTIME_MANAGER: Mutex<HashMap<RuntimeId, Arc<RuntimeManager>>> =
         Mutex::new(HashMap::new());
+    static ref LOGGER_READY: Mutex<bool> = Mutex::new(false);
 }
 
 pub fn reload(key: RuntimeId) -> Result<(), Error> {
@@ -283,31 +528,96 @@ pub fn shutdown(key: RuntimeId) -> bool {
     false
 }
 
+/// Shuts down a runtime after draining its sessions, see
+/// [`RuntimeManager::blocking_shutdown_graceful`]. Must not be called from
+/// within the runtime.
+pub fn shutdown_graceful(key: RuntimeId, timeout: Duration) -> Result<GracefulShutdown, Error> {
+    let m = RUNTIME_MANAGER
+        .lock()
+        .map_err(|_| Error::RuntimeManager)?
+        .get(&key)
+        .cloned()
+        .ok_or(Error::RuntimeManager)?;
+    m.blocking_shutdown_graceful(timeout)
+}
+
 pub fn is_running(key: RuntimeId) -> bool {
     RUNTIME_MANAGER.lock().unwrap().contains_key(&key)
 }
 
+/// Returns the IDs of the running runtimes in ascending order.
+pub fn runtime_ids() -> Vec<RuntimeId> {
+    let mut ids: Vec<RuntimeId> = RUNTIME_MANAGER.lock().unwrap().keys().copied().collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Returns the status of the running runtimes in ascending order of the IDs.
+pub fn runtimes() -> Vec<RuntimeStatus> {
+    let mut runtimes: Vec<RuntimeStatus> = RUNTIME_MANAGER
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, m)| RuntimeStatus {
+            id: *id,
+            config_source: m.config_source().clone(),
+            uptime: m.uptime(),
+            inbound_addrs: m.inbound_addrs(),
+            active_sessions: m.conn_manager().connections().len(),
+        })
+        .collect();
+    runtimes.sort_unstable_by_key(|x| x.id);
+    runtimes
+}
+
+/// Returns the bound addresses of the network inbounds, keyed by tag. The
+/// inbounds are bound once the runtime is running, inbounds configured with
+/// port 0 report the port picked.
+pub fn inbound_addrs(key: RuntimeId) -> Option<HashMap<String, SocketAddr>> {
+    RUNTIME_MANAGER
+        .lock()
+        .unwrap()
+        .get(&key)
+        .map(|m| m.inbound_addrs())
+}
+
+/// Returns the bound address of the api server, the api configured with port
+/// 0 reports the port picked.
+#[cfg(feature = "api")]
+pub fn api_addr(key: RuntimeId) -> Option<SocketAddr> {
+    RUNTIME_MANAGER
+        .lock()
+        .unwrap()
+        .get(&key)
+        .and_then(|m| m.api_addr())
+}
+
 pub fn test_config(config_path: &str) -> Result<(), Error> {
-    config::from_file(config_path)
-        .map(|_| ())
-        .map_err(Error::Config)
+    validate(Config::File(config_path.to_string()))
 }
 
-fn new_runtime(opt: &RuntimeOption) -> Result<tokio::runtime::Runtime, Error> {
+fn new_runtime(rt_id: RuntimeId, opt: &RuntimeOption) -> Result<tokio::runtime::Runtime, Error> {
     match opt {
         RuntimeOption::SingleThread => tokio::runtime::Builder::new_current_thread()
+            .on_thread_start(move || app::logger::set_runtime_id(rt_id))
             .enable_all()
             .build()
             .map_err(Error::Io),
         RuntimeOption::MultiThreadAuto(stack_size) => tokio::runtime::Builder::new_multi_thread()
             .thread_stack_size(*stack_size)
+            .on_thread_start(move || app::logger::set_runtime_id(rt_id))
             .enable_all()
             .build()
             .map_err(Error::Io),
         RuntimeOption::MultiThread(worker_threads, stack_size) => {
-            tokio::runtime::Builder::new_multi_thread()
-                .worker_threads(*worker_threads)
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            // Tokio panics on 0 worker threads, it's taken as the default instead.
+            if *worker_threads > 0 {
+                builder.worker_threads(*worker_threads);
+            }
+            builder
                 .thread_stack_size(*stack_size)
+                .on_thread_start(move || app::logger::set_runtime_id(rt_id))
                 .enable_all()
                 .build()
                 .map_err(Error::Io)
@@ -319,9 +629,11 @@ fn new_runtime(opt: &RuntimeOption) -> Result<tokio::runtime::Runtime, Error> {
 pub enum RuntimeOption {
     // Single-threaded runtime.
     SingleThread,
-    // Multi-threaded runtime with thread stack size.
+    // Multi-threaded runtime with thread stack size, the number of worker
+    // threads is the number of CPUs.
     MultiThreadAuto(usize),
-    // Multi-threaded runtime with the number of worker threads and thread stack size.
+    // Multi-threaded runtime with the number of worker threads and thread stack size,
+    // 0 worker threads means the number of CPUs.
     MultiThread(usize, usize),
 }
 
@@ -343,22 +655,173 @@ pub struct StartOptions {
     pub runtime_opt: RuntimeOption,
 }
 
+impl StartOptions {
+    /// Checks the config file is readable, configs given in other forms are
+    /// validated on parsing.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Config::File(p) = &self.config {
+            std::fs::File::open(p).map_err(|e| Error::ConfigPath(p.to_owned(), e))?;
+        }
+        Ok(())
+    }
+}
+
+// Also returns the text the config is parsed from, none for internal configs.
+fn load_config(config: Config) -> Result<(config::Config, Option<String>), Error> {
+    match config {
+        Config::File(p) => {
+            let text = std::fs::read_to_string(&p).map_err(|e| Error::Config(e.into()))?;
+            let config = config::from_file_string(&p, &text).map_err(Error::Config)?;
+            Ok((config, Some(text)))
+        }
+        Config::Str(s) => {
+            let config = config::from_string(&s).map_err(Error::Config)?;
+            Ok((config, Some(s)))
+        }
+        Config::Internal(c) => Ok((c, None)),
+    }
+}
+
+// The address and the secret of the API server, none if it's disabled.
+#[cfg(feature = "api")]
+fn api_settings(config: &config::Config) -> Result<Option<(SocketAddr, Option<String>)>, Error> {
+    use std::net::IpAddr;
+    let listen_addr = if !(&*option::API_LISTEN).is_empty() {
+        Some(
+            (&*option::API_LISTEN)
+                .parse::<SocketAddr>()
+                .map_err(|e| Error::Config(anyhow!("parse SocketAddr failed: {}", e)))?,
+        )
+    } else if let Some(api) = config.api.as_ref() {
+        Some(SocketAddr::new(
+            api.address
+                .parse::<IpAddr>()
+                .map_err(|e| Error::Config(anyhow!("parse IpAddr failed: {}", e)))?,
+            api.port as u16,
+        ))
+    } else {
+        None
+    };
+    let secret = config
+        .api
+        .as_ref()
+        .map(|api| api.secret.clone())
+        .filter(|s| !s.is_empty());
+    let listen_addr = match listen_addr {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    // The API controls the whole runtime, anyone able to reach it must
+    // authenticate.
+    if secret.is_none() && !listen_addr.ip().is_loopback() {
+        return Err(Error::Config(anyhow!(
+            "api server {} requires a secret on a non-loopback address",
+            &listen_addr
+        )));
+    }
+    Ok(Some((listen_addr, secret)))
+}
+
+// The parts of a runtime built from a config, nothing is listening yet.
+struct Components {
+    dns_client: Arc<RwLock<DnsClient>>,
+    outbound_manager: Arc<RwLock<OutboundManager>>,
+    router: Arc<RwLock<Router>>,
+    conn_manager: Arc<ConnManager>,
+    inbound_manager: InboundManager,
+}
+
+// Must be called within a runtime, as some handlers spawn tasks on creation.
+fn build_components(config: &mut config::Config) -> Result<Components, Error> {
+    let dns_client = Arc::new(RwLock::new(
+        DnsClient::new(&config.dns).map_err(Error::Config)?,
+    ));
+    let outbound_manager = Arc::new(RwLock::new(
+        OutboundManager::new(&config.outbounds, dns_client.clone()).map_err(Error::Config)?,
+    ));
+    dns_client
+        .try_write()
+        .map_err(|e| Error::Config(e.into()))?
+        .set_outbound_manager(Arc::downgrade(&outbound_manager));
+    let router = Arc::new(RwLock::new(
+        Router::new(&mut config.router, dns_client.clone()).map_err(Error::Config)?,
+    ));
+    let conn_manager = Arc::new(ConnManager::new());
+    let dispatcher = Arc::new(Dispatcher::new(
+        outbound_manager.clone(),
+        router.clone(),
+        dns_client.clone(),
+        conn_manager.clone(),
+    ));
+    let nat_manager = Arc::new(NatManager::new(
+        dispatcher.clone(),
+        nat_manager::session_timeouts(&config.inbounds),
+    ));
+    let inbound_manager =
+        InboundManager::new(&config.inbounds, dispatcher, nat_manager).map_err(Error::Config)?;
+    Ok(Components {
+        dns_client,
+        outbound_manager,
+        router,
+        conn_manager,
+        inbound_manager,
+    })
+}
+
+/// Checks a config the way `start` does, parsing it, compiling the routing
+/// rules and building the handlers, but stops before listening on anything.
+/// Rules routing to unknown outbounds are reported as well.
+pub fn validate(config: Config) -> Result<(), Error> {
+    let (mut config, _) = load_config(config)?;
+    #[cfg(feature = "api")]
+    api_settings(&config)?;
+    let targets: Vec<String> = config
+        .router
+        .as_ref()
+        .map(|x| x.rules.iter().map(|r| r.target_tag.clone()).collect())
+        .unwrap_or_default();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(Error::Io)?;
+    let _g = rt.enter();
+    let components = build_components(&mut config)?;
+    let outbound_manager = components
+        .outbound_manager
+        .try_read()
+        .map_err(|e| Error::Config(e.into()))?;
+    for target in targets.iter() {
+        if outbound_manager.get(target).is_none() {
+            return Err(Error::Config(anyhow!(
+                "rule target [{}] not found in outbounds",
+                target
+            )));
+        }
+    }
+    Ok(())
+}
+
 pub fn start(rt_id: RuntimeId, opts: StartOptions) -> Result<(), Error> {
     println!("start with options:\n{:#?}", opts);
 
+    opts.validate()?;
+
     let (reload_tx, mut reload_rx) = mpsc::channel(1);
     let (shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
+    let (drain_tx, mut drain_rx) = mpsc::channel::<DrainRequest>(1);
+    let (stop_accepting_tx, stop_accepting_rx) = tokio::sync::oneshot::channel::<()>();
 
-    let config_path = match opts.config {
-        Config::File(ref p) => Some(p.to_owned()),
-        _ => None,
+    let config_source = match opts.config {
+        Config::File(ref p) => ConfigSource::File(p.to_owned()),
+        Config::Str(_) => ConfigSource::Str,
+        Config::Internal(_) => ConfigSource::Internal,
     };
 
-    let mut config = match opts.config {
-        Config::File(p) => config::from_file(&p).map_err(Error::Config)?,
-        Config::Str(s) => config::from_string(&s).map_err(Error::Config)?,
-        Config::Internal(c) => c,
-    };
+    let (mut config, config_text) = load_config(opts.config)?;
+    #[cfg(feature = "api")]
+    let api = api_settings(&config)?;
+    // Kept for comparing on reload, building the router modifies the rules.
+    let loaded_config = config.clone();
 
     // FIXME Unfortunately fern does not allow re-initializing the logger,
     // should consider another logging lib if the situation doesn't change.
@@ -366,39 +829,31 @@ pub fn start(rt_id: RuntimeId, opts: StartOptions) -> Result<(), Error> {
         .log
         .as_ref()
         .ok_or_else(|| Error::Config(anyhow!("empty log setting")))?;
-    static ONCE: Once = Once::new();
-    ONCE.call_once(move || {
-        app::logger::setup_logger(log).expect("setup logger failed");
-    });
+    // Set up by the first start that succeeds in it, a failed attempt leaves
+    // it to the next one.
+    if let Ok(mut logger_ready) = LOGGER_READY.lock() {
+        if !*logger_ready {
+            app::logger::setup_logger(log).map_err(Error::Config)?;
+            *logger_ready = true;
+        }
+    }
+    // The runtime is driven on this thread.
+    app::logger::set_runtime_id(rt_id);
 
-    let rt = new_runtime(&opts.runtime_opt)?;
+    let rt = new_runtime(rt_id, &opts.runtime_opt)?;
     let _g = rt.enter();
 
     let mut tasks: Vec<Runner> = Vec::new();
-    let mut runners = Vec::new();
+    let mut runners: Vec<Runner> = Vec::new();
 
-    let dns_client = Arc::new(RwLock::new(
-        DnsClient::new(&config.dns).map_err(Error::Config)?,
-    ));
-    let outbound_manager = Arc::new(RwLock::new(
-        OutboundManager::new(&config.outbounds, dns_client.clone()).map_err(Error::Config)?,
-    ));
-    let router = Arc::new(RwLock::new(Router::new(
-        &mut config.router,
-        dns_client.clone(),
-    )));
-    let dispatcher = Arc::new(Dispatcher::new(
-        outbound_manager.clone(),
-        router.clone(),
-        dns_client.clone(),
-    ));
-    let nat_manager = Arc::new(NatManager::new(dispatcher.clone()));
-    let inbound_manager =
-        InboundManager::new(&config.inbounds, dispatcher, nat_manager).map_err(Error::Config)?;
-    let mut inbound_net_runners = inbound_manager
-        .get_network_runners()
-        .map_err(Error::Config)?;
-    runners.append(&mut inbound_net_runners);
+    let Components {
+        dns_client,
+        outbound_manager,
+        router,
+        conn_manager,
+        mut inbound_manager,
+    } = build_components(&mut config)?;
+    inbound_manager.start_listeners().map_err(Error::Config)?;
 
     #[cfg(all(feature = "inbound-tun", any(target_os = "macos", target_os = "linux")))]
     let net_info = if inbound_manager.has_tun_listener() && inbound_manager.tun_auto() {
@@ -439,17 +894,39 @@ pub fn start(rt_id: RuntimeId, opts: StartOptions) -> Result<(), Error> {
     #[cfg(all(feature = "inbound-tun", any(target_os = "macos", target_os = "linux")))]
     sys::post_tun_creation_setup(&net_info);
 
+    let inbound_addrs = inbound_manager.bound_addrs().clone();
+    let has_network_listeners = inbound_manager.has_network_listeners();
+    let inbound_manager = Arc::new(RwLock::new(inbound_manager));
+    // The listeners are stopped on a graceful shutdown, the relays already
+    // spawned keep running.
+    if has_network_listeners {
+        let inbound_manager = inbound_manager.clone();
+        runners.push(Box::pin(async move {
+            if stop_accepting_rx.await.is_ok() {
+                inbound_manager.write().await.stop_listeners().await;
+                log::info!("stopped accepting inbound connections");
+            }
+            futures::future::pending::<()>().await;
+        }));
+    }
+
     let runtime_manager = RuntimeManager::new(
         #[cfg(feature = "auto-reload")]
         rt_id,
-        config_path,
+        config_source,
         #[cfg(feature = "auto-reload")]
         opts.auto_reload,
         reload_tx,
         shutdown_tx,
+        drain_tx,
         router,
         dns_client,
         outbound_manager,
+        conn_manager.clone(),
+        inbound_manager,
+        inbound_addrs,
+        loaded_config,
+        config_text,
     );
 
     // Monitor config file changes.
@@ -462,26 +939,11 @@ pub fn start(rt_id: RuntimeId, opts: StartOptions) -> Result<(), Error> {
 
     #[cfg(feature = "api")]
     {
-        use std::net::{IpAddr, SocketAddr};
-        let listen_addr = if !(&*option::API_LISTEN).is_empty() {
-            Some(
-                (&*option::API_LISTEN)
-                    .parse::<SocketAddr>()
-                    .map_err(|e| Error::Config(anyhow!("parse SocketAddr failed: {}", e)))?,
-            )
-        } else if let Some(api) = config.api.as_ref() {
-            Some(SocketAddr::new(
-                api.address
-                    .parse::<IpAddr>()
-                    .map_err(|e| Error::Config(anyhow!("parse IpAddr failed: {}", e)))?,
-                api.port as u16,
-            ))
-        } else {
-            None
-        };
-        if let Some(listen_addr) = listen_addr {
-            let api_server = ApiServer::new(runtime_manager.clone());
-            runners.push(api_server.serve(listen_addr));
+        if let Some((listen_addr, secret)) = api {
+            let api_server = ApiServer::new(runtime_manager.clone(), secret);
+            let (addr, runner) = api_server.serve(listen_addr).map_err(Error::Config)?;
+            *runtime_manager.api_addr.lock().unwrap() = Some(addr);
+            runners.push(runner);
         }
     }
 
@@ -512,6 +974,20 @@ pub fn start(rt_id: RuntimeId, opts: StartOptions) -> Result<(), Error> {
         let _ = shutdown_rx.recv().await;
     }));
 
+    // Monitor graceful shutdown signal.
+    tasks.push(Box::pin(async move {
+        if let Some((timeout, res_tx)) = drain_rx.recv().await {
+            let _ = stop_accepting_tx.send(());
+            let (finished, aborted) = conn_manager.drain(timeout).await;
+            log::info!(
+                "drained sessions, {} finished, {} aborted",
+                finished,
+                aborted
+            );
+            let _ = res_tx.send(GracefulShutdown { finished, aborted });
+        }
+    }));
+
     // Monitor ctrl-c exit signal.
     #[cfg(feature = "ctrlc")]
     tasks.push(Box::pin(async move {
@@ -545,6 +1021,7 @@ pub fn start(rt_id: RuntimeId, opts: StartOptions) -> Result<(), Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::IpAddr;
     use std::thread;
 
     #[test]
@@ -581,4 +1058,450 @@ Direct = direct
             }
         }
     }
+
+    #[cfg(feature = "api")]
+    #[test]
+    fn test_start_rejects_open_api_without_secret() {
+        let conf = r#"
+[General]
+dns-server = 1.1.1.1
+api-interface = 0.0.0.0
+api-port = 0
+
+[Proxy]
+Direct = direct
+"#;
+        let err = validate(Config::Str(conf.to_string())).unwrap_err();
+        assert!(err.to_string().contains("secret"), "{}", err);
+        let opts = StartOptions {
+            config: Config::Str(conf.to_string()),
+            #[cfg(feature = "auto-reload")]
+            auto_reload: false,
+            runtime_opt: RuntimeOption::SingleThread,
+        };
+        assert!(matches!(start(10, opts), Err(Error::Config(..))));
+        assert!(!is_running(10));
+
+        let conf = conf.replace("0.0.0.0", "127.0.0.1");
+        assert!(validate(Config::Str(conf.clone())).is_ok());
+        let conf = conf
+            .replace("127.0.0.1", "0.0.0.0")
+            .replace("api-port = 0", "api-port = 0\napi-secret = s3cret");
+        assert!(validate(Config::Str(conf)).is_ok());
+    }
+
+    #[test]
+    fn test_start_missing_config() {
+        let opts = StartOptions {
+            config: Config::File("/nonexistent/flower.conf".to_string()),
+            #[cfg(feature = "auto-reload")]
+            auto_reload: true,
+            runtime_opt: RuntimeOption::SingleThread,
+        };
+        assert!(matches!(start(1, opts), Err(Error::ConfigPath(..))));
+        assert!(!is_running(1));
+    }
+
+    #[test]
+    fn test_reload_no_config_file() {
+        let conf = r#"
+[General]
+dns-server = 1.1.1.1
+socks-listen = 127.0.0.1:0
+
+[Proxy]
+Direct = direct
+"#;
+
+        let t = thread::spawn(move || {
+            let opts = StartOptions {
+                config: Config::Str(conf.to_string()),
+                #[cfg(feature = "auto-reload")]
+                auto_reload: true,
+                runtime_opt: RuntimeOption::SingleThread,
+            };
+            start(2, opts)
+        });
+        for _ in 0..500 {
+            if is_running(2) {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(matches!(reload(2), Err(Error::NoConfigFile)));
+        assert!(shutdown(2));
+        assert!(t.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_multi_thread_shutdown() {
+        let conf = r#"
+[General]
+dns-server = 1.1.1.1
+socks-listen = 127.0.0.1:0
+
+[Proxy]
+Direct = direct
+"#;
+
+        for (rt_id, runtime_opt) in [
+            (4, RuntimeOption::MultiThreadAuto(2 * 1024 * 1024)),
+            (5, RuntimeOption::MultiThread(2, 2 * 1024 * 1024)),
+            (6, RuntimeOption::MultiThread(0, 2 * 1024 * 1024)),
+        ] {
+            let t = thread::spawn(move || {
+                let opts = StartOptions {
+                    config: Config::Str(conf.to_string()),
+                    #[cfg(feature = "auto-reload")]
+                    auto_reload: false,
+                    runtime_opt,
+                };
+                start(rt_id, opts)
+            });
+            for _ in 0..500 {
+                if is_running(rt_id) {
+                    break;
+                }
+                thread::sleep(std::time::Duration::from_millis(10));
+            }
+            assert!(is_running(rt_id));
+            assert!(shutdown(rt_id));
+            assert!(t.join().unwrap().is_ok());
+            assert!(!is_running(rt_id));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        // Nothing is bound, the port is taken.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let conf = format!(
+            r#"
+[General]
+dns-server = 1.1.1.1
+socks-listen = {}
+
+[Proxy]
+Direct = direct
+
+[Rule]
+FINAL, Direct
+"#,
+            listener.local_addr().unwrap()
+        );
+        assert!(validate(Config::Str(conf.clone())).is_ok());
+
+        let bad_cidr = conf.replace("[Rule]", "[Rule]\nIP-CIDR, 1.2.3.4/99, Direct");
+        assert!(matches!(
+            validate(Config::Str(bad_cidr)),
+            Err(Error::Config(..))
+        ));
+        let missing_target = conf.replace("[Rule]", "[Rule]\nDOMAIN, example.com, Proxy");
+        let err = validate(Config::Str(missing_target)).unwrap_err();
+        assert!(err.to_string().contains("[Proxy]"));
+    }
+
+    #[test]
+    fn test_reload_changed_inbounds() {
+        let conf = r#"
+[General]
+dns-server = 1.1.1.1
+socks-listen = 127.0.0.1:0
+
+[Proxy]
+Direct = direct
+"#;
+        let path = std::env::temp_dir().join(format!("flower-reload-{}.conf", std::process::id()));
+        std::fs::write(&path, conf).unwrap();
+
+        let config_path = path.to_str().unwrap().to_string();
+        let t = thread::spawn(move || {
+            let opts = StartOptions {
+                config: Config::File(config_path),
+                #[cfg(feature = "auto-reload")]
+                auto_reload: false,
+                runtime_opt: RuntimeOption::SingleThread,
+            };
+            start(3, opts)
+        });
+        for _ in 0..500 {
+            if is_running(3) {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let addrs = inbound_addrs(3).unwrap();
+        assert_eq!(addrs.len(), 1);
+        let status = runtimes().into_iter().find(|x| x.id == 3).unwrap();
+        assert_eq!(
+            status.config_source,
+            ConfigSource::File(path.to_str().unwrap().to_string())
+        );
+        assert_eq!(status.inbound_addrs, addrs);
+        assert_eq!(status.active_sessions, 0);
+
+        // The socks inbound is kept, it would get another port otherwise.
+        std::fs::write(
+            &path,
+            conf.replace("[Proxy]", "http-listen = 127.0.0.1:0\n\n[Proxy]"),
+        )
+        .unwrap();
+        assert!(reload(3).is_ok());
+        let new_addrs = inbound_addrs(3).unwrap();
+        assert_eq!(new_addrs.get("socks"), addrs.get("socks"));
+        assert!(new_addrs.contains_key("http"));
+
+        // Nor if an inbound fails to bind, the current ones keep listening.
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let listen = |socks: &str, http: &str| {
+            conf.replace(
+                "socks-listen = 127.0.0.1:0",
+                &format!("socks-listen = {}", socks),
+            )
+            .replace("[Proxy]", &format!("http-listen = {}\n\n[Proxy]", http))
+        };
+        std::fs::write(
+            &path,
+            listen("127.0.0.1:0", &taken.local_addr().unwrap().to_string()),
+        )
+        .unwrap();
+        assert!(reload(3).is_err());
+        assert_eq!(inbound_addrs(3).unwrap(), new_addrs);
+        drop(taken);
+        // Including those closed to bind the new ones on the same port.
+        let socks_port = new_addrs.get("socks").unwrap().port();
+        std::fs::write(
+            &path,
+            listen(&format!("1.2.3.4:{}", socks_port), "127.0.0.1:0"),
+        )
+        .unwrap();
+        assert!(reload(3).is_err());
+        assert_eq!(inbound_addrs(3).unwrap(), new_addrs);
+
+        // Nothing is swapped in if any part of the config is invalid.
+        std::fs::write(
+            &path,
+            format!("{}\n[Rule]\nIP-CIDR, 1.2.3.4/99, Direct\n", conf),
+        )
+        .unwrap();
+        assert!(reload(3).is_err());
+        assert_eq!(inbound_addrs(3).unwrap(), new_addrs);
+        assert!(is_running(3));
+
+        assert!(shutdown(3));
+        assert!(t.join().unwrap().is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_assets_keeps_applied_rules() {
+        let conf = r#"
+[General]
+dns-server = 1.1.1.1
+socks-listen = 127.0.0.1:0
+
+[Proxy]
+Direct = direct
+Reject = reject
+
+[Rule]
+DOMAIN, example.com, Direct
+"#;
+        let path =
+            std::env::temp_dir().join(format!("flower-reload-assets-{}.conf", std::process::id()));
+        std::fs::write(&path, conf).unwrap();
+
+        let config_path = path.to_str().unwrap().to_string();
+        let t = thread::spawn(move || {
+            let opts = StartOptions {
+                config: Config::File(config_path),
+                #[cfg(feature = "auto-reload")]
+                auto_reload: false,
+                runtime_opt: RuntimeOption::SingleThread,
+            };
+            start(8, opts)
+        });
+        for _ in 0..500 {
+            if is_running(8) {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let m = RUNTIME_MANAGER.lock().unwrap()[&8].clone();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let sess = session::Session {
+            destination: session::SocksAddr::Domain("example.com".to_string(), 443),
+            ..Default::default()
+        };
+        let route = || {
+            rt.block_on(async {
+                m.router
+                    .read()
+                    .await
+                    .pick_route(&sess)
+                    .await
+                    .unwrap()
+                    .to_owned()
+            })
+        };
+
+        // The rules changed on disk are not picked up with the assets.
+        std::fs::write(
+            &path,
+            conf.replace("example.com, Direct", "example.com, Reject"),
+        )
+        .unwrap();
+        assert!(rt.block_on(m.reload_assets()).is_ok());
+        assert_eq!(route(), "Direct");
+
+        // They are with the next reload, compared against the applied config.
+        assert!(reload(8).is_ok());
+        assert_eq!(route(), "Reject");
+        assert!(rt.block_on(m.reload_assets()).is_ok());
+        assert_eq!(route(), "Reject");
+
+        drop(m);
+        assert!(shutdown(8));
+        assert!(t.join().unwrap().is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_assets_site() {
+        let dir = std::env::temp_dir().join(format!("flower-reload-site-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ads"), "example.com\n").unwrap();
+        let conf = format!(
+            r#"
+[General]
+dns-server = 1.1.1.1
+socks-listen = 127.0.0.1:0
+
+[Proxy]
+Direct = direct
+Reject = reject
+
+[Rule]
+EXTERNAL, site:{}:ads, Reject
+"#,
+            dir.display()
+        );
+        let path = dir.join("flower.conf");
+        std::fs::write(&path, conf).unwrap();
+
+        let config_path = path.to_str().unwrap().to_string();
+        let t = thread::spawn(move || {
+            let opts = StartOptions {
+                config: Config::File(config_path),
+                #[cfg(feature = "auto-reload")]
+                auto_reload: false,
+                runtime_opt: RuntimeOption::SingleThread,
+            };
+            start(9, opts)
+        });
+        for _ in 0..500 {
+            if is_running(9) {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let m = RUNTIME_MANAGER.lock().unwrap()[&9].clone();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let route = |domain: &str| {
+            let sess = session::Session {
+                destination: session::SocksAddr::Domain(domain.to_string(), 443),
+                ..Default::default()
+            };
+            rt.block_on(async { m.router.read().await.pick_route(&sess).await.ok().cloned() })
+        };
+        assert_eq!(route("example.com").as_deref(), Some("Reject"));
+        assert_eq!(route("example.org"), None);
+
+        // The updated list is read again along with the assets.
+        std::fs::write(dir.join("ads"), "example.org\n").unwrap();
+        assert!(rt.block_on(m.reload_assets()).is_ok());
+        assert_eq!(route("example.com"), None);
+        assert_eq!(route("example.org").as_deref(), Some("Reject"));
+
+        drop(m);
+        assert!(shutdown(9));
+        assert!(t.join().unwrap().is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reload_keeps_dns_on_bind_failure() {
+        let conf = r#"
+[General]
+dns-server = 1.1.1.1
+socks-listen = 127.0.0.1:0
+
+[Proxy]
+Direct = direct
+
+[Host]
+example.com = 1.2.3.4
+"#;
+        let path =
+            std::env::temp_dir().join(format!("flower-reload-dns-{}.conf", std::process::id()));
+        std::fs::write(&path, conf).unwrap();
+
+        let config_path = path.to_str().unwrap().to_string();
+        let t = thread::spawn(move || {
+            let opts = StartOptions {
+                config: Config::File(config_path),
+                #[cfg(feature = "auto-reload")]
+                auto_reload: false,
+                runtime_opt: RuntimeOption::SingleThread,
+            };
+            start(7, opts)
+        });
+        for _ in 0..500 {
+            if is_running(7) {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let dns_client = RUNTIME_MANAGER.lock().unwrap()[&7].dns_client.clone();
+        let lookup = || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            rt.block_on(async {
+                dns_client
+                    .read()
+                    .await
+                    .lookup(&"example.com".to_string())
+                    .await
+                    .unwrap()
+            })
+        };
+        assert_eq!(lookup(), vec!["1.2.3.4".parse::<IpAddr>().unwrap()]);
+
+        // The new hosts are dropped along with the rest of the config if an
+        // inbound fails to bind.
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        std::fs::write(
+            &path,
+            conf.replace("1.2.3.4", "5.6.7.8").replace(
+                "[Proxy]",
+                &format!("http-listen = {}\n\n[Proxy]", taken.local_addr().unwrap()),
+            ),
+        )
+        .unwrap();
+        assert!(reload(7).is_err());
+        assert_eq!(lookup(), vec!["1.2.3.4".parse::<IpAddr>().unwrap()]);
+
+        std::fs::write(&path, conf.replace("1.2.3.4", "5.6.7.8")).unwrap();
+        assert!(reload(7).is_ok());
+        assert_eq!(lookup(), vec!["5.6.7.8".parse::<IpAddr>().unwrap()]);
+
+        assert!(shutdown(7));
+        assert!(t.join().unwrap().is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
 }
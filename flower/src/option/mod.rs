@@ -77,18 +77,57 @@ lazy_static! {
         get_env_var_or("TCP_DOWNLINK_TIMEOUT", 4)
     };
 
+    /// Sets `TCP_NODELAY` on accepted and dialed TCP sockets whose inbound
+    /// or outbound has no nodelay mode, off by default. UDP sockets,
+    /// including the ones of QUIC, are not affected.
+    pub static ref TCP_NODELAY: bool = {
+        get_env_var_or("TCP_NODELAY", false)
+    };
+
+    /// Idle seconds before keepalive probes are sent on accepted and dialed
+    /// TCP sockets, 0 by default which means the system default. UDP
+    /// sockets, including the ones of QUIC, are not affected.
+    pub static ref TCP_KEEPALIVE_SECS: u64 = {
+        get_env_var_or("TCP_KEEPALIVE_SECS", 0)
+    };
+
     /// Buffer size for uplink and downlink connections, in KB.
     pub static ref LINK_BUFFER_SIZE: usize = {
         get_env_var_or("LINK_BUFFER_SIZE", 2)
     };
 
+    /// Buffer size for relaying streams in inbound handlers, in KB.
+    pub static ref RELAY_BUFFER_SIZE: usize = {
+        get_env_var_or("RELAY_BUFFER_SIZE", 16)
+    };
+
+    /// Maximum number of idle relay buffers kept for reuse.
+    pub static ref RELAY_BUFFER_POOL_SIZE: usize = {
+        get_env_var_or("RELAY_BUFFER_POOL_SIZE", 64)
+    };
+
+    /// Timeout for connecting to an address of an outbound TCP destination,
+    /// in seconds, overridden by the `connect_timeout` of outbounds.
     pub static ref OUTBOUND_DIAL_TIMEOUT: u64 = {
         get_env_var_or("OUTBOUND_DIAL_TIMEOUT", 4)
     };
 
-    /// Maximum outbound dial concurrency.
-    pub static ref OUTBOUND_DIAL_CONCURRENCY: usize = {
-        get_env_var_or("OUTBOUND_DIAL_CONCURRENCY", 1)
+    /// Timeout for completing a TLS or QUIC handshake, in seconds,
+    /// overridden by the `handshake_timeout` of outbounds.
+    pub static ref TLS_HANDSHAKE_TIMEOUT: u64 = {
+        get_env_var_or("TLS_HANDSHAKE_TIMEOUT", 4)
+    };
+
+    /// Time to wait for the beginning of a TCP session when sniffing it,
+    /// e.g. a whole TLS ClientHello, in milliseconds.
+    pub static ref SNIFF_TIMEOUT: u64 = {
+        get_env_var_or("SNIFF_TIMEOUT", 100)
+    };
+
+    /// Delay before dialing the next address of a destination while the
+    /// previous dials are pending, in milliseconds.
+    pub static ref OUTBOUND_DIAL_ATTEMPT_DELAY: u64 = {
+        get_env_var_or("OUTBOUND_DIAL_ATTEMPT_DELAY", 250)
     };
 
     pub static ref ASSET_LOCATION: String = {
@@ -148,13 +187,20 @@ lazy_static! {
         get_env_var_or("SOCKET_PROTECT_SERVER", "".to_string()).parse().ok()
     };
 
+    /// The file descriptor of a tun device opened by the platform, e.g. the
+    /// one established by the VPN service on Android. Used by the tun inbound
+    /// if its settings don't specify a file descriptor.
+    pub static ref TUN_FD: Option<i32> = {
+        get_env_var_or("TUN_FD", "".to_string()).parse().ok()
+    };
+
     pub static ref GATEWAY_MODE: bool = {
         get_env_var_or("GATEWAY_MODE", false)
     };
 
     /// UDP session timeout. A UDP session shall be terminated if there are no
     /// activities in this period. The timeouts are observed only when a check
-    /// is happened.
+    /// is happened. Overridden by the `udp_session_timeout` of inbounds.
     pub static ref UDP_SESSION_TIMEOUT: u64 = {
         get_env_var_or("UDP_SESSION_TIMEOUT", 30)
     };
@@ -175,6 +221,30 @@ lazy_static! {
         get_env_var_or("DNS_TIMEOUT", 4)
     };
 
+    /// Overall timeout for the built-in DNS client to get a response from any
+    /// of the servers, queried concurrently, defaults to the time a server is
+    /// given with all the retries.
+    pub static ref DNS_LOOKUP_TIMEOUT: u64 = {
+        get_env_var_or("DNS_LOOKUP_TIMEOUT", *DNS_TIMEOUT * *MAX_DNS_RETRIES as u64)
+    };
+
+    /// Maximum number of QUIC connections pooled by all QUIC outbounds, the
+    /// least recently used idle connection is closed when exceeded.
+    pub static ref QUIC_MAX_POOLED_CONNECTIONS: usize = {
+        get_env_var_or("QUIC_MAX_POOLED_CONNECTIONS", 64)
+    };
+
+    /// Number of runtime events buffered for each event stream consumer.
+    pub static ref EVENT_BUFFER_SIZE: usize = {
+        get_env_var_or("EVENT_BUFFER_SIZE", 256)
+    };
+
+    /// Whether an event stream consumer falling behind is disconnected,
+    /// instead of skipping the events it missed.
+    pub static ref EVENT_STREAM_DISCONNECT_LAGGING: bool = {
+        get_env_var_or("EVENT_STREAM_DISCONNECT_LAGGING", false)
+    };
+
     pub static ref DEFAULT_TUN_NAME: String = {
         get_env_var_or("DEFAULT_TUN_NAME", "utun233".to_string())
     };
@@ -34,6 +34,8 @@ impl Stream for Incoming {
             ready!(Pin::new(&mut self.acceptor).poll_next(cx)).map(|stream| {
                 let mut sess = self.sess.clone();
                 sess.stream_id = Some(stream.id().into());
+                // Streams are accounted separately.
+                sess.traffic = Default::default();
                 AnyBaseInboundTransport::Stream(Box::new(stream), sess)
             }),
         )
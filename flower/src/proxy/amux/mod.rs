@@ -33,6 +33,9 @@ pub mod outbound;
 pub const FRAME_STREAM: u8 = 0x01;
 pub const FRAME_STREAM_FIN: u8 = 0x02;
 
+/// The largest payload of a stream frame, larger writes are split.
+pub const MAX_FRAME_DATA_SIZE: usize = u16::MAX as usize;
+
 pub fn random_u16() -> u16 {
     use rand::{rngs::StdRng, RngCore, SeedableRng};
     let mut buf = [0u8; std::mem::size_of::<u16>()];
@@ -59,7 +62,8 @@ impl MuxFrame {
             MuxFrame::Stream(id, data) => {
                 buf.put_u8(FRAME_STREAM);
                 buf.put_u16(*id as u16);
-                buf.put_u16(data.len() as u16); // FIXME check len
+                debug_assert!(data.len() <= MAX_FRAME_DATA_SIZE);
+                buf.put_u16(data.len() as u16);
                 buf.put_slice(data);
             }
             MuxFrame::StreamFin(id) => {
@@ -177,6 +181,11 @@ impl AsyncWrite for MuxStream {
         cx: &mut Context,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
+        // An empty stream frame is read as EOF by the peer.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let buf = &buf[..min(buf.len(), MAX_FRAME_DATA_SIZE)];
         loop {
             match self.write_state {
                 TaskState::Idle => {
@@ -701,3 +710,27 @@ impl Stream for MuxAcceptor {
         self.stream_accept_rx.poll_recv(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_frame() {
+        let data = vec![7u8; MAX_FRAME_DATA_SIZE];
+        let mut conn = MuxConnection::new(());
+        conn.read_buf
+            .extend_from_slice(&MuxFrame::Stream(1, data.clone()).to_bytes());
+        conn.read_buf
+            .extend_from_slice(&MuxFrame::StreamFin(1).to_bytes());
+        match conn.decode_frame().unwrap() {
+            Some(MuxFrame::Stream(1, decoded)) => assert_eq!(decoded, data),
+            _ => panic!("expected a stream frame"),
+        }
+        assert!(matches!(
+            conn.decode_frame().unwrap(),
+            Some(MuxFrame::StreamFin(1))
+        ));
+        assert!(conn.decode_frame().unwrap().is_none());
+    }
+}
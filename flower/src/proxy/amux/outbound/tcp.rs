@@ -77,6 +77,7 @@ impl MuxManager {
             }
         }
 
+        self.connectors.lock().await.retain(|c| !c.is_done());
         for c in self.connectors.lock().await.iter_mut() {
             if let Some(s) = c.new_stream().await {
                 return Ok(s);
@@ -0,0 +1,211 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::proxy::AnyOutboundHandler;
+
+pub mod tcp;
+pub mod udp;
+
+pub use tcp::Handler as TcpHandler;
+pub use udp::Handler as UdpHandler;
+
+/// Seconds an actor is skipped after failing a session by default.
+pub const DEFAULT_FAIL_COOLDOWN: u32 = 30;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    RoundRobin,
+    Random,
+    LeastConnections,
+}
+
+impl Strategy {
+    /// Accepts round-robin, random or least-connections, an empty string
+    /// means round-robin.
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "" | "round-robin" => Ok(Strategy::RoundRobin),
+            "random" => Ok(Strategy::Random),
+            "least-connections" => Ok(Strategy::LeastConnections),
+            _ => Err(anyhow!("unsupported balance strategy [{}]", s)),
+        }
+    }
+}
+
+struct Actor {
+    handler: AnyOutboundHandler,
+    // Number of open sessions.
+    active: AtomicUsize,
+    failed_at: Mutex<Option<Instant>>,
+}
+
+/// Selects an actor per session, shared by the TCP and UDP handlers so that
+/// both count toward the connections and failures of the actors.
+pub struct Balancer {
+    actors: Vec<Actor>,
+    strategy: Strategy,
+    fail_cooldown: Duration,
+    next: AtomicUsize,
+}
+
+impl Balancer {
+    /// Actors failing a session are skipped for `fail_cooldown` seconds, or
+    /// `DEFAULT_FAIL_COOLDOWN` if it's 0, unless all of them are failing.
+    pub fn new(actors: Vec<AnyOutboundHandler>, strategy: Strategy, fail_cooldown: u32) -> Self {
+        let fail_cooldown = if fail_cooldown == 0 {
+            DEFAULT_FAIL_COOLDOWN
+        } else {
+            fail_cooldown
+        };
+        Balancer {
+            actors: actors
+                .into_iter()
+                .map(|handler| Actor {
+                    handler,
+                    active: AtomicUsize::new(0),
+                    failed_at: Mutex::new(None),
+                })
+                .collect(),
+            strategy,
+            fail_cooldown: Duration::from_secs(fail_cooldown as u64),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_failing(&self, actor: &Actor) -> bool {
+        actor
+            .failed_at
+            .lock()
+            .unwrap()
+            .map_or(false, |t| t.elapsed() < self.fail_cooldown)
+    }
+
+    fn select(&self) -> usize {
+        let mut candidates: Vec<usize> = (0..self.actors.len())
+            .filter(|&i| !self.is_failing(&self.actors[i]))
+            .collect();
+        if candidates.is_empty() {
+            candidates = (0..self.actors.len()).collect();
+        }
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        match self.strategy {
+            Strategy::RoundRobin => candidates[next % candidates.len()],
+            Strategy::Random => {
+                let mut rng = StdRng::from_entropy();
+                candidates[rng.gen_range(0..candidates.len())]
+            }
+            // Ties are broken in turn.
+            Strategy::LeastConnections => {
+                let start = next % candidates.len();
+                candidates[start..]
+                    .iter()
+                    .chain(candidates[..start].iter())
+                    .copied()
+                    .min_by_key(|&i| self.actors[i].active.load(Ordering::Relaxed))
+                    .unwrap()
+            }
+        }
+    }
+
+    /// Selects an actor for a new session, the session counts as open on it
+    /// until the guard drops.
+    pub fn acquire(self: &Arc<Self>) -> (AnyOutboundHandler, ActorGuard) {
+        let i = self.select();
+        self.actors[i].active.fetch_add(1, Ordering::Relaxed);
+        (
+            self.actors[i].handler.clone(),
+            ActorGuard {
+                balancer: self.clone(),
+                index: i,
+            },
+        )
+    }
+}
+
+/// Keeps a session open on an actor.
+pub struct ActorGuard {
+    balancer: Arc<Balancer>,
+    index: usize,
+}
+
+impl ActorGuard {
+    /// Records whether the actor handled the session.
+    pub fn report(&self, ok: bool) {
+        let mut failed_at = self.balancer.actors[self.index].failed_at.lock().unwrap();
+        *failed_at = if ok { None } else { Some(Instant::now()) };
+    }
+}
+
+impl Drop for ActorGuard {
+    fn drop(&mut self) {
+        self.balancer.actors[self.index]
+            .active
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::{outbound::HandlerBuilder, Tag};
+
+    use super::*;
+
+    fn balancer(strategy: Strategy) -> Arc<Balancer> {
+        let actors = (0..3)
+            .map(|i| {
+                let handler: AnyOutboundHandler =
+                    HandlerBuilder::default().tag(i.to_string()).build();
+                handler
+            })
+            .collect();
+        Arc::new(Balancer::new(actors, strategy, 0))
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!(Strategy::parse("").unwrap(), Strategy::RoundRobin);
+        assert_eq!(
+            Strategy::parse("least-connections").unwrap(),
+            Strategy::LeastConnections
+        );
+        assert!(Strategy::parse("fastest").is_err());
+    }
+
+    #[test]
+    fn test_round_robin_skips_failing() {
+        let b = balancer(Strategy::RoundRobin);
+        let tags: Vec<String> = (0..3).map(|_| b.acquire().0.tag().clone()).collect();
+        assert_eq!(tags, ["0", "1", "2"]);
+
+        b.acquire().1.report(false);
+        for _ in 0..4 {
+            assert_ne!(b.acquire().0.tag(), "0");
+        }
+    }
+
+    #[test]
+    fn test_least_connections() {
+        let b = balancer(Strategy::LeastConnections);
+        let (a0, _g0) = b.acquire();
+        let (a1, _g1) = b.acquire();
+        let (a2, g2) = b.acquire();
+        assert_ne!(a0.tag(), a1.tag());
+        assert_ne!(a1.tag(), a2.tag());
+        drop(g2);
+        assert_eq!(b.acquire().0.tag(), a2.tag());
+    }
+
+    #[test]
+    fn test_all_failing() {
+        let b = balancer(Strategy::Random);
+        for _ in 0..3 {
+            let (_, guard) = b.acquire();
+            guard.report(false);
+        }
+        b.acquire();
+    }
+}
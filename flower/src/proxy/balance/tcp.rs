@@ -0,0 +1,84 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::task::{Context, Poll};
+use log::*;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{app::SyncDnsClient, proxy::*, session::Session};
+
+use super::{ActorGuard, Balancer};
+
+// A stream counted as open on its actor until dropped.
+struct BalancedStream {
+    inner: AnyStream,
+    _guard: ActorGuard,
+}
+
+impl AsyncRead for BalancedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BalancedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+pub struct Handler {
+    pub balancer: Arc<Balancer>,
+    pub dns_client: SyncDnsClient,
+}
+
+#[async_trait]
+impl TcpOutboundHandler for Handler {
+    type Stream = AnyStream;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        None
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        _stream: Option<Self::Stream>,
+    ) -> io::Result<Self::Stream> {
+        let (a, guard) = self.balancer.acquire();
+        debug!(
+            "balance handles tcp [{}] to [{}]",
+            sess.destination,
+            a.tag()
+        );
+        let res = async {
+            let stream =
+                crate::proxy::connect_tcp_outbound(sess, self.dns_client.clone(), &a).await?;
+            TcpOutboundHandler::handle(a.as_ref(), sess, stream).await
+        }
+        .await;
+        guard.report(res.is_ok());
+        Ok(Box::new(BalancedStream {
+            inner: res?,
+            _guard: guard,
+        }))
+    }
+}
@@ -0,0 +1,89 @@
+use std::io;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::*;
+
+use crate::{app::SyncDnsClient, proxy::*, session::Session, session::SocksAddr};
+
+use super::{ActorGuard, Balancer};
+
+// A datagram counted as open on its actor until both halves drop.
+struct BalancedDatagram {
+    inner: AnyOutboundDatagram,
+    guard: ActorGuard,
+}
+
+impl OutboundDatagram for BalancedDatagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ) {
+        let guard = Arc::new(self.guard);
+        let (r, s) = self.inner.split();
+        (
+            Box::new(DatagramRecvHalf(r, guard.clone())),
+            Box::new(DatagramSendHalf(s, guard)),
+        )
+    }
+}
+
+struct DatagramRecvHalf(Box<dyn OutboundDatagramRecvHalf>, Arc<ActorGuard>);
+
+#[async_trait]
+impl OutboundDatagramRecvHalf for DatagramRecvHalf {
+    async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocksAddr)> {
+        self.0.recv_from(buf).await
+    }
+}
+
+struct DatagramSendHalf(Box<dyn OutboundDatagramSendHalf>, Arc<ActorGuard>);
+
+#[async_trait]
+impl OutboundDatagramSendHalf for DatagramSendHalf {
+    async fn send_to(&mut self, buf: &[u8], dst_addr: &SocksAddr) -> io::Result<usize> {
+        self.0.send_to(buf, dst_addr).await
+    }
+}
+
+pub struct Handler {
+    pub balancer: Arc<Balancer>,
+    pub dns_client: SyncDnsClient,
+}
+
+#[async_trait]
+impl UdpOutboundHandler for Handler {
+    type UStream = AnyStream;
+    type Datagram = AnyOutboundDatagram;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        None
+    }
+
+    fn transport_type(&self) -> DatagramTransportType {
+        DatagramTransportType::Undefined
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        _transport: Option<OutboundTransport<Self::UStream, Self::Datagram>>,
+    ) -> io::Result<Self::Datagram> {
+        let (a, guard) = self.balancer.acquire();
+        debug!(
+            "balance handles udp [{}] to [{}]",
+            sess.destination,
+            a.tag()
+        );
+        let res = async {
+            let transport =
+                crate::proxy::connect_udp_outbound(sess, self.dns_client.clone(), &a).await?;
+            UdpOutboundHandler::handle(a.as_ref(), sess, transport).await
+        }
+        .await;
+        guard.report(res.is_ok());
+        Ok(Box::new(BalancedDatagram { inner: res?, guard }))
+    }
+}
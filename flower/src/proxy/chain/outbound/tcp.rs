@@ -58,13 +58,16 @@ impl TcpOutboundHandler for Handler {
                 }
             }
         }
+        let mut alpn = sess.negotiated_alpn.clone();
         for (i, a) in self.actors.iter().enumerate() {
-            let new_sess = self.next_session(sess.clone(), i + 1);
-            let s = stream.take();
-            stream.replace(TcpOutboundHandler::handle(a.as_ref(), &new_sess, s).await?);
+            let mut new_sess = self.next_session(sess.clone(), i + 1);
+            new_sess.negotiated_alpn = alpn;
+            let s = TcpOutboundHandler::handle(a.as_ref(), &new_sess, stream.take()).await?;
+            alpn = negotiated_alpn(&s);
+            stream.replace(s);
         }
         if let Some(stream) = stream {
-            Ok(Box::new(stream))
+            Ok(stream)
         } else {
             Err(io::Error::new(io::ErrorKind::Other, "invalid input"))
         }
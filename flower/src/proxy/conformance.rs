@@ -0,0 +1,222 @@
+//! Conformance checks of proxy streams. Each check writes to a stream and
+//! verifies what the peer reads, catching wrappers mishandling empty writes,
+//! flushes with nothing buffered, or data pending on shutdown.
+
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+
+// How long the peer waits to make sure nothing arrives.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+async fn assert_idle<R: AsyncRead + Unpin>(r: &mut R) {
+    let mut buf = [0u8; 1];
+    let res = timeout(IDLE_TIMEOUT, r.read(&mut buf)).await;
+    assert!(res.is_err(), "unexpected read: {:?}", res);
+}
+
+async fn assert_read<R: AsyncRead + Unpin>(r: &mut R, expected: &[u8]) {
+    let mut buf = vec![0u8; expected.len()];
+    timeout(READ_TIMEOUT, r.read_exact(&mut buf))
+        .await
+        .expect("read timed out")
+        .unwrap();
+    assert_eq!(buf, expected);
+}
+
+/// Zero-length writes are no-ops, the peer sees neither data nor EOF.
+pub async fn check_empty_writes<W, R>(w: &mut W, r: &mut R)
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    assert_eq!(w.write(&[]).await.unwrap(), 0);
+    w.flush().await.unwrap();
+    assert_idle(r).await;
+
+    w.write_all(b"abc").await.unwrap();
+    assert_eq!(w.write(&[]).await.unwrap(), 0);
+    w.flush().await.unwrap();
+    assert_read(r, b"abc").await;
+    assert_idle(r).await;
+}
+
+/// Flushes interleaved with writes, including flushes with nothing buffered,
+/// keep the data intact.
+pub async fn check_interleaved_flushes<W, R>(w: &mut W, r: &mut R)
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    w.flush().await.unwrap();
+    w.flush().await.unwrap();
+    w.write_all(b"a").await.unwrap();
+    w.flush().await.unwrap();
+    w.flush().await.unwrap();
+    w.write_all(b"bc").await.unwrap();
+    assert_eq!(w.write(&[]).await.unwrap(), 0);
+    w.write_all(b"def").await.unwrap();
+    w.flush().await.unwrap();
+    assert_read(r, b"abcdef").await;
+}
+
+/// Data written before a shutdown reaches the peer, flushed or not.
+pub async fn check_shutdown<W, R>(w: &mut W, r: &mut R)
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    w.write_all(b"xyz").await.unwrap();
+    assert_eq!(w.write(&[]).await.unwrap(), 0);
+    w.shutdown().await.unwrap();
+    assert_read(r, b"xyz").await;
+}
+
+/// Runs all the checks in both directions of a connected pair, `a` is shut
+/// down in the end.
+pub async fn check_stream<A, B>(a: &mut A, b: &mut B)
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    check_empty_writes(a, b).await;
+    check_empty_writes(b, a).await;
+    check_interleaved_flushes(a, b).await;
+    check_interleaved_flushes(b, a).await;
+    check_shutdown(a, b).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use tokio::io::duplex;
+
+    use crate::proxy::stream::*;
+
+    use super::*;
+
+    fn run<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn test_simple_stream() {
+        run(async {
+            let (a, mut b) = duplex(1024);
+            check_stream(&mut SimpleProxyStream(a), &mut b).await;
+
+            let (a, mut b) = duplex(1024);
+            check_stream(&mut ShutdownOnDrop::new(a), &mut b).await;
+
+            let (a, mut b) = duplex(1024);
+            check_stream(&mut PrefixedProxyStream::new(a, BytesMut::new()), &mut b).await;
+        });
+    }
+
+    #[test]
+    fn test_buf_head_stream() {
+        run(async {
+            let (a, mut b) = duplex(1024);
+            let mut a = BufHeadProxyStream::new(a, Bytes::from_static(b"head"));
+            // The header isn't sent without payload.
+            assert_eq!(a.write(&[]).await.unwrap(), 0);
+            a.flush().await.unwrap();
+            assert_idle(&mut b).await;
+            a.write_all(b"!").await.unwrap();
+            assert_read(&mut b, b"head!").await;
+            check_stream(&mut a, &mut b).await;
+        });
+    }
+
+    #[cfg(any(feature = "inbound-shadowsocks", feature = "outbound-shadowsocks"))]
+    #[test]
+    fn test_shadowed_stream() {
+        use crate::common::crypto::Cipher;
+        use crate::proxy::shadowsocks::shadow;
+
+        run(async {
+            for cipher in [Cipher::Aes128Gcm, Cipher::ChaCha20Poly1305] {
+                let (a, b) = duplex(1024);
+                let mut a = shadow::new_stream(a, cipher, "password");
+                let mut b = shadow::new_stream(b, cipher, "password");
+                check_stream(&mut a, &mut b).await;
+            }
+        });
+    }
+
+    #[cfg(any(feature = "inbound-ws", feature = "outbound-ws"))]
+    #[test]
+    fn test_ws_stream() {
+        use tokio_tungstenite::WebSocketStream;
+        use tungstenite::protocol::Role;
+
+        use crate::proxy::ws::WebSocketToStream;
+
+        run(async {
+            let (a, b) = duplex(1024);
+            let a = WebSocketStream::from_raw_socket(a, Role::Client, None).await;
+            let b = WebSocketStream::from_raw_socket(b, Role::Server, None).await;
+            check_stream(
+                &mut WebSocketToStream::new(a),
+                &mut WebSocketToStream::new(b),
+            )
+            .await;
+        });
+    }
+
+    #[cfg(any(feature = "inbound-grpc", feature = "outbound-grpc"))]
+    #[test]
+    fn test_grpc_stream() {
+        use crate::proxy::grpc::GrpcStream;
+
+        run(async {
+            let (a, b) = duplex(1024);
+            let server = tokio::spawn(async move {
+                let mut conn = h2::server::handshake(b).await.unwrap();
+                let (req, mut respond) = conn.accept().await.unwrap().unwrap();
+                let resp = http::Response::builder().body(()).unwrap();
+                let send = respond.send_response(resp, false).unwrap();
+                // Drives the connection.
+                tokio::spawn(async move { while conn.accept().await.is_some() {} });
+                GrpcStream::new(req.into_body(), send, true)
+            });
+            let (client, conn) = h2::client::handshake(a).await.unwrap();
+            tokio::spawn(conn);
+            let mut client = client.ready().await.unwrap();
+            let req = http::Request::builder().body(()).unwrap();
+            let (resp, send) = client.send_request(req, false).unwrap();
+            let mut b = server.await.unwrap();
+            let resp = resp.await.unwrap();
+            let mut a = GrpcStream::new(resp.into_body(), send, false);
+            check_stream(&mut a, &mut b).await;
+            check_shutdown(&mut b, &mut a).await;
+        });
+    }
+
+    #[cfg(any(feature = "inbound-amux", feature = "outbound-amux"))]
+    #[test]
+    fn test_mux_stream() {
+        use futures::StreamExt;
+
+        use crate::proxy::amux::MuxSession;
+
+        run(async {
+            let (a, b) = duplex(1024);
+            let mut connector = MuxSession::connector(a, 1, 1);
+            let mut acceptor = MuxSession::acceptor(b);
+            let mut a = connector.new_stream().await.unwrap();
+            // The peer stream is accepted on the first frame.
+            a.write_all(b"!").await.unwrap();
+            let mut b = acceptor.next().await.unwrap();
+            assert_read(&mut b, b"!").await;
+            check_stream(&mut a, &mut b).await;
+        });
+    }
+}
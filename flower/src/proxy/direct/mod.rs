@@ -1,5 +1,64 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::proxy::OutboundBind;
+
 pub mod tcp;
 pub mod udp;
 
 pub use tcp::Handler as TcpHandler;
 pub use udp::Handler as UdpHandler;
+
+/// Returns what the sockets bind to, the interface takes precedence over the
+/// address, `None` if both are empty.
+pub fn parse_binds(
+    bind_address: &str,
+    bind_interface: &str,
+) -> Result<Option<Arc<Vec<OutboundBind>>>> {
+    if !bind_interface.is_empty() {
+        check_interface(bind_interface)?;
+        return Ok(Some(Arc::new(vec![OutboundBind::Interface(
+            bind_interface.to_owned(),
+        )])));
+    }
+    if !bind_address.is_empty() {
+        let addr = crate::common::net::parse_bind_addr(bind_address)
+            .map_err(|e| anyhow!("invalid bind address [{}]: {}", bind_address, e))?;
+        return Ok(Some(Arc::new(vec![OutboundBind::Ip(addr)])));
+    }
+    Ok(None)
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn check_interface(name: &str) -> Result<()> {
+    let ifa =
+        std::ffi::CString::new(name).map_err(|_| anyhow!("invalid bind interface [{}]", name))?;
+    if unsafe { libc::if_nametoindex(ifa.as_ptr()) } == 0 {
+        return Err(anyhow!("bind interface [{}] not found", name));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn check_interface(_name: &str) -> Result<()> {
+    Err(anyhow!(
+        "binding to interface is not supported on this platform"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_binds() {
+        assert!(parse_binds("", "").unwrap().is_none());
+        match parse_binds("192.168.1.2", "").unwrap().unwrap().as_slice() {
+            [OutboundBind::Ip(addr)] => assert_eq!(addr.to_string(), "192.168.1.2:0"),
+            b => panic!("unexpected binds {:?}", b),
+        }
+        assert!(parse_binds("192.168.1", "").is_err());
+        assert!(parse_binds("", "no-such-interface0").is_err());
+    }
+}
@@ -1,24 +1,49 @@
 use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 
-use crate::{proxy::*, session::Session};
+use crate::{app::SyncDnsClient, proxy::*, session::Session};
 
-pub struct Handler;
+pub struct Handler {
+    // Sockets bind to these instead of the process wide outbound binds, the
+    // handler dials the destination itself if set.
+    pub binds: Option<Arc<Vec<OutboundBind>>>,
+    pub nodelay: NoDelay,
+    pub connect_timeout: Duration,
+    pub dns_client: SyncDnsClient,
+}
 
 #[async_trait]
 impl TcpOutboundHandler for Handler {
     type Stream = AnyStream;
 
     fn connect_addr(&self) -> Option<OutboundConnect> {
-        Some(OutboundConnect::Direct)
+        if self.binds.is_some() {
+            Some(OutboundConnect::NoConnect)
+        } else {
+            Some(OutboundConnect::Direct)
+        }
     }
 
     async fn handle<'a>(
         &'a self,
-        _sess: &'a Session,
+        sess: &'a Session,
         stream: Option<Self::Stream>,
     ) -> io::Result<Self::Stream> {
+        if let Some(binds) = &self.binds {
+            let (stream, _) = new_tcp_stream_with_binds(
+                self.dns_client.clone(),
+                &sess.destination.host(),
+                &sess.destination.port(),
+                self.nodelay,
+                binds,
+                self.connect_timeout,
+            )
+            .await?;
+            return Ok(stream);
+        }
         stream.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid input"))
     }
 }
@@ -1,10 +1,20 @@
 use std::io;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 
-use crate::{proxy::*, session::Session};
+use crate::{
+    app::SyncDnsClient,
+    proxy::*,
+    session::{Session, SocksAddr},
+};
 
-pub struct Handler;
+pub struct Handler {
+    // Sockets bind to these instead of the process wide outbound binds, the
+    // handler creates the socket itself if set.
+    pub binds: Option<Arc<Vec<OutboundBind>>>,
+    pub dns_client: SyncDnsClient,
+}
 
 #[async_trait]
 impl UdpOutboundHandler for Handler {
@@ -12,7 +22,11 @@ impl UdpOutboundHandler for Handler {
     type Datagram = AnyOutboundDatagram;
 
     fn connect_addr(&self) -> Option<OutboundConnect> {
-        Some(OutboundConnect::Direct)
+        if self.binds.is_some() {
+            Some(OutboundConnect::NoConnect)
+        } else {
+            Some(OutboundConnect::Direct)
+        }
     }
 
     fn transport_type(&self) -> DatagramTransportType {
@@ -21,9 +35,28 @@ impl UdpOutboundHandler for Handler {
 
     async fn handle<'a>(
         &'a self,
-        _sess: &'a Session,
+        sess: &'a Session,
         transport: Option<OutboundTransport<Self::UStream, Self::Datagram>>,
     ) -> io::Result<Self::Datagram> {
+        if let Some(binds) = &self.binds {
+            // The socket must be of the same family as the bind address.
+            let indicator = match binds.first() {
+                Some(OutboundBind::Ip(addr)) => *addr,
+                _ => sess.source,
+            };
+            let socket = new_udp_socket_with_binds(&indicator, binds).await?;
+            let destination = match &sess.destination {
+                SocksAddr::Domain(domain, port) => {
+                    Some(SocksAddr::Domain(domain.to_owned(), port.to_owned()))
+                }
+                _ => None,
+            };
+            return Ok(Box::new(SimpleOutboundDatagram::new(
+                socket,
+                destination,
+                self.dns_client.clone(),
+            )));
+        }
         if let Some(OutboundTransport::Datagram(dgram)) = transport {
             Ok(dgram)
         } else {
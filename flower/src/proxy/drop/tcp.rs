@@ -1,11 +1,95 @@
 use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
 
 use async_trait::async_trait;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
-use crate::{proxy::*, session::Session};
+use crate::{common::sniff::sniff_stream_protocol, proxy::*, session::Session};
 
-pub struct Handler;
+const HTTP_NO_CONTENT: &[u8] =
+    b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+enum Verdict {
+    // Waits for the first bytes of the client, with the waker of the reader.
+    Undecided(Option<Waker>),
+    Reply(&'static [u8]),
+    Drop,
+}
+
+// Replies to HTTP requests then closes, what the client sends is discarded.
+// Unless the session was sniffed, the first bytes written tell whether it's
+// an HTTP request.
+struct RejectStream {
+    verdict: Verdict,
+}
+
+impl RejectStream {
+    fn decide(&mut self, verdict: Verdict) {
+        if let Verdict::Undecided(waker) = std::mem::replace(&mut self.verdict, verdict) {
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl AsyncRead for RejectStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match &mut self.verdict {
+            Verdict::Undecided(waker) => {
+                waker.replace(cx.waker().clone());
+                Poll::Pending
+            }
+            Verdict::Reply(reply) => {
+                let n = reply.len().min(buf.remaining());
+                buf.put_slice(&reply[..n]);
+                *reply = &reply[n..];
+                Poll::Ready(Ok(()))
+            }
+            Verdict::Drop => Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, "dropped"))),
+        }
+    }
+}
+
+impl AsyncWrite for RejectStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if matches!(self.verdict, Verdict::Undecided(_)) && !buf.is_empty() {
+            if sniff_stream_protocol(buf) == Some("http") {
+                self.decide(Verdict::Reply(HTTP_NO_CONTENT));
+            } else {
+                self.decide(Verdict::Drop);
+            }
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        // Nothing was sent.
+        if matches!(self.verdict, Verdict::Undecided(_)) {
+            self.decide(Verdict::Drop);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub struct Handler {
+    // Replies to HTTP sessions with an empty response instead of dropping
+    // them, so that clients don't hang or retry.
+    pub reject: bool,
+}
 
 #[async_trait]
 impl TcpOutboundHandler for Handler {
@@ -17,9 +101,69 @@ impl TcpOutboundHandler for Handler {
 
     async fn handle<'a>(
         &'a self,
-        _sess: &'a Session,
-        stream: Option<Self::Stream>,
+        sess: &'a Session,
+        _stream: Option<Self::Stream>,
     ) -> io::Result<Self::Stream> {
+        if self.reject {
+            let verdict = match sess.protocol {
+                Some("http") => Verdict::Reply(HTTP_NO_CONTENT),
+                // Not sniffed, the client tells.
+                None => Verdict::Undecided(None),
+                Some(_) => Verdict::Drop,
+            };
+            if !matches!(verdict, Verdict::Drop) {
+                return Ok(Box::new(RejectStream { verdict }));
+            }
+        }
         Err(io::Error::new(io::ErrorKind::Other, "dropped"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn test_reject_http() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let h = Handler { reject: true };
+            let sess = Session {
+                protocol: Some("http"),
+                ..Default::default()
+            };
+            let mut stream = h.handle(&sess, None).await.unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply).await.unwrap();
+            assert_eq!(reply, HTTP_NO_CONTENT);
+
+            // Sessions not sniffed are rejected if they start with an HTTP
+            // request.
+            let mut stream = h.handle(&Session::default(), None).await.unwrap();
+            stream.write_all(b"POST /ad HTTP/1.1\r\n").await.unwrap();
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply).await.unwrap();
+            assert_eq!(reply, HTTP_NO_CONTENT);
+
+            // Non-HTTP sessions are still dropped.
+            let mut stream = h.handle(&Session::default(), None).await.unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_8.9\r\n").await.unwrap();
+            assert!(stream.read_to_end(&mut Vec::new()).await.is_err());
+            let mut stream = h.handle(&Session::default(), None).await.unwrap();
+            stream.shutdown().await.unwrap();
+            assert!(stream.read_to_end(&mut Vec::new()).await.is_err());
+            let sess_tls = Session {
+                protocol: Some("tls"),
+                ..Default::default()
+            };
+            assert!(h.handle(&sess_tls, None).await.is_err());
+            let h = Handler { reject: false };
+            assert!(h.handle(&sess, None).await.is_err());
+        });
+    }
+}
@@ -1,5 +1,156 @@
+use anyhow::{anyhow, Result};
+
+use crate::session::SocksAddr;
+
 pub mod tcp;
 pub mod udp;
 
 pub use tcp::Handler as TcpHandler;
 pub use udp::Handler as UdpHandler;
+
+/// The URL requested by the TCP health checks by default.
+pub const DEFAULT_TEST_URL: &str = "http://www.google.com/";
+
+#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct Measure(usize, u128); // (index, duration in millis)
+
+impl Measure {
+    // Failed checks are measured as durations close to u128::MAX.
+    fn latency(&self) -> Option<u64> {
+        if self.1 < u128::MAX - 3 {
+            Some(self.1 as u64)
+        } else {
+            None
+        }
+    }
+}
+
+// Sorts the measures from the fastest actor, the `current` actor stays first
+// unless it failed or another actor is faster by more than `tolerance` millis.
+fn prioritize(measures: &mut Vec<Measure>, current: Option<usize>, tolerance: u128) {
+    measures.sort_by(|a, b| a.1.cmp(&b.1));
+    if let Some(pos) = current.and_then(|c| measures.iter().position(|m| m.0 == c)) {
+        if measures[pos].latency().is_some()
+            && measures[pos].1 <= measures[0].1.saturating_add(tolerance)
+        {
+            let m = measures.remove(pos);
+            measures.insert(0, m);
+        }
+    }
+}
+
+/// What a TCP health check does through an actor.
+#[derive(Clone, Debug)]
+pub enum Probe {
+    /// Completes the handshake of the actor to the address, from a
+    /// tcp://host:port URL.
+    Connect(SocksAddr),
+    /// Sends a GET request and waits for the response, from an http:// URL.
+    Http {
+        addr: SocksAddr,
+        host: String,
+        path: String,
+    },
+}
+
+impl Probe {
+    /// Accepts tcp:// and http:// URLs, an empty string means
+    /// `DEFAULT_TEST_URL`.
+    pub fn parse(url: &str) -> Result<Self> {
+        let url = if url.is_empty() {
+            DEFAULT_TEST_URL
+        } else {
+            url
+        };
+        let invalid = || anyhow!("invalid test url [{}]", url);
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.strip_prefix('[') {
+            // An IPv6 address.
+            Some(v) => {
+                let (host, port) = v.split_once(']').ok_or_else(invalid)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let port = match (port, scheme) {
+            (Some(port), _) => port.parse::<u16>().map_err(|_| invalid())?,
+            (None, "http") => 80,
+            _ => return Err(invalid()),
+        };
+        let addr = SocksAddr::try_from((host, port)).map_err(|_| invalid())?;
+        match scheme {
+            "tcp" => Ok(Probe::Connect(addr)),
+            "http" => Ok(Probe::Http {
+                addr,
+                host: authority.to_owned(),
+                path: path.to_owned(),
+            }),
+            _ => Err(anyhow!("unsupported test url scheme [{}]", scheme)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe() {
+        match Probe::parse("").unwrap() {
+            Probe::Http { addr, host, path } => {
+                assert_eq!(addr, SocksAddr::Domain("www.google.com".to_string(), 80));
+                assert_eq!(host, "www.google.com");
+                assert_eq!(path, "/");
+            }
+            p => panic!("unexpected probe {:?}", p),
+        }
+        match Probe::parse("http://[::1]:8080/generate_204").unwrap() {
+            Probe::Http { addr, host, path } => {
+                assert_eq!(addr.port(), 8080);
+                assert_eq!(host, "[::1]:8080");
+                assert_eq!(path, "/generate_204");
+            }
+            p => panic!("unexpected probe {:?}", p),
+        }
+        match Probe::parse("tcp://1.1.1.1:443").unwrap() {
+            Probe::Connect(addr) => assert_eq!(addr.to_string(), "1.1.1.1:443"),
+            p => panic!("unexpected probe {:?}", p),
+        }
+        assert!(Probe::parse("tcp://1.1.1.1").is_err());
+        assert!(Probe::parse("https://www.google.com/").is_err());
+        assert!(Probe::parse("www.google.com").is_err());
+    }
+
+    #[test]
+    fn test_prioritize() {
+        let measures = || vec![Measure(0, 120), Measure(1, 100), Measure(2, u128::MAX)];
+
+        let mut m = measures();
+        prioritize(&mut m, None, 50);
+        assert_eq!(m, [Measure(1, 100), Measure(0, 120), Measure(2, u128::MAX)]);
+
+        // Within tolerance.
+        let mut m = measures();
+        prioritize(&mut m, Some(0), 50);
+        assert_eq!(m, [Measure(0, 120), Measure(1, 100), Measure(2, u128::MAX)]);
+
+        let mut m = measures();
+        prioritize(&mut m, Some(0), 10);
+        assert_eq!(m[0], Measure(1, 100));
+
+        // Failed actors don't stay first.
+        let mut m = measures();
+        prioritize(&mut m, Some(2), u128::MAX);
+        assert_eq!(m[0], Measure(1, 100));
+    }
+}
@@ -12,11 +12,13 @@ use tokio::sync::Mutex as TokioMutex;
 use tokio::time::timeout;
 
 use crate::{
-    app::SyncDnsClient,
+    app::{outbound::health::HealthStats, SyncDnsClient},
     proxy::*,
-    session::{Session, SocksAddr},
+    session::Session,
 };
 
+use super::{prioritize, Measure, Probe};
+
 pub struct Handler {
     pub actors: Vec<AnyOutboundHandler>,
     pub fail_timeout: u32,
@@ -26,12 +28,10 @@ pub struct Handler {
     pub dns_client: SyncDnsClient,
 }
 
-#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
-struct Measure(usize, u128); // (index, duration in millis)
-
 async fn health_check_task(
     i: usize,
     h: AnyOutboundHandler,
+    probe: Probe,
     dns_client: SyncDnsClient,
     mut delay: Option<time::Duration>,
 ) -> Measure {
@@ -40,8 +40,18 @@ async fn health_check_task(
     }
     debug!("health checking tcp for [{}] index [{}]", h.tag(), i);
     let measure = async move {
+        let (destination, request) = match probe {
+            Probe::Connect(addr) => (addr, None),
+            Probe::Http { addr, host, path } => (
+                addr,
+                Some(format!(
+                    "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                    path, host
+                )),
+            ),
+        };
         let sess = Session {
-            destination: SocksAddr::Domain("www.google.com".to_string(), 80),
+            destination,
             ..Default::default()
         };
         let start = tokio::time::Instant::now();
@@ -51,7 +61,15 @@ async fn health_check_task(
         };
         match TcpOutboundHandler::handle(h.as_ref(), &sess, stream).await {
             Ok(mut stream) => {
-                if stream.write_all(b"HEAD / HTTP/1.1\r\n\r\n").await.is_err() {
+                let request = match request {
+                    Some(r) => r,
+                    // handshake is ok
+                    None => {
+                        let elapsed = tokio::time::Instant::now().duration_since(start);
+                        return Measure(i, elapsed.as_millis());
+                    }
+                };
+                if stream.write_all(request.as_bytes()).await.is_err() {
                     return Measure(i, u128::MAX - 2); // handshake is ok
                 }
                 let mut buf = vec![0u8; 1];
@@ -87,6 +105,9 @@ impl Handler {
         fallback_cache: bool,
         cache_size: usize,
         cache_timeout: u64, // in minutes
+        probe: Probe,
+        tolerance: u32, // in millis
+        health_stats: Arc<HealthStats>,
         dns_client: SyncDnsClient,
     ) -> (Self, Vec<AbortHandle>) {
         let mut abort_handles = Vec::new();
@@ -115,13 +136,18 @@ impl Handler {
                         checks.push(Box::pin(health_check_task(
                             i,
                             a.clone(),
+                            probe.clone(),
                             dns_client4,
                             delay,
                         )));
                     }
                     let mut measures = futures::future::join_all(checks).await;
+                    for m in measures.iter() {
+                        health_stats.update_tcp(actors2[m.0].tag(), m.latency());
+                    }
 
-                    measures.sort_by(|a, b| a.1.cmp(&b.1));
+                    let current = schedule2.lock().await.first().copied();
+                    prioritize(&mut measures, current, tolerance as u128);
                     trace!("sorted tcp health check results:\n{:#?}", measures);
 
                     let priorities: Vec<String> = measures
@@ -20,11 +20,13 @@ use trust_dns_proto::{
 };
 
 use crate::{
-    app::SyncDnsClient,
+    app::{outbound::health::HealthStats, SyncDnsClient},
     proxy::*,
     session::{Session, SocksAddr},
 };
 
+use super::{prioritize, Measure};
+
 pub struct Handler {
     pub actors: Vec<AnyOutboundHandler>,
     pub fail_timeout: u32,
@@ -33,9 +35,6 @@ pub struct Handler {
     pub dns_client: SyncDnsClient,
 }
 
-#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
-struct Measure(usize, u128); // (index, duration in millis)
-
 async fn health_check_task(
     i: usize,
     h: AnyOutboundHandler,
@@ -112,12 +111,15 @@ async fn health_check_task(
 }
 
 impl Handler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         actors: Vec<AnyOutboundHandler>,
         fail_timeout: u32,
         health_check: bool,
         check_interval: u32,
         failover: bool,
+        tolerance: u32, // in millis
+        health_stats: Arc<HealthStats>,
         dns_client: SyncDnsClient,
     ) -> (Self, Vec<AbortHandle>) {
         let mut abort_handles = Vec::new();
@@ -151,8 +153,12 @@ impl Handler {
                         )));
                     }
                     let mut measures = futures::future::join_all(checks).await;
+                    for m in measures.iter() {
+                        health_stats.update_udp(actors2[m.0].tag(), m.latency());
+                    }
 
-                    measures.sort_by(|a, b| a.1.cmp(&b.1));
+                    let current = schedule2.lock().await.first().copied();
+                    prioritize(&mut measures, current, tolerance as u128);
                     trace!("sorted udp health check results:\n{:#?}", measures);
 
                     let priorities: Vec<String> = measures
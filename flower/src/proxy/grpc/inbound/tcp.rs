@@ -0,0 +1,101 @@
+use std::{io, pin::Pin};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::Stream;
+use futures::{
+    ready,
+    task::{Context, Poll},
+};
+use h2::server::Connection;
+use http::{Response, StatusCode};
+use log::*;
+
+use crate::{proxy::*, session::Session};
+
+use super::{path, stream::GrpcStream};
+
+/// Accepts the calls of an HTTP/2 connection, each call is a proxy stream.
+pub struct Incoming {
+    conn: Connection<AnyStream, Bytes>,
+    sess: Session,
+    path: String,
+}
+
+impl Stream for Incoming {
+    type Item = AnyBaseInboundTransport;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let (req, mut respond) = match ready!(self.conn.poll_accept(cx)) {
+                Some(Ok(v)) => v,
+                Some(Err(e)) => {
+                    debug!("accept grpc call failed: {}", e);
+                    return Poll::Ready(None);
+                }
+                None => return Poll::Ready(None),
+            };
+            if req.uri().path() != self.path {
+                let resp = Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(())
+                    .unwrap();
+                let _ = respond.send_response(resp, true);
+                continue;
+            }
+            let resp = Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/grpc")
+                .body(())
+                .unwrap();
+            let send = match respond.send_response(resp, false) {
+                Ok(send) => send,
+                Err(e) => {
+                    debug!("respond grpc call failed: {}", e);
+                    continue;
+                }
+            };
+            let mut sess = self.sess.clone();
+            sess.stream_id = Some(u32::from(respond.stream_id()) as u64);
+            sess.traffic = Default::default();
+            let stream = GrpcStream::new(req.into_body(), send, true);
+            return Poll::Ready(Some(AnyBaseInboundTransport::Stream(
+                Box::new(stream),
+                sess,
+            )));
+        }
+    }
+}
+
+pub struct Handler {
+    path: String,
+}
+
+impl Handler {
+    pub fn new(service_name: &str) -> Self {
+        Handler {
+            path: path(service_name),
+        }
+    }
+}
+
+#[async_trait]
+impl TcpInboundHandler for Handler {
+    type TStream = AnyStream;
+    type TDatagram = AnyInboundDatagram;
+
+    async fn handle<'a>(
+        &'a self,
+        sess: Session,
+        stream: Self::TStream,
+    ) -> std::io::Result<InboundTransport<Self::TStream, Self::TDatagram>> {
+        let conn = h2::server::handshake(stream).await.map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("accept grpc failed: {}", e))
+        })?;
+        Ok(InboundTransport::Incoming(Box::new(Incoming {
+            conn,
+            sess,
+            path: self.path.clone(),
+        })))
+    }
+}
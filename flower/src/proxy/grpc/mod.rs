@@ -0,0 +1,19 @@
+#[cfg(feature = "inbound-grpc")]
+pub mod inbound;
+#[cfg(feature = "outbound-grpc")]
+pub mod outbound;
+
+mod stream;
+
+pub use stream::GrpcStream;
+
+/// Path of the streaming call carrying the proxy stream, compatible with the
+/// `gun` transport.
+fn path(service_name: &str) -> String {
+    let service_name = if service_name.is_empty() {
+        "GunService"
+    } else {
+        service_name
+    };
+    format!("/{}/Tun", service_name)
+}
@@ -0,0 +1,5 @@
+mod tcp;
+
+pub use tcp::{Handler as TcpHandler, Pool};
+
+use super::{path, stream};
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use h2::client::SendRequest;
+use http::{Method, Request, StatusCode, Version};
+use log::*;
+use tokio::sync::Mutex;
+
+use crate::{
+    app::SyncDnsClient,
+    common::header,
+    proxy::*,
+    session::{Session, SocksAddr},
+};
+
+use super::{path, stream::GrpcStream};
+
+/// Dials the server through the actors and carries the calls of all sessions
+/// over one HTTP/2 connection.
+pub struct Pool {
+    address: String,
+    port: u16,
+    actors: Vec<AnyOutboundHandler>,
+    dns_client: SyncDnsClient,
+    client: Mutex<Option<SendRequest<Bytes>>>,
+}
+
+impl Pool {
+    pub fn new(
+        address: String,
+        port: u16,
+        actors: Vec<AnyOutboundHandler>,
+        dns_client: SyncDnsClient,
+    ) -> Self {
+        Pool {
+            address,
+            port,
+            actors,
+            dns_client,
+            client: Mutex::new(None),
+        }
+    }
+
+    async fn connect(&self, sess: &Session) -> io::Result<SendRequest<Bytes>> {
+        let mut stream = self
+            .new_tcp_stream(self.dns_client.clone(), &self.address, &self.port)
+            .await?;
+        let mut sess = sess.clone();
+        if let Ok(addr) = SocksAddr::try_from((&self.address, self.port)) {
+            sess.destination = addr;
+        }
+        for a in self.actors.iter() {
+            stream = TcpOutboundHandler::handle(a.as_ref(), &sess, Some(stream)).await?;
+            sess.negotiated_alpn = negotiated_alpn(&stream);
+        }
+        handshake(&sess, stream).await
+    }
+
+    /// Returns a client ready to send a request, the pooled connection is
+    /// replaced once it's gone.
+    async fn client(&self, sess: &Session) -> io::Result<SendRequest<Bytes>> {
+        // Held while connecting so that concurrent sessions share the new
+        // connection.
+        let mut pooled = self.client.lock().await;
+        if let Some(client) = pooled.as_ref() {
+            if let Ok(client) = client.clone().ready().await {
+                return Ok(client);
+            }
+            debug!("grpc connection to {}:{} closed", &self.address, self.port);
+        }
+        let client = self.connect(sess).await?;
+        pooled.replace(client.clone());
+        client.ready().await.map_err(h2_err)
+    }
+}
+
+impl TcpConnector for Pool {}
+
+pub struct Handler {
+    pub service_name: String,
+    /// The authority of the request, the `Host` header or the destination is
+    /// used if empty.
+    pub host: String,
+    pub headers: HashMap<String, String>,
+    /// Multiplexes the calls over a pooled connection if set, otherwise each
+    /// call has a connection over the stream of the session.
+    pub pool: Option<Pool>,
+}
+
+// Headers gRPC depends on, they can't be overridden by the template.
+const RESERVED_HEADERS: [&str; 3] = ["host", "content-type", "te"];
+
+impl Handler {
+    fn request(&self, sess: &Session) -> io::Result<Request<()>> {
+        let headers = header::render(&self.headers);
+        let host = if !self.host.is_empty() {
+            self.host.clone()
+        } else if let Some((_, host)) = headers.iter().find(|(k, _)| k.eq_ignore_ascii_case("Host"))
+        {
+            host.to_owned()
+        } else if let Some(pool) = &self.pool {
+            pool.address.clone()
+        } else {
+            sess.destination.host()
+        };
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .version(Version::HTTP_2)
+            .uri(format!("https://{}{}", host, path(&self.service_name)))
+            .header("content-type", "application/grpc")
+            .header("te", "trailers");
+        // A per-outbound User-Agent takes precedence over the global one.
+        if !headers
+            .iter()
+            .any(|(k, _)| k.eq_ignore_ascii_case("User-Agent"))
+        {
+            builder = builder.header("user-agent", &*crate::option::USER_AGENT);
+        }
+        for (k, v) in headers.iter() {
+            if !RESERVED_HEADERS.iter().any(|x| k.eq_ignore_ascii_case(x)) {
+                builder = builder.header(k.as_str(), v.as_str());
+            }
+        }
+        builder
+            .body(())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}
+
+fn h2_err(e: h2::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("grpc request failed: {}", e))
+}
+
+// Starts an HTTP/2 connection over the stream.
+async fn handshake(sess: &Session, stream: AnyStream) -> io::Result<SendRequest<Bytes>> {
+    // A TLS transport below must have negotiated HTTP/2.
+    if let Some(alpn) = sess.negotiated_alpn.as_deref() {
+        if alpn != "h2" {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("grpc requires h2 but {} negotiated", alpn),
+            ));
+        }
+    }
+    let (client, conn) = h2::client::handshake(stream).await.map_err(h2_err)?;
+    // The connection ends once the clients and the streams are dropped.
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            debug!("grpc connection failed: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+#[async_trait]
+impl TcpOutboundHandler for Handler {
+    type Stream = AnyStream;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        if self.pool.is_some() {
+            Some(OutboundConnect::NoConnect)
+        } else {
+            None
+        }
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        stream: Option<Self::Stream>,
+    ) -> io::Result<Self::Stream> {
+        let req = self.request(sess)?;
+        let mut client = if let Some(pool) = &self.pool {
+            pool.client(sess).await?
+        } else {
+            let stream =
+                stream.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid input"))?;
+            let client = handshake(sess, stream).await?;
+            client.ready().await.map_err(h2_err)?
+        };
+        let (resp, send) = client.send_request(req, false).map_err(h2_err)?;
+        let resp = resp.await.map_err(h2_err)?;
+        if resp.status() != StatusCode::OK {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("grpc request failed: {}", resp.status()),
+            ));
+        }
+        let recv = resp.into_body();
+        Ok(Box::new(GrpcStream::new(recv, send, false)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        let sess = Session {
+            destination: crate::session::SocksAddr::Domain("dest.com".to_string(), 443),
+            ..Default::default()
+        };
+        let mut handler = Handler {
+            service_name: "flower".to_string(),
+            host: "".to_string(),
+            headers: HashMap::new(),
+            pool: None,
+        };
+        let req = handler.request(&sess).unwrap();
+        assert_eq!(req.uri(), "https://dest.com/flower/Tun");
+        assert_eq!(req.headers()["content-type"], "application/grpc");
+        assert_eq!(req.headers()["user-agent"], *crate::option::USER_AGENT);
+
+        handler.headers = [
+            ("Host", "header.com"),
+            ("User-Agent", "Mozilla/5.0 (X11)"),
+            ("Content-Type", "text/plain"),
+            ("X-Request-Id", "{uuid}"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let req = handler.request(&sess).unwrap();
+        assert_eq!(req.uri(), "https://header.com/flower/Tun");
+        assert_eq!(req.headers()["user-agent"], "Mozilla/5.0 (X11)");
+        assert_eq!(req.headers().get_all("user-agent").iter().count(), 1);
+        assert_eq!(req.headers()["content-type"], "application/grpc");
+        assert_eq!(req.headers()["x-request-id"].len(), 36);
+        assert!(req.headers().get("host").is_none());
+
+        handler.host = "example.com".to_string();
+        let req = handler.request(&sess).unwrap();
+        assert_eq!(req.uri(), "https://example.com/flower/Tun");
+    }
+
+    #[cfg(feature = "inbound-grpc")]
+    #[test]
+    fn test_pool() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use futures::StreamExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        use crate::config;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let accepted2 = accepted.clone();
+            // Echoes the payload of each call.
+            tokio::spawn(async move {
+                let inbound = crate::proxy::grpc::inbound::TcpHandler::new("flower");
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    accepted2.fetch_add(1, Ordering::SeqCst);
+                    let mut incoming = match TcpInboundHandler::handle(
+                        &inbound,
+                        Session::default(),
+                        Box::new(stream),
+                    )
+                    .await
+                    .unwrap()
+                    {
+                        InboundTransport::Incoming(incoming) => incoming,
+                        _ => panic!("unexpected transport"),
+                    };
+                    tokio::spawn(async move {
+                        while let Some(BaseInboundTransport::Stream(stream, _)) =
+                            incoming.next().await
+                        {
+                            tokio::spawn(async move {
+                                let (mut r, mut w) = tokio::io::split(stream);
+                                let _ = tokio::io::copy(&mut r, &mut w).await;
+                                let _ = w.shutdown().await;
+                            });
+                        }
+                    });
+                }
+            });
+
+            let mut dns = config::Dns::new();
+            dns.servers.push("127.0.0.1".to_string());
+            let dns_client = Arc::new(tokio::sync::RwLock::new(
+                crate::app::dns_client::DnsClient::new(&protobuf::SingularPtrField::some(dns))
+                    .unwrap(),
+            ));
+            let handler = Handler {
+                service_name: "flower".to_string(),
+                host: "".to_string(),
+                headers: HashMap::new(),
+                pool: Some(Pool::new("127.0.0.1".to_string(), port, vec![], dns_client)),
+            };
+            assert!(matches!(
+                handler.connect_addr(),
+                Some(OutboundConnect::NoConnect)
+            ));
+            let sess = Session::default();
+            for msg in [&b"hello"[..], &b"world"[..]] {
+                let mut stream = handler.handle(&sess, None).await.unwrap();
+                stream.write_all(msg).await.unwrap();
+                let mut buf = [0u8; 5];
+                stream.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf[..], msg);
+            }
+            // Both calls are carried by the pooled connection.
+            assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        });
+    }
+}
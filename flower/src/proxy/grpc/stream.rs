@@ -0,0 +1,278 @@
+use std::cmp::min;
+use std::io;
+use std::pin::Pin;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{
+    ready,
+    task::{Context, Poll},
+};
+use h2::{RecvStream, SendStream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+// Payload carried by a single message.
+const MAX_MESSAGE_PAYLOAD: usize = 0x4000;
+// Largest message accepted, peers may send larger messages than we do but
+// the buffer of an incomplete message must be bounded.
+const MAX_MESSAGE_LEN: usize = 4 * MAX_MESSAGE_PAYLOAD;
+
+fn h2_err(e: h2::Error) -> io::Error {
+    if e.is_io() {
+        e.into_io().unwrap()
+    } else {
+        io::Error::new(io::ErrorKind::Other, e)
+    }
+}
+
+fn invalid_message() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid grpc message")
+}
+
+fn broken_pipe() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "grpc stream closed")
+}
+
+fn put_varint(buf: &mut BytesMut, mut n: usize) {
+    while n >= 0x80 {
+        buf.put_u8((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.put_u8(n as u8);
+}
+
+// Returns the value and its size, or None if the buffer is too short.
+fn get_varint(buf: &[u8]) -> io::Result<Option<(usize, usize)>> {
+    let mut n = 0usize;
+    for (i, b) in buf.iter().enumerate().take(10) {
+        n |= ((b & 0x7f) as usize) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok(Some((n, i + 1)));
+        }
+    }
+    if buf.len() >= 10 {
+        return Err(invalid_message());
+    }
+    Ok(None)
+}
+
+/// Encodes the payload as a message, i.e. a length-prefixed `Hunk { bytes
+/// data = 1; }`.
+pub fn encode_message(buf: &mut BytesMut, payload: &[u8]) {
+    let mut len_buf = BytesMut::with_capacity(10);
+    put_varint(&mut len_buf, payload.len());
+    buf.put_u8(0); // uncompressed
+    buf.put_u32((1 + len_buf.len() + payload.len()) as u32);
+    buf.put_u8(0x0a); // field 1, length-delimited
+    buf.put_slice(&len_buf);
+    buf.put_slice(payload);
+}
+
+/// Decodes a message from the start of the buffer, returns the payload, or
+/// None if the message is incomplete.
+pub fn decode_message(buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+    if buf.len() < 5 {
+        return Ok(None);
+    }
+    if buf[0] != 0 {
+        // Compression is never negotiated.
+        return Err(invalid_message());
+    }
+    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("grpc message too large: {}", len),
+        ));
+    }
+    if buf.len() < 5 + len {
+        return Ok(None);
+    }
+    buf.advance(5);
+    let mut msg = buf.split_to(len);
+    let mut payload = BytesMut::new();
+    // Unknown fields are skipped, the data field may repeat.
+    while !msg.is_empty() {
+        let (key, n) = get_varint(&msg)?.ok_or_else(invalid_message)?;
+        msg.advance(n);
+        let size = match key & 0x7 {
+            0 => get_varint(&msg)?.ok_or_else(invalid_message)?.1,
+            1 => 8,
+            2 => {
+                let (size, n) = get_varint(&msg)?.ok_or_else(invalid_message)?;
+                msg.advance(n);
+                size
+            }
+            5 => 4,
+            _ => return Err(invalid_message()),
+        };
+        if msg.len() < size {
+            return Err(invalid_message());
+        }
+        let field = msg.split_to(size);
+        if key == 0x0a {
+            payload.extend_from_slice(&field);
+        }
+    }
+    Ok(Some(payload.freeze()))
+}
+
+/// A byte stream carried by the messages of a bidirectional streaming call.
+pub struct GrpcStream {
+    recv: RecvStream,
+    send: SendStream<Bytes>,
+    // Whether the stream is the server side, which ends with trailers.
+    server: bool,
+    // Received data not yet decoded.
+    recv_buf: BytesMut,
+    // Decoded payload not yet read.
+    read_buf: Bytes,
+    // Encoded messages not yet sent.
+    send_buf: BytesMut,
+    // Whether the send side has been ended.
+    shutdown: bool,
+}
+
+impl GrpcStream {
+    pub fn new(recv: RecvStream, send: SendStream<Bytes>, server: bool) -> Self {
+        GrpcStream {
+            recv,
+            send,
+            server,
+            recv_buf: BytesMut::new(),
+            read_buf: Bytes::new(),
+            send_buf: BytesMut::new(),
+            shutdown: false,
+        }
+    }
+
+    fn poll_send_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.send_buf.is_empty() {
+            self.send.reserve_capacity(self.send_buf.len());
+            // Capacity left from previous reservations is not notified.
+            let mut n = self.send.capacity();
+            while n == 0 {
+                n = match ready!(self.send.poll_capacity(cx)) {
+                    Some(Ok(n)) => n,
+                    Some(Err(e)) => return Poll::Ready(Err(h2_err(e))),
+                    None => return Poll::Ready(Err(broken_pipe())),
+                };
+            }
+            let data = self.send_buf.split_to(min(n, self.send_buf.len()));
+            self.send.send_data(data.freeze(), false).map_err(h2_err)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for GrpcStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if !self.read_buf.is_empty() {
+                let n = min(buf.remaining(), self.read_buf.len());
+                buf.put_slice(&self.read_buf.split_to(n));
+                return Poll::Ready(Ok(()));
+            }
+            if let Some(payload) = decode_message(&mut self.recv_buf)? {
+                self.read_buf = payload;
+                continue;
+            }
+            match ready!(self.recv.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    let _ = self.recv.flow_control().release_capacity(data.len());
+                    self.recv_buf.extend_from_slice(&data);
+                }
+                Some(Err(e)) => return Poll::Ready(Err(h2_err(e))),
+                None => {
+                    if !self.recv_buf.is_empty() {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "incomplete grpc message",
+                        )));
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for GrpcStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        // Applies backpressure by sending previous writes first.
+        ready!(self.poll_send_buf(cx))?;
+        let n = min(buf.len(), MAX_MESSAGE_PAYLOAD);
+        encode_message(&mut self.send_buf, &buf[..n]);
+        // Starts sending, the rest goes in subsequent writes or flushes.
+        let _ = self.poll_send_buf(cx)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_send_buf(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.shutdown {
+            return Poll::Ready(Ok(()));
+        }
+        ready!(self.poll_send_buf(cx))?;
+        if self.server {
+            let mut trailers = http::HeaderMap::new();
+            trailers.insert("grpc-status", http::HeaderValue::from_static("0"));
+            self.send.send_trailers(trailers).map_err(h2_err)?;
+        } else {
+            self.send.send_data(Bytes::new(), true).map_err(h2_err)?;
+        }
+        self.shutdown = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        for size in [0, 1, 127, 128, 300, MAX_MESSAGE_PAYLOAD] {
+            let payload = vec![7u8; size];
+            let mut buf = BytesMut::new();
+            encode_message(&mut buf, &payload);
+            encode_message(&mut buf, b"next");
+            let mut partial = BytesMut::from(&buf[..buf.len() - 5]);
+            assert_eq!(
+                decode_message(&mut partial).unwrap().unwrap(),
+                Bytes::from(payload.clone())
+            );
+            assert!(decode_message(&mut partial).unwrap().is_none());
+            assert_eq!(decode_message(&mut buf).unwrap().unwrap(), payload);
+            assert_eq!(decode_message(&mut buf).unwrap().unwrap(), "next");
+            assert!(buf.is_empty());
+        }
+
+        // Unknown fields are skipped.
+        let mut buf =
+            BytesMut::from(&[0, 0, 0, 0, 7, 0x10, 0x01, 0x0a, 0x03, b'a', b'b', b'c'][..]);
+        assert_eq!(decode_message(&mut buf).unwrap().unwrap(), "abc");
+
+        let mut buf = BytesMut::from(&[1, 0, 0, 0, 0][..]);
+        assert!(decode_message(&mut buf).is_err());
+
+        // The length is checked before the message is buffered.
+        let mut buf = BytesMut::new();
+        buf.put_u8(0);
+        buf.put_u32(MAX_MESSAGE_LEN as u32 + 1);
+        assert!(decode_message(&mut buf).is_err());
+    }
+}
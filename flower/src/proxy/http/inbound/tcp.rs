@@ -1,73 +1,360 @@
+use std::cmp::min;
 use std::convert::TryFrom;
 use std::io;
-use std::{net::IpAddr, pin::Pin, task::Poll};
+use std::net::IpAddr;
+use std::pin::Pin;
 
-use anyhow::Result;
 use async_trait::async_trait;
-use futures::future::{self, Future};
-use hyper::{server::conn::Http, service::Service, Body, Request, Response, Client, Method};
+use bytes::{Buf, BufMut, BytesMut};
+use futures::{
+    ready,
+    task::{Context, Poll},
+};
 use log::*;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 
 use crate::{
-    proxy::*,
+    proxy::{stream::PrefixedProxyStream, *},
     session::{Session, SocksAddr},
-    proxy::{
-        stream::SimpleProxyStream,
-    },
 };
 
-struct ProxyService {
-    uri: String,
+// Upper bound of a request header.
+const MAX_REQUEST_HEADER_SIZE: usize = 16 * 1024;
+
+// Headers concerning the client-proxy hop, dropped from forwarded requests,
+// along with the headers listed in the Connection header. The framing of the
+// body is kept, it's forwarded as is.
+const HOP_HEADERS: [&str; 7] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "upgrade",
+];
+
+enum Request {
+    /// A CONNECT request.
+    Tunnel(SocksAddr),
+    /// A request in absolute-form, along with the request header rewritten
+    /// to origin-form and the framing of the body following it.
+    Forward(SocksAddr, BytesMut, Body),
+}
+
+/// How the body of a request is delimited.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Body {
+    /// The number of bytes left.
+    Length(u64),
+    Chunked(Chunk),
+}
+
+/// The position in a chunked body.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Chunk {
+    Size,
+    /// The bytes left of the chunk data, including the trailing CRLF.
+    Data(u64),
+    Trailer,
+}
+
+fn invalid_request(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid request: {}", msg),
+    )
 }
 
-impl ProxyService {
-    pub fn new() -> Self {
-        ProxyService {
-            uri: "".to_string(),
+// Parses `host:port`, or `host` if a default port is given. IPv6 hosts are
+// enclosed in brackets.
+fn parse_authority(authority: &str, default_port: Option<u16>) -> Option<SocksAddr> {
+    if let Some(rest) = authority.strip_prefix('[') {
+        let end = rest.find(']')?;
+        let ip = rest[..end].parse::<IpAddr>().ok()?;
+        let port = match &rest[end + 1..] {
+            "" => default_port?,
+            p => p.strip_prefix(':')?.parse().ok()?,
+        };
+        return Some(SocksAddr::from((ip, port)));
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, default_port?),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(SocksAddr::from((ip, port)));
+    }
+    SocksAddr::try_from((host, port)).ok()
+}
+
+// Parses a request header, including the terminating empty line.
+fn parse_request(header: &[u8]) -> io::Result<Request> {
+    let header = std::str::from_utf8(header).map_err(|_| invalid_request("not utf-8"))?;
+    let mut lines = header.split("\r\n");
+    let request_line = lines.next().unwrap_or("");
+    let parts: Vec<&str> = request_line.split(' ').collect();
+    if parts.len() != 3 || parts[0].is_empty() || !parts[2].starts_with("HTTP/1.") {
+        return Err(invalid_request(request_line));
+    }
+    let (method, target, version) = (parts[0], parts[1], parts[2]);
+
+    if method == "CONNECT" {
+        return parse_authority(target, None)
+            .map(Request::Tunnel)
+            .ok_or_else(|| invalid_request(request_line));
+    }
+
+    // Requests to a proxy carry the target in absolute-form.
+    if target.len() < 7 || !target[..7].eq_ignore_ascii_case("http://") {
+        return Err(invalid_request(request_line));
+    }
+    let rest = &target[7..];
+    let (authority, path) = match rest.find(|c: char| c == '/' || c == '?') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    // Strips the userinfo, if any.
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    let destination =
+        parse_authority(authority, Some(80)).ok_or_else(|| invalid_request(request_line))?;
+
+    let headers: Vec<(&str, &str)> = lines
+        .filter(|l| !l.is_empty())
+        .map(|l| match l.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (l.trim(), ""),
+        })
+        .collect();
+    let mut hop_headers: Vec<&str> = HOP_HEADERS.to_vec();
+    let mut close = false;
+    for (name, value) in headers.iter() {
+        if name.eq_ignore_ascii_case("connection") || name.eq_ignore_ascii_case("proxy-connection")
+        {
+            for token in value.split(',').map(str::trim) {
+                close |= token.eq_ignore_ascii_case("close");
+                hop_headers.push(token);
+            }
         }
     }
 
-    pub fn get_uri(&self) -> &String {
-        &self.uri
+    let mut head = BytesMut::with_capacity(header.len());
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    head.put_slice(format!("{} {} {}\r\n", method, path, version).as_bytes());
+    let mut has_host = false;
+    let mut content_length = None;
+    let mut chunked = false;
+    for (name, value) in headers.iter() {
+        if hop_headers.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+            continue;
+        }
+        if name.eq_ignore_ascii_case("host") {
+            has_host = true;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            // The chunked coding is the last one applied, if any.
+            let last = value.rsplit(',').next().unwrap_or("").trim();
+            if !last.eq_ignore_ascii_case("chunked") {
+                return Err(invalid_request("unknown body length"));
+            }
+            chunked = true;
+        } else if name.eq_ignore_ascii_case("content-length") {
+            let len = value
+                .parse()
+                .map_err(|_| invalid_request("invalid content length"))?;
+            if content_length.map_or(false, |prev| prev != len) {
+                return Err(invalid_request("conflicting content lengths"));
+            }
+            content_length = Some(len);
+        }
+        head.put_slice(format!("{}: {}\r\n", name, value).as_bytes());
     }
+    // A request delimited both ways could be read differently by the origin.
+    let body = match (chunked, content_length) {
+        (true, Some(_)) => return Err(invalid_request("ambiguous body length")),
+        (true, None) => Body::Chunked(Chunk::Size),
+        (false, len) => Body::Length(len.unwrap_or(0)),
+    };
+    if !has_host {
+        head.put_slice(format!("Host: {}\r\n", authority).as_bytes());
+    }
+    if close {
+        head.put_slice(b"Connection: close\r\n");
+    }
+    head.put_slice(b"\r\n");
+    Ok(Request::Forward(destination, head, body))
+}
+
+fn find_header_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
+
+fn find_line_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(2).position(|w| w == b"\r\n").map(|i| i + 2)
+}
+
+/// Forwards the requests read from the client to the origin of the first
+/// one, each rewritten to origin-form. The stream ends before a request to
+/// another origin, the client has to send it on a new connection.
+struct ForwardStream<T> {
+    inner: T,
+    destination: SocksAddr,
+    // Data read from the client, yet to be rewritten.
+    buf: BytesMut,
+    // Data rewritten, yet to be read.
+    out: BytesMut,
+    // The body being forwarded, none if a request header is expected.
+    body: Option<Body>,
+    done: bool,
 }
 
-#[allow(clippy::type_complexity)]
-impl Service<Request<Body>> for ProxyService {
-    type Error = Box<dyn std::error::Error + Send + Sync>;
-    type Future = Pin<Box<dyn Future<Output=Result<Self::Response, Self::Error>> + Send>>;
-    type Response = Response<Body>;
-
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
-        self.uri = req.uri().to_string();
-
-        // if req.method() == Method::CONNECT {
-        //     Box::pin(future::ready(Ok(Response::builder()
-        //         .status(200)
-        //         .body(hyper::Body::empty())
-        //         .unwrap())))
-        // } else {
-        //     let client = Client::builder()
-        //         .http1_title_case_headers(true)
-        //         .http1_preserve_header_case(true)
-        //         .build_http();
-        //     Box::pin(async move { Ok(client.clone().request(req).await.unwrap()) })
-        // }
-
-        Box::pin(future::ready(Ok(Response::builder()
-            .status(200)
-            .body(hyper::Body::empty())
-            .unwrap())))
-    }
-
-    fn poll_ready(
-        &mut self,
-        _cx: &mut std::task::Context<'_>,
-    ) -> Poll<std::result::Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
+impl<T> ForwardStream<T> {
+    fn new(inner: T, destination: SocksAddr, head: BytesMut, body: Body, buf: BytesMut) -> Self {
+        ForwardStream {
+            inner,
+            destination,
+            buf,
+            out: head,
+            body: Some(body),
+            done: false,
+        }
+    }
+
+    // Moves up to `len` bytes of a body to the output, returns the number of
+    // bytes left, none if there's nothing buffered.
+    fn forward(&mut self, len: u64) -> Option<u64> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let n = min(len, self.buf.len() as u64);
+        self.out.extend_from_slice(&self.buf.split_to(n as usize));
+        Some(len - n)
+    }
+
+    // Moves the next piece of the buffered data to the output, returns false
+    // if more data has to be read first.
+    fn advance(&mut self) -> io::Result<bool> {
+        match self.body {
+            None => {
+                let n = match find_header_end(&self.buf) {
+                    Some(n) => n,
+                    None if self.buf.len() >= MAX_REQUEST_HEADER_SIZE => {
+                        return Err(invalid_request("header too large"));
+                    }
+                    None => return Ok(false),
+                };
+                match parse_request(&self.buf[..n])? {
+                    Request::Forward(dst, head, body) if dst == self.destination => {
+                        self.buf.advance(n);
+                        self.out.extend_from_slice(&head);
+                        self.body = Some(body);
+                    }
+                    _ => self.done = true,
+                }
+            }
+            Some(Body::Length(0)) => self.body = None,
+            Some(Body::Chunked(Chunk::Data(0))) => self.body = Some(Body::Chunked(Chunk::Size)),
+            Some(Body::Length(len)) => match self.forward(len) {
+                Some(left) => self.body = Some(Body::Length(left)),
+                None => return Ok(false),
+            },
+            Some(Body::Chunked(Chunk::Data(len))) => match self.forward(len) {
+                Some(left) => self.body = Some(Body::Chunked(Chunk::Data(left))),
+                None => return Ok(false),
+            },
+            Some(Body::Chunked(chunk)) => {
+                let n = match find_line_end(&self.buf) {
+                    Some(n) => n,
+                    None if self.buf.len() >= MAX_REQUEST_HEADER_SIZE => {
+                        return Err(invalid_request("chunk line too large"));
+                    }
+                    None => return Ok(false),
+                };
+                let line = self.buf.split_to(n);
+                self.body = if chunk == Chunk::Trailer {
+                    // The body ends with an empty line after the trailers.
+                    if n == 2 {
+                        None
+                    } else {
+                        Some(Body::Chunked(Chunk::Trailer))
+                    }
+                } else {
+                    let size = std::str::from_utf8(&line[..n - 2])
+                        .ok()
+                        .and_then(|l| u64::from_str_radix(l.split(';').next()?.trim(), 16).ok())
+                        .ok_or_else(|| invalid_request("invalid chunk size"))?;
+                    match size {
+                        0 => Some(Body::Chunked(Chunk::Trailer)),
+                        size => Some(Body::Chunked(Chunk::Data(
+                            size.checked_add(2)
+                                .ok_or_else(|| invalid_request("invalid chunk size"))?,
+                        ))),
+                    }
+                };
+                self.out.extend_from_slice(&line);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for ForwardStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let me = &mut *self;
+        loop {
+            if !me.out.is_empty() {
+                let n = min(buf.remaining(), me.out.len());
+                buf.put_slice(&me.out.split_to(n));
+                return Poll::Ready(Ok(()));
+            }
+            if me.done {
+                return Poll::Ready(Ok(()));
+            }
+            if me.advance()? {
+                continue;
+            }
+            let mut data = [0u8; 8 * 1024];
+            let mut data = ReadBuf::new(&mut data);
+            ready!(Pin::new(&mut me.inner).poll_read(cx, &mut data))?;
+            if data.filled().is_empty() {
+                me.done = true;
+            }
+            me.buf.extend_from_slice(data.filled());
+        }
     }
 }
 
+impl<T: AsyncWrite + Unpin> AsyncWrite for ForwardStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Handler of HTTP proxy requests, CONNECT requests are tunneled, requests
+/// in absolute-form are forwarded in origin-form to the target, along with
+/// the requests following them to the same target.
 pub struct Handler;
 
 #[async_trait]
@@ -78,50 +365,224 @@ impl TcpInboundHandler for Handler {
     async fn handle<'a>(
         &'a self,
         mut sess: Session,
-        stream: Box<dyn ProxyStream>,
+        mut stream: Self::TStream,
     ) -> std::io::Result<InboundTransport<Self::TStream, Self::TDatagram>> {
-        let http = Http::new();
-        let proxy_service = ProxyService::new();
-        let conn = http
-            .serve_connection(stream, proxy_service)
-            .without_shutdown();
-        let parts = match conn.await {
-            Ok(v) => v,
-            Err(err) => {
-                debug!("accept conn failed: {}", err);
-                return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
+        // The header may arrive in pieces, and data may follow the header in
+        // the same read.
+        let mut buf = BytesMut::with_capacity(1024);
+        let header_len = loop {
+            if let Some(n) = find_header_end(&buf) {
+                break n;
+            }
+            if buf.len() >= MAX_REQUEST_HEADER_SIZE {
+                debug!("request header from {} too large", &sess.source);
+                return Err(invalid_request("header too large"));
+            }
+            if stream.read_buf(&mut buf).await? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "incomplete request header",
+                ));
             }
         };
+        let header = buf.split_to(header_len);
+
+        match parse_request(&header) {
+            Ok(Request::Tunnel(destination)) => {
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                    .await?;
+                sess.destination = destination;
+                Ok(InboundTransport::Stream(
+                    Box::new(PrefixedProxyStream::new(stream, buf)),
+                    sess,
+                ))
+            }
+            Ok(Request::Forward(destination, head, body)) => {
+                sess.destination = destination.clone();
+                Ok(InboundTransport::Stream(
+                    Box::new(ForwardStream::new(stream, destination, head, body, buf)),
+                    sess,
+                ))
+            }
+            Err(e) => {
+                debug!("{} from {}", e, &sess.source);
+                let _ = stream
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n")
+                    .await;
+                let _ = stream.shutdown().await;
+                Err(e)
+            }
+        }
+    }
+}
 
-        let uri = parts.service.get_uri();
-        let host_port: Vec<&str> = uri.split(':').collect();
-        if host_port.len() != 2 {
-            debug!("invalid target {:?}", uri);
-            return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forward(header: &str) -> (SocksAddr, String, Body) {
+        match parse_request(header.as_bytes()).unwrap() {
+            Request::Forward(dst, head, body) => {
+                (dst, String::from_utf8(head.to_vec()).unwrap(), body)
+            }
+            Request::Tunnel(_) => panic!("not a forward request"),
         }
+    }
 
-        let destination = if let Ok(port) = host_port[1].parse::<u16>() {
-            if let Ok(ip) = host_port[0].parse::<IpAddr>() {
-                SocksAddr::from((ip, port))
-            } else {
-                match SocksAddr::try_from((host_port[0], port)) {
-                    Ok(v) => v,
-                    Err(err) => {
-                        debug!("invalid target {:?}: {}", uri, err);
-                        return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
-                    }
+    #[test]
+    fn test_parse_request() {
+        match parse_request(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n")
+            .unwrap()
+        {
+            Request::Tunnel(dst) => assert_eq!(dst.to_string(), "example.com:443"),
+            Request::Forward(..) => panic!("not a connect request"),
+        }
+        match parse_request(b"CONNECT [::1]:8443 HTTP/1.1\r\n\r\n").unwrap() {
+            Request::Tunnel(dst) => assert_eq!(dst.to_string(), "[::1]:8443"),
+            Request::Forward(..) => panic!("not a connect request"),
+        }
+
+        let (dst, head, body) = forward(
+            "GET http://example.com/a?b=1 HTTP/1.1\r\nHost: example.com\r\nProxy-Connection: keep-alive\r\nAccept: */*\r\n\r\n",
+        );
+        assert_eq!(dst.to_string(), "example.com:80");
+        assert_eq!(
+            head,
+            "GET /a?b=1 HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n"
+        );
+        assert_eq!(body, Body::Length(0));
+        let (dst, head, _) = forward("POST http://user@1.2.3.4:8080?q HTTP/1.0\r\n\r\n");
+        assert_eq!(dst.to_string(), "1.2.3.4:8080");
+        assert_eq!(head, "POST /?q HTTP/1.0\r\nHost: 1.2.3.4:8080\r\n\r\n");
+
+        // Hop-by-hop headers are dropped, including the ones listed in the
+        // Connection header.
+        let (_, head, body) = forward(
+            "PUT http://example.com/ HTTP/1.1\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\nConnection: close, X-Hop\r\nX-Hop: 1\r\nTE: trailers\r\nUpgrade: websocket\r\nKeep-Alive: timeout=5\r\nContent-Length: 3\r\n\r\n",
+        );
+        assert_eq!(
+            head,
+            "PUT / HTTP/1.1\r\nContent-Length: 3\r\nHost: example.com\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(body, Body::Length(3));
+        let (_, _, body) = forward(
+            "POST http://example.com/ HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n",
+        );
+        assert_eq!(body, Body::Chunked(Chunk::Size));
+
+        for header in [
+            "GARBAGE\r\n\r\n",
+            "GET /index.html HTTP/1.1\r\n\r\n",
+            "GET https://example.com/ HTTP/1.1\r\n\r\n",
+            "GET http://example.com/ HTTP/2\r\n\r\n",
+            "GET  http://example.com/ HTTP/1.1\r\n\r\n",
+            "CONNECT example.com HTTP/1.1\r\n\r\n",
+            "CONNECT example.com:https HTTP/1.1\r\n\r\n",
+            "CONNECT [example.com]:443 HTTP/1.1\r\n\r\n",
+            "POST http://example.com/ HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "POST http://example.com/ HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+            "POST http://example.com/ HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            "POST http://example.com/ HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+        ] {
+            assert!(parse_request(header.as_bytes()).is_err(), "{}", header);
+        }
+        assert!(parse_request(b"GET http://\xff/ HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_handle() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            // The header arrives in pieces, followed by pipelined data.
+            let (client, mut peer) = tokio::io::duplex(1024);
+            peer.write_all(b"CONNECT example.com:443 HT").await.unwrap();
+            let peer_task = async move {
+                tokio::task::yield_now().await;
+                peer.write_all(b"TP/1.1\r\n\r\nhello").await.unwrap();
+                peer
+            };
+            let (res, mut peer) = futures::future::join(
+                Handler.handle(Session::default(), Box::new(client)),
+                peer_task,
+            )
+            .await;
+            let mut stream = match res.unwrap() {
+                InboundTransport::Stream(stream, sess) => {
+                    assert_eq!(sess.destination.to_string(), "example.com:443");
+                    stream
                 }
-            }
-        } else {
-            debug!("invalid target {:?}", uri);
-            return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
-        };
+                _ => panic!("not a stream"),
+            };
+            let reply = b"HTTP/1.1 200 Connection Established\r\n\r\n";
+            let mut buf = vec![0u8; reply.len()];
+            peer.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf[..], &reply[..]);
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+
+            // The rewritten header and the body are forwarded.
+            let (client, mut peer) = tokio::io::duplex(1024);
+            peer.write_all(b"POST http://example.com/submit HTTP/1.1\r\nHost: example.com\r\nContent-Length: 3\r\n\r\nabc")
+                .await
+                .unwrap();
+            drop(peer);
+            let mut stream = match Handler
+                .handle(Session::default(), Box::new(client))
+                .await
+                .unwrap()
+            {
+                InboundTransport::Stream(stream, sess) => {
+                    assert_eq!(sess.destination.to_string(), "example.com:80");
+                    stream
+                }
+                _ => panic!("not a stream"),
+            };
+            let mut forwarded = String::new();
+            stream.read_to_string(&mut forwarded).await.unwrap();
+            assert_eq!(
+                forwarded,
+                "POST /submit HTTP/1.1\r\nHost: example.com\r\nContent-Length: 3\r\n\r\nabc"
+            );
 
-        sess.destination = destination;
+            // Pipelined requests are forwarded until one to another target,
+            // the stream ends even though the client is still connected.
+            let (client, mut peer) = tokio::io::duplex(1024);
+            peer.write_all(b"POST http://example.com/a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3;x=y\r\nabc\r\n0\r\nX-Trailer: 1\r\n\r\n")
+                .await
+                .unwrap();
+            peer.write_all(b"GET http://example.com/b HTTP/1.1\r\nProxy-Authorization: Basic eA==\r\n\r\nGET http://example.com:8080/c HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap();
+            let mut stream = match Handler
+                .handle(Session::default(), Box::new(client))
+                .await
+                .unwrap()
+            {
+                InboundTransport::Stream(stream, _) => stream,
+                _ => panic!("not a stream"),
+            };
+            let mut forwarded = String::new();
+            stream.read_to_string(&mut forwarded).await.unwrap();
+            assert_eq!(
+                forwarded,
+                "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\nHost: example.com\r\n\r\n3;x=y\r\nabc\r\n0\r\nX-Trailer: 1\r\n\r\nGET /b HTTP/1.1\r\nHost: example.com\r\n\r\n"
+            );
+            drop(peer);
 
-        Ok(InboundTransport::Stream(
-            Box::new(SimpleProxyStream(parts.io)),
-            sess,
-        ))
+            // Malformed requests are answered with 400 and closed.
+            let (client, mut peer) = tokio::io::duplex(1024);
+            peer.write_all(b"HELLO\r\n\r\n").await.unwrap();
+            assert!(Handler
+                .handle(Session::default(), Box::new(client))
+                .await
+                .is_err());
+            let mut reply = String::new();
+            peer.read_to_string(&mut reply).await.unwrap();
+            assert!(reply.starts_with("HTTP/1.1 400 "));
+        });
     }
 }
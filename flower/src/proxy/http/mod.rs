@@ -1,2 +1,4 @@
 #[cfg(feature = "inbound-http")]
 pub mod inbound;
+#[cfg(feature = "outbound-http")]
+pub mod outbound;
@@ -0,0 +1,3 @@
+pub mod tcp;
+
+pub use tcp::Handler as TcpHandler;
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::io;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::{common::header, proxy::*, session::Session};
+
+// Upper bound of the response header of a CONNECT request.
+const MAX_RESPONSE_HEADER_SIZE: usize = 8 * 1024;
+
+/// Handler of HTTP proxies supporting the CONNECT method.
+pub struct Handler {
+    pub address: String,
+    pub port: u16,
+    /// Credentials for basic authentication, no authentication if the
+    /// username is empty.
+    pub username: String,
+    pub password: String,
+    /// Extra headers of the CONNECT request, `Host` is always the target.
+    pub headers: HashMap<String, String>,
+}
+
+impl Handler {
+    fn connect_request(&self, sess: &Session) -> String {
+        let target = sess.destination.to_string();
+        let mut req = format!(
+            "CONNECT {} HTTP/1.1\r\nHost: {}\r\nProxy-Connection: Keep-Alive\r\n",
+            &target, &target
+        );
+        if !self.username.is_empty() {
+            let credentials = base64::encode(format!("{}:{}", &self.username, &self.password));
+            req.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+        }
+        for (k, v) in header::render(&self.headers) {
+            if k.eq_ignore_ascii_case("Host")
+                || k.eq_ignore_ascii_case("Proxy-Connection")
+                || (!self.username.is_empty() && k.eq_ignore_ascii_case("Proxy-Authorization"))
+            {
+                continue;
+            }
+            req.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        req.push_str("\r\n");
+        req
+    }
+}
+
+fn parse_status_line(line: &[u8]) -> io::Result<()> {
+    let line = String::from_utf8_lossy(line);
+    let mut parts = line.trim_end().splitn(3, ' ');
+    let version = parts.next().unwrap_or("");
+    if !version.starts_with("HTTP/1.") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid http proxy response: {}", line.trim_end()),
+        ));
+    }
+    let code = parts
+        .next()
+        .and_then(|x| x.parse::<u16>().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid http proxy response: {}", line.trim_end()),
+            )
+        })?;
+    if code != 200 {
+        let reason = parts.next().unwrap_or("").trim();
+        let kind = match code {
+            407 => io::ErrorKind::PermissionDenied,
+            _ => io::ErrorKind::ConnectionRefused,
+        };
+        return Err(io::Error::new(
+            kind,
+            format!("http proxy connect failed: {} {}", code, reason),
+        ));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl TcpOutboundHandler for Handler {
+    type Stream = AnyStream;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        Some(OutboundConnect::Proxy(self.address.clone(), self.port))
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        stream: Option<Self::Stream>,
+    ) -> io::Result<Self::Stream> {
+        let mut stream =
+            stream.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid input"))?;
+        stream
+            .write_all(self.connect_request(sess).as_bytes())
+            .await?;
+
+        // The header may arrive in pieces, and the tunneled data may follow
+        // the header in the same read, the buffered reader keeps what's read
+        // beyond the header for the caller.
+        let mut stream = BufReader::new(stream);
+        let mut line = Vec::new();
+        let mut header_size = 0;
+        let mut status_read = false;
+        loop {
+            line.clear();
+            let n = stream.read_until(b'\n', &mut line).await?;
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "http proxy closed the connection before responding",
+                ));
+            }
+            header_size += n;
+            if header_size > MAX_RESPONSE_HEADER_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "http proxy response header too large",
+                ));
+            }
+            if !status_read {
+                parse_status_line(&line)?;
+                status_read = true;
+            } else if line == b"\r\n" || line == b"\n" {
+                break;
+            }
+        }
+        Ok(Box::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::session::SocksAddr;
+
+    use super::*;
+
+    fn handler(username: &str) -> Handler {
+        Handler {
+            address: "127.0.0.1".to_string(),
+            port: 8080,
+            username: username.to_string(),
+            password: "pass".to_string(),
+            headers: HashMap::new(),
+        }
+    }
+
+    fn sess() -> Session {
+        Session {
+            destination: SocksAddr::Domain("example.com".to_string(), 443),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_connect_request() {
+        let req = handler("").connect_request(&sess());
+        assert_eq!(
+            req,
+            "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\nProxy-Connection: Keep-Alive\r\n\r\n"
+        );
+        let req = handler("user").connect_request(&sess());
+        assert!(req.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+
+        let mut h = handler("user");
+        h.headers = [
+            ("Host", "other.com"),
+            ("Proxy-Authorization", "Basic b3RoZXI6b3RoZXI="),
+            ("User-Agent", "Mozilla/5.0 (X11)"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let req = h.connect_request(&sess());
+        assert!(req.starts_with("CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n"));
+        assert_eq!(req.matches("Host:").count(), 1);
+        assert_eq!(req.matches("Proxy-Authorization:").count(), 1);
+        assert!(req.ends_with("User-Agent: Mozilla/5.0 (X11)\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_connect() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            // The response header is split across writes, and followed by
+            // tunneled data.
+            let (client, mut server) = tokio::io::duplex(1024);
+            let server_task = async move {
+                let mut buf = vec![0u8; 1024];
+                let n = server.read(&mut buf).await.unwrap();
+                assert!(buf[..n].starts_with(b"CONNECT example.com:443 HTTP/1.1\r\n"));
+                server.write_all(b"HTTP/1.1 200 Conn").await.unwrap();
+                tokio::task::yield_now().await;
+                server
+                    .write_all(b"ection established\r\nVia: test\r\n")
+                    .await
+                    .unwrap();
+                tokio::task::yield_now().await;
+                server.write_all(b"\r\nhello").await.unwrap();
+                server
+            };
+            let sess = sess();
+            let h = handler("");
+            let (stream, _server) =
+                futures::future::join(h.handle(&sess, Some(Box::new(client))), server_task).await;
+            let mut stream = stream.unwrap();
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+
+            let (client, mut server) = tokio::io::duplex(1024);
+            server
+                .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                .await
+                .unwrap();
+            let err = match h.handle(&sess, Some(Box::new(client))).await {
+                Err(e) => e,
+                Ok(_) => panic!("connect should fail"),
+            };
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            assert!(err.to_string().contains("407"));
+
+            let (client, mut server) = tokio::io::duplex(1024);
+            server.write_all(b"HTTP/1.1 200 OK\r\n").await.unwrap();
+            server.shutdown().await.unwrap();
+            let err = match h.handle(&sess, Some(Box::new(client))).await {
+                Err(e) => e,
+                Ok(_) => panic!("connect should fail"),
+            };
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        });
+    }
+}
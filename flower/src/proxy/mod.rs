@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::ffi::CString;
 use std::io;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
@@ -5,7 +6,6 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
-use futures::future::select_ok;
 use futures::stream::Stream;
 use futures::TryFutureExt;
 use log::*;
@@ -15,18 +15,15 @@ use tokio::net::{TcpSocket, TcpStream, UdpSocket};
 use tokio::time::timeout;
 
 #[cfg(unix)]
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 #[cfg(windows)]
-use std::os::windows::io::AsRawSocket;
-#[cfg(target_os = "android")]
-use {
-    std::os::unix::io::RawFd, tokio::io::AsyncReadExt, tokio::io::AsyncWriteExt,
-    tokio::net::UnixStream,
-};
+use std::os::windows::io::{AsRawSocket, RawSocket};
 
+#[cfg(target_os = "android")]
+use crate::common::net::protect_socket;
 use crate::{
     app::SyncDnsClient,
-    common::resolver::Resolver,
+    common::net::{happy_eyeballs, set_keepalive, set_nodelay},
     option,
     session::{DatagramSource, Session, SocksAddr},
 };
@@ -36,10 +33,15 @@ pub mod inbound;
 pub mod outbound;
 pub mod stream;
 
+#[cfg(feature = "test-util")]
+pub mod conformance;
+
 pub mod null;
 
 #[cfg(any(feature = "inbound-amux", feature = "outbound-amux"))]
 pub mod amux;
+#[cfg(feature = "outbound-balance")]
+pub mod balance;
 #[cfg(any(feature = "inbound-chain", feature = "outbound-chain"))]
 pub mod chain;
 #[cfg(feature = "outbound-direct")]
@@ -48,8 +50,12 @@ pub mod direct;
 pub mod drop;
 #[cfg(feature = "outbound-failover")]
 pub mod failover;
-#[cfg(feature = "inbound-http")]
+#[cfg(any(feature = "inbound-grpc", feature = "outbound-grpc"))]
+pub mod grpc;
+#[cfg(any(feature = "inbound-http", feature = "outbound-http"))]
 pub mod http;
+#[cfg(any(feature = "inbound-obfs", feature = "outbound-obfs"))]
+pub mod obfs;
 #[cfg(any(feature = "inbound-quic", feature = "outbound-quic"))]
 pub mod quic;
 #[cfg(feature = "outbound-random")]
@@ -68,6 +74,11 @@ pub mod shadowsocks;
 pub mod socks;
 #[cfg(feature = "outbound-tls")]
 pub mod tls;
+#[cfg(all(
+    feature = "inbound-tproxy",
+    any(target_os = "linux", target_os = "macos")
+))]
+pub mod tproxy;
 #[cfg(any(feature = "inbound-trojan", feature = "outbound-trojan"))]
 pub mod trojan;
 #[cfg(feature = "outbound-tryall")]
@@ -82,8 +93,12 @@ pub mod tryall;
     )
 ))]
 pub mod tun;
+#[cfg(feature = "outbound-vless")]
+pub mod vless;
 #[cfg(feature = "outbound-vmess")]
 pub mod vmess;
+#[cfg(feature = "outbound-wireguard")]
+pub mod wireguard;
 #[cfg(any(feature = "inbound-ws", feature = "outbound-ws"))]
 pub mod ws;
 
@@ -114,30 +129,100 @@ pub enum OutboundBind {
     Interface(String),
 }
 
-#[cfg(target_os = "android")]
-async fn protect_socket(fd: RawFd) -> io::Result<()> {
-    // TODO Warns about empty protect path?
-    if let Some(addr) = &*option::SOCKET_PROTECT_SERVER {
-        let mut stream = TcpStream::connect(addr).await?;
-        stream.write_i32(fd as i32).await?;
-        if stream.read_i32().await? != 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("failed to protect outbound socket {}", fd.as_raw_fd()),
-            ));
-        }
+/// Controls `TCP_NODELAY` of the TCP sockets of an inbound or outbound.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NoDelay {
+    /// Leaves the system default untouched, unless the `TCP_NODELAY` option
+    /// is set.
+    Default,
+    On,
+    Off,
+    /// Disables Nagle's algorithm during the handshake, where latency
+    /// matters, and enables it for the bulk relay that follows.
+    Smart,
+}
+
+impl Default for NoDelay {
+    fn default() -> Self {
+        NoDelay::Default
     }
-    if !option::SOCKET_PROTECT_PATH.is_empty() {
-        let mut stream = UnixStream::connect(&*option::SOCKET_PROTECT_PATH).await?;
-        stream.write_i32(fd as i32).await?;
-        if stream.read_i32().await? != 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("failed to protect outbound socket {}", fd),
-            ));
+}
+
+impl std::str::FromStr for NoDelay {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" => Ok(NoDelay::Default),
+            "on" | "true" => Ok(NoDelay::On),
+            "off" | "false" => Ok(NoDelay::Off),
+            "smart" => Ok(NoDelay::Smart),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid nodelay mode: {}", s),
+            )),
         }
     }
-    Ok(())
+}
+
+/// A handle to a socket in smart nodelay mode, used to switch the socket to
+/// bulk mode once the handshake is done. It borrows the socket by its raw
+/// handle, so it must be used only while the stream owning the socket is
+/// alive.
+pub struct NoDelaySwitch {
+    #[cfg(unix)]
+    fd: RawFd,
+    #[cfg(windows)]
+    socket: RawSocket,
+}
+
+#[cfg(unix)]
+impl AsRawFd for NoDelaySwitch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+#[cfg(windows)]
+impl AsRawSocket for NoDelaySwitch {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.socket
+    }
+}
+
+impl NoDelaySwitch {
+    pub fn enter_relay(&self) -> io::Result<()> {
+        SockRef::from(self).set_nodelay(false)
+    }
+}
+
+fn apply_nodelay_internal(s: SockRef, mode: NoDelay) -> io::Result<bool> {
+    match mode {
+        NoDelay::Default if *option::TCP_NODELAY => set_nodelay(&s, true).map(|_| false),
+        NoDelay::Default => Ok(false),
+        NoDelay::On => set_nodelay(&s, true).map(|_| false),
+        NoDelay::Off => set_nodelay(&s, false).map(|_| false),
+        NoDelay::Smart => set_nodelay(&s, true).map(|_| true),
+    }
+}
+
+#[cfg(unix)]
+pub fn apply_nodelay<S: AsRawFd>(socket: &S, mode: NoDelay) -> io::Result<Option<NoDelaySwitch>> {
+    let smart = apply_nodelay_internal(SockRef::from(socket), mode)?;
+    Ok(smart.then(|| NoDelaySwitch {
+        fd: socket.as_raw_fd(),
+    }))
+}
+
+#[cfg(windows)]
+pub fn apply_nodelay<S: AsRawSocket>(
+    socket: &S,
+    mode: NoDelay,
+) -> io::Result<Option<NoDelaySwitch>> {
+    let smart = apply_nodelay_internal(SockRef::from(socket), mode)?;
+    Ok(smart.then(|| NoDelaySwitch {
+        socket: socket.as_raw_socket(),
+    }))
 }
 
 #[cfg(any(target_os = "macos", target_os = "linux"))]
@@ -173,6 +258,12 @@ impl TcpListener {
         })
     }
 
+    pub fn from_std(listener: std::net::TcpListener) -> io::Result<Self> {
+        Ok(Self {
+            inner: tokio::net::TcpListener::from_std(listener)?,
+        })
+    }
+
     pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
         let (stream, addr) = self.inner.accept().await?;
         apply_socket_opts(&stream)?;
@@ -180,7 +271,11 @@ impl TcpListener {
     }
 }
 
-async fn bind_socket<T: BindSocket>(socket: &T, indicator: &SocketAddr) -> io::Result<()> {
+async fn bind_socket<T: BindSocket>(
+    socket: &T,
+    indicator: &SocketAddr,
+    binds: &[OutboundBind],
+) -> io::Result<()> {
     match indicator.ip() {
         IpAddr::V4(v4) if v4.is_loopback() => {
             socket.bind(&SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 0).into())?;
@@ -195,7 +290,7 @@ async fn bind_socket<T: BindSocket>(socket: &T, indicator: &SocketAddr) -> io::R
         _ => {}
     }
     let mut last_err = None;
-    for bind in option::OUTBOUND_BINDS.iter() {
+    for bind in binds.iter() {
         match bind {
             OutboundBind::Interface(iface) => {
                 #[cfg(target_os = "macos")]
@@ -287,6 +382,14 @@ async fn bind_socket<T: BindSocket>(socket: &T, indicator: &SocketAddr) -> io::R
 
 // New UDP socket.
 pub async fn new_udp_socket(indicator: &SocketAddr) -> io::Result<UdpSocket> {
+    new_udp_socket_with_binds(indicator, &option::OUTBOUND_BINDS).await
+}
+
+// New UDP socket bound to the first of `binds` matching the indicator.
+pub async fn new_udp_socket_with_binds(
+    indicator: &SocketAddr,
+    binds: &[OutboundBind],
+) -> io::Result<UdpSocket> {
     use socket2::{Domain, Socket, Type};
     let socket = if *option::ENABLE_IPV6 {
         // Dual-stack socket.
@@ -303,9 +406,9 @@ pub async fn new_udp_socket(indicator: &SocketAddr) -> io::Result<UdpSocket> {
     // If the proxy request is coming from an inbound listens on the loopback,
     // the indicator could be a loopback address, we must ignore it.
     if indicator.ip().is_loopback() || *option::ENABLE_IPV6 {
-        bind_socket(&socket, &*option::UNSPECIFIED_BIND_ADDR).await?;
+        bind_socket(&socket, &*option::UNSPECIFIED_BIND_ADDR, binds).await?;
     } else {
-        bind_socket(&socket, indicator).await?;
+        bind_socket(&socket, indicator, binds).await?;
     }
 
     #[cfg(target_os = "android")]
@@ -315,7 +418,7 @@ pub async fn new_udp_socket(indicator: &SocketAddr) -> io::Result<UdpSocket> {
 }
 
 fn apply_socket_opts_internal(s: SockRef) -> io::Result<()> {
-    s.set_keepalive(true)
+    set_keepalive(&s, *option::TCP_KEEPALIVE_SECS)
 }
 
 #[cfg(unix)]
@@ -330,28 +433,30 @@ fn apply_socket_opts<S: AsRawSocket>(socket: &S) -> io::Result<()> {
 }
 
 // A single TCP dial.
-async fn tcp_dial_task(dial_addr: SocketAddr) -> io::Result<(AnyStream, SocketAddr)> {
+async fn tcp_dial_task(
+    dial_addr: SocketAddr,
+    nodelay: NoDelay,
+    binds: &[OutboundBind],
+    connect_timeout: Duration,
+) -> io::Result<(AnyStream, SocketAddr, Option<NoDelaySwitch>)> {
     let socket = match dial_addr {
         SocketAddr::V4(..) => TcpSocket::new_v4()?,
         SocketAddr::V6(..) => TcpSocket::new_v6()?,
     };
 
-    bind_socket(&socket, &dial_addr).await?;
+    bind_socket(&socket, &dial_addr, binds).await?;
 
     #[cfg(target_os = "android")]
     protect_socket(socket.as_raw_fd()).await?;
 
     trace!("tcp dialing {}", &dial_addr);
-    let stream = timeout(
-        Duration::from_secs(*option::OUTBOUND_DIAL_TIMEOUT),
-        socket.connect(dial_addr),
-    )
-    .await??;
+    let stream = timeout(connect_timeout, socket.connect(dial_addr)).await??;
 
     apply_socket_opts(&stream)?;
+    let switch = apply_nodelay(&stream, nodelay)?;
 
     trace!("tcp connected {} <-> {}", stream.local_addr()?, &dial_addr);
-    Ok((Box::new(stream), dial_addr))
+    Ok((Box::new(stream), dial_addr, switch))
 }
 
 pub async fn connect_tcp_outbound(
@@ -359,19 +464,46 @@ pub async fn connect_tcp_outbound(
     dns_client: SyncDnsClient,
     handler: &AnyOutboundHandler,
 ) -> io::Result<Option<AnyStream>> {
+    connect_tcp_outbound_with_nodelay(sess, dns_client, handler)
+        .await
+        .map(|(stream, _)| stream)
+}
+
+/// Same as `connect_tcp_outbound`, also returns the switch of the dialed
+/// socket if the handler is in smart nodelay mode.
+pub async fn connect_tcp_outbound_with_nodelay(
+    sess: &Session,
+    dns_client: SyncDnsClient,
+    handler: &AnyOutboundHandler,
+) -> io::Result<(Option<AnyStream>, Option<NoDelaySwitch>)> {
+    let nodelay = handler.nodelay();
+    let connect_timeout = handler.connect_timeout();
     match TcpOutboundHandler::connect_addr(handler.as_ref()) {
         Some(OutboundConnect::Proxy(addr, port)) => {
-            Ok(Some(new_tcp_stream(dns_client, &addr, &port).await?))
+            let (stream, switch) = new_tcp_stream_with_binds(
+                dns_client,
+                &addr,
+                &port,
+                nodelay,
+                &option::OUTBOUND_BINDS,
+                connect_timeout,
+            )
+            .await?;
+            Ok((Some(stream), switch))
         }
-        Some(OutboundConnect::Direct) => Ok(Some(
-            new_tcp_stream(
+        Some(OutboundConnect::Direct) => {
+            let (stream, switch) = new_tcp_stream_with_binds(
                 dns_client,
                 &sess.destination.host(),
                 &sess.destination.port(),
+                nodelay,
+                &option::OUTBOUND_BINDS,
+                connect_timeout,
             )
-            .await?,
-        )),
-        Some(OutboundConnect::NoConnect) | None => Ok(None),
+            .await?;
+            Ok((Some(stream), switch))
+        }
+        Some(OutboundConnect::NoConnect) | None => Ok((None, None)),
     }
 }
 
@@ -418,56 +550,71 @@ pub async fn new_tcp_stream(
     address: &String,
     port: &u16,
 ) -> io::Result<AnyStream> {
-    let mut resolver = Resolver::new(dns_client.clone(), address, port)
+    new_tcp_stream_with_nodelay(dns_client, address, port, NoDelay::Default)
+        .await
+        .map(|(stream, _)| stream)
+}
+
+// Dials a TCP stream with the given nodelay mode.
+pub async fn new_tcp_stream_with_nodelay(
+    dns_client: SyncDnsClient,
+    address: &String,
+    port: &u16,
+    nodelay: NoDelay,
+) -> io::Result<(AnyStream, Option<NoDelaySwitch>)> {
+    new_tcp_stream_with_binds(
+        dns_client,
+        address,
+        port,
+        nodelay,
+        &option::OUTBOUND_BINDS,
+        Duration::from_secs(*option::OUTBOUND_DIAL_TIMEOUT),
+    )
+    .await
+}
+
+// Dials a TCP stream from the first of `binds` matching the dialed address,
+// each address of the destination times out after `connect_timeout`.
+pub async fn new_tcp_stream_with_binds(
+    dns_client: SyncDnsClient,
+    address: &String,
+    port: &u16,
+    nodelay: NoDelay,
+    binds: &[OutboundBind],
+    connect_timeout: Duration,
+) -> io::Result<(AnyStream, Option<NoDelaySwitch>)> {
+    let ips = dns_client
+        .read()
+        .await
+        .lookup(address)
         .map_err(|e| {
             io::Error::new(
                 io::ErrorKind::Other,
-                format!("resolve address failed: {}", e),
+                format!("resolve address failed: lookup {} failed: {}", address, e),
             )
         })
         .await?;
-
-    let mut last_err = None;
-
-    let mut done = false;
-
-    while !done {
-        let mut tasks = Vec::new();
-        for _ in 0..*option::OUTBOUND_DIAL_CONCURRENCY {
-            let dial_addr = match resolver.next() {
-                Some(a) => a,
-                None => {
-                    done = true; // run out
-                    break; // break and execute tasks if there're any
-                }
-            };
-            let t = tcp_dial_task(dial_addr);
-            tasks.push(Box::pin(t));
-        }
-        if !tasks.is_empty() {
-            match select_ok(tasks.into_iter()).await {
-                Ok(v) => {
-                    #[rustfmt::skip]
-                    dns_client.read().await.optimize_cache(address.to_owned(), v.0.1.ip()).await;
-                    #[rustfmt::skip]
-                    return Ok(v.0.0);
-                }
-                Err(e) => {
-                    last_err = Some(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("all attempts failed, last error: {}", e),
-                    ));
-                }
-            }
-        }
-    }
-
-    Err(last_err.unwrap_or_else(|| {
-        io::Error::new(
+    if ips.is_empty() {
+        return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
             "could not resolve to any address",
-        )
-    }))
+        ));
+    }
+
+    let (stream, dial_addr, switch) = happy_eyeballs(
+        ips,
+        *port,
+        Duration::from_millis(*option::OUTBOUND_DIAL_ATTEMPT_DELAY),
+        |dial_addr| tcp_dial_task(dial_addr, nodelay, binds, connect_timeout),
+    )
+    .await
+    .map_err(|e| io::Error::new(e.kind(), format!("all attempts failed, last error: {}", e)))?;
+    dns_client
+        .read()
+        .await
+        .optimize_cache(address.to_owned(), dial_addr.ip())
+        .await;
+    Ok((stream, switch))
 }
 
 /// An interface with the ability to dial TCP connections.
@@ -494,9 +641,32 @@ pub trait UdpConnector: Send + Sync + Unpin {
 }
 
 /// A reliable transport for both inbound and outbound handlers.
-pub trait ProxyStream: AsyncRead + AsyncWrite + Send + Sync + Unpin {}
+pub trait ProxyStream: AsyncRead + AsyncWrite + Send + Sync + Unpin {
+    /// Returns the stream as `Any` so that the concrete type, e.g. a TLS
+    /// stream, can be inspected.
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<S> ProxyStream for S
+where
+    S: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
 
-impl<S> ProxyStream for S where S: AsyncRead + AsyncWrite + Send + Sync + Unpin {}
+/// Returns the ALPN protocol negotiated over the stream if it's a TLS stream
+/// made by the TLS outbound.
+#[cfg(feature = "outbound-tls")]
+pub fn negotiated_alpn(stream: &AnyStream) -> Option<String> {
+    tls::outbound::negotiated_alpn(stream)
+}
+
+#[cfg(not(feature = "outbound-tls"))]
+pub fn negotiated_alpn(_stream: &AnyStream) -> Option<String> {
+    None
+}
 
 pub type AnyStream = Box<dyn ProxyStream>;
 
@@ -504,6 +674,16 @@ pub type AnyStream = Box<dyn ProxyStream>;
 pub trait OutboundHandler:
     TcpOutboundHandler + UdpOutboundHandler + Tag + Color + Send + Unpin
 {
+    /// Returns the nodelay mode of the TCP sockets dialed by this handler.
+    fn nodelay(&self) -> NoDelay {
+        NoDelay::Default
+    }
+
+    /// Returns the timeout of connecting to an address of the TCP
+    /// destinations of this handler.
+    fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(*option::OUTBOUND_DIAL_TIMEOUT)
+    }
 }
 
 pub type AnyOutboundHandler = Arc<
@@ -733,3 +913,42 @@ pub enum InboundTransport<S, D> {
 }
 
 pub type AnyInboundTransport = InboundTransport<AnyStream, AnyInboundDatagram>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nodelay_parse() {
+        assert_eq!("".parse::<NoDelay>().unwrap(), NoDelay::Default);
+        assert_eq!("on".parse::<NoDelay>().unwrap(), NoDelay::On);
+        assert_eq!("false".parse::<NoDelay>().unwrap(), NoDelay::Off);
+        assert_eq!("Smart".parse::<NoDelay>().unwrap(), NoDelay::Smart);
+        assert!("fast".parse::<NoDelay>().is_err());
+    }
+
+    #[test]
+    fn test_nodelay_smart_transition() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let _accepted = listener.accept().await.unwrap();
+
+            stream.set_nodelay(false).unwrap();
+            let switch = apply_nodelay(&stream, NoDelay::Smart).unwrap().unwrap();
+            // Handshake phase.
+            assert!(stream.nodelay().unwrap());
+            switch.enter_relay().unwrap();
+            // Relay phase.
+            assert!(!stream.nodelay().unwrap());
+
+            assert!(apply_nodelay(&stream, NoDelay::On).unwrap().is_none());
+            assert!(stream.nodelay().unwrap());
+        });
+    }
+}
@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+
+use crate::{proxy::*, session::Session};
+
+use super::{Mode, ObfsStream, Role};
+
+pub struct Handler {
+    mode: Mode,
+    path: String,
+}
+
+impl Handler {
+    pub fn new(mode: Mode, path: String) -> Self {
+        Handler { mode, path }
+    }
+}
+
+#[async_trait]
+impl TcpInboundHandler for Handler {
+    type TStream = AnyStream;
+    type TDatagram = AnyInboundDatagram;
+
+    async fn handle<'a>(
+        &'a self,
+        sess: Session,
+        stream: Self::TStream,
+    ) -> std::io::Result<InboundTransport<Self::TStream, Self::TDatagram>> {
+        let role = Role::Server {
+            path: self.path.clone(),
+        };
+        let stream = ObfsStream::new(stream, self.mode, role);
+        Ok(InboundTransport::Stream(Box::new(stream), sess))
+    }
+}
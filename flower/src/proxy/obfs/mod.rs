@@ -0,0 +1,47 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+
+#[cfg(feature = "inbound-obfs")]
+pub mod inbound;
+#[cfg(feature = "outbound-obfs")]
+pub mod outbound;
+
+mod stream;
+
+pub use stream::{ObfsStream, Role};
+
+/// How the first packet of each direction is disguised.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// A WebSocket upgrade request and response.
+    Http,
+    /// A TLS 1.2 session resumption handshake, followed by application data
+    /// records.
+    Tls,
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(Mode::Http),
+            "tls" => Ok(Mode::Tls),
+            _ => Err(anyhow!("unsupported obfs mode: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!("http".parse::<Mode>().unwrap(), Mode::Http);
+        assert_eq!("tls".parse::<Mode>().unwrap(), Mode::Tls);
+        assert!("".parse::<Mode>().is_err());
+        assert!("HTTP".parse::<Mode>().is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use std::io;
+
+use async_trait::async_trait;
+
+use crate::{proxy::*, session::Session};
+
+use super::{Mode, ObfsStream, Role};
+
+pub struct Handler {
+    pub mode: Mode,
+    /// The Host header or the server name, the destination is used if
+    /// empty.
+    pub host: String,
+    /// The request path in http mode, "/" if empty.
+    pub path: String,
+}
+
+#[async_trait]
+impl TcpOutboundHandler for Handler {
+    type Stream = AnyStream;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        None
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        stream: Option<Self::Stream>,
+    ) -> io::Result<Self::Stream> {
+        let stream = stream.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid input"))?;
+        let host = if self.host.is_empty() {
+            sess.destination.host()
+        } else {
+            self.host.clone()
+        };
+        let path = if self.path.is_empty() {
+            "/".to_string()
+        } else {
+            self.path.clone()
+        };
+        let role = Role::Client { host, path };
+        Ok(Box::new(ObfsStream::new(stream, self.mode, role)))
+    }
+}
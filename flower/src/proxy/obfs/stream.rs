@@ -0,0 +1,724 @@
+use std::{cmp::min, io, pin::Pin};
+
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{Buf, BufMut, BytesMut};
+use futures::{
+    ready,
+    task::{Context, Poll},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::io::{poll_read_buf, poll_write_buf};
+
+use super::Mode;
+
+/// Maximum size of the HTTP header stripped from the first packet.
+const MAX_HEADER_SIZE: usize = 8192;
+
+/// Maximum payload size of a TLS record.
+const MAX_RECORD_SIZE: usize = 16384;
+
+/// Maximum payload carried in the session ticket of the ClientHello, leaves
+/// room for the rest of the handshake in the record.
+const MAX_TICKET_SIZE: usize = 8192;
+
+const READ_BUFFER_SIZE: usize = 5 + MAX_RECORD_SIZE;
+
+// The cipher suites offered by simple-obfs.
+const CIPHER_SUITES: &[u8] = &[
+    0xc0, 0x2c, 0xc0, 0x30, 0x00, 0x9f, 0xcc, 0xa9, 0xcc, 0xa8, 0xcc, 0xaa, 0xc0, 0x2b, 0xc0, 0x2f,
+    0x00, 0x9e, 0xc0, 0x24, 0xc0, 0x28, 0x00, 0x6b, 0xc0, 0x23, 0xc0, 0x27, 0x00, 0x67, 0xc0, 0x0a,
+    0xc0, 0x14, 0x00, 0x39, 0xc0, 0x09, 0xc0, 0x13, 0x00, 0x33, 0x00, 0x9d, 0x00, 0x9c, 0x00, 0x3d,
+    0x00, 0x3c, 0x00, 0x35, 0x00, 0x2f, 0x00, 0xff,
+];
+
+// The extensions of the simple-obfs ClientHello following the session ticket
+// and the server name: ec_point_formats, supported_groups,
+// signature_algorithms, encrypt_then_mac and extended_master_secret.
+const CLIENT_HELLO_EXTENSIONS: &[u8] = &[
+    0x00, 0x0b, 0x00, 0x04, 0x03, 0x01, 0x00, 0x02, 0x00, 0x0a, 0x00, 0x0a, 0x00, 0x08, 0x00, 0x1d,
+    0x00, 0x17, 0x00, 0x19, 0x00, 0x18, 0x00, 0x0d, 0x00, 0x20, 0x00, 0x1e, 0x06, 0x01, 0x06, 0x02,
+    0x06, 0x03, 0x05, 0x01, 0x05, 0x02, 0x05, 0x03, 0x04, 0x01, 0x04, 0x02, 0x04, 0x03, 0x03, 0x01,
+    0x03, 0x02, 0x03, 0x03, 0x02, 0x01, 0x02, 0x02, 0x02, 0x03, 0x00, 0x16, 0x00, 0x00, 0x00, 0x17,
+    0x00, 0x00,
+];
+
+// The extensions of the simple-obfs ServerHello: renegotiation_info,
+// extended_master_secret and ec_point_formats.
+const SERVER_HELLO_EXTENSIONS: &[u8] = &[
+    0xff, 0x01, 0x00, 0x01, 0x00, 0x00, 0x17, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x02, 0x01, 0x00,
+];
+
+const CONTENT_CHANGE_CIPHER_SPEC: u8 = 0x14;
+const CONTENT_HANDSHAKE: u8 = 0x16;
+const CONTENT_APPLICATION_DATA: u8 = 0x17;
+
+const EXTENSION_SESSION_TICKET: u16 = 0x0023;
+
+/// Which side of the obfuscation a stream is.
+pub enum Role {
+    /// Sends the request, for `host` and `path`, and strips the response.
+    Client { host: String, path: String },
+    /// Strips the request, which must be for `path` unless it's empty, and
+    /// sends the response.
+    Server { path: String },
+}
+
+/// Disguises the first packet of each direction as an HTTP upgrade or a TLS
+/// handshake, wire-compatible with simple-obfs.
+///
+/// In http mode the rest of the stream passes through as is, in tls mode it
+/// is framed in application data records. The client's payload starts in the
+/// session ticket of its ClientHello, the server's in the Finished message
+/// following its ServerHello and ChangeCipherSpec.
+pub struct ObfsStream<S> {
+    inner: S,
+    mode: Mode,
+    role: Role,
+    // Bytes read from the inner stream not yet deobfuscated.
+    recv_buf: BytesMut,
+    // Payload not yet returned.
+    read_buf: BytesMut,
+    // The obfuscated piece being written.
+    write_buf: BytesMut,
+    header_received: bool,
+    header_sent: bool,
+    // Whether the Finished message after the ServerHello has been received,
+    // it carries the first payload of the server.
+    finished_received: bool,
+    // The session ID of the ClientHello, echoed in the ServerHello.
+    session_id: Vec<u8>,
+    // How much of the caller's buf the piece being written consumed.
+    pending_write: Option<usize>,
+}
+
+impl<S> ObfsStream<S> {
+    pub fn new(inner: S, mode: Mode, role: Role) -> Self {
+        ObfsStream {
+            inner,
+            mode,
+            role,
+            recv_buf: BytesMut::new(),
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+            header_received: false,
+            header_sent: false,
+            finished_received: false,
+            session_id: Vec::new(),
+            pending_write: None,
+        }
+    }
+
+    // Deobfuscates what has been received so far into `read_buf`, false if
+    // more is needed.
+    fn decode(&mut self) -> io::Result<bool> {
+        match self.mode {
+            Mode::Http => {
+                let end = match find_header_end(&self.recv_buf) {
+                    Some(end) => end,
+                    None if self.recv_buf.len() > MAX_HEADER_SIZE => {
+                        return Err(invalid_data("http header too large".to_string()));
+                    }
+                    None => return Ok(false),
+                };
+                let header = self.recv_buf.split_to(end);
+                check_header(&self.role, &header)?;
+                self.header_received = true;
+                self.read_buf = self.recv_buf.split();
+                Ok(true)
+            }
+            Mode::Tls => {
+                if self.recv_buf.len() < 5 {
+                    return Ok(false);
+                }
+                let len = BigEndian::read_u16(&self.recv_buf[3..5]) as usize;
+                if len > MAX_RECORD_SIZE + 2048 {
+                    return Err(invalid_data(format!("invalid tls record length {}", len)));
+                }
+                if self.recv_buf.len() < 5 + len {
+                    return Ok(false);
+                }
+                let mut record = self.recv_buf.split_to(5 + len);
+                let content_type = record[0];
+                record.advance(5);
+                match (content_type, &self.role) {
+                    (CONTENT_APPLICATION_DATA, _) if self.header_received => {
+                        self.read_buf = record;
+                    }
+                    (CONTENT_HANDSHAKE, Role::Server { .. }) if !self.header_received => {
+                        let (session_id, ticket) = parse_client_hello(&record)?;
+                        self.session_id = session_id.to_vec();
+                        self.read_buf = BytesMut::from(ticket);
+                        self.header_received = true;
+                    }
+                    (CONTENT_HANDSHAKE, Role::Client { .. }) if !self.header_received => {
+                        if record.first() != Some(&0x02) {
+                            return Err(invalid_data("invalid tls server hello".to_string()));
+                        }
+                        self.header_received = true;
+                    }
+                    (CONTENT_HANDSHAKE, Role::Client { .. }) if !self.finished_received => {
+                        self.read_buf = record;
+                        self.finished_received = true;
+                    }
+                    // The ChangeCipherSpec and Finished some clients send
+                    // after the ServerHello carry no payload.
+                    (CONTENT_CHANGE_CIPHER_SPEC, _) | (CONTENT_HANDSHAKE, Role::Server { .. })
+                        if self.header_received => {}
+                    (t, _) => {
+                        return Err(invalid_data(format!("unexpected tls record type {}", t)));
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    // Obfuscates a piece of `buf` into `write_buf`, returns the size of the
+    // piece.
+    fn encode(&mut self, buf: &[u8]) -> usize {
+        let first = !self.header_sent;
+        self.header_sent = true;
+        match (self.mode, &self.role) {
+            (Mode::Http, Role::Client { host, path }) => {
+                put_http_request(&mut self.write_buf, host, path, buf.len());
+                self.write_buf.put_slice(buf);
+                buf.len()
+            }
+            (Mode::Http, Role::Server { .. }) => {
+                put_http_response(&mut self.write_buf);
+                self.write_buf.put_slice(buf);
+                buf.len()
+            }
+            (Mode::Tls, Role::Client { host, .. }) if first => {
+                let n = min(buf.len(), MAX_TICKET_SIZE);
+                put_client_hello(&mut self.write_buf, host, &buf[..n]);
+                n
+            }
+            (Mode::Tls, Role::Server { .. }) if first => {
+                let n = min(buf.len(), MAX_RECORD_SIZE);
+                put_server_hello(&mut self.write_buf, &self.session_id, &buf[..n]);
+                n
+            }
+            (Mode::Tls, _) => {
+                let n = min(buf.len(), MAX_RECORD_SIZE);
+                put_record(
+                    &mut self.write_buf,
+                    CONTENT_APPLICATION_DATA,
+                    0x0303,
+                    &buf[..n],
+                );
+                n
+            }
+        }
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn early_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "early eof")
+}
+
+fn find_header_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+fn check_header(role: &Role, header: &[u8]) -> io::Result<()> {
+    let header =
+        std::str::from_utf8(header).map_err(|_| invalid_data("invalid http header".to_string()))?;
+    let line = header.lines().next().unwrap_or_default();
+    match role {
+        Role::Client { .. } => {
+            if !line.starts_with("HTTP/1.1 101 ") {
+                return Err(invalid_data(format!("unexpected http response: {}", line)));
+            }
+        }
+        Role::Server { path } => {
+            let mut parts = line.split(' ');
+            let (method, target, version) = (parts.next(), parts.next(), parts.next());
+            if method != Some("GET") || version != Some("HTTP/1.1") {
+                return Err(invalid_data(format!("unexpected http request: {}", line)));
+            }
+            if !path.is_empty() && target != Some(path.as_str()) {
+                return Err(invalid_data(format!(
+                    "unexpected http path: {}",
+                    target.unwrap_or_default()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn random_bytes(n: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; n];
+    StdRng::from_entropy().fill(&mut bytes[..]);
+    bytes
+}
+
+fn put_http_request(buf: &mut BytesMut, host: &str, path: &str, content_length: usize) {
+    let user_agent = if crate::option::USER_AGENT.is_empty() {
+        "curl/7.88.1"
+    } else {
+        crate::option::USER_AGENT.as_str()
+    };
+    buf.put_slice(
+        format!(
+            "GET {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             User-Agent: {}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\n\
+             Content-Length: {}\r\n\r\n",
+            path,
+            host,
+            user_agent,
+            base64::encode(random_bytes(16)),
+            content_length,
+        )
+        .as_bytes(),
+    );
+}
+
+fn put_http_response(buf: &mut BytesMut) {
+    buf.put_slice(
+        format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Server: nginx/1.24.0\r\n\
+             Date: {}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT"),
+            base64::encode(random_bytes(20)),
+        )
+        .as_bytes(),
+    );
+}
+
+fn put_record(buf: &mut BytesMut, content_type: u8, version: u16, payload: &[u8]) {
+    buf.put_u8(content_type);
+    buf.put_u16(version);
+    buf.put_u16(payload.len() as u16);
+    buf.put_slice(payload);
+}
+
+fn handshake(msg_type: u8, body: &[u8]) -> BytesMut {
+    let mut msg = BytesMut::with_capacity(4 + body.len());
+    msg.put_u8(msg_type);
+    msg.put_uint(body.len() as u64, 3);
+    msg.put_slice(body);
+    msg
+}
+
+// The 32 bytes of a TLS random, starting with the time as simple-obfs does.
+fn put_random(buf: &mut BytesMut) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    buf.put_u32(now.as_secs() as u32);
+    buf.put_slice(&random_bytes(28));
+}
+
+// A ClientHello resuming a session, the session ticket is the payload.
+fn put_client_hello(buf: &mut BytesMut, host: &str, ticket: &[u8]) {
+    let mut ext = BytesMut::new();
+    ext.put_u16(EXTENSION_SESSION_TICKET);
+    ext.put_u16(ticket.len() as u16);
+    ext.put_slice(ticket);
+    // server_name
+    ext.put_u16(0x0000);
+    ext.put_u16(host.len() as u16 + 5);
+    ext.put_u16(host.len() as u16 + 3);
+    ext.put_u8(0x00);
+    ext.put_u16(host.len() as u16);
+    ext.put_slice(host.as_bytes());
+    ext.put_slice(CLIENT_HELLO_EXTENSIONS);
+
+    let mut hello = BytesMut::new();
+    hello.put_u16(0x0303);
+    put_random(&mut hello);
+    hello.put_u8(32);
+    hello.put_slice(&random_bytes(32));
+    hello.put_u16(CIPHER_SUITES.len() as u16);
+    hello.put_slice(CIPHER_SUITES);
+    // null compression
+    hello.put_slice(&[0x01, 0x00]);
+    hello.put_u16(ext.len() as u16);
+    hello.put_slice(&ext);
+
+    put_record(buf, CONTENT_HANDSHAKE, 0x0301, &handshake(0x01, &hello));
+}
+
+// A ServerHello resuming the session of `session_id`, followed by
+// ChangeCipherSpec and a Finished carrying the payload.
+fn put_server_hello(buf: &mut BytesMut, session_id: &[u8], payload: &[u8]) {
+    let mut hello = BytesMut::new();
+    hello.put_u16(0x0303);
+    put_random(&mut hello);
+    hello.put_u8(session_id.len() as u8);
+    hello.put_slice(session_id);
+    // TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256, null compression
+    hello.put_slice(&[0xcc, 0xa8, 0x00]);
+    hello.put_u16(SERVER_HELLO_EXTENSIONS.len() as u16);
+    hello.put_slice(SERVER_HELLO_EXTENSIONS);
+
+    put_record(buf, CONTENT_HANDSHAKE, 0x0301, &handshake(0x02, &hello));
+    put_record(buf, CONTENT_CHANGE_CIPHER_SPEC, 0x0303, &[0x01]);
+    put_record(buf, CONTENT_HANDSHAKE, 0x0303, payload);
+}
+
+fn take<'a>(r: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if r.len() < n {
+        return Err(invalid_data("invalid tls client hello".to_string()));
+    }
+    let (head, tail) = r.split_at(n);
+    *r = tail;
+    Ok(head)
+}
+
+// The session ID and the session ticket of a ClientHello, the ticket is empty
+// if there is none.
+fn parse_client_hello(hello: &[u8]) -> io::Result<(&[u8], &[u8])> {
+    let mut r = hello;
+    // msg_type, length, version, random
+    if take(&mut r, 4 + 2 + 32)?[0] != 0x01 {
+        return Err(invalid_data("invalid tls client hello".to_string()));
+    }
+    let n = take(&mut r, 1)?[0] as usize;
+    let session_id = take(&mut r, n)?;
+    let n = BigEndian::read_u16(take(&mut r, 2)?) as usize;
+    take(&mut r, n)?;
+    let n = take(&mut r, 1)?[0] as usize;
+    take(&mut r, n)?;
+    let n = BigEndian::read_u16(take(&mut r, 2)?) as usize;
+    let mut ext = take(&mut r, n)?;
+    while !ext.is_empty() {
+        let ext_type = BigEndian::read_u16(take(&mut ext, 2)?);
+        let n = BigEndian::read_u16(take(&mut ext, 2)?) as usize;
+        let data = take(&mut ext, n)?;
+        if ext_type == EXTENSION_SESSION_TICKET {
+            return Ok((session_id, data));
+        }
+    }
+    Ok((session_id, &[]))
+}
+
+impl<S> AsyncRead for ObfsStream<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let me = &mut *self;
+        loop {
+            if !me.read_buf.is_empty() {
+                let n = min(buf.remaining(), me.read_buf.len());
+                buf.put_slice(&me.read_buf.split_to(n));
+                return Poll::Ready(Ok(()));
+            }
+            if me.mode == Mode::Http && me.header_received {
+                return Pin::new(&mut me.inner).poll_read(cx, buf);
+            }
+            if me.decode()? {
+                continue;
+            }
+            me.recv_buf.reserve(READ_BUFFER_SIZE);
+            let n = ready!(poll_read_buf(Pin::new(&mut me.inner), cx, &mut me.recv_buf))?;
+            if n == 0 {
+                // EOF between records is a clean EOF.
+                if me.recv_buf.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                return Poll::Ready(Err(early_eof()));
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for ObfsStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // The header can wait for the first payload.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let me = &mut *self;
+        loop {
+            match me.pending_write {
+                None => {
+                    if me.mode == Mode::Http && me.header_sent {
+                        return Pin::new(&mut me.inner).poll_write(cx, buf);
+                    }
+                    let consumed = me.encode(buf);
+                    me.pending_write = Some(consumed);
+                }
+                Some(consumed) => {
+                    // As with `AeadStream`, the caller is expected to retry
+                    // with the same buf upon pending.
+                    while !me.write_buf.is_empty() {
+                        let nw = ready!(poll_write_buf(
+                            Pin::new(&mut me.inner),
+                            cx,
+                            &mut me.write_buf
+                        ))?;
+                        if nw == 0 {
+                            return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                        }
+                    }
+                    me.pending_write = None;
+                    return Poll::Ready(Ok(consumed));
+                }
+            }
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use super::*;
+
+    // A ClientHello of simple-obfs for www.bing.com carrying "hello".
+    const CLIENT_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x00, 0xe5, 0x01, 0x00, 0x00, 0xe1, 0x03, 0x03, 0x65, 0x2f, 0x4a, 0x10,
+        0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e,
+        0x2f, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x20, 0x80,
+        0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d, 0x8e, 0x8f,
+        0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b, 0x9c, 0x9d, 0x9e,
+        0x9f, 0x00, 0x38, 0xc0, 0x2c, 0xc0, 0x30, 0x00, 0x9f, 0xcc, 0xa9, 0xcc, 0xa8, 0xcc, 0xaa,
+        0xc0, 0x2b, 0xc0, 0x2f, 0x00, 0x9e, 0xc0, 0x24, 0xc0, 0x28, 0x00, 0x6b, 0xc0, 0x23, 0xc0,
+        0x27, 0x00, 0x67, 0xc0, 0x0a, 0xc0, 0x14, 0x00, 0x39, 0xc0, 0x09, 0xc0, 0x13, 0x00, 0x33,
+        0x00, 0x9d, 0x00, 0x9c, 0x00, 0x3d, 0x00, 0x3c, 0x00, 0x35, 0x00, 0x2f, 0x00, 0xff, 0x01,
+        0x00, 0x00, 0x60, 0x00, 0x23, 0x00, 0x05, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x00, 0x00,
+        0x11, 0x00, 0x0f, 0x00, 0x00, 0x0c, 0x77, 0x77, 0x77, 0x2e, 0x62, 0x69, 0x6e, 0x67, 0x2e,
+        0x63, 0x6f, 0x6d, 0x00, 0x0b, 0x00, 0x04, 0x03, 0x01, 0x00, 0x02, 0x00, 0x0a, 0x00, 0x0a,
+        0x00, 0x08, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x19, 0x00, 0x18, 0x00, 0x0d, 0x00, 0x20, 0x00,
+        0x1e, 0x06, 0x01, 0x06, 0x02, 0x06, 0x03, 0x05, 0x01, 0x05, 0x02, 0x05, 0x03, 0x04, 0x01,
+        0x04, 0x02, 0x04, 0x03, 0x03, 0x01, 0x03, 0x02, 0x03, 0x03, 0x02, 0x01, 0x02, 0x02, 0x02,
+        0x03, 0x00, 0x16, 0x00, 0x00, 0x00, 0x17, 0x00, 0x00,
+    ];
+
+    // The response of simple-obfs to `CLIENT_HELLO` carrying "hello".
+    const SERVER_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x00, 0x5b, 0x02, 0x00, 0x00, 0x57, 0x03, 0x03, 0x65, 0x2f, 0x4a, 0x11,
+        0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4c, 0x4d, 0x4e,
+        0x4f, 0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x5b, 0x20, 0x80,
+        0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d, 0x8e, 0x8f,
+        0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b, 0x9c, 0x9d, 0x9e,
+        0x9f, 0xcc, 0xa8, 0x00, 0x00, 0x0f, 0xff, 0x01, 0x00, 0x01, 0x00, 0x00, 0x17, 0x00, 0x00,
+        0x00, 0x0b, 0x00, 0x02, 0x01, 0x00, 0x14, 0x03, 0x03, 0x00, 0x01, 0x01, 0x16, 0x03, 0x03,
+        0x00, 0x05, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+    ];
+
+    fn new_pair(mode: Mode, path: &str) -> (ObfsStream<DuplexStream>, ObfsStream<DuplexStream>) {
+        let (a, b) = tokio::io::duplex(READ_BUFFER_SIZE * 2);
+        let client = Role::Client {
+            host: "www.example.com".to_string(),
+            path: "/flower".to_string(),
+        };
+        let server = Role::Server {
+            path: path.to_string(),
+        };
+        (
+            ObfsStream::new(a, mode, client),
+            ObfsStream::new(b, mode, server),
+        )
+    }
+
+    #[test]
+    fn test_obfs_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            for mode in [Mode::Http, Mode::Tls] {
+                let (mut client, mut server) = new_pair(mode, "/flower");
+                client.write_all(b"hello").await.unwrap();
+                let mut buf = [0u8; 5];
+                server.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"hello");
+                server.write_all(b"world").await.unwrap();
+                client.read_exact(&mut buf).await.unwrap();
+                assert_eq!(&buf, b"world");
+
+                // More than a record each way after the first packets.
+                let data: Vec<u8> = (0..MAX_RECORD_SIZE * 2 + 5).map(|i| i as u8).collect();
+                let data2 = data.clone();
+                let writer = tokio::spawn(async move {
+                    client.write_all(&data2).await.unwrap();
+                    client.shutdown().await.unwrap();
+                    client
+                });
+                let mut received = Vec::new();
+                server.read_to_end(&mut received).await.unwrap();
+                assert_eq!(received, data);
+                writer.await.unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_http_wire_format() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (a, mut b) = tokio::io::duplex(1024);
+            let role = Role::Client {
+                host: "www.example.com".to_string(),
+                path: "/flower".to_string(),
+            };
+            let mut client = ObfsStream::new(a, Mode::Http, role);
+            client.write_all(b"hello").await.unwrap();
+            client.write_all(b"world").await.unwrap();
+            drop(client);
+            let mut raw = Vec::new();
+            b.read_to_end(&mut raw).await.unwrap();
+            let raw = String::from_utf8(raw).unwrap();
+            assert!(raw.starts_with("GET /flower HTTP/1.1\r\nHost: www.example.com\r\n"));
+            assert!(raw.contains("\r\nUpgrade: websocket\r\n"));
+            assert!(raw.ends_with("\r\nContent-Length: 5\r\n\r\nhelloworld"));
+        });
+    }
+
+    #[test]
+    fn test_http_unexpected_path() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut client, mut server) = new_pair(Mode::Http, "/other");
+            client.write_all(b"hello").await.unwrap();
+            let mut buf = [0u8; 5];
+            let err = server.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            // Any path is accepted if the server path is empty.
+            let (mut client, mut server) = new_pair(Mode::Http, "");
+            client.write_all(b"hello").await.unwrap();
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        });
+    }
+
+    #[test]
+    fn test_tls_wire_format() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (a, mut b) = tokio::io::duplex(1024);
+            let role = Role::Client {
+                host: "www.example.com".to_string(),
+                path: "".to_string(),
+            };
+            let mut client = ObfsStream::new(a, Mode::Tls, role);
+            client.write_all(b"hello").await.unwrap();
+            client.write_all(b"world").await.unwrap();
+            drop(client);
+            let mut raw = Vec::new();
+            b.read_to_end(&mut raw).await.unwrap();
+
+            assert_eq!(raw[..3], [0x16, 0x03, 0x01]);
+            let len = BigEndian::read_u16(&raw[3..5]) as usize;
+            let hello = &raw[5..5 + len];
+            assert_eq!(parse_client_hello(hello).unwrap().1, b"hello");
+            assert!(hello
+                .windows(b"www.example.com".len())
+                .any(|w| w == b"www.example.com"));
+            assert_eq!(raw[5 + len..], *b"\x17\x03\x03\x00\x05world");
+        });
+    }
+
+    #[test]
+    fn test_tls_simple_obfs_server() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (a, mut b) = tokio::io::duplex(1024);
+            let role = Role::Client {
+                host: "www.bing.com".to_string(),
+                path: "".to_string(),
+            };
+            let mut client = ObfsStream::new(a, Mode::Tls, role);
+            client.write_all(b"hello").await.unwrap();
+            client.write_all(b"world").await.unwrap();
+
+            // The same ClientHello but the random and the session ID.
+            let mut hello = vec![0u8; CLIENT_HELLO.len()];
+            b.read_exact(&mut hello).await.unwrap();
+            assert_eq!(hello[..11], CLIENT_HELLO[..11]);
+            assert_eq!(hello[43..44], CLIENT_HELLO[43..44]);
+            assert_eq!(hello[76..], CLIENT_HELLO[76..]);
+            let mut record = [0u8; 10];
+            b.read_exact(&mut record).await.unwrap();
+            assert_eq!(record, *b"\x17\x03\x03\x00\x05world");
+
+            b.write_all(SERVER_HELLO).await.unwrap();
+            b.write_all(b"\x17\x03\x03\x00\x05world").await.unwrap();
+            let mut buf = [0u8; 10];
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"helloworld");
+        });
+    }
+
+    #[test]
+    fn test_tls_simple_obfs_client() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (a, mut b) = tokio::io::duplex(1024);
+            let role = Role::Server {
+                path: "".to_string(),
+            };
+            let mut server = ObfsStream::new(a, Mode::Tls, role);
+            b.write_all(CLIENT_HELLO).await.unwrap();
+            let mut buf = [0u8; 5];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+
+            // The same response but the random.
+            server.write_all(b"hello").await.unwrap();
+            let mut response = vec![0u8; SERVER_HELLO.len()];
+            b.read_exact(&mut response).await.unwrap();
+            assert_eq!(response[..11], SERVER_HELLO[..11]);
+            assert_eq!(response[43..], SERVER_HELLO[43..]);
+
+            // The ChangeCipherSpec and Finished a client may send next carry
+            // no payload.
+            b.write_all(b"\x14\x03\x03\x00\x01\x01").await.unwrap();
+            b.write_all(b"\x16\x03\x03\x00\x20").await.unwrap();
+            b.write_all(&[0u8; 32]).await.unwrap();
+            b.write_all(b"\x17\x03\x03\x00\x05world").await.unwrap();
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"world");
+        });
+    }
+}
@@ -1,5 +1,6 @@
 use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 
@@ -12,6 +13,8 @@ use super::*;
 pub struct Handler {
     tag: String,
     color: colored::Color,
+    nodelay: NoDelay,
+    connect_timeout: Duration,
     tcp_handler: AnyTcpOutboundHandler,
     udp_handler: AnyUdpOutboundHandler,
 }
@@ -20,19 +23,31 @@ impl Handler {
     pub(self) fn new(
         tag: String,
         color: colored::Color,
+        nodelay: NoDelay,
+        connect_timeout: Duration,
         tcp_handler: AnyTcpOutboundHandler,
         udp_handler: AnyUdpOutboundHandler,
     ) -> Arc<Self> {
         Arc::new(Handler {
             tag,
             color,
+            nodelay,
+            connect_timeout,
             tcp_handler,
             udp_handler,
         })
     }
 }
 
-impl OutboundHandler for Handler {}
+impl OutboundHandler for Handler {
+    fn nodelay(&self) -> NoDelay {
+        self.nodelay
+    }
+
+    fn connect_timeout(&self) -> Duration {
+        self.connect_timeout
+    }
+}
 
 impl Tag for Handler {
     fn tag(&self) -> &String {
@@ -88,6 +103,8 @@ impl UdpOutboundHandler for Handler {
 pub struct HandlerBuilder {
     tag: String,
     color: colored::Color,
+    nodelay: NoDelay,
+    connect_timeout: Duration,
     tcp_handler: AnyTcpOutboundHandler,
     udp_handler: AnyUdpOutboundHandler,
 }
@@ -97,6 +114,8 @@ impl HandlerBuilder {
         Self {
             tag: "".to_string(),
             color: colored::Color::Magenta,
+            nodelay: NoDelay::Default,
+            connect_timeout: Duration::from_secs(*crate::option::OUTBOUND_DIAL_TIMEOUT),
             tcp_handler: Box::new(super::null::outbound::TcpHandler { connect: None }),
             udp_handler: Box::new(super::null::outbound::UdpHandler {
                 connect: None,
@@ -115,6 +134,16 @@ impl HandlerBuilder {
         self
     }
 
+    pub fn nodelay(mut self, v: NoDelay) -> Self {
+        self.nodelay = v;
+        self
+    }
+
+    pub fn connect_timeout(mut self, v: Duration) -> Self {
+        self.connect_timeout = v;
+        self
+    }
+
     pub fn tcp_handler(mut self, v: AnyTcpOutboundHandler) -> Self {
         self.tcp_handler = v;
         self
@@ -126,7 +155,14 @@ impl HandlerBuilder {
     }
 
     pub fn build(self) -> Arc<Handler> {
-        Handler::new(self.tag, self.color, self.tcp_handler, self.udp_handler)
+        Handler::new(
+            self.tag,
+            self.color,
+            self.nodelay,
+            self.connect_timeout,
+            self.tcp_handler,
+            self.udp_handler,
+        )
     }
 }
 
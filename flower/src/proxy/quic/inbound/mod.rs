@@ -2,4 +2,7 @@ mod udp;
 
 pub use udp::Handler as UdpHandler;
 
-use super::QuicProxyStream;
+use super::{
+    alpn_protocols, decode_packet, encode_packet, idle_timeout, send_packet, QuicProxyStream,
+    DATAGRAM_RECEIVE_BUFFER_SIZE, MAX_PACKET_SIZE,
+};
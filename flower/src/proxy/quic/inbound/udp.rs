@@ -1,38 +1,251 @@
-use std::{
-    ascii, fs, io, pin::Pin,
-    net::SocketAddr,
-    path::{self, Path, PathBuf},
-    str,
-    sync::Arc,
-};
-use std::str::FromStr;
-use anyhow::{anyhow, Context};
+use std::{collections::HashMap, io, net::SocketAddr, pin::Pin, sync::Arc};
 
 use async_trait::async_trait;
-use futures::stream::Stream;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, SelectAll, Stream, StreamExt};
 use futures::{
+    ready,
     task::{Context as TaskContext, Poll},
-    Future,
 };
 use quinn_proto::EndpointConfig;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{
+    common::cert,
+    proxy::*,
+    session::{DatagramSource, Session, SocksAddr},
+};
+
+use super::{
+    alpn_protocols, decode_packet, encode_packet, idle_timeout, send_packet, QuicProxyStream,
+    DATAGRAM_RECEIVE_BUFFER_SIZE, MAX_PACKET_SIZE,
+};
+
+// Packets queued for a UDP session before new ones are dropped.
+const SESSION_QUEUE_SIZE: usize = 64;
+
+// UDP sessions of a connection, packets starting more are dropped.
+const MAX_SESSIONS: usize = 256;
+
+// UDP packets relayed on a connection, in datagrams or on unidirectional
+// streams, demultiplexed by the session IDs chosen by the client.
+struct Relay {
+    connection: quinn::Connection,
+    datagrams: quinn::Datagrams,
+    uni_streams: quinn::IncomingUniStreams,
+    reads: FuturesUnordered<BoxFuture<'static, Result<Vec<u8>, quinn::ReadToEndError>>>,
+    sessions: HashMap<u16, mpsc::Sender<(SocksAddr, Bytes)>>,
+}
+
+impl Relay {
+    // Queues the packet to its session, returns the datagram of the session
+    // if the packet starts a new one.
+    fn dispatch(
+        &mut self,
+        packet: Bytes,
+        remote_address: SocketAddr,
+    ) -> Option<AnyBaseInboundTransport> {
+        let (session_id, addr, payload) = match decode_packet(packet) {
+            Ok(v) => v,
+            Err(e) => {
+                log::debug!("invalid quic udp packet from {}: {}", remote_address, e);
+                return None;
+            }
+        };
+        let packet = match self.sessions.get(&session_id) {
+            Some(sender) => match sender.try_send((addr, payload)) {
+                Ok(()) => return None,
+                Err(TrySendError::Full(_)) => {
+                    log::trace!("dropped quic udp packet of session {}", session_id);
+                    return None;
+                }
+                // The session has ended, start it over.
+                Err(TrySendError::Closed(packet)) => packet,
+            },
+            None => (addr, payload),
+        };
+        self.sessions.retain(|_, sender| !sender.is_closed());
+        if self.sessions.len() >= MAX_SESSIONS {
+            log::debug!(
+                "dropped quic udp packet of session {} from {}, too many sessions",
+                session_id,
+                remote_address
+            );
+            return None;
+        }
+        let (sender, receiver) = mpsc::channel(SESSION_QUEUE_SIZE);
+        let _ = sender.try_send(packet);
+        self.sessions.insert(session_id, sender);
+        Some(AnyBaseInboundTransport::Datagram(Box::new(Datagram {
+            connection: self.connection.clone(),
+            session_id,
+            source: DatagramSource::new(remote_address, Some(session_id as u64)),
+            packets: receiver,
+        })))
+    }
+
+    fn poll_session(
+        &mut self,
+        remote_address: SocketAddr,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<AnyBaseInboundTransport> {
+        loop {
+            while let Poll::Ready(Some(Ok(recv))) = self.uni_streams.poll_next_unpin(cx) {
+                self.reads.push(Box::pin(recv.read_to_end(MAX_PACKET_SIZE)));
+            }
+            let packet = match self.datagrams.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(packet))) => packet,
+                _ => match self.reads.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(packet))) => Bytes::from(packet),
+                    Poll::Ready(Some(Err(e))) => {
+                        log::debug!("read quic udp packet failed: {}", e);
+                        continue;
+                    }
+                    _ => return Poll::Pending,
+                },
+            };
+            if let Some(session) = self.dispatch(packet, remote_address) {
+                return Poll::Ready(session);
+            }
+        }
+    }
+}
+
+// Bidirectional streams and UDP sessions of an established connection, ends
+// on the first stream error.
+struct Connection {
+    remote_address: SocketAddr,
+    bi_streams: quinn::IncomingBiStreams,
+    relay: Option<Relay>,
+}
+
+impl Stream for Connection {
+    type Item = AnyBaseInboundTransport;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let remote_address = self.remote_address;
+        if let Some(relay) = self.relay.as_mut() {
+            if let Poll::Ready(session) = relay.poll_session(remote_address, cx) {
+                return Poll::Ready(Some(session));
+            }
+        }
+        match ready!(Pin::new(&mut self.bi_streams).poll_next(cx)) {
+            Some(Ok((send, recv))) => {
+                let mut sess = Session {
+                    source: self.remote_address,
+                    ..Default::default()
+                };
+                // TODO Check whether the index suitable for this purpose.
+                sess.stream_id = Some(send.id().index());
+                Poll::Ready(Some(AnyBaseInboundTransport::Stream(
+                    Box::new(QuicProxyStream { recv, send }),
+                    sess,
+                )))
+            }
+            Some(Err(e)) => {
+                log::debug!("new quic bidirectional stream failed: {}", e);
+                Poll::Ready(None)
+            }
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+// A UDP session relayed on a connection.
+struct Datagram {
+    connection: quinn::Connection,
+    session_id: u16,
+    source: DatagramSource,
+    packets: mpsc::Receiver<(SocksAddr, Bytes)>,
+}
+
+impl InboundDatagram for Datagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn InboundDatagramRecvHalf>,
+        Box<dyn InboundDatagramSendHalf>,
+    ) {
+        (
+            Box::new(DatagramRecvHalf {
+                source: self.source,
+                packets: self.packets,
+            }),
+            Box::new(DatagramSendHalf {
+                connection: self.connection,
+                session_id: self.session_id,
+            }),
+        )
+    }
+
+    fn into_std(self: Box<Self>) -> io::Result<std::net::UdpSocket> {
+        Err(io::Error::new(io::ErrorKind::Other, "quic transport"))
+    }
+}
 
-use crate::{proxy::*, session::Session};
+struct DatagramRecvHalf {
+    source: DatagramSource,
+    packets: mpsc::Receiver<(SocksAddr, Bytes)>,
+}
 
-use super::QuicProxyStream;
+#[async_trait]
+impl InboundDatagramRecvHalf for DatagramRecvHalf {
+    async fn recv_from(
+        &mut self,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, DatagramSource, Option<SocksAddr>)> {
+        let (dst_addr, payload) = self.packets.recv().await.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::ConnectionAborted, "quic connection closed")
+        })?;
+        let n = payload.len().min(buf.len());
+        if n < payload.len() {
+            log::warn!("truncated quic udp packet of {} bytes", payload.len());
+        }
+        buf[..n].copy_from_slice(&payload[..n]);
+        Ok((n, self.source, Some(dst_addr)))
+    }
+}
+
+struct DatagramSendHalf {
+    connection: quinn::Connection,
+    session_id: u16,
+}
 
+#[async_trait]
+impl InboundDatagramSendHalf for DatagramSendHalf {
+    async fn send_to(
+        &mut self,
+        buf: &[u8],
+        src_addr: Option<&SocksAddr>,
+        _dst_addr: &SocketAddr,
+    ) -> io::Result<usize> {
+        let src_addr = src_addr.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "missing udp source address")
+        })?;
+        let packet = encode_packet(self.session_id, src_addr, buf)?;
+        send_packet(&self.connection, packet).await?;
+        Ok(buf.len())
+    }
+}
+
+// Connections are polled only when woken, the handshakes by the
+// `FuturesUnordered`, the established ones by the `SelectAll`.
 struct Incoming {
     inner: quinn::Incoming,
-    connectings: Vec<quinn::Connecting>,
-    new_conns: Vec<quinn::NewConnection>,
+    datagram: bool,
+    connectings: FuturesUnordered<quinn::Connecting>,
+    conns: SelectAll<Connection>,
     incoming_closed: bool,
 }
 
 impl Incoming {
-    pub fn new(inner: quinn::Incoming) -> Self {
+    pub fn new(inner: quinn::Incoming, datagram: bool) -> Self {
         Incoming {
             inner,
-            connectings: Vec::new(),
-            new_conns: Vec::new(),
+            datagram,
+            connectings: FuturesUnordered::new(),
+            conns: SelectAll::new(),
             incoming_closed: false,
         }
     }
@@ -42,81 +255,44 @@ impl Stream for Incoming {
     type Item = AnyBaseInboundTransport;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
-        // FIXME don't iterate and poll all
-
-        if !self.incoming_closed {
+        while !self.incoming_closed {
             match Pin::new(&mut self.inner).poll_next(cx) {
-                Poll::Ready(Some(connecting)) => {
-                    self.connectings.push(connecting);
-                }
-                Poll::Ready(None) => {
-                    self.incoming_closed = true;
-                }
-                Poll::Pending => (),
-            }
-        }
-
-        let mut new_conns = Vec::new();
-        let mut completed = Vec::new();
-        for (idx, connecting) in self.connectings.iter_mut().enumerate() {
-            match Pin::new(connecting).poll(cx) {
-                Poll::Ready(Ok(new_conn)) => {
-                    new_conns.push(new_conn);
-                    completed.push(idx);
-                }
-                Poll::Ready(Err(e)) => {
-                    log::debug!("quic connect failed: {}", e);
-                    completed.push(idx);
-                }
-                Poll::Pending => (),
+                Poll::Ready(Some(connecting)) => self.connectings.push(connecting),
+                Poll::Ready(None) => self.incoming_closed = true,
+                Poll::Pending => break,
             }
         }
-        if !new_conns.is_empty() {
-            self.new_conns.append(&mut new_conns);
-        }
-        for idx in completed.iter().rev() {
-            self.connectings.swap_remove(*idx);
-        }
 
-        let mut stream: Option<Self::Item> = None;
-        let mut completed = Vec::new();
-        for (idx, new_conn) in self.new_conns.iter_mut().enumerate() {
-            match Pin::new(&mut new_conn.bi_streams).poll_next(cx) {
-                Poll::Ready(Some(Ok((send, recv)))) => {
-                    let mut sess = Session {
-                        source: new_conn.connection.remote_address(),
-                        ..Default::default()
+        while let Poll::Ready(Some(res)) = self.connectings.poll_next_unpin(cx) {
+            match res {
+                Ok(new_conn) => {
+                    let relay = if self.datagram {
+                        Some(Relay {
+                            connection: new_conn.connection.clone(),
+                            datagrams: new_conn.datagrams,
+                            uni_streams: new_conn.uni_streams,
+                            reads: FuturesUnordered::new(),
+                            sessions: HashMap::new(),
+                        })
+                    } else {
+                        None
                     };
-                    // TODO Check whether the index suitable for this purpose.
-                    sess.stream_id = Some(send.id().index());
-                    stream.replace(AnyBaseInboundTransport::Stream(
-                        Box::new(QuicProxyStream { recv, send }),
-                        sess,
-                    ));
-                    break;
-                }
-                Poll::Ready(Some(Err(e))) => {
-                    log::debug!("new quic bidirectional stream failed: {}", e);
-                    completed.push(idx);
+                    self.conns.push(Connection {
+                        remote_address: new_conn.connection.remote_address(),
+                        bi_streams: new_conn.bi_streams,
+                        relay,
+                    })
                 }
-                Poll::Ready(None) => {
-                    // FIXME what?
-                    log::warn!("quic bidirectional stream exhausted");
-                    completed.push(idx);
-                }
-                Poll::Pending => (),
+                Err(e) => log::debug!("quic connect failed: {}", e),
             }
         }
-        for idx in completed.iter().rev() {
-            self.new_conns.remove(*idx);
-        }
 
-        if let Some(stream) = stream.take() {
-            Poll::Ready(Some(stream))
-        } else if self.incoming_closed && self.connectings.is_empty() && self.new_conns.is_empty() {
-            Poll::Ready(None)
-        } else {
-            Poll::Pending
+        match self.conns.poll_next_unpin(cx) {
+            Poll::Ready(Some(stream)) => Poll::Ready(Some(stream)),
+            _ if self.incoming_closed && self.connectings.is_empty() && self.conns.is_empty() => {
+                Poll::Ready(None)
+            }
+            _ => Poll::Pending,
         }
     }
 }
@@ -128,16 +304,52 @@ where
     io::Error::new(io::ErrorKind::Other, error)
 }
 
+fn invalid_input<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
 pub struct Handler {
     certificate: String,
     certificate_key: String,
+    certificate_password: String,
+    alpn: Vec<String>,
+    idle_timeout: u32,
+    max_concurrent_bidi_streams: u32,
+    datagram: bool,
 }
 
 impl Handler {
-    pub fn new(certificate: String, certificate_key: String) -> Self {
+    /// The private key is read from `certificate` if `certificate_key` is
+    /// empty, `certificate_password` decrypts a PKCS#12 `certificate`.
+    /// Clients must offer one of the `alpn` protocols, if any.
+    ///
+    /// Idle connections are closed after `idle_timeout` seconds, or
+    /// `DEFAULT_IDLE_TIMEOUT` if it's 0. Quinn's default stream limit applies
+    /// if `max_concurrent_bidi_streams` is 0.
+    ///
+    /// UDP sessions relayed by clients in datagrams are accepted if
+    /// `datagram` is set, such an inbound can't be chained as the sessions
+    /// would bypass the protocols after it.
+    pub fn new(
+        certificate: String,
+        certificate_key: String,
+        certificate_password: String,
+        alpn: Vec<String>,
+        idle_timeout: u32,
+        max_concurrent_bidi_streams: u32,
+        datagram: bool,
+    ) -> Self {
         Self {
             certificate,
             certificate_key,
+            certificate_password,
+            alpn,
+            idle_timeout,
+            max_concurrent_bidi_streams,
+            datagram,
         }
     }
 }
@@ -151,64 +363,53 @@ impl UdpInboundHandler for Handler {
         &'a self,
         socket: Self::UDatagram,
     ) -> io::Result<InboundTransport<Self::UStream, Self::UDatagram>> {
-        let (cert, key) =
-            fs::read(&self.certificate).and_then(|x| Ok((x, fs::read(&self.certificate_key)?)))?;
-
-        let (certs, key) =  {
-            let key = fs::read(&self.certificate_key).context("failed to read private key").unwrap();
-            let key = if Path::new(&self.certificate_key).extension().map_or(false, |x| x == "der") {
-                rustls::PrivateKey(key)
-            } else {
-                let pkcs8 = rustls_pemfile::pkcs8_private_keys(&mut &*key).unwrap();
-                match pkcs8.into_iter().next() {
-                    Some(x) => rustls::PrivateKey(x),
-                    None => {
-                        let rsa = rustls_pemfile::rsa_private_keys(&mut &*key)
-                            .context("malformed PKCS #1 private key").unwrap();
-                        if let Some(x) = rsa.into_iter().next() {
-                             rustls::PrivateKey(x)
-                        } else {
-                            rustls::PrivateKey(Vec::new()) // FIXME return errors
-                        }
-                    }
-                }
-            };
-            let cert_chain = fs::read(&self.certificate).context("failed to read certificate chain").unwrap();
-            let cert_chain = if Path::new(&self.certificate).extension().map_or(false, |x| x == "der") {
-                vec![rustls::Certificate(cert_chain)]
-            } else {
-                rustls_pemfile::certs(&mut &*cert_chain)
-                    .context("invalid PEM-encoded certificate")
-                    .unwrap()
-                    .into_iter()
-                    .map(rustls::Certificate)
-                    .collect()
-            };
-
-            (cert_chain, key)
-        };
+        let certified = cert::load(
+            &self.certificate,
+            &self.certificate_key,
+            &self.certificate_password,
+        )?;
+        let certs = certified
+            .certs
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        let key = rustls::PrivateKey(certified.key);
 
         let mut server_crypto = rustls::ServerConfig::builder()
             .with_safe_defaults()
             .with_no_client_auth()
             .with_single_cert(certs, key)
-            .unwrap();
-        // server_crypto.alpn_protocols = common::ALPN_QUIC_HTTP.iter().map(|&x| x.into()).collect();
+            .map_err(|e| invalid_input(format!("invalid certificate or key: {}", e)))?;
+        server_crypto.alpn_protocols = alpn_protocols(&self.alpn);
 
         let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(server_crypto));
         let mut transport_config = quinn::TransportConfig::default();
-        transport_config
-            .max_concurrent_uni_streams(0_u8.into())
-            .max_idle_timeout(Some(std::time::Duration::from_secs(300).try_into().unwrap()));
+        transport_config.max_idle_timeout(Some(idle_timeout(self.idle_timeout)));
+        if self.max_concurrent_bidi_streams > 0 {
+            transport_config.max_concurrent_bidi_streams(self.max_concurrent_bidi_streams.into());
+        }
+        if self.datagram {
+            // Packets too large for a datagram come on unidirectional streams.
+            transport_config
+                .max_concurrent_uni_streams(100_u8.into())
+                .datagram_receive_buffer_size(Some(DATAGRAM_RECEIVE_BUFFER_SIZE));
+        } else {
+            transport_config
+                .max_concurrent_uni_streams(0_u8.into())
+                .datagram_receive_buffer_size(None);
+        }
         server_config.transport = Arc::new(transport_config);
 
-        let (endpoint, mut incoming) = quinn::Endpoint::new(EndpointConfig::default(),
-                                                            Some(server_config),
-                                                            socket.into_std().unwrap())?;
+        let (endpoint, incoming) = quinn::Endpoint::new(
+            EndpointConfig::default(),
+            Some(server_config),
+            socket.into_std()?,
+        )?;
 
-        debug!("listening on: {}",endpoint.local_addr()?);
+        debug!("listening on: {}", endpoint.local_addr()?);
         Ok(InboundTransport::Incoming(Box::new(Incoming::new(
             incoming,
+            self.datagram,
         ))))
     }
 }
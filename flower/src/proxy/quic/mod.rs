@@ -1,13 +1,104 @@
-use std::{io, pin::Pin};
+use std::{convert::TryFrom, io, pin::Pin};
 
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::task::{Context, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
+use crate::session::{SocksAddr, SocksAddrWireType};
+
 #[cfg(feature = "inbound-quic")]
 pub mod inbound;
 #[cfg(feature = "outbound-quic")]
 pub mod outbound;
 
+/// Seconds before an idle connection is closed by default.
+pub const DEFAULT_IDLE_TIMEOUT: u32 = 300;
+
+/// Returns the protocols in wire format. No ALPN is negotiated if `alpn` is
+/// empty, so peers without the setting keep working with each other.
+pub fn alpn_protocols(alpn: &[String]) -> Vec<Vec<u8>> {
+    alpn.iter().map(|x| x.as_bytes().to_vec()).collect()
+}
+
+/// Returns the idle timeout, `DEFAULT_IDLE_TIMEOUT` if `secs` is 0.
+pub fn idle_timeout(secs: u32) -> quinn::IdleTimeout {
+    let secs = if secs == 0 {
+        DEFAULT_IDLE_TIMEOUT
+    } else {
+        secs
+    };
+    // Fails only beyond 2^62 milliseconds.
+    std::time::Duration::from_secs(secs as u64)
+        .try_into()
+        .unwrap()
+}
+
+/// Bytes buffered for incoming datagrams of a connection.
+pub const DATAGRAM_RECEIVE_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// The largest UDP packet relayed on a unidirectional stream.
+pub const MAX_PACKET_SIZE: usize = 64 * 1024;
+
+/// Encodes a UDP packet relayed on a connection, `addr` is the destination of
+/// a packet from the client, or the origin of a packet to the client.
+pub fn encode_packet(session_id: u16, addr: &SocksAddr, payload: &[u8]) -> io::Result<Bytes> {
+    let mut buf = BytesMut::with_capacity(2 + addr.size() + payload.len());
+    buf.put_u16(session_id);
+    addr.write_buf(&mut buf, SocksAddrWireType::PortLast)?;
+    buf.put_slice(payload);
+    Ok(buf.freeze())
+}
+
+/// Decodes a UDP packet relayed on a connection, returns the session ID, the
+/// address and the payload.
+pub fn decode_packet(mut buf: Bytes) -> io::Result<(u16, SocksAddr, Bytes)> {
+    if buf.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid quic udp packet",
+        ));
+    }
+    let session_id = buf.get_u16();
+    let addr = SocksAddr::try_from((&buf[..], SocksAddrWireType::PortLast))?;
+    buf.advance(addr.size());
+    Ok((session_id, addr, buf))
+}
+
+/// How long a UDP packet waits for the peer to allow a new unidirectional
+/// stream.
+pub const OPEN_UNI_STREAM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Sends a UDP packet in a datagram, or on a unidirectional stream if the
+/// peer doesn't support datagrams or the packet is too large for one.
+pub async fn send_packet(connection: &quinn::Connection, packet: Bytes) -> io::Result<()> {
+    if connection
+        .max_datagram_size()
+        .map_or(false, |max| packet.len() <= max)
+    {
+        match connection.send_datagram(packet.clone()) {
+            Ok(()) => return Ok(()),
+            Err(e) => log::trace!("send quic datagram failed: {}", e),
+        }
+    }
+    // Never opens if the peer doesn't allow unidirectional streams.
+    let mut send = tokio::time::timeout(OPEN_UNI_STREAM_TIMEOUT, connection.open_uni())
+        .await
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                "open quic stream for udp packet timed out",
+            )
+        })?
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    send.write_all(&packet)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    send.finish()
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    Ok(())
+}
+
 pub struct QuicProxyStream<R, W> {
     recv: R,
     send: W,
@@ -40,3 +131,28 @@ impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> AsyncWrite for QuicProxyStream
         Pin::new(&mut self.send).poll_shutdown(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet() {
+        let addr = SocksAddr::Domain("example.com".to_string(), 53);
+        let packet = encode_packet(7, &addr, b"query").unwrap();
+        let (session_id, decoded, payload) = decode_packet(packet).unwrap();
+        assert_eq!(session_id, 7);
+        assert_eq!(decoded.to_string(), addr.to_string());
+        assert_eq!(&payload[..], b"query");
+
+        let addr = SocksAddr::from("1.2.3.4:5".parse::<std::net::SocketAddr>().unwrap());
+        let packet = encode_packet(u16::MAX, &addr, b"").unwrap();
+        let (session_id, decoded, payload) = decode_packet(packet).unwrap();
+        assert_eq!(session_id, u16::MAX);
+        assert_eq!(decoded.to_string(), addr.to_string());
+        assert!(payload.is_empty());
+
+        assert!(decode_packet(Bytes::from_static(&[0])).is_err());
+        assert!(decode_packet(Bytes::from_static(&[0, 1, 9])).is_err());
+    }
+}
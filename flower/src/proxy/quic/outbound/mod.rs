@@ -1,5 +1,11 @@
+mod pool;
 mod tcp;
-pub const ALPN_QUIC_HTTP: &[&[u8]] = &[b"hq-29"];
+mod udp;
+mod zero_rtt;
 pub use tcp::Handler as TcpHandler;
+pub use udp::Handler as UdpHandler;
 
-use super::QuicProxyStream;
+use super::{
+    alpn_protocols, decode_packet, encode_packet, idle_timeout, send_packet, QuicProxyStream,
+    DATAGRAM_RECEIVE_BUFFER_SIZE, MAX_PACKET_SIZE,
+};
@@ -0,0 +1,237 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+// Value of `PoolEntry::active` once the connection is evicted.
+const EVICTED: usize = usize::MAX;
+
+lazy_static! {
+    /// Connections pooled by all QUIC outbounds.
+    pub static ref POOL: Pool = Pool::new(*crate::option::QUIC_MAX_POOLED_CONNECTIONS);
+}
+
+/// A pooled connection.
+pub struct PoolEntry {
+    // Number of open streams.
+    active: AtomicUsize,
+    last_used: Mutex<Instant>,
+    close: Box<dyn Fn() + Send + Sync>,
+}
+
+impl PoolEntry {
+    pub fn is_evicted(&self) -> bool {
+        self.active.load(Ordering::Acquire) == EVICTED
+    }
+
+    /// Marks a new stream open on the connection, fails if the connection is
+    /// evicted.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<StreamGuard> {
+        let mut active = self.active.load(Ordering::Acquire);
+        loop {
+            if active == EVICTED {
+                return None;
+            }
+            match self.active.compare_exchange(
+                active,
+                active + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(v) => active = v,
+            }
+        }
+        *self.last_used.lock().unwrap() = Instant::now();
+        Some(StreamGuard {
+            entry: self.clone(),
+        })
+    }
+}
+
+/// Keeps a connection in use while a stream on it is open.
+pub struct StreamGuard {
+    entry: Arc<PoolEntry>,
+}
+
+impl StreamGuard {
+    pub fn entry(&self) -> Arc<PoolEntry> {
+        self.entry.clone()
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        *self.entry.last_used.lock().unwrap() = Instant::now();
+        self.entry.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Caps the total number of pooled connections. Only idle connections are
+/// evicted, so the cap may be exceeded while all connections are in use.
+pub struct Pool {
+    cap: usize,
+    entries: Mutex<Vec<Arc<PoolEntry>>>,
+}
+
+impl Pool {
+    pub fn new(cap: usize) -> Self {
+        Pool {
+            cap,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Adds a connection with its first stream open, `close` is called if the
+    /// connection is evicted later.
+    pub fn register<F>(&self, close: F) -> StreamGuard
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let entry = Arc::new(PoolEntry {
+            active: AtomicUsize::new(1),
+            last_used: Mutex::new(Instant::now()),
+            close: Box::new(close),
+        });
+        self.entries.lock().unwrap().push(entry.clone());
+        self.evict();
+        StreamGuard { entry }
+    }
+
+    /// Removes a connection no longer in use by its outbound.
+    pub fn remove(&self, entry: &Arc<PoolEntry>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|e| !Arc::ptr_eq(e, entry));
+    }
+
+    pub fn size(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    fn evict(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        while entries.len() > self.cap {
+            let lru = entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.active.load(Ordering::Acquire) == 0)
+                .min_by_key(|(_, e)| *e.last_used.lock().unwrap())
+                .map(|(i, _)| i);
+            let i = match lru {
+                Some(i) => i,
+                None => break,
+            };
+            // A stream may have been opened since.
+            if entries[i]
+                .active
+                .compare_exchange(0, EVICTED, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                continue;
+            }
+            let entry = entries.remove(i);
+            (entry.close)();
+            log::debug!("evicted idle quic connection, {} pooled", entries.len());
+        }
+    }
+}
+
+/// A stream holding its connection in use until dropped.
+pub struct PooledStream<T> {
+    inner: T,
+    _guard: StreamGuard,
+}
+
+impl<T> PooledStream<T> {
+    pub fn new(inner: T, guard: StreamGuard) -> Self {
+        PooledStream {
+            inner,
+            _guard: guard,
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for PooledStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for PooledStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+
+    fn register(pool: &Pool) -> (StreamGuard, Arc<AtomicBool>) {
+        let closed = Arc::new(AtomicBool::new(false));
+        let closed2 = closed.clone();
+        let guard = pool.register(move || closed2.store(true, Ordering::Relaxed));
+        (guard, closed)
+    }
+
+    #[test]
+    fn test_pool_evict_lru_idle() {
+        let pool = Pool::new(2);
+        let (g1, closed1) = register(&pool);
+        let (g2, closed2) = register(&pool);
+        let (e1, e2) = (g1.entry(), g2.entry());
+        drop(g1);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        drop(g2);
+
+        // The oldest idle connection goes.
+        let (g3, closed3) = register(&pool);
+        assert_eq!(pool.size(), 2);
+        assert!(closed1.load(Ordering::Relaxed));
+        assert!(e1.is_evicted());
+        assert!(e1.try_acquire().is_none());
+        assert!(!closed2.load(Ordering::Relaxed));
+
+        // Connections with open streams are kept even if older.
+        let _s2 = e2.try_acquire().unwrap();
+        let (_g4, closed4) = register(&pool);
+        assert_eq!(pool.size(), 3);
+        assert!(!closed2.load(Ordering::Relaxed));
+        assert!(!closed3.load(Ordering::Relaxed));
+
+        // Until they become idle.
+        drop(g3);
+        let (_g5, _) = register(&pool);
+        assert!(closed3.load(Ordering::Relaxed));
+        assert!(!closed4.load(Ordering::Relaxed));
+        assert_eq!(pool.size(), 3);
+
+        pool.remove(&e2);
+        assert_eq!(pool.size(), 2);
+    }
+}
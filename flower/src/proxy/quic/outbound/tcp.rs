@@ -1,17 +1,23 @@
 use std::fs;
 use std::io;
-use std::net::SocketAddr;
+use std::net::{Ipv6Addr, SocketAddr};
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use futures::future::{FutureExt, Shared};
 use futures::TryFutureExt;
 use rustls::{OwnedTrustAnchor, RootCertStore};
 use tokio::sync::Mutex;
+use tokio::time::timeout;
 
-use crate::{app::SyncDnsClient, proxy::*, session::Session};
+use crate::{app::SyncDnsClient, common::net::happy_eyeballs, proxy::*, session::Session};
 
-use super::QuicProxyStream;
+use super::pool::{PoolEntry, PooledStream, StreamGuard, POOL};
+use super::udp::Sessions;
+use super::zero_rtt::ZeroRttStream;
+use super::{alpn_protocols, idle_timeout, QuicProxyStream, DATAGRAM_RECEIVE_BUFFER_SIZE};
 
 fn quic_err<E>(error: E) -> io::Error
 where
@@ -20,42 +26,72 @@ where
     io::Error::new(io::ErrorKind::Other, error)
 }
 
+// Fails unless one of the offered protocols is negotiated, if any.
+fn check_alpn(offered: &[Vec<u8>], negotiated: Option<&[u8]>) -> io::Result<()> {
+    if offered.is_empty() {
+        return Ok(());
+    }
+    match negotiated {
+        Some(p) if offered.iter().any(|x| x == p) => Ok(()),
+        Some(p) => Err(quic_err(format!(
+            "unexpected alpn {} negotiated",
+            String::from_utf8_lossy(p)
+        ))),
+        None => Err(quic_err("no alpn negotiated")),
+    }
+}
+
+/// Streams opened on a connection before a new one is made by default.
+pub const DEFAULT_STREAMS_PER_CONNECTION: usize = 128;
+
 struct Connection {
-    pub new_conn: quinn::NewConnection,
+    pub connection: quinn::Connection,
+    pub sessions: Arc<Sessions>,
+    // Resolves once the handshake completes if the connection was made in
+    // 0-RTT.
+    pub accepted: Option<Shared<quinn::ZeroRttAccepted>>,
+    pub pool_entry: Arc<PoolEntry>,
     pub total_accepted: usize,
     pub completed: bool,
 }
 
-struct Manager {
+pub struct Manager {
     address: String,
     port: u16,
     server_name: Option<String>,
     dns_client: SyncDnsClient,
+    alpn: Vec<Vec<u8>>,
+    streams_per_connection: usize,
+    datagram: bool,
+    zero_rtt: bool,
+    handshake_timeout: Duration,
     client_config: quinn::ClientConfig,
     connections: Mutex<Vec<Connection>>,
 }
 
 impl Manager {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         address: String,
         port: u16,
         server_name: Option<String>,
         certificate: Option<String>,
+        alpn: Vec<String>,
+        idle_timeout_secs: u32,
+        streams_per_connection: usize,
+        datagram: bool,
+        zero_rtt: bool,
+        handshake_timeout: Duration,
         dns_client: SyncDnsClient,
     ) -> Self {
         let mut root_certs = RootCertStore::empty();
-        root_certs.add_server_trust_anchors(
-            webpki_roots::TLS_SERVER_ROOTS
-                .0
-                .iter()
-                .map(|ta| {
-                    OwnedTrustAnchor::from_subject_spki_name_constraints(
-                        ta.subject,
-                        ta.spki,
-                        ta.name_constraints,
-                    )
-                }),
-        );
+        root_certs.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
 
         if let Some(cert_path) = certificate.as_ref() {
             match fs::read(cert_path) {
@@ -75,13 +111,18 @@ impl Manager {
             .with_root_certificates(root_certs)
             .with_no_client_auth();
         crypto_config.enable_early_data = true;
-        // crypto_config.alpn_protocols = ALPN_QUIC_HTTP.iter().map(|&x| x.into()).collect();
+        let alpn = alpn_protocols(&alpn);
+        crypto_config.alpn_protocols = alpn.clone();
 
         let mut client_config = quinn::ClientConfig::new(Arc::new(crypto_config));
 
         let mut transport_config = quinn::TransportConfig::default();
-        transport_config
-            .max_idle_timeout(Some(std::time::Duration::from_secs(300).try_into().unwrap()));
+        transport_config.max_idle_timeout(Some(idle_timeout(idle_timeout_secs)));
+        transport_config.datagram_receive_buffer_size(if datagram {
+            Some(DATAGRAM_RECEIVE_BUFFER_SIZE)
+        } else {
+            None
+        });
         client_config.transport = Arc::new(transport_config);
 
         Manager {
@@ -89,6 +130,15 @@ impl Manager {
             port,
             server_name,
             dns_client,
+            alpn,
+            streams_per_connection: if streams_per_connection == 0 {
+                DEFAULT_STREAMS_PER_CONNECTION
+            } else {
+                streams_per_connection
+            },
+            datagram,
+            zero_rtt,
+            handshake_timeout,
             client_config,
             connections: Mutex::new(Vec::new()),
         }
@@ -96,23 +146,43 @@ impl Manager {
 }
 
 impl Manager {
-    pub async fn new_stream(
-        &self,
-    ) -> io::Result<QuicProxyStream<quinn::RecvStream, quinn::SendStream>> {
-        self.connections.lock().await.retain(|c| !c.completed);
+    async fn retain_connections(&self) {
+        self.connections.lock().await.retain(|c| {
+            if c.completed || c.pool_entry.is_evicted() {
+                POOL.remove(&c.pool_entry);
+                return false;
+            }
+            true
+        });
+    }
+
+    pub async fn new_stream(&self) -> io::Result<PooledStream<ZeroRttStream>> {
+        self.retain_connections().await;
 
         for conn in self.connections.lock().await.iter_mut() {
-            if conn.total_accepted < 128 {
+            if conn.total_accepted < self.streams_per_connection {
+                let guard = match conn.pool_entry.try_acquire() {
+                    Some(g) => g,
+                    None => {
+                        conn.completed = true;
+                        continue;
+                    }
+                };
                 // FIXME I think awaiting here is fine, it should return immediately, not sure.
-                match conn.new_conn.connection.open_bi().await {
+                match conn.connection.open_bi().await {
                     Ok((send, recv)) => {
                         conn.total_accepted += 1;
                         log::trace!(
                             "opened quic stream on connection with rtt {}ms, total_accepted {}",
-                            conn.new_conn.connection.rtt().as_millis(),
+                            conn.connection.rtt().as_millis(),
                             conn.total_accepted,
                         );
-                        return Ok(QuicProxyStream { recv, send });
+                        let stream = ZeroRttStream::new(
+                            conn.connection.clone(),
+                            QuicProxyStream { recv, send },
+                            conn.accepted.clone(),
+                        );
+                        return Ok(PooledStream::new(stream, guard));
                     }
                     Err(e) => {
                         conn.completed = true;
@@ -124,9 +194,43 @@ impl Manager {
             }
         }
 
-        let mut endpoint = quinn::Endpoint::client(*crate::option::UNSPECIFIED_BIND_ADDR)?;
-        endpoint.set_default_client_config(self.client_config.clone());
+        let (new_conn, accepted) = self.connect().await?;
+        let (send, recv) = new_conn.connection.open_bi().await.map_err(quic_err)?;
+        let stream = ZeroRttStream::new(
+            new_conn.connection.clone(),
+            QuicProxyStream { recv, send },
+            accepted.clone(),
+        );
+        let (_, _, guard) = self.add_connection(new_conn, accepted).await;
+        Ok(PooledStream::new(stream, guard))
+    }
+
+    /// Returns a connection to relay a new UDP session on, along with its
+    /// sessions, the session keeps the connection in use until the guard is
+    /// dropped.
+    pub async fn new_session(&self) -> io::Result<(quinn::Connection, Arc<Sessions>, StreamGuard)> {
+        self.retain_connections().await;
+
+        for conn in self.connections.lock().await.iter_mut() {
+            if conn.total_accepted < self.streams_per_connection {
+                if let Some(guard) = conn.pool_entry.try_acquire() {
+                    conn.total_accepted += 1;
+                    return Ok((conn.connection.clone(), conn.sessions.clone(), guard));
+                }
+            }
+            conn.completed = true;
+        }
+
+        let (new_conn, accepted) = self.connect().await?;
+        Ok(self.add_connection(new_conn, accepted).await)
+    }
 
+    // Connects in 0-RTT if enabled and a session ticket of the server is
+    // cached, returns a future resolving once the handshake completes if so.
+    // Otherwise the handshakes with the addresses of the server race.
+    async fn connect(
+        &self,
+    ) -> io::Result<(quinn::NewConnection, Option<Shared<quinn::ZeroRttAccepted>>)> {
         let ips = {
             self.dns_client
                 .read()
@@ -146,54 +250,203 @@ impl Manager {
                 "could not resolve to any address",
             ));
         }
-        let connect_addr = SocketAddr::new(ips[0], self.port);
+
+        if self.zero_rtt {
+            // Early data can't be raced, it goes to the first address.
+            let connect_addr = SocketAddr::new(ips[0], self.port);
+            let connecting = self.connecting(connect_addr).await?;
+            match connecting.into_0rtt() {
+                Ok((new_conn, accepted)) => {
+                    // The ALPN is the one negotiated on the resumed session.
+                    log::trace!("quic 0-rtt connection to {}", connect_addr);
+                    return Ok((new_conn, Some(accepted.shared())));
+                }
+                Err(connecting) => {
+                    return Ok((self.handshake(connect_addr, connecting).await?, None));
+                }
+            }
+        }
+
+        let new_conn = happy_eyeballs(
+            ips,
+            self.port,
+            Duration::from_millis(*crate::option::OUTBOUND_DIAL_ATTEMPT_DELAY),
+            |connect_addr| async move {
+                let connecting = self.connecting(connect_addr).await?;
+                self.handshake(connect_addr, connecting).await
+            },
+        )
+        .await?;
+        Ok((new_conn, None))
+    }
+
+    async fn connecting(&self, connect_addr: SocketAddr) -> io::Result<quinn::Connecting> {
+        // An IPv4 socket can't reach IPv6 addresses.
+        let bind_addr = match (connect_addr, *crate::option::UNSPECIFIED_BIND_ADDR) {
+            (SocketAddr::V6(..), SocketAddr::V4(..)) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            (_, bind_addr) => bind_addr,
+        };
+        let socket = std::net::UdpSocket::bind(bind_addr)?;
+        #[cfg(target_os = "android")]
+        crate::common::net::protect_socket(std::os::unix::io::AsRawFd::as_raw_fd(&socket)).await?;
+        let (mut endpoint, _) =
+            quinn::Endpoint::new(quinn::EndpointConfig::default(), None, socket)?;
+        endpoint.set_default_client_config(self.client_config.clone());
 
         let server_name = if let Some(name) = self.server_name.as_ref() {
             name
         } else {
             &self.address
         };
-
-        let new_conn = endpoint
+        endpoint
             .connect(connect_addr, server_name)
-            .map_err(quic_err)?
+            .map_err(quic_err)
+    }
+
+    async fn handshake(
+        &self,
+        connect_addr: SocketAddr,
+        connecting: quinn::Connecting,
+    ) -> io::Result<quinn::NewConnection> {
+        let new_conn = timeout(self.handshake_timeout, connecting)
             .await
-            .map_err(quic_err)?;
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("quic handshake with {} timed out", connect_addr),
+                )
+            })?
+            .map_err(|e| {
+                quic_err(format!(
+                    "quic handshake with {} failed: {}",
+                    connect_addr, e
+                ))
+            })?;
 
-        let (send, recv) = new_conn.connection.open_bi().await.map_err(quic_err)?;
+        let protocol = new_conn
+            .connection
+            .handshake_data()
+            .and_then(|x| x.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+            .and_then(|x| x.protocol);
+        if let Err(e) = check_alpn(&self.alpn, protocol.as_deref()) {
+            new_conn
+                .connection
+                .close(quinn::VarInt::from_u32(0), b"alpn mismatch");
+            return Err(e);
+        }
+        if self.datagram && new_conn.connection.max_datagram_size().is_none() {
+            log::debug!(
+                "quic datagrams not supported by {}, relaying udp on streams",
+                connect_addr
+            );
+        }
+        Ok(new_conn)
+    }
+
+    // Pools the connection with its first stream or session open.
+    async fn add_connection(
+        &self,
+        new_conn: quinn::NewConnection,
+        accepted: Option<Shared<quinn::ZeroRttAccepted>>,
+    ) -> (quinn::Connection, Arc<Sessions>, StreamGuard) {
+        let quinn::NewConnection {
+            connection,
+            uni_streams,
+            datagrams,
+            ..
+        } = new_conn;
+        let sessions = Arc::new(Sessions::default());
+        if self.datagram {
+            sessions.clone().serve(datagrams, uni_streams);
+        }
+
+        let connection2 = connection.clone();
+        let guard = POOL.register(move || {
+            connection2.close(quinn::VarInt::from_u32(0), b"");
+        });
 
         self.connections.lock().await.push(Connection {
-            new_conn,
+            connection: connection.clone(),
+            sessions: sessions.clone(),
+            accepted,
+            pool_entry: guard.entry(),
             total_accepted: 1,
             completed: false,
         });
 
-        Ok(QuicProxyStream { recv, send })
+        (connection, sessions, guard)
+    }
+}
+
+impl Drop for Manager {
+    fn drop(&mut self) {
+        for conn in self.connections.get_mut().iter() {
+            POOL.remove(&conn.pool_entry);
+        }
     }
 }
 
 impl UdpConnector for Manager {}
 
 pub struct Handler {
-    manager: Manager,
+    manager: Arc<Manager>,
 }
 
 impl Handler {
+    /// Idle connections are closed after `idle_timeout` seconds, or
+    /// `DEFAULT_IDLE_TIMEOUT` if it's 0. A new connection is made once
+    /// `streams_per_connection` streams, or `DEFAULT_STREAMS_PER_CONNECTION`
+    /// if it's 0, have been opened on the current ones.
+    ///
+    /// UDP sessions are relayed in datagrams, or on streams if the server
+    /// doesn't support them, if `datagram` is set.
+    ///
+    /// New connections are made in 0-RTT if `zero_rtt` is set and a session
+    /// ticket of the server is cached, the first data of the streams is then
+    /// sent before the handshake completes, and written again if the server
+    /// rejects it. Early data can be replayed by an attacker, so it should
+    /// only be enabled if the server tolerates replayed proxy requests.
+    ///
+    /// Handshakes fail with `io::ErrorKind::TimedOut` if not completed
+    /// within `handshake_timeout`.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         address: String,
         port: u16,
         server_name: Option<String>,
         certificate: Option<String>,
+        alpn: Vec<String>,
+        idle_timeout: u32,
+        streams_per_connection: usize,
+        datagram: bool,
+        zero_rtt: bool,
+        handshake_timeout: Duration,
         dns_client: SyncDnsClient,
     ) -> Self {
         Self {
-            manager: Manager::new(address, port, server_name, certificate, dns_client),
+            manager: Arc::new(Manager::new(
+                address,
+                port,
+                server_name,
+                certificate,
+                alpn,
+                idle_timeout,
+                streams_per_connection,
+                datagram,
+                zero_rtt,
+                handshake_timeout,
+                dns_client,
+            )),
         }
     }
 
-    pub async fn new_stream(
-        &self,
-    ) -> io::Result<QuicProxyStream<quinn::RecvStream, quinn::SendStream>> {
+    /// Returns a UDP handler relaying sessions on the connections of this
+    /// handler.
+    pub fn udp_handler(&self) -> super::UdpHandler {
+        super::UdpHandler::new(self.manager.clone())
+    }
+
+    pub async fn new_stream(&self) -> io::Result<PooledStream<ZeroRttStream>> {
         self.manager.new_stream().await
     }
 }
@@ -216,3 +469,19 @@ impl TcpOutboundHandler for Handler {
         Ok(Box::new(self.new_stream().await?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_alpn() {
+        assert!(check_alpn(&alpn_protocols(&[]), None).is_ok());
+        let offered = alpn_protocols(&["hq-29".to_string()]);
+        assert!(check_alpn(&offered, Some(&b"hq-29"[..])).is_ok());
+        assert!(check_alpn(&offered, Some(&b"h3"[..])).is_err());
+        assert!(check_alpn(&offered, None).is_err());
+        let offered = alpn_protocols(&["h3".to_string()]);
+        assert!(check_alpn(&offered, Some(&b"h3"[..])).is_ok());
+    }
+}
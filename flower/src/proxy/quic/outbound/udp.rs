@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::StreamExt;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{
+    proxy::*,
+    session::{Session, SocksAddr},
+};
+
+use super::pool::StreamGuard;
+use super::tcp::Manager;
+use super::{decode_packet, encode_packet, send_packet, MAX_PACKET_SIZE};
+
+// Packets queued for a UDP session before new ones are dropped.
+const SESSION_QUEUE_SIZE: usize = 64;
+
+/// The UDP sessions relayed on a connection.
+#[derive(Default)]
+pub struct Sessions {
+    next_id: AtomicU16,
+    senders: Mutex<HashMap<u16, mpsc::Sender<(SocksAddr, Bytes)>>>,
+}
+
+impl Sessions {
+    fn add(&self) -> (u16, mpsc::Receiver<(SocksAddr, Bytes)>) {
+        let (sender, receiver) = mpsc::channel(SESSION_QUEUE_SIZE);
+        let mut senders = self.senders.lock().unwrap();
+        loop {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            if let std::collections::hash_map::Entry::Vacant(e) = senders.entry(id) {
+                e.insert(sender);
+                return (id, receiver);
+            }
+        }
+    }
+
+    fn remove(&self, id: u16) {
+        self.senders.lock().unwrap().remove(&id);
+    }
+
+    fn dispatch(&self, packet: Bytes) {
+        let (session_id, addr, payload) = match decode_packet(packet) {
+            Ok(v) => v,
+            Err(e) => {
+                log::debug!("invalid quic udp packet: {}", e);
+                return;
+            }
+        };
+        let senders = self.senders.lock().unwrap();
+        if let Some(sender) = senders.get(&session_id) {
+            if let Err(TrySendError::Full(_)) = sender.try_send((addr, payload)) {
+                log::trace!("dropped quic udp packet of session {}", session_id);
+            }
+        }
+    }
+
+    /// Receives the packets of the sessions until the connection closes.
+    pub fn serve(
+        self: Arc<Self>,
+        mut datagrams: quinn::Datagrams,
+        mut uni_streams: quinn::IncomingUniStreams,
+    ) {
+        let sessions = self.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(packet)) = datagrams.next().await {
+                sessions.dispatch(packet);
+            }
+        });
+        tokio::spawn(async move {
+            while let Some(Ok(recv)) = uni_streams.next().await {
+                let sessions = self.clone();
+                tokio::spawn(async move {
+                    match recv.read_to_end(MAX_PACKET_SIZE).await {
+                        Ok(packet) => sessions.dispatch(Bytes::from(packet)),
+                        Err(e) => log::debug!("read quic udp packet failed: {}", e),
+                    }
+                });
+            }
+        });
+    }
+}
+
+// A UDP session on a connection, the session ID is released once both halves
+// drop.
+struct Flow {
+    connection: quinn::Connection,
+    sessions: Arc<Sessions>,
+    session_id: u16,
+    _guard: StreamGuard,
+}
+
+impl Drop for Flow {
+    fn drop(&mut self) {
+        self.sessions.remove(self.session_id);
+    }
+}
+
+pub struct Datagram {
+    flow: Arc<Flow>,
+    packets: mpsc::Receiver<(SocksAddr, Bytes)>,
+    destination: Option<SocksAddr>,
+}
+
+impl OutboundDatagram for Datagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ) {
+        (
+            Box::new(DatagramRecvHalf {
+                _flow: self.flow.clone(),
+                packets: self.packets,
+                destination: self.destination,
+            }),
+            Box::new(DatagramSendHalf(self.flow)),
+        )
+    }
+}
+
+pub struct DatagramRecvHalf {
+    _flow: Arc<Flow>,
+    packets: mpsc::Receiver<(SocksAddr, Bytes)>,
+    destination: Option<SocksAddr>,
+}
+
+#[async_trait]
+impl OutboundDatagramRecvHalf for DatagramRecvHalf {
+    async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocksAddr)> {
+        let (src_addr, payload) = self.packets.recv().await.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::ConnectionAborted, "quic connection closed")
+        })?;
+        let n = payload.len().min(buf.len());
+        if n < payload.len() {
+            log::warn!("truncated quic udp packet of {} bytes", payload.len());
+        }
+        buf[..n].copy_from_slice(&payload[..n]);
+        // Must be a domain destination.
+        if let Some(destination) = self.destination.as_ref() {
+            return Ok((n, destination.clone()));
+        }
+        Ok((n, src_addr))
+    }
+}
+
+pub struct DatagramSendHalf(Arc<Flow>);
+
+#[async_trait]
+impl OutboundDatagramSendHalf for DatagramSendHalf {
+    async fn send_to(&mut self, buf: &[u8], dst_addr: &SocksAddr) -> io::Result<usize> {
+        let packet = encode_packet(self.0.session_id, dst_addr, buf)?;
+        send_packet(&self.0.connection, packet).await?;
+        Ok(buf.len())
+    }
+}
+
+pub struct Handler {
+    manager: Arc<Manager>,
+}
+
+impl Handler {
+    pub(super) fn new(manager: Arc<Manager>) -> Self {
+        Self { manager }
+    }
+}
+
+#[async_trait]
+impl UdpOutboundHandler for Handler {
+    type UStream = AnyStream;
+    type Datagram = AnyOutboundDatagram;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        Some(OutboundConnect::NoConnect)
+    }
+
+    fn transport_type(&self) -> DatagramTransportType {
+        DatagramTransportType::Undefined
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        _transport: Option<OutboundTransport<Self::UStream, Self::Datagram>>,
+    ) -> io::Result<Self::Datagram> {
+        let (connection, sessions, guard) = self.manager.new_session().await?;
+        let (session_id, packets) = sessions.add();
+        let destination = match &sess.destination {
+            SocksAddr::Domain(domain, port) => {
+                Some(SocksAddr::Domain(domain.to_owned(), port.to_owned()))
+            }
+            _ => None,
+        };
+        Ok(Box::new(Datagram {
+            flow: Arc::new(Flow {
+                connection,
+                sessions,
+                session_id,
+                _guard: guard,
+            }),
+            packets,
+            destination,
+        }))
+    }
+}
+
+#[cfg(all(test, feature = "inbound-quic"))]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use crate::proxy::datagram::SimpleInboundDatagram;
+
+    use super::*;
+
+    async fn new_datagram(
+        client: &Handler,
+    ) -> (
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ) {
+        UdpOutboundHandler::handle(client, &Session::default(), None)
+            .await
+            .unwrap()
+            .split()
+    }
+
+    type InboundSession = (
+        Box<dyn InboundDatagramRecvHalf>,
+        Box<dyn InboundDatagramSendHalf>,
+    );
+
+    // Returns the next UDP session, none if there's no new one in a second.
+    async fn next_session(
+        sessions: &mut mpsc::UnboundedReceiver<InboundSession>,
+    ) -> Option<InboundSession> {
+        tokio::time::timeout(Duration::from_secs(1), sessions.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[test]
+    fn test_datagram_relay() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let dir = std::env::temp_dir().join(format!("flower-quic-udp-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            fs::write(path("cert.der"), cert.serialize_der().unwrap()).unwrap();
+            fs::write(path("key.der"), cert.serialize_private_key_der()).unwrap();
+
+            let server = crate::proxy::quic::inbound::UdpHandler::new(
+                path("cert.der"),
+                path("key.der"),
+                "".to_string(),
+                Vec::new(),
+                0,
+                0,
+                true,
+            );
+            let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let port = socket.local_addr().unwrap().port();
+            let mut incoming =
+                match UdpInboundHandler::handle(&server, Box::new(SimpleInboundDatagram(socket)))
+                    .await
+                    .unwrap()
+                {
+                    InboundTransport::Incoming(incoming) => incoming,
+                    _ => panic!("not incoming"),
+                };
+            // The packets of the sessions come as the incoming is polled.
+            let (sessions_tx, mut sessions_rx) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Some(transport) = incoming.next().await {
+                    if let BaseInboundTransport::Datagram(d) = transport {
+                        let _ = sessions_tx.send(d.split());
+                    }
+                }
+            });
+
+            let mut dns = crate::config::Dns::new();
+            dns.servers.push("127.0.0.1".to_string());
+            let dns_client = Arc::new(tokio::sync::RwLock::new(
+                crate::app::dns_client::DnsClient::new(&protobuf::SingularPtrField::some(dns))
+                    .unwrap(),
+            ));
+            let client = super::super::TcpHandler::new(
+                "127.0.0.1".to_string(),
+                port,
+                Some("localhost".to_string()),
+                Some(path("cert.der")),
+                Vec::new(),
+                0,
+                1024,
+                true,
+                false,
+                Duration::from_secs(5),
+                dns_client,
+            )
+            .udp_handler();
+            let dst = SocksAddr::from("1.2.3.4:53".parse::<std::net::SocketAddr>().unwrap());
+            let mut buf = vec![0u8; 2 * 1024 * 1024];
+
+            // A packet fitting in a datagram and its reply.
+            let (mut client_recv, mut client_send) = new_datagram(&client).await;
+            client_send.send_to(b"ping", &dst).await.unwrap();
+            let (mut server_recv, mut server_send) = next_session(&mut sessions_rx).await.unwrap();
+            let (n, source, dst_addr) = server_recv.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"ping");
+            assert_eq!(dst_addr.unwrap().to_string(), dst.to_string());
+            server_send
+                .send_to(b"pong", Some(&dst), &source.address)
+                .await
+                .unwrap();
+            let (n, src_addr) = client_recv.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"pong");
+            assert_eq!(src_addr.to_string(), dst.to_string());
+
+            // Too large for a datagram, it goes on a stream.
+            let large = vec![7u8; 16 * 1024];
+            client_send.send_to(&large, &dst).await.unwrap();
+            let (n, _, _) = server_recv.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], &large[..]);
+
+            // The sessions of a connection are bounded.
+            let mut sessions = vec![(client_recv, client_send, server_recv, server_send)];
+            loop {
+                let (client_recv, mut client_send) = new_datagram(&client).await;
+                client_send.send_to(b"ping", &dst).await.unwrap();
+                match next_session(&mut sessions_rx).await {
+                    Some((server_recv, server_send)) => {
+                        sessions.push((client_recv, client_send, server_recv, server_send))
+                    }
+                    None => break,
+                }
+            }
+            assert_eq!(sessions.len(), 256);
+
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
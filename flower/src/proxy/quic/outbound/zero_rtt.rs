@@ -0,0 +1,274 @@
+use std::{future::Future, io, pin::Pin};
+
+use bytes::BytesMut;
+use futures::future::Shared;
+use futures::{
+    ready,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::oneshot;
+
+use super::QuicProxyStream;
+
+type Stream = QuicProxyStream<quinn::RecvStream, quinn::SendStream>;
+
+enum State {
+    // Data written before the handshake completes is kept until the server
+    // accepts or rejects the early data.
+    Early {
+        accepted: Shared<quinn::ZeroRttAccepted>,
+        written: BytesMut,
+    },
+    // The early data was rejected, it's being written on a new stream.
+    Replay(oneshot::Receiver<io::Result<Stream>>),
+    Done,
+}
+
+/// A stream possibly opened in 0-RTT, what was written on it is written again
+/// on a new stream if the server rejects the early data.
+pub struct ZeroRttStream {
+    connection: quinn::Connection,
+    inner: Stream,
+    state: State,
+}
+
+impl ZeroRttStream {
+    /// `accepted` resolves once the handshake of a 0-RTT connection
+    /// completes.
+    pub fn new(
+        connection: quinn::Connection,
+        inner: Stream,
+        accepted: Option<Shared<quinn::ZeroRttAccepted>>,
+    ) -> Self {
+        let state = match accepted {
+            Some(accepted) if accepted.peek().is_none() => State::Early {
+                accepted,
+                written: BytesMut::new(),
+            },
+            _ => State::Done,
+        };
+        ZeroRttStream {
+            connection,
+            inner,
+            state,
+        }
+    }
+
+    // Waits for the handshake to complete and the early data, if rejected,
+    // to be replayed.
+    fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            match &mut self.state {
+                State::Early { accepted, written } => {
+                    if ready!(Pin::new(accepted).poll(cx)) {
+                        self.state = State::Done;
+                        continue;
+                    }
+                    log::debug!(
+                        "quic 0-rtt data rejected, replaying {} bytes",
+                        written.len()
+                    );
+                    let written = written.split().freeze();
+                    let connection = self.connection.clone();
+                    let (tx, rx) = oneshot::channel();
+                    tokio::spawn(async move {
+                        let res = async move {
+                            let (mut send, recv) = connection
+                                .open_bi()
+                                .await
+                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                            send.write_all(&written)
+                                .await
+                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                            Ok(QuicProxyStream { recv, send })
+                        }
+                        .await;
+                        let _ = tx.send(res);
+                    });
+                    self.state = State::Replay(rx);
+                }
+                State::Replay(rx) => {
+                    let res = ready!(Pin::new(rx).poll(cx));
+                    self.state = State::Done;
+                    self.inner = res.map_err(|_| {
+                        io::Error::new(io::ErrorKind::Other, "quic 0-rtt replay aborted")
+                    })??;
+                }
+                State::Done => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+
+    // Ready unless the rejected early data is being replayed, writes go on in
+    // 0-RTT until the handshake completes.
+    fn poll_writable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.poll_handshake(cx) {
+            Poll::Pending if matches!(self.state, State::Early { .. }) => Poll::Ready(Ok(())),
+            res => res,
+        }
+    }
+}
+
+impl AsyncRead for ZeroRttStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        ready!(self.poll_handshake(cx))?;
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ZeroRttStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_writable(cx))?;
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        if let State::Early { written, .. } = &mut this.state {
+            written.extend_from_slice(&buf[..n]);
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        ready!(self.poll_writable(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        // The replay must include everything written.
+        ready!(self.poll_handshake(cx))?;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+    use futures::{FutureExt, StreamExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    // Echoes what's read on each stream, a new server can't resume the
+    // sessions of another and rejects their early data.
+    fn echo_server(cert: &rcgen::Certificate) -> SocketAddr {
+        let config = quinn::ServerConfig::with_single_cert(
+            vec![rustls::Certificate(cert.serialize_der().unwrap())],
+            rustls::PrivateKey(cert.serialize_private_key_der()),
+        )
+        .unwrap();
+        let (endpoint, mut incoming) = quinn::Endpoint::new(
+            quinn::EndpointConfig::default(),
+            Some(config),
+            std::net::UdpSocket::bind("127.0.0.1:0").unwrap(),
+        )
+        .unwrap();
+        let addr = endpoint.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _endpoint = endpoint;
+            while let Some(connecting) = incoming.next().await {
+                tokio::spawn(async move {
+                    let mut new_conn = match connecting.into_0rtt() {
+                        Ok((new_conn, _)) => new_conn,
+                        Err(connecting) => connecting.await.unwrap(),
+                    };
+                    while let Some(Ok((mut send, recv))) = new_conn.bi_streams.next().await {
+                        tokio::spawn(async move {
+                            let data = recv.read_to_end(1024).await.unwrap();
+                            send.write_all(&data).await.unwrap();
+                            send.finish().await.unwrap();
+                        });
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    // Writes and reads back on a stream made on a 0-RTT connection, returns
+    // whether the early data was accepted.
+    async fn echo(endpoint: &quinn::Endpoint, addr: SocketAddr) -> bool {
+        let (new_conn, accepted) = endpoint
+            .connect(addr, "localhost")
+            .unwrap()
+            .into_0rtt()
+            .map_err(|_| ())
+            .expect("no 0-rtt");
+        let accepted = accepted.shared();
+        let (send, recv) = new_conn.connection.open_bi().await.unwrap();
+        let mut stream = ZeroRttStream::new(
+            new_conn.connection.clone(),
+            QuicProxyStream { recv, send },
+            Some(accepted.clone()),
+        );
+        assert!(matches!(stream.state, State::Early { .. }));
+        stream.write_all(b"early").await.unwrap();
+        stream.write_all(b" data").await.unwrap();
+        stream.shutdown().await.unwrap();
+        assert!(matches!(stream.state, State::Done));
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"early data");
+        accepted.await
+    }
+
+    #[test]
+    fn test_zero_rtt_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            let mut roots = rustls::RootCertStore::empty();
+            roots
+                .add(&rustls::Certificate(cert.serialize_der().unwrap()))
+                .unwrap();
+            let mut crypto = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            crypto.enable_early_data = true;
+            let (mut endpoint, _) = quinn::Endpoint::new(
+                quinn::EndpointConfig::default(),
+                None,
+                std::net::UdpSocket::bind("127.0.0.1:0").unwrap(),
+            )
+            .unwrap();
+            endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+
+            // A full handshake caches a session ticket.
+            let addr = echo_server(&cert);
+            let new_conn = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+            let (send, recv) = new_conn.connection.open_bi().await.unwrap();
+            let mut stream = ZeroRttStream::new(
+                new_conn.connection.clone(),
+                QuicProxyStream { recv, send },
+                None,
+            );
+            assert!(matches!(stream.state, State::Done));
+            stream.write_all(b"hello").await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+            // The ticket comes after the handshake.
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            // Accepted, the early data isn't written again.
+            assert!(echo(&endpoint, addr).await);
+
+            // Rejected, the early data is written again on a new stream.
+            let addr = echo_server(&cert);
+            assert!(!echo(&endpoint, addr).await);
+        });
+    }
+}
@@ -1,8 +1,4 @@
-use anyhow::anyhow;
 use anyhow::Result;
-use hkdf::Hkdf;
-use md5::{Digest, Md5};
-use sha1::Sha1;
 
 use crate::common::crypto::NonceSequence;
 
@@ -29,23 +25,3 @@ impl NonceSequence for ShadowsocksNonceSequence {
         Ok(self.0.clone())
     }
 }
-
-pub fn kdf(pass: &str, size: usize) -> Result<Vec<u8>> {
-    let pass = pass.as_bytes();
-    let mut key = Vec::new();
-    let mut sum = Md5::digest(pass).to_vec();
-    std::io::Write::write(&mut key, &sum)?;
-    while key.len() < size {
-        sum = Md5::digest(&[sum, pass.to_vec()].concat()).to_vec();
-        std::io::Write::write(&mut key, &sum)?;
-    }
-    Ok(key)
-}
-
-pub fn hkdf_sha1(key: &[u8], salt: &[u8], info: Vec<u8>, size: usize) -> Result<Vec<u8>> {
-    let (_, h) = Hkdf::<Sha1>::extract(Some(salt), key);
-    let mut okm = vec![0u8; size];
-    h.expand(&info, &mut okm)
-        .map_err(|_| anyhow!("hkdf expand failed"))?;
-    Ok(okm.to_vec())
-}
@@ -1,14 +1,15 @@
 use async_trait::async_trait;
 
 use crate::{
+    common::crypto::Cipher,
     proxy::*,
     session::{Session, SocksAddr, SocksAddrWireType},
 };
 
-use super::shadow::ShadowedStream;
+use super::shadow;
 
 pub struct Handler {
-    pub cipher: String,
+    pub cipher: Cipher,
     pub password: String,
 }
 
@@ -22,7 +23,7 @@ impl TcpInboundHandler for Handler {
         mut sess: Session,
         stream: Self::TStream,
     ) -> std::io::Result<InboundTransport<Self::TStream, Self::TDatagram>> {
-        let mut stream = ShadowedStream::new(stream, &self.cipher, &self.password)?;
+        let mut stream = shadow::new_stream(stream, self.cipher, &self.password);
         let destination = SocksAddr::read_from(&mut stream, SocksAddrWireType::PortLast).await?;
         sess.destination = destination;
 
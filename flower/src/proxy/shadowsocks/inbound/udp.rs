@@ -9,6 +9,7 @@ use bytes::{BufMut, BytesMut};
 use log::*;
 
 use crate::{
+    common::crypto::Cipher,
     proxy::*,
     session::{SocksAddr, SocksAddrWireType},
 };
@@ -16,7 +17,7 @@ use crate::{
 use super::shadow::{self, ShadowedDatagram};
 
 pub struct Handler {
-    pub cipher: String,
+    pub cipher: Cipher,
     pub password: String,
 }
 
@@ -29,7 +30,7 @@ impl UdpInboundHandler for Handler {
         &'a self,
         socket: Self::UDatagram,
     ) -> io::Result<InboundTransport<Self::UStream, Self::UDatagram>> {
-        let dgram = ShadowedDatagram::new(&self.cipher, &self.password)?;
+        let dgram = ShadowedDatagram::new(self.cipher, &self.password);
         Ok(InboundTransport::Datagram(Box::new(Datagram {
             dgram,
             socket,
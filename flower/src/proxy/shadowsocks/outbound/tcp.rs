@@ -4,8 +4,9 @@ use async_trait::async_trait;
 use bytes::BytesMut;
 use tokio::io::AsyncWriteExt;
 
-use super::shadow::ShadowedStream;
+use super::shadow;
 use crate::{
+    common::crypto::Cipher,
     proxy::*,
     session::{Session, SocksAddrWireType},
 };
@@ -13,7 +14,7 @@ use crate::{
 pub struct Handler {
     pub address: String,
     pub port: u16,
-    pub cipher: String,
+    pub cipher: Cipher,
     pub password: String,
 }
 
@@ -31,7 +32,7 @@ impl TcpOutboundHandler for Handler {
         stream: Option<Self::Stream>,
     ) -> io::Result<Self::Stream> {
         let stream = stream.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid input"))?;
-        let mut stream = ShadowedStream::new(stream, &self.cipher, &self.password)?;
+        let mut stream = shadow::new_stream(stream, self.cipher, &self.password);
         let mut buf = BytesMut::new();
         sess.destination
             .write_buf(&mut buf, SocksAddrWireType::PortLast)?;
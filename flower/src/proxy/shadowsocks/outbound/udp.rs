@@ -5,6 +5,7 @@ use bytes::{BufMut, BytesMut};
 use log::*;
 
 use crate::{
+    common::crypto::Cipher,
     proxy::*,
     session::{Session, SocksAddr, SocksAddrWireType},
 };
@@ -14,7 +15,7 @@ use super::shadow::{self, ShadowedDatagram};
 pub struct Handler {
     pub address: String,
     pub port: u16,
-    pub cipher: String,
+    pub cipher: Cipher,
     pub password: String,
 }
 
@@ -44,7 +45,7 @@ impl UdpOutboundHandler for Handler {
             return Err(io::Error::new(io::ErrorKind::Other, "invalid input"));
         };
 
-        let dgram = ShadowedDatagram::new(&self.cipher, &self.password)?;
+        let dgram = ShadowedDatagram::new(self.cipher, &self.password);
 
         let destination = match &sess.destination {
             SocksAddr::Domain(domain, port) => {
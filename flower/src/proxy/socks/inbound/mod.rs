@@ -1,5 +1,41 @@
 mod tcp;
 mod udp;
 
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
 pub use tcp::Handler as TcpHandler;
 pub use udp::Handler as UdpHandler;
+
+use super::udp_over_tcp;
+
+/// Clients having UDP associations established over authenticated control
+/// connections, keyed by IP as the UDP port of a client isn't known until it
+/// sends the first packet.
+#[derive(Default)]
+pub struct UdpAssociations(Mutex<HashMap<IpAddr, usize>>);
+
+impl UdpAssociations {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn add(&self, ip: IpAddr) {
+        *self.0.lock().unwrap().entry(ip).or_insert(0) += 1;
+    }
+
+    fn remove(&self, ip: &IpAddr) {
+        let mut associations = self.0.lock().unwrap();
+        if let Some(n) = associations.get_mut(ip) {
+            *n -= 1;
+            if *n == 0 {
+                associations.remove(ip);
+            }
+        }
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.lock().unwrap().contains_key(ip)
+    }
+}
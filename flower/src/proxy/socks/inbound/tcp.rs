@@ -1,16 +1,128 @@
 use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use bytes::{BufMut, BytesMut};
+use futures::TryFutureExt;
 use log::*;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use crate::{
     proxy::*,
-    session::{Session, SocksAddr, SocksAddrWireType},
+    session::{DatagramSource, Session, SocksAddr, SocksAddrWireType},
 };
 
-pub struct Handler;
+use super::{udp_over_tcp, UdpAssociations};
+
+// UDP over the TCP connection of a CONNECT request.
+struct StreamDatagram {
+    stream: AnyStream,
+    source: DatagramSource,
+}
+
+impl InboundDatagram for StreamDatagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn InboundDatagramRecvHalf>,
+        Box<dyn InboundDatagramSendHalf>,
+    ) {
+        let (r, s) = tokio::io::split(self.stream);
+        (
+            Box::new(StreamDatagramRecvHalf(r, self.source)),
+            Box::new(StreamDatagramSendHalf(s)),
+        )
+    }
+
+    fn into_std(self: Box<Self>) -> io::Result<std::net::UdpSocket> {
+        Err(io::Error::new(io::ErrorKind::Other, "stream transport"))
+    }
+}
+
+struct StreamDatagramRecvHalf<T>(T, DatagramSource);
+
+#[async_trait]
+impl<T> InboundDatagramRecvHalf for StreamDatagramRecvHalf<T>
+where
+    T: AsyncRead + Send + Sync + Unpin,
+{
+    async fn recv_from(
+        &mut self,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, DatagramSource, Option<SocksAddr>)> {
+        let (n, dst_addr) = udp_over_tcp::read_packet(&mut self.0, buf).await?;
+        Ok((n, self.1, Some(dst_addr)))
+    }
+}
+
+struct StreamDatagramSendHalf<T>(T);
+
+#[async_trait]
+impl<T> InboundDatagramSendHalf for StreamDatagramSendHalf<T>
+where
+    T: AsyncWrite + Send + Sync + Unpin,
+{
+    async fn send_to(
+        &mut self,
+        buf: &[u8],
+        src_addr: Option<&SocksAddr>,
+        _dst_addr: &SocketAddr,
+    ) -> io::Result<usize> {
+        let src_addr = src_addr.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Other, "sending message without source")
+        })?;
+        let mut data = BytesMut::new();
+        udp_over_tcp::write_packet(&mut data, src_addr, buf)?;
+        self.0.write_all(&data).map_ok(|_| buf.len()).await
+    }
+}
+
+pub struct Handler {
+    /// The IP or domain returned in UDP ASSOCIATE replies, for clients
+    /// reaching the inbound through a NAT. Falls back to the local address of
+    /// the control connection if not set. The port is always the local one,
+    /// the UDP relay shares it.
+    pub advertised_address: Option<String>,
+    /// Clients must authenticate with the username and password, no
+    /// authentication is required if the username is empty.
+    pub username: String,
+    pub password: String,
+    /// Tracks the UDP associations of authenticated clients, so that the UDP
+    /// relay accepts packets only from them.
+    pub associations: Option<Arc<UdpAssociations>>,
+}
+
+impl Handler {
+    // Username/password authentication, RFC 1929.
+    async fn authenticate(&self, stream: &mut AnyStream) -> io::Result<()> {
+        let mut buf = [0u8; 255];
+        // ver, ulen
+        stream.read_exact(&mut buf[..2]).await?;
+        if buf[0] != 0x01 {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("unknown auth version {}", buf[0]),
+            ));
+        }
+        let ulen = buf[1] as usize;
+        stream.read_exact(&mut buf[..ulen]).await?;
+        let username = buf[..ulen].to_vec();
+        // plen, passwd
+        stream.read_exact(&mut buf[..1]).await?;
+        let plen = buf[0] as usize;
+        stream.read_exact(&mut buf[..plen]).await?;
+        let password = &buf[..plen];
+        if username != self.username.as_bytes() || password != self.password.as_bytes() {
+            stream.write_all(&[0x01, 0x01]).await?;
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "invalid username or password",
+            ));
+        }
+        stream.write_all(&[0x01, 0x00]).await
+    }
+}
 
 #[async_trait]
 impl TcpInboundHandler for Handler {
@@ -46,26 +158,28 @@ impl TcpInboundHandler for Handler {
             debug!("read methods failed: {}", e);
             return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
         };
-        let mut method_accepted = false;
-        let mut method_idx: u8 = 0;
-        let supported_method: u8 = 0x0;
-        for (idx, method) in buf[..].iter().enumerate() {
-            if method == &supported_method {
-                method_accepted = true;
-                method_idx = idx as u8;
-                break;
-            }
-        }
-        if !method_accepted {
+        let supported_method: u8 = if self.username.is_empty() {
+            0x00 // no authentication
+        } else {
+            0x02 // username/password
+        };
+        if !buf.contains(&supported_method) {
             warn!("unsupported socks5 authentication methods");
             if let Err(e) = stream.write_all(&[0x05, 0xff]).await {
                 debug!("write auth response failed: {}", e);
             };
             return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
-        } else if let Err(e) = stream.write_all(&[0x05, method_idx]).await {
+        } else if let Err(e) = stream.write_all(&[0x05, supported_method]).await {
             debug!("write auth response failed: {}", e);
             return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
         };
+        if supported_method == 0x02 {
+            if let Err(e) = self.authenticate(&mut stream).await {
+                warn!("socks5 authentication from {} failed: {}", &sess.source, e);
+                return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
+            }
+            sess.user = Some(self.username.clone());
+        }
 
         // handle request
         buf.resize(3, 0);
@@ -122,6 +236,14 @@ impl TcpInboundHandler for Handler {
                     return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
                 };
 
+                if matches!(&destination, SocksAddr::Domain(domain, _) if domain == udp_over_tcp::DOMAIN)
+                {
+                    return Ok(InboundTransport::Datagram(Box::new(StreamDatagram {
+                        stream,
+                        source: DatagramSource::new(sess.source, sess.stream_id),
+                    })));
+                }
+
                 sess.destination = destination;
 
                 Ok(InboundTransport::Stream(stream, sess))
@@ -131,7 +253,14 @@ impl TcpInboundHandler for Handler {
                 buf.put_u8(0x05); // version 5
                 buf.put_u8(0x0); // succeeded
                 buf.put_u8(0x0); // rsv
-                let relay_addr = SocksAddr::from(sess.local_addr);
+                let relay_addr = match self.advertised_address.as_ref() {
+                    Some(addr) => SocksAddr::try_from((addr.clone(), sess.local_addr.port()))
+                        .map_err(|e| {
+                            debug!("invalid advertised address: {}", e);
+                            io::Error::new(io::ErrorKind::Other, "unspecified")
+                        })?,
+                    None => SocksAddr::from(sess.local_addr),
+                };
                 if let Err(e) = relay_addr.write_buf(&mut buf, SocksAddrWireType::PortLast) {
                     debug!("write address buffer: {}", e);
                     return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
@@ -140,6 +269,11 @@ impl TcpInboundHandler for Handler {
                     debug!("write response failed: {}", e);
                     return Err(io::Error::new(io::ErrorKind::Other, "unspecified"));
                 };
+                let associations = self.associations.clone();
+                let client_ip = sess.source.ip();
+                if let Some(associations) = associations.as_ref() {
+                    associations.add(client_ip);
+                }
                 tokio::spawn(async move {
                     let mut buf = [0u8; 1];
                     // TODO explicitly drop resources allocated above before waiting?
@@ -151,6 +285,9 @@ impl TcpInboundHandler for Handler {
                         // perhaps explicitly notifies the NAT manager?
                         debug!("udp association end: {}", e);
                     }
+                    if let Some(associations) = associations {
+                        associations.remove(&client_ip);
+                    }
                 });
                 Ok(InboundTransport::Empty)
             }
@@ -158,3 +295,245 @@ impl TcpInboundHandler for Handler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn udp_associate(handler: Handler, sess: Session) -> Vec<u8> {
+        let (client, server) = tokio::io::duplex(1024);
+        let task = tokio::spawn(async move { handler.handle(sess, Box::new(server)).await });
+        let mut client = client;
+        client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut buf = [0u8; 2];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0x05, 0x00]);
+        client
+            .write_all(&[0x05, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        let mut buf = vec![0u8; 10];
+        client.read_exact(&mut buf).await.unwrap();
+        assert!(matches!(task.await.unwrap(), Ok(InboundTransport::Empty)));
+        buf
+    }
+
+    #[test]
+    fn test_udp_associate_advertised_address() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let sess = Session {
+            local_addr: "0.0.0.0:1086".parse().unwrap(),
+            ..Default::default()
+        };
+        let handler = Handler {
+            advertised_address: Some("203.0.113.1".to_string()),
+            username: "".to_string(),
+            password: "".to_string(),
+            associations: None,
+        };
+        let reply = rt.block_on(udp_associate(handler, sess.clone()));
+        assert_eq!(reply, [0x05, 0x00, 0x00, 0x01, 203, 0, 113, 1, 0x04, 0x3e]);
+
+        // The port of a randomly bound inbound.
+        let handler = Handler {
+            advertised_address: Some("203.0.113.1".to_string()),
+            username: "".to_string(),
+            password: "".to_string(),
+            associations: None,
+        };
+        let random_sess = Session {
+            local_addr: "0.0.0.0:40000".parse().unwrap(),
+            ..Default::default()
+        };
+        let reply = rt.block_on(udp_associate(handler, random_sess));
+        assert_eq!(reply, [0x05, 0x00, 0x00, 0x01, 203, 0, 113, 1, 0x9c, 0x40]);
+
+        let handler = Handler {
+            advertised_address: None,
+            username: "".to_string(),
+            password: "".to_string(),
+            associations: None,
+        };
+        let reply = rt.block_on(udp_associate(handler, sess));
+        assert_eq!(reply, [0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0x04, 0x3e]);
+    }
+
+    // Negotiates the username/password method and authenticates, returns the
+    // status of the authentication, and the control connection of the UDP
+    // association requested on success.
+    async fn authenticate(
+        handler: Arc<Handler>,
+        username: &str,
+        password: &str,
+    ) -> (u8, Option<tokio::io::DuplexStream>) {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let sess = Session {
+            source: "127.0.0.1:50000".parse().unwrap(),
+            local_addr: "127.0.0.1:1086".parse().unwrap(),
+            ..Default::default()
+        };
+        let task = tokio::spawn(async move { handler.handle(sess, Box::new(server)).await });
+        client.write_all(&[0x05, 0x02, 0x00, 0x02]).await.unwrap();
+        let mut buf = [0u8; 2];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0x05, 0x02]);
+        let mut req = vec![0x01, username.len() as u8];
+        req.extend_from_slice(username.as_bytes());
+        req.push(password.len() as u8);
+        req.extend_from_slice(password.as_bytes());
+        client.write_all(&req).await.unwrap();
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf[0], 0x01);
+        if buf[1] != 0 {
+            assert!(task.await.unwrap().is_err());
+            return (buf[1], None);
+        }
+        client
+            .write_all(&[0x05, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        let mut buf = vec![0u8; 10];
+        client.read_exact(&mut buf).await.unwrap();
+        assert!(matches!(task.await.unwrap(), Ok(InboundTransport::Empty)));
+        (0, Some(client))
+    }
+
+    #[test]
+    fn test_auth() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let associations = Arc::new(UdpAssociations::new());
+        let handler = Arc::new(Handler {
+            advertised_address: None,
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            associations: Some(associations.clone()),
+        });
+        let ip = "127.0.0.1".parse().unwrap();
+        rt.block_on(async {
+            let (status, _) = authenticate(handler.clone(), "user", "wrong").await;
+            assert_eq!(status, 1);
+            assert!(!associations.contains(&ip));
+            let (status, control) = authenticate(handler.clone(), "user", "pass").await;
+            assert_eq!(status, 0);
+            assert!(associations.contains(&ip));
+
+            // The association ends along with the control connection.
+            drop(control);
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+            assert!(!associations.contains(&ip));
+        });
+
+        // Clients offering no acceptable method are rejected.
+        rt.block_on(async {
+            let (mut client, server) = tokio::io::duplex(1024);
+            let task =
+                tokio::spawn(
+                    async move { handler.handle(Session::default(), Box::new(server)).await },
+                );
+            client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+            let mut buf = [0u8; 2];
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [0x05, 0xff]);
+            assert!(task.await.unwrap().is_err());
+        });
+    }
+
+    #[test]
+    fn test_auth_sets_user() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let handler = Handler {
+            advertised_address: None,
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            associations: None,
+        };
+        rt.block_on(async {
+            let (mut client, server) = tokio::io::duplex(1024);
+            let task =
+                tokio::spawn(
+                    async move { handler.handle(Session::default(), Box::new(server)).await },
+                );
+            client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+            client.write_all(b"\x01\x04user\x04pass").await.unwrap();
+            client
+                .write_all(&[0x05, 0x01, 0x00, 0x01, 1, 2, 3, 4, 0, 80])
+                .await
+                .unwrap();
+            let mut buf = [0u8; 14];
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf[..6], &[0x05, 0x02, 0x01, 0x00, 0x05, 0x00]);
+            match task.await.unwrap() {
+                Ok(InboundTransport::Stream(_, sess)) => {
+                    assert_eq!(sess.user.as_deref(), Some("user"));
+                    assert_eq!(sess.destination.to_string(), "1.2.3.4:80");
+                }
+                _ => panic!("expected a stream"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_udp_over_tcp() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let handler = Handler {
+            advertised_address: None,
+            username: "".to_string(),
+            password: "".to_string(),
+            associations: None,
+        };
+        let target = SocksAddr::from(("1.2.3.4".parse::<std::net::IpAddr>().unwrap(), 53));
+        rt.block_on(async {
+            let (mut client, server) = tokio::io::duplex(1024);
+            let task =
+                tokio::spawn(
+                    async move { handler.handle(Session::default(), Box::new(server)).await },
+                );
+            client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+            let mut buf = [0u8; 3];
+            client.read_exact(&mut buf[..2]).await.unwrap();
+            let mut req = vec![0x05, 0x01, 0x00, 0x03, udp_over_tcp::DOMAIN.len() as u8];
+            req.extend_from_slice(udp_over_tcp::DOMAIN.as_bytes());
+            req.extend_from_slice(&[0, 0]);
+            client.write_all(&req).await.unwrap();
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [0x05, 0x00, 0x00]);
+            SocksAddr::read_from(&mut client, SocksAddrWireType::PortLast)
+                .await
+                .unwrap();
+            let datagram = if let Ok(InboundTransport::Datagram(d)) = task.await.unwrap() {
+                d
+            } else {
+                panic!("expected a datagram transport");
+            };
+            let (mut recv_half, mut send_half) = datagram.split();
+
+            client
+                .write_all(&[0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'a', b'b', b'c'])
+                .await
+                .unwrap();
+            let mut buf = [0u8; 16];
+            let (n, _, dst_addr) = recv_half.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"abc");
+            assert_eq!(dst_addr, Some(target.clone()));
+
+            let n = send_half
+                .send_to(b"xyz", Some(&target), &"127.0.0.1:50000".parse().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(n, 3);
+            let mut buf = [0u8; 12];
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'x', b'y', b'z']);
+        });
+    }
+}
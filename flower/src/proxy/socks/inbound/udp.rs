@@ -2,6 +2,7 @@ use std::cmp::min;
 use std::convert::TryFrom;
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use bytes::{BufMut, BytesMut};
@@ -12,7 +13,12 @@ use crate::{
     session::{DatagramSource, SocksAddr, SocksAddrWireType},
 };
 
-pub struct Handler;
+use super::UdpAssociations;
+
+pub struct Handler {
+    /// Accepts packets only from clients with UDP associations if set.
+    pub associations: Option<Arc<UdpAssociations>>,
+}
 
 #[async_trait]
 impl UdpInboundHandler for Handler {
@@ -23,12 +29,16 @@ impl UdpInboundHandler for Handler {
         &'a self,
         socket: Self::UDatagram,
     ) -> io::Result<InboundTransport<Self::UStream, Self::UDatagram>> {
-        Ok(InboundTransport::Datagram(Box::new(Datagram { socket })))
+        Ok(InboundTransport::Datagram(Box::new(Datagram {
+            socket,
+            associations: self.associations.clone(),
+        })))
     }
 }
 
 pub struct Datagram {
     socket: Box<dyn InboundDatagram>,
+    associations: Option<Arc<UdpAssociations>>,
 }
 
 impl InboundDatagram for Datagram {
@@ -40,7 +50,7 @@ impl InboundDatagram for Datagram {
     ) {
         let (rh, sh) = self.socket.split();
         (
-            Box::new(DatagramRecvHalf(rh)),
+            Box::new(DatagramRecvHalf(rh, self.associations)),
             Box::new(DatagramSendHalf(sh)),
         )
     }
@@ -50,7 +60,10 @@ impl InboundDatagram for Datagram {
     }
 }
 
-pub struct DatagramRecvHalf(Box<dyn InboundDatagramRecvHalf>);
+pub struct DatagramRecvHalf(
+    Box<dyn InboundDatagramRecvHalf>,
+    Option<Arc<UdpAssociations>>,
+);
 
 #[async_trait]
 impl InboundDatagramRecvHalf for DatagramRecvHalf {
@@ -64,6 +77,17 @@ impl InboundDatagramRecvHalf for DatagramRecvHalf {
             warn!("short socks5 udp pkt");
             return Ok((0, src_addr, None));
         }
+        if let Some(associations) = self.1.as_ref() {
+            if !associations.contains(&src_addr.address.ip()) {
+                debug!("drop socks5 udp pkt from unassociated {}", &src_addr);
+                return Ok((0, src_addr, None));
+            }
+        }
+        // Fragmentation is not supported.
+        if recv_buf[2] != 0 {
+            debug!("drop fragmented socks5 udp pkt");
+            return Ok((0, src_addr, None));
+        }
         let dst_addr = match SocksAddr::try_from((&recv_buf[3..], SocksAddrWireType::PortLast)) {
             Ok(v) => v,
             Err(e) => {
@@ -109,3 +133,59 @@ impl InboundDatagramSendHalf for DatagramSendHalf {
         self.0.send_to(&send_buf[..], None, dst_addr).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::UdpSocket;
+
+    use crate::proxy::datagram::SimpleInboundDatagram;
+
+    use super::*;
+
+    #[test]
+    fn test_recv_from() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let associations = Arc::new(UdpAssociations::new());
+            let handler = Handler {
+                associations: Some(associations.clone()),
+            };
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let relay_addr = socket.local_addr().unwrap();
+            let datagram = match handler
+                .handle(Box::new(SimpleInboundDatagram(socket)))
+                .await
+                .unwrap()
+            {
+                InboundTransport::Datagram(d) => d,
+                _ => panic!("unexpected transport"),
+            };
+            let (mut recv_half, _) = datagram.split();
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let mut pkt = vec![0, 0, 0, 0x01, 127, 0, 0, 1, 0, 53];
+            pkt.extend_from_slice(b"abc");
+            let mut buf = [0u8; 16];
+
+            // Packets from clients without associations are dropped.
+            client.send_to(&pkt, relay_addr).await.unwrap();
+            let (_, _, dst_addr) = recv_half.recv_from(&mut buf).await.unwrap();
+            assert!(dst_addr.is_none());
+
+            associations.add("127.0.0.1".parse().unwrap());
+            pkt[2] = 1;
+            client.send_to(&pkt, relay_addr).await.unwrap();
+            let (_, _, dst_addr) = recv_half.recv_from(&mut buf).await.unwrap();
+            assert!(dst_addr.is_none());
+
+            pkt[2] = 0;
+            client.send_to(&pkt, relay_addr).await.unwrap();
+            let (n, src_addr, dst_addr) = recv_half.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"abc");
+            assert_eq!(src_addr.address, client.local_addr().unwrap());
+            assert_eq!(dst_addr.unwrap().to_string(), "127.0.0.1:53");
+        });
+    }
+}
@@ -2,3 +2,5 @@
 pub mod inbound;
 #[cfg(feature = "outbound-socks")]
 pub mod outbound;
+
+mod udp_over_tcp;
@@ -3,3 +3,5 @@ mod udp;
 
 pub use tcp::Handler as TcpHandler;
 pub use udp::Handler as UdpHandler;
+
+use super::udp_over_tcp;
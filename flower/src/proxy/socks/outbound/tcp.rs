@@ -1,16 +1,118 @@
 use std::io;
 
 use async_trait::async_trait;
-use futures::future::TryFutureExt;
+use bytes::{BufMut, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use crate::{
     proxy::*,
-    session::{Session, SocksAddr},
+    session::{Session, SocksAddr, SocksAddrWireType},
 };
 
 pub struct Handler {
     pub address: String,
     pub port: u16,
+    /// Authenticates with the username and password if the server asks for
+    /// it, only no authentication is offered if the username is empty.
+    pub username: String,
+    pub password: String,
+}
+
+// Negotiates the authentication method, then authenticates with the username
+// and password if the server selects it, RFC 1929. Only no authentication is
+// offered if the username is empty.
+async fn authenticate<S>(stream: &mut S, username: &str, password: &str) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // ver, nmethods, methods
+    if username.is_empty() {
+        stream.write_all(&[0x05, 0x01, 0x00]).await?;
+    } else {
+        stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?;
+    }
+    let mut buf = [0u8; 2];
+    stream.read_exact(&mut buf).await?;
+    if buf[0] != 0x05 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("unknown socks version {}", buf[0]),
+        ));
+    }
+    match buf[1] {
+        0x00 => return Ok(()),
+        0x02 if !username.is_empty() => (),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "no acceptable socks5 authentication method",
+            ))
+        }
+    }
+    if username.len() > 255 || password.len() > 255 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socks5 username or password too long",
+        ));
+    }
+    let mut req = BytesMut::with_capacity(3 + username.len() + password.len());
+    req.put_u8(0x01);
+    req.put_u8(username.len() as u8);
+    req.put_slice(username.as_bytes());
+    req.put_u8(password.len() as u8);
+    req.put_slice(password.as_bytes());
+    stream.write_all(&req).await?;
+    stream.read_exact(&mut buf).await?;
+    if buf[0] != 0x01 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("unknown socks5 authentication version {}", buf[0]),
+        ));
+    }
+    if buf[1] != 0x00 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("socks5 authentication failed with status {}", buf[1]),
+        ));
+    }
+    Ok(())
+}
+
+/// Authenticates and sends a CONNECT request to `destination`.
+pub(super) async fn connect<S>(
+    stream: &mut S,
+    username: &str,
+    password: &str,
+    destination: &SocksAddr,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    authenticate(stream, username, password).await?;
+
+    // ver, cmd, rsv, dst.addr, dst.port
+    let mut req = BytesMut::with_capacity(3 + destination.size());
+    req.put_slice(&[0x05, 0x01, 0x00]);
+    destination.write_buf(&mut req, SocksAddrWireType::PortLast)?;
+    stream.write_all(&req).await?;
+
+    // ver, rep, rsv, bnd.addr, bnd.port
+    let mut buf = [0u8; 3];
+    stream.read_exact(&mut buf).await?;
+    if buf[0] != 0x05 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("unknown socks version {}", buf[0]),
+        ));
+    }
+    if buf[1] != 0x00 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("socks5 connect failed with reply {}", buf[1]),
+        ));
+    }
+    SocksAddr::read_from(stream, SocksAddrWireType::PortLast).await?;
+    Ok(())
 }
 
 #[async_trait]
@@ -28,19 +130,94 @@ impl TcpOutboundHandler for Handler {
     ) -> io::Result<Self::Stream> {
         let mut stream =
             stream.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid input"))?;
-        match &sess.destination {
-            SocksAddr::Ip(a) => {
-                let _ = async_socks5::connect(&mut stream, a.to_owned(), None)
-                    .map_err(|x| io::Error::new(io::ErrorKind::Other, x))
-                    .await?;
-            }
-            SocksAddr::Domain(domain, port) => {
-                let _ =
-                    async_socks5::connect(&mut stream, (domain.to_owned(), port.to_owned()), None)
-                        .map_err(|x| io::Error::new(io::ErrorKind::Other, x))
-                        .await?;
+        connect(
+            &mut stream,
+            &self.username,
+            &self.password,
+            &sess.destination,
+        )
+        .await?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Accepts the method the client offers last, then an authentication
+    // with `reply`, then a connect request.
+    async fn serve(mut stream: tokio::io::DuplexStream, reply: [u8; 2]) -> Vec<u8> {
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        let mut methods = vec![0u8; buf[1] as usize];
+        stream.read_exact(&mut methods).await.unwrap();
+        let method = *methods.last().unwrap();
+        stream.write_all(&[0x05, method]).await.unwrap();
+        let mut received = methods;
+        if method == 0x02 {
+            let mut auth = vec![0u8; 1 + 1 + 4 + 1 + 4];
+            stream.read_exact(&mut auth).await.unwrap();
+            received.extend_from_slice(&auth);
+            stream.write_all(&reply).await.unwrap();
+            if reply != [0x01, 0x00] {
+                return received;
             }
         }
-        Ok(stream)
+        let mut req = vec![0u8; 10];
+        stream.read_exact(&mut req).await.unwrap();
+        received.extend_from_slice(&req);
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        received
+    }
+
+    #[test]
+    fn test_connect() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let destination = SocksAddr::from(("1.2.3.4".parse::<std::net::IpAddr>().unwrap(), 80));
+        let request = [0x05, 0x01, 0x00, 0x01, 1, 2, 3, 4, 0x00, 0x50];
+        rt.block_on(async {
+            // No authentication.
+            let (mut client, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(serve(server, [0x01, 0x00]));
+            connect(&mut client, "", "", &destination).await.unwrap();
+            let received = server.await.unwrap();
+            assert_eq!(received[..1], [0x00]);
+            assert_eq!(received[1..], request);
+
+            // Username/password.
+            let (mut client, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(serve(server, [0x01, 0x00]));
+            connect(&mut client, "user", "pass", &destination)
+                .await
+                .unwrap();
+            let received = server.await.unwrap();
+            assert_eq!(received[..2], [0x00, 0x02]);
+            assert_eq!(received[2..13], *b"\x01\x04user\x04pass");
+            assert_eq!(received[13..], request);
+
+            // A failed authentication doesn't send the connect request.
+            let (mut client, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(serve(server, [0x01, 0x01]));
+            let err = connect(&mut client, "user", "pass", &destination)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            assert_eq!(server.await.unwrap().len(), 2 + 11);
+
+            // Nor does a reply of another version.
+            let (mut client, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(serve(server, [0x05, 0x00]));
+            let err = connect(&mut client, "user", "pass", &destination)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::Other);
+            assert_eq!(server.await.unwrap().len(), 2 + 11);
+        });
     }
 }
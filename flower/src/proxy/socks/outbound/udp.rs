@@ -5,8 +5,9 @@ use std::{
 
 use async_socks5::{AddrKind, Auth, SocksDatagram};
 use async_trait::async_trait;
+use bytes::BytesMut;
 use futures::future::TryFutureExt;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
 
 use crate::{
     app::SyncDnsClient,
@@ -14,9 +15,16 @@ use crate::{
     session::{Session, SocksAddr},
 };
 
+use super::{tcp, udp_over_tcp};
+
 pub struct Handler {
     pub address: String,
     pub port: u16,
+    pub username: String,
+    pub password: String,
+    /// Tunnels UDP over the TCP connection to the server instead of UDP
+    /// ASSOCIATE, which also allows chaining.
+    pub udp_over_tcp: bool,
     pub dns_client: SyncDnsClient,
 }
 
@@ -33,20 +41,46 @@ impl UdpOutboundHandler for Handler {
     }
 
     fn transport_type(&self) -> DatagramTransportType {
-        DatagramTransportType::Datagram
+        if self.udp_over_tcp {
+            DatagramTransportType::Stream
+        } else {
+            DatagramTransportType::Datagram
+        }
     }
 
     async fn handle<'a>(
         &'a self,
         sess: &'a Session,
-        _transport: Option<OutboundTransport<Self::UStream, Self::Datagram>>,
+        transport: Option<OutboundTransport<Self::UStream, Self::Datagram>>,
     ) -> io::Result<Self::Datagram> {
+        if self.udp_over_tcp {
+            let mut stream = match transport {
+                Some(OutboundTransport::Stream(stream)) => stream,
+                None => {
+                    self.new_tcp_stream(self.dns_client.clone(), &self.address, &self.port)
+                        .await?
+                }
+                _ => return Err(Error::new(ErrorKind::Other, "invalid input")),
+            };
+            let destination = SocksAddr::Domain(udp_over_tcp::DOMAIN.to_string(), 0);
+            tcp::connect(&mut stream, &self.username, &self.password, &destination).await?;
+            return Ok(Box::new(StreamDatagram { stream }));
+        }
+
         // TODO support chaining, this requires implementing our own socks5 client
         let stream = self
             .new_tcp_stream(self.dns_client.clone(), &self.address, &self.port)
             .await?;
         let socket = self.new_udp_socket(&sess.source).await?;
-        let socket = SocksDatagram::associate(stream, socket, None::<Auth>, None::<AddrKind>)
+        let auth = if self.username.is_empty() {
+            None
+        } else {
+            Some(Auth {
+                username: self.username.clone(),
+                password: self.password.clone(),
+            })
+        };
+        let socket = SocksDatagram::associate(stream, socket, auth, None::<AddrKind>)
             .map_err(|x| Error::new(ErrorKind::Other, x))
             .await?;
         Ok(Box::new(Datagram { socket }))
@@ -120,3 +154,121 @@ where
         }
     }
 }
+
+/// UDP over the TCP connection of a CONNECT request.
+pub struct StreamDatagram<S> {
+    stream: S,
+}
+
+impl<S> OutboundDatagram for StreamDatagram<S>
+where
+    S: 'static + AsyncRead + AsyncWrite + Unpin + Send + Sync,
+{
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ) {
+        let (r, w) = tokio::io::split(self.stream);
+        (
+            Box::new(StreamDatagramRecvHalf(r)),
+            Box::new(StreamDatagramSendHalf(w)),
+        )
+    }
+}
+
+pub struct StreamDatagramRecvHalf<T>(ReadHalf<T>);
+
+#[async_trait]
+impl<T> OutboundDatagramRecvHalf for StreamDatagramRecvHalf<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Sync,
+{
+    async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, SocksAddr)> {
+        udp_over_tcp::read_packet(&mut self.0, buf).await
+    }
+}
+
+pub struct StreamDatagramSendHalf<T>(WriteHalf<T>);
+
+#[async_trait]
+impl<T> OutboundDatagramSendHalf for StreamDatagramSendHalf<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Sync,
+{
+    async fn send_to(&mut self, buf: &[u8], target: &SocksAddr) -> Result<usize> {
+        let mut data = BytesMut::new();
+        udp_over_tcp::write_packet(&mut data, target, buf)?;
+        self.0.write_all(&data).map_ok(|_| buf.len()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[test]
+    fn test_udp_over_tcp() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut dns = crate::config::Dns::new();
+        dns.servers.push("127.0.0.1".to_string());
+        let dns_client =
+            crate::app::dns_client::DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap();
+        rt.block_on(async {
+            let handler = Handler {
+                address: "127.0.0.1".to_string(),
+                port: 1080,
+                username: "".to_string(),
+                password: "".to_string(),
+                udp_over_tcp: true,
+                dns_client: Arc::new(tokio::sync::RwLock::new(dns_client)),
+            };
+            assert_eq!(handler.transport_type(), DatagramTransportType::Stream);
+            let target = SocksAddr::from(("1.2.3.4".parse::<std::net::IpAddr>().unwrap(), 53));
+            let sess = Session {
+                destination: target.clone(),
+                ..Default::default()
+            };
+            let (client, mut server) = tokio::io::duplex(1024);
+            let server_task = tokio::spawn(async move {
+                let mut buf = [0u8; 3];
+                server.read_exact(&mut buf).await.unwrap();
+                server.write_all(&[0x05, 0x00]).await.unwrap();
+                let mut req = vec![0u8; 3 + 1 + 1 + udp_over_tcp::DOMAIN.len() + 2];
+                server.read_exact(&mut req).await.unwrap();
+                server
+                    .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                    .await
+                    .unwrap();
+                (server, req)
+            });
+            let transport = OutboundTransport::Stream(Box::new(client) as AnyStream);
+            let datagram = handler.handle(&sess, Some(transport)).await.unwrap();
+            let (mut server, req) = server_task.await.unwrap();
+            let mut expected = vec![0x05, 0x01, 0x00, 0x03, udp_over_tcp::DOMAIN.len() as u8];
+            expected.extend_from_slice(udp_over_tcp::DOMAIN.as_bytes());
+            expected.extend_from_slice(&[0, 0]);
+            assert_eq!(req, expected);
+
+            let (mut recv_half, mut send_half) = datagram.split();
+            assert_eq!(send_half.send_to(b"abc", &target).await.unwrap(), 3);
+            let mut buf = [0u8; 12];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'a', b'b', b'c']);
+
+            server
+                .write_all(&[0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'x', b'y', b'z'])
+                .await
+                .unwrap();
+            let mut buf = [0u8; 16];
+            let (n, addr) = recv_half.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"xyz");
+            assert_eq!(addr, target);
+        });
+    }
+}
@@ -0,0 +1,82 @@
+use std::cmp::min;
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+use log::*;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::session::{SocksAddr, SocksAddrWireType};
+
+// UDP over TCP is requested by a CONNECT to this domain, the connection then
+// carries UDP packets framed as the address, the 2-byte payload length and
+// the payload. The address is the destination of the packets sent by the
+// client, and the source of the packets sent by the server.
+pub const DOMAIN: &str = "udp-over-tcp.arpa";
+
+pub fn write_packet(buf: &mut BytesMut, addr: &SocksAddr, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "udp payload too large",
+        ));
+    }
+    addr.write_buf(buf, SocksAddrWireType::PortLast)?;
+    buf.put_u16(payload.len() as u16);
+    buf.put_slice(payload);
+    Ok(())
+}
+
+pub async fn read_packet<R>(r: &mut R, buf: &mut [u8]) -> io::Result<(usize, SocksAddr)>
+where
+    R: AsyncRead + Unpin,
+{
+    let addr = SocksAddr::read_from(r, SocksAddrWireType::PortLast).await?;
+    let payload_len = r.read_u16().await? as usize;
+    let mut payload = BytesMut::new();
+    payload.resize(payload_len, 0);
+    r.read_exact(&mut payload).await?;
+    let to_write = min(payload_len, buf.len());
+    if to_write < payload_len {
+        warn!(
+            "truncated udp payload, buf size too small: {} < {}",
+            buf.len(),
+            payload_len
+        );
+    }
+    buf[..to_write].copy_from_slice(&payload[..to_write]);
+    Ok((to_write, addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let v4 = SocksAddr::from(("1.2.3.4".parse::<std::net::IpAddr>().unwrap(), 53));
+            let domain = SocksAddr::Domain("example.com".to_string(), 443);
+            let mut data = BytesMut::new();
+            write_packet(&mut data, &v4, b"abc").unwrap();
+            assert_eq!(data[..], [0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'a', b'b', b'c']);
+            write_packet(&mut data, &domain, b"").unwrap();
+            write_packet(&mut data, &v4, b"defgh").unwrap();
+
+            let mut r = &data[..];
+            let mut buf = [0u8; 4];
+            assert_eq!(
+                read_packet(&mut r, &mut buf).await.unwrap(),
+                (3, v4.clone())
+            );
+            assert_eq!(&buf[..3], b"abc");
+            assert_eq!(read_packet(&mut r, &mut buf).await.unwrap(), (0, domain));
+            // Truncated to the buf, the rest of the payload is discarded.
+            assert_eq!(read_packet(&mut r, &mut buf).await.unwrap(), (4, v4));
+            assert_eq!(&buf, b"defg");
+            assert!(r.is_empty());
+        });
+    }
+}
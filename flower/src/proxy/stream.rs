@@ -2,7 +2,7 @@ use std::{io, pin::Pin};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::ready;
-use futures::task::{Context, Poll};
+use futures::task::{noop_waker_ref, Context, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use super::ProxyStream;
 /// A proxy stream simply wraps a stream implements `AsyncRead` and `AsyncWrite`.
@@ -81,6 +81,10 @@ where
         cx: &mut Context,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
+        // Keeps the header for the first non-empty payload.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
         let me = &mut *self;
         // Combine header and first payload.
         if let Some(head) = me.head.take() {
@@ -109,3 +113,204 @@ where
         Pin::new(&mut self.inner).poll_shutdown(cx)
     }
 }
+
+/// A proxy stream returns the given data before reading from the wrapped
+/// stream, e.g. data read along with a protocol header.
+pub struct PrefixedProxyStream<T> {
+    inner: T,
+    prefix: BytesMut,
+}
+
+impl<T> PrefixedProxyStream<T> {
+    pub fn new(inner: T, prefix: BytesMut) -> Self {
+        Self { inner, prefix }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for PrefixedProxyStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if !self.prefix.is_empty() {
+            let n = std::cmp::min(buf.remaining(), self.prefix.len());
+            let data = self.prefix.split_to(n);
+            buf.put_slice(&data);
+            if self.prefix.is_empty() {
+                self.prefix = BytesMut::new();
+            }
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for PrefixedProxyStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Shuts down the wrapped stream when dropped, unless it has been shut down
+/// already. Streams of an aborted relay are dropped without being shut down,
+/// and a stream nested in layers holding the underlying transport elsewhere,
+/// e.g. in a connection pool, isn't closed by dropping it alone.
+///
+/// The shutdown is polled once on drop, it is best effort as there's no way
+/// to wait for it.
+pub struct ShutdownOnDrop<T: AsyncWrite + Unpin> {
+    inner: T,
+    shutdown: bool,
+}
+
+impl<T: AsyncWrite + Unpin> ShutdownOnDrop<T> {
+    pub fn new(inner: T) -> Self {
+        ShutdownOnDrop {
+            inner,
+            shutdown: false,
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Drop for ShutdownOnDrop<T> {
+    fn drop(&mut self) {
+        if !self.shutdown {
+            let mut cx = Context::from_waker(noop_waker_ref());
+            let _ = Pin::new(&mut self.inner).poll_shutdown(&mut cx);
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for ShutdownOnDrop<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for ShutdownOnDrop<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let res = ready!(Pin::new(&mut self.inner).poll_shutdown(cx));
+        self.shutdown = true;
+        Poll::Ready(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time::timeout;
+
+    use super::*;
+
+    // A stream whose socket outlives it, like a stream whose transport is
+    // kept by a connection pool.
+    struct SharedStream(Arc<Mutex<TcpStream>>);
+
+    impl AsyncRead for SharedStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut *self.0.lock().unwrap()).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for SharedStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut *self.0.lock().unwrap()).poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+            Pin::new(&mut *self.0.lock().unwrap()).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+            Pin::new(&mut *self.0.lock().unwrap()).poll_shutdown(cx)
+        }
+    }
+
+    async fn tcp_pair() -> (TcpStream, Arc<Mutex<TcpStream>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (client, Arc::new(Mutex::new(server)))
+    }
+
+    #[test]
+    fn test_shutdown_on_drop() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut a, a_socket) = tcp_pair().await;
+            let (mut b, b_socket) = tcp_pair().await;
+            let mut lhs = ShutdownOnDrop::new(SharedStream(a_socket.clone()));
+            let mut rhs = ShutdownOnDrop::new(SharedStream(b_socket.clone()));
+            let relay = tokio::spawn(async move {
+                let _ = tokio::io::copy_bidirectional(&mut lhs, &mut rhs).await;
+            });
+
+            let mut buf = [0u8; 3];
+            a.write_all(b"abc").await.unwrap();
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"abc");
+
+            // Both peers see the connection closed once the relay is aborted,
+            // though the sockets are still referenced.
+            relay.abort();
+            assert!(relay.await.is_err());
+            let n = timeout(Duration::from_secs(1), a.read(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(n, 0);
+            let n = timeout(Duration::from_secs(1), b.read(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(n, 0);
+            drop((a_socket, b_socket));
+        });
+    }
+}
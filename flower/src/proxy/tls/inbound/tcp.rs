@@ -3,21 +3,28 @@ use std::fs::File;
 use std::io::{self, BufReader};
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 #[cfg(feature = "openssl-tls")]
-use openssl::ssl::{Ssl, SslMethod, SslAcceptor, SslFiletype};
-#[cfg(feature = "openssl-tls")]
-use tokio_openssl::SslStream;
+use {
+    openssl::ssl::{Ssl, SslAcceptor, SslFiletype, SslMethod, SslVerifyMode},
+    openssl::x509::X509Name,
+    std::pin::Pin,
+    tokio_openssl::SslStream,
+};
 
 #[cfg(feature = "rustls-tls")]
 use {
-    rustls_pemfile::{certs, pkcs8_private_keys, rsa_private_keys},
-    tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig},
+    rustls_pemfile::certs,
+    tokio_rustls::rustls::server::{AllowAnyAuthenticatedClient, NoClientAuth},
+    tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig},
     tokio_rustls::TlsAcceptor,
-    tokio_rustls::rustls::server::NoClientAuth,
 };
 
-use crate::{proxy::*, session::Session};
+use crate::{
+    common::cert,
+    proxy::{tls::Protocols, *},
+    session::Session,
+};
 
 pub struct Handler {
     #[cfg(feature = "rustls-tls")]
@@ -27,45 +34,108 @@ pub struct Handler {
 }
 
 #[cfg(feature = "rustls-tls")]
-fn load_certs(path: &Path) -> io::Result<Vec<Certificate>> {
-    let bufs = certs(&mut BufReader::new(File::open(path)?))
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid cert"))
-        .unwrap();
-    let mut certs = Vec::<Certificate>::new();
-    for buf in bufs {
-        certs.push(Certificate(buf))
+fn load_certs(path: &Path) -> Result<Vec<Certificate>> {
+    let file = File::open(path).map_err(|e| anyhow!("read {} failed: {}", path.display(), e))?;
+    let certs =
+        certs(&mut BufReader::new(file)).map_err(|_| anyhow!("invalid cert {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificates in {}", path.display()));
     }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
 
-    return Ok(certs)
+// Reads a DER element, returns the tag, the content and the remaining data.
+fn read_der(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *buf.first()?;
+    let first = *buf.get(1)? as usize;
+    let (len, offset) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 4 || buf.len() < 2 + n {
+            return None;
+        }
+        let len = buf[2..2 + n]
+            .iter()
+            .fold(0usize, |len, b| (len << 8) | *b as usize);
+        (len, 2 + n)
+    };
+    if buf.len() < offset + len {
+        return None;
+    }
+    Some((tag, &buf[offset..offset + len], &buf[offset + len..]))
 }
 
-#[cfg(feature = "rustls-tls")]
-fn load_keys(path: &Path) -> io::Result<Vec<PrivateKey>> {
-    let mut keys = pkcs8_private_keys(&mut BufReader::new(File::open(path)?))
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid key"))?;
-    let mut keys2 = rsa_private_keys(&mut BufReader::new(File::open(path)?))
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid key"))?;
-    keys.append(&mut keys2);
-    let mut results = Vec::<PrivateKey>::new();
-    for key in keys {
-        results.push(PrivateKey(key))
+/// Returns the common name in the subject of a DER encoded certificate.
+pub fn common_name(cert: &[u8]) -> Option<String> {
+    const SEQUENCE: u8 = 0x30;
+    const SET: u8 = 0x31;
+    const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+    let (_, cert, _) = read_der(cert).filter(|(tag, ..)| *tag == SEQUENCE)?;
+    let (_, mut tbs, _) = read_der(cert).filter(|(tag, ..)| *tag == SEQUENCE)?;
+    // version, which is optional
+    if tbs.first() == Some(&0xa0) {
+        tbs = read_der(tbs)?.2;
     }
-    Ok(results)
+    // serial number, signature algorithm, issuer and validity
+    for _ in 0..4 {
+        tbs = read_der(tbs)?.2;
+    }
+    let (_, mut subject, _) = read_der(tbs).filter(|(tag, ..)| *tag == SEQUENCE)?;
+    while !subject.is_empty() {
+        let (tag, mut rdn, rest) = read_der(subject)?;
+        subject = rest;
+        if tag != SET {
+            continue;
+        }
+        while !rdn.is_empty() {
+            let (_, attr, rest) = read_der(rdn)?;
+            rdn = rest;
+            let (_, oid, value) = read_der(attr)?;
+            if oid == OID_COMMON_NAME {
+                let (_, value, _) = read_der(value)?;
+                return Some(String::from_utf8_lossy(value).to_string());
+            }
+        }
+    }
+    None
 }
 
 impl Handler {
-    pub fn new(certificate: String, certificate_key: String) -> Result<Self> {
+    /// Clients must present a certificate signed by `client_ca` if it's not
+    /// empty. The private key is read from `certificate` if
+    /// `certificate_key` is empty, `certificate_password` decrypts a PKCS#12
+    /// `certificate`.
+    pub fn new(
+        certificate: String,
+        certificate_key: String,
+        certificate_password: String,
+        client_ca: String,
+        protocols: Protocols,
+    ) -> Result<Self> {
         #[cfg(feature = "rustls-tls")]
         {
-            let certs = load_certs(Path::new(&certificate))?;
-            let mut keys = load_keys(Path::new(&certificate_key))?;
-            let config = ServerConfig::builder()
-                .with_safe_default_cipher_suites()
+            let certified = cert::load(&certificate, &certificate_key, &certificate_password)?;
+            let certs = certified.certs.into_iter().map(Certificate).collect();
+            let builder = ServerConfig::builder()
+                .with_cipher_suites(&protocols.rustls_cipher_suites())
                 .with_safe_default_kx_groups()
-                .with_safe_default_protocol_versions()
-                .unwrap()
-                .with_no_client_auth()
-                .with_single_cert(certs, keys.remove(0))
+                .with_protocol_versions(&protocols.rustls_versions())
+                .map_err(|e| anyhow!("invalid tls protocols: {}", e))?;
+            let builder = if client_ca.is_empty() {
+                builder.with_client_cert_verifier(NoClientAuth::new())
+            } else {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(Path::new(&client_ca))? {
+                    roots
+                        .add(&cert)
+                        .map_err(|e| anyhow!("invalid client ca {}: {}", &client_ca, e))?;
+                }
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+            };
+            let config = builder
+                .with_single_cert(certs, PrivateKey(certified.key))
                 .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
             // config
             //     .set_single_cert(certs, keys.remove(0))
@@ -75,15 +145,56 @@ impl Handler {
             Ok(Self { acceptor })
         }
         #[cfg(feature = "openssl-tls")]
-        unimplemented!()
-        // {
-        //     let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
-        //     acceptor.set_private_key_file(certificate_key, SslFiletype::PEM).unwrap();
-        //     acceptor.set_certificate_chain_file(certificate).unwrap();
-        //     acceptor.check_private_key().unwrap();
-        //     let acceptor = Arc::new(acceptor.build());
-        //     Ok(Self {ssl_acceptor: acceptor.clone() })
-        // }
+        {
+            let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
+                .map_err(|e| anyhow!("create ssl acceptor failed: {}", e))?;
+            if cert::is_pkcs12(&certificate) {
+                let parsed = cert::load_pkcs12(&certificate, &certificate_password)?;
+                let (cert, pkey) = match (parsed.cert, parsed.pkey) {
+                    (Some(cert), Some(pkey)) => (cert, pkey),
+                    _ => return Err(anyhow!("no certificate or private key in {}", &certificate)),
+                };
+                builder
+                    .set_certificate(&cert)
+                    .map_err(|e| anyhow!("invalid cert {}: {}", &certificate, e))?;
+                builder
+                    .set_private_key(&pkey)
+                    .map_err(|e| anyhow!("invalid key {}: {}", &certificate, e))?;
+                for ca in parsed.ca.into_iter().flatten() {
+                    builder
+                        .add_extra_chain_cert(ca)
+                        .map_err(|e| anyhow!("invalid cert {}: {}", &certificate, e))?;
+                }
+            } else {
+                let key_file = if certificate_key.is_empty() {
+                    &certificate
+                } else {
+                    &certificate_key
+                };
+                builder
+                    .set_private_key_file(key_file, SslFiletype::PEM)
+                    .map_err(|e| anyhow!("invalid key {}: {}", key_file, e))?;
+                builder
+                    .set_certificate_chain_file(&certificate)
+                    .map_err(|e| anyhow!("invalid cert {}: {}", &certificate, e))?;
+            }
+            builder
+                .check_private_key()
+                .map_err(|e| anyhow!("cert and key mismatch: {}", e))?;
+            protocols.apply_openssl(&mut builder)?;
+            if !client_ca.is_empty() {
+                builder
+                    .set_ca_file(&client_ca)
+                    .map_err(|e| anyhow!("invalid client ca {}: {}", &client_ca, e))?;
+                let names = X509Name::load_client_ca_file(&client_ca)
+                    .map_err(|e| anyhow!("invalid client ca {}: {}", &client_ca, e))?;
+                builder.set_client_ca_list(names);
+                builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+            }
+            Ok(Self {
+                ssl_acceptor: Arc::new(builder.build()),
+            })
+        }
     }
 }
 
@@ -99,22 +210,209 @@ impl TcpInboundHandler for Handler {
     ) -> std::io::Result<InboundTransport<Self::TStream, Self::TDatagram>> {
         #[cfg(feature = "rustls-tls")]
         {
-            Ok(InboundTransport::Stream(
-                Box::new(self.acceptor.accept(stream).await?),
-                sess,
-            ))
+            let mut sess = sess;
+            let stream = self.acceptor.accept(stream).await?;
+            // Present only if the client is authenticated.
+            let (_, conn) = stream.get_ref();
+            if let Some(cert) = conn.peer_certificates().and_then(|c| c.first()) {
+                sess.user = common_name(&cert.0);
+            }
+            Ok(InboundTransport::Stream(Box::new(stream), sess))
         }
 
         #[cfg(feature = "openssl-tls")]
-        unimplemented!()
-        // {
-        //     let ssl  = Ssl::new(self.ssl_acceptor.context()).unwrap();
-        //     let mut steam = SslStream::new(ssl, stream).unwrap();
-        //     steam.accept().await?;
-        //     Ok(InboundTransport::Stream(
-        //         Box::new(steam),
-        //         sess,
-        //     ))
-        // }
+        {
+            let mut sess = sess;
+            let ssl = Ssl::new(self.ssl_acceptor.context())
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            let mut stream =
+                SslStream::new(ssl, stream).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            Pin::new(&mut stream)
+                .accept()
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            // Present only if the client is authenticated.
+            if let Some(cert) = stream.ssl().peer_certificate() {
+                sess.user = cert.to_der().ok().and_then(|der| common_name(&der));
+            }
+            Ok(InboundTransport::Stream(Box::new(stream), sess))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut v = vec![tag];
+        if content.len() < 0x80 {
+            v.push(content.len() as u8);
+        } else {
+            v.push(0x82);
+            v.extend_from_slice(&(content.len() as u16).to_be_bytes());
+        }
+        v.extend_from_slice(content);
+        v
+    }
+
+    fn name(attrs: &[(&[u8], &str)]) -> Vec<u8> {
+        let mut rdns = Vec::new();
+        for (oid, value) in attrs {
+            let mut attr = der(0x06, oid);
+            attr.extend(der(0x0c, value.as_bytes()));
+            rdns.extend(der(0x31, &der(0x30, &attr)));
+        }
+        der(0x30, &rdns)
+    }
+
+    const OID_ORGANIZATION: &[u8] = &[0x55, 0x04, 0x0a];
+    const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+    fn new_cert(subject: &[(&[u8], &str)]) -> Vec<u8> {
+        let mut tbs = der(0xa0, &der(0x02, &[2]));
+        tbs.extend(der(0x02, &[1]));
+        tbs.extend(der(0x30, &der(0x06, &[0x2a, 0x86, 0x48])));
+        tbs.extend(name(&[(OID_COMMON_NAME, "ca")]));
+        tbs.extend(der(0x30, &[0u8; 200]));
+        tbs.extend(name(subject));
+        der(0x30, &der(0x30, &tbs))
+    }
+
+    #[test]
+    fn test_common_name() {
+        let cert = new_cert(&[(OID_ORGANIZATION, "flower"), (OID_COMMON_NAME, "alice")]);
+        assert_eq!(common_name(&cert).as_deref(), Some("alice"));
+        let cert = new_cert(&[(OID_ORGANIZATION, "flower")]);
+        assert_eq!(common_name(&cert), None);
+        assert_eq!(common_name(&cert[..cert.len() - 1]), None);
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    fn new_ca() -> rcgen::Certificate {
+        let mut params = rcgen::CertificateParams::new(Vec::new());
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        rcgen::Certificate::from_params(params).unwrap()
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    fn new_leaf(names: Vec<String>, common_name: &str) -> rcgen::Certificate {
+        let mut params = rcgen::CertificateParams::new(names);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, common_name);
+        rcgen::Certificate::from_params(params).unwrap()
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn test_client_auth() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_rustls::rustls::{ClientConfig, ServerName};
+        use tokio_rustls::TlsConnector;
+
+        let dir = std::env::temp_dir().join(format!("flower-mtls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let ca = new_ca();
+        let server = new_leaf(vec!["localhost".to_string()], "localhost");
+        std::fs::write(path("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+        std::fs::write(
+            path("server.pem"),
+            server.serialize_pem_with_signer(&ca).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(path("server.key"), server.serialize_private_key_pem()).unwrap();
+        let handler = Handler::new(
+            path("server.pem"),
+            path("server.key"),
+            String::new(),
+            path("ca.pem"),
+            Protocols::default(),
+        )
+        .unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(&Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let client_config = |client: Option<(&rcgen::Certificate, &rcgen::Certificate)>| {
+            let config = match client {
+                Some((cert, signer)) => builder
+                    .clone()
+                    .with_single_cert(
+                        vec![Certificate(cert.serialize_der_with_signer(signer).unwrap())],
+                        PrivateKey(cert.serialize_private_key_der()),
+                    )
+                    .unwrap(),
+                None => builder.clone().with_no_client_auth(),
+            };
+            TlsConnector::from(Arc::new(config))
+        };
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            // Returns the authenticated user, or the handshake error.
+            let connect = |connector: TlsConnector| async {
+                let client = async move {
+                    let stream = tokio::net::TcpStream::connect(addr).await?;
+                    let name = ServerName::try_from("localhost").unwrap();
+                    let mut stream = connector.connect(name, stream).await?;
+                    // A rejected TLS 1.3 client learns it on the first read.
+                    stream.write_all(b"ping").await?;
+                    stream.read_exact(&mut [0u8; 4]).await
+                };
+                let server = async {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let res = handler.handle(Session::default(), Box::new(stream)).await;
+                    match res {
+                        Ok(InboundTransport::Stream(mut stream, sess)) => {
+                            let mut buf = [0u8; 4];
+                            stream.read_exact(&mut buf).await?;
+                            stream.write_all(&buf).await?;
+                            Ok(sess.user)
+                        }
+                        Ok(_) => unreachable!(),
+                        Err(e) => Err(e),
+                    }
+                };
+                let (client_res, server_res) = tokio::join!(client, server);
+                if server_res.is_err() {
+                    assert!(client_res.is_err());
+                }
+                server_res
+            };
+
+            let alice = new_leaf(vec!["alice".to_string()], "alice");
+            let user = connect(client_config(Some((&alice, &ca)))).await.unwrap();
+            assert_eq!(user.as_deref(), Some("alice"));
+            // No client certificate.
+            assert!(connect(client_config(None)).await.is_err());
+            // A client certificate not signed by the client CA.
+            let other_ca = new_ca();
+            assert!(connect(client_config(Some((&alice, &other_ca))))
+                .await
+                .is_err());
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn test_load_certs() {
+        assert!(load_certs(Path::new("/nonexistent/flower/ca.pem")).is_err());
+        let path = std::env::temp_dir().join(format!("flower-test-ca-{}.pem", std::process::id()));
+        std::fs::write(&path, "not a certificate").unwrap();
+        let res = load_certs(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(res.is_err());
     }
 }
@@ -2,3 +2,6 @@
 pub mod inbound;
 #[cfg(feature = "outbound-tls")]
 pub mod outbound;
+pub mod protocols;
+
+pub use protocols::Protocols;
@@ -1,3 +1,3 @@
 pub mod tcp;
 
-pub use tcp::Handler as TcpHandler;
+pub use tcp::{negotiated_alpn, Handler as TcpHandler};
@@ -1,51 +1,178 @@
 use std::fs::File;
 use std::io;
 use std::io::BufReader;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use futures::TryFutureExt;
 use log::*;
+use tokio::time::timeout;
 
 #[cfg(feature = "rustls-tls")]
 use {
-    std::sync::Arc,
-    tokio_rustls::TlsConnector,
+    rustls::client::{
+        ClientSessionMemoryCache, ServerCertVerified, ServerCertVerifier, WebPkiVerifier,
+    },
+    rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore},
     rustls_pemfile::certs,
     std::path::Path,
-    rustls::{OwnedTrustAnchor, RootCertStore, ClientConfig},
+    std::sync::Arc,
+    tokio_rustls::TlsConnector,
 };
 
 #[cfg(feature = "openssl-tls")]
 use {
-    openssl::ssl::{Ssl, SslConnector, SslMethod},
+    openssl::ssl::{Ssl, SslConnector, SslMethod, SslVerifyMode},
     std::pin::Pin,
     std::sync::Once,
     tokio_openssl::SslStream,
 };
 
-use crate::{proxy::*, session::Session};
+use crate::{
+    proxy::{tls::Protocols, *},
+    session::Session,
+};
+
+/// A trusted time for certificate validity checks, for devices with a wrong
+/// system clock. It advances along with the monotonic clock.
+struct VerifyTime {
+    base: SystemTime,
+    since: Instant,
+}
+
+impl VerifyTime {
+    // Accepts an RFC 3339 timestamp, e.g. 2022-01-01T00:00:00Z.
+    fn parse(s: &str) -> Result<Self> {
+        let t = chrono::DateTime::parse_from_rfc3339(s)
+            .map_err(|e| anyhow!("invalid verify time [{}]: {}", s, e))?;
+        let secs = u64::try_from(t.timestamp())
+            .map_err(|_| anyhow!("invalid verify time [{}]: before 1970", s))?;
+        Ok(VerifyTime {
+            base: UNIX_EPOCH + Duration::from_secs(secs),
+            since: Instant::now(),
+        })
+    }
+
+    fn now(&self) -> SystemTime {
+        self.base + self.since.elapsed()
+    }
+}
+
+#[cfg(feature = "rustls-tls")]
+struct VerifyTimeVerifier {
+    inner: WebPkiVerifier,
+    time: VerifyTime,
+}
+
+#[cfg(feature = "rustls-tls")]
+impl ServerCertVerifier for VerifyTimeVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            self.time.now(),
+        )
+    }
+}
+
+/// Accepts any server certificate, for testing against self-signed servers.
+#[cfg(feature = "rustls-tls")]
+struct InsecureVerifier;
+
+#[cfg(feature = "rustls-tls")]
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
 
 pub struct Handler {
     server_name: String,
+    alpns: Vec<String>,
+    handshake_timeout: Duration,
     #[cfg(feature = "rustls-tls")]
     tls_config: Arc<ClientConfig>,
     #[cfg(feature = "openssl-tls")]
     ssl_connector: SslConnector,
+    #[cfg(feature = "openssl-tls")]
+    verify_time: Option<VerifyTime>,
 }
 
 #[cfg(feature = "rustls-tls")]
-fn load_certs(path: &Path) -> io::Result<Vec<Vec<u8>>> {
-    certs(&mut BufReader::new(File::open(path)?))
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid cert"))
+fn load_certs(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let file = File::open(path).map_err(|e| anyhow!("read {} failed: {}", path.display(), e))?;
+    let certs =
+        certs(&mut BufReader::new(file)).map_err(|_| anyhow!("invalid cert {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificates in {}", path.display()));
+    }
+    Ok(certs)
 }
 
+/// The number of TLS sessions cached for resumption by default.
+pub const DEFAULT_SESSION_CACHE_SIZE: usize = 256;
+
 impl Handler {
+    /// Sessions are cached per server name for resumption, at most
+    /// `session_cache_size` of them, or `DEFAULT_SESSION_CACHE_SIZE` if it's
+    /// 0. Resumption is only supported by the rustls backend.
+    ///
+    /// Server certificates are not verified at all if `insecure` is set, this
+    /// is meant for testing only.
+    ///
+    /// Handshakes fail with `io::ErrorKind::TimedOut` if not completed
+    /// within `handshake_timeout`.
+    ///
+    /// Fails if none of the cipher suites of `protocols` is usable.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         server_name: String,
         alpns: Vec<String>,
         certificate: Option<String>,
+        verify_time: Option<String>,
+        session_cache_size: usize,
+        insecure: bool,
+        protocols: Protocols,
+        handshake_timeout: Duration,
     ) -> Result<Self> {
+        if insecure {
+            warn!(
+                "tls certificates to {} are NOT verified, the connections are open to interception, use it for testing only",
+                if server_name.is_empty() { "destinations" } else { &server_name },
+            );
+        }
+        let session_cache_size = if session_cache_size == 0 {
+            DEFAULT_SESSION_CACHE_SIZE
+        } else {
+            session_cache_size
+        };
+        let verify_time = verify_time.as_deref().map(VerifyTime::parse).transpose()?;
+        if verify_time.is_some() {
+            warn!(
+                "tls certificates to {} are verified against a time override instead of the system clock",
+                if server_name.is_empty() { "destinations" } else { &server_name },
+            );
+        }
         #[cfg(feature = "rustls-tls")]
         {
             let mut root_certs = RootCertStore::empty();
@@ -63,20 +190,41 @@ impl Handler {
             );
             if let Some(cert) = certificate {
                 let path = Path::new(&cert);
-                let c = load_certs(path).unwrap();
+                let c = load_certs(path)?;
                 root_certs.add_parsable_certificates(c.as_slice());
             }
 
-            let mut config = rustls::ClientConfig::builder()
-                .with_safe_defaults()
-                .with_root_certificates(root_certs)
-                .with_no_client_auth();
+            let builder = rustls::ClientConfig::builder()
+                .with_cipher_suites(&protocols.rustls_cipher_suites())
+                .with_safe_default_kx_groups()
+                .with_protocol_versions(&protocols.rustls_versions())
+                .map_err(|e| anyhow!("invalid tls protocols: {}", e))?;
+            let mut config = if insecure {
+                builder
+                    .with_custom_certificate_verifier(Arc::new(InsecureVerifier))
+                    .with_no_client_auth()
+            } else if let Some(time) = verify_time {
+                builder
+                    .with_custom_certificate_verifier(Arc::new(VerifyTimeVerifier {
+                        inner: WebPkiVerifier::new(root_certs, None),
+                        time,
+                    }))
+                    .with_no_client_auth()
+            } else {
+                builder
+                    .with_root_certificates(root_certs)
+                    .with_no_client_auth()
+            };
 
-            for alpn in alpns {
+            for alpn in alpns.iter() {
                 config.alpn_protocols.push(alpn.as_bytes().to_vec());
             }
+            // Shared by the connections of this outbound.
+            config.session_storage = ClientSessionMemoryCache::new(session_cache_size);
             Ok(Handler {
                 server_name,
+                alpns,
+                handshake_timeout,
                 tls_config: Arc::new(config),
             })
         }
@@ -90,16 +238,25 @@ impl Handler {
                 SslConnector::builder(SslMethod::tls()).expect("create ssl connector failed");
             if alpns.len() > 0 {
                 let wire = alpns
-                    .into_iter()
+                    .iter()
                     .map(|a| [&[a.len() as u8], a.as_bytes()].concat())
                     .collect::<Vec<Vec<u8>>>()
                     .concat();
                 builder.set_alpn_protos(&wire).expect("set alpn failed");
             }
+            if insecure {
+                builder.set_verify(SslVerifyMode::NONE);
+            }
+            protocols.apply_openssl(&mut builder)?;
+            // TODO Resume sessions.
+            let _ = session_cache_size;
             let ssl_connector = builder.build();
             Ok(Handler {
                 server_name,
+                alpns,
+                handshake_timeout,
                 ssl_connector,
+                verify_time,
             })
         }
     }
@@ -112,6 +269,37 @@ where
     io::Error::new(io::ErrorKind::Other, "tls error")
 }
 
+// Fails if protocols are requested but the negotiated one is not among them,
+// returns the negotiated protocol.
+fn check_alpn(requested: &[String], negotiated: Option<&[u8]>) -> io::Result<Option<String>> {
+    let negotiated = negotiated.map(|p| String::from_utf8_lossy(p).to_string());
+    if requested.is_empty() {
+        return Ok(negotiated);
+    }
+    match negotiated {
+        Some(p) if requested.contains(&p) => Ok(Some(p)),
+        Some(p) => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("unexpected alpn {} negotiated", p),
+        )),
+        None => Err(io::Error::new(io::ErrorKind::Other, "no alpn negotiated")),
+    }
+}
+
+/// Returns the ALPN protocol negotiated over a stream made by the handler.
+pub fn negotiated_alpn(stream: &AnyStream) -> Option<String> {
+    let stream = (**stream).as_any();
+    #[cfg(feature = "rustls-tls")]
+    let alpn = stream
+        .downcast_ref::<tokio_rustls::client::TlsStream<AnyStream>>()
+        .and_then(|s| s.get_ref().1.alpn_protocol());
+    #[cfg(feature = "openssl-tls")]
+    let alpn = stream
+        .downcast_ref::<SslStream<AnyStream>>()
+        .and_then(|s| s.ssl().selected_alpn_protocol());
+    alpn.map(|p| String::from_utf8_lossy(p).to_string())
+}
+
 #[async_trait]
 impl TcpOutboundHandler for Handler {
     type Stream = AnyStream;
@@ -138,23 +326,37 @@ impl TcpOutboundHandler for Handler {
                 // // let dnsname = DnsNameRef::try_from_ascii_str(&name).map_err(tls_err)?;
                 let domain = rustls::ServerName::try_from(name.as_str())
                     .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid dnsname"))?;
-                let tls_stream = config.connect(domain, stream).map_err(tls_err).await?;
+                let tls_stream = timeout(
+                    self.handshake_timeout,
+                    config.connect(domain, stream).map_err(tls_err),
+                )
+                .await??;
 
-                // TODO check negotiated alpn
+                check_alpn(&self.alpns, tls_stream.get_ref().1.alpn_protocol())?;
                 Ok(Box::new(tls_stream))
             }
             #[cfg(feature = "openssl-tls")]
             {
                 let mut ssl = Ssl::new(self.ssl_connector.context()).map_err(tls_err)?;
                 ssl.set_hostname(&name).map_err(tls_err)?;
+                if let Some(time) = &self.verify_time {
+                    let secs = time
+                        .now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    ssl.param_mut().set_time(secs as _);
+                }
                 let mut stream = SslStream::new(ssl, stream).map_err(tls_err)?;
-                Pin::new(&mut stream)
-                    .connect()
-                    .map_err(|e| {
+                timeout(
+                    self.handshake_timeout,
+                    Pin::new(&mut stream).connect().map_err(|e| {
                         log::trace!("connect tls stream failed: {}", e);
                         tls_err(e)
-                    })
-                    .await?;
+                    }),
+                )
+                .await??;
+                check_alpn(&self.alpns, stream.ssl().selected_alpn_protocol())?;
                 Ok(Box::new(stream))
             }
         } else {
@@ -162,3 +364,179 @@ impl TcpOutboundHandler for Handler {
         }
     }
 }
+
+#[cfg(all(test, feature = "rustls-tls"))]
+mod tests {
+    use super::*;
+
+    // A CA and a certificate for example.com issued by it, both valid only in 2020.
+    const CA_CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBljCCAT2gAwIBAgIUcV1Ue8av577rgQK7g+bGho1JyBAwCgYIKoZIzj0EAwIw
+GTEXMBUGA1UEAwwORmxvd2VyIFRlc3QgQ0EwHhcNMjAwMTAxMDAwMDAwWhcNMjAx
+MjMxMDAwMDAwWjAZMRcwFQYDVQQDDA5GbG93ZXIgVGVzdCBDQTBZMBMGByqGSM49
+AgEGCCqGSM49AwEHA0IABHSkgNqsmOdbjJO2pdBPjYlF4+j/iUXDpzr8EZXce+/x
+tA7toUPTH2W7/RnVGzvFMbLzV5+A7NT0m2Y1mYRISP+jYzBhMB0GA1UdDgQWBBSt
+QyPlziKxwTw10adeT+yL9pf99zAfBgNVHSMEGDAWgBStQyPlziKxwTw10adeT+yL
+9pf99zAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB/wQEAwICBDAKBggqhkjOPQQD
+AgNHADBEAiB9n9uXkF6jz+oUXBXbStuHHaeFdnsVWo62nOvKSODKUAIgfD3r0F2X
+JArdQT+hB6+9bQlTB5XQGwOzetAeHppSVFk=
+-----END CERTIFICATE-----
+";
+    const LEAF_CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBlzCCAT6gAwIBAgIBAjAKBggqhkjOPQQDAjAZMRcwFQYDVQQDDA5GbG93ZXIg
+VGVzdCBDQTAeFw0yMDAxMDEwMDAwMDBaFw0yMDEyMzEwMDAwMDBaMBYxFDASBgNV
+BAMMC2V4YW1wbGUuY29tMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAERLu8Nq0M
+CfJlo56Wf0G1dGfiJOZhH/1rUIMNYAXbTqFI+2Jfb7zzuQOtOrYtxRPS9NhNUJ8W
+QZSog2scnBUz3qN6MHgwFgYDVR0RBA8wDYILZXhhbXBsZS5jb20wCQYDVR0TBAIw
+ADATBgNVHSUEDDAKBggrBgEFBQcDATAdBgNVHQ4EFgQUMPPr4L6nC2hIy7Y+eoiZ
+b2WM3OUwHwYDVR0jBBgwFoAUrUMj5c4iscE8NdGnXk/si/aX/fcwCgYIKoZIzj0E
+AwIDRwAwRAIgYv40qAHiTYvfWeCoay9YHUmFH2E9y7y35EnduCD4FqsCIDTNzV2C
+9SE+LxPn+Z+IgLVFuxRaHXzuv/bdCcQ/i/IQ
+-----END CERTIFICATE-----
+";
+
+    fn verify(verifier: &dyn ServerCertVerifier) -> Result<ServerCertVerified, rustls::Error> {
+        let leaf = certs(&mut LEAF_CERT.as_bytes()).unwrap().remove(0);
+        let name = rustls::ServerName::try_from("example.com").unwrap();
+        verifier.verify_server_cert(
+            &rustls::Certificate(leaf),
+            &[],
+            &name,
+            &mut std::iter::empty(),
+            &[],
+            SystemTime::now(),
+        )
+    }
+
+    fn roots() -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(&certs(&mut CA_CERT.as_bytes()).unwrap());
+        roots
+    }
+
+    #[test]
+    fn test_handshake_timeout() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            // Accepts TCP connections but never answers the TLS handshake.
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut conns = Vec::new();
+                while let Ok((conn, _)) = listener.accept().await {
+                    conns.push(conn);
+                }
+            });
+
+            let handler = Handler::new(
+                "example.com".to_string(),
+                Vec::new(),
+                None,
+                None,
+                0,
+                false,
+                Protocols::default(),
+                Duration::from_millis(100),
+            )
+            .unwrap();
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let start = Instant::now();
+            let res =
+                TcpOutboundHandler::handle(&handler, &Session::default(), Some(Box::new(stream)))
+                    .await;
+            assert_eq!(res.err().unwrap().kind(), io::ErrorKind::TimedOut);
+            assert!(start.elapsed() < Duration::from_secs(2));
+        });
+    }
+
+    #[test]
+    fn test_check_alpn() {
+        let requested = vec!["h2".to_string(), "http/1.1".to_string()];
+        assert_eq!(
+            check_alpn(&requested, Some(&b"h2"[..])).unwrap().as_deref(),
+            Some("h2")
+        );
+        assert!(check_alpn(&requested, Some(&b"spdy/3"[..])).is_err());
+        assert!(check_alpn(&requested, None).is_err());
+        assert_eq!(check_alpn(&[], None).unwrap(), None);
+        assert_eq!(
+            check_alpn(&[], Some(&b"h2"[..])).unwrap().as_deref(),
+            Some("h2")
+        );
+    }
+
+    #[test]
+    fn test_negotiated_alpn() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            let mut config = rustls::ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![rustls::Certificate(cert.serialize_der().unwrap())],
+                    rustls::PrivateKey(cert.serialize_private_key_der()),
+                )
+                .unwrap();
+            config.alpn_protocols = vec![b"h2".to_vec()];
+            let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut conns = Vec::new();
+                while let Ok((conn, _)) = listener.accept().await {
+                    if let Ok(conn) = acceptor.accept(conn).await {
+                        conns.push(conn);
+                    }
+                }
+            });
+
+            let handler = Handler::new(
+                "localhost".to_string(),
+                vec!["h2".to_string(), "http/1.1".to_string()],
+                None,
+                None,
+                0,
+                true,
+                Protocols::default(),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+            let stream: AnyStream = Box::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+            assert_eq!(negotiated_alpn(&stream), None);
+            let stream = TcpOutboundHandler::handle(&handler, &Session::default(), Some(stream))
+                .await
+                .unwrap();
+            assert_eq!(negotiated_alpn(&stream).as_deref(), Some("h2"));
+        });
+    }
+
+    #[test]
+    fn test_verify_time() {
+        // The system clock is past the validity period.
+        assert!(verify(&WebPkiVerifier::new(roots(), None)).is_err());
+
+        let verifier = VerifyTimeVerifier {
+            inner: WebPkiVerifier::new(roots(), None),
+            time: VerifyTime::parse("2020-06-01T00:00:00Z").unwrap(),
+        };
+        assert!(verify(&verifier).is_ok());
+
+        let verifier = VerifyTimeVerifier {
+            inner: WebPkiVerifier::new(roots(), None),
+            time: VerifyTime::parse("2021-06-01T00:00:00+08:00").unwrap(),
+        };
+        assert!(verify(&verifier).is_err());
+
+        assert!(verify(&InsecureVerifier).is_ok());
+
+        assert!(VerifyTime::parse("yesterday").is_err());
+        assert!(VerifyTime::parse("1960-01-01T00:00:00Z").is_err());
+    }
+}
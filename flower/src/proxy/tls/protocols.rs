@@ -0,0 +1,279 @@
+use anyhow::{anyhow, Result};
+
+#[cfg(feature = "openssl-tls")]
+use openssl::ssl::{SslContextBuilder, SslVersion};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Version {
+    Tls12,
+    Tls13,
+}
+
+impl Version {
+    // Accepts 1.2 or 1.3, an empty string means no limit.
+    fn parse(s: &str) -> Result<Option<Self>> {
+        match s {
+            "" => Ok(None),
+            "1.2" => Ok(Some(Version::Tls12)),
+            "1.3" => Ok(Some(Version::Tls13)),
+            _ => Err(anyhow!("unsupported tls version [{}]", s)),
+        }
+    }
+}
+
+struct CipherSuite {
+    // The IANA name.
+    name: &'static str,
+    openssl_name: &'static str,
+    version: Version,
+}
+
+const CIPHER_SUITES: &[CipherSuite] = &[
+    CipherSuite {
+        name: "TLS_AES_128_GCM_SHA256",
+        openssl_name: "TLS_AES_128_GCM_SHA256",
+        version: Version::Tls13,
+    },
+    CipherSuite {
+        name: "TLS_AES_256_GCM_SHA384",
+        openssl_name: "TLS_AES_256_GCM_SHA384",
+        version: Version::Tls13,
+    },
+    CipherSuite {
+        name: "TLS_CHACHA20_POLY1305_SHA256",
+        openssl_name: "TLS_CHACHA20_POLY1305_SHA256",
+        version: Version::Tls13,
+    },
+    CipherSuite {
+        name: "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        openssl_name: "ECDHE-ECDSA-AES128-GCM-SHA256",
+        version: Version::Tls12,
+    },
+    CipherSuite {
+        name: "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        openssl_name: "ECDHE-ECDSA-AES256-GCM-SHA384",
+        version: Version::Tls12,
+    },
+    CipherSuite {
+        name: "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        openssl_name: "ECDHE-ECDSA-CHACHA20-POLY1305",
+        version: Version::Tls12,
+    },
+    CipherSuite {
+        name: "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        openssl_name: "ECDHE-RSA-AES128-GCM-SHA256",
+        version: Version::Tls12,
+    },
+    CipherSuite {
+        name: "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        openssl_name: "ECDHE-RSA-AES256-GCM-SHA384",
+        version: Version::Tls12,
+    },
+    CipherSuite {
+        name: "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        openssl_name: "ECDHE-RSA-CHACHA20-POLY1305",
+        version: Version::Tls12,
+    },
+];
+
+/// The protocol versions and cipher suites enabled for TLS connections,
+/// shared by the rustls and openssl backends. Only the cipher suites both
+/// backends support can be listed.
+pub struct Protocols {
+    min_version: Version,
+    max_version: Version,
+    cipher_suites: Vec<&'static CipherSuite>,
+}
+
+impl Default for Protocols {
+    fn default() -> Self {
+        Protocols {
+            min_version: Version::Tls12,
+            max_version: Version::Tls13,
+            cipher_suites: Vec::new(),
+        }
+    }
+}
+
+impl Protocols {
+    /// Versions are either empty, 1.2 or 1.3. Cipher suites are IANA names,
+    /// all of them are enabled if empty. Versions without any of the listed
+    /// cipher suites are disabled.
+    pub fn new(min_version: &str, max_version: &str, cipher_suites: &[String]) -> Result<Self> {
+        let mut min_version = Version::parse(min_version)?.unwrap_or(Version::Tls12);
+        let mut max_version = Version::parse(max_version)?.unwrap_or(Version::Tls13);
+        if min_version > max_version {
+            return Err(anyhow!(
+                "tls min version {:?} is above max version {:?}",
+                min_version,
+                max_version
+            ));
+        }
+        let mut suites = Vec::new();
+        for name in cipher_suites {
+            let suite = CIPHER_SUITES
+                .iter()
+                .find(|s| s.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow!("unsupported cipher suite [{}]", name))?;
+            suites.push(suite);
+        }
+        if !suites.is_empty() {
+            let enabled = |v: Version| {
+                v >= min_version && v <= max_version && suites.iter().any(|s| s.version == v)
+            };
+            match (enabled(Version::Tls12), enabled(Version::Tls13)) {
+                (true, true) => (),
+                (true, false) => max_version = Version::Tls12,
+                (false, true) => min_version = Version::Tls13,
+                (false, false) => {
+                    return Err(anyhow!(
+                        "none of the cipher suites is usable with tls {:?} to {:?}",
+                        min_version,
+                        max_version
+                    ))
+                }
+            }
+        }
+        Ok(Protocols {
+            min_version,
+            max_version,
+            cipher_suites: suites,
+        })
+    }
+
+    fn versions(&self) -> impl Iterator<Item = Version> + '_ {
+        [Version::Tls12, Version::Tls13]
+            .into_iter()
+            .filter(move |v| *v >= self.min_version && *v <= self.max_version)
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    pub fn rustls_versions(&self) -> Vec<&'static rustls::SupportedProtocolVersion> {
+        self.versions()
+            .map(|v| match v {
+                Version::Tls12 => &rustls::version::TLS12,
+                Version::Tls13 => &rustls::version::TLS13,
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    pub fn rustls_cipher_suites(&self) -> Vec<rustls::SupportedCipherSuite> {
+        if self.cipher_suites.is_empty() {
+            return rustls::DEFAULT_CIPHER_SUITES.to_vec();
+        }
+        // The rustls names of TLS 1.3 suites start with TLS13_ instead of TLS_.
+        self.cipher_suites
+            .iter()
+            .filter_map(|s| {
+                let name = match s.version {
+                    Version::Tls12 => s.name.to_string(),
+                    Version::Tls13 => s.name.replacen("TLS_", "TLS13_", 1),
+                };
+                rustls::ALL_CIPHER_SUITES
+                    .iter()
+                    .find(|c| format!("{:?}", c.suite()) == name)
+                    .copied()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "openssl-tls")]
+    pub fn apply_openssl(&self, builder: &mut SslContextBuilder) -> Result<()> {
+        let version = |v| match v {
+            Version::Tls12 => SslVersion::TLS1_2,
+            Version::Tls13 => SslVersion::TLS1_3,
+        };
+        builder
+            .set_min_proto_version(Some(version(self.min_version)))
+            .map_err(|e| anyhow!("set tls min version failed: {}", e))?;
+        builder
+            .set_max_proto_version(Some(version(self.max_version)))
+            .map_err(|e| anyhow!("set tls max version failed: {}", e))?;
+        let names = |v: Version| {
+            self.cipher_suites
+                .iter()
+                .filter(|s| s.version == v)
+                .map(|s| s.openssl_name)
+                .collect::<Vec<_>>()
+                .join(":")
+        };
+        if self.versions().any(|v| v == Version::Tls12) && !self.cipher_suites.is_empty() {
+            builder
+                .set_cipher_list(&names(Version::Tls12))
+                .map_err(|e| anyhow!("set tls 1.2 cipher suites failed: {}", e))?;
+        }
+        if self.versions().any(|v| v == Version::Tls13) && !self.cipher_suites.is_empty() {
+            builder
+                .set_ciphersuites(&names(Version::Tls13))
+                .map_err(|e| anyhow!("set tls 1.3 cipher suites failed: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suites(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_protocols() {
+        let p = Protocols::new("", "", &[]).unwrap();
+        assert_eq!(
+            p.versions().collect::<Vec<_>>(),
+            vec![Version::Tls12, Version::Tls13]
+        );
+
+        let p = Protocols::new("1.3", "", &[]).unwrap();
+        assert_eq!(p.versions().collect::<Vec<_>>(), vec![Version::Tls13]);
+
+        assert!(Protocols::new("1.1", "", &[]).is_err());
+        assert!(Protocols::new("1.3", "1.2", &[]).is_err());
+        assert!(Protocols::new("", "", &suites(&["TLS_RSA_WITH_RC4_128_MD5"])).is_err());
+
+        // TLS 1.3 is disabled without any of its cipher suites.
+        let p =
+            Protocols::new("", "", &suites(&["tls_ecdhe_rsa_with_aes_128_gcm_sha256"])).unwrap();
+        assert_eq!(p.versions().collect::<Vec<_>>(), vec![Version::Tls12]);
+
+        // No usable cipher suite for the versions.
+        assert!(Protocols::new(
+            "1.3",
+            "",
+            &suites(&["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"])
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn test_rustls_cipher_suites() {
+        let p = Protocols::new(
+            "",
+            "",
+            &suites(&[
+                "TLS_AES_128_GCM_SHA256",
+                "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            p.rustls_cipher_suites(),
+            vec![
+                rustls::cipher_suite::TLS13_AES_128_GCM_SHA256,
+                rustls::cipher_suite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+            ]
+        );
+        // Every listed suite has a rustls counterpart.
+        let names = CIPHER_SUITES
+            .iter()
+            .map(|s| s.name.to_string())
+            .collect::<Vec<_>>();
+        let p = Protocols::new("", "", &names).unwrap();
+        assert_eq!(p.rustls_cipher_suites().len(), CIPHER_SUITES.len());
+    }
+}
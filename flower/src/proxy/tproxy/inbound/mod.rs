@@ -0,0 +1,7 @@
+mod tcp;
+#[cfg(target_os = "linux")]
+mod udp;
+
+pub use tcp::{original_dst, Handler as TcpHandler};
+#[cfg(target_os = "linux")]
+pub use udp::Handler as UdpHandler;
@@ -0,0 +1,84 @@
+use std::io;
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+#[cfg(target_os = "linux")]
+use socket2::SockRef;
+use tokio::net::TcpStream;
+
+use crate::{
+    common::net,
+    proxy::*,
+    session::{Session, SocksAddr},
+};
+
+/// Returns the destination the client connected to, as looked up from
+/// netfilter or pf for redirected connections, or the local address for
+/// those redirected by `TPROXY`.
+#[cfg(target_os = "linux")]
+pub async fn original_dst(stream: &TcpStream) -> io::Result<SocketAddr> {
+    net::original_dst(&SockRef::from(stream)).or_else(|_| stream.local_addr())
+}
+
+/// Returns the destination the client connected to, as looked up from pf
+/// for redirected connections, or the local address.
+#[cfg(target_os = "macos")]
+pub async fn original_dst(stream: &TcpStream) -> io::Result<SocketAddr> {
+    let peer_addr = stream.peer_addr()?;
+    let local_addr = stream.local_addr()?;
+    match tokio::task::spawn_blocking(move || net::pf_original_dst(peer_addr, local_addr)).await {
+        Ok(Ok(addr)) => Ok(addr),
+        _ => Ok(local_addr),
+    }
+}
+
+/// Proxies connections to their original destinations, which the listener
+/// resolves into the local address of the session.
+pub struct Handler;
+
+#[async_trait]
+impl TcpInboundHandler for Handler {
+    type TStream = AnyStream;
+    type TDatagram = AnyInboundDatagram;
+
+    async fn handle<'a>(
+        &'a self,
+        mut sess: Session,
+        stream: Self::TStream,
+    ) -> io::Result<InboundTransport<Self::TStream, Self::TDatagram>> {
+        sess.destination = SocksAddr::from(sess.local_addr);
+        Ok(InboundTransport::Stream(stream, sess))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_original_dst() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let _client = TcpStream::connect(addr).await.unwrap();
+            let (stream, _) = listener.accept().await.unwrap();
+            // Not redirected, so the original destination is the local address.
+            assert_eq!(original_dst(&stream).await.unwrap(), addr);
+
+            let sess = Session {
+                local_addr: addr,
+                ..Default::default()
+            };
+            match Handler.handle(sess, Box::new(stream)).await.unwrap() {
+                InboundTransport::Stream(_, sess) => {
+                    assert_eq!(sess.destination, SocksAddr::from(addr))
+                }
+                _ => panic!("unexpected transport"),
+            }
+        });
+    }
+}
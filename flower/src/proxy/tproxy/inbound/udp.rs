@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use log::*;
+use socket2::{Domain, SockAddr, SockRef, Socket, Type};
+use tokio::io::Interest;
+use tokio::net::UdpSocket;
+
+use crate::{
+    common::net,
+    proxy::*,
+    session::{DatagramSource, SocksAddr},
+};
+
+/// How long a socket bound to an original destination is kept for replies
+/// after its last use.
+const SOCKET_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Proxies datagrams to their original destinations, which the socket
+/// receives along with each datagram.
+pub struct Handler;
+
+#[async_trait]
+impl UdpInboundHandler for Handler {
+    type UStream = AnyStream;
+    type UDatagram = AnyInboundDatagram;
+
+    async fn handle<'a>(
+        &'a self,
+        socket: Self::UDatagram,
+    ) -> io::Result<InboundTransport<Self::UStream, Self::UDatagram>> {
+        let socket = socket.into_std()?;
+        net::set_recv_orig_dst_addr(&SockRef::from(&socket))?;
+        let socket = UdpSocket::from_std(socket)?;
+        Ok(InboundTransport::Datagram(Box::new(Datagram(socket))))
+    }
+}
+
+// Receives a datagram along with the original destination from the
+// control messages.
+fn recv_msg(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Option<SocketAddr>)> {
+    let mut control = [0u64; 8];
+    let mut orig_dst = None;
+    let (n, src_addr) = unsafe {
+        SockAddr::init(|storage, len| {
+            let mut iov = libc::iovec {
+                iov_base: buf.as_mut_ptr().cast(),
+                iov_len: buf.len(),
+            };
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_name = storage.cast();
+            msg.msg_namelen = *len;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr().cast();
+            msg.msg_controllen = std::mem::size_of_val(&control) as _;
+            let n = libc::recvmsg(socket.as_raw_fd(), &mut msg, 0);
+            if n == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            *len = msg.msg_namelen;
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                let hdr = &*cmsg;
+                if (hdr.cmsg_level == libc::SOL_IP && hdr.cmsg_type == libc::IP_ORIGDSTADDR)
+                    || (hdr.cmsg_level == libc::SOL_IPV6 && hdr.cmsg_type == libc::IPV6_ORIGDSTADDR)
+                {
+                    let data_len = hdr.cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                    let (_, addr) = SockAddr::init(|storage, len| {
+                        let data_len = data_len.min(*len as usize);
+                        std::ptr::copy_nonoverlapping(
+                            libc::CMSG_DATA(cmsg),
+                            storage.cast::<u8>(),
+                            data_len,
+                        );
+                        *len = data_len as libc::socklen_t;
+                        Ok(())
+                    })?;
+                    orig_dst = addr.as_socket();
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+            Ok(n as usize)
+        })?
+    };
+    let src_addr = src_addr
+        .as_socket()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "unexpected source address"))?;
+    Ok((n, src_addr, orig_dst))
+}
+
+pub struct Datagram(UdpSocket);
+
+impl InboundDatagram for Datagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn InboundDatagramRecvHalf>,
+        Box<dyn InboundDatagramSendHalf>,
+    ) {
+        let socket = Arc::new(self.0);
+        (
+            Box::new(DatagramRecvHalf(socket)),
+            Box::new(DatagramSendHalf::new()),
+        )
+    }
+
+    fn into_std(self: Box<Self>) -> io::Result<std::net::UdpSocket> {
+        self.0.into_std()
+    }
+}
+
+pub struct DatagramRecvHalf(Arc<UdpSocket>);
+
+#[async_trait]
+impl InboundDatagramRecvHalf for DatagramRecvHalf {
+    async fn recv_from(
+        &mut self,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, DatagramSource, Option<SocksAddr>)> {
+        let socket = &self.0;
+        let listen_addr = socket.local_addr()?;
+        loop {
+            let (n, src_addr, orig_dst) = socket
+                .async_io(Interest::READABLE, || recv_msg(socket, buf))
+                .await?;
+            // Proxying datagrams sent to the listener itself would loop.
+            if let Some(dst) = orig_dst {
+                if net::is_listener_addr(&dst, &listen_addr) {
+                    debug!("dropping datagram from {} to the listener", src_addr);
+                    continue;
+                }
+            }
+            return Ok((
+                n,
+                DatagramSource::new(src_addr, None),
+                orig_dst.map(SocksAddr::from),
+            ));
+        }
+    }
+}
+
+// Replies are sent from the original destination, on sockets bound to it
+// transparently, which are kept until they have been idle for
+// `SOCKET_IDLE_TIMEOUT`.
+pub struct DatagramSendHalf {
+    sockets: HashMap<SocketAddr, (UdpSocket, Instant)>,
+    last_sweep: Instant,
+}
+
+impl DatagramSendHalf {
+    fn new() -> Self {
+        DatagramSendHalf {
+            sockets: HashMap::new(),
+            last_sweep: Instant::now(),
+        }
+    }
+
+    fn bind(src_addr: &SocketAddr) -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::for_address(*src_addr), Type::DGRAM, None)?;
+        socket.set_reuse_address(true)?;
+        net::set_ip_transparent(&socket)?;
+        socket.bind(&(*src_addr).into())?;
+        socket.set_nonblocking(true)?;
+        UdpSocket::from_std(socket.into())
+    }
+
+    fn sweep(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.last_sweep) < SOCKET_IDLE_TIMEOUT {
+            return;
+        }
+        self.last_sweep = now;
+        self.sockets
+            .retain(|_, (_, last_used)| now.duration_since(*last_used) < SOCKET_IDLE_TIMEOUT);
+    }
+}
+
+#[async_trait]
+impl InboundDatagramSendHalf for DatagramSendHalf {
+    async fn send_to(
+        &mut self,
+        buf: &[u8],
+        src_addr: Option<&SocksAddr>,
+        dst_addr: &SocketAddr,
+    ) -> io::Result<usize> {
+        let src_addr = match src_addr {
+            Some(SocksAddr::Ip(a)) => *a,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "sending message without source",
+                ))
+            }
+        };
+        self.sweep();
+        if !self.sockets.contains_key(&src_addr) {
+            let socket = Self::bind(&src_addr)?;
+            self.sockets.insert(src_addr, (socket, Instant::now()));
+        }
+        let (socket, last_used) = self.sockets.get_mut(&src_addr).unwrap();
+        *last_used = Instant::now();
+        let res = socket.send_to(buf, dst_addr).await;
+        if res.is_err() {
+            self.sockets.remove(&src_addr);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::datagram::SimpleInboundDatagram;
+
+    use super::*;
+
+    #[test]
+    fn test_recv_orig_dst() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = socket.local_addr().unwrap();
+            net::set_recv_orig_dst_addr(&SockRef::from(&socket)).unwrap();
+
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.send_to(b"abc", addr).await.unwrap();
+            let mut buf = [0u8; 16];
+            let (n, src, dst) = socket
+                .async_io(Interest::READABLE, || recv_msg(&socket, &mut buf))
+                .await
+                .unwrap();
+            assert_eq!(&buf[..n], b"abc");
+            assert_eq!(src, client.local_addr().unwrap());
+            // Not redirected, so the original destination is the local address.
+            assert_eq!(dst, Some(addr));
+
+            // Which is the listener itself, such datagrams are dropped.
+            let datagram = match Handler
+                .handle(Box::new(SimpleInboundDatagram(socket)))
+                .await
+                .unwrap()
+            {
+                InboundTransport::Datagram(v) => v,
+                _ => panic!("unexpected transport"),
+            };
+            let (mut recv, _) = datagram.split();
+            client.send_to(b"abc", addr).await.unwrap();
+            let res =
+                tokio::time::timeout(Duration::from_millis(100), recv.recv_from(&mut buf)).await;
+            assert!(res.is_err());
+        });
+    }
+
+    #[test]
+    fn test_send_half_reuses_sockets() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let client_addr = client.local_addr().unwrap();
+            let src_addr = UdpSocket::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap();
+            let mut send = DatagramSendHalf::new();
+            let mut buf = [0u8; 16];
+            for _ in 0..2 {
+                send.send_to(b"abc", Some(&SocksAddr::from(src_addr)), &client_addr)
+                    .await
+                    .unwrap();
+                let (n, from) = client.recv_from(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"abc");
+                assert_eq!(from, src_addr);
+            }
+            assert_eq!(send.sockets.len(), 1);
+
+            // Idle sockets are closed.
+            let past = Instant::now() - SOCKET_IDLE_TIMEOUT * 2;
+            send.sockets.get_mut(&src_addr).unwrap().1 = past;
+            send.last_sweep = past;
+            send.sweep();
+            assert!(send.sockets.is_empty());
+        });
+    }
+}
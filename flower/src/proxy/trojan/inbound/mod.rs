@@ -1,32 +1,82 @@
 mod tcp;
 
-use std::io;
-pub use tcp::Handler as TcpHandler;
+use crate::common::buffer::RELAY_BUFFER_POOL;
 use crate::proxy::ProxyStream;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, split};
+use crate::session::Traffic;
 use log::*;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::Poll;
+pub use tcp::Handler as TcpHandler;
+use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+// Reads what's immediately available, returns None if the read would block.
+async fn try_read<R: AsyncRead + Unpin>(r: &mut R, buf: &mut [u8]) -> Option<io::Result<usize>> {
+    futures::future::poll_fn(|cx| {
+        let mut buf = ReadBuf::new(&mut *buf);
+        match Pin::new(&mut *r).poll_read(cx, &mut buf) {
+            Poll::Ready(res) => Poll::Ready(Some(res.map(|_| buf.filled().len()))),
+            Poll::Pending => Poll::Ready(None),
+        }
+    })
+    .await
+}
 
-async fn copy_tcp<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+// Copies until EOF, written data is flushed only when the read would block.
+// Bytes written are reported to `count` as they're written, so that the
+// bytes copied by a cancelled copy are accounted.
+async fn copy_tcp<R: AsyncRead + Unpin, W: AsyncWrite + Unpin, F: Fn(u64)>(
     r: &mut R,
     w: &mut W,
+    count: F,
 ) -> io::Result<()> {
-    let mut buf = [0u8; 0x4000];
+    let mut buf = RELAY_BUFFER_POOL.get();
+    let mut unflushed = false;
     loop {
-        let len = r.read(&mut buf).await?;
+        let len = match try_read(r, &mut buf).await {
+            Some(res) => res?,
+            None => {
+                if unflushed {
+                    w.flush().await?;
+                    unflushed = false;
+                }
+                r.read(&mut buf).await?
+            }
+        };
         if len == 0 {
             break;
         }
-        w.write(&buf[..len]).await?;
+        w.write_all(&buf[..len]).await?;
+        count(len as u64);
+        unflushed = true;
+    }
+    if unflushed {
         w.flush().await?;
     }
     Ok(())
 }
 
-pub async fn relay_tcp<T: ProxyStream, U: ProxyStream>(a: T, b: U) {
+/// Relays between the inbound stream `a` and the outbound stream `b` until
+/// either direction ends, returns the uplink and downlink bytes of this relay,
+/// which are also added to `traffic`.
+pub async fn relay_tcp<T: ProxyStream, U: ProxyStream>(
+    a: T,
+    b: U,
+    traffic: &Traffic,
+) -> (u64, u64) {
+    let uplink = AtomicU64::new(0);
+    let downlink = AtomicU64::new(0);
     let (mut a_rx, mut a_tx) = split(a);
     let (mut b_rx, mut b_tx) = split(b);
-    let t1 = copy_tcp(&mut a_rx, &mut b_tx);
-    let t2 = copy_tcp(&mut b_rx, &mut a_tx);
+    let t1 = copy_tcp(&mut a_rx, &mut b_tx, |n| {
+        uplink.fetch_add(n, Ordering::Relaxed);
+        traffic.add_uplink(n);
+    });
+    let t2 = copy_tcp(&mut b_rx, &mut a_tx, |n| {
+        downlink.fetch_add(n, Ordering::Relaxed);
+        traffic.add_downlink(n);
+    });
     let e = tokio::select! {
         e = t1 => {e}
         e = t2 => {e}
@@ -38,5 +88,78 @@ pub async fn relay_tcp<T: ProxyStream, U: ProxyStream>(a: T, b: U) {
     let mut b = b_rx.unsplit(b_tx);
     let _ = a.shutdown().await;
     let _ = b.shutdown().await;
-    info!("tcp session ends");
+    let (uplink, downlink) = (uplink.into_inner(), downlink.into_inner());
+    info!(
+        "tcp session ends, uplink {} bytes, downlink {} bytes",
+        uplink, downlink
+    );
+    (uplink, downlink)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{duplex, BufWriter};
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[test]
+    fn test_copy_tcp() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut src, mut r) = duplex(1024);
+            let (w, mut dst) = duplex(1024);
+            // Nothing reaches the peer until flushed.
+            let mut w = BufWriter::new(w);
+            let copy = tokio::spawn(async move { copy_tcp(&mut r, &mut w, |_| ()).await });
+
+            // Written data is flushed once the source has nothing to read.
+            let mut buf = [0u8; 3];
+            src.write_all(b"abc").await.unwrap();
+            timeout(Duration::from_secs(1), dst.read_exact(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf, b"abc");
+
+            src.write_all(b"def").await.unwrap();
+            drop(src);
+            copy.await.unwrap().unwrap();
+            dst.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"def");
+        });
+    }
+    #[test]
+    fn test_relay_tcp_traffic() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut client, a) = duplex(1024);
+            let (b, mut server) = duplex(1024);
+            let traffic = std::sync::Arc::new(Traffic::default());
+            traffic.add_uplink(1);
+            let traffic2 = traffic.clone();
+            let relay = tokio::spawn(async move { relay_tcp(a, b, &traffic2).await });
+
+            let mut buf = [0u8; 5];
+            client.write_all(b"hello").await.unwrap();
+            server.read_exact(&mut buf).await.unwrap();
+            server.write_all(b"hi").await.unwrap();
+            client.read_exact(&mut buf[..2]).await.unwrap();
+            assert_eq!(traffic.uplink(), 6);
+            assert_eq!(traffic.downlink(), 2);
+
+            drop(client);
+            assert_eq!(relay.await.unwrap(), (5, 2));
+            assert_eq!(traffic.uplink(), 6);
+            assert_eq!(traffic.downlink(), 2);
+        });
+    }
 }
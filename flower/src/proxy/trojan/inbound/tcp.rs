@@ -138,11 +138,19 @@ impl TcpInboundHandler for Handler {
         buf.resize(56, 0);
         stream.read_exact(&mut buf).await?;
         if self.key[..] != buf[..] {
+            let traffic = sess.traffic.clone();
             tokio::spawn(async move {
                 let inbound = stream;
-                let mut outbound = TcpStream::connect("127.0.0.1:80").await.unwrap();
-                let _ = outbound.write(&buf).await;
-                relay_tcp(inbound, outbound).await;
+                let mut outbound = match TcpStream::connect("127.0.0.1:80").await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        debug!("connect fallback failed: {}", e);
+                        return;
+                    }
+                };
+                if outbound.write_all(&buf).await.is_ok() {
+                    relay_tcp(inbound, outbound, &traffic).await;
+                }
             });
             return Ok(InboundTransport::Empty);
         }
@@ -185,3 +193,66 @@ impl TcpInboundHandler for Handler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sends a UDP request through a trojan stream, along with a packet to
+    // 1.2.3.4:53, returns the datagram of the session and the client side of
+    // the stream.
+    async fn udp_session(
+        handler: &Handler,
+        stream_id: u64,
+    ) -> (AnyInboundDatagram, tokio::io::DuplexStream) {
+        let sess = Session {
+            source: "127.0.0.1:50000".parse().unwrap(),
+            stream_id: Some(stream_id),
+            ..Default::default()
+        };
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut req = b"d63dc919e201d7bc4c825630d2cf25fdc93d4b2f0d46706d29038d01\r\n".to_vec();
+        req.extend_from_slice(&[0x03, 0x01, 1, 2, 3, 4, 0, 53, b'\r', b'\n']);
+        req.extend_from_slice(&[0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'\r', b'\n']);
+        req.extend_from_slice(b"abc");
+        client.write_all(&req).await.unwrap();
+        match handler.handle(sess, Box::new(server)).await.unwrap() {
+            InboundTransport::Datagram(d) => (d, client),
+            _ => panic!("unexpected transport"),
+        }
+    }
+
+    #[test]
+    fn test_udp() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let handler = Handler::new("password");
+            let dst = SocksAddr::from(("1.2.3.4".parse::<std::net::IpAddr>().unwrap(), 53));
+            let mut sources = Vec::new();
+            // Sessions multiplexed on a same connection are told apart by
+            // the stream IDs.
+            for stream_id in 1..=2 {
+                let (datagram, mut client) = udp_session(&handler, stream_id).await;
+                let (mut recv_half, mut send_half) = datagram.split();
+                let mut buf = [0u8; 16];
+                let (n, src, dst_addr) = recv_half.recv_from(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"abc");
+                assert_eq!(dst_addr, Some(dst.clone()));
+                sources.push(src);
+
+                let n = send_half
+                    .send_to(b"xyz", Some(&dst), &src.address)
+                    .await
+                    .unwrap();
+                assert_eq!(n, 3);
+                let mut resp = [0u8; 14];
+                client.read_exact(&mut resp).await.unwrap();
+                assert_eq!(&resp[..11], &[0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'\r', b'\n']);
+                assert_eq!(&resp[11..], b"xyz");
+            }
+            assert_ne!(sources[0], sources[1]);
+        });
+    }
+}
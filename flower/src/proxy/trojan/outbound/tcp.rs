@@ -45,3 +45,40 @@ impl TcpOutboundHandler for Handler {
         Ok(Box::new(stream))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use crate::session::SocksAddr;
+
+    use super::*;
+
+    #[test]
+    fn test_request_header() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let handler = Handler {
+                address: "127.0.0.1".to_string(),
+                port: 443,
+                password: "password".to_string(),
+            };
+            let sess = Session {
+                destination: SocksAddr::Domain("example.com".to_string(), 443),
+                ..Default::default()
+            };
+            let (client, mut server) = tokio::io::duplex(1024);
+            let _stream = handler.handle(&sess, Some(Box::new(client))).await.unwrap();
+            let mut expected =
+                b"d63dc919e201d7bc4c825630d2cf25fdc93d4b2f0d46706d29038d01\r\n".to_vec();
+            expected.extend_from_slice(&[0x01, 0x03, 11]);
+            expected.extend_from_slice(b"example.com");
+            expected.extend_from_slice(&[0x01, 0xbb, b'\r', b'\n']);
+            let mut buf = vec![0u8; expected.len()];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, expected);
+        });
+    }
+}
@@ -162,3 +162,56 @@ where
         self.0.write_all(&data).map_ok(|_| payload_size).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datagram() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let handler = Handler {
+                address: "127.0.0.1".to_string(),
+                port: 443,
+                password: "password".to_string(),
+            };
+            let target = SocksAddr::from(("1.2.3.4".parse::<std::net::IpAddr>().unwrap(), 53));
+            let sess = Session {
+                destination: target.clone(),
+                ..Default::default()
+            };
+            let (client, mut server) = tokio::io::duplex(1024);
+            let transport = OutboundTransport::Stream(Box::new(client) as AnyStream);
+            let datagram = handler.handle(&sess, Some(transport)).await.unwrap();
+            let (mut recv_half, mut send_half) = datagram.split();
+
+            // The request header goes along with the first packet only.
+            assert_eq!(send_half.send_to(b"abc", &target).await.unwrap(), 3);
+            assert_eq!(send_half.send_to(b"de", &target).await.unwrap(), 2);
+            let mut expected =
+                b"d63dc919e201d7bc4c825630d2cf25fdc93d4b2f0d46706d29038d01\r\n".to_vec();
+            expected.extend_from_slice(&[0x03, 0x01, 1, 2, 3, 4, 0, 53, b'\r', b'\n']);
+            expected.extend_from_slice(&[0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'\r', b'\n']);
+            expected.extend_from_slice(b"abc");
+            expected.extend_from_slice(&[0x01, 1, 2, 3, 4, 0, 53, 0, 2, b'\r', b'\n']);
+            expected.extend_from_slice(b"de");
+            let mut buf = vec![0u8; expected.len()];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, expected);
+
+            server
+                .write_all(&[
+                    0x01, 1, 2, 3, 4, 0, 53, 0, 3, b'\r', b'\n', b'x', b'y', b'z',
+                ])
+                .await
+                .unwrap();
+            let mut buf = [0u8; 16];
+            let (n, addr) = recv_half.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"xyz");
+            assert_eq!(addr, target);
+        });
+    }
+}
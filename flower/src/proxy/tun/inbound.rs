@@ -27,9 +27,15 @@ pub fn new(
 ) -> Result<Runner> {
     let settings = TunInboundSettings::parse_from_bytes(&inbound.settings)?;
 
+    let fd = if settings.fd >= 0 {
+        settings.fd
+    } else {
+        option::TUN_FD.unwrap_or(-1)
+    };
+
     let mut cfg = tun::Configuration::default();
-    if settings.fd >= 0 {
-        cfg.raw_fd(settings.fd);
+    if fd >= 0 {
+        cfg.raw_fd(fd);
     } else if settings.auto {
         cfg.name(&*option::DEFAULT_TUN_NAME)
             .address(&*option::DEFAULT_TUN_IPV4_ADDR)
@@ -83,15 +89,22 @@ pub fn new(
     let tun = tun::create_as_async(&cfg).map_err(|e| anyhow!("create tun failed: {}", e))?;
 
     if settings.auto {
-        assert!(settings.fd == -1, "tun-auto is not compatible with tun-fd");
+        assert!(fd == -1, "tun-auto is not compatible with tun-fd");
     }
 
     Ok(Box::pin(async move {
-        let fakedns = Arc::new(TokioMutex::new(FakeDns::new(fake_dns_mode)));
-
-        for filter in fake_dns_filters.into_iter() {
-            fakedns.lock().await.add_filter(filter);
-        }
+        // The fake DNS of the DNS client takes precedence, fake IPs it
+        // allocates are mapped back for all inbounds.
+        let shared_fakedns = dispatcher.dns_client().read().await.fake_dns();
+        let fakedns = if let Some(fakedns) = shared_fakedns {
+            fakedns
+        } else {
+            let fakedns = Arc::new(TokioMutex::new(FakeDns::new(fake_dns_mode)));
+            for filter in fake_dns_filters.into_iter() {
+                fakedns.lock().await.add_filter(filter);
+            }
+            fakedns
+        };
 
         let stack = NetStack::new(inbound.tag.clone(), dispatcher, nat_manager, fakedns);
 
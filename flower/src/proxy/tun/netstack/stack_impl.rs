@@ -274,6 +274,7 @@ impl NetStackImpl {
                         source: dgram_src.address,
                         destination: socks_dst_addr.clone(),
                         inbound_tag: inbound_tag.clone(),
+                        protocol: crate::common::sniff::sniff_datagram_protocol(&pkt.data),
                         ..Default::default()
                     };
 
@@ -283,13 +284,7 @@ impl NetStackImpl {
 
                     // Note that subsequent packets on this session may have different
                     // destination addresses.
-                    debug!(
-                        "added udp session {} -> {}:{} ({})",
-                        &dgram_src,
-                        &dst_addr.ip(),
-                        &dst_addr.port(),
-                        nat_manager.size().await,
-                    );
+                    debug!("added udp session {} ({})", &sess, nat_manager.size().await);
                 }
 
                 let pkt = UdpPacket {
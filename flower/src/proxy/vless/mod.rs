@@ -0,0 +1,2 @@
+#[cfg(feature = "outbound-vless")]
+pub mod outbound;
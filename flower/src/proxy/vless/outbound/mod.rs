@@ -0,0 +1,5 @@
+mod stream;
+
+pub mod tcp;
+
+pub use tcp::Handler as TcpHandler;
@@ -0,0 +1,92 @@
+use std::{io, pin::Pin};
+
+use futures::{
+    ready,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::tcp::VERSION;
+
+/// A VLESS client stream, the response header is verified and stripped on
+/// the first read, payload is relayed as is in both directions.
+pub struct VlessStream<T> {
+    inner: T,
+    // Response header read so far, the version and the addons length
+    // followed by the addons.
+    header: Vec<u8>,
+    header_done: bool,
+}
+
+impl<T> VlessStream<T> {
+    pub fn new(inner: T) -> Self {
+        VlessStream {
+            inner,
+            header: Vec::with_capacity(2),
+            header_done: false,
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> VlessStream<T> {
+    fn poll_read_header(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let needed = if self.header.len() < 2 {
+                2
+            } else {
+                2 + self.header[1] as usize
+            };
+            if self.header.len() == needed {
+                self.header_done = true;
+                return Poll::Ready(Ok(()));
+            }
+            let mut tmp = [0u8; 257];
+            let mut buf = ReadBuf::new(&mut tmp[..needed - self.header.len()]);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+            if buf.filled().is_empty() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "incomplete vless response header",
+                )));
+            }
+            self.header.extend_from_slice(buf.filled());
+            if self.header[0] != VERSION {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected vless response version {}", self.header[0]),
+                )));
+            }
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for VlessStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if !self.header_done {
+            ready!(self.poll_read_header(cx))?;
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for VlessStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
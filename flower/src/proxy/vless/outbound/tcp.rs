@@ -0,0 +1,142 @@
+use std::io;
+
+use async_trait::async_trait;
+use bytes::{BufMut, BytesMut};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::{
+    proxy::*,
+    session::{Session, SocksAddr, SocksAddrWireType},
+};
+
+use super::stream::VlessStream;
+
+pub(super) const VERSION: u8 = 0x00;
+
+const COMMAND_TCP: u8 = 0x01;
+
+/// Handler of the VLESS protocol. The protocol doesn't encrypt the traffic,
+/// so the handler is meant to be chained after a tls or quic outbound.
+pub struct Handler {
+    pub address: String,
+    pub port: u16,
+    pub uuid: String,
+    /// Flow control, sent to the server as a request addon if not empty.
+    pub flow: String,
+}
+
+impl Handler {
+    fn encode_request(&self, uuid: &Uuid, destination: &SocksAddr) -> io::Result<BytesMut> {
+        let mut buf = BytesMut::new();
+        buf.put_u8(VERSION);
+        buf.put_slice(uuid.as_bytes());
+        // Addons are a protobuf message, the flow is its only field.
+        if self.flow.is_empty() {
+            buf.put_u8(0);
+        } else {
+            if self.flow.len() > 127 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "flow too long"));
+            }
+            buf.put_u8(2 + self.flow.len() as u8);
+            buf.put_u8(0x0a); // field 1, length-delimited
+            buf.put_u8(self.flow.len() as u8);
+            buf.put_slice(self.flow.as_bytes());
+        }
+        buf.put_u8(COMMAND_TCP);
+        destination.write_buf(&mut buf, SocksAddrWireType::PortFirst)?;
+        Ok(buf)
+    }
+}
+
+#[async_trait]
+impl TcpOutboundHandler for Handler {
+    type Stream = AnyStream;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        Some(OutboundConnect::Proxy(self.address.clone(), self.port))
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        stream: Option<Self::Stream>,
+    ) -> io::Result<Self::Stream> {
+        let mut stream =
+            stream.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "invalid input"))?;
+        let uuid = Uuid::parse_str(&self.uuid).map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("parse uuid failed: {}", e))
+        })?;
+        let buf = self.encode_request(&uuid, &sess.destination)?;
+        stream.write_all(&buf).await?;
+        Ok(Box::new(VlessStream::new(stream)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    const UUID: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+
+    #[test]
+    fn test_encode_request() {
+        let uuid = Uuid::parse_str(UUID).unwrap();
+        let mut handler = Handler {
+            address: "127.0.0.1".to_string(),
+            port: 443,
+            uuid: UUID.to_string(),
+            flow: "".to_string(),
+        };
+        let dst = SocksAddr::Domain("example.com".to_string(), 80);
+        let buf = handler.encode_request(&uuid, &dst).unwrap();
+        let mut expected = vec![VERSION];
+        expected.extend_from_slice(uuid.as_bytes());
+        expected.extend_from_slice(&[0, COMMAND_TCP, 0, 80, 2, 11]);
+        expected.extend_from_slice(b"example.com");
+        assert_eq!(&buf[..], &expected[..]);
+
+        handler.flow = "xtls-rprx-vision".to_string();
+        let dst = SocksAddr::Ip("1.2.3.4:443".parse().unwrap());
+        let buf = handler.encode_request(&uuid, &dst).unwrap();
+        assert_eq!(buf[17], 18);
+        assert_eq!(&buf[18..20], &[0x0a, 16]);
+        assert_eq!(&buf[20..36], b"xtls-rprx-vision");
+        assert_eq!(&buf[36..], &[COMMAND_TCP, 1, 187, 1, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_response_header() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            // The response header and its addons are stripped.
+            let (client, mut server) = tokio::io::duplex(64);
+            let mut stream = VlessStream::new(client);
+            server.write_all(&[VERSION, 2, 0xaa, 0xbb]).await.unwrap();
+            server.write_all(b"abc").await.unwrap();
+            let mut buf = [0u8; 3];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"abc");
+            stream.write_all(b"def").await.unwrap();
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"def");
+
+            let (client, mut server) = tokio::io::duplex(64);
+            let mut stream = VlessStream::new(client);
+            server.write_all(&[0x01, 0]).await.unwrap();
+            let err = stream.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            let (client, mut server) = tokio::io::duplex(64);
+            let mut stream = VlessStream::new(client);
+            server.write_all(&[VERSION]).await.unwrap();
+            drop(server);
+            let err = stream.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        });
+    }
+}
@@ -7,47 +7,95 @@ use log::*;
 use md5::{Digest, Md5};
 use sha3::Shake128;
 
-use crate::common::crypto::{
-    aead::{AeadCipher, AeadDecryptor, AeadEncryptor},
-    Cipher, NonceSequence, SizedCipher,
+use crate::common::crypto::{AeadDecryptor, AeadEncryptor, Cipher, NonceSequence};
+
+use super::protocol::{
+    SECURITY_TYPE_AES128_GCM, SECURITY_TYPE_CHACHA20_POLY1305, SECURITY_TYPE_NONE,
 };
 
+/// How the request and response bodies are protected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BodySecurity {
+    /// Chunks are sealed with an AEAD cipher.
+    Aead(Cipher),
+    /// Chunks are sent in plain, still length masked and padded.
+    None,
+}
+
+impl BodySecurity {
+    /// The security byte of the request header.
+    pub fn header_security(&self) -> u8 {
+        match self {
+            BodySecurity::Aead(Cipher::Aes128Gcm) => SECURITY_TYPE_AES128_GCM,
+            BodySecurity::Aead(_) => SECURITY_TYPE_CHACHA20_POLY1305,
+            BodySecurity::None => SECURITY_TYPE_NONE,
+        }
+    }
+
+    pub fn tag_len(&self) -> usize {
+        match self {
+            BodySecurity::Aead(cipher) => cipher.tag_len(),
+            BodySecurity::None => 0,
+        }
+    }
+}
+
+/// Parses the security of a VMess outbound, AES-128-GCM, ChaCha20-Poly1305
+/// and none are supported.
+pub fn parse_security(security: &str) -> Result<BodySecurity> {
+    if security == "none" {
+        return Ok(BodySecurity::None);
+    }
+    match security.parse()? {
+        cipher @ (Cipher::Aes128Gcm | Cipher::ChaCha20Poly1305) => Ok(BodySecurity::Aead(cipher)),
+        _ => Err(anyhow!("unsupported cipher: {}", security)),
+    }
+}
+
 pub fn generate_chacha20poly1305_key(key: &[u8]) -> Vec<u8> {
     let key_1 = Md5::digest(key).to_vec();
     let key_2 = Md5::digest(&key_1).to_vec();
     [key_1, key_2].concat()
 }
 
+fn body_key(cipher: Cipher, key: &[u8]) -> Result<Vec<u8>> {
+    match cipher {
+        Cipher::ChaCha20Poly1305 => Ok(generate_chacha20poly1305_key(key)),
+        Cipher::Aes128Gcm => Ok(key.to_vec()),
+        _ => Err(anyhow!("unsupported cipher: {}", cipher)),
+    }
+}
+
+/// Returns the body encryptor, none if the bodies are not encrypted.
 pub fn new_encryptor(
-    cipher: &str,
+    security: BodySecurity,
     key: &[u8],
     iv: &[u8],
-) -> Result<AeadEncryptor<VMessAEADSequence>> {
-    let aead_cipher = AeadCipher::new(cipher)?;
-    let key = match cipher.to_lowercase().as_str() {
-        "chacha20-poly1305" | "chacha20-ietf-poly1305" => generate_chacha20poly1305_key(key),
-        "aes-128-gcm" => key.to_vec(),
-        _ => return Err(anyhow!("unsupported cipher: {}", cipher)),
+) -> Result<Option<AeadEncryptor<VMessAEADSequence>>> {
+    let cipher = match security {
+        BodySecurity::Aead(cipher) => cipher,
+        BodySecurity::None => return Ok(None),
     };
-    let nonce = VMessAEADSequence::new(iv.to_vec(), aead_cipher.nonce_len());
-    let enc = aead_cipher.encryptor(&key, nonce)?;
-    Ok(enc)
+    let key = body_key(cipher, key)?;
+    let nonce = VMessAEADSequence::new(iv.to_vec(), cipher.nonce_len());
+    let enc = cipher.encryptor(&key, nonce)?;
+    Ok(Some(enc))
 }
 
+/// Returns the body decryptor, none if the bodies are not encrypted.
 pub fn new_decryptor(
-    cipher: &str,
+    security: BodySecurity,
     key: &[u8],
     iv: &[u8],
-) -> Result<AeadDecryptor<VMessAEADSequence>> {
-    let aead_cipher = AeadCipher::new(cipher)?;
-    let key = match cipher.to_lowercase().as_str() {
-        "chacha20-poly1305" | "chacha20-ietf-poly1305" => generate_chacha20poly1305_key(key),
-        "aes-128-gcm" => key.to_vec(),
-        _ => return Err(anyhow!("unsupported cipher: {}", cipher)),
+) -> Result<Option<AeadDecryptor<VMessAEADSequence>>> {
+    let cipher = match security {
+        BodySecurity::Aead(cipher) => cipher,
+        BodySecurity::None => return Ok(None),
     };
-    let nonce = VMessAEADSequence::new(iv.to_vec(), aead_cipher.nonce_len());
-    let dec = aead_cipher.decryptor(&key, nonce)?;
-    Ok(dec)
+    let key = body_key(cipher, key)?;
+    let nonce = VMessAEADSequence::new(iv.to_vec(), cipher.nonce_len());
+    let dec = cipher.decryptor(&key, nonce)?;
+    Ok(Some(dec))
 }
 
 pub struct VMessAEADSequence {
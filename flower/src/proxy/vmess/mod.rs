@@ -5,5 +5,6 @@ mod stream;
 pub mod tcp;
 pub mod udp;
 
+pub use crypto::{parse_security, BodySecurity};
 pub use tcp::Handler as TcpHandler;
 pub use udp::Handler as UdpHandler;
@@ -23,6 +23,7 @@ type Security = u8;
 
 pub const SECURITY_TYPE_AES128_GCM: Security = 0x03;
 pub const SECURITY_TYPE_CHACHA20_POLY1305: Security = 0x04;
+pub const SECURITY_TYPE_NONE: Security = 0x05;
 
 type RequestOption = u8;
 
@@ -37,6 +38,9 @@ pub struct RequestHeader {
     pub security: Security,
     pub address: SocksAddr,
     pub uuid: Uuid,
+    /// The id authenticating the request, the primary `uuid` or one of the
+    /// alter ids derived from it.
+    pub auth_id: Uuid,
 }
 
 impl RequestHeader {
@@ -45,7 +49,6 @@ impl RequestHeader {
     }
 
     pub fn encode(&self, buf: &mut BytesMut, sess: &ClientSession) -> Result<()> {
-        // generate auth info
         let mut timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
             Ok(n) => n.as_secs(),
             Err(_) => return Err(anyhow!("invalid system time")),
@@ -53,8 +56,25 @@ impl RequestHeader {
         let mut rng = StdRng::from_entropy();
         let delta: i32 = rng.gen_range(0..30 * 2) - 30;
         timestamp = timestamp.wrapping_add(delta as u64);
+
+        // random bytes
+        let padding_len = rng.gen_range(0..16);
+        let mut padding = vec![0u8; padding_len];
+        rng.fill(&mut padding[..]);
+
+        self.encode_with(buf, sess, timestamp, &padding)
+    }
+
+    fn encode_with(
+        &self,
+        buf: &mut BytesMut,
+        sess: &ClientSession,
+        timestamp: u64,
+        padding: &[u8],
+    ) -> Result<()> {
+        // generate auth info
         let mut mac =
-            Hmac::<Md5>::new_varkey(self.uuid.as_bytes()).map_err(|_| anyhow!("md5 failed"))?;
+            Hmac::<Md5>::new_varkey(self.auth_id.as_bytes()).map_err(|_| anyhow!("md5 failed"))?;
         let mut tmp = [0u8; 8];
         BigEndian::write_u64(&mut tmp, timestamp as u64);
         mac.update(&tmp);
@@ -68,8 +88,7 @@ impl RequestHeader {
         buf.put_u8(sess.response_header);
         buf.put_u8(self.option);
 
-        let padding_len = StdRng::from_entropy().gen_range(0..16) as u8;
-        let security = (padding_len << 4) | self.security as u8;
+        let security = ((padding.len() as u8) << 4) | self.security as u8;
 
         buf.put_u8(security);
         buf.put_u8(0);
@@ -77,16 +96,7 @@ impl RequestHeader {
 
         self.address.write_buf(buf, SocksAddrWireType::PortFirst)?;
 
-        // add random bytes
-        if padding_len > 0 {
-            let mut padding_bytes = BytesMut::with_capacity(padding_len as usize);
-            unsafe { padding_bytes.set_len(padding_len as usize) };
-            let mut rng = StdRng::from_entropy();
-            for i in 0..padding_bytes.len() {
-                padding_bytes[i] = rng.gen();
-            }
-            buf.put_slice(&padding_bytes);
-        }
+        buf.put_slice(padding);
 
         // checksum
         let mut hasher = Fnv1a::<u32>::default();
@@ -124,6 +134,34 @@ impl RequestHeader {
     }
 }
 
+/// Returns the id authenticating a request, the primary id if there are no
+/// alter ids, otherwise a random one of the alter ids.
+pub fn auth_id(primary: &Uuid, alter_id: u16) -> Uuid {
+    if alter_id == 0 {
+        return *primary;
+    }
+    let n = StdRng::from_entropy().gen_range(0..alter_id);
+    let mut id = next_id(primary);
+    for _ in 0..n {
+        id = next_id(&id);
+    }
+    id
+}
+
+// Derives the next alter id the way V2Ray does.
+fn next_id(prev: &Uuid) -> Uuid {
+    let mut hasher = Md5::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(b"16167dc8-16b6-4e6d-b8bb-65dd68113a81");
+    loop {
+        let id = Uuid::from_slice(&hasher.clone().finalize()).unwrap();
+        if &id != prev {
+            return id;
+        }
+        hasher.update(b"533eff8a-4113-4b10-b5ce-0f5d76b98cd2");
+    }
+}
+
 pub struct ClientSession {
     pub request_body_key: Vec<u8>,
     pub request_body_iv: Vec<u8>,
@@ -161,3 +199,55 @@ impl ClientSession {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The vectors are generated with an independent implementation of the
+    // V2Ray legacy header.
+    #[test]
+    fn test_encode_request_header() {
+        let uuid = Uuid::parse_str("b831381d-6324-4d53-ad4f-8cda48b30811").unwrap();
+        let alter_id_1 = next_id(&uuid);
+        let alter_id_2 = next_id(&alter_id_1);
+        assert_eq!(
+            alter_id_1.to_string(),
+            "5a071834-12d5-980a-72ac-845d5568d17d"
+        );
+        assert_eq!(
+            alter_id_2.to_string(),
+            "b5f0dba2-de16-aea1-087e-fdbaa0aac7a3"
+        );
+        assert_eq!(auth_id(&uuid, 0), uuid);
+        assert_eq!(auth_id(&uuid, 1), alter_id_1);
+
+        let header = RequestHeader {
+            version: 0x1,
+            command: REQUEST_COMMAND_TCP,
+            option: REQUEST_OPTION_CHUNK_STREAM
+                | REQUEST_OPTION_CHUNK_MASKING
+                | REQUEST_OPTION_GLOBAL_PADDING,
+            security: SECURITY_TYPE_AES128_GCM,
+            address: SocksAddr::Ip("127.0.0.1:443".parse().unwrap()),
+            uuid,
+            auth_id: alter_id_2,
+        };
+        let sess = ClientSession {
+            request_body_key: (16..32).collect(),
+            request_body_iv: (0..16).collect(),
+            response_body_key: Vec::new(),
+            response_body_iv: Vec::new(),
+            response_header: 0x2a,
+        };
+        let mut buf = BytesMut::new();
+        header
+            .encode_with(&mut buf, &sess, 1600000000, &[])
+            .unwrap();
+        assert_eq!(
+            buf.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            "fb6e31eceadd382bcb1099018a3d92700584d48d70fa690bff577e3d11baddbb\
+             e756f4bfdc36b2354e40f33c02ad3de8c1593ede918066d6a1f7edf14c55b1b8b0"
+        );
+    }
+}
@@ -12,10 +12,7 @@ use futures::{
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
-use crate::common::crypto::{
-    aead::{AeadDecryptor, AeadEncryptor},
-    Decryptor, Encryptor,
-};
+use crate::common::crypto::{AeadDecryptor, AeadEncryptor, Decryptor, Encryptor};
 
 use super::crypto::{PaddingLengthGenerator, ShakeSizeParser, VMessAEADSequence};
 use super::protocol::ClientSession;
@@ -35,9 +32,9 @@ enum WriteState {
 pub struct VMessAuthStream<T> {
     inner: T,
     sess: ClientSession,
-    enc: AeadEncryptor<VMessAEADSequence>,
+    enc: Option<AeadEncryptor<VMessAEADSequence>>,
     enc_size_parser: ShakeSizeParser,
-    dec: AeadDecryptor<VMessAEADSequence>,
+    dec: Option<AeadDecryptor<VMessAEADSequence>>,
     dec_size_parser: ShakeSizeParser,
     tag_len: usize,
     read_buf: BytesMut,
@@ -51,9 +48,9 @@ impl<T> VMessAuthStream<T> {
     pub fn new(
         s: T,
         sess: ClientSession,
-        enc: AeadEncryptor<VMessAEADSequence>,
+        enc: Option<AeadEncryptor<VMessAEADSequence>>,
         enc_size_parser: ShakeSizeParser,
-        dec: AeadDecryptor<VMessAEADSequence>,
+        dec: Option<AeadDecryptor<VMessAEADSequence>>,
         dec_size_parser: ShakeSizeParser,
         tag_len: usize,
     ) -> Self {
@@ -158,7 +155,9 @@ impl<T: AsyncRead + Unpin> AsyncRead for VMessAuthStream<T> {
                     ready!(me.poll_read_exact(cx, size))?;
                     let encrypted_size = size - padding_size;
                     let _ = me.read_buf.split_off(encrypted_size); // trim padding
-                    me.dec.decrypt(&mut me.read_buf).map_err(|_| crypto_err())?;
+                    if let Some(dec) = me.dec.as_mut() {
+                        dec.decrypt(&mut me.read_buf).map_err(|_| crypto_err())?;
+                    }
 
                     // ready to read plaintext payload into buf
                     me.read_state = ReadState::PendingData(encrypted_size - me.tag_len);
@@ -189,6 +188,10 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for VMessAuthStream<T> {
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
         use tokio_util::io::poll_write_buf;
+        // A chunk without payload marks the end of the stream.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
         loop {
             match self.write_state {
                 WriteState::WaitingChunk => {
@@ -209,7 +212,9 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for VMessAuthStream<T> {
                     // seal payload
                     piece2.reserve(consume_len + me.tag_len);
                     piece2.put_slice(&buf[..consume_len]);
-                    me.enc.encrypt(&mut piece2).map_err(|_| crypto_err())?;
+                    if let Some(enc) = me.enc.as_mut() {
+                        enc.encrypt(&mut piece2).map_err(|_| crypto_err())?;
+                    }
 
                     let mut piece3 = piece2.split_off(consume_len + me.tag_len);
 
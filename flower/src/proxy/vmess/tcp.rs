@@ -22,7 +22,8 @@ pub struct Handler {
     pub address: String,
     pub port: u16,
     pub uuid: String,
-    pub security: String,
+    pub alter_id: u16,
+    pub security: BodySecurity,
     // pub bind_addr: SocketAddr,
     pub dns_client: SyncDnsClient,
 }
@@ -51,28 +52,14 @@ impl TcpOutboundHandler for Handler {
             version: 0x1,
             command: REQUEST_COMMAND_TCP,
             option: REQUEST_OPTION_CHUNK_STREAM,
-            security: SECURITY_TYPE_CHACHA20_POLY1305,
+            security: self.security.header_security(),
             address: sess.destination.clone(),
             uuid,
+            auth_id: auth_id(&uuid, self.alter_id),
         };
         request_header.set_option(REQUEST_OPTION_CHUNK_MASKING);
         request_header.set_option(REQUEST_OPTION_GLOBAL_PADDING);
 
-        match self.security.to_lowercase().as_str() {
-            "chacha20-poly1305" | "chacha20-ietf-poly1305" => {
-                request_header.security = SECURITY_TYPE_CHACHA20_POLY1305;
-            }
-            "aes-128-gcm" => {
-                request_header.security = SECURITY_TYPE_AES128_GCM;
-            }
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("unsupported cipher: {}", &self.security),
-                ))
-            }
-        }
-
         let mut header_buf = BytesMut::new();
         let client_sess = ClientSession::new();
         request_header
@@ -87,7 +74,7 @@ impl TcpOutboundHandler for Handler {
         let enc_size_parser = ShakeSizeParser::new(&client_sess.request_body_iv);
 
         let enc = new_encryptor(
-            self.security.as_str(),
+            self.security,
             &client_sess.request_body_key,
             &client_sess.request_body_iv,
         )
@@ -97,7 +84,7 @@ impl TcpOutboundHandler for Handler {
 
         let dec_size_parser = ShakeSizeParser::new(&client_sess.response_body_iv);
         let dec = new_decryptor(
-            self.security.as_str(),
+            self.security,
             &client_sess.response_body_key,
             &client_sess.response_body_iv,
         )
@@ -124,8 +111,124 @@ impl TcpOutboundHandler for Handler {
             enc_size_parser,
             dec,
             dec_size_parser,
-            16, // FIXME
+            self.security.tag_len(),
         );
         Ok(Box::new(SimpleProxyStream(stream)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use aes::Aes128;
+    use cfb_mode::cipher::{NewStreamCipher, StreamCipher};
+    use cfb_mode::Cfb;
+    use hmac::{Hmac, Mac, NewMac};
+    use md5::{Digest, Md5};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+    use crate::config;
+    use crate::session::SocksAddr;
+
+    use super::*;
+
+    const UUID: &str = "b831381d-6324-4d53-ad4f-8cda48b30811";
+
+    // Serves a single request with security none the way a VMess server
+    // does, checking the payload arrives in plain and answering it.
+    async fn serve_none<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) {
+        let uuid = Uuid::parse_str(UUID).unwrap();
+        let mut auth = [0u8; 16];
+        stream.read_exact(&mut auth).await.unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let timestamp = (now - 60..now + 60)
+            .find(|ts| {
+                let mut mac = Hmac::<Md5>::new_varkey(uuid.as_bytes()).unwrap();
+                mac.update(&ts.to_be_bytes());
+                mac.finalize().into_bytes()[..] == auth[..]
+            })
+            .expect("unauthenticated request");
+
+        let mut hasher = Md5::new();
+        hasher.update(uuid.as_bytes());
+        hasher.update(b"c48619fe-8f02-49e0-b9e9-edf763e17e21");
+        let key = hasher.finalize();
+        let iv = Md5::digest(&timestamp.to_be_bytes().repeat(4));
+        let mut dec = Cfb::<Aes128>::new_var(&key, &iv).unwrap();
+
+        // version, body iv and key, response header, option, padding length
+        // and security, reserved, command and the port first ipv4 address
+        let mut header = [0u8; 45];
+        stream.read_exact(&mut header).await.unwrap();
+        dec.decrypt(&mut header);
+        assert_eq!(header[35] & 0x0f, SECURITY_TYPE_NONE);
+        assert_eq!(header[37], REQUEST_COMMAND_TCP);
+        assert_eq!(&header[38..], &[0, 80, 1, 1, 2, 3, 4]);
+        // padding and checksum
+        let mut rest = vec![0u8; (header[35] >> 4) as usize + 4];
+        stream.read_exact(&mut rest).await.unwrap();
+        let body_iv = header[1..17].to_vec();
+        let body_key = header[17..33].to_vec();
+        let response_header = header[33];
+
+        let mut size_parser = ShakeSizeParser::new(&body_iv);
+        let padding = size_parser.next_padding_len() as usize;
+        let mut size = [0u8; 2];
+        stream.read_exact(&mut size).await.unwrap();
+        let mut chunk = vec![0u8; size_parser.decode(&size) as usize];
+        stream.read_exact(&mut chunk).await.unwrap();
+        assert_eq!(&chunk[..chunk.len() - padding], b"hello");
+
+        let response_body_iv = Md5::digest(&body_iv);
+        let mut enc = Cfb::<Aes128>::new_var(&Md5::digest(&body_key), &response_body_iv).unwrap();
+        let mut response = vec![response_header, 0, 0, 0];
+        enc.encrypt(&mut response);
+        let mut size_parser = ShakeSizeParser::new(&response_body_iv);
+        let padding = size_parser.next_padding_len() as usize;
+        let mut size = [0u8; 2];
+        size_parser.encode((5 + padding) as u16, &mut size);
+        response.extend_from_slice(&size);
+        response.extend_from_slice(b"world");
+        response.resize(response.len() + padding, 0);
+        stream.write_all(&response).await.unwrap();
+    }
+
+    #[test]
+    fn test_security_none() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut dns = config::Dns::new();
+        dns.servers.push("127.0.0.1".to_string());
+        let dns_client = Arc::new(tokio::sync::RwLock::new(
+            crate::app::dns_client::DnsClient::new(&protobuf::SingularPtrField::some(dns)).unwrap(),
+        ));
+        let handler = Handler {
+            address: "127.0.0.1".to_string(),
+            port: 10086,
+            uuid: UUID.to_string(),
+            alter_id: 0,
+            security: parse_security("none").unwrap(),
+            dns_client,
+        };
+        let sess = Session {
+            destination: SocksAddr::Ip("1.2.3.4:80".parse().unwrap()),
+            ..Default::default()
+        };
+        rt.block_on(async {
+            let (client, server) = tokio::io::duplex(1024);
+            let server = tokio::spawn(serve_none(server));
+            let mut stream = handler.handle(&sess, Some(Box::new(client))).await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"world");
+            server.await.unwrap();
+        });
+    }
+}
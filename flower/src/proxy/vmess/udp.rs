@@ -24,7 +24,8 @@ pub struct Handler {
     pub address: String,
     pub port: u16,
     pub uuid: String,
-    pub security: String,
+    pub alter_id: u16,
+    pub security: BodySecurity,
     // pub bind_addr: SocketAddr,
     pub dns_client: SyncDnsClient,
 }
@@ -59,28 +60,14 @@ impl UdpOutboundHandler for Handler {
             version: 0x1,
             command: REQUEST_COMMAND_UDP,
             option: REQUEST_OPTION_CHUNK_STREAM,
-            security: SECURITY_TYPE_CHACHA20_POLY1305,
+            security: self.security.header_security(),
             address: sess.destination.clone(),
             uuid,
+            auth_id: auth_id(&uuid, self.alter_id),
         };
         request_header.set_option(REQUEST_OPTION_CHUNK_MASKING);
         request_header.set_option(REQUEST_OPTION_GLOBAL_PADDING);
 
-        match self.security.to_lowercase().as_str() {
-            "chacha20-poly1305" | "chacha20-ietf-poly1305" => {
-                request_header.security = SECURITY_TYPE_CHACHA20_POLY1305;
-            }
-            "aes-128-gcm" => {
-                request_header.security = SECURITY_TYPE_AES128_GCM;
-            }
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("unsupported cipher: {}", &self.security),
-                ))
-            }
-        }
-
         let mut header_buf = BytesMut::new();
         let client_sess = ClientSession::new();
         request_header
@@ -94,7 +81,7 @@ impl UdpOutboundHandler for Handler {
 
         let enc_size_parser = ShakeSizeParser::new(&client_sess.request_body_iv);
         let enc = new_encryptor(
-            self.security.as_str(),
+            self.security,
             &client_sess.request_body_key,
             &client_sess.request_body_iv,
         )
@@ -104,7 +91,7 @@ impl UdpOutboundHandler for Handler {
 
         let dec_size_parser = ShakeSizeParser::new(&client_sess.response_body_iv);
         let dec = new_decryptor(
-            self.security.as_str(),
+            self.security,
             &client_sess.response_body_key,
             &client_sess.response_body_iv,
         )
@@ -131,7 +118,7 @@ impl UdpOutboundHandler for Handler {
             enc_size_parser,
             dec,
             dec_size_parser,
-            16, // FIXME
+            self.security.tag_len(),
         );
         Ok(Box::new(Datagram {
             stream,
@@ -0,0 +1,2 @@
+#[cfg(feature = "outbound-wireguard")]
+pub mod outbound;
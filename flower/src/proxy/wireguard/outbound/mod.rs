@@ -0,0 +1,9 @@
+mod device;
+mod noise;
+mod tcp;
+mod tunnel;
+mod udp;
+
+pub use tcp::Handler as TcpHandler;
+pub use tunnel::Tunnel;
+pub use udp::Handler as UdpHandler;
@@ -0,0 +1,649 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use blake2::{Blake2s, Digest, VarBlake2s};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use digest::{Update, VariableOutput};
+use hmac::{Hmac, Mac, NewMac};
+use log::*;
+use rand::{thread_rng, Rng};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const CONSTRUCTION: &[u8] = b"Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s";
+const IDENTIFIER: &[u8] = b"WireGuard v1 zx2c4 Jason@zx2c4.com";
+const LABEL_MAC1: &[u8] = b"mac1----";
+
+const MESSAGE_INITIATION: u8 = 1;
+const MESSAGE_RESPONSE: u8 = 2;
+const MESSAGE_COOKIE_REPLY: u8 = 3;
+const MESSAGE_DATA: u8 = 4;
+
+const INITIATION_LEN: usize = 148;
+const RESPONSE_LEN: usize = 92;
+const COOKIE_REPLY_LEN: usize = 64;
+const DATA_HEADER_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+
+const REKEY_AFTER_MESSAGES: u64 = 1 << 60;
+const REJECT_AFTER_MESSAGES: u64 = u64::MAX - (1 << 13);
+const REKEY_AFTER_TIME: Duration = Duration::from_secs(120);
+const REJECT_AFTER_TIME: Duration = Duration::from_secs(180);
+const REKEY_TIMEOUT: Duration = Duration::from_secs(5);
+const REKEY_ATTEMPT_TIME: Duration = Duration::from_secs(90);
+
+// Packets waiting for a session beyond this are dropped.
+const MAX_QUEUED_PACKETS: usize = 128;
+// Counters more than this far behind the highest received one are rejected.
+const REPLAY_WINDOW: u64 = 128;
+
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut h = Blake2s::new();
+    for part in parts {
+        Digest::update(&mut h, part);
+    }
+    h.finalize().into()
+}
+
+fn mac(key: &[u8], input: &[u8]) -> [u8; 16] {
+    let mut m = VarBlake2s::new_keyed(key, 16);
+    m.update(input);
+    let mut out = [0u8; 16];
+    m.finalize_variable(|res| out.copy_from_slice(res));
+    out
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut m = Hmac::<Blake2s>::new_varkey(key).expect("HMAC accepts keys of any size");
+    for part in parts {
+        m.update(part);
+    }
+    m.finalize().into_bytes().into()
+}
+
+// HKDF with n outputs, as KDF1, KDF2 and KDF3 in the WireGuard paper.
+fn kdf<const N: usize>(key: &[u8], input: &[u8]) -> [[u8; 32]; N] {
+    let prk = hmac(key, &[input]);
+    let mut out = [[0u8; 32]; N];
+    for i in 0..N {
+        out[i] = if i == 0 {
+            hmac(&prk, &[&[1]])
+        } else {
+            hmac(&prk, &[&out[i - 1], &[i as u8 + 1]])
+        };
+    }
+    out
+}
+
+fn aead_nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+    Nonce::from(nonce)
+}
+
+fn seal(key: &[u8; 32], counter: u64, msg: &[u8], aad: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(&Key::from(*key))
+        .encrypt(&aead_nonce(counter), Payload { msg, aad })
+        .expect("in-memory encryption never fails")
+}
+
+fn open(key: &[u8; 32], counter: u64, msg: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new(&Key::from(*key))
+        .decrypt(&aead_nonce(counter), Payload { msg, aad })
+        .map_err(|_| anyhow!("decryption failed"))
+}
+
+// TAI64N label of the current time, monotonic timestamps keep the peer from
+// accepting replayed initiations.
+fn timestamp() -> [u8; 12] {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut out = [0u8; 12];
+    out[..8].copy_from_slice(&(0x400000000000000a + now.as_secs()).to_be_bytes());
+    out[8..].copy_from_slice(&now.subsec_nanos().to_be_bytes());
+    out
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+// Length of the IP packet at the start of a decrypted payload, which may be
+// followed by padding.
+fn ip_packet_len(packet: &[u8]) -> Option<usize> {
+    let len = match packet.first()? >> 4 {
+        4 if packet.len() >= 20 => u16::from_be_bytes([packet[2], packet[3]]) as usize,
+        6 if packet.len() >= 40 => 40 + u16::from_be_bytes([packet[4], packet[5]]) as usize,
+        _ => return None,
+    };
+    if len > packet.len() {
+        return None;
+    }
+    Some(len)
+}
+
+struct Handshake {
+    local_index: u32,
+    ephemeral: StaticSecret,
+    chaining_key: [u8; 32],
+    hash: [u8; 32],
+    sent_at: Instant,
+}
+
+struct ReplayWindow {
+    // The next counter above all received ones.
+    next: u64,
+    // Bit i marks counter `next - 1 - i` as received.
+    bitmap: u128,
+}
+
+impl ReplayWindow {
+    fn check(&self, counter: u64) -> bool {
+        if counter >= self.next {
+            return true;
+        }
+        let age = self.next - 1 - counter;
+        age < REPLAY_WINDOW && self.bitmap & (1 << age) == 0
+    }
+
+    fn update(&mut self, counter: u64) {
+        if counter >= self.next {
+            let shift = counter - self.next + 1;
+            self.bitmap = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.bitmap << shift
+            };
+            self.bitmap |= 1;
+            self.next = counter + 1;
+        } else {
+            self.bitmap |= 1 << (self.next - 1 - counter);
+        }
+    }
+}
+
+struct Session {
+    local_index: u32,
+    remote_index: u32,
+    send_key: [u8; 32],
+    recv_key: [u8; 32],
+    send_counter: u64,
+    replay: ReplayWindow,
+    established: Instant,
+}
+
+impl Session {
+    fn expired(&self, now: Instant) -> bool {
+        now.duration_since(self.established) >= REJECT_AFTER_TIME
+            || self.send_counter >= REJECT_AFTER_MESSAGES
+    }
+
+    fn needs_rekey(&self, now: Instant) -> bool {
+        now.duration_since(self.established) >= REKEY_AFTER_TIME
+            || self.send_counter >= REKEY_AFTER_MESSAGES
+    }
+
+    fn encrypt(&mut self, packet: &[u8]) -> Vec<u8> {
+        // Pads to a multiple of 16 bytes to hide the exact packet length.
+        let padded_len = (packet.len() + 15) & !15;
+        let mut padded = packet.to_vec();
+        padded.resize(padded_len, 0);
+        let mut msg = Vec::with_capacity(DATA_HEADER_LEN + padded_len + TAG_LEN);
+        msg.extend_from_slice(&[MESSAGE_DATA, 0, 0, 0]);
+        msg.extend_from_slice(&self.remote_index.to_le_bytes());
+        msg.extend_from_slice(&self.send_counter.to_le_bytes());
+        msg.extend_from_slice(&seal(&self.send_key, self.send_counter, &padded, &[]));
+        self.send_counter += 1;
+        msg
+    }
+}
+
+/// What to do with a datagram received from the peer.
+pub enum Decapsulated {
+    /// An IP packet to pass to the stack.
+    Packet(Vec<u8>),
+    /// Datagrams to send to the peer, e.g. packets queued during the
+    /// handshake.
+    Send(Vec<Vec<u8>>),
+    /// Nothing to do, e.g. on keepalives.
+    None,
+}
+
+/// Initiator side of the WireGuard protocol towards a single peer, the
+/// Noise_IKpsk2 handshake and the transport data messages.
+///
+/// Cookie replies sent by peers under load are not supported, they are
+/// ignored and the handshake is retried.
+pub struct Noise {
+    static_private: StaticSecret,
+    static_public: PublicKey,
+    peer_public: PublicKey,
+    preshared_key: [u8; 32],
+    // DH(static_private, peer_public), it's the same for every handshake.
+    static_shared: [u8; 32],
+    peer_mac1_key: [u8; 32],
+    own_mac1_key: [u8; 32],
+    keepalive: Option<Duration>,
+    handshake: Option<Handshake>,
+    // First attempt of the pending handshake.
+    handshake_started: Option<Instant>,
+    current: Option<Session>,
+    // Kept after a rekey to decrypt packets in flight.
+    previous: Option<Session>,
+    last_sent: Instant,
+    queue: VecDeque<Vec<u8>>,
+}
+
+impl Noise {
+    pub fn new(
+        private_key: [u8; 32],
+        peer_public_key: [u8; 32],
+        preshared_key: Option<[u8; 32]>,
+        keepalive: Option<Duration>,
+    ) -> Self {
+        let static_private = StaticSecret::from(private_key);
+        let static_public = PublicKey::from(&static_private);
+        let peer_public = PublicKey::from(peer_public_key);
+        let static_shared = *static_private.diffie_hellman(&peer_public).as_bytes();
+        Noise {
+            peer_mac1_key: hash(&[LABEL_MAC1, peer_public.as_bytes()]),
+            own_mac1_key: hash(&[LABEL_MAC1, static_public.as_bytes()]),
+            static_private,
+            static_public,
+            peer_public,
+            preshared_key: preshared_key.unwrap_or_default(),
+            static_shared,
+            keepalive,
+            handshake: None,
+            handshake_started: None,
+            current: None,
+            previous: None,
+            last_sent: Instant::now(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Whether a session is established.
+    pub fn is_established(&self) -> bool {
+        self.current.is_some()
+    }
+
+    fn new_initiation(&mut self, now: Instant) -> Vec<u8> {
+        let local_index: u32 = thread_rng().gen();
+        let ephemeral = StaticSecret::from(thread_rng().gen::<[u8; 32]>());
+        let ephemeral_public = PublicKey::from(&ephemeral);
+
+        let chaining_key = hash(&[CONSTRUCTION]);
+        let h = hash(&[&chaining_key, IDENTIFIER]);
+        let h = hash(&[&h, self.peer_public.as_bytes()]);
+        let [chaining_key] = kdf(&chaining_key, ephemeral_public.as_bytes());
+        let h = hash(&[&h, ephemeral_public.as_bytes()]);
+        let [chaining_key, key] = kdf(
+            &chaining_key,
+            ephemeral.diffie_hellman(&self.peer_public).as_bytes(),
+        );
+        let encrypted_static = seal(&key, 0, self.static_public.as_bytes(), &h);
+        let h = hash(&[&h, &encrypted_static]);
+        let [chaining_key, key] = kdf(&chaining_key, &self.static_shared);
+        let encrypted_timestamp = seal(&key, 0, &timestamp(), &h);
+        let h = hash(&[&h, &encrypted_timestamp]);
+
+        let mut msg = Vec::with_capacity(INITIATION_LEN);
+        msg.extend_from_slice(&[MESSAGE_INITIATION, 0, 0, 0]);
+        msg.extend_from_slice(&local_index.to_le_bytes());
+        msg.extend_from_slice(ephemeral_public.as_bytes());
+        msg.extend_from_slice(&encrypted_static);
+        msg.extend_from_slice(&encrypted_timestamp);
+        let mac1 = mac(&self.peer_mac1_key, &msg);
+        msg.extend_from_slice(&mac1);
+        msg.extend_from_slice(&[0u8; 16]);
+
+        self.handshake = Some(Handshake {
+            local_index,
+            ephemeral,
+            chaining_key,
+            hash: h,
+            sent_at: now,
+        });
+        self.handshake_started.get_or_insert(now);
+        self.last_sent = now;
+        msg
+    }
+
+    fn consume_response(&mut self, msg: &[u8], now: Instant) -> Result<Vec<Vec<u8>>> {
+        if msg.len() != RESPONSE_LEN {
+            return Err(anyhow!("invalid handshake response length {}", msg.len()));
+        }
+        if mac(&self.own_mac1_key, &msg[..60]) != msg[60..76] {
+            return Err(anyhow!("invalid handshake response mac"));
+        }
+        let remote_index = read_u32(msg, 4);
+        let receiver_index = read_u32(msg, 8);
+        let handshake = match &self.handshake {
+            Some(h) if h.local_index == receiver_index => h,
+            _ => return Err(anyhow!("unexpected handshake response")),
+        };
+        let ephemeral_public = PublicKey::from(<[u8; 32]>::try_from(&msg[12..44]).unwrap());
+
+        let [chaining_key] = kdf(&handshake.chaining_key, ephemeral_public.as_bytes());
+        let h = hash(&[&handshake.hash, ephemeral_public.as_bytes()]);
+        let [chaining_key] = kdf(
+            &chaining_key,
+            handshake
+                .ephemeral
+                .diffie_hellman(&ephemeral_public)
+                .as_bytes(),
+        );
+        let [chaining_key] = kdf(
+            &chaining_key,
+            self.static_private
+                .diffie_hellman(&ephemeral_public)
+                .as_bytes(),
+        );
+        let [chaining_key, t, key] = kdf(&chaining_key, &self.preshared_key);
+        let h = hash(&[&h, &t]);
+        open(&key, 0, &msg[44..60], &h)?;
+        let [send_key, recv_key] = kdf(&chaining_key, &[]);
+
+        let session = Session {
+            local_index: handshake.local_index,
+            remote_index,
+            send_key,
+            recv_key,
+            send_counter: 0,
+            replay: ReplayWindow { next: 0, bitmap: 0 },
+            established: now,
+        };
+        self.handshake = None;
+        self.handshake_started = None;
+        self.previous = self.current.replace(session);
+        debug!("wireguard handshake completed");
+
+        // The responder can't send before it receives the first data
+        // message, a keepalive confirms the session if nothing is queued.
+        let session = self.current.as_mut().unwrap();
+        let mut out: Vec<Vec<u8>> = self.queue.drain(..).map(|p| session.encrypt(&p)).collect();
+        if out.is_empty() {
+            out.push(session.encrypt(&[]));
+        }
+        self.last_sent = now;
+        Ok(out)
+    }
+
+    fn consume_data(&mut self, msg: &[u8], now: Instant) -> Result<Option<Vec<u8>>> {
+        if msg.len() < DATA_HEADER_LEN + TAG_LEN {
+            return Err(anyhow!("invalid data message length {}", msg.len()));
+        }
+        let receiver_index = read_u32(msg, 4);
+        let counter = u64::from_le_bytes(msg[8..16].try_into().unwrap());
+        let session = [self.current.as_mut(), self.previous.as_mut()]
+            .into_iter()
+            .flatten()
+            .find(|s| s.local_index == receiver_index)
+            .ok_or_else(|| anyhow!("no session for index {}", receiver_index))?;
+        if session.expired(now) {
+            return Err(anyhow!("session expired"));
+        }
+        if !session.replay.check(counter) {
+            return Err(anyhow!("replayed counter {}", counter));
+        }
+        let mut packet = open(&session.recv_key, counter, &msg[DATA_HEADER_LEN..], &[])?;
+        session.replay.update(counter);
+        if packet.is_empty() {
+            trace!("received wireguard keepalive");
+            return Ok(None);
+        }
+        let len = ip_packet_len(&packet).ok_or_else(|| anyhow!("invalid ip packet"))?;
+        packet.truncate(len);
+        Ok(Some(packet))
+    }
+
+    /// Encrypts an IP packet, returns the datagrams to send to the peer. The
+    /// packet is queued and a handshake initiated if no session is usable.
+    pub fn encapsulate(&mut self, packet: &[u8], now: Instant) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        if let Some(session) = self.current.as_mut().filter(|s| !s.expired(now)) {
+            out.push(session.encrypt(packet));
+            self.last_sent = now;
+            if session.needs_rekey(now) && self.handshake.is_none() {
+                out.push(self.new_initiation(now));
+            }
+            return out;
+        }
+        if self.queue.len() >= MAX_QUEUED_PACKETS {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(packet.to_vec());
+        if self.handshake.is_none() {
+            out.push(self.new_initiation(now));
+        }
+        out
+    }
+
+    /// Processes a datagram received from the peer.
+    pub fn decapsulate(&mut self, datagram: &[u8], now: Instant) -> Result<Decapsulated> {
+        match datagram.first() {
+            Some(&MESSAGE_RESPONSE) => {
+                Ok(Decapsulated::Send(self.consume_response(datagram, now)?))
+            }
+            Some(&MESSAGE_DATA) => Ok(match self.consume_data(datagram, now)? {
+                Some(packet) => Decapsulated::Packet(packet),
+                None => Decapsulated::None,
+            }),
+            Some(&MESSAGE_COOKIE_REPLY) if datagram.len() == COOKIE_REPLY_LEN => {
+                debug!("ignored wireguard cookie reply");
+                Ok(Decapsulated::None)
+            }
+            Some(&MESSAGE_INITIATION) => Err(anyhow!("unexpected handshake initiation")),
+            _ => Err(anyhow!("invalid message")),
+        }
+    }
+
+    /// Drives retransmissions, rekeying and keepalives, returns the datagrams
+    /// to send to the peer. It should be called every few hundred
+    /// milliseconds.
+    pub fn update_timers(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        if matches!(&self.current, Some(s) if s.expired(now)) {
+            debug!("wireguard session expired");
+            self.current = None;
+        }
+        if matches!(&self.previous, Some(s) if s.expired(now)) {
+            self.previous = None;
+        }
+        if let Some(handshake) = &self.handshake {
+            if now.duration_since(handshake.sent_at) >= REKEY_TIMEOUT {
+                let started = self.handshake_started.unwrap_or(now);
+                if now.duration_since(started) >= REKEY_ATTEMPT_TIME {
+                    warn!("wireguard handshake did not complete, giving up");
+                    self.handshake = None;
+                    self.handshake_started = None;
+                    self.queue.clear();
+                } else {
+                    debug!("retrying wireguard handshake");
+                    out.push(self.new_initiation(now));
+                }
+            }
+        }
+        if let (Some(keepalive), Some(session)) = (self.keepalive, self.current.as_mut()) {
+            if now.duration_since(self.last_sent) >= keepalive {
+                out.push(session.encrypt(&[]));
+                self.last_sent = now;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    // The responder side of a handshake, as the peer would do it.
+    fn respond(
+        initiation: &[u8],
+        private_key: &StaticSecret,
+        initiator_public: &PublicKey,
+    ) -> (Vec<u8>, [u8; 32], [u8; 32]) {
+        let public = PublicKey::from(private_key);
+        assert_eq!(
+            mac(&hash(&[LABEL_MAC1, public.as_bytes()]), &initiation[..116]),
+            initiation[116..132]
+        );
+        let chaining_key = hash(&[CONSTRUCTION]);
+        let h = hash(&[&chaining_key, IDENTIFIER]);
+        let h = hash(&[&h, public.as_bytes()]);
+        let ei = PublicKey::from(<[u8; 32]>::try_from(&initiation[8..40]).unwrap());
+        let [chaining_key] = kdf(&chaining_key, ei.as_bytes());
+        let h = hash(&[&h, ei.as_bytes()]);
+        let [chaining_key, key] = kdf(&chaining_key, private_key.diffie_hellman(&ei).as_bytes());
+        let s = open(&key, 0, &initiation[40..88], &h).unwrap();
+        assert_eq!(s, initiator_public.as_bytes());
+        let h = hash(&[&h, &initiation[40..88]]);
+        let [chaining_key, key] = kdf(
+            &chaining_key,
+            private_key.diffie_hellman(initiator_public).as_bytes(),
+        );
+        open(&key, 0, &initiation[88..116], &h).unwrap();
+        let h = hash(&[&h, &initiation[88..116]]);
+
+        let er = StaticSecret::from([7u8; 32]);
+        let er_public = PublicKey::from(&er);
+        let [chaining_key] = kdf(&chaining_key, er_public.as_bytes());
+        let h = hash(&[&h, er_public.as_bytes()]);
+        let [chaining_key] = kdf(&chaining_key, er.diffie_hellman(&ei).as_bytes());
+        let [chaining_key] = kdf(
+            &chaining_key,
+            er.diffie_hellman(initiator_public).as_bytes(),
+        );
+        let [chaining_key, t, key] = kdf(&chaining_key, &[0u8; 32]);
+        let h = hash(&[&h, &t]);
+        let empty = seal(&key, 0, &[], &h);
+        let [recv_key, send_key] = kdf(&chaining_key, &[]);
+
+        let mut msg = vec![MESSAGE_RESPONSE, 0, 0, 0];
+        msg.extend_from_slice(&42u32.to_le_bytes());
+        msg.extend_from_slice(&initiation[4..8]);
+        msg.extend_from_slice(er_public.as_bytes());
+        msg.extend_from_slice(&empty);
+        let mac1 = mac(&hash(&[LABEL_MAC1, initiator_public.as_bytes()]), &msg);
+        msg.extend_from_slice(&mac1);
+        msg.extend_from_slice(&[0u8; 16]);
+        (msg, send_key, recv_key)
+    }
+
+    /// The peer end of a tunnel, answering the handshake and carrying packets
+    /// over the session established.
+    pub(in crate::proxy::wireguard::outbound) struct Peer {
+        private_key: StaticSecret,
+        initiator_public: PublicKey,
+        // Sending and receiving keys, and the index of the initiator.
+        session: Option<([u8; 32], [u8; 32], [u8; 4])>,
+        counter: u64,
+    }
+
+    impl Peer {
+        pub fn new(private_key: [u8; 32], initiator_public: [u8; 32]) -> Self {
+            Peer {
+                private_key: StaticSecret::from(private_key),
+                initiator_public: PublicKey::from(initiator_public),
+                session: None,
+                counter: 0,
+            }
+        }
+
+        /// Consumes a datagram of the initiator, returns the response to a
+        /// handshake or the packet of a data message.
+        pub fn decapsulate(&mut self, datagram: &[u8]) -> Decapsulated {
+            match datagram[0] {
+                MESSAGE_INITIATION => {
+                    let (response, send_key, recv_key) =
+                        respond(datagram, &self.private_key, &self.initiator_public);
+                    let index = datagram[4..8].try_into().unwrap();
+                    self.session = Some((send_key, recv_key, index));
+                    self.counter = 0;
+                    Decapsulated::Send(vec![response])
+                }
+                MESSAGE_DATA => {
+                    let (_, recv_key, _) = self.session.as_ref().unwrap();
+                    let counter = u64::from_le_bytes(datagram[8..16].try_into().unwrap());
+                    let mut packet =
+                        open(recv_key, counter, &datagram[DATA_HEADER_LEN..], &[]).unwrap();
+                    if packet.is_empty() {
+                        return Decapsulated::None;
+                    }
+                    packet.truncate(ip_packet_len(&packet).unwrap());
+                    Decapsulated::Packet(packet)
+                }
+                _ => Decapsulated::None,
+            }
+        }
+
+        pub fn encapsulate(&mut self, packet: &[u8]) -> Vec<u8> {
+            let (send_key, _, index) = self.session.as_ref().unwrap();
+            let mut padded = packet.to_vec();
+            padded.resize((packet.len() + 15) / 16 * 16, 0);
+            let mut msg = vec![MESSAGE_DATA, 0, 0, 0];
+            msg.extend_from_slice(index);
+            msg.extend_from_slice(&self.counter.to_le_bytes());
+            msg.extend_from_slice(&seal(send_key, self.counter, &padded, &[]));
+            self.counter += 1;
+            msg
+        }
+    }
+
+    #[test]
+    fn test_handshake() {
+        let peer_private = StaticSecret::from([1u8; 32]);
+        let peer_public = PublicKey::from(&peer_private);
+        let mut noise = Noise::new([2u8; 32], *peer_public.as_bytes(), None, None);
+        let own_public = PublicKey::from(&StaticSecret::from([2u8; 32]));
+        let now = Instant::now();
+
+        // A 20-byte IPv4 header without payload.
+        let mut packet = vec![0x45, 0, 0, 20];
+        packet.resize(20, 0);
+        let out = noise.encapsulate(&packet, now);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].len(), INITIATION_LEN);
+        assert!(!noise.is_established());
+
+        let (response, send_key, recv_key) = respond(&out[0], &peer_private, &own_public);
+        let out = match noise.decapsulate(&response, now).unwrap() {
+            Decapsulated::Send(out) => out,
+            _ => panic!("expected queued packets"),
+        };
+        assert!(noise.is_established());
+        assert_eq!(out.len(), 1);
+        assert_eq!(read_u32(&out[0], 4), 42);
+        let padded = open(&recv_key, 0, &out[0][DATA_HEADER_LEN..], &[]).unwrap();
+        assert_eq!(padded.len(), 32);
+        assert_eq!(&padded[..20], &packet[..]);
+
+        let mut msg = vec![MESSAGE_DATA, 0, 0, 0];
+        msg.extend_from_slice(&response[8..12]);
+        msg.extend_from_slice(&0u64.to_le_bytes());
+        msg.extend_from_slice(&seal(&send_key, 0, &padded, &[]));
+        match noise.decapsulate(&msg, now).unwrap() {
+            Decapsulated::Packet(p) => assert_eq!(p, packet),
+            _ => panic!("expected a packet"),
+        }
+        // Replayed.
+        assert!(noise.decapsulate(&msg, now).is_err());
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let mut w = ReplayWindow { next: 0, bitmap: 0 };
+        for c in [0, 2, 1, 200, 199] {
+            assert!(w.check(c));
+            w.update(c);
+            assert!(!w.check(c));
+        }
+        assert!(!w.check(50));
+        assert!(w.check(198));
+    }
+}
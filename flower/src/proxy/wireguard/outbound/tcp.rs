@@ -0,0 +1,141 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::poll_fn;
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::tcp;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::timeout;
+
+use crate::{proxy::*, session::Session};
+
+use super::tunnel::{stack_err, Stack, Tunnel};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TCP connection over the tunnel.
+struct Stream {
+    stack: Arc<Stack>,
+    handle: SocketHandle,
+}
+
+impl Stream {
+    fn connect(stack: Arc<Stack>, remote: SocketAddr) -> io::Result<Self> {
+        let handle = stack.new_tcp_socket(remote)?;
+        Ok(Stream { stack, handle })
+    }
+
+    fn poll_established(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.stack.inner.lock().unwrap();
+        let socket = inner.sockets.get_mut::<tcp::Socket>(self.handle);
+        match socket.state() {
+            tcp::State::SynSent | tcp::State::SynReceived => {
+                socket.register_send_waker(cx.waker());
+                Poll::Pending
+            }
+            tcp::State::Closed => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "connection refused",
+            ))),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut inner = self.stack.inner.lock().unwrap();
+        let socket = inner.sockets.get_mut::<tcp::Socket>(self.handle);
+        if socket.can_recv() {
+            let n = socket
+                .recv_slice(buf.initialize_unfilled())
+                .map_err(stack_err)?;
+            buf.advance(n);
+            // The receive window may have opened.
+            self.stack.notify();
+            return Poll::Ready(Ok(()));
+        }
+        if !socket.may_recv() {
+            return Poll::Ready(Ok(()));
+        }
+        socket.register_recv_waker(cx.waker());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = self.stack.inner.lock().unwrap();
+        let socket = inner.sockets.get_mut::<tcp::Socket>(self.handle);
+        if !socket.may_send() {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "connection closed",
+            )));
+        }
+        if !socket.can_send() {
+            socket.register_send_waker(cx.waker());
+            return Poll::Pending;
+        }
+        let n = socket.send_slice(buf).map_err(stack_err)?;
+        self.stack.notify();
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.stack.inner.lock().unwrap();
+        inner.sockets.get_mut::<tcp::Socket>(self.handle).close();
+        self.stack.notify();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.stack.release_tcp_socket(self.handle);
+    }
+}
+
+pub struct Handler {
+    pub tunnel: Arc<Tunnel>,
+}
+
+#[async_trait]
+impl TcpOutboundHandler for Handler {
+    type Stream = AnyStream;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        Some(OutboundConnect::NoConnect)
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        _stream: Option<Self::Stream>,
+    ) -> io::Result<Self::Stream> {
+        let remote = self.tunnel.resolve(&sess.destination).await?;
+        let stack = self.tunnel.stack().await?;
+        let stream = Stream::connect(stack, remote)?;
+        timeout(CONNECT_TIMEOUT, poll_fn(|cx| stream.poll_established(cx)))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+        Ok(Box::new(stream))
+    }
+}
@@ -0,0 +1,713 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures::future::{self, abortable, poll_fn, AbortHandle, Either};
+use futures::TryFutureExt;
+use log::*;
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::socket::{tcp, udp, Socket};
+use smoltcp::wire::{HardwareAddress, IpAddress, IpCidr};
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex as TokioMutex, Notify};
+use tokio::time::timeout;
+use trust_dns_proto::{
+    op::{header::MessageType, op_code::OpCode, query::Query, Message},
+    rr::{record_data::RData, record_type::RecordType, Name},
+};
+
+use crate::{app::SyncDnsClient, proxy, session::SocksAddr};
+
+use super::device::VirtualDevice;
+use super::noise::{Decapsulated, Noise};
+
+pub const DEFAULT_MTU: usize = 1420;
+
+// Interval of the WireGuard timers, e.g. handshake retries and keepalives.
+const TIMER_TICK: Duration = Duration::from_millis(250);
+// Keeps NAT mappings between us and the peer alive.
+const KEEPALIVE: Duration = Duration::from_secs(25);
+const TCP_BUFFER_SIZE: usize = 64 * 1024;
+const TCP_KEEPALIVE_SECS: u64 = 30;
+const TCP_TIMEOUT_SECS: u64 = 120;
+const UDP_BUFFER_SIZE: usize = 64 * 1024;
+const UDP_PACKET_SLOTS: usize = 64;
+// Local ports of the sockets on the tunnel address start from here.
+const EPHEMERAL_PORT_START: u16 = 49152;
+// Timeout of a query to a DNS server of the tunnel.
+const DNS_TIMEOUT: Duration = Duration::from_secs(4);
+
+pub(super) fn stack_err<E: std::fmt::Debug>(e: E) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!("wireguard stack error: {:?}", e),
+    )
+}
+
+fn decode_key(key: &str) -> Result<[u8; 32]> {
+    base64::decode(key)
+        .map_err(|e| anyhow!("invalid key: {}", e))?
+        .try_into()
+        .map_err(|_| anyhow!("invalid key length"))
+}
+
+pub(super) struct StackInner {
+    pub iface: Interface,
+    pub device: VirtualDevice,
+    pub sockets: SocketSet<'static>,
+    next_port: u16,
+    // Sockets of UDP flows keyed by the session source, with the number of
+    // datagrams sharing them.
+    udp_flows: HashMap<SocketAddr, (SocketHandle, usize)>,
+    // Sockets of dropped TCP streams, removed once the connections finish.
+    closing: Vec<SocketHandle>,
+}
+
+impl StackInner {
+    fn next_port(&mut self) -> u16 {
+        let port = self.next_port;
+        self.next_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+        port
+    }
+}
+
+/// A userspace TCP/IP stack on the tunnel address, its packets are carried
+/// by the tunnel.
+pub(super) struct Stack {
+    pub inner: Mutex<StackInner>,
+    pub address: IpAddr,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl Stack {
+    fn new(address: IpAddr, mtu: usize) -> Self {
+        let mut device = VirtualDevice::new(mtu);
+        let mut config = Config::new(HardwareAddress::Ip);
+        config.random_seed = rand::random();
+        let mut iface = Interface::new(config, &mut device, smoltcp::time::Instant::now());
+        let prefix_len = if address.is_ipv4() { 32 } else { 128 };
+        iface.update_ip_addrs(|addrs| {
+            let _ = addrs.push(IpCidr::new(IpAddress::from(address), prefix_len));
+        });
+        let next_port =
+            EPHEMERAL_PORT_START + rand::random::<u16>() % (u16::MAX - EPHEMERAL_PORT_START);
+        Stack {
+            inner: Mutex::new(StackInner {
+                iface,
+                device,
+                sockets: SocketSet::new(vec![]),
+                next_port,
+                udp_flows: HashMap::new(),
+                closing: Vec::new(),
+            }),
+            address,
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Wakes the driver to poll the stack, e.g. after data is written to a
+    /// socket.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn new_tcp_socket(&self, remote: SocketAddr) -> io::Result<SocketHandle> {
+        if self.is_closed() {
+            return Err(io::Error::new(io::ErrorKind::Other, "tunnel closed"));
+        }
+        let mut socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+            tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+        );
+        socket.set_keep_alive(Some(smoltcp::time::Duration::from_secs(TCP_KEEPALIVE_SECS)));
+        socket.set_timeout(Some(smoltcp::time::Duration::from_secs(TCP_TIMEOUT_SECS)));
+        let handle = {
+            let mut inner = self.inner.lock().unwrap();
+            let port = inner.next_port();
+            socket
+                .connect(inner.iface.context(), remote, (self.address, port))
+                .map_err(stack_err)?;
+            inner.sockets.add(socket)
+        };
+        self.notify();
+        Ok(handle)
+    }
+
+    /// Closes a TCP socket, it's freed once the connection finishes.
+    pub fn release_tcp_socket(&self, handle: SocketHandle) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.sockets.get_mut::<tcp::Socket>(handle).close();
+        inner.closing.push(handle);
+        drop(inner);
+        self.notify();
+    }
+
+    /// Returns the UDP socket of the flow from `source`, flows from the same
+    /// source share the socket and hence the local port.
+    pub fn udp_socket(&self, source: SocketAddr) -> io::Result<SocketHandle> {
+        if self.is_closed() {
+            return Err(io::Error::new(io::ErrorKind::Other, "tunnel closed"));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if let Some((handle, n)) = inner.udp_flows.get_mut(&source) {
+            *n += 1;
+            return Ok(*handle);
+        }
+        let handle = self.bind_udp_socket(&mut inner)?;
+        inner.udp_flows.insert(source, (handle, 1));
+        Ok(handle)
+    }
+
+    fn bind_udp_socket(&self, inner: &mut StackInner) -> io::Result<SocketHandle> {
+        let port = inner.next_port();
+        let mut socket = udp::Socket::new(
+            udp::PacketBuffer::new(
+                vec![udp::PacketMetadata::EMPTY; UDP_PACKET_SLOTS],
+                vec![0; UDP_BUFFER_SIZE],
+            ),
+            udp::PacketBuffer::new(
+                vec![udp::PacketMetadata::EMPTY; UDP_PACKET_SLOTS],
+                vec![0; UDP_BUFFER_SIZE],
+            ),
+        );
+        socket
+            .bind((IpAddress::from(self.address), port))
+            .map_err(stack_err)?;
+        Ok(inner.sockets.add(socket))
+    }
+
+    pub fn release_udp_socket(&self, source: &SocketAddr) {
+        let mut inner = self.inner.lock().unwrap();
+        let handle = match inner.udp_flows.get_mut(source) {
+            Some((handle, n)) => {
+                *n -= 1;
+                if *n > 0 {
+                    return;
+                }
+                *handle
+            }
+            None => return,
+        };
+        inner.udp_flows.remove(source);
+        inner.sockets.remove(handle);
+    }
+
+    pub async fn udp_send_to(
+        &self,
+        handle: SocketHandle,
+        buf: &[u8],
+        remote: SocketAddr,
+    ) -> io::Result<()> {
+        poll_fn(|cx| {
+            let mut inner = self.inner.lock().unwrap();
+            let socket = inner.sockets.get_mut::<udp::Socket>(handle);
+            if socket.can_send() {
+                return Poll::Ready(socket.send_slice(buf, remote).map_err(stack_err));
+            }
+            socket.register_send_waker(cx.waker());
+            Poll::Pending
+        })
+        .await?;
+        self.notify();
+        Ok(())
+    }
+
+    pub async fn udp_recv_from(
+        &self,
+        handle: SocketHandle,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr)> {
+        let (n, meta) = poll_fn(|cx| {
+            let mut inner = self.inner.lock().unwrap();
+            let socket = inner.sockets.get_mut::<udp::Socket>(handle);
+            if socket.can_recv() {
+                return Poll::Ready(socket.recv_slice(buf).map_err(stack_err));
+            }
+            socket.register_recv_waker(cx.waker());
+            Poll::Pending
+        })
+        .await?;
+        let addr = SocketAddr::new(IpAddr::from(meta.endpoint.addr), meta.endpoint.port);
+        Ok((n, addr))
+    }
+
+    // Sends a DNS query to `server` from a socket of its own, returns the
+    // addresses answered.
+    async fn query_dns(&self, query: &Message, server: SocketAddr) -> io::Result<Vec<IpAddr>> {
+        let query_bytes = query.to_vec().map_err(stack_err)?;
+        let handle = {
+            if self.is_closed() {
+                return Err(io::Error::new(io::ErrorKind::Other, "tunnel closed"));
+            }
+            let mut inner = self.inner.lock().unwrap();
+            self.bind_udp_socket(&mut inner)?
+        };
+        let exchange = async {
+            self.udp_send_to(handle, &query_bytes, server).await?;
+            let mut buf = vec![0u8; 512];
+            loop {
+                let (n, from) = self.udp_recv_from(handle, &mut buf).await?;
+                if from != server {
+                    continue;
+                }
+                let resp = match Message::from_vec(&buf[..n]) {
+                    Ok(resp) if resp.id() == query.id() => resp,
+                    _ => continue,
+                };
+                return Ok(resp
+                    .answers()
+                    .iter()
+                    .filter_map(|ans| match ans.rdata() {
+                        RData::A(ip) => Some(IpAddr::V4(*ip)),
+                        RData::AAAA(ip) => Some(IpAddr::V6(*ip)),
+                        _ => None,
+                    })
+                    .collect());
+            }
+        };
+        let res = timeout(DNS_TIMEOUT, exchange).await.unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("query to {} timed out", server),
+            ))
+        });
+        self.inner.lock().unwrap().sockets.remove(handle);
+        res
+    }
+
+    // Queues a packet decrypted from the peer.
+    fn receive(&self, packet: Vec<u8>) {
+        self.inner.lock().unwrap().device.rx.push_back(packet);
+        self.notify();
+    }
+
+    // Processes the queued packets and the sockets, returns the packets to
+    // send to the peer and when the stack should be polled again.
+    fn poll(&self) -> (Vec<Vec<u8>>, Option<Duration>) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let now = smoltcp::time::Instant::now();
+        inner.iface.poll(now, &mut inner.device, &mut inner.sockets);
+        let sockets = &mut inner.sockets;
+        inner.closing.retain(|handle| {
+            let state = sockets.get::<tcp::Socket>(*handle).state();
+            if matches!(state, tcp::State::Closed | tcp::State::TimeWait) {
+                sockets.remove(*handle);
+                return false;
+            }
+            true
+        });
+        let packets = inner.device.tx.drain(..).collect();
+        let delay = inner
+            .iface
+            .poll_delay(now, &inner.sockets)
+            .map(Duration::from);
+        (packets, delay)
+    }
+
+    // Fails all connections, the streams see the sockets closed.
+    fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        let mut inner = self.inner.lock().unwrap();
+        for (_, socket) in inner.sockets.iter_mut() {
+            if let Socket::Tcp(socket) = socket {
+                socket.abort();
+            }
+        }
+    }
+}
+
+async fn recv_loop(stack: &Stack, noise: &Mutex<Noise>, socket: &UdpSocket) -> io::Result<()> {
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let n = socket.recv(&mut buf).await?;
+        let res = noise.lock().unwrap().decapsulate(&buf[..n], Instant::now());
+        match res {
+            Ok(Decapsulated::Packet(packet)) => stack.receive(packet),
+            Ok(Decapsulated::Send(datagrams)) => {
+                for datagram in datagrams.iter() {
+                    socket.send(datagram).await?;
+                }
+                stack.notify();
+            }
+            Ok(Decapsulated::None) => (),
+            Err(e) => debug!("drop wireguard datagram: {}", e),
+        }
+    }
+}
+
+async fn send_loop(stack: &Stack, noise: &Mutex<Noise>, socket: &UdpSocket) -> io::Result<()> {
+    loop {
+        let (packets, delay) = stack.poll();
+        let datagrams = {
+            let mut noise = noise.lock().unwrap();
+            let now = Instant::now();
+            let mut datagrams = Vec::new();
+            for packet in packets.iter() {
+                datagrams.append(&mut noise.encapsulate(packet, now));
+            }
+            datagrams.append(&mut noise.update_timers(now));
+            datagrams
+        };
+        for datagram in datagrams.iter() {
+            socket.send(datagram).await?;
+        }
+        let delay = delay.map_or(TIMER_TICK, |d| d.min(TIMER_TICK));
+        let _ = timeout(delay, stack.notify.notified()).await;
+    }
+}
+
+async fn run(stack: Arc<Stack>, noise: Mutex<Noise>, socket: UdpSocket) {
+    let res = match future::select(
+        Box::pin(recv_loop(&stack, &noise, &socket)),
+        Box::pin(send_loop(&stack, &noise, &socket)),
+    )
+    .await
+    {
+        Either::Left((res, _)) | Either::Right((res, _)) => res,
+    };
+    if let Err(e) = res {
+        warn!("wireguard tunnel failed: {}", e);
+    }
+    stack.close();
+}
+
+/// A WireGuard tunnel to a single peer, started on first use. TCP and UDP
+/// sessions are carried by a userspace stack on the tunnel address.
+pub struct Tunnel {
+    private_key: [u8; 32],
+    peer_public_key: [u8; 32],
+    endpoint_address: String,
+    endpoint_port: u16,
+    address: IpAddr,
+    mtu: usize,
+    // DNS servers reached through the tunnel.
+    dns_servers: Vec<IpAddr>,
+    dns_client: SyncDnsClient,
+    stack: TokioMutex<Option<(Arc<Stack>, AbortHandle)>>,
+}
+
+impl Tunnel {
+    /// Keys are base64 encoded, `endpoint` is the host and port of the peer
+    /// and `address` the address of this end in the tunnel. Domains of the
+    /// sessions are resolved by the `dns` servers through the tunnel, or by
+    /// `dns_client` outside the tunnel if there are none.
+    pub fn new(
+        private_key: &str,
+        peer_public_key: &str,
+        endpoint: &str,
+        address: &str,
+        mtu: usize,
+        dns: &[String],
+        dns_client: SyncDnsClient,
+    ) -> Result<Self> {
+        let (endpoint_address, endpoint_port) = endpoint
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| anyhow!("invalid endpoint: {}", endpoint))?;
+        // Brackets of IPv6 addresses.
+        let endpoint_address = endpoint_address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        // A prefix length is allowed but makes no difference, the stack
+        // routes everything to the peer.
+        let address = address
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .parse::<IpAddr>()
+            .map_err(|e| anyhow!("invalid address {}: {}", address, e))?;
+        let dns_servers = dns
+            .iter()
+            .map(|s| {
+                s.parse::<IpAddr>()
+                    .map_err(|e| anyhow!("invalid dns server {}: {}", s, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Tunnel {
+            private_key: decode_key(private_key)?,
+            peer_public_key: decode_key(peer_public_key)?,
+            endpoint_address,
+            endpoint_port,
+            address,
+            mtu: if mtu == 0 { DEFAULT_MTU } else { mtu },
+            dns_servers,
+            dns_client,
+            stack: TokioMutex::new(None),
+        })
+    }
+
+    async fn lookup(&self, host: &String) -> io::Result<Vec<IpAddr>> {
+        self.dns_client
+            .read()
+            .await
+            .lookup(host)
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("lookup {} failed: {}", host, e),
+                )
+            })
+            .await
+    }
+
+    /// Returns the stack of the running tunnel, starts the tunnel if it's not
+    /// running.
+    pub(super) async fn stack(&self) -> io::Result<Arc<Stack>> {
+        let mut state = self.stack.lock().await;
+        if let Some((stack, _)) = state.as_ref() {
+            if !stack.is_closed() {
+                return Ok(stack.clone());
+            }
+        }
+        // The endpoint is outside the tunnel, so is its lookup.
+        let ip = match self.endpoint_address.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => *self
+                .lookup(&self.endpoint_address)
+                .await?
+                .first()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "could not resolve to any address",
+                    )
+                })?,
+        };
+        let endpoint = SocketAddr::new(ip, self.endpoint_port);
+        let socket = proxy::new_udp_socket(&endpoint).await?;
+        socket.connect(endpoint).await?;
+        let noise = Noise::new(
+            self.private_key,
+            self.peer_public_key,
+            None,
+            Some(KEEPALIVE),
+        );
+        let stack = Arc::new(Stack::new(self.address, self.mtu));
+        let (task, abort_handle) = abortable(run(stack.clone(), Mutex::new(noise), socket));
+        tokio::spawn(task);
+        if let Some((_, old)) = state.replace((stack.clone(), abort_handle)) {
+            old.abort();
+        }
+        debug!("started wireguard tunnel to {}", endpoint);
+        Ok(stack)
+    }
+
+    // Looks up a domain with the DNS servers of the tunnel, the servers are
+    // tried in order.
+    async fn lookup_in_tunnel(&self, domain: &str) -> io::Result<Vec<IpAddr>> {
+        let stack = self.stack().await?;
+        let name = Name::from_ascii(format!("{}.", domain.trim_end_matches('.')))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        let ty = if self.address.is_ipv4() {
+            RecordType::A
+        } else {
+            RecordType::AAAA
+        };
+        let mut query = Message::new();
+        query.add_query(Query::query(name, ty));
+        query.set_id(rand::random());
+        query.set_op_code(OpCode::Query);
+        query.set_message_type(MessageType::Query);
+        query.set_recursion_desired(true);
+        let mut last_err = None;
+        for server in self.dns_servers.iter() {
+            match stack.query_dns(&query, SocketAddr::new(*server, 53)).await {
+                Ok(ips) => return Ok(ips),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "lookup {} through the tunnel failed: {}",
+                domain,
+                last_err.map_or_else(|| "no dns servers".to_string(), |e| e.to_string())
+            ),
+        ))
+    }
+
+    /// Resolves the destination of a session, domains are resolved to an
+    /// address of the family of the tunnel address. Without DNS servers of
+    /// the tunnel the lookups go to the outer DNS, leaking the domains
+    /// outside the tunnel.
+    pub(super) async fn resolve(&self, addr: &SocksAddr) -> io::Result<SocketAddr> {
+        match addr {
+            SocksAddr::Ip(addr) => Ok(*addr),
+            SocksAddr::Domain(domain, port) => {
+                let ips = if self.dns_servers.is_empty() {
+                    self.lookup(domain).await?
+                } else {
+                    self.lookup_in_tunnel(domain).await?
+                };
+                ips.into_iter()
+                    .find(|ip| ip.is_ipv4() == self.address.is_ipv4())
+                    .map(|ip| SocketAddr::new(ip, *port))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::Other,
+                            format!("no address of {} reachable through the tunnel", domain),
+                        )
+                    })
+            }
+        }
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        if let Some((stack, abort_handle)) = self.stack.get_mut().take() {
+            abort_handle.abort();
+            stack.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smoltcp::phy::ChecksumCapabilities;
+    use smoltcp::wire::{IpProtocol, Ipv4Packet, Ipv4Repr, UdpPacket, UdpRepr};
+    use trust_dns_proto::rr::Record;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    use crate::config;
+    use crate::proxy::UdpOutboundHandler;
+    use crate::session::Session;
+
+    use super::super::noise::tests::Peer;
+    use super::super::UdpHandler;
+    use super::*;
+
+    // Answers a UDP packet carried by the tunnel, DNS queries are answered
+    // with 10.0.0.9 and other datagrams are echoed.
+    fn answer(packet: &[u8]) -> Vec<u8> {
+        let caps = ChecksumCapabilities::default();
+        let ip = Ipv4Packet::new_checked(packet).unwrap();
+        let ip_repr = Ipv4Repr::parse(&ip, &caps).unwrap();
+        assert_eq!(ip_repr.next_header, IpProtocol::Udp);
+        let (src, dst) = (ip_repr.src_addr.into(), ip_repr.dst_addr.into());
+        let udp = UdpPacket::new_checked(ip.payload()).unwrap();
+        let udp_repr = UdpRepr::parse(&udp, &src, &dst, &caps).unwrap();
+        let payload = if udp_repr.dst_port == 53 {
+            let req = Message::from_vec(udp.payload()).unwrap();
+            let mut resp = Message::new();
+            resp.set_id(req.id());
+            resp.set_message_type(MessageType::Response);
+            resp.add_queries(req.queries().to_vec());
+            resp.add_answer(Record::from_rdata(
+                req.queries()[0].name().clone(),
+                60,
+                RData::A("10.0.0.9".parse().unwrap()),
+            ));
+            resp.to_vec().unwrap()
+        } else {
+            udp.payload().to_vec()
+        };
+
+        let reply_ip = Ipv4Repr {
+            src_addr: ip_repr.dst_addr,
+            dst_addr: ip_repr.src_addr,
+            next_header: IpProtocol::Udp,
+            payload_len: 8 + payload.len(),
+            hop_limit: 64,
+        };
+        let reply_udp = UdpRepr {
+            src_port: udp_repr.dst_port,
+            dst_port: udp_repr.src_port,
+        };
+        let mut buf = vec![0u8; reply_ip.buffer_len() + reply_ip.payload_len];
+        let mut ip = Ipv4Packet::new_unchecked(&mut buf);
+        reply_ip.emit(&mut ip, &caps);
+        reply_udp.emit(
+            &mut UdpPacket::new_unchecked(ip.payload_mut()),
+            &dst,
+            &src,
+            payload.len(),
+            |b| b.copy_from_slice(&payload),
+            &caps,
+        );
+        buf
+    }
+
+    #[test]
+    fn test_tunnel() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let private_key = [2u8; 32];
+            let peer_private_key = [1u8; 32];
+            let public_key = PublicKey::from(&StaticSecret::from(private_key));
+            let peer_public_key = PublicKey::from(&StaticSecret::from(peer_private_key));
+
+            // The peer, it ends the tunnel and answers the packets.
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = socket.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut peer = Peer::new(peer_private_key, *public_key.as_bytes());
+                let mut buf = vec![0u8; 65536];
+                loop {
+                    let (n, from) = socket.recv_from(&mut buf).await.unwrap();
+                    let reply = match peer.decapsulate(&buf[..n]) {
+                        Decapsulated::Send(datagrams) => datagrams[0].clone(),
+                        Decapsulated::Packet(packet) => peer.encapsulate(&answer(&packet)),
+                        Decapsulated::None => continue,
+                    };
+                    socket.send_to(&reply, from).await.unwrap();
+                }
+            });
+
+            let mut dns = config::Dns::new();
+            dns.servers.push("127.0.0.1".to_string());
+            let dns_client = Arc::new(tokio::sync::RwLock::new(
+                crate::app::dns_client::DnsClient::new(&protobuf::SingularPtrField::some(dns))
+                    .unwrap(),
+            ));
+            let tunnel = Tunnel::new(
+                &base64::encode(private_key),
+                &base64::encode(peer_public_key.as_bytes()),
+                &endpoint.to_string(),
+                "10.0.0.2/32",
+                0,
+                &["10.0.0.1".to_string()],
+                dns_client,
+            )
+            .unwrap();
+            let handler = UdpHandler {
+                tunnel: Arc::new(tunnel),
+            };
+
+            let sess = Session::default();
+            let datagram = handler.handle(&sess, None).await.unwrap();
+            let (mut r, mut s) = datagram.split();
+            // The domain is resolved by the DNS server of the tunnel.
+            let dst = SocksAddr::Domain("tunnel.test".to_string(), 7);
+            let n = timeout(Duration::from_secs(5), s.send_to(b"hello", &dst))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(n, 5);
+            let mut buf = [0u8; 64];
+            let (n, from) = timeout(Duration::from_secs(5), r.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..n], b"hello");
+            assert_eq!(from, SocksAddr::Ip("10.0.0.9:7".parse().unwrap()));
+        });
+    }
+}
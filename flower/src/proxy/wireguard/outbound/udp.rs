@@ -0,0 +1,103 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use smoltcp::iface::SocketHandle;
+
+use crate::{
+    proxy::*,
+    session::{Session, SocksAddr},
+};
+
+use super::tunnel::{Stack, Tunnel};
+
+// A UDP flow over the tunnel, the socket is released once both halves drop.
+struct Flow {
+    stack: Arc<Stack>,
+    handle: SocketHandle,
+    source: SocketAddr,
+}
+
+impl Drop for Flow {
+    fn drop(&mut self) {
+        self.stack.release_udp_socket(&self.source);
+    }
+}
+
+pub struct Datagram {
+    flow: Arc<Flow>,
+    tunnel: Arc<Tunnel>,
+}
+
+impl OutboundDatagram for Datagram {
+    fn split(
+        self: Box<Self>,
+    ) -> (
+        Box<dyn OutboundDatagramRecvHalf>,
+        Box<dyn OutboundDatagramSendHalf>,
+    ) {
+        (
+            Box::new(DatagramRecvHalf(self.flow.clone())),
+            Box::new(DatagramSendHalf(self.flow, self.tunnel)),
+        )
+    }
+}
+
+pub struct DatagramRecvHalf(Arc<Flow>);
+
+#[async_trait]
+impl OutboundDatagramRecvHalf for DatagramRecvHalf {
+    async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocksAddr)> {
+        let (n, addr) = self.0.stack.udp_recv_from(self.0.handle, buf).await?;
+        Ok((n, SocksAddr::Ip(addr)))
+    }
+}
+
+pub struct DatagramSendHalf(Arc<Flow>, Arc<Tunnel>);
+
+#[async_trait]
+impl OutboundDatagramSendHalf for DatagramSendHalf {
+    async fn send_to(&mut self, buf: &[u8], dst_addr: &SocksAddr) -> io::Result<usize> {
+        let remote = self.1.resolve(dst_addr).await?;
+        self.0.stack.udp_send_to(self.0.handle, buf, remote).await?;
+        Ok(buf.len())
+    }
+}
+
+pub struct Handler {
+    pub tunnel: Arc<Tunnel>,
+}
+
+#[async_trait]
+impl UdpOutboundHandler for Handler {
+    type UStream = AnyStream;
+    type Datagram = AnyOutboundDatagram;
+
+    fn connect_addr(&self) -> Option<OutboundConnect> {
+        Some(OutboundConnect::NoConnect)
+    }
+
+    fn transport_type(&self) -> DatagramTransportType {
+        DatagramTransportType::Undefined
+    }
+
+    async fn handle<'a>(
+        &'a self,
+        sess: &'a Session,
+        _transport: Option<OutboundTransport<Self::UStream, Self::Datagram>>,
+    ) -> io::Result<Self::Datagram> {
+        let stack = self.tunnel.stack().await?;
+        // Flows from the same source share a local port, as an endpoint
+        // independent NAT would do.
+        let handle = stack.udp_socket(sess.source)?;
+        Ok(Box::new(Datagram {
+            flow: Arc::new(Flow {
+                stack,
+                handle,
+                source: sess.source,
+            }),
+            tunnel: self.tunnel.clone(),
+        }))
+    }
+}
@@ -4,3 +4,5 @@ pub mod inbound;
 pub mod outbound;
 
 mod stream;
+
+pub use stream::WebSocketToStream;
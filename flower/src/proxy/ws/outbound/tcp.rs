@@ -7,31 +7,62 @@ use tokio_tungstenite::client_async_with_config;
 use tungstenite::protocol::WebSocketConfig;
 use url::Url;
 
-use crate::{proxy::*, session::Session};
+use crate::{common::header, proxy::*, session::Session};
 
 use super::stream;
 extern crate http;
 
 pub struct Handler {
     pub path: String,
+    /// The host of the request, the `Host` header or the destination is used
+    /// if empty.
+    pub host: String,
     pub headers: HashMap<String, String>,
 }
 
+impl Handler {
+    fn host(&self, sess: &Session, headers: &[(String, String)]) -> String {
+        if !self.host.is_empty() {
+            return self.host.clone();
+        }
+        if let Some((_, host)) = headers.iter().find(|(k, _)| k.eq_ignore_ascii_case("Host")) {
+            return host.to_owned();
+        }
+        sess.destination.host()
+    }
+
+    fn url(&self, host: &str) -> io::Result<Url> {
+        Url::parse(&format!("ws://{}", host))
+            .and_then(|url| url.join(&self.path))
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid ws url {}{}: {}", host, &self.path, e),
+                )
+            })
+    }
+}
+
 struct Request<'a> {
     pub uri: &'a str,
-    pub headers: &'a HashMap<String, String>,
+    pub headers: &'a [(String, String)],
 }
 
 impl<'a> tungstenite::client::IntoClientRequest for Request<'a> {
     fn into_client_request(
         self,
     ) -> tungstenite::error::Result<tungstenite::handshake::client::Request> {
-        let mut builder = http::Request::builder()
-            .method("GET")
-            .uri(self.uri)
-            .header("User-Agent", &*crate::option::USER_AGENT);
+        let mut builder = http::Request::builder().method("GET").uri(self.uri);
+        // A per-outbound User-Agent takes precedence over the global one.
+        if !self
+            .headers
+            .iter()
+            .any(|(k, _)| k.eq_ignore_ascii_case("User-Agent"))
+        {
+            builder = builder.header("User-Agent", &*crate::option::USER_AGENT);
+        }
         for (k, v) in self.headers.iter() {
-            if k != "Host" {
+            if !k.eq_ignore_ascii_case("Host") {
                 builder = builder.header(k, v);
             }
         }
@@ -53,16 +84,12 @@ impl TcpOutboundHandler for Handler {
         stream: Option<Self::Stream>,
     ) -> io::Result<Self::Stream> {
         if let Some(stream) = stream {
-            let host = if let Some(host) = self.headers.get("Host") {
-                host.to_owned()
-            } else {
-                sess.destination.host()
-            };
-            let mut url = Url::parse(&format!("ws://{}", host)).unwrap();
-            url = url.join(self.path.as_str()).unwrap();
+            let headers = header::render(&self.headers);
+            let host = self.host(sess, &headers);
+            let url = self.url(&host)?;
             let req = Request {
                 uri: &url.to_string(),
-                headers: &self.headers,
+                headers: &headers,
             };
             let ws_config = WebSocketConfig {
                 max_send_queue: Some(4),
@@ -85,3 +112,65 @@ impl TcpOutboundHandler for Handler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::client::IntoClientRequest;
+
+    #[test]
+    fn test_request_headers() {
+        let headers = vec![
+            ("Cookie".to_string(), "a=1; b=2".to_string()),
+            ("Host".to_string(), "example.com".to_string()),
+            ("User-Agent".to_string(), "Mozilla/5.0 (X11)".to_string()),
+        ];
+        let req = Request {
+            uri: "ws://example.com/flower",
+            headers: &headers,
+        }
+        .into_client_request()
+        .unwrap();
+        assert_eq!(req.headers().get("Cookie").unwrap(), "a=1; b=2");
+        assert_eq!(
+            req.headers().get("User-Agent").unwrap(),
+            "Mozilla/5.0 (X11)"
+        );
+        assert_eq!(req.headers().get_all("User-Agent").iter().count(), 1);
+        assert!(req.headers().get("Host").is_none());
+        assert_eq!(req.uri(), "ws://example.com/flower");
+    }
+
+    #[test]
+    fn test_host() {
+        let sess = Session {
+            destination: crate::session::SocksAddr::Domain("dest.com".to_string(), 443),
+            ..Default::default()
+        };
+        let mut handler = Handler {
+            path: "/".to_string(),
+            host: "".to_string(),
+            headers: HashMap::new(),
+        };
+        assert_eq!(handler.host(&sess, &[]), "dest.com");
+        let headers = vec![("host".to_string(), "header.com".to_string())];
+        assert_eq!(handler.host(&sess, &headers), "header.com");
+        handler.host = "example.com".to_string();
+        assert_eq!(handler.host(&sess, &headers), "example.com");
+    }
+
+    #[test]
+    fn test_url() {
+        let handler = Handler {
+            path: "/flower".to_string(),
+            host: "".to_string(),
+            headers: HashMap::new(),
+        };
+        assert_eq!(
+            handler.url("example.com").unwrap().as_str(),
+            "ws://example.com/flower"
+        );
+        assert!(handler.url("exa mple.com").is_err());
+        assert!(handler.url("[::1").is_err());
+    }
+}
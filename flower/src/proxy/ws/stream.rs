@@ -75,6 +75,10 @@ impl<S: Sink<Message> + Unpin> AsyncWrite for WebSocketToStream<S> {
         cx: &mut Context,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
+        // An empty message is read as EOF by the peer.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
         ready!(Pin::new(&mut self.inner)
             .poll_ready(cx)
             .map_err(|_| broken_pipe()))?;
@@ -93,7 +97,11 @@ impl<S: Sink<Message> + Unpin> AsyncWrite for WebSocketToStream<S> {
             .map_err(|_| broken_pipe())
     }
 
-    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        // Messages sent but not yet flushed would be lost otherwise.
+        ready!(Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(|_| broken_pipe()))?;
         // We're using WebSocket as a transport, a shutdown on the write side
         // means a half close of the stream, it seems that WebSocket lacks this
         // half closing capability, sending a close frame means closing the
@@ -3,6 +3,8 @@ use std::{
     fmt, io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
     string::ToString,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::Arc,
 };
 
 use byteorder::{BigEndian, ByteOrder};
@@ -27,6 +29,41 @@ impl std::fmt::Display for Network {
 
 pub type StreamId = u64;
 
+static REDACT_USER: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the `Display` and `Debug` of sessions hide the user
+/// identity, called by the logger setup.
+pub fn set_redact_user(redact: bool) {
+    REDACT_USER.store(redact, Ordering::Relaxed);
+}
+
+/// Bytes relayed in each direction of a session.
+#[derive(Debug, Default)]
+pub struct Traffic {
+    uplink: AtomicU64,
+    downlink: AtomicU64,
+}
+
+impl Traffic {
+    /// Bytes sent from the inbound peer to the outbound peer.
+    pub fn uplink(&self) -> u64 {
+        self.uplink.load(Ordering::Relaxed)
+    }
+
+    /// Bytes sent from the outbound peer to the inbound peer.
+    pub fn downlink(&self) -> u64 {
+        self.downlink.load(Ordering::Relaxed)
+    }
+
+    pub fn add_uplink(&self, n: u64) {
+        self.uplink.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn add_downlink(&self, n: u64) {
+        self.downlink.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct DatagramSource {
     pub address: SocketAddr,
@@ -62,6 +99,97 @@ pub struct Session {
     pub inbound_tag: String,
     /// Optional stream ID for multiplexing transports.
     pub stream_id: Option<StreamId>,
+    /// The user identity authenticated by the inbound, if any.
+    pub user: Option<String>,
+    /// The application protocol detected by sniffing, e.g. "tls".
+    pub protocol: Option<&'static str>,
+    /// The server name sniffed from the TLS ClientHello, if any. The router
+    /// matches the domain rules against it if the destination is an IP.
+    pub sniffed_host: Option<String>,
+    /// The name of the process originating the session, looked up by the
+    /// router if a rule matches on it.
+    pub process_name: Option<String>,
+    /// The ALPN protocol negotiated by the TLS transport right below the
+    /// outbound handling this session, set by the handler stacking them.
+    pub negotiated_alpn: Option<String>,
+    /// Bytes relayed for this session, counted as the connection tracked for
+    /// the session relays, shared by the clones of the session.
+    pub traffic: Arc<Traffic>,
+}
+
+/// Formats a session, with the user identity hidden or not.
+pub struct SessionDisplay<'a> {
+    sess: &'a Session,
+    redact_user: bool,
+}
+
+impl Session {
+    /// Formats the session regardless of the logging config, the `Display`
+    /// and `Debug` of the session redact the user if `set_redact_user` says
+    /// so.
+    pub fn display(&self, redact_user: bool) -> SessionDisplay<'_> {
+        SessionDisplay {
+            sess: self,
+            redact_user,
+        }
+    }
+}
+
+impl<'a> SessionDisplay<'a> {
+    fn user(&self) -> &str {
+        match &self.sess.user {
+            Some(_) if self.redact_user => "<redacted>",
+            Some(user) => user,
+            None => "-",
+        }
+    }
+}
+
+impl<'a> fmt::Display for SessionDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sess = self.sess;
+        write!(
+            f,
+            "{} -> {} ({}, {}, {})",
+            sess.source,
+            sess.destination,
+            sess.network,
+            sess.inbound_tag,
+            self.user(),
+        )
+    }
+}
+
+impl<'a> fmt::Debug for SessionDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sess = self.sess;
+        f.debug_struct("Session")
+            .field("network", &sess.network)
+            .field("source", &sess.source)
+            .field("local_addr", &sess.local_addr)
+            .field("destination", &sess.destination)
+            .field("inbound_tag", &sess.inbound_tag)
+            .field("stream_id", &sess.stream_id)
+            .field("user", &self.user())
+            .field("protocol", &sess.protocol)
+            .field("sniffed_host", &sess.sniffed_host)
+            .field("process_name", &sess.process_name)
+            .field("negotiated_alpn", &sess.negotiated_alpn)
+            .field("traffic", &sess.traffic)
+            .finish()
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.display(REDACT_USER.load(Ordering::Relaxed)), f)
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.display(REDACT_USER.load(Ordering::Relaxed)), f)
+    }
 }
 
 impl Clone for Session {
@@ -73,6 +201,12 @@ impl Clone for Session {
             destination: self.destination.clone(),
             inbound_tag: self.inbound_tag.clone(),
             stream_id: self.stream_id,
+            user: self.user.clone(),
+            protocol: self.protocol,
+            sniffed_host: self.sniffed_host.clone(),
+            process_name: self.process_name.clone(),
+            negotiated_alpn: self.negotiated_alpn.clone(),
+            traffic: self.traffic.clone(),
         }
     }
 }
@@ -86,6 +220,12 @@ impl Default for Session {
             destination: SocksAddr::any(),
             inbound_tag: "".to_string(),
             stream_id: None,
+            user: None,
+            protocol: None,
+            sniffed_host: None,
+            process_name: None,
+            negotiated_alpn: None,
+            traffic: Arc::new(Traffic::default()),
         }
     }
 }
@@ -106,6 +246,7 @@ impl SocksAddrPortFirstType {
     const DOMAIN: u8 = 0x2;
 }
 
+#[derive(Clone, Copy, Debug)]
 pub enum SocksAddrWireType {
     PortFirst,
     PortLast,
@@ -202,12 +343,22 @@ impl SocksAddr {
         }
     }
 
-    /// Writes `self` into `buf`.
+    /// Writes `self` into `buf`. The scope ID of an IPv6 address isn't
+    /// written, it means nothing to the peer.
     pub fn write_buf<T: BufMut>(
         &self,
         buf: &mut T,
         addr_type: SocksAddrWireType,
     ) -> io::Result<()> {
+        if let Self::Domain(domain, _) = self {
+            // The length takes a single byte.
+            if domain.len() > 0xff {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "domain too long",
+                ));
+            }
+        }
         match self {
             Self::Ip(addr) => match addr {
                 SocketAddr::V4(addr) => match addr_type {
@@ -280,28 +431,28 @@ impl SocksAddr {
                 }
                 _ => Err(invalid_addr_type()),
             },
-            SocksAddrWireType::PortFirst => match r.read_u8().await? {
-                SocksAddrPortFirstType::V4 => {
-                    let port = r.read_u16().await?;
-                    let ip = Ipv4Addr::from(r.read_u32().await?);
-                    Ok(Self::Ip((ip, port).into()))
-                }
-                SocksAddrPortFirstType::V6 => {
-                    let port = r.read_u16().await?;
-                    let ip = Ipv6Addr::from(r.read_u128().await?);
-                    Ok(Self::Ip((ip, port).into()))
-                }
-                SocksAddrPortFirstType::DOMAIN => {
-                    let port = r.read_u16().await?;
-                    let domain_len = r.read_u8().await? as usize;
-                    let mut buf = vec![0u8; domain_len];
-                    let n = r.read_exact(&mut buf).await?;
-                    debug_assert_eq!(domain_len, n);
-                    let domain = String::from_utf8(buf).map_err(|_| invalid_domain())?;
-                    Ok(Self::Domain(domain, port))
+            SocksAddrWireType::PortFirst => {
+                let port = r.read_u16().await?;
+                match r.read_u8().await? {
+                    SocksAddrPortFirstType::V4 => {
+                        let ip = Ipv4Addr::from(r.read_u32().await?);
+                        Ok(Self::Ip((ip, port).into()))
+                    }
+                    SocksAddrPortFirstType::V6 => {
+                        let ip = Ipv6Addr::from(r.read_u128().await?);
+                        Ok(Self::Ip((ip, port).into()))
+                    }
+                    SocksAddrPortFirstType::DOMAIN => {
+                        let domain_len = r.read_u8().await? as usize;
+                        let mut buf = vec![0u8; domain_len];
+                        let n = r.read_exact(&mut buf).await?;
+                        debug_assert_eq!(domain_len, n);
+                        let domain = String::from_utf8(buf).map_err(|_| invalid_domain())?;
+                        Ok(Self::Domain(domain, port))
+                    }
+                    _ => Err(invalid_addr_type()),
                 }
-                _ => Err(invalid_addr_type()),
-            },
+            }
         }
     }
 }
@@ -433,11 +584,11 @@ impl TryFrom<(&[u8], SocksAddrWireType)> for SocksAddr {
                     Ok(Self::Ip((ip, port).into()))
                 }
                 SocksAddrPortLastType::DOMAIN => {
-                    if buf.is_empty() {
+                    if buf.len() < 2 {
                         return Err(insuff_bytes());
                     }
                     let domain_len = buf[1] as usize;
-                    if buf.len() < 1 + domain_len + 2 {
+                    if buf.len() < 2 + domain_len + 2 {
                         return Err(insuff_bytes());
                     }
                     let domain =
@@ -451,50 +602,146 @@ impl TryFrom<(&[u8], SocksAddrWireType)> for SocksAddr {
                 }
                 _ => Err(io::Error::new(io::ErrorKind::Other, "invalid address type")),
             },
-            SocksAddrWireType::PortFirst => match buf[0] {
-                SocksAddrPortFirstType::V4 => {
-                    let buf = &buf[1..];
-                    if buf.len() < 4 + 2 {
-                        return Err(insuff_bytes());
-                    }
-                    let port = BigEndian::read_u16(&buf[..2]);
-                    let buf = &buf[2..];
-                    let mut ip_bytes = [0u8; 4];
-                    (&mut ip_bytes).copy_from_slice(&buf[..4]);
-                    let ip = Ipv4Addr::from(ip_bytes);
-                    Ok(Self::Ip((ip, port).into()))
+            SocksAddrWireType::PortFirst => {
+                if buf.len() < 3 {
+                    return Err(insuff_bytes());
                 }
-                SocksAddrPortFirstType::V6 => {
-                    let buf = &buf[1..];
-                    if buf.len() < 16 + 2 {
-                        return Err(insuff_bytes());
+                let port = BigEndian::read_u16(&buf[..2]);
+                let addr_type = buf[2];
+                let buf = &buf[3..];
+                match addr_type {
+                    SocksAddrPortFirstType::V4 => {
+                        if buf.len() < 4 {
+                            return Err(insuff_bytes());
+                        }
+                        let mut ip_bytes = [0u8; 4];
+                        (&mut ip_bytes).copy_from_slice(&buf[..4]);
+                        let ip = Ipv4Addr::from(ip_bytes);
+                        Ok(Self::Ip((ip, port).into()))
                     }
-                    let port = BigEndian::read_u16(&buf[..2]);
-                    let buf = &buf[2..];
-                    let mut ip_bytes = [0u8; 16];
-                    (&mut ip_bytes).copy_from_slice(&buf[..16]);
-                    let ip = Ipv6Addr::from(ip_bytes);
-                    Ok(Self::Ip((ip, port).into()))
-                }
-                SocksAddrPortFirstType::DOMAIN => {
-                    let buf = &buf[1..];
-                    if buf.len() < 3 {
-                        return Err(insuff_bytes());
+                    SocksAddrPortFirstType::V6 => {
+                        if buf.len() < 16 {
+                            return Err(insuff_bytes());
+                        }
+                        let mut ip_bytes = [0u8; 16];
+                        (&mut ip_bytes).copy_from_slice(&buf[..16]);
+                        let ip = Ipv6Addr::from(ip_bytes);
+                        Ok(Self::Ip((ip, port).into()))
                     }
-                    let port = BigEndian::read_u16(&buf[..2]);
-                    let buf = &buf[2..];
-                    let domain_len = buf[0] as usize;
-                    let buf = &buf[1..];
-                    if buf.len() < domain_len {
-                        return Err(insuff_bytes());
+                    SocksAddrPortFirstType::DOMAIN => {
+                        if buf.is_empty() {
+                            return Err(insuff_bytes());
+                        }
+                        let domain_len = buf[0] as usize;
+                        let buf = &buf[1..];
+                        if buf.len() < domain_len {
+                            return Err(insuff_bytes());
+                        }
+                        let domain =
+                            String::from_utf8((&buf[..domain_len]).to_vec()).map_err(|e| {
+                                io::Error::new(
+                                    io::ErrorKind::Other,
+                                    format!("invalid domain: {}", e),
+                                )
+                            })?;
+                        Ok(Self::Domain(domain, port))
                     }
-                    let domain = String::from_utf8((&buf[..domain_len]).to_vec()).map_err(|e| {
-                        io::Error::new(io::ErrorKind::Other, format!("invalid domain: {}", e))
-                    })?;
-                    Ok(Self::Domain(domain, port))
+                    _ => Err(io::Error::new(io::ErrorKind::Other, "invalid address type")),
                 }
-                _ => Err(io::Error::new(io::ErrorKind::Other, "invalid address type")),
-            },
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_display() {
+        let mut sess = Session {
+            network: Network::Udp,
+            source: "192.168.1.2:51000".parse().unwrap(),
+            destination: SocksAddr::Domain("www.google.com".to_string(), 443),
+            inbound_tag: "socks".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            sess.to_string(),
+            "192.168.1.2:51000 -> www.google.com:443 (udp, socks, -)"
+        );
+
+        sess.user = Some("alice".to_string());
+        assert_eq!(
+            sess.to_string(),
+            "192.168.1.2:51000 -> www.google.com:443 (udp, socks, alice)"
+        );
+
+        assert_eq!(
+            sess.display(true).to_string(),
+            "192.168.1.2:51000 -> www.google.com:443 (udp, socks, <redacted>)"
+        );
+        assert!(!format!("{:?}", sess.display(true)).contains("alice"));
+        assert!(format!("{:?}", sess.display(false)).contains("alice"));
+    }
+
+    fn addrs() -> Vec<SocksAddr> {
+        let mut link_local: SocketAddrV6 = "[fe80::1]:443".parse().unwrap();
+        link_local.set_scope_id(3);
+        vec![
+            SocksAddr::from(("1.2.3.4".parse::<Ipv4Addr>().unwrap(), 80)),
+            SocksAddr::from(("2001:db8::1".parse::<Ipv6Addr>().unwrap(), 8080)),
+            SocksAddr::from(link_local),
+            SocksAddr::try_from(("www.google.com", 443)).unwrap(),
+            SocksAddr::try_from(("a".repeat(253), 53)).unwrap(),
+            SocksAddr::try_from(("b".repeat(255), 53)).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_socks_addr_round_trip() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for addr in addrs() {
+            for wire_type in [SocksAddrWireType::PortLast, SocksAddrWireType::PortFirst] {
+                let mut buf = Vec::new();
+                addr.write_buf(&mut buf, wire_type).unwrap();
+                assert_eq!(buf.len(), addr.size());
+
+                // The scope ID doesn't go on the wire.
+                let expected = match &addr {
+                    SocksAddr::Ip(SocketAddr::V6(a)) => {
+                        SocksAddr::from(SocketAddrV6::new(*a.ip(), a.port(), 0, 0))
+                    }
+                    a => a.clone(),
+                };
+                let read = rt
+                    .block_on(SocksAddr::read_from(&mut &buf[..], wire_type))
+                    .unwrap();
+                assert_eq!(read, expected);
+                let parsed = SocksAddr::try_from((&buf[..], wire_type)).unwrap();
+                assert_eq!(parsed, expected);
+
+                // Truncated addresses are rejected instead of panicking.
+                for n in 0..buf.len() {
+                    assert!(SocksAddr::try_from((&buf[..n], wire_type)).is_err());
+                    assert!(rt
+                        .block_on(SocksAddr::read_from(&mut &buf[..n], wire_type))
+                        .is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_socks_addr_domain_too_long() {
+        assert!(SocksAddr::try_from(("a".repeat(256), 53)).is_err());
+        let addr = SocksAddr::Domain("a".repeat(256), 53);
+        let mut buf = Vec::new();
+        assert!(addr
+            .write_buf(&mut buf, SocksAddrWireType::PortLast)
+            .is_err());
+        assert!(buf.is_empty());
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -12,6 +14,10 @@ use flower::proxy::*;
 
 pub async fn run_tcp_echo_server<A: ToSocketAddrs>(addr: A) {
     let listener = TcpListener::bind(addr).await.unwrap();
+    serve_tcp_echo(listener).await;
+}
+
+pub async fn serve_tcp_echo(listener: TcpListener) {
     loop {
         match listener.accept().await {
             Ok((mut stream, _)) => {
@@ -29,6 +35,10 @@ pub async fn run_tcp_echo_server<A: ToSocketAddrs>(addr: A) {
 
 pub async fn run_udp_echo_server<A: ToSocketAddrs>(addr: A) {
     let socket = UdpSocket::bind(addr).await.unwrap();
+    serve_udp_echo(socket).await;
+}
+
+pub async fn serve_udp_echo(socket: UdpSocket) {
     let mut buf = vec![0u8; 2 * 1024];
     loop {
         let (n, raddr) = socket.recv_from(&mut buf).await.unwrap();
@@ -36,11 +46,31 @@ pub async fn run_udp_echo_server<A: ToSocketAddrs>(addr: A) {
     }
 }
 
-// Runs echo servers.
-pub async fn run_echo_servers<A: ToSocketAddrs + 'static + Copy>(addr: A) {
-    let tcp_task = run_tcp_echo_server(addr);
-    let udp_task = run_udp_echo_server(addr);
-    futures::future::join(tcp_task, udp_task).await;
+// Binds echo servers on an ephemeral port, the same for TCP and UDP. Returns
+// the bound address and the task running the servers.
+pub async fn bind_echo_servers() -> (SocketAddr, impl std::future::Future<Output = ()>) {
+    loop {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // The port may be taken for UDP, try another one then.
+        if let Ok(socket) = UdpSocket::bind(addr).await {
+            let task = futures::future::join(serve_tcp_echo(listener), serve_udp_echo(socket));
+            return (addr, task.map(|_| ()));
+        }
+    }
+}
+
+fn run_flower_instance(rt: &tokio::runtime::Runtime, rt_id: flower::RuntimeId, config: &str) {
+    let config = flower::config::json::from_string(config).unwrap();
+    let opts = flower::StartOptions {
+        config: flower::Config::Internal(config),
+        #[cfg(feature = "auto-reload")]
+        auto_reload: false,
+        runtime_opt: flower::RuntimeOption::SingleThread,
+    };
+    rt.spawn_blocking(move || {
+        flower::start(rt_id, opts).unwrap();
+    });
 }
 
 // Runs multiple flower instances.
@@ -49,55 +79,95 @@ pub fn run_flower_instances(
     configs: Vec<String>,
 ) -> Vec<flower::RuntimeId> {
     let mut flower_rt_ids = Vec::new();
-    let mut rt_id = 0;
-    for config in configs {
-        let config = flower::config::json::from_string(&config).unwrap();
-        let opts = flower::StartOptions {
-            config: flower::Config::Internal(config),
-            #[cfg(feature = "auto-reload")]
-            auto_reload: false,
-            runtime_opt: flower::RuntimeOption::SingleThread,
-        };
-        rt.spawn_blocking(move || {
-            flower::start(rt_id, opts).unwrap();
-        });
-        flower_rt_ids.push(rt_id);
-        rt_id += 1;
+    for (rt_id, config) in configs.iter().enumerate() {
+        run_flower_instance(rt, rt_id as flower::RuntimeId, config);
+        flower_rt_ids.push(rt_id as flower::RuntimeId);
     }
     flower_rt_ids
 }
 
+// Runs the flower instances of a proxy chain, the last one first. The inbounds
+// listen on port 0, a config refers to the port bound by a later one with the
+// `${configN}` placeholder, N counting from 1. Returns the runtime IDs and the
+// address bound by the first instance.
+pub fn run_chained_flower_instances(
+    rt: &tokio::runtime::Runtime,
+    mut configs: Vec<String>,
+) -> (Vec<flower::RuntimeId>, SocketAddr) {
+    let mut flower_rt_ids = Vec::new();
+    let mut addr = None;
+    for rt_id in (0..configs.len()).rev() {
+        run_flower_instance(rt, rt_id as flower::RuntimeId, &configs[rt_id]);
+        let addrs = wait_inbound_addrs(rt_id as flower::RuntimeId);
+        assert_eq!(addrs.len(), 1);
+        let bound_addr = addrs.into_values().next().unwrap();
+        let placeholder = format!("${{config{}}}", rt_id + 1);
+        for config in configs[..rt_id].iter_mut() {
+            *config = config.replace(&placeholder, &bound_addr.port().to_string());
+        }
+        flower_rt_ids.push(rt_id as flower::RuntimeId);
+        addr = Some(bound_addr);
+    }
+    (flower_rt_ids, addr.unwrap())
+}
+
+// Waits for a flower instance to start, returns the bound addresses of its
+// inbounds.
+pub fn wait_inbound_addrs(rt_id: flower::RuntimeId) -> HashMap<String, SocketAddr> {
+    for _ in 0..500 {
+        if let Some(addrs) = flower::inbound_addrs(rt_id) {
+            return addrs;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("flower instance {} not started", rt_id);
+}
+
 // Runs multiple flower instances, thereafter a socks request will be sent to the
-// given socks server to test the proxy chain. The proxy chain is expected to
-// correctly handle the request to it's destination.
-pub fn test_configs(configs: Vec<String>, socks_addr: &str, socks_port: u16) {
+// socks inbound of the first one to test the proxy chain. The proxy chain is
+// expected to correctly handle the request to it's destination. See
+// `run_chained_flower_instances` for the ports of the inbounds.
+pub fn test_configs(configs: Vec<String>) {
+    run_test_configs(configs, false);
+}
+
+// Same as `test_configs`, with UDP tunneled over the TCP connection to the
+// socks server.
+pub fn test_configs_udp_over_tcp(configs: Vec<String>) {
+    run_test_configs(configs, true);
+}
+
+fn run_test_configs(configs: Vec<String>, udp_over_tcp: bool) {
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap();
 
     // Use an echo server as the destination of the socks request.
-    let mut bg_tasks: Vec<flower::Runner> = Vec::new();
-    let echo_server_task = run_echo_servers("127.0.0.1:3000");
-    bg_tasks.push(Box::pin(echo_server_task));
-    let (bg_task, bg_task_handle) = abortable(futures::future::join_all(bg_tasks));
+    let (echo_addr, echo_server_task) = rt.block_on(bind_echo_servers());
+    let (bg_task, bg_task_handle) = abortable(echo_server_task);
 
-    let flower_rt_ids = run_flower_instances(&rt, configs);
+    let (flower_rt_ids, socks_addr) = run_chained_flower_instances(&rt, configs);
 
     // Simulates an application request.
     let app_task = async move {
-        tokio::time::sleep(Duration::from_millis(100)).await;
-
         // Make use of a socks outbound to initiate a socks request to a flower instance.
         let settings = flower::config::json::SocksOutboundSettings {
-            address: Some(socks_addr.to_string()),
-            port: Some(socks_port),
+            address: Some(socks_addr.ip().to_string()),
+            port: Some(socks_addr.port()),
+            username: None,
+            password: None,
+            udp_over_tcp: Some(udp_over_tcp),
         };
         let settings_str = serde_json::to_string(&settings).unwrap();
         let raw_settings = serde_json::value::RawValue::from_string(settings_str).unwrap();
         let outbounds = vec![flower::config::json::Outbound {
             protocol: "socks".to_string(),
             tag: Some("socks".to_string()),
+            nodelay: None,
+            quota: None,
+            connect_timeout: None,
+            handshake_timeout: None,
             settings: Some(raw_settings),
         }];
         let mut config = flower::config::json::Config {
@@ -117,12 +187,10 @@ pub fn test_configs(configs: Vec<String>, socks_addr: &str, socks_port: u16) {
                 .unwrap();
         let handler = outbound_manager.get("socks").unwrap();
         let mut sess = flower::session::Session::default();
-        sess.destination = flower::session::SocksAddr::Ip("127.0.0.1:3000".parse().unwrap());
+        sess.destination = flower::session::SocksAddr::Ip(echo_addr);
 
         // Test TCP
-        let stream = tokio::net::TcpStream::connect(format!("{}:{}", socks_addr, socks_port))
-            .await
-            .unwrap();
+        let stream = tokio::net::TcpStream::connect(socks_addr).await.unwrap();
         let mut s = TcpOutboundHandler::handle(handler.as_ref(), &sess, Some(Box::new(stream)))
             .await
             .unwrap();
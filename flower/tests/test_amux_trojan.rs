@@ -20,7 +20,7 @@ fn test_amux_trojan() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -38,7 +38,7 @@ fn test_amux_trojan() {
                 "tag": "amux",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001
+                    "port": ${config2}
                 }
             },
             {
@@ -58,7 +58,7 @@ fn test_amux_trojan() {
             {
                 "protocol": "chain",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "amux",
@@ -87,5 +87,5 @@ fn test_amux_trojan() {
     "#;
 
     let configs = vec![config1.to_string(), config2.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
@@ -0,0 +1,275 @@
+mod common;
+
+// app(socks) -> (socks)client(direct) -> echo, inspected over the api
+#[cfg(all(
+    feature = "api",
+    feature = "inbound-socks",
+    feature = "outbound-direct",
+))]
+#[test]
+fn test_api() {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use futures::future::abortable;
+    use futures::FutureExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time::timeout;
+
+    let config = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "socks",
+                "tag": "socks",
+                "address": "127.0.0.1",
+                "port": 0
+            }
+        ],
+        "outbounds": [
+            {
+                "protocol": "direct"
+            }
+        ],
+        "api": {
+            "address": "127.0.0.1",
+            "port": 0,
+            "secret": "s3cr3t"
+        }
+    }
+    "#
+    .to_string();
+
+    // Sends a request and returns the status code and body of the response.
+    async fn request(
+        api_addr: SocketAddr,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+    ) -> (u16, String) {
+        let mut stream = TcpStream::connect(api_addr).await.unwrap();
+        let mut req = format!(
+            "{} {} HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 0\r\nConnection: close\r\n",
+            method, path
+        );
+        if let Some(token) = token {
+            req.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        req.push_str("\r\n");
+        stream.write_all(req.as_bytes()).await.unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.unwrap();
+        let status = resp[9..12].parse().unwrap();
+        let body = resp.split("\r\n\r\n").nth(1).unwrap_or("").to_string();
+        (status, body)
+    }
+
+    // Reads from a stream until the received data contains the given pattern.
+    async fn read_until(stream: &mut TcpStream, buf: &mut Vec<u8>, pattern: &str) {
+        timeout(Duration::from_secs(2), async {
+            while !String::from_utf8_lossy(buf).contains(pattern) {
+                let n = stream.read_buf(buf).await.unwrap();
+                assert_ne!(n, 0);
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let echo_listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let echo_addr = echo_listener.local_addr().unwrap();
+    let (echo_task, echo_handle) = abortable(common::serve_tcp_echo(echo_listener));
+    let flower_rt_ids = common::run_flower_instances(&rt, vec![config]);
+    let socks_addr = common::wait_inbound_addrs(flower_rt_ids[0])["socks"];
+    let api_addr = flower::api_addr(flower_rt_ids[0]).unwrap();
+
+    let app_task = async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (status, _) = request(api_addr, "GET", "/api/v1/runtime/stats", None).await;
+        assert_eq!(status, 401);
+        let (status, _) = request(api_addr, "GET", "/api/v1/runtime/stats", Some("wrong")).await;
+        assert_eq!(status, 401);
+
+        // Subscribes to the event stream.
+        let mut events = TcpStream::connect(api_addr).await.unwrap();
+        events
+            .write_all(
+                b"GET /api/v1/runtime/events HTTP/1.1\r\nHost: 127.0.0.1\r\nAuthorization: Bearer s3cr3t\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut events_buf = Vec::new();
+        read_until(&mut events, &mut events_buf, "application/x-ndjson").await;
+
+        // Opens a connection through the socks inbound.
+        let mut s = TcpStream::connect(socks_addr).await.unwrap();
+        s.write_all(&[5, 1, 0]).await.unwrap();
+        let mut buf = [0u8; 10];
+        s.read_exact(&mut buf[..2]).await.unwrap();
+        let port = echo_addr.port().to_be_bytes();
+        s.write_all(&[5, 1, 0, 1, 127, 0, 0, 1, port[0], port[1]])
+            .await
+            .unwrap();
+        s.read_exact(&mut buf).await.unwrap();
+        s.write_all(b"abc").await.unwrap();
+        s.read_exact(&mut buf[..3]).await.unwrap();
+        assert_eq!(&buf[..3], b"abc");
+
+        read_until(&mut events, &mut events_buf, r#""type":"session_start""#).await;
+        let events_str = String::from_utf8_lossy(&events_buf).to_string();
+        assert!(events_str.contains(&format!(r#""destination":"{}""#, echo_addr)));
+
+        let (status, body) = request(
+            api_addr,
+            "GET",
+            "/api/v1/runtime/connections",
+            Some("s3cr3t"),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let conns: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let conns = conns.as_array().unwrap();
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0]["destination"], echo_addr.to_string());
+        assert_eq!(conns[0]["uplink"], 3);
+        assert_eq!(conns[0]["downlink"], 3);
+        let id = conns[0]["id"].as_u64().unwrap();
+
+        let (status, body) =
+            request(api_addr, "GET", "/api/v1/runtime/stats", Some("s3cr3t")).await;
+        assert_eq!(status, 200);
+        let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(stats["connections"], 1);
+        assert_eq!(stats["uplink"], 3);
+
+        let path = format!("/api/v1/runtime/connections/{}", id);
+        let (status, _) = request(api_addr, "DELETE", &path, Some("s3cr3t")).await;
+        assert_eq!(status, 200);
+        let n = timeout(Duration::from_secs(1), s.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        read_until(&mut events, &mut events_buf, r#""type":"session_end""#).await;
+        let (status, _) = request(api_addr, "DELETE", &path, Some("s3cr3t")).await;
+        assert_eq!(status, 404);
+
+        let (status, _) = request(
+            api_addr,
+            "POST",
+            "/api/v1/runtime/dns/flush",
+            Some("s3cr3t"),
+        )
+        .await;
+        assert_eq!(status, 200);
+
+        echo_handle.abort();
+    };
+    rt.block_on(futures::future::join(echo_task, app_task).map(|_| ()));
+    for id in flower_rt_ids.into_iter() {
+        assert!(flower::shutdown(id));
+    }
+}
+
+// The config file is changed and reloaded over the api.
+#[cfg(all(
+    feature = "api",
+    feature = "inbound-socks",
+    feature = "outbound-direct",
+))]
+#[test]
+fn test_api_reload() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    let config = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "socks",
+                "tag": "socks",
+                "address": "127.0.0.1",
+                "port": 0
+            }EXTRA_INBOUNDS
+        ],
+        "outbounds": [
+            {
+                "protocol": "direct"
+            }
+        ],
+        "api": {
+            "address": "127.0.0.1",
+            "port": 0
+        }
+    }
+    "#;
+    let extra_inbound = r#",
+            {
+                "protocol": "socks",
+                "tag": "socks2",
+                "address": "127.0.0.1",
+                "port": 0
+            }"#;
+
+    let dir = std::env::temp_dir().join(format!("flower-api-reload-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_file = dir.join("config.json");
+    std::fs::write(&config_file, config.replace("EXTRA_INBOUNDS", "")).unwrap();
+
+    let rt_id = 1;
+    let opts = flower::StartOptions {
+        config: flower::Config::File(config_file.to_string_lossy().to_string()),
+        #[cfg(feature = "auto-reload")]
+        auto_reload: false,
+        runtime_opt: flower::RuntimeOption::SingleThread,
+    };
+    std::thread::spawn(move || flower::start(rt_id, opts).unwrap());
+    let addrs = common::wait_inbound_addrs(rt_id);
+    assert_eq!(addrs.len(), 1);
+    let api_addr = flower::api_addr(rt_id).unwrap();
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let reload = || {
+        rt.block_on(async {
+            let mut stream = TcpStream::connect(api_addr).await.unwrap();
+            stream
+                .write_all(
+                    b"POST /api/v1/runtime/reload HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            let mut resp = String::new();
+            stream.read_to_string(&mut resp).await.unwrap();
+            resp[9..12].parse::<u16>().unwrap()
+        })
+    };
+
+    // The added inbound is started.
+    std::fs::write(
+        &config_file,
+        config.replace("EXTRA_INBOUNDS", extra_inbound),
+    )
+    .unwrap();
+    assert_eq!(reload(), 200);
+    let new_addrs = flower::inbound_addrs(rt_id).unwrap();
+    assert_eq!(new_addrs.len(), 2);
+    assert_eq!(new_addrs["socks"], addrs["socks"]);
+
+    // An invalid config is not applied.
+    std::fs::write(&config_file, "{").unwrap();
+    assert_eq!(reload(), 202);
+    assert_eq!(flower::inbound_addrs(rt_id).unwrap(), new_addrs);
+
+    assert!(flower::shutdown(rt_id));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -20,7 +20,7 @@ fn test_in_chain_1() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -45,7 +45,7 @@ fn test_in_chain_1() {
                 "tag": "trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "password": "password"
                 }
             }
@@ -59,7 +59,7 @@ fn test_in_chain_1() {
             {
                 "protocol": "chain",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -91,5 +91,5 @@ fn test_in_chain_1() {
     "#;
 
     let configs = vec![config1.to_string(), config2.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
@@ -20,7 +20,7 @@ fn test_out_chain_1() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -56,7 +56,7 @@ fn test_out_chain_1() {
                 "tag": "server1-trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "password": "password"
                 }
             },
@@ -82,7 +82,7 @@ fn test_out_chain_1() {
                 "tag": "server2-trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3002,
+                    "port": ${config3},
                     "password": "password"
                 }
             }
@@ -97,7 +97,7 @@ fn test_out_chain_1() {
                 "protocol": "chain",
                 "tag": "server1",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -135,7 +135,7 @@ fn test_out_chain_1() {
                 "protocol": "chain",
                 "tag": "server2",
                 "address": "127.0.0.1",
-                "port": 3002,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -171,5 +171,5 @@ fn test_out_chain_1() {
         config2.to_string(),
         config3.to_string(),
     ];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
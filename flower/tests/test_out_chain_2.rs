@@ -17,7 +17,7 @@ fn test_out_chain_2() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -35,7 +35,7 @@ fn test_out_chain_2() {
                 "tag": "server1",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "method": "chacha20-ietf-poly1305",
                     "password": "password"
                 }
@@ -45,7 +45,7 @@ fn test_out_chain_2() {
                 "tag": "server2",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3002,
+                    "port": ${config3},
                     "method": "aes-128-gcm",
                     "password": "password"
                 }
@@ -60,7 +60,7 @@ fn test_out_chain_2() {
             {
                 "protocol": "shadowsocks",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "method": "chacha20-ietf-poly1305",
                     "password": "password"
@@ -81,7 +81,7 @@ fn test_out_chain_2() {
             {
                 "protocol": "shadowsocks",
                 "address": "127.0.0.1",
-                "port": 3002,
+                "port": 0,
                 "settings": {
                     "method": "aes-128-gcm",
                     "password": "password"
@@ -101,5 +101,5 @@ fn test_out_chain_2() {
         config2.to_string(),
         config3.to_string(),
     ];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
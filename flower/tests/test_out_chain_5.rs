@@ -22,7 +22,7 @@ fn test_out_chain_5() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -49,7 +49,7 @@ fn test_out_chain_5() {
                 "tag": "server1-trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "password": "password"
                 }
             },
@@ -58,7 +58,7 @@ fn test_out_chain_5() {
                 "tag": "server2",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3002,
+                    "port": ${config3},
                     "method": "aes-128-gcm",
                     "password": "password"
                 }
@@ -74,7 +74,7 @@ fn test_out_chain_5() {
                 "protocol": "chain",
                 "tag": "server1",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -111,7 +111,7 @@ fn test_out_chain_5() {
             {
                 "protocol": "shadowsocks",
                 "address": "127.0.0.1",
-                "port": 3002,
+                "port": 0,
                 "settings": {
                     "method": "aes-128-gcm",
                     "password": "password"
@@ -131,5 +131,5 @@ fn test_out_chain_5() {
         config2.to_string(),
         config3.to_string(),
     ];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
@@ -22,7 +22,7 @@ fn test_out_chain_7() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -59,7 +59,7 @@ fn test_out_chain_7() {
                 "tag": "server1-trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "password": "password"
                 }
             },
@@ -68,7 +68,7 @@ fn test_out_chain_7() {
                 "tag": "server2",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3002,
+                    "port": ${config3},
                     "method": "aes-128-gcm",
                     "password": "password"
                 }
@@ -95,7 +95,7 @@ fn test_out_chain_7() {
                 "tag": "server3-trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3003,
+                    "port": ${config4},
                     "password": "password"
                 }
             }
@@ -110,7 +110,7 @@ fn test_out_chain_7() {
                 "protocol": "chain",
                 "tag": "server1",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -147,7 +147,7 @@ fn test_out_chain_7() {
             {
                 "protocol": "shadowsocks",
                 "address": "127.0.0.1",
-                "port": 3002,
+                "port": 0,
                 "settings": {
                     "method": "aes-128-gcm",
                     "password": "password"
@@ -169,7 +169,7 @@ fn test_out_chain_7() {
                 "protocol": "chain",
                 "tag": "server1",
                 "address": "127.0.0.1",
-                "port": 3003,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -206,5 +206,5 @@ fn test_out_chain_7() {
         config3.to_string(),
         config4.to_string(),
     ];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
@@ -22,7 +22,7 @@ fn test_out_chain_8() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -51,7 +51,7 @@ fn test_out_chain_8() {
                 "tag": "server1-trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "password": "password"
                 }
             },
@@ -60,7 +60,7 @@ fn test_out_chain_8() {
                 "tag": "server2",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3002,
+                    "port": ${config3},
                     "method": "aes-128-gcm",
                     "password": "password"
                 }
@@ -77,7 +77,7 @@ fn test_out_chain_8() {
                 "tag": "server3-trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3003,
+                    "port": ${config4},
                     "password": "password"
                 }
             }
@@ -92,7 +92,7 @@ fn test_out_chain_8() {
                 "protocol": "chain",
                 "tag": "server1",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -129,7 +129,7 @@ fn test_out_chain_8() {
             {
                 "protocol": "shadowsocks",
                 "address": "127.0.0.1",
-                "port": 3002,
+                "port": 0,
                 "settings": {
                     "method": "aes-128-gcm",
                     "password": "password"
@@ -151,7 +151,7 @@ fn test_out_chain_8() {
                 "protocol": "chain",
                 "tag": "server1",
                 "address": "127.0.0.1",
-                "port": 3003,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "ws",
@@ -188,5 +188,5 @@ fn test_out_chain_8() {
         config3.to_string(),
         config4.to_string(),
     ];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
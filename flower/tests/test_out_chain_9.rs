@@ -0,0 +1,33 @@
+// A chain referencing an outbound which doesn't exist fails to load.
+#[cfg(all(feature = "outbound-direct", feature = "outbound-chain"))]
+#[test]
+fn test_out_chain_9() {
+    let config = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "chain",
+                "tag": "chain",
+                "settings": {
+                    "actors": [
+                        "direct",
+                        "missing"
+                    ]
+                }
+            },
+            {
+                "protocol": "direct",
+                "tag": "direct"
+            }
+        ]
+    }
+    "#;
+    let config = flower::config::json::from_string(config).unwrap();
+    let opts = flower::StartOptions {
+        config: flower::Config::Internal(config),
+        #[cfg(feature = "auto-reload")]
+        auto_reload: false,
+        runtime_opt: flower::RuntimeOption::SingleThread,
+    };
+    assert!(flower::start(0, opts).is_err());
+}
@@ -0,0 +1,60 @@
+mod common;
+
+// app(socks) -> (socks)client(direct) -> echo, the inbound listens on a random port
+#[cfg(all(feature = "inbound-socks", feature = "outbound-direct"))]
+#[test]
+fn test_random_port() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    let config = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "socks",
+                "tag": "socks",
+                "address": "127.0.0.1",
+                "port": 0
+            }
+        ],
+        "outbounds": [
+            {
+                "protocol": "direct"
+            }
+        ]
+    }
+    "#;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let echo_listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let echo_addr = echo_listener.local_addr().unwrap();
+    rt.spawn(common::serve_tcp_echo(echo_listener));
+
+    let flower_rt_ids = common::run_flower_instances(&rt, vec![config.to_string()]);
+    let addrs = common::wait_inbound_addrs(flower_rt_ids[0]);
+    let socks_addr = addrs["socks"];
+    assert_ne!(socks_addr.port(), 0);
+
+    rt.block_on(async move {
+        let mut s = TcpStream::connect(socks_addr).await.unwrap();
+        s.write_all(&[5, 1, 0]).await.unwrap();
+        let mut buf = [0u8; 10];
+        s.read_exact(&mut buf[..2]).await.unwrap();
+        let port = echo_addr.port().to_be_bytes();
+        s.write_all(&[5, 1, 0, 1, 127, 0, 0, 1, port[0], port[1]])
+            .await
+            .unwrap();
+        s.read_exact(&mut buf).await.unwrap();
+        s.write_all(b"abc").await.unwrap();
+        s.read_exact(&mut buf[..3]).await.unwrap();
+        assert_eq!(&buf[..3], b"abc");
+    });
+
+    for id in flower_rt_ids.into_iter() {
+        assert!(flower::shutdown(id));
+    }
+}
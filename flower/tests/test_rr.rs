@@ -17,7 +17,7 @@ fn test_rr() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -34,7 +34,7 @@ fn test_rr() {
                 "tag": "ss_out",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "method": "chacha20-ietf-poly1305",
                     "password": "password"
                 }
@@ -49,7 +49,7 @@ fn test_rr() {
             {
                 "protocol": "shadowsocks",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "method": "chacha20-ietf-poly1305",
                     "password": "password"
@@ -65,5 +65,5 @@ fn test_rr() {
     "#;
 
     let configs = vec![config1.to_string(), config2.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
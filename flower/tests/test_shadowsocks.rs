@@ -16,7 +16,7 @@ fn test_shadowsocks() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -24,7 +24,7 @@ fn test_shadowsocks() {
                 "protocol": "shadowsocks",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "method": "chacha20-ietf-poly1305",
                     "password": "password"
                 }
@@ -39,7 +39,7 @@ fn test_shadowsocks() {
             {
                 "protocol": "shadowsocks",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "method": "chacha20-ietf-poly1305",
                     "password": "password"
@@ -55,5 +55,37 @@ fn test_shadowsocks() {
     "#;
 
     let configs = vec![config1.to_string(), config2.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
+}
+
+// An unknown cipher fails to load.
+#[cfg(feature = "outbound-shadowsocks")]
+#[test]
+fn test_shadowsocks_unknown_cipher() {
+    let config = r#"
+    {
+        "outbounds": [
+            {
+                "protocol": "shadowsocks",
+                "settings": {
+                    "address": "127.0.0.1",
+                    "port": 3001,
+                    "method": "rc4-md5",
+                    "password": "password"
+                }
+            }
+        ]
+    }
+    "#;
+    let config = flower::config::json::from_string(config).unwrap();
+    let opts = flower::StartOptions {
+        config: flower::Config::Internal(config),
+        #[cfg(feature = "auto-reload")]
+        auto_reload: false,
+        runtime_opt: flower::RuntimeOption::SingleThread,
+    };
+    assert!(matches!(
+        flower::start(0, opts),
+        Err(flower::Error::Config(_))
+    ));
 }
@@ -14,7 +14,7 @@ fn test_socks() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -26,5 +26,5 @@ fn test_socks() {
     "#;
 
     let configs = vec![config1.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
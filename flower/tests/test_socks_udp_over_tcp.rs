@@ -0,0 +1,52 @@
+mod common;
+
+// app(socks, udp over tcp) -> (socks)client(socks, udp over tcp) -> (socks)server(direct) -> echo
+#[cfg(all(
+    feature = "outbound-socks",
+    feature = "inbound-socks",
+    feature = "outbound-direct",
+))]
+#[test]
+fn test_socks_udp_over_tcp() {
+    let config1 = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "socks",
+                "address": "127.0.0.1",
+                "port": 0
+            }
+        ],
+        "outbounds": [
+            {
+                "protocol": "socks",
+                "settings": {
+                    "address": "127.0.0.1",
+                    "port": ${config2},
+                    "udpOverTcp": true
+                }
+            }
+        ]
+    }
+    "#;
+
+    let config2 = r#"
+    {
+        "inbounds": [
+            {
+                "protocol": "socks",
+                "address": "127.0.0.1",
+                "port": 0
+            }
+        ],
+        "outbounds": [
+            {
+                "protocol": "direct"
+            }
+        ]
+    }
+    "#;
+
+    let configs = vec![config1.to_string(), config2.to_string()];
+    common::test_configs_udp_over_tcp(configs);
+}
@@ -20,7 +20,7 @@ fn test_tls_trojan() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -46,7 +46,7 @@ fn test_tls_trojan() {
                 "tag": "trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "password": "password"
                 }
             }
@@ -60,7 +60,7 @@ fn test_tls_trojan() {
             {
                 "protocol": "chain",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "tls",
@@ -103,5 +103,5 @@ fn test_tls_trojan() {
     std::fs::write(&key_path, &key).unwrap();
 
     let configs = vec![config1.to_string(), config2.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
@@ -16,7 +16,7 @@ fn test_trojan() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -24,7 +24,7 @@ fn test_trojan() {
                 "protocol": "trojan",
                 "settings": {
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "password": "password"
                 }
             }
@@ -38,7 +38,7 @@ fn test_trojan() {
             {
                 "protocol": "trojan",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "password": "password"
                 }
@@ -53,5 +53,5 @@ fn test_trojan() {
     "#;
 
     let configs = vec![config1.to_string(), config2.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }
@@ -22,7 +22,7 @@ fn test_ws_amux_trojan() {
             {
                 "protocol": "socks",
                 "address": "127.0.0.1",
-                "port": 1086
+                "port": 0
             }
         ],
         "outbounds": [
@@ -43,7 +43,7 @@ fn test_ws_amux_trojan() {
                         "ws"
                     ],
                     "address": "127.0.0.1",
-                    "port": 3001,
+                    "port": ${config2},
                     "maxAccepts": 16,
                     "concurrency": 1
                 }
@@ -72,7 +72,7 @@ fn test_ws_amux_trojan() {
             {
                 "protocol": "chain",
                 "address": "127.0.0.1",
-                "port": 3001,
+                "port": 0,
                 "settings": {
                     "actors": [
                         "amux",
@@ -113,5 +113,5 @@ fn test_ws_amux_trojan() {
     "#;
 
     let configs = vec![config1.to_string(), config2.to_string()];
-    common::test_configs(configs, "127.0.0.1", 1086);
+    common::test_configs(configs);
 }